@@ -3,8 +3,9 @@ import { invoke } from '@tauri-apps/api/core';
 import './App.css';
 import Installer from './components/Installer';
 import Dashboard from './components/Dashboard';
+import Uninstaller from './components/Uninstaller';
 
-type AppMode = 'installer' | 'studio';
+type AppMode = 'installer' | 'studio' | 'uninstaller';
 type ModeOverride = 'auto' | AppMode;
 
 function App() {
@@ -94,7 +95,7 @@ function App() {
           <div className="mode-hint">Crown override: {modeOverride} (auto is {detectedMode})</div>
         )}
       </div>
-      {modeToRender === 'installer' ? <Installer /> : <Dashboard />}
+      {modeToRender === 'installer' ? <Installer /> : modeToRender === 'uninstaller' ? <Uninstaller /> : <Dashboard />}
     </div>
   );
 }
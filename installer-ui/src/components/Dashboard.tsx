@@ -3,6 +3,7 @@ import { useState, useRef, useEffect, useMemo } from 'react';
 import { invoke } from '@tauri-apps/api/core';
 import { listen } from '@tauri-apps/api/event';
 import { open, confirm, save } from '@tauri-apps/plugin-dialog';
+import { errorMessage, isMisfitError } from '../errors';
 import '../App.css';
 
 interface InstallStep {
@@ -27,8 +28,69 @@ interface BuildRequest {
     manifest: InstallManifest;
     payloadFiles: [string, string][]; // [source, relative_dest]
     forceOverwrite?: boolean;
+    exeName?: string;
+    productVersion?: string;
+    company?: string;
+    fileDescription?: string;
+    iconPath?: string;
+    outputFormat?: 'folder' | 'zip' | 'singleFile';
+    zipRootFolder?: boolean;
+    verifySources?: boolean;
+    dedupePayloads?: boolean;
+    outputLayout?: 'replace' | 'versioned' | 'timestamped';
+    keepLast?: number;
 }
 
+interface BuildOutput {
+    outputDir: string;
+    exePath: string;
+    zipPath?: string;
+}
+
+type BuildWatchEvent =
+    | { status: 'rebuilding'; changed: string[] }
+    | { status: 'built'; output: BuildOutput; incremental: boolean; updatedFiles: number }
+    | { status: 'failed'; error: unknown };
+
+type BuildStep = 'exe' | 'manifest' | 'payloads' | 'lockfile' | 'compress' | 'sign' | 'embed' | 'codesign' | 'zip';
+
+interface BuildProgress {
+    step: BuildStep;
+    currentFile?: string;
+    bytesDone: number;
+    bytesTotal: number;
+}
+
+const BUILD_STEP_LABELS: Record<BuildStep, string> = {
+    exe: 'Copying the installer executable',
+    manifest: 'Writing the decree',
+    payloads: 'Copying payloads',
+    lockfile: 'Writing the payload lockfile',
+    compress: 'Compressing payloads',
+    sign: 'Signing the decree',
+    embed: 'Embedding into the executable',
+    codesign: 'Signing the executable',
+    zip: 'Writing the release zip'
+};
+
+// Writes a file the user picked; outside the project, dist and payload folders the backend
+// only writes in advanced mode, after asking the user itself. Resolves false when they decline.
+async function writeTextFile(path: string, contents: string): Promise<boolean> {
+    try {
+        await invoke('write_text_file', { path, contents });
+        return true;
+    } catch (e) {
+        if (isMisfitError(e) && e.code === 'cancelled') return false;
+        throw e;
+    }
+}
+
+const formatBytes = (bytes: number) => {
+    if (bytes < 1024) return `${bytes} B`;
+    if (bytes < 1024 * 1024) return `${(bytes / 1024).toFixed(1)} KB`;
+    return `${(bytes / (1024 * 1024)).toFixed(1)} MB`;
+};
+
 interface BuildTargetInfo {
     path: string;
     exists: boolean;
@@ -39,6 +101,55 @@ interface BuildTargetInfo {
 interface ScanEntry {
     name: string;
     path: string;
+    relativePath: string;
+    depth: number;
+    fileCount: number;
+    totalBytes: number;
+    modified?: string;
+    hasPackageJson: boolean;
+    extension?: ExtensionInfo;
+}
+
+// What a scanned folder's package.json says (ExtensionInfo in the engine crate)
+interface ExtensionInfo {
+    id: string;
+    name: string;
+    displayName?: string;
+    version?: string;
+    publisher?: string;
+}
+
+// "Python (ms-python.python 2024.1.0)", or the folder name without a package.json
+const describeScanned = (entry: ScanEntry) => {
+    const ext = entry.extension;
+    if (!ext) return entry.relativePath;
+    const id = [ext.id, ext.version].filter(Boolean).join(' ');
+    return ext.displayName ? `${ext.displayName} (${id})` : id;
+};
+
+// Comma-separated globs from a text field
+const splitGlobs = (text: string) => text.split(',').map(g => g.trim()).filter(Boolean);
+
+interface VsCodeInstall {
+    variant: 'code' | 'insiders' | 'vsCodium' | 'cursor';
+    name: string;
+    version?: string;
+    scope: 'user' | 'system';
+    appRoot: string;
+    resourcesPath: string;
+    extensionsDir: string;
+    settingsPath: string;
+    cliPath?: string;
+    portable: boolean;
+}
+
+interface ElectronApp {
+    name: string;
+    version?: string;
+    appRoot: string;
+    resourcesPath: string;
+    appPath: string;
+    packed: boolean;
 }
 
 type StepType = 'copy' | 'patchBlock' | 'setJsonValue' | 'base64Embed' | 'runCommand';
@@ -68,6 +179,8 @@ interface PatchBlockStep extends BaseStep {
     endMarker: string;
     contentSource: string;
     contentRel: string;
+    // Inline patch text; used instead of the content file when set
+    content: string;
     replacements: ReplacementPair[];
 }
 
@@ -124,6 +237,23 @@ interface Preset {
 }
 
 const PRESET_STORAGE_KEY = 'misfitPresetLibrary';
+const DRAFT_AUTOSAVE_MS = 30_000;
+
+interface StudioDraft {
+    savedAt: string;
+    projectPath?: string;
+    data: {
+        projectName: string;
+        appName: string;
+        version: string;
+        publisher: string;
+        description: string;
+        advancedMode: boolean;
+        targets: string[];
+        payloadDir: string;
+        steps: UiStep[];
+    };
+}
 
 const makeId = () => {
     if (typeof crypto !== 'undefined' && 'randomUUID' in crypto) {
@@ -169,6 +299,7 @@ const createStep = (type: StepType): UiStep => {
                 endMarker: '',
                 contentSource: '',
                 contentRel: '',
+                content: '',
                 replacements: []
             };
         case 'setJsonValue':
@@ -254,6 +385,7 @@ const manifestStepToUi = (step: InstallStep, payloadDir?: string): UiStep | null
                 endMarker: step.endMarker ?? '',
                 contentSource: resolvePayloadSource(payloadDir, contentRel),
                 contentRel,
+                content: typeof step.content === 'string' ? step.content : '',
                 replacements
             };
         }
@@ -335,6 +467,7 @@ const coercePresetSteps = (steps: unknown): PresetStep[] => {
                         endMarker: typeof raw.endMarker === 'string' ? raw.endMarker : '',
                         contentSource: typeof raw.contentSource === 'string' ? raw.contentSource : '',
                         contentRel: typeof raw.contentRel === 'string' ? raw.contentRel : String(raw.contentFile ?? ''),
+                        content: typeof raw.content === 'string' ? raw.content : '',
                         replacements
                     };
                 }
@@ -431,6 +564,7 @@ const presetStepsToUi = (steps: PresetStep[], payloadDir?: string): UiStep[] =>
                         ? resolvePayloadSource(payloadDir, contentRel)
                         : (rawContentSource || resolvePayloadSource(payloadDir, contentRel)),
                     contentRel,
+                    content: step.content ?? '',
                     replacements: Array.isArray(step.replacements)
                         ? step.replacements.map(pair => ({ key: String(pair.key ?? ''), value: String(pair.value ?? '') }))
                         : []
@@ -579,6 +713,69 @@ export default function Dashboard() {
     const [selectedPresetName, setSelectedPresetName] = useState('Custom');
 
     const [building, setBuilding] = useState(false);
+    const [buildProgress, setBuildProgress] = useState<BuildProgress | null>(null);
+    const [cancelling, setCancelling] = useState(false);
+    const [watching, setWatching] = useState(false);
+    const [lastOutput, setLastOutput] = useState<BuildOutput | null>(null);
+    const [vscodeInstalls, setVscodeInstalls] = useState<VsCodeInstall[]>([]);
+    // How deep "Scan Extensions Folder" looks and which folders it keeps
+    const [scanDepth, setScanDepth] = useState(1);
+    const [scanInclude, setScanInclude] = useState('');
+    const [scanExclude, setScanExclude] = useState('node_modules, .git');
+    const [electronApps, setElectronApps] = useState<ElectronApp[]>([]);
+    const lastDraftRef = useRef<string | null>(null);
+
+    const draftData = useMemo<StudioDraft['data']>(
+        () => ({ projectName, appName, version, publisher, description, advancedMode, targets, payloadDir, steps }),
+        [projectName, appName, version, publisher, description, advancedMode, targets, payloadDir, steps]
+    );
+    const draftRef = useRef(draftData);
+    draftRef.current = draftData;
+
+    useEffect(() => {
+        const restoreDraft = async () => {
+            try {
+                const draft = await invoke<StudioDraft | null>('load_draft');
+                if (!draft) return;
+                const restore = await confirm(
+                    `Unsaved work from ${new Date(draft.savedAt).toLocaleString()} was found. Restore it?`,
+                    { title: 'Restore draft?', kind: 'info' }
+                );
+                if (!restore) {
+                    await invoke('discard_draft');
+                    return;
+                }
+                const data = draft.data;
+                setProjectName(data.projectName);
+                setAppName(data.appName);
+                setVersion(data.version);
+                setPublisher(data.publisher);
+                setDescription(data.description);
+                setAdvancedMode(Boolean(data.advancedMode));
+                setTargets(data.targets);
+                setPayloadDir(data.payloadDir);
+                setSteps(data.steps);
+                lastDraftRef.current = JSON.stringify(data);
+            } catch (e) {
+                console.warn('Failed to restore draft', e);
+            }
+        };
+        restoreDraft();
+
+        // Start from the untouched form so only real edits are autosaved
+        lastDraftRef.current ??= JSON.stringify(draftRef.current);
+        const timer = setInterval(async () => {
+            const snapshot = JSON.stringify(draftRef.current);
+            if (snapshot === lastDraftRef.current) return;
+            try {
+                await invoke('save_draft', { data: draftRef.current });
+                lastDraftRef.current = snapshot;
+            } catch (e) {
+                console.warn('Failed to autosave draft', e);
+            }
+        }, DRAFT_AUTOSAVE_MS);
+        return () => clearInterval(timer);
+    }, []);
 
     useEffect(() => {
         const fetchRemotePresets = async () => {
@@ -612,6 +809,32 @@ export default function Dashboard() {
         fetchRemotePresets();
     }, []);
 
+    useEffect(() => {
+        invoke<VsCodeInstall[]>('detect_vscode_installs').then(setVscodeInstalls).catch(() => {});
+        invoke<ElectronApp[]>('detect_electron_apps').then(setElectronApps).catch(() => {});
+    }, []);
+
+    // Common targets plus the paths of every VS Code install and Electron app found on this machine
+    const pathHelpers = useMemo(
+        () => [
+            ...COMMON_TARGETS,
+            ...vscodeInstalls.flatMap(install => {
+                const label = `${install.name}${install.version ? ` ${install.version}` : ''} (${install.scope})`;
+                return [
+                    { label: `${label} resources`, value: install.resourcesPath },
+                    { label: `${label} settings.json`, value: install.settingsPath },
+                    { label: `${label} extensions`, value: install.extensionsDir }
+                ];
+            }),
+            ...electronApps.map(app => ({
+                label: `${app.name}${app.version ? ` ${app.version}` : ''} ${app.packed ? 'app.asar' : 'app'}`,
+                // Files inside an archive are addressed as app.asar!/path
+                value: app.packed ? `${app.appPath}!/` : app.appPath
+            }))
+        ],
+        [vscodeInstalls, electronApps]
+    );
+
     useEffect(() => {
         const unlistenPromise = listen<string>('log', (event) => {
             setLogs(prev => [...prev, `[${new Date().toLocaleTimeString()}] ${event.payload}`]);
@@ -619,6 +842,38 @@ export default function Dashboard() {
         return () => { unlistenPromise.then(unlisten => unlisten()); };
     }, []);
 
+    useEffect(() => {
+        const unlistenPromise = listen<BuildWatchEvent>('build-watch', (event) => {
+            const update = event.payload;
+            if (update.status === 'rebuilding') {
+                setLogs(prev => [...prev, `Payloads changed (${update.changed.length}), re-forging...`]);
+            } else if (update.status === 'built' && !update.incremental) {
+                setLogs(prev => [...prev, `Decree re-forged. Output at: ${update.output.outputDir}`]);
+                setLastOutput(update.output);
+            }
+        });
+        return () => { unlistenPromise.then(unlisten => unlisten()); };
+    }, []);
+
+    // A project's own code signing command waits for the user's approval on every build
+    useEffect(() => {
+        const unlistenPromise = listen<{ id: number; details: string }>('command-prompt', async (event) => {
+            const run = await confirm(
+                `This project wants to sign the installer by running:\n\n${event.payload.details}\n\nRun it?`,
+                { title: 'Run signing command?', kind: 'warning' }
+            );
+            await invoke('answer_command_prompt', { id: event.payload.id, run }).catch(() => {});
+        });
+        return () => { unlistenPromise.then(unlisten => unlisten()); };
+    }, []);
+
+    useEffect(() => {
+        const unlistenPromise = listen<BuildProgress>('build-progress', (event) => {
+            setBuildProgress(event.payload);
+        });
+        return () => { unlistenPromise.then(unlisten => unlisten()); };
+    }, []);
+
     useEffect(() => {
         logEndRef.current?.scrollIntoView({ behavior: 'smooth' });
     }, [logs]);
@@ -795,7 +1050,7 @@ export default function Dashboard() {
             setSelectedPresetName(name);
             setLogs(p => [...p, `Preset imported: ${name}`]);
         } catch (e) {
-            setLogs(p => [...p, `Preset import failed: ${String(e)}`]);
+            setLogs(p => [...p, `Preset import failed: ${errorMessage(e)}`]);
         }
     };
 
@@ -815,10 +1070,10 @@ export default function Dashboard() {
                 data: selectedPreset.data
             };
             const json = JSON.stringify(payload, null, 2);
-            await invoke('write_text_file', { path: filePath, contents: json });
+            if (!(await writeTextFile(filePath, json))) return;
             setLogs(p => [...p, `Preset exported to ${filePath}`]);
         } catch (e) {
-            setLogs(p => [...p, `Preset export failed: ${String(e)}`]);
+            setLogs(p => [...p, `Preset export failed: ${errorMessage(e)}`]);
         }
     };
 
@@ -826,7 +1081,10 @@ export default function Dashboard() {
         const selected = await open({ multiple: false, directory: true });
         if (!selected || Array.isArray(selected)) return;
         try {
-            const entries = await invoke<ScanEntry[]>('scan_extension_folders', { root: selected });
+            const options = { depth: scanDepth, include: splitGlobs(scanInclude), exclude: splitGlobs(scanExclude) };
+            const scanned = await invoke<ScanEntry[]>('scan_extension_folders', { root: selected, options });
+            // Below the first level, only packages make sense as extensions
+            const entries = scanned.filter(entry => entry.depth === 1 || entry.hasPackageJson);
             if (!entries.length) {
                 setLogs(p => [...p, 'No extension folders found in the selected directory.']);
                 return;
@@ -840,9 +1098,15 @@ export default function Dashboard() {
                 dest: `%USERPROFILE%\\.antigravity\\extensions\\${entry.name}`
             }));
             setSteps(prev => [...prev, ...newSteps]);
-            setLogs(p => [...p, `Loaded ${entries.length} extension copy decrees.`]);
+            setLogs(p => [
+                ...p,
+                ...entries.map(entry =>
+                    `  ${describeScanned(entry)}: ${entry.fileCount} files, ${formatBytes(entry.totalBytes)}${entry.hasPackageJson ? ', package.json' : ''}`
+                ),
+                `Loaded ${entries.length} extension copy decrees.`
+            ]);
         } catch (e) {
-            setLogs(p => [...p, `Scan failed: ${String(e)}`]);
+            setLogs(p => [...p, `Scan failed: ${errorMessage(e)}`]);
         }
     };
 
@@ -879,7 +1143,7 @@ export default function Dashboard() {
                 ...warnings.map(warning => `Warning: ${warning}`)
             ]);
         } catch (e) {
-            setLogs(p => [...p, `Manifest import failed: ${String(e)}`]);
+            setLogs(p => [...p, `Manifest import failed: ${errorMessage(e)}`]);
         }
     };
 
@@ -896,8 +1160,8 @@ export default function Dashboard() {
             }
 
             if (step.type === 'patchBlock') {
-                if (!step.file.trim() && !step.contentRel.trim() && !step.contentSource.trim()) continue;
-                const rel = step.contentRel.trim() || (step.contentSource ? toBaseName(step.contentSource) : '');
+                if (!step.file.trim() && !step.contentRel.trim() && !step.contentSource.trim() && !step.content) continue;
+                const rel = step.content ? '' : step.contentRel.trim() || (step.contentSource ? toBaseName(step.contentSource) : '');
                 const replacements = step.replacements
                     .filter(pair => pair.key.trim().length > 0)
                     .reduce((acc, pair) => {
@@ -909,7 +1173,8 @@ export default function Dashboard() {
                     file: step.file.trim(),
                     startMarker: step.startMarker.trim(),
                     endMarker: step.endMarker.trim(),
-                    contentFile: rel,
+                    contentFile: rel || undefined,
+                    content: step.content || undefined,
                     replacements: Object.keys(replacements).length ? replacements : undefined
                 });
             }
@@ -979,10 +1244,10 @@ export default function Dashboard() {
         try {
             const manifest = buildManifestForExport();
             const json = JSON.stringify(manifest, null, 2);
-            await invoke('write_text_file', { path: filePath, contents: json });
+            if (!(await writeTextFile(filePath, json))) return;
             setLogs(p => [...p, `Manifest exported to ${filePath}`]);
         } catch (e) {
-            setLogs(p => [...p, `Manifest export failed: ${String(e)}`]);
+            setLogs(p => [...p, `Manifest export failed: ${errorMessage(e)}`]);
         }
     };
 
@@ -1035,8 +1300,8 @@ export default function Dashboard() {
                 if (!step.startMarker.trim() || !step.endMarker.trim()) {
                     pushIssue(step.id, 'error', 'Patch decree needs both start/end markers.');
                 }
-                if (!step.contentSource.trim()) {
-                    pushIssue(step.id, 'error', 'Patch decree is missing a content file.');
+                if (!step.contentSource.trim() && !step.content) {
+                    pushIssue(step.id, 'error', 'Patch decree is missing a content file or inline content.');
                 }
                 if (placeholderPattern.test(step.file)) {
                     pushIssue(step.id, 'warning', 'Target file contains a placeholder.');
@@ -1096,7 +1361,7 @@ export default function Dashboard() {
         return { issueMap, errorCount, warningCount };
     }, [steps]);
 
-    const doBuild = async (fastMode: boolean) => {
+    const doBuild = async (fastMode: boolean, watch = false) => {
         setBuilding(true);
         setLogs(p => [...p, fastMode ? 'Quick forging engaged...' : 'Summoning the forge...']);
 
@@ -1154,12 +1419,12 @@ export default function Dashboard() {
                         errors.push('A patch decree is missing an end marker.');
                         continue;
                     }
-                    if (!contentSource) {
-                        errors.push('A patch decree is missing a content file.');
+                    if (!contentSource && !step.content) {
+                        errors.push('A patch decree is missing a content file or inline content.');
                         continue;
                     }
-                    const rel = step.contentRel.trim() || toBaseName(contentSource);
-                    addPayload(rel, contentSource);
+                    const rel = step.content ? '' : step.contentRel.trim() || toBaseName(contentSource);
+                    if (rel) addPayload(rel, contentSource);
                     const replacements = step.replacements
                         .filter(pair => pair.key.trim().length > 0)
                         .reduce((acc, pair) => {
@@ -1171,7 +1436,8 @@ export default function Dashboard() {
                         file: target,
                         startMarker,
                         endMarker,
-                        contentFile: rel,
+                        contentFile: rel || undefined,
+                        content: step.content || undefined,
                         replacements: Object.keys(replacements).length ? replacements : undefined
                     });
                 }
@@ -1318,17 +1584,50 @@ export default function Dashboard() {
                 forceOverwrite
             };
 
-            const path = await invoke('build_project', { request: req });
-            setLogs(p => [...p, `Decree forged. Output at: ${path}`]);
+            const output = await invoke<BuildOutput>(watch ? 'start_build_watch' : 'build_project', { request: req });
+            setLogs(p => [...p, `Decree forged. Output at: ${output.outputDir}`]);
+            setLastOutput(output);
+            if (watch) setWatching(true);
         } catch (e) {
-            setLogs(p => [...p, `Forge failed: ${String(e)}`]);
+            if (!isMisfitError(e) || e.code !== 'cancelled') {
+                setLogs(p => [...p, `Forge failed: ${errorMessage(e)}`]);
+            }
         } finally {
             setBuilding(false);
+            setBuildProgress(null);
+            setCancelling(false);
+        }
+    };
+
+    const handleCancelBuild = async () => {
+        setCancelling(true);
+        try {
+            await invoke('cancel_build');
+        } catch (e) {
+            console.warn('Failed to cancel build', e);
         }
     };
 
     const handleBuild = () => doBuild(false);
     const handleFastForge = () => doBuild(true);
+    const handleShowOutput = async () => {
+        if (!lastOutput) return;
+        try {
+            await invoke('open_path_in_explorer', { path: lastOutput.zipPath ?? lastOutput.outputDir });
+        } catch (e) {
+            setLogs(p => [...p, `Failed to open the output folder: ${errorMessage(e)}`]);
+        }
+    };
+
+    const handleToggleWatch = async () => {
+        if (!watching) return doBuild(true, true);
+        try {
+            await invoke('stop_build_watch');
+            setWatching(false);
+        } catch (e) {
+            setLogs(p => [...p, `Failed to stop watching: ${errorMessage(e)}`]);
+        }
+    };
 
     return (
         <div className="studio-page">
@@ -1469,6 +1768,18 @@ export default function Dashboard() {
                                 <button className="btn-ghost" type="button" onClick={handleScanExtensions}>
                                     Scan Extensions Folder
                                 </button>
+                                <label className="field">
+                                    <span>Scan depth</span>
+                                    <input type="number" min={1} max={8} value={scanDepth} onChange={e => setScanDepth(Math.max(1, Number(e.target.value) || 1))} />
+                                </label>
+                                <label className="field">
+                                    <span>Include</span>
+                                    <input value={scanInclude} placeholder="ext-*, *-theme" onChange={e => setScanInclude(e.target.value)} />
+                                </label>
+                                <label className="field">
+                                    <span>Exclude</span>
+                                    <input value={scanExclude} onChange={e => setScanExclude(e.target.value)} />
+                                </label>
                                 <button className="btn-ghost" type="button" onClick={handleImportManifest}>
                                     Import Manifest
                                 </button>
@@ -1594,7 +1905,7 @@ export default function Dashboard() {
                                                             onChange={e => updateStep(step.id, { dest: e.target.value })}
                                                         >
                                                             <option value="">Path helper...</option>
-                                                            {COMMON_TARGETS.map(option => (
+                                                            {pathHelpers.map(option => (
                                                                 <option key={`copy-dest-${option.value}`} value={option.value}>
                                                                     {option.label}
                                                                 </option>
@@ -1621,7 +1932,7 @@ export default function Dashboard() {
                                                             onChange={e => updateStep(step.id, { file: e.target.value })}
                                                         >
                                                             <option value="">Path helper...</option>
-                                                            {COMMON_TARGETS.map(option => (
+                                                            {pathHelpers.map(option => (
                                                                 <option key={`patch-file-${option.value}`} value={option.value}>
                                                                     {option.label}
                                                                 </option>
@@ -1675,6 +1986,14 @@ export default function Dashboard() {
                                                         onChange={e => updateStep(step.id, { contentRel: e.target.value })}
                                                     />
                                                 </label>
+                                                <label className="field">
+                                                    <span>Inline Content (instead of a file)</span>
+                                                    <textarea
+                                                        value={step.content}
+                                                        placeholder={'.monaco-workbench {\n  background: #101010;\n}'}
+                                                        onChange={e => updateStep(step.id, { content: e.target.value })}
+                                                    />
+                                                </label>
 
                                                 <div className="replacements">
                                                     <div className="replacements-head">
@@ -1761,7 +2080,7 @@ export default function Dashboard() {
                                                             onChange={e => updateStep(step.id, { file: e.target.value })}
                                                         >
                                                             <option value="">Path helper...</option>
-                                                            {COMMON_TARGETS.map(option => (
+                                                            {pathHelpers.map(option => (
                                                                 <option key={`json-file-${option.value}`} value={option.value}>
                                                                     {option.label}
                                                                 </option>
@@ -1847,7 +2166,7 @@ export default function Dashboard() {
                                                             onChange={e => updateStep(step.id, { file: e.target.value })}
                                                         >
                                                             <option value="">Path helper...</option>
-                                                            {COMMON_TARGETS.map(option => (
+                                                            {pathHelpers.map(option => (
                                                                 <option key={`embed-file-${option.value}`} value={option.value}>
                                                                     {option.label}
                                                                 </option>
@@ -1941,8 +2260,36 @@ export default function Dashboard() {
                         <button className="btn-secondary" onClick={handleFastForge} disabled={building} title="Overwrite target immediately">
                             Fast Forge (Overwrite)
                         </button>
+                        <button className="btn-secondary" onClick={handleToggleWatch} disabled={building} title="Re-forge whenever a payload changes">
+                            {watching ? 'Stop Watching' : 'Watch & Forge'}
+                        </button>
+                        {lastOutput && (
+                            <button className="btn-secondary" onClick={handleShowOutput} title={lastOutput.zipPath ?? lastOutput.outputDir}>
+                                Show in Folder
+                            </button>
+                        )}
                     </div>
 
+                    {building && buildProgress && (
+                        <div className="build-progress" role="dialog" aria-label="Build progress">
+                            <div className="build-progress-step">{BUILD_STEP_LABELS[buildProgress.step]}</div>
+                            {buildProgress.currentFile && (
+                                <div className="build-progress-file">{buildProgress.currentFile}</div>
+                            )}
+                            {buildProgress.bytesTotal > 0 && (
+                                <>
+                                    <progress value={buildProgress.bytesDone} max={buildProgress.bytesTotal} />
+                                    <div className="build-progress-file">
+                                        {formatBytes(buildProgress.bytesDone)} of {formatBytes(buildProgress.bytesTotal)}
+                                    </div>
+                                </>
+                            )}
+                            <button className="btn-secondary" onClick={handleCancelBuild} disabled={cancelling}>
+                                {cancelling ? 'Cancelling...' : 'Cancel'}
+                            </button>
+                        </div>
+                    )}
+
                     <div className="log-title">Forge log</div>
                     <div className="log-panel" style={{ height: '170px' }}>
                         {logs.map((log, i) => <div key={i} className="log-line">{log}</div>)}
@@ -1,7 +1,9 @@
 import { useState, useEffect, useRef } from 'react';
 import { invoke } from '@tauri-apps/api/core';
 import { listen } from '@tauri-apps/api/event';
+import { getCurrentWindow } from '@tauri-apps/api/window';
 import { confirm } from '@tauri-apps/plugin-dialog';
+import { errorMessage, recoveryHint } from '../errors';
 import '../App.css';
 
 interface InstallStep {
@@ -9,36 +11,153 @@ interface InstallStep {
     [key: string]: any;
 }
 
+function withHint(message: string, e: unknown): string {
+    const hint = recoveryHint(e);
+    return hint ? `${message}\n${hint}` : message;
+}
+
+// A plain string or translations keyed by locale (LocalizedText in the engine crate)
+type LocalizedText = string | Record<string, string>;
+
+// The locale-independent text the engine keys backups on: the string, or the English (else first) entry
+function defaultText(text: LocalizedText): string {
+    if (typeof text === 'string') return text;
+    const keys = Object.keys(text).sort();
+    const key = keys.find(k => k.replace('_', '-').toLowerCase() === 'en') ?? keys[0];
+    return key ? text[key] : '';
+}
+
 interface InstallManifest {
-    appName: string;
+    appName: LocalizedText;
     version: string;
     publisher: string;
-    description: string;
+    description: LocalizedText;
     logoPath?: string;
     advancedMode?: boolean;
     targets: string[];
     payloadDir: string;
     installSteps: InstallStep[];
+    launchAfterInstall?: { command: string; args?: string[] };
+    prompts?: PromptedVariable[];
+    licenseFile?: string;
+    branding?: Branding;
+}
+
+// Size and expected duration of the install (InstallEstimate in the engine crate)
+interface InstallEstimate {
+    totalBytes: number;
+    fileCount: number;
+    patchedFiles: number;
+    bytesPerSec: number;
+    estimatedMs: number;
+    basedOnInstalls: number;
+}
+
+// The machine the installer runs on (SystemInfo in the engine crate); the first volume holds the install target
+interface SystemInfo {
+    os: string;
+    osVersion: string;
+    arch: string;
+    elevated: boolean;
+    locale?: string;
+    volumes: { paths: string[]; availableBytes: number; totalBytes: number }[];
+}
+
+// Installed files that no longer match their recorded hashes (IntegrityReport in the engine crate)
+interface IntegrityReport {
+    watched: boolean;
+    installedAt?: string;
+    checked: number;
+    modified: string[];
+    missing: string[];
+}
+
+// "~420 MB, about 2 minutes"
+function describeEstimate(estimate: InstallEstimate): string {
+    const mb = estimate.totalBytes / (1024 * 1024);
+    const size = mb >= 1 ? `~${Math.round(mb)} MB` : `~${Math.max(1, Math.round(estimate.totalBytes / 1024))} KB`;
+    const minutes = Math.round(estimate.estimatedMs / 60000);
+    const duration = minutes >= 2 ? `about ${minutes} minutes` : minutes === 1 ? 'about a minute' : 'under a minute';
+    return `${size}, ${duration}`;
+}
+
+interface Branding {
+    accentColor?: string;
+    background?: string;
+    windowTitle?: LocalizedText;
+    welcomeText?: LocalizedText;
+    logo?: string;
+}
+
+// Recolors the window and sets its title from the (localized) manifest's branding
+function applyBranding(branding: Branding | undefined) {
+    if (!branding) return;
+    const root = document.documentElement;
+    if (branding.accentColor) {
+        root.style.setProperty('--accent', branding.accentColor);
+        root.classList.add('branded');
+    }
+    if (branding.background) document.body.style.background = branding.background;
+    if (typeof branding.windowTitle === 'string') getCurrentWindow().setTitle(branding.windowTitle).catch(() => {});
+}
+
+interface PromptedVariable {
+    name: string;
+    label?: string;
+    description?: string;
+    default?: string;
+    secret?: boolean;
+    required?: boolean;
 }
 
 export default function Installer() {
     const [manifest, setManifest] = useState<InstallManifest | null>(null);
+    // The same manifest with its texts in the user's language, for display only
+    const [shown, setShown] = useState<InstallManifest | null>(null);
+    const [logoUrl, setLogoUrl] = useState<string | null>(null);
     const [status, setStatus] = useState<'loading' | 'ready' | 'installing' | 'complete' | 'error'>('loading');
     const [logs, setLogs] = useState<string[]>([]);
     const [errorMsg, setErrorMsg] = useState<string>('');
+    // An earlier install of this manifest failed or was interrupted and can be resumed
+    const [canResume, setCanResume] = useState(false);
+    // Offer "Launch now" only after an install, not a restore
+    const [installed, setInstalled] = useState(false);
+    // Files that were in use and get replaced when Windows restarts
+    const [rebootFiles, setRebootFiles] = useState<string[]>([]);
+    // Values typed for the manifest's prompts; kept in memory only
+    const [promptValues, setPromptValues] = useState<Record<string, string>>({});
+    // License the user has to accept before installing, if the manifest ships one
+    const [licenseText, setLicenseText] = useState<string | null>(null);
+    const [acceptedLicense, setAcceptedLicense] = useState(false);
+    const [estimate, setEstimate] = useState<InstallEstimate | null>(null);
+    const [systemInfo, setSystemInfo] = useState<SystemInfo | null>(null);
+    // Files of an earlier install that an update of the app put back, if the manifest watches them
+    const [reverted, setReverted] = useState<string[]>([]);
     const logEndRef = useRef<HTMLDivElement>(null);
 
     useEffect(() => {
         async function load() {
             try {
                 const man = await invoke<InstallManifest>('get_manifest');
+                const localized = await invoke<InstallManifest>('get_localized_manifest', { locale: navigator.language });
                 setManifest(man);
+                setShown(localized);
+                applyBranding(localized.branding);
+                setLogoUrl(await invoke<string | null>('get_branding_logo', { manifest: man }).catch(() => null));
+                setLicenseText(await invoke<string | null>('get_license_text', { manifest: man }));
+                setEstimate(await invoke<InstallEstimate>('estimate_install', { manifest: man }).catch(() => null));
+                setSystemInfo(await invoke<SystemInfo>('get_system_info', { manifest: man }).catch(() => null));
                 setStatus('ready');
-                addLog(`Decree loaded for ${man.appName} v${man.version}`);
+                addLog(`Decree loaded for ${localized.appName} v${man.version}`);
+                await checkPending(man);
+                // Runs in the background; hashing the installed files can take a moment
+                invoke<IntegrityReport>('check_install_integrity', { manifest: man })
+                    .then(report => setReverted([...report.modified, ...report.missing]))
+                    .catch(() => {});
             } catch (e) {
                 setStatus('error');
-                setErrorMsg(`Failed to load decree: ${e}`);
-                addLog(`Error: ${e}`);
+                setErrorMsg(withHint(`Failed to load decree: ${errorMessage(e)}`, e));
+                addLog(`Error: ${errorMessage(e)}`);
             }
         }
         load();
@@ -46,9 +165,33 @@ export default function Installer() {
         const unlistenPromise = listen<string>('log', (event) => {
             addLog(event.payload);
         });
+        const unlistenRebootPromise = listen<string[]>('reboot-required', (event) => {
+            setRebootFiles(prev => [...prev, ...event.payload]);
+        });
+        // Each command of a manifest without advancedMode waits for an answer here
+        const unlistenCommandPromise = listen<{ id: number; details: string }>('command-prompt', async (event) => {
+            const run = await confirm(
+                `This installer wants to run a system command:\n\n${event.payload.details}\n\nRun it?`,
+                { title: 'Run command?', kind: 'warning' }
+            );
+            await invoke('answer_command_prompt', { id: event.payload.id, run }).catch(() => {});
+        });
+        // Copies with `overwrite: prompt` ask before replacing files that already exist
+        const unlistenOverwritePromise = listen<{ id: number; details: string[] }>('overwrite-prompt', async (event) => {
+            const files = event.payload.details;
+            const shown = files.slice(0, 10).join('\n') + (files.length > 10 ? `\n…and ${files.length - 10} more` : '');
+            const overwrite = await confirm(
+                `These files already exist:\n\n${shown}\n\nReplace them?`,
+                { title: 'Replace existing files?', kind: 'warning' }
+            );
+            await invoke('answer_overwrite_prompt', { id: event.payload.id, overwrite }).catch(() => {});
+        });
 
         return () => {
             unlistenPromise.then(unlisten => unlisten());
+            unlistenCommandPromise.then(unlisten => unlisten());
+            unlistenOverwritePromise.then(unlisten => unlisten());
+            unlistenRebootPromise.then(unlisten => unlisten());
         };
     }, []);
 
@@ -60,35 +203,54 @@ export default function Installer() {
         logEndRef.current?.scrollIntoView({ behavior: 'smooth' });
     }, [logs]);
 
-    const handleInstall = async () => {
+    const checkPending = async (man: InstallManifest) => {
+        const pending = await invoke<{ completed: number[] } | null>('pending_install', { manifest: man }).catch(() => null);
+        setCanResume(pending !== null);
+        if (pending) addLog(`An unfinished installation was found (${pending.completed.length} step(s) done).`);
+    };
+
+    const handleInstall = async (resume = false) => {
         if (!manifest) return;
-        const commandSteps = manifest.installSteps.filter(step => step.type === 'runCommand');
-        if (commandSteps.length > 0) {
-            const commands = commandSteps
-                .map(step => step.command)
-                .filter((cmd): cmd is string => typeof cmd === 'string' && cmd.trim().length > 0);
-            const sample = commands.slice(0, 5);
-            const extra = commands.length > 5 ? `\n...and ${commands.length - 5} more` : '';
-            const summary = sample.length ? `\n${sample.join('\n')}${extra}` : '';
-            const proceed = await confirm(
-                `This installer will run system commands.${summary}\n\nContinue?`,
-                { title: 'Run commands?', kind: 'warning' }
-            );
-            if (!proceed) {
-                addLog('Installation cancelled by user.');
-                return;
-            }
-        }
         setStatus('installing');
-        addLog('Enacting installation...');
+        setRebootFiles([]);
+        addLog(resume ? 'Resuming installation...' : 'Enacting installation...');
         try {
-            await invoke('run_install', { manifest });
+            await invoke(resume ? 'resume_install' : 'run_install', { manifest, promptValues, acceptedLicense });
             setStatus('complete');
+            setCanResume(false);
+            setInstalled(true);
             addLog('Decree enacted.');
         } catch (e) {
             setStatus('error');
-            setErrorMsg(`Enactment failed: ${e}`);
-            addLog(`Error: ${e}`);
+            setErrorMsg(withHint(`Enactment failed: ${errorMessage(e)}`, e));
+            addLog(`Error: ${errorMessage(e)}`);
+            await checkPending(manifest);
+        }
+    };
+
+    const handleLaunch = async () => {
+        if (!manifest) return;
+        try {
+            await invoke('launch_target', { manifest });
+        } catch (e) {
+            addLog(`Launch Error: ${errorMessage(e)}`);
+        }
+    };
+
+    const handleRepair = async () => {
+        if (!manifest) return;
+        setStatus('installing');
+        addLog('Repairing the earlier installation...');
+        try {
+            const report = await invoke<{ steps: { status: string }[] }>('repair_install', { manifest, promptValues });
+            const count = (status: string) => report.steps.filter(s => s.status === status).length;
+            setReverted([]);
+            setStatus('complete');
+            addLog(`Repair complete: ${count('repaired')} step(s) repaired, ${count('failed')} failed.`);
+        } catch (e) {
+            setStatus('error');
+            setErrorMsg(withHint(`Repair failed: ${errorMessage(e)}`, e));
+            addLog(`Repair Error: ${errorMessage(e)}`);
         }
     };
 
@@ -97,13 +259,13 @@ export default function Installer() {
         setStatus('installing');
         addLog('Restoring from the royal archive...');
         try {
-            await invoke('restore_backup', { app_name: manifest.appName });
+            await invoke('restore_backup', { app_name: defaultText(manifest.appName) });
             setStatus('complete');
             addLog('Restoration complete.');
         } catch (e) {
             setStatus('error');
-            setErrorMsg(`Restoration failed: ${e}`);
-            addLog(`Restore Error: ${e}`);
+            setErrorMsg(withHint(`Restoration failed: ${errorMessage(e)}`, e));
+            addLog(`Restore Error: ${errorMessage(e)}`);
         }
     };
 
@@ -113,17 +275,65 @@ export default function Installer() {
     return (
         <div className="container">
             <header className="header">
-                {manifest?.logoPath && <img src={manifest.logoPath} alt="Logo" className="logo" />}
+                {logoUrl ? <img src={logoUrl} alt="Logo" className="logo" /> : manifest?.logoPath && <img src={manifest.logoPath} alt="Logo" className="logo" />}
                 <div className="title-area">
-                    <h1>{manifest?.appName || 'Installer'}</h1>
+                    <h1>{shown?.appName || 'Installer'}</h1>
                     <p className="subtitle">Edition {manifest?.version} by {manifest?.publisher}</p>
                 </div>
             </header>
 
             <main className="main-content">
-                <p className="description">{manifest?.description}</p>
+                <p className="description">{(shown?.branding?.welcomeText as string | undefined) || shown?.description}</p>
+                {estimate && estimate.totalBytes > 0 && (
+                    <p className="hint">
+                        {describeEstimate(estimate)} ({estimate.fileCount} files to copy, {estimate.patchedFiles} to patch)
+                    </p>
+                )}
+                {estimate && systemInfo && systemInfo.volumes.length > 0 && systemInfo.volumes[0].availableBytes < estimate.totalBytes && (
+                    <p className="issue warning">
+                        Only {Math.floor(systemInfo.volumes[0].availableBytes / (1024 * 1024))} MB are free where the files go; the install needs more.
+                    </p>
+                )}
 
+                {status === 'ready' && reverted.length > 0 && (
+                    <div className="issue warning">
+                        {reverted.length} installed file(s) changed since the install, probably by an update of {shown?.appName}. Repair to apply the changes again.
+                        {reverted.map(file => <div key={file} className="log-line">{file}</div>)}
+                        <button className="btn-secondary" onClick={handleRepair}>Repair</button>
+                    </div>
+                )}
                 {status === 'error' && <div className="error-banner">{errorMsg}</div>}
+                {(status === 'ready' || status === 'error') && manifest?.prompts?.map(prompt => (
+                    <label key={prompt.name} className="field">
+                        <span>{prompt.label || prompt.name}{prompt.required !== false && !prompt.default ? ' *' : ''}</span>
+                        <input
+                            type={prompt.secret ? 'password' : 'text'}
+                            autoComplete="off"
+                            placeholder={prompt.default ?? ''}
+                            value={promptValues[prompt.name] ?? ''}
+                            onChange={e => setPromptValues(prev => ({ ...prev, [prompt.name]: e.target.value }))}
+                        />
+                        {prompt.description && <span className="hint">{prompt.description}</span>}
+                    </label>
+                ))}
+                {status === 'complete' && rebootFiles.length > 0 && (
+                    <div className="error-banner">
+                        Restart Windows to finish: {rebootFiles.length} file(s) were in use and will be replaced then.
+                        {rebootFiles.map(file => <div key={file} className="log-line">{file}</div>)}
+                    </div>
+                )}
+
+                {licenseText !== null && status !== 'complete' && (
+                    <>
+                        <div className="log-panel">
+                            {licenseText.split('\n').map((line, i) => <div key={i} className="log-line">{line}</div>)}
+                        </div>
+                        <label className="toggle">
+                            <input type="checkbox" checked={acceptedLicense} onChange={e => setAcceptedLicense(e.target.checked)} />
+                            I accept the license agreement
+                        </label>
+                    </>
+                )}
 
                 <div className="log-panel">
                     {logs.map((log, i) => <div key={i} className="log-line">{log}</div>)}
@@ -134,12 +344,21 @@ export default function Installer() {
                     {status === 'ready' && (
                         <>
                             <button className="btn-secondary" onClick={handleRestore} style={{ marginRight: '1rem' }}>Restore Archive</button>
-                            <button className="btn-primary" onClick={handleInstall}>Enact Install</button>
+                            {canResume && (
+                                <button className="btn-secondary" onClick={() => handleInstall(true)} disabled={licenseText !== null && !acceptedLicense} style={{ marginRight: '1rem' }}>Resume Install</button>
+                            )}
+                            <button className="btn-primary" onClick={() => handleInstall()} disabled={licenseText !== null && !acceptedLicense}>Enact Install</button>
                         </>
                     )}
+                    {status === 'error' && canResume && (
+                        <button className="btn-primary" onClick={() => handleInstall(true)} disabled={licenseText !== null && !acceptedLicense}>Resume Install</button>
+                    )}
                     {(status === 'installing') && (
                         <button className="btn-primary" disabled>Enacting...</button>
                     )}
+                    {status === 'complete' && installed && manifest?.launchAfterInstall && (
+                        <button className="btn-primary" onClick={handleLaunch} style={{ marginRight: '1rem' }}>Launch {shown?.appName} now</button>
+                    )}
                     {status === 'complete' && (
                         <button className="btn-success" disabled>Decree Complete</button>
                     )}
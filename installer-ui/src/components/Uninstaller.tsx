@@ -0,0 +1,117 @@
+import { useState, useEffect, useRef } from 'react';
+import { invoke } from '@tauri-apps/api/core';
+import { listen } from '@tauri-apps/api/event';
+import { errorMessage, recoveryHint } from '../errors';
+import '../App.css';
+
+interface UninstallState {
+    appName: string;
+    version: string;
+    publisher: string;
+    // Unset when no install of this app is recorded
+    installedAt?: string;
+    patchedFiles: string[];
+}
+
+function withHint(message: string, e: unknown): string {
+    const hint = recoveryHint(e);
+    return hint ? `${message}\n${hint}` : message;
+}
+
+export default function Uninstaller() {
+    const [state, setState] = useState<UninstallState | null>(null);
+    const [status, setStatus] = useState<'loading' | 'ready' | 'uninstalling' | 'complete' | 'error'>('loading');
+    const [logs, setLogs] = useState<string[]>([]);
+    const [errorMsg, setErrorMsg] = useState<string>('');
+    const logEndRef = useRef<HTMLDivElement>(null);
+
+    const addLog = (msg: string) => {
+        setLogs(prev => [...prev, `[${new Date().toLocaleTimeString()}] ${msg}`]);
+    };
+
+    const load = async () => {
+        try {
+            const loaded = await invoke<UninstallState>('get_uninstall_state');
+            setState(loaded);
+            setStatus('ready');
+            if (!loaded.installedAt) addLog(`No installation of ${loaded.appName} is recorded.`);
+        } catch (e) {
+            setStatus('error');
+            setErrorMsg(withHint(`Failed to read the install receipt: ${errorMessage(e)}`, e));
+        }
+    };
+
+    useEffect(() => {
+        load();
+        const unlistenPromise = listen<string>('log', (event) => {
+            addLog(event.payload);
+        });
+        return () => {
+            unlistenPromise.then(unlisten => unlisten());
+        };
+    }, []);
+
+    useEffect(() => {
+        logEndRef.current?.scrollIntoView({ behavior: 'smooth' });
+    }, [logs]);
+
+    const handleUninstall = async () => {
+        if (!state) return;
+        setStatus('uninstalling');
+        addLog('Revoking the decree...');
+        try {
+            await invoke('revert_patches', { appName: state.appName });
+            setStatus('complete');
+            addLog('Decree revoked.');
+        } catch (e) {
+            setStatus('error');
+            setErrorMsg(withHint(`Uninstall failed: ${errorMessage(e)}`, e));
+            addLog(`Error: ${errorMessage(e)}`);
+        }
+    };
+
+    if (status === 'loading') return <div className="container">Summoning uninstaller...</div>;
+    if (!state && status === 'error') return <div className="container error"><h1>Error</h1><p>{errorMsg}</p></div>;
+
+    return (
+        <div className="container">
+            <header className="header">
+                <div className="title-area">
+                    <h1>Uninstall {state?.appName}</h1>
+                    <p className="subtitle">Edition {state?.version} by {state?.publisher}</p>
+                </div>
+            </header>
+
+            <main className="main-content">
+                {state?.installedAt && status === 'ready' && (
+                    <p className="description">
+                        Installed {new Date(state.installedAt).toLocaleString()}. These files will be restored:
+                    </p>
+                )}
+                {status === 'ready' && state?.patchedFiles.map(file => <div key={file} className="log-line">{file}</div>)}
+
+                {status === 'error' && <div className="error-banner">{errorMsg}</div>}
+
+                <div className="log-panel">
+                    {logs.map((log, i) => <div key={i} className="log-line">{log}</div>)}
+                    <div ref={logEndRef} />
+                </div>
+
+                <div className="actions">
+                    {status === 'ready' && (
+                        <button className="btn-primary" onClick={handleUninstall} disabled={!state?.installedAt}>Uninstall</button>
+                    )}
+                    {status === 'error' && (
+                        <button className="btn-primary" onClick={handleUninstall}>Retry</button>
+                    )}
+                    {status === 'uninstalling' && (
+                        <button className="btn-primary" disabled>Revoking...</button>
+                    )}
+                    {status === 'complete' && (
+                        <button className="btn-success" disabled>Uninstalled</button>
+                    )}
+                </div>
+            </main>
+        </div>
+    );
+}
@@ -0,0 +1,84 @@
+// Shape of the errors returned by Tauri commands (see MisfitError in the engine crate).
+export type ErrorCode =
+    | 'manifest-not-found'
+    | 'invalid-manifest'
+    | 'payload-missing'
+    | 'integrity-failed'
+    | 'marker-not-found'
+    | 'permission-denied'
+    | 'file-locked'
+    | 'elevation-required'
+    | 'incompatible-target'
+    | 'backup-not-found'
+    | 'not-found'
+    | 'validation-failed'
+    | 'license-not-accepted'
+    | 'operation-in-progress'
+    | 'file-too-large'
+    | 'path-not-allowed'
+    | 'cancelled'
+    | 'sources-changed'
+    | 'failed';
+
+// One problem found by lint_manifest, or by build_project before it writes anything.
+export interface LintIssue {
+    severity: 'error' | 'warning' | 'info';
+    code: string;
+    message: string;
+    stepIndex?: number;
+}
+
+export interface MisfitError {
+    code: ErrorCode;
+    message: string;
+    path?: string;
+    stepIndex?: number;
+    issues?: LintIssue[];
+    exitCode?: number;
+}
+
+export function isMisfitError(e: unknown): e is MisfitError {
+    return typeof e === 'object' && e !== null && 'code' in e && 'message' in e;
+}
+
+export function errorMessage(e: unknown): string {
+    if (isMisfitError(e)) {
+        if (e.issues?.length) {
+            const lines = e.issues.map(i => (i.stepIndex !== undefined ? `Step ${i.stepIndex + 1}: ${i.message}` : i.message));
+            return [`Build blocked by ${e.issues.length} problem(s):`, ...lines].join('\n');
+        }
+        return e.stepIndex !== undefined ? `Step ${e.stepIndex + 1}: ${e.message}` : e.message;
+    }
+    return String(e);
+}
+
+// What the user can do about it, when there is something better than retrying.
+export function recoveryHint(e: unknown): string | null {
+    if (!isMisfitError(e)) return null;
+    const where = e.path ? ` (${e.path})` : '';
+    switch (e.code) {
+        case 'permission-denied':
+        case 'elevation-required':
+            return `Run the installer as administrator${where}.`;
+        case 'file-locked':
+            return `Close the application using this file and try again${where}.`;
+        case 'incompatible-target':
+            return `Install a supported version of the target app, or get an installer built for this one${where}.`;
+        case 'marker-not-found':
+            return `The target file does not contain the expected markers; it may be a different version${where}.`;
+        case 'payload-missing':
+            return `Payload files are missing; keep the payloads folder next to the installer${where}.`;
+        case 'integrity-failed':
+            return 'Installer files were modified or are incomplete; download the installer again.';
+        case 'operation-in-progress':
+            return 'Another installer or build is working on the same files; wait for it to finish and try again.';
+        case 'file-too-large':
+            return `The file is too large to open here${where}; raise the text file limit in the settings to open it anyway.`;
+        case 'license-not-accepted':
+            return 'Read and accept the license agreement to install.';
+        case 'backup-not-found':
+            return 'No backup exists yet for this app.';
+        default:
+            return null;
+    }
+}
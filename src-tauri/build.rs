@@ -1,3 +1,6 @@
 fn main() {
-  tauri_build::build()
+  // Tauri's default manifest plus longPathAware, so paths past MAX_PATH work outside std::fs too
+  // (dialogs, the webview, shell APIs) once long paths are enabled in Windows.
+  let windows = tauri_build::WindowsAttributes::new().app_manifest(include_str!("windows-app-manifest.xml"));
+  tauri_build::try_build(tauri_build::Attributes::new().windows_attributes(windows)).expect("failed to run tauri-build");
 }
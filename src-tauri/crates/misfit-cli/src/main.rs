@@ -0,0 +1,331 @@
+//! `misfit`: runs installer manifests from a terminal or CI job using the same engine as the
+//! desktop app, without Tauri.
+
+use anyhow::{anyhow, Result};
+use misfit_engine::{self as engine, InstallObserver};
+use std::path::{Path, PathBuf};
+
+const EXIT_OK: i32 = 0;
+const EXIT_FAILED: i32 = 1;
+const EXIT_USAGE: i32 = 2;
+
+const USAGE: &str = "Usage: misfit <command> [options]
+
+Commands:
+  install <manifest>     Run every install step
+  resume <manifest>      Continue a failed or interrupted install, skipping finished steps
+  repair <manifest>      Re-apply only the steps whose changes are missing, e.g. after an app update
+  preflight <manifest>   Check free space and file access without changing anything
+  export-script <manifest>
+                         Print the steps as a PowerShell or bash script (see --format)
+  uninstall <manifest>   Revert every patch recorded by previous installs
+  restore <manifest>     Restore the latest backup (or --backup-id) for the manifest's app
+  backups [<manifest>]   List backups, for the manifest's app only when one is given
+
+Options:
+  --target <path>        Resolve relative target paths from <path> instead of the manifest folder
+  --backup-dir <path>    Keep backups under <path> (overrides MISFIT_BACKUP_DIR and backupDir)
+  --backup-id <id>       Backup to restore, as printed by `misfit backups`
+  --phase <name>         Only run one phase: prepare, install, configure or finish
+  --components <ids>     Comma-separated optional components to install, repair or export (default:
+                         the manifest's defaults)
+  --profile <name>       Apply the manifest's profile <name> (e.g. dev, prod) before running
+  --format <name>        Script for export-script: powershell or bash (default for this system)
+  --allow-commands       Run the manifest's commands (only advancedMode manifests run them otherwise)
+  --accept-license       Accept the manifest's licenseFile (installs of a licensed manifest need it)
+  --quiet                Only print errors
+
+Exit codes: 0 success, 1 failure, 2 invalid arguments";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Command {
+    Install,
+    Resume,
+    Repair,
+    Preflight,
+    ExportScript,
+    Uninstall,
+    Restore,
+    Backups,
+}
+
+#[derive(Debug)]
+struct Args {
+    command: Command,
+    manifest: Option<PathBuf>,
+    target: Option<PathBuf>,
+    backup_dir: Option<PathBuf>,
+    backup_id: Option<String>,
+    phase: Option<engine::Phase>,
+    components: Option<Vec<String>>,
+    profile: Option<String>,
+    format: Option<engine::ScriptFormat>,
+    quiet: bool,
+    allow_commands: bool,
+    accept_license: bool,
+}
+
+fn parse_args<I: IntoIterator<Item = String>>(args: I) -> Result<Args, String> {
+    let mut args = args.into_iter();
+    let command = match args.next().as_deref() {
+        Some("install") => Command::Install,
+        Some("resume") => Command::Resume,
+        Some("repair") => Command::Repair,
+        Some("preflight") => Command::Preflight,
+        Some("export-script") => Command::ExportScript,
+        Some("uninstall") => Command::Uninstall,
+        Some("restore") => Command::Restore,
+        Some("backups") => Command::Backups,
+        Some(other) => return Err(format!("Unknown command: {}", other)),
+        None => return Err("No command given".to_string()),
+    };
+
+    let mut parsed = Args {
+        command,
+        manifest: None,
+        target: None,
+        backup_dir: None,
+        backup_id: None,
+        phase: None,
+        components: None,
+        profile: None,
+        format: None,
+        quiet: false,
+        allow_commands: false,
+        accept_license: false,
+    };
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--quiet" | "-q" => parsed.quiet = true,
+            "--allow-commands" => parsed.allow_commands = true,
+            "--accept-license" => parsed.accept_license = true,
+            "--target" => parsed.target = Some(args.next().map(PathBuf::from).ok_or("--target needs a path")?),
+            "--backup-dir" => parsed.backup_dir = Some(args.next().map(PathBuf::from).ok_or("--backup-dir needs a path")?),
+            "--backup-id" => parsed.backup_id = Some(args.next().ok_or("--backup-id needs an id")?),
+            "--phase" => {
+                let name = args.next().ok_or("--phase needs a name")?;
+                let phase = serde_json::from_value(serde_json::Value::String(name.clone())).map_err(|_| format!("Unknown phase: {}", name))?;
+                parsed.phase = Some(phase);
+            }
+            "--components" => {
+                let ids = args.next().ok_or("--components needs a list of ids")?;
+                parsed.components = Some(ids.split(',').map(|id| id.trim().to_string()).filter(|id| !id.is_empty()).collect());
+            }
+            "--profile" => parsed.profile = Some(args.next().ok_or("--profile needs a name")?),
+            "--format" => {
+                let name = args.next().ok_or("--format needs a name")?;
+                let format = serde_json::from_value(serde_json::Value::String(name.clone())).map_err(|_| format!("Unknown script format: {}", name))?;
+                parsed.format = Some(format);
+            }
+            flag if flag.starts_with('-') => return Err(format!("Unknown option: {}", flag)),
+            _ if parsed.manifest.is_none() => parsed.manifest = Some(PathBuf::from(arg)),
+            _ => return Err(format!("Unexpected argument: {}", arg)),
+        }
+    }
+
+    if parsed.manifest.is_none() && command != Command::Backups {
+        return Err("A manifest path is required".to_string());
+    }
+    Ok(parsed)
+}
+
+/// Prints engine output to the terminal. Overwrite prompts are declined since there is
+/// nobody to answer them; commands run only with `--allow-commands`.
+struct ConsoleObserver {
+    quiet: bool,
+    allow_commands: bool,
+}
+
+impl InstallObserver for ConsoleObserver {
+    fn log(&self, message: &str) {
+        if !self.quiet {
+            println!("{}", message);
+        }
+    }
+
+    fn event(&self, name: &str, payload: serde_json::Value) {
+        match name {
+            "integrity-warning" | "elevation-required" => {
+                eprintln!("{}", payload.as_str().map(str::to_string).unwrap_or_else(|| payload.to_string()))
+            }
+            "copy-progress" if !self.quiet => println!(
+                "Copied {}/{} files",
+                payload["filesCopied"].as_u64().unwrap_or(0),
+                payload["totalFiles"].as_u64().unwrap_or(0)
+            ),
+            _ => {}
+        }
+    }
+
+    fn confirm_command(&self, _command: &str, command_line: &str) -> bool {
+        if !self.allow_commands {
+            eprintln!("Not running `{}`; pass --allow-commands to run the manifest's commands", command_line);
+        }
+        self.allow_commands
+    }
+}
+
+fn backups_root(args: &Args, manifest: Option<&engine::InstallManifest>, manifest_dir: &Path) -> Result<PathBuf> {
+    if let Some(dir) = &args.backup_dir {
+        return Ok(dir.clone());
+    }
+    if let Some(dir) = engine::configured_backups_root(manifest, manifest_dir) {
+        return Ok(dir);
+    }
+    let home = engine::home_dir().ok_or_else(|| anyhow!("Could not find the home folder; pass --backup-dir"))?;
+    Ok(home.join("Documents").join("MisfitBackups"))
+}
+
+fn run(args: &Args) -> Result<()> {
+    let observer = ConsoleObserver { quiet: args.quiet, allow_commands: args.allow_commands };
+    let manifest = args.manifest.as_deref().map(engine::load_manifest).transpose()?;
+    let manifest = manifest.map(|m| engine::apply_profile(&m, args.profile.as_deref())).transpose()?;
+    let manifest_dir = args
+        .manifest
+        .as_deref()
+        .and_then(Path::parent)
+        .map(Path::to_path_buf)
+        .unwrap_or_else(|| PathBuf::from("."));
+    let backups_root = backups_root(args, manifest.as_ref(), &manifest_dir)?;
+
+    let Some(manifest) = manifest else {
+        return print_backups(&backups_root, None);
+    };
+    let namespace = engine::backup_namespace(&manifest.app_name);
+
+    match args.command {
+        Command::Install | Command::Resume | Command::Repair | Command::Preflight | Command::ExportScript => {
+            let project_root = engine::project_root_for_manifest(args.manifest.as_deref().unwrap_or(Path::new(".")));
+            // Holds an extracted payload folder until the command is done
+            let payload = engine::resolve_payload_source(&project_root, &manifest.payload_dir)?;
+            let payload_source = payload.path().to_path_buf();
+            if args.command == Command::ExportScript {
+                let default = if cfg!(windows) { engine::ScriptFormat::Powershell } else { engine::ScriptFormat::Bash };
+                print!("{}", engine::export_install_script(&manifest, &payload_source, args.components.as_deref(), args.format.unwrap_or(default))?);
+                return Ok(());
+            }
+            let target_base = args.target.clone().unwrap_or_else(|| manifest_dir.clone());
+            if args.command == Command::Preflight {
+                let report = engine::preflight_manifest(&manifest, &target_base, &payload_source, &backups_root)?;
+                println!("{}", serde_json::to_string_pretty(&report)?);
+                return if report.ok { Ok(()) } else { Err(anyhow!("Preflight check failed")) };
+            }
+            let options = engine::InstallOptions {
+                manifest_dir,
+                payload_source,
+                target_base,
+                backups_root,
+                trusted_key: None,
+                phase: args.phase,
+                resume: args.command == Command::Resume,
+                components: args.components.clone(),
+                prompt_values: engine::prompt_values_from_env(&manifest),
+                accepted_license: args.accept_license,
+            };
+            if args.command == Command::Repair {
+                return print_repair(&engine::repair_install(&manifest, &options, &observer)?);
+            }
+            engine::run_install(&manifest, &options, &observer).map(|_| ())
+        }
+        Command::Uninstall => {
+            engine::revert_all_patches(&backups_root.join(namespace), &observer)?;
+            engine::unregister_program(&manifest.app_name)
+        }
+        Command::Restore => {
+            let restored = match &args.backup_id {
+                Some(id) => {
+                    let backup_dir = engine::resolve_backup_id(&backups_root, id)?;
+                    let _lock = engine::lock_operation(backup_dir.parent().unwrap_or(&backups_root), "restore")?;
+                    engine::restore_backup_dir(&backup_dir)?;
+                    id.clone()
+                }
+                None => engine::restore_latest_backup(&backups_root.join(namespace))?,
+            };
+            observer.log(&format!("Restored successfully from {}", restored));
+            Ok(())
+        }
+        Command::Backups => print_backups(&backups_root, Some(&namespace)),
+    }
+}
+
+fn print_repair(report: &engine::RepairReport) -> Result<()> {
+    for step in &report.steps {
+        let status = serde_json::to_value(step.status)?;
+        let detail = step.error.as_deref().or(step.problem.as_deref()).unwrap_or_default();
+        println!("{}\t{}\t{}\t{}", step.index + 1, step.kind, status.as_str().unwrap_or_default(), detail);
+    }
+    match report.count(engine::RepairStatus::Failed) {
+        0 => Ok(()),
+        failed => Err(anyhow!("{} step(s) could not be repaired", failed)),
+    }
+}
+
+fn print_backups(backups_root: &Path, namespace: Option<&str>) -> Result<()> {
+    for backup in engine::list_backups(backups_root, namespace)? {
+        println!(
+            "{}\t{}\t{} file(s)\t{} bytes",
+            backup.id,
+            backup.created_at.as_deref().unwrap_or("-"),
+            backup.file_count,
+            backup.total_bytes
+        );
+    }
+    Ok(())
+}
+
+fn main() {
+    let code = match parse_args(std::env::args().skip(1)) {
+        Ok(args) => match run(&args) {
+            Ok(()) => EXIT_OK,
+            Err(err) => {
+                eprintln!("Error: {:#}", err);
+                EXIT_FAILED
+            }
+        },
+        Err(err) => {
+            eprintln!("{}\n\n{}", err, USAGE);
+            EXIT_USAGE
+        }
+    };
+    std::process::exit(code);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<Args, String> {
+        parse_args(args.iter().map(|a| a.to_string()))
+    }
+
+    #[test]
+    fn parses_install_options() {
+        let args = parse(&["install", "manifests/app.json", "--target", "C:/Apps", "--quiet", "--phase", "configure"]).expect("parse");
+        assert_eq!(args.phase, Some(engine::Phase::Configure));
+        assert_eq!(args.command, Command::Install);
+        assert_eq!(args.manifest, Some(PathBuf::from("manifests/app.json")));
+        assert_eq!(args.target, Some(PathBuf::from("C:/Apps")));
+        assert!(args.quiet);
+
+        let args = parse(&["install", "manifests/app.json", "--components", "core, extras", "--profile", "insiders"]).expect("parse");
+        assert_eq!(args.components, Some(vec!["core".to_string(), "extras".to_string()]));
+        assert_eq!(args.profile.as_deref(), Some("insiders"));
+
+        let args = parse(&["export-script", "manifests/app.json", "--format", "bash"]).expect("parse");
+        assert_eq!(args.command, Command::ExportScript);
+        assert_eq!(args.format, Some(engine::ScriptFormat::Bash));
+    }
+
+    #[test]
+    fn rejects_bad_arguments() {
+        assert!(parse(&[]).is_err());
+        assert!(parse(&["deploy"]).is_err());
+        assert!(parse(&["install"]).is_err());
+        assert!(parse(&["restore", "app.json", "--backup-id"]).is_err());
+        assert!(parse(&["install", "app.json", "--profile"]).is_err());
+        assert!(parse(&["install", "a.json", "b.json"]).is_err());
+        assert!(parse(&["install", "a.json", "--phase", "cleanup"]).is_err());
+        assert!(parse(&["export-script", "a.json", "--format", "zsh"]).is_err());
+        assert!(parse(&["backups"]).expect("parse").manifest.is_none());
+    }
+}
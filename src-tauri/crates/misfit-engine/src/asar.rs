@@ -0,0 +1,237 @@
+//! Files inside Electron `.asar` archives. A step's `file` can point into one with `!`, e.g.
+//! `resources/app.asar!/out/main.js`: reads take the file out of the archive and writes
+//! repack it with the new contents. Backups and restores work on the whole archive.
+//!
+//! An archive is a small header (a Chromium pickle holding a JSON index of every file's size
+//! and offset) followed by the file contents back to back. Files marked `unpacked` live in
+//! `app.asar.unpacked` next to it instead.
+
+use crate::*;
+use std::io::{Read, Seek, SeekFrom, Write};
+
+/// Block size Electron uses for the per-block hashes in `integrity`.
+const INTEGRITY_BLOCK_SIZE: usize = 4 * 1024 * 1024;
+
+/// Splits `archive.asar!/inner/path` into the archive and the path inside it.
+pub fn split_asar_path(path: &Path) -> Option<(PathBuf, String)> {
+    let text = path.to_string_lossy();
+    let at = text.to_ascii_lowercase().find(".asar!")? + ".asar".len();
+    let inner = text[at + 1..].replace('\\', "/");
+    let inner = inner.trim_matches('/');
+    (!inner.is_empty()).then(|| (PathBuf::from(&text[..at]), inner.to_string()))
+}
+
+/// The file on disk that holds `path`: the archive for a path inside one, else `path` itself.
+/// Backups and free-space checks use this.
+pub fn containing_file(path: &Path) -> PathBuf {
+    split_asar_path(path).map_or_else(|| path.to_path_buf(), |(archive, _)| archive)
+}
+
+/// Reads `path`, which may point into an asar archive.
+pub fn read_target(path: &Path) -> Result<Vec<u8>> {
+    let Some((archive, inner)) = split_asar_path(path) else { return Ok(fs::read(path)?) };
+    let index = AsarIndex::open(&archive)?;
+    let entry = index.entry(&inner)?;
+    if entry.get("unpacked").and_then(|v| v.as_bool()).unwrap_or(false) {
+        return Ok(fs::read(unpacked_path(&archive, &inner))?);
+    }
+    let (offset, size) = entry_span(entry, &inner)?;
+    let mut file = fs::File::open(&archive)?;
+    file.seek(SeekFrom::Start(index.data_start + offset))?;
+    let mut bytes = vec![0; size as usize];
+    file.read_exact(&mut bytes).with_context(|| format!("{} is truncated", archive.display()))?;
+    Ok(bytes)
+}
+
+/// Writes `path`, repacking the archive when it points into one. Only files that already
+/// exist in the archive can be written.
+pub fn write_target(path: &Path, contents: &[u8]) -> Result<()> {
+    let Some((archive, inner)) = split_asar_path(path) else { return Ok(write_atomic(path, contents)?) };
+    let mut index = AsarIndex::open(&archive)?;
+    if index.entry(&inner)?.get("unpacked").and_then(|v| v.as_bool()).unwrap_or(false) {
+        let unpacked = unpacked_path(&archive, &inner);
+        return Ok(write_atomic(&unpacked, contents)?);
+    }
+    index.repack(&archive, &inner, contents)
+}
+
+fn unpacked_path(archive: &Path, inner: &str) -> PathBuf {
+    let mut unpacked = archive.as_os_str().to_owned();
+    unpacked.push(".unpacked");
+    PathBuf::from(unpacked).join(inner)
+}
+
+/// JSON pointer to the entry of `inner` in the index.
+fn entry_pointer(inner: &str) -> String {
+    inner.split('/').map(|name| format!("/files/{}", name.replace('~', "~0"))).collect()
+}
+
+fn entry_span(entry: &serde_json::Value, inner: &str) -> Result<(u64, u64)> {
+    let offset = entry.get("offset").and_then(|v| v.as_str()).and_then(|v| v.parse().ok());
+    let size = entry.get("size").and_then(|v| v.as_u64());
+    match (offset, size) {
+        (Some(offset), Some(size)) => Ok((offset, size)),
+        _ => Err(anyhow!("{} is not a regular file in the archive", inner)),
+    }
+}
+
+struct AsarIndex {
+    header: serde_json::Value,
+    /// Where file contents start: offsets in the index are relative to this.
+    data_start: u64,
+}
+
+impl AsarIndex {
+    fn open(archive: &Path) -> Result<AsarIndex> {
+        let invalid = || MisfitError::new(ErrorCode::Failed, format!("{} is not a valid asar archive", archive.display())).with_path(archive);
+        let mut file = fs::File::open(archive).map_err(|e| MisfitError::from(e).with_path(archive))?;
+        let mut prefix = [0u8; 16];
+        file.read_exact(&mut prefix).map_err(|_| invalid())?;
+        let word = |at: usize| u32::from_le_bytes([prefix[at], prefix[at + 1], prefix[at + 2], prefix[at + 3]]);
+        let (header_size, json_len) = (word(4) as u64, word(12) as usize);
+        if word(0) != 4 || json_len as u64 + 8 > header_size {
+            return Err(invalid().into());
+        }
+        let mut json = vec![0; json_len];
+        file.read_exact(&mut json).map_err(|_| invalid())?;
+        let header = serde_json::from_slice(&json).map_err(|_| invalid())?;
+        Ok(AsarIndex { header, data_start: 8 + header_size })
+    }
+
+    fn entry(&self, inner: &str) -> Result<&serde_json::Value> {
+        let entry = self
+            .header
+            .pointer(&entry_pointer(inner))
+            .ok_or_else(|| MisfitError::new(ErrorCode::NotFound, format!("{} not found in the archive", inner)))?;
+        if entry.get("link").is_some() || entry.get("files").is_some() {
+            return Err(anyhow!("{} is not a regular file in the archive", inner));
+        }
+        Ok(entry)
+    }
+
+    /// Rewrites `archive` with `contents` for `inner`, moving the files after it, and swaps
+    /// the result in once it is complete.
+    fn repack(&mut self, archive: &Path, inner: &str, contents: &[u8]) -> Result<()> {
+        let mut packed = Vec::new();
+        collect_packed(&self.header, String::new(), &mut packed);
+        packed.sort_by_key(|(_, offset, _)| *offset);
+
+        let target = entry_pointer(inner);
+        let mut next = 0u64;
+        for (pointer, _, size) in &packed {
+            let size = if *pointer == target { contents.len() as u64 } else { *size };
+            let entry = self.header.pointer_mut(pointer).and_then(|e| e.as_object_mut()).ok_or_else(|| anyhow!("Broken asar index"))?;
+            entry.insert("offset".to_string(), serde_json::Value::String(next.to_string()));
+            entry.insert("size".to_string(), serde_json::json!(size));
+            if *pointer == target && entry.contains_key("integrity") {
+                entry.insert("integrity".to_string(), integrity(contents));
+            }
+            next += size;
+        }
+
+        let json = serde_json::to_vec(&self.header)?;
+        let padded = (json.len() + 3) & !3;
+        let mut header = Vec::with_capacity(16 + padded);
+        for word in [4, padded as u32 + 8, padded as u32 + 4, json.len() as u32] {
+            header.extend_from_slice(&word.to_le_bytes());
+        }
+        header.extend_from_slice(&json);
+        header.resize(16 + padded, 0);
+
+        let mut temp = archive.as_os_str().to_owned();
+        temp.push(".misfit-tmp");
+        let temp = PathBuf::from(temp);
+        let written = (|| -> Result<()> {
+            let mut source = fs::File::open(archive)?;
+            let mut out = std::io::BufWriter::new(fs::File::create(&temp)?);
+            out.write_all(&header)?;
+            for (pointer, offset, size) in &packed {
+                if *pointer == target {
+                    out.write_all(contents)?;
+                } else {
+                    source.seek(SeekFrom::Start(self.data_start + offset))?;
+                    let copied = std::io::copy(&mut (&mut source).take(*size), &mut out)?;
+                    if copied != *size {
+                        return Err(anyhow!("{} is truncated", archive.display()));
+                    }
+                }
+            }
+            out.flush()?;
+            out.get_ref().sync_all()?;
+            fs::set_permissions(&temp, fs::metadata(archive)?.permissions())?;
+            Ok(())
+        })();
+        if let Err(err) = written.and_then(|_| Ok(retry_if_locked(|| fs::rename(&temp, archive))?)) {
+            let _ = fs::remove_file(&temp);
+            return Err(MisfitError::from(err).with_path(archive).into());
+        }
+        self.data_start = header.len() as u64;
+        Ok(())
+    }
+}
+
+/// Pointer, offset and size of every file stored in the archive itself.
+fn collect_packed(node: &serde_json::Value, pointer: String, out: &mut Vec<(String, u64, u64)>) {
+    let Some(files) = node.get("files").and_then(|f| f.as_object()) else { return };
+    for (name, entry) in files {
+        let pointer = format!("{}/files/{}", pointer, name.replace('~', "~0"));
+        if entry.get("files").is_some() {
+            collect_packed(entry, pointer, out);
+        } else if !entry.get("unpacked").and_then(|v| v.as_bool()).unwrap_or(false) {
+            if let Ok((offset, size)) = entry_span(entry, name) {
+                out.push((pointer, offset, size));
+            }
+        }
+    }
+}
+
+/// The `integrity` record Electron checks: a hash of the whole file and of each block.
+fn integrity(contents: &[u8]) -> serde_json::Value {
+    serde_json::json!({
+        "algorithm": "SHA256",
+        "hash": sha256_hex(contents),
+        "blockSize": INTEGRITY_BLOCK_SIZE,
+        "blocks": contents.chunks(INTEGRITY_BLOCK_SIZE).map(sha256_hex).collect::<Vec<_>>(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::{spec, temp_file};
+
+    #[test]
+    fn asar_files_are_patched_in_place() {
+        // Two files back to back, as `asar pack` lays them out
+        let header = serde_json::json!({ "files": { "out": { "files": {
+            "main.js": { "size": 26, "offset": "0", "integrity": { "algorithm": "SHA256", "hash": "", "blockSize": 4194304, "blocks": [] } },
+            "other.js": { "size": 5, "offset": "26" }
+        } } } });
+        let json = serde_json::to_vec(&header).unwrap();
+        let padded = (json.len() + 3) & !3;
+        let mut archive_bytes = Vec::new();
+        for word in [4u32, padded as u32 + 8, padded as u32 + 4, json.len() as u32] {
+            archive_bytes.extend_from_slice(&word.to_le_bytes());
+        }
+        archive_bytes.extend_from_slice(&json);
+        archive_bytes.resize(16 + padded, 0);
+        archive_bytes.extend_from_slice(b"a/*S*/old/*E*/b // 26 byte");
+        archive_bytes.extend_from_slice(b"other");
+        let archive = temp_file("asar/resources/app.asar", "");
+        std::fs::write(&archive, &archive_bytes).unwrap();
+
+        let main = PathBuf::from(format!("{}!/out/main.js", archive.display()));
+        assert_eq!(split_asar_path(&main), Some((archive.clone(), "out/main.js".to_string())));
+        assert_eq!(containing_file(&main), archive);
+        patch_file(&main, &spec("/*S*/", "/*E*/", "a much longer replacement", false)).expect("patch");
+
+        let patched = read_target(&main).unwrap();
+        assert_eq!(String::from_utf8(patched.clone()).unwrap(), "a/*S*/a much longer replacement/*E*/b // 26 byte");
+        assert_eq!(read_target(Path::new(&format!("{}!/out/other.js", archive.display()))).unwrap(), b"other");
+        let repacked = std::fs::read(&archive).unwrap();
+        let json_len = u32::from_le_bytes(repacked[12..16].try_into().unwrap()) as usize;
+        let index: serde_json::Value = serde_json::from_slice(&repacked[16..16 + json_len]).unwrap();
+        assert_eq!(index["files"]["out"]["files"]["main.js"]["integrity"]["hash"], sha256_hex(&patched));
+        assert!(read_target(Path::new(&format!("{}!/out/missing.js", archive.display()))).is_err());
+    }
+}
@@ -0,0 +1,91 @@
+//! The manifest's `branding`: colors, window title, welcome text and logo that make a generated
+//! installer look like the author's product. The Studio copies the logo into the build's
+//! `manifests/branding/` folder and points `logo` at the copy.
+
+use crate::*;
+
+/// Folder next to the built manifest that holds the branding logo.
+pub const BRANDING_DIR: &str = "branding";
+
+/// Logo formats the installer window can show, by extension, with their MIME type.
+pub const LOGO_FORMATS: &[(&str, &str)] = &[
+    ("png", "image/png"),
+    ("jpg", "image/jpeg"),
+    ("jpeg", "image/jpeg"),
+    ("gif", "image/gif"),
+    ("webp", "image/webp"),
+    ("svg", "image/svg+xml"),
+    ("ico", "image/x-icon"),
+];
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct Branding {
+    /// Buttons, links and highlights, as a hex color (`#2f7a5b`).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub accent_color: Option<String>,
+    /// Window background, as a hex color.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub background: Option<String>,
+    /// Title bar text; the app name when omitted.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub window_title: Option<LocalizedText>,
+    /// Shown above the install button instead of the description.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub welcome_text: Option<LocalizedText>,
+    /// Image file: the source path in the Studio, relative to the manifest folder once built.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub logo: Option<String>,
+}
+
+/// `#rgb`, `#rgba`, `#rrggbb` or `#rrggbbaa`.
+pub(crate) fn is_hex_color(color: &str) -> bool {
+    color
+        .strip_prefix('#')
+        .is_some_and(|hex| matches!(hex.len(), 3 | 4 | 6 | 8) && hex.chars().all(|c| c.is_ascii_hexdigit()))
+}
+
+pub(crate) fn logo_mime(logo: &str) -> Option<&'static str> {
+    let extension = Path::new(logo).extension()?.to_string_lossy().to_ascii_lowercase();
+    LOGO_FORMATS.iter().find(|(ext, _)| *ext == extension).map(|(_, mime)| *mime)
+}
+
+/// The branding logo as a `data:` URL the installer window can show, resolved from
+/// `manifest_dir`. `None` without a logo.
+pub fn branding_logo_data_url(manifest: &InstallManifest, manifest_dir: &Path) -> Result<Option<String>> {
+    use base64::Engine as _;
+    let Some(logo) = manifest.branding.as_ref().and_then(|b| b.logo.as_deref()).filter(|l| !l.trim().is_empty()) else {
+        return Ok(None);
+    };
+    let mime = logo_mime(logo).ok_or_else(|| MisfitError::new(ErrorCode::InvalidManifest, format!("Unsupported logo format: {}", logo)))?;
+    let path = resolve_path(manifest_dir, logo);
+    let bytes = fs::read(&path).map_err(|e| MisfitError::from(e).with_path(&path))?;
+    Ok(Some(format!("data:{};base64,{}", mime, base64::engine::general_purpose::STANDARD.encode(bytes))))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::{temp_file, test_manifest};
+
+    #[test]
+    fn branding_colors_and_logo_are_checked_and_the_logo_is_served_inline() {
+        let logo = temp_file("branding/manifests/branding/logo.svg", "<svg/>");
+        let manifest_dir = logo.parent().unwrap().parent().unwrap().to_path_buf();
+        let mut manifest = test_manifest("Branded", serde_json::json!({
+            "installSteps": [],
+            "branding": { "accentColor": "#ff6600", "background": "#FFF", "windowTitle": { "en": "Setup", "de": "Einrichtung" }, "logo": "branding/logo.svg" }
+        }));
+        assert!(lint_manifest(&manifest, None).is_empty());
+        let data_url = branding_logo_data_url(&manifest, &manifest_dir).unwrap().unwrap();
+        assert_eq!(data_url, "data:image/svg+xml;base64,PHN2Zy8+");
+        let german = manifest.localized("de");
+        assert_eq!(german.branding.unwrap().window_title, Some(LocalizedText::from("Einrichtung")));
+
+        let branding = manifest.branding.as_mut().unwrap();
+        branding.accent_color = Some("orange".to_string());
+        branding.logo = Some("branding/logo.bmp".to_string());
+        let codes: Vec<&str> = lint_manifest(&manifest, None).iter().map(|issue| issue.code).collect();
+        assert_eq!(codes, ["branding-color", "branding-logo"]);
+    }
+}
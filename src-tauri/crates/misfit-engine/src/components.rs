@@ -0,0 +1,131 @@
+//! Optional components: named groups of install steps (a step's `component`) the user can
+//! leave out, for a "choose features" screen. Steps without a component always run.
+
+use crate::*;
+use std::collections::BTreeSet;
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct ManifestComponent {
+    pub id: String,
+    pub name: LocalizedText,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<LocalizedText>,
+    /// Whether the component is selected when the user doesn't choose.
+    #[serde(default = "selected_by_default")]
+    pub default: bool,
+    /// Ids of components this one needs; selecting it selects them too.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub depends_on: Vec<String>,
+}
+
+fn selected_by_default() -> bool {
+    true
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct ComponentSummary {
+    #[serde(flatten)]
+    pub component: ManifestComponent,
+    /// Steps of the component that run on this platform.
+    pub step_count: usize,
+}
+
+/// The manifest's components in declaration order, for the selection screen.
+pub fn list_components(manifest: &InstallManifest) -> Vec<ComponentSummary> {
+    let manifest = manifest.for_platform(Platform::current());
+    manifest
+        .components
+        .iter()
+        .map(|component| ComponentSummary {
+            step_count: manifest.ordered_steps(None).iter().filter(|(_, s)| s.component.as_deref() == Some(component.id.as_str())).count(),
+            component: component.clone(),
+        })
+        .collect()
+}
+
+/// The components an install runs: `requested` plus everything they depend on, or the
+/// defaults (and their dependencies) when nothing was requested. Unknown ids are an error.
+pub fn resolve_components(manifest: &InstallManifest, requested: Option<&[String]>) -> Result<BTreeSet<String>> {
+    let find = |id: &str| manifest.components.iter().find(|c| c.id == id);
+    let mut pending: Vec<String> = match requested {
+        Some(ids) => ids.to_vec(),
+        None => manifest.components.iter().filter(|c| c.default).map(|c| c.id.clone()).collect(),
+    };
+    let mut selected = BTreeSet::new();
+    while let Some(id) = pending.pop() {
+        let component = find(&id).ok_or_else(|| MisfitError::new(ErrorCode::InvalidManifest, format!("Unknown component {:?}", id)))?;
+        if selected.insert(id) {
+            pending.extend(component.depends_on.iter().cloned());
+        }
+    }
+    Ok(selected)
+}
+
+impl ManifestStep {
+    /// Whether the step runs with `selected` components.
+    pub fn in_components(&self, selected: &BTreeSet<String>) -> bool {
+        self.component.as_ref().map_or(true, |id| selected.contains(id))
+    }
+}
+
+impl InstallManifest {
+    /// The manifest without the steps of components outside `selected`. Step indexes shift,
+    /// so this is for checks over the whole install (e.g. preflight), not for running it.
+    pub fn with_components(&self, selected: &BTreeSet<String>) -> InstallManifest {
+        let mut manifest = self.clone();
+        manifest.install_steps.retain(|step| step.in_components(selected));
+        manifest
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::{Quiet, install_options, temp_file, test_manifest};
+
+    #[test]
+    fn run_install_skips_unselected_components() {
+        let target_base = temp_file("components/target/keep.txt", "x").parent().unwrap().to_path_buf();
+        let payload = temp_file("components/payload/core.txt", "core").parent().unwrap().to_path_buf();
+        temp_file("components/payload/extra.txt", "extra");
+        temp_file("components/payload/dep.txt", "dep");
+        let manifest = test_manifest("Components", serde_json::json!({
+            "components": [
+                { "id": "extras", "name": "Extras", "dependsOn": ["base"] },
+                { "id": "base", "name": "Base", "default": false },
+                { "id": "docs", "name": "Docs", "default": false }
+            ],
+            "installSteps": [
+                { "type": "copy", "src": "core.txt", "dest": "core.txt" },
+                { "type": "copy", "src": "extra.txt", "dest": "extra.txt", "component": "extras" },
+                { "type": "copy", "src": "dep.txt", "dest": "dep.txt", "component": "base" },
+                { "type": "copy", "src": "core.txt", "dest": "docs.txt", "component": "docs" }
+            ]
+        }));
+        assert!(lint_manifest(&manifest, None).iter().all(|issue| issue.code != "unknown-component"));
+        let defaults: Vec<String> = resolve_components(&manifest, None).unwrap().into_iter().collect();
+        assert_eq!(defaults, ["base", "extras"]);
+        assert!(resolve_components(&manifest, Some(&["nope".to_string()])).is_err());
+        assert_eq!(list_components(&manifest)[0].step_count, 1);
+
+        // The plan, simulation and script cover the same steps as the install
+        let docs = ["docs".to_string()];
+        let planned: Vec<usize> = resolve_install_plan(&manifest, &target_base, &payload, None).unwrap().steps.iter().map(|s| s.index).collect();
+        assert_eq!(planned, [0, 1, 2]);
+        let planned: Vec<usize> = resolve_install_plan(&manifest, &target_base, &payload, Some(&docs)).unwrap().steps.iter().map(|s| s.index).collect();
+        assert_eq!(planned, [0, 3]);
+        let simulated = simulate_install(&manifest, &target_base, &payload, Some(&docs), &BTreeMap::new(), &Quiet).unwrap();
+        assert_eq!(simulated.steps.iter().map(|s| s.index).collect::<Vec<_>>(), [0, 3]);
+        let script = export_install_script(&manifest, &payload, Some(&docs), ScriptFormat::Bash).unwrap();
+        assert!(script.contains("docs.txt") && !script.contains("extra.txt"));
+
+        let options = InstallOptions { manifest_dir: payload.clone(), components: Some(vec!["docs".to_string()]), ..install_options(&target_base, payload, target_base.join("backups")) };
+        run_install(&manifest, &options, &Quiet).expect("install");
+        assert!(target_base.join("core.txt").exists());
+        assert!(target_base.join("docs.txt").exists());
+        assert!(!target_base.join("extra.txt").exists());
+        assert!(!target_base.join("dep.txt").exists());
+    }
+}
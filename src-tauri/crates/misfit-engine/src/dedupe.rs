@@ -0,0 +1,119 @@
+//! Payload files with the same contents. `analyze_payload_files` reports them; a deduplicated
+//! payload archive stores each such file once plus a list of the other paths it stands for,
+//! and extraction writes the copies back before anything reads the payload.
+
+use crate::*;
+
+/// Entry at the root of a deduplicated archive: duplicate path → the stored file it repeats.
+pub const PAYLOAD_LINKS_FILE: &str = ".misfit-links.json";
+
+#[derive(Serialize, Debug, Clone, Default, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct PayloadAnalysis {
+    pub file_count: usize,
+    pub total_bytes: u64,
+    /// Bytes spent on second and later copies; what deduplication saves before compression.
+    pub wasted_bytes: u64,
+    /// Largest waste first.
+    pub duplicates: Vec<DuplicateGroup>,
+}
+
+/// Files with identical contents; the first is the one a deduplicated archive keeps.
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct DuplicateGroup {
+    pub sha256: String,
+    pub size: u64,
+    pub files: Vec<String>,
+}
+
+impl PayloadAnalysis {
+    /// Each duplicate's name mapped to the kept file it repeats.
+    pub fn links(&self) -> BTreeMap<String, String> {
+        self.duplicates.iter().flat_map(|group| group.files[1..].iter().map(|dup| (dup.clone(), group.files[0].clone()))).collect()
+    }
+}
+
+/// Finds the duplicates among `(name, file on disk)` pairs. Only files sharing a size are
+/// hashed; empty files never count as duplicates.
+pub fn analyze_payload_files(files: &[(String, PathBuf)]) -> Result<PayloadAnalysis> {
+    let mut analysis = PayloadAnalysis::default();
+    let mut by_size: BTreeMap<u64, Vec<&(String, PathBuf)>> = BTreeMap::new();
+    for file in files {
+        let size = fs::metadata(&file.1).with_context(|| format!("Failed to read {}", file.1.display()))?.len();
+        analysis.file_count += 1;
+        analysis.total_bytes += size;
+        by_size.entry(size).or_default().push(file);
+    }
+    for (size, same_size) in by_size {
+        if size == 0 || same_size.len() < 2 {
+            continue;
+        }
+        let mut by_hash: BTreeMap<String, Vec<String>> = BTreeMap::new();
+        for (name, path) in same_size {
+            let bytes = fs::read(path).with_context(|| format!("Failed to hash {}", path.display()))?;
+            by_hash.entry(sha256_hex(&bytes)).or_default().push(name.clone());
+        }
+        for (sha256, mut names) in by_hash {
+            if names.len() > 1 {
+                names.sort();
+                analysis.wasted_bytes += size * (names.len() as u64 - 1);
+                analysis.duplicates.push(DuplicateGroup { sha256, size, files: names });
+            }
+        }
+    }
+    analysis.duplicates.sort_by_key(|group| std::cmp::Reverse(group.size * (group.files.len() as u64 - 1)));
+    Ok(analysis)
+}
+
+/// Writes back the duplicates listed in `dest`'s [`PAYLOAD_LINKS_FILE`] and removes the list.
+/// Does nothing for an archive that wasn't deduplicated.
+pub(crate) fn restore_payload_links(dest: &Path) -> Result<()> {
+    let list = dest.join(PAYLOAD_LINKS_FILE);
+    let Ok(json) = fs::read_to_string(&list) else { return Ok(()) };
+    let links: BTreeMap<String, String> = serde_json::from_str(&json).context("Invalid payload link list")?;
+    for (duplicate, kept) in &links {
+        let to = dest.join(normalize_rel_path(duplicate, false)?);
+        let from = dest.join(normalize_rel_path(kept, false)?);
+        if let Some(parent) = to.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::copy(&from, &to).with_context(|| format!("Failed to restore {} from {}", duplicate, kept))?;
+    }
+    fs::remove_file(&list)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::temp_file;
+
+    #[test]
+    fn duplicate_payloads_are_reported_and_stored_once() {
+        let root = temp_file("dedupe/payload/a/big.bin", &"z".repeat(1000)).parent().unwrap().parent().unwrap().to_path_buf();
+        std::fs::create_dir_all(root.join("b")).unwrap();
+        std::fs::write(root.join("b/copy.bin"), "z".repeat(1000)).unwrap();
+        std::fs::write(root.join("b/other.bin"), "y".repeat(1000)).unwrap();
+        let entries = [PathBuf::from("a"), PathBuf::from("b")];
+        let files: Vec<(String, PathBuf)> = ["a/big.bin", "b/copy.bin", "b/other.bin"].iter().map(|n| (n.to_string(), root.join(n))).collect();
+
+        let analysis = analyze_payload_files(&files).unwrap();
+        assert_eq!((analysis.file_count, analysis.total_bytes, analysis.wasted_bytes), (3, 3000, 1000));
+        assert_eq!(analysis.duplicates[0].files, ["a/big.bin", "b/copy.bin"]);
+
+        let lock = build_payload_lock(&root, &entries).unwrap();
+        for format in [PayloadCompression::Zip, PayloadCompression::TarZst] {
+            let archive = root.with_file_name(format.archive_name().unwrap());
+            create_payload_archive(&root, &entries, &archive, format, true).unwrap();
+            let out = root.with_file_name(format!("out-{:?}", format));
+            extract_payload_archive(&archive, &out).unwrap();
+            verify_payload_lock(&out, &lock).unwrap();
+            assert!(!out.join(PAYLOAD_LINKS_FILE).exists());
+        }
+        let zipped = zip::ZipArchive::new(std::fs::File::open(root.with_file_name("payloads.zip")).unwrap()).unwrap();
+        let mut names: Vec<&str> = zipped.file_names().collect();
+        names.sort();
+        assert_eq!(names, [PAYLOAD_LINKS_FILE, "a/big.bin", "b/other.bin"]);
+    }
+}
@@ -0,0 +1,112 @@
+//! Finding Electron apps to patch: any folder in the usual install locations whose
+//! `resources` folder holds an `app.asar` archive or an unpacked `app` folder.
+
+use crate::*;
+
+/// An installed Electron app.
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct ElectronApp {
+    /// `productName` (or `name`) from the app's `package.json`, else the folder name.
+    pub name: String,
+    pub version: Option<String>,
+    pub app_root: PathBuf,
+    pub resources_path: PathBuf,
+    /// `app.asar`, or the `app` folder when the app isn't packed.
+    pub app_path: PathBuf,
+    pub packed: bool,
+}
+
+/// Folders whose subfolders are searched for apps.
+fn search_roots(home: &Path) -> Vec<PathBuf> {
+    let mut roots = Vec::new();
+    if cfg!(windows) {
+        if let Some(local) = std::env::var_os("LOCALAPPDATA").map(PathBuf::from) {
+            // Squirrel installers (Discord, Slack, ...) put apps straight into LOCALAPPDATA
+            roots.push(local.join("Programs"));
+            roots.push(local);
+        }
+        roots.extend(["ProgramFiles", "ProgramFiles(x86)"].iter().filter_map(|var| std::env::var_os(var).map(PathBuf::from)));
+    } else if cfg!(target_os = "macos") {
+        roots.push(PathBuf::from("/Applications"));
+        roots.push(home.join("Applications"));
+    } else {
+        roots.extend(["/opt", "/usr/lib", "/usr/share"].iter().map(PathBuf::from));
+        roots.push(home.join(".local/share"));
+    }
+    roots
+}
+
+/// Electron apps in the usual per-user and system install folders, sorted by name.
+pub fn detect_electron_apps() -> Vec<ElectronApp> {
+    let home = home_dir().unwrap_or_default();
+    let mut apps: Vec<ElectronApp> = Vec::new();
+    for root in search_roots(&home) {
+        let Ok(entries) = fs::read_dir(&root) else { continue };
+        for entry in entries.flatten() {
+            let dir = entry.path();
+            if !dir.is_dir() {
+                continue;
+            }
+            if let Some(app) = inspect_electron_app(&dir) {
+                if !apps.iter().any(|a| a.app_path == app.app_path) {
+                    apps.push(app);
+                }
+            }
+        }
+    }
+    apps.sort_by_key(|a| a.name.to_lowercase());
+    apps
+}
+
+/// The Electron app in `dir`, if there is one. Apps that keep each version in its own
+/// subfolder (`app-1.2.3/resources`) are found through the newest one.
+pub(crate) fn inspect_electron_app(dir: &Path) -> Option<ElectronApp> {
+    let resources_in = |root: &Path| {
+        ["resources", "Contents/Resources"]
+            .iter()
+            .map(|sub| root.join(sub))
+            .find(|resources| resources.join("app.asar").is_file() || resources.join("app").is_dir())
+    };
+    let resources_path = resources_in(dir).or_else(|| {
+        let mut subdirs: Vec<PathBuf> = fs::read_dir(dir).ok()?.flatten().map(|e| e.path()).filter(|p| p.is_dir()).collect();
+        subdirs.sort();
+        subdirs.iter().rev().find_map(|sub| resources_in(sub))
+    })?;
+    let packed = resources_path.join("app.asar").is_file();
+    let app_path = resources_path.join(if packed { "app.asar" } else { "app" });
+
+    let package_path = if packed {
+        PathBuf::from(format!("{}!/package.json", app_path.display()))
+    } else {
+        app_path.join("package.json")
+    };
+    let package: Option<serde_json::Value> = read_target(&package_path).ok().and_then(|bytes| serde_json::from_slice(&bytes).ok());
+    let field = |key: &str| package.as_ref().and_then(|p| p.get(key)).and_then(|v| v.as_str()).map(str::to_string);
+    let folder_name = dir.file_stem().map(|n| n.to_string_lossy().to_string()).unwrap_or_default();
+    Some(ElectronApp {
+        name: field("productName").or_else(|| field("name")).unwrap_or(folder_name),
+        version: field("version"),
+        app_root: dir.to_path_buf(),
+        resources_path,
+        app_path,
+        packed,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::temp_file;
+
+    #[test]
+    fn inspect_electron_app_finds_versioned_resources() {
+        let package = temp_file("electron/Chat/app-1.2.0/resources/app/package.json", r#"{ "name": "chat", "productName": "Chat", "version": "1.2.0" }"#);
+        let dir = package.ancestors().nth(4).unwrap().to_path_buf();
+        let app = inspect_electron_app(&dir).expect("app");
+        assert_eq!((app.name.as_str(), app.version.as_deref(), app.packed), ("Chat", Some("1.2.0"), false));
+        assert_eq!(app.resources_path, dir.join("app-1.2.0/resources"));
+        assert_eq!(app.app_path, dir.join("app-1.2.0/resources/app"));
+        assert!(inspect_electron_app(&dir.join("app-1.2.0/resources/app")).is_none());
+    }
+}
@@ -0,0 +1,170 @@
+//! Errors handed to frontends. Engine functions return `anyhow::Result`; the places that know
+//! what went wrong raise a [`MisfitError`] inside the chain, and [`MisfitError::from`] picks it
+//! (or an underlying `io::Error`) back out so callers can branch on [`ErrorCode`].
+
+use crate::LintIssue;
+use serde::Serialize;
+use std::fmt;
+use std::io;
+use std::path::Path;
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum ErrorCode {
+    ManifestNotFound,
+    InvalidManifest,
+    PayloadMissing,
+    IntegrityFailed,
+    MarkerNotFound,
+    PermissionDenied,
+    /// Another process has the file open: a sharing violation on Windows, a busy file or
+    /// running executable on Unix.
+    FileLocked,
+    ElevationRequired,
+    /// The installed target app is outside the manifest's `minTargetVersion`..`maxTargetVersion`.
+    IncompatibleTarget,
+    BackupNotFound,
+    NotFound,
+    /// The studio refused to build; `issues` lists why.
+    ValidationFailed,
+    /// The manifest has a `licenseFile` and the install was started without accepting it.
+    LicenseNotAccepted,
+    /// Another process is installing, building or restoring in the same place.
+    OperationInProgress,
+    /// A file is over the size limit of the command reading or writing it.
+    FileTooLarge,
+    /// A write outside the folders the Studio works in, which the user hasn't confirmed.
+    PathNotAllowed,
+    /// Stopped by the user, or through a [`CancelToken`](crate::CancelToken).
+    Cancelled,
+    /// Payload sources were edited while the studio was building from them.
+    SourcesChanged,
+    /// Anything without a more specific code.
+    Failed,
+}
+
+impl ErrorCode {
+    fn from_io(err: &io::Error) -> Self {
+        if crate::is_lock_error(err) {
+            return ErrorCode::FileLocked;
+        }
+        match err.kind() {
+            io::ErrorKind::PermissionDenied => ErrorCode::PermissionDenied,
+            io::ErrorKind::NotFound => ErrorCode::NotFound,
+            _ => ErrorCode::Failed,
+        }
+    }
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct MisfitError {
+    pub code: ErrorCode,
+    pub message: String,
+    /// File or folder the error is about, when known.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub path: Option<String>,
+    /// Index into `installSteps` of the step that failed.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub step_index: Option<usize>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub issues: Vec<LintIssue>,
+    /// Exit code of a command that failed.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub exit_code: Option<i32>,
+}
+
+impl MisfitError {
+    pub fn new(code: ErrorCode, message: impl Into<String>) -> Self {
+        MisfitError { code, message: message.into(), path: None, step_index: None, issues: Vec::new(), exit_code: None }
+    }
+
+    pub fn with_path(mut self, path: &Path) -> Self {
+        self.path = Some(path.to_string_lossy().to_string());
+        self
+    }
+
+    pub fn at_step(mut self, index: usize) -> Self {
+        self.step_index = Some(index);
+        self
+    }
+
+    pub fn with_issues(mut self, issues: Vec<LintIssue>) -> Self {
+        self.issues = issues;
+        self
+    }
+
+    pub fn with_exit_code(mut self, exit_code: Option<i32>) -> Self {
+        self.exit_code = exit_code;
+        self
+    }
+}
+
+impl fmt::Display for MisfitError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl std::error::Error for MisfitError {}
+
+impl From<anyhow::Error> for MisfitError {
+    /// Keeps the full context chain as the message; code, path and step come from the first
+    /// `MisfitError` in the chain, else from an `io::Error`.
+    fn from(err: anyhow::Error) -> Self {
+        let message = format!("{:#}", err);
+        let inner = err.chain().find_map(|e| e.downcast_ref::<MisfitError>()).cloned();
+        let classified = inner.unwrap_or_else(|| {
+            let code = err.chain().find_map(|e| e.downcast_ref::<io::Error>()).map_or(ErrorCode::Failed, ErrorCode::from_io);
+            MisfitError::new(code, String::new())
+        });
+        MisfitError { message, ..classified }
+    }
+}
+
+impl From<io::Error> for MisfitError {
+    fn from(err: io::Error) -> Self {
+        MisfitError::new(ErrorCode::from_io(&err), err.to_string())
+    }
+}
+
+impl From<serde_json::Error> for MisfitError {
+    fn from(err: serde_json::Error) -> Self {
+        MisfitError::new(ErrorCode::Failed, err.to_string())
+    }
+}
+
+impl From<String> for MisfitError {
+    fn from(message: String) -> Self {
+        MisfitError::new(ErrorCode::Failed, message)
+    }
+}
+
+impl From<&str> for MisfitError {
+    fn from(message: &str) -> Self {
+        MisfitError::new(ErrorCode::Failed, message)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::{spec, temp_file};
+    use crate::{patch_file, patch_file_blocks};
+
+    #[test]
+    fn errors_keep_code_path_and_context() {
+        let path = temp_file("error_marker.css", "no markers");
+        let err = MisfitError::from(patch_file_blocks(&path, &[spec("/*S*/", "/*E*/", "x", false)]).unwrap_err());
+        assert_eq!(err.code, ErrorCode::MarkerNotFound);
+        assert!(err.message.starts_with("Patch block 1 failed"));
+
+        let missing = path.with_file_name("error_missing.css");
+        let err = MisfitError::from(patch_file(&missing, &spec("/*S*/", "/*E*/", "x", false)).unwrap_err()).with_path(&missing).at_step(2);
+        assert_eq!(err.code, ErrorCode::NotFound);
+        let json = serde_json::to_value(&err).unwrap();
+        assert_eq!(json["code"], "not-found");
+        assert_eq!(json["stepIndex"], 2);
+        assert_eq!(json["path"], missing.to_string_lossy().as_ref());
+    }
+}
@@ -0,0 +1,196 @@
+//! Windows resources for built installers. Every installer is a copy of the studio
+//! executable; [`version_info_resource`] builds the `VS_VERSIONINFO` resource that replaces the
+//! studio's own, so Windows shows the project's name, version and company in the file's
+//! properties, and [`icon_resources`] turns a project's `.ico` into icon resources.
+
+use crate::*;
+
+/// What a built installer reports about itself.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct ExeMetadata {
+    pub product_name: String,
+    pub product_version: String,
+    pub company_name: String,
+    pub file_description: String,
+    /// File name of the executable, e.g. `MyThemeSetup.exe`.
+    pub original_filename: String,
+}
+
+/// US English: the language of the only string table written.
+pub const VERSION_INFO_LANGUAGE: u16 = 0x0409;
+/// UTF-16.
+const CODEPAGE: u16 = 1200;
+
+/// The four numbers of a `major.minor.patch.build` version. Missing parts are zero and
+/// anything after the digits of a part (`3-beta`) is ignored.
+pub fn version_quad(version: &str) -> [u16; 4] {
+    let mut quad = [0u16; 4];
+    for (slot, part) in quad.iter_mut().zip(version.trim().trim_start_matches(['v', 'V']).split('.')) {
+        let digits: String = part.chars().take_while(|c| c.is_ascii_digit()).collect();
+        *slot = digits.parse().unwrap_or(0);
+    }
+    quad
+}
+
+/// The `VS_VERSIONINFO` resource (type `RT_VERSION`, id 1) describing `meta`.
+pub fn version_info_resource(meta: &ExeMetadata) -> Vec<u8> {
+    let [a, b, c, d] = version_quad(&meta.product_version);
+    let (ms, ls) = (u32::from(a) << 16 | u32::from(b), u32::from(c) << 16 | u32::from(d));
+    // VS_FIXEDFILEINFO
+    let fixed: [u32; 13] = [
+        0xFEEF_04BD, // signature
+        0x0001_0000, // structure version
+        ms,          // file version
+        ls,
+        ms, // product version
+        ls,
+        0x3F,        // flags mask
+        0,           // flags
+        0x0004_0004, // VOS_NT_WINDOWS32
+        1,           // VFT_APP
+        0,           // subtype
+        0,           // date
+        0,
+    ];
+    let fixed: Vec<u8> = fixed.iter().flat_map(|v| v.to_le_bytes()).collect();
+
+    let version = format!("{}.{}.{}.{}", a, b, c, d);
+    let strings = [
+        ("CompanyName", meta.company_name.as_str()),
+        ("FileDescription", meta.file_description.as_str()),
+        ("FileVersion", version.as_str()),
+        ("InternalName", meta.product_name.as_str()),
+        ("OriginalFilename", meta.original_filename.as_str()),
+        ("ProductName", meta.product_name.as_str()),
+        ("ProductVersion", meta.product_version.as_str()),
+    ];
+    let strings: Vec<Vec<u8>> = strings
+        .iter()
+        .map(|(key, value)| {
+            let value = utf16z(value);
+            version_node(key, 1, (value.len() / 2) as u16, &value, &[])
+        })
+        .collect();
+    let table = version_node(&format!("{:04x}{:04x}", VERSION_INFO_LANGUAGE, CODEPAGE), 1, 0, &[], &strings);
+    let string_info = version_node("StringFileInfo", 1, 0, &[], &[table]);
+
+    let translation: Vec<u8> = [VERSION_INFO_LANGUAGE, CODEPAGE].iter().flat_map(|v| v.to_le_bytes()).collect();
+    let var = version_node("Translation", 0, translation.len() as u16, &translation, &[]);
+    let var_info = version_node("VarFileInfo", 1, 0, &[], &[var]);
+
+    version_node("VS_VERSION_INFO", 0, fixed.len() as u16, &fixed, &[string_info, var_info])
+}
+
+fn utf16z(s: &str) -> Vec<u8> {
+    s.encode_utf16().chain(Some(0)).flat_map(u16::to_le_bytes).collect()
+}
+
+fn pad4(out: &mut Vec<u8>) {
+    out.resize(out.len().next_multiple_of(4), 0);
+}
+
+/// One block of the version resource: `wLength`, `wValueLength`, `wType`, the key, the value
+/// and the child blocks, each 32-bit aligned.
+fn version_node(key: &str, value_type: u16, value_length: u16, value: &[u8], children: &[Vec<u8>]) -> Vec<u8> {
+    let mut out = vec![0u8; 6];
+    out[2..4].copy_from_slice(&value_length.to_le_bytes());
+    out[4..6].copy_from_slice(&value_type.to_le_bytes());
+    out.extend(utf16z(key));
+    pad4(&mut out);
+    out.extend_from_slice(value);
+    for child in children {
+        pad4(&mut out);
+        out.extend_from_slice(child);
+    }
+    let length = out.len() as u16;
+    out[0..2].copy_from_slice(&length.to_le_bytes());
+    out
+}
+
+/// An `.ico` file split the way executables store it: one `RT_ICON` resource per image (ids
+/// `1..=images.len()`) and an `RT_GROUP_ICON` directory pointing at them.
+pub struct IconResources {
+    pub images: Vec<Vec<u8>>,
+    pub group: Vec<u8>,
+}
+
+pub fn icon_resources(ico: &[u8]) -> Result<IconResources> {
+    let word = |at: usize| ico.get(at..at + 2).map(|b| u16::from_le_bytes([b[0], b[1]]));
+    let dword = |at: usize| ico.get(at..at + 4).map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]) as usize);
+    if word(0) != Some(0) || word(2) != Some(1) {
+        return Err(anyhow!("Not an .ico file"));
+    }
+    let count = word(4).unwrap_or(0);
+    if count == 0 {
+        return Err(anyhow!("Icon file contains no images"));
+    }
+
+    let mut images = Vec::new();
+    let mut group = ico[..6].to_vec();
+    for index in 0..count {
+        // ICONDIRENTRY: 12 bytes of size/format, the image length and its offset in the file
+        let entry = 6 + usize::from(index) * 16;
+        let (Some(len), Some(offset)) = (dword(entry + 8), dword(entry + 12)) else {
+            return Err(anyhow!("Icon file is truncated"));
+        };
+        let image = offset.checked_add(len).and_then(|end| ico.get(offset..end)).ok_or_else(|| anyhow!("Icon image {} is truncated", index + 1))?;
+        images.push(image.to_vec());
+        // GRPICONDIRENTRY: the same 12 bytes followed by the RT_ICON id
+        group.extend_from_slice(&ico[entry..entry + 12]);
+        group.extend_from_slice(&(index + 1).to_le_bytes());
+    }
+    Ok(IconResources { images, group })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn version_info_resource_is_well_formed() {
+        assert_eq!(version_quad("2.10.3-beta"), [2, 10, 3, 0]);
+        assert_eq!(version_quad("v1"), [1, 0, 0, 0]);
+
+        let meta = ExeMetadata {
+            product_name: "Night Theme".into(),
+            product_version: "2.10.3-beta".into(),
+            company_name: "Misfit".into(),
+            file_description: "Night Theme installer".into(),
+            original_filename: "NightTheme.exe".into(),
+        };
+        let data = version_info_resource(&meta);
+        let word = |at: usize| u16::from_le_bytes([data[at], data[at + 1]]);
+        let dword = |at: usize| u32::from_le_bytes(data[at..at + 4].try_into().unwrap());
+        assert_eq!(word(0) as usize, data.len());
+        assert_eq!(word(2), 52);
+        // "VS_VERSION_INFO\0" ends at byte 38; the fixed info starts on the next 4-byte boundary
+        assert_eq!(dword(40), 0xFEEF_04BD);
+        assert_eq!((dword(48), dword(52)), (2 << 16 | 10, 3 << 16));
+
+        let utf16 = |s: &str| s.encode_utf16().flat_map(u16::to_le_bytes).collect::<Vec<u8>>();
+        for text in ["Night Theme installer", "NightTheme.exe", "2.10.3.0", "040904b0"] {
+            assert!(find_bytes(&data, &utf16(text)).is_some(), "{} missing", text);
+        }
+    }
+
+    #[test]
+    fn ico_files_split_into_icon_resources() {
+        // Two 1-byte "images" after a header and two directory entries
+        let mut ico = vec![0, 0, 1, 0, 2, 0];
+        ico.extend([16, 16, 0, 0, 1, 0, 32, 0, 1, 0, 0, 0, 38, 0, 0, 0]);
+        ico.extend([32, 32, 0, 0, 1, 0, 32, 0, 1, 0, 0, 0, 39, 0, 0, 0]);
+        ico.extend([0xAA, 0xBB]);
+
+        let icon = icon_resources(&ico).unwrap();
+        assert_eq!(icon.images, vec![vec![0xAA], vec![0xBB]]);
+        assert_eq!(icon.group.len(), 6 + 2 * 14);
+        assert_eq!(&icon.group[6..18], &ico[6..18]);
+        assert_eq!(&icon.group[18..20], &[1, 0]);
+        assert_eq!(&icon.group[32..34], &[2, 0]);
+
+        ico.truncate(38);
+        assert!(icon_resources(&ico).is_err());
+        assert!(icon_resources(b"\x89PNG....").is_err());
+    }
+}
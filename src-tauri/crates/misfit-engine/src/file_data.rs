@@ -0,0 +1,230 @@
+//! Files of any kind for the Studio's editors, carried as base64 so images and icons survive
+//! the trip to the frontend, with the text decoded when the bytes turn out to be text; and the
+//! [`TextStyle`] the patch engine writes patched files back in.
+
+use crate::*;
+use base64::Engine as _;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TextEncoding {
+    #[serde(rename = "utf-8")]
+    Utf8,
+    /// UTF-8 starting with a byte order mark, as Windows tools often write it.
+    #[serde(rename = "utf-8-bom")]
+    Utf8Bom,
+    #[serde(rename = "utf-16le")]
+    Utf16Le,
+    #[serde(rename = "utf-16be")]
+    Utf16Be,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct FileData {
+    /// The bytes read, base64-encoded.
+    pub base64: String,
+    /// Size of the whole file, which is more than was read when `truncated`.
+    pub size: u64,
+    pub truncated: bool,
+    /// `None` for binary files.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub encoding: Option<TextEncoding>,
+    /// The decoded text, without its byte order mark, when `encoding` is set.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub text: Option<String>,
+}
+
+/// Reads `path`, or its first `max_bytes`.
+pub fn read_file_data(path: &Path, max_bytes: Option<u64>) -> Result<FileData> {
+    use std::io::Read;
+    let file = fs::File::open(path).map_err(|e| MisfitError::from(e).with_path(path))?;
+    let size = file.metadata()?.len();
+    let limit = max_bytes.unwrap_or(u64::MAX);
+    let mut bytes = Vec::new();
+    file.take(limit).read_to_end(&mut bytes).map_err(|e| MisfitError::from(e).with_path(path))?;
+    let truncated = size > bytes.len() as u64;
+    let decoded = decode_text(&bytes, truncated);
+    Ok(FileData {
+        base64: base64::engine::general_purpose::STANDARD.encode(&bytes),
+        size,
+        truncated,
+        encoding: decoded.as_ref().map(|(encoding, _)| *encoding),
+        text: decoded.map(|(_, text)| text),
+    })
+}
+
+/// Writes base64-encoded `data` to `path`, creating its folder, and returns the bytes written.
+pub fn write_file_data(path: &Path, data: &str) -> Result<usize> {
+    let bytes = base64::engine::general_purpose::STANDARD
+        .decode(data.trim())
+        .map_err(|e| MisfitError::new(ErrorCode::Failed, format!("Invalid base64 data: {}", e)).with_path(path))?;
+    if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+        fs::create_dir_all(parent).map_err(|e| MisfitError::from(e).with_path(parent))?;
+    }
+    write_atomic(path, &bytes).map_err(|e| MisfitError::from(e).with_path(path))?;
+    Ok(bytes.len())
+}
+
+/// Recognizes text by its byte order mark, else as UTF-8 without NUL bytes. `truncated` allows
+/// a character cut off at the end.
+pub fn decode_text(bytes: &[u8], truncated: bool) -> Option<(TextEncoding, String)> {
+    if let Some(rest) = bytes.strip_prefix(&[0xef, 0xbb, 0xbf]) {
+        return Some((TextEncoding::Utf8Bom, utf8_prefix(rest, truncated)?));
+    }
+    for (bom, encoding) in [([0xff, 0xfe], TextEncoding::Utf16Le), ([0xfe, 0xff], TextEncoding::Utf16Be)] {
+        let Some(rest) = bytes.strip_prefix(&bom) else { continue };
+        let rest = if truncated { &rest[..rest.len() & !1] } else { rest };
+        if rest.len() % 2 != 0 {
+            return None;
+        }
+        let mut units: Vec<u16> = rest
+            .chunks_exact(2)
+            .map(|pair| match encoding {
+                TextEncoding::Utf16Be => u16::from_be_bytes([pair[0], pair[1]]),
+                _ => u16::from_le_bytes([pair[0], pair[1]]),
+            })
+            .collect();
+        // A surrogate pair cut in half by the size cap
+        if truncated && units.last().is_some_and(|unit| (0xd800..0xdc00).contains(unit)) {
+            units.pop();
+        }
+        let text = String::from_utf16(&units).ok()?;
+        return Some((encoding, text));
+    }
+    let text = utf8_prefix(bytes, truncated)?;
+    (!text.contains('\0')).then_some((TextEncoding::Utf8, text))
+}
+
+fn utf8_prefix(bytes: &[u8], truncated: bool) -> Option<String> {
+    match std::str::from_utf8(bytes) {
+        Ok(text) => Some(text.to_string()),
+        // `error_len` is `None` when the bytes only end in the middle of a character
+        Err(err) if truncated && err.error_len().is_none() => Some(String::from_utf8_lossy(&bytes[..err.valid_up_to()]).into_owned()),
+        Err(_) => None,
+    }
+}
+
+/// How a text file is stored: its encoding and whether its lines end in CRLF. Patches are
+/// applied to the decoded text and written back in the same style, so the rest of the file
+/// stays byte-for-byte identical.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TextStyle {
+    pub encoding: TextEncoding,
+    pub crlf: bool,
+}
+
+impl TextStyle {
+    /// The file's text as UTF-8 without its byte order mark, and its style. Files that aren't
+    /// UTF-16 come back unchanged (apart from the BOM), valid UTF-8 or not.
+    pub fn decode(bytes: &[u8]) -> (std::borrow::Cow<'_, [u8]>, TextStyle) {
+        use std::borrow::Cow;
+        let (encoding, body) = if let Some(rest) = bytes.strip_prefix(&[0xef, 0xbb, 0xbf]) {
+            (TextEncoding::Utf8Bom, Cow::Borrowed(rest))
+        } else {
+            match decode_text(bytes, false) {
+                Some((encoding @ (TextEncoding::Utf16Le | TextEncoding::Utf16Be), text)) => (encoding, Cow::Owned(text.into_bytes())),
+                _ => (TextEncoding::Utf8, Cow::Borrowed(bytes)),
+            }
+        };
+        let crlf = body.iter().position(|b| *b == b'\n').is_some_and(|i| i > 0 && body[i - 1] == b'\r');
+        (body, TextStyle { encoding, crlf })
+    }
+
+    /// `text` (UTF-8) encoded back into this style's encoding, BOM included.
+    pub fn encode(&self, text: &[u8]) -> Vec<u8> {
+        let utf16 = |to_bytes: fn(u16) -> [u8; 2]| -> Vec<u8> {
+            std::iter::once(0xfeff).chain(String::from_utf8_lossy(text).encode_utf16()).flat_map(to_bytes).collect()
+        };
+        match self.encoding {
+            TextEncoding::Utf8 => text.to_vec(),
+            TextEncoding::Utf8Bom => [&[0xef, 0xbb, 0xbf], text].concat(),
+            TextEncoding::Utf16Le => utf16(u16::to_le_bytes),
+            TextEncoding::Utf16Be => utf16(u16::to_be_bytes),
+        }
+    }
+
+    /// `text` with every line break in this style.
+    pub fn line_breaks(&self, text: &str) -> String {
+        let lf = text.replace("\r\n", "\n");
+        if self.crlf {
+            lf.replace('\n', "\r\n")
+        } else {
+            lf
+        }
+    }
+
+    pub fn newline(&self) -> &'static str {
+        if self.crlf {
+            "\r\n"
+        } else {
+            "\n"
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::{spec, temp_file};
+
+    #[test]
+    fn file_data_round_trips_binary_and_detects_text_encodings() {
+        use base64::Engine as _;
+        let icon = temp_file("file-data/icon.ico", "");
+        let bytes = [0u8, 1, 2, 0xff, 0xfe, 0];
+        let b64 = base64::engine::general_purpose::STANDARD.encode(bytes);
+        assert_eq!(write_file_data(&icon.with_file_name("sub/copy.ico"), &b64).unwrap(), 6);
+        let read = read_file_data(&icon.with_file_name("sub/copy.ico"), None).unwrap();
+        assert_eq!((read.base64.as_str(), read.size, read.truncated, read.encoding), (b64.as_str(), 6, false, None));
+        assert!(write_file_data(&icon, "not base64!").is_err());
+
+        assert_eq!(decode_text("héllo".as_bytes(), false), Some((TextEncoding::Utf8, "héllo".into())));
+        assert_eq!(decode_text(b"\xef\xbb\xbfbom", false), Some((TextEncoding::Utf8Bom, "bom".into())));
+        assert_eq!(decode_text(&[0xff, 0xfe, b'h', 0, b'i', 0], false), Some((TextEncoding::Utf16Le, "hi".into())));
+        assert_eq!(decode_text(&[0xfe, 0xff, 0, b'h', 0], true), Some((TextEncoding::Utf16Be, "h".into())));
+
+        // Cut in the middle of "é"
+        let text = icon.with_file_name("text.txt");
+        std::fs::write(&text, "abé").unwrap();
+        let capped = read_file_data(&text, Some(3)).unwrap();
+        assert_eq!((capped.size, capped.truncated, capped.text.as_deref()), (4, true, Some("ab")));
+        assert_eq!(capped.encoding, Some(TextEncoding::Utf8));
+    }
+
+    #[test]
+    fn patches_keep_the_file_encoding_and_line_endings() {
+        let utf16 = |text: &str| -> Vec<u8> { std::iter::once(0xfeff).chain(text.encode_utf16()).flat_map(u16::to_le_bytes).collect() };
+        let cases: Vec<(&str, Vec<u8>, Vec<u8>)> = vec![
+            ("patch_crlf.css", b"a\r\n/*S*/old/*E*/\r\nb\r\n".to_vec(), b"a\r\n/*S*/x\r\ny/*E*/\r\nb\r\n".to_vec()),
+            ("patch_bom.css", b"\xef\xbb\xbf/*S*/old/*E*/\n".to_vec(), b"\xef\xbb\xbf/*S*/x\ny/*E*/\n".to_vec()),
+            ("patch_utf16.css", utf16("/*S*/old/*E*/\r\n"), utf16("/*S*/x\r\ny/*E*/\r\n")),
+        ];
+        for (name, before, after) in cases {
+            let path = std::env::temp_dir().join(format!("misfit_{}_{}", std::process::id(), name));
+            std::fs::write(&path, &before).unwrap();
+            let outcomes = patch_file_blocks(&path, &[spec("/*S*/", "/*E*/", "x\ny", false)]).expect("patch");
+            assert_eq!(std::fs::read(&path).unwrap(), after, "{}", name);
+            let PatchOutcome::Applied { original } = &outcomes[0] else { panic!("not applied: {}", name) };
+            let (original, original_base64) = original_fields(original.clone());
+            let record = PatchRecord {
+                file: path.to_string_lossy().to_string(),
+                start_marker: "/*S*/".to_string(),
+                end_marker: "/*E*/".to_string(),
+                original,
+                original_base64,
+                applied: "x\ny".to_string(),
+                markers_stripped: false,
+                matching: MarkerMatch::default(),
+                inserted: false,
+            };
+            revert_patch(&record).expect("revert");
+            assert_eq!(std::fs::read(&path).unwrap(), before, "{}", name);
+        }
+
+        let path = temp_file("patch_insert_crlf.css", "a\r\nb");
+        let mut insert = spec("/*S*/", "/*E*/", "x\ny", false);
+        insert.insert_if_missing = Some(InsertAnchor::AppendToEnd);
+        patch_file_blocks(&path, &[insert]).expect("insert");
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "a\r\nb\r\n/*S*/x\r\ny/*E*/\r\n");
+    }
+}
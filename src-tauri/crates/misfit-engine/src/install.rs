@@ -0,0 +1,2049 @@
+//! Running a manifest end to end: integrity checks, preflight, backup, the steps themselves
+//! with rollback on failure, and the install receipt. Frontends (the Tauri app, the `misfit`
+//! CLI) pass an [`InstallObserver`] to show progress and answer prompts.
+
+use crate::*;
+use std::env;
+
+/// Receives progress from [`run_install`] and friends.
+pub trait InstallObserver: Sync {
+    fn log(&self, message: &str);
+
+    /// Something the user should know about that doesn't stop the install; it is also kept
+    /// in the install report.
+    fn warn(&self, message: &str) {
+        self.log(message);
+    }
+
+    /// Structured notifications: `copy-progress` ([`CopyProgress`]), `integrity-warning`
+    /// (message), `elevation-required` and `reboot-required` (lists of paths).
+    fn event(&self, _name: &str, _payload: serde_json::Value) {}
+
+    /// Called with the existing files an `overwrite: prompt` Copy would replace; `true`
+    /// overwrites them, `false` keeps them.
+    fn confirm_overwrite(&self, _files: &[String]) -> bool {
+        false
+    }
+
+    /// Called with the program and the full command line before each `runCommand` step of a
+    /// manifest without `advancedMode`; `true` runs it, `false` fails the step.
+    fn confirm_command(&self, _command: &str, _command_line: &str) -> bool {
+        false
+    }
+}
+
+pub struct InstallOptions {
+    /// Folder holding the manifest, payload lockfile and signature.
+    pub manifest_dir: PathBuf,
+    pub payload_source: PathBuf,
+    /// Base for relative `file` / `dest` paths; normally `manifest_dir`.
+    pub target_base: PathBuf,
+    /// Root shared by all apps' backups; this app uses its `backup_namespace` subfolder.
+    pub backups_root: PathBuf,
+    /// Public key embedded in the installer executable, if any.
+    pub trusted_key: Option<Vec<u8>>,
+    /// Run only this phase's steps (e.g. to re-run `configure`); every phase when `None`.
+    pub phase: Option<Phase>,
+    /// Continue the unfinished install recorded in the journal, skipping completed steps and
+    /// reusing its backup. `phase` is taken from the journal.
+    pub resume: bool,
+    /// Optional components to install (their dependencies are added); the manifest's
+    /// defaults when `None`. A resumed install keeps the components it started with.
+    pub components: Option<Vec<String>>,
+    /// Values for the manifest's `prompts`, by name. Never persisted; a resumed install needs
+    /// them again.
+    pub prompt_values: BTreeMap<String, String>,
+    /// The user accepted the manifest's `licenseFile`; without it an install of a manifest
+    /// whose license is in the payload is refused.
+    pub accepted_license: bool,
+}
+
+/// What a single step needs besides the step itself.
+pub struct StepContext {
+    pub target_base: PathBuf,
+    pub payload_source: PathBuf,
+    pub advanced_mode: bool,
+    /// Copy files that stay locked are replaced at the next restart instead of failing the step.
+    pub replace_locked_on_reboot: bool,
+    /// Filled into each step's `replacements` right before it runs.
+    pub prompts: PromptValues,
+}
+
+pub fn normalize_rel_path(path_str: &str, allow_current: bool) -> Result<PathBuf> {
+    // FIX: Normalize backslashes to forward slashes for cross-platform compatibility
+    let path_str = path_str.replace('\\', "/");
+    let trimmed = path_str.trim();
+    if trimmed.is_empty() {
+        return if allow_current {
+            Ok(PathBuf::from("."))
+        } else {
+            Err(anyhow!("Path cannot be empty"))
+        };
+    }
+
+    let path = Path::new(trimmed);
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::Normal(os) => normalized.push(os),
+            Component::CurDir => {},
+            Component::ParentDir => return Err(anyhow!("Path cannot contain '..'")),
+            Component::RootDir | Component::Prefix(_) => return Err(anyhow!("Path must be relative")),
+        }
+    }
+
+    if normalized.as_os_str().is_empty() {
+        if allow_current {
+            Ok(PathBuf::from("."))
+        } else {
+            Err(anyhow!("Path cannot be '.'"))
+        }
+    } else {
+        Ok(normalized)
+    }
+}
+
+pub fn home_dir() -> Option<PathBuf> {
+    env::var_os("USERPROFILE")
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(PathBuf::from))
+}
+
+pub fn backup_namespace(app_name: &str) -> String {
+    let trimmed = app_name.trim();
+    if trimmed.is_empty() {
+        return "default".to_string();
+    }
+
+    let mut out = String::new();
+    for ch in trimmed.chars() {
+        if ch.is_ascii_alphanumeric() || ch == '-' || ch == '_' || ch == '.' {
+            out.push(ch);
+        } else {
+            out.push('_');
+        }
+    }
+
+    if out.is_empty() {
+        "default".to_string()
+    } else {
+        out
+    }
+}
+
+/// Lowercased names of the `{appData}`-style path tokens [`known_folder`] resolves.
+pub(crate) const KNOWN_FOLDERS: [&str; 7] = ["appdata", "localappdata", "documents", "desktop", "downloads", "home", "temp"];
+
+/// Folders for the `{appData}`-style path tokens, looked up through the OS (known folders on
+/// Windows, the standard directories on macOS, XDG on Linux) rather than environment variables.
+pub fn known_folder(token: &str) -> Option<PathBuf> {
+    match token.to_ascii_lowercase().as_str() {
+        "appdata" => dirs::config_dir(),
+        "localappdata" => dirs::data_local_dir(),
+        "documents" => dirs::document_dir(),
+        "desktop" => dirs::desktop_dir(),
+        "downloads" => dirs::download_dir(),
+        "home" => dirs::home_dir(),
+        "temp" => Some(env::temp_dir()),
+        _ => None,
+    }
+}
+
+/// Expands `%VAR%`, `$VAR`, `${VAR}`, a leading `~` and the `{appData}`, `{localAppData}`,
+/// `{documents}`, `{desktop}`, `{downloads}`, `{home}` and `{temp}` tokens. Anything that
+/// can't be resolved is left as written.
+pub fn expand_env_vars(input: &str) -> String {
+    let mut output = String::new();
+    let chars: Vec<char> = input.chars().collect();
+    let mut i = 0;
+
+    if input.starts_with('~')
+        && (chars.len() == 1 || chars.get(1) == Some(&'\\') || chars.get(1) == Some(&'/'))
+    {
+        if let Some(home) = home_dir() {
+            output.push_str(&home.to_string_lossy());
+            i = 1;
+        }
+    }
+
+    while i < chars.len() {
+        let ch = chars[i];
+        if ch == '%' {
+            if let Some(end) = chars[i + 1..].iter().position(|c| *c == '%') {
+                let end_idx = i + 1 + end;
+                let name: String = chars[i + 1..end_idx].iter().collect();
+                if !name.is_empty() {
+                    if let Ok(val) = env::var(&name) {
+                        output.push_str(&val);
+                    } else {
+                        output.push('%');
+                        output.push_str(&name);
+                        output.push('%');
+                    }
+                    i = end_idx + 1;
+                    continue;
+                }
+            }
+        }
+
+        if ch == '{' {
+            if let Some(end) = chars[i + 1..].iter().position(|c| *c == '}') {
+                let end_idx = i + 1 + end;
+                let name: String = chars[i + 1..end_idx].iter().collect();
+                if let Some(dir) = known_folder(&name) {
+                    output.push_str(&dir.to_string_lossy());
+                    i = end_idx + 1;
+                    continue;
+                }
+            }
+        }
+
+        if ch == '$' {
+            if i + 1 < chars.len() && chars[i + 1] == '{' {
+                if let Some(end) = chars[i + 2..].iter().position(|c| *c == '}') {
+                    let end_idx = i + 2 + end;
+                    let name: String = chars[i + 2..end_idx].iter().collect();
+                    if !name.is_empty() {
+                        if let Ok(val) = env::var(&name) {
+                            output.push_str(&val);
+                        } else {
+                            output.push_str("${");
+                            output.push_str(&name);
+                            output.push('}');
+                        }
+                        i = end_idx + 1;
+                        continue;
+                    }
+                }
+            } else {
+                let mut end_idx = i + 1;
+                while end_idx < chars.len() {
+                    let c = chars[end_idx];
+                    if c.is_ascii_alphanumeric() || c == '_' {
+                        end_idx += 1;
+                    } else {
+                        break;
+                    }
+                }
+                if end_idx > i + 1 {
+                    let name: String = chars[i + 1..end_idx].iter().collect();
+                    if let Ok(val) = env::var(&name) {
+                        output.push_str(&val);
+                    } else {
+                        output.push('$');
+                        output.push_str(&name);
+                    }
+                    i = end_idx;
+                    continue;
+                }
+            }
+        }
+
+        output.push(ch);
+        i += 1;
+    }
+
+    output
+}
+
+pub fn resolve_path(base: &Path, path: &str) -> PathBuf {
+    let mut expanded = expand_env_vars(path);
+    
+    // FIX: Specialized mapping for macOS to handle Windows-centric presets
+    if cfg!(target_os = "macos") {
+        expanded = expanded.replace('\\', "/");
+        // Check for common Windows preset paths and map them to macOS standards
+        if expanded.contains("%LOCALAPPDATA%/Programs/Antigravity/resources") {
+            expanded = expanded.replace("%LOCALAPPDATA%/Programs/Antigravity/resources", "/Applications/Antigravity.app/Contents/Resources");
+        } else if expanded.contains("Programs/Antigravity/resources") {
+             // Fallback if env var was stripped or different
+            expanded = expanded.replace("Programs/Antigravity/resources", "/Applications/Antigravity.app/Contents/Resources");
+        }
+    }
+    
+    let candidate = PathBuf::from(&expanded);
+    if candidate.is_absolute() {
+        candidate
+    } else {
+        base.join(candidate)
+    }
+}
+
+/// The folder a manifest's `payloadDir` is relative to: the parent of `manifests/` when the
+/// manifest sits in one, otherwise the manifest's own folder.
+pub fn project_root_for_manifest(manifest_path: &Path) -> PathBuf {
+    let parent = manifest_path.parent().unwrap_or(Path::new("."));
+    let root = if parent.file_name().is_some_and(|n| n == "manifests") {
+        parent.parent().unwrap_or(Path::new("."))
+    } else {
+        parent
+    };
+    root.to_path_buf()
+}
+
+/// Backup root configured through `MISFIT_BACKUP_DIR` or the manifest's `backupDir` (relative
+/// to `manifest_dir`). `None` means the frontend's default location.
+pub fn configured_backups_root(manifest: Option<&InstallManifest>, manifest_dir: &Path) -> Option<PathBuf> {
+    if let Ok(dir) = env::var("MISFIT_BACKUP_DIR") {
+        if !dir.trim().is_empty() {
+            return Some(PathBuf::from(expand_env_vars(dir.trim())));
+        }
+    }
+    let dir = manifest?.backup_dir.as_deref().filter(|d| !d.trim().is_empty())?;
+    Some(resolve_path(manifest_dir, dir.trim()))
+}
+
+/// A payload folder from [`resolve_payload_source`]. One extracted to the temp folder is
+/// deleted when this is dropped, so keep it for as long as the folder is used.
+#[derive(Debug)]
+pub struct PayloadSource {
+    path: PathBuf,
+    extracted: bool,
+}
+
+impl PayloadSource {
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Whether the folder was extracted from a compressed build.
+    pub fn is_extracted(&self) -> bool {
+        self.extracted
+    }
+}
+
+impl Drop for PayloadSource {
+    fn drop(&mut self) {
+        if self.extracted {
+            let _ = fs::remove_dir_all(&self.path);
+        }
+    }
+}
+
+/// The payload folder for a project: a `payloads.zip` / `payloads.tar.zst` next to it is
+/// extracted to a temporary folder, otherwise `payload_dir` below `project_root` is used.
+pub fn resolve_payload_source(project_root: &Path, payload_dir: &str) -> Result<PayloadSource> {
+    static EXTRACTIONS: std::sync::atomic::AtomicUsize = std::sync::atomic::AtomicUsize::new(0);
+    let payload_dir = normalize_rel_path(payload_dir, true)?;
+
+    // A compressed build ships one archive instead of the payload folder
+    for format in [PayloadCompression::Zip, PayloadCompression::TarZst] {
+        let Some(archive_name) = format.archive_name() else { continue };
+        let archive = project_root.join(archive_name);
+        if archive.exists() {
+            let n = EXTRACTIONS.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+            let extracted = PayloadSource { path: env::temp_dir().join(format!("misfit_payload_{}_{}", std::process::id(), n)), extracted: true };
+            extract_payload_archive(&archive, extracted.path())?;
+            return Ok(extracted);
+        }
+    }
+
+    let payload_source = project_root.join(&payload_dir);
+    if !payload_source.exists() {
+        return Err(MisfitError::new(ErrorCode::PayloadMissing, format!("Payload directory not found: {}", payload_source.display()))
+            .with_path(&payload_source)
+            .into());
+    }
+    Ok(PayloadSource { path: payload_source, extracted: false })
+}
+
+/// The text of the manifest's `licenseFile` (relative to the payload folder), for the user to
+/// accept before installing. `None` when the manifest has no license or the file isn't there.
+pub fn license_text(manifest: &InstallManifest, payload_source: &Path) -> Result<Option<String>> {
+    let Some(file) = manifest.license_file.as_deref().filter(|f| !f.trim().is_empty()) else { return Ok(None) };
+    let path = payload_source.join(normalize_rel_path(file, false)?);
+    match fs::read(&path) {
+        Ok(bytes) => Ok(Some(String::from_utf8_lossy(&bytes).into_owned())),
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(err) => Err(MisfitError::from(err).with_path(&path).into()),
+    }
+}
+
+/// Forwards copy progress as `copy-progress` events, at most every 50 ms plus the final
+/// update, so large trees don't flood the frontend.
+pub fn throttled_progress(observer: &dyn InstallObserver) -> impl Fn(&CopyProgress) + Sync + '_ {
+    throttle_progress(move |progress| {
+        if let Ok(payload) = serde_json::to_value(progress) {
+            observer.event("copy-progress", payload);
+        }
+    })
+}
+
+/// Passes copy progress on to `on_progress` at most every 50 ms, plus the final update.
+pub fn throttle_progress(on_progress: impl Fn(&CopyProgress) + Sync) -> impl Fn(&CopyProgress) + Sync {
+    let last = std::sync::Mutex::new(None::<std::time::Instant>);
+    move |progress| {
+        let done = progress.files_copied == progress.total_files;
+        let Ok(mut last) = last.lock() else { return };
+        if done || last.map_or(true, |t| t.elapsed() >= std::time::Duration::from_millis(50)) {
+            *last = Some(std::time::Instant::now());
+            on_progress(progress);
+        }
+    }
+}
+
+pub const JOURNAL_FILE: &str = "install_journal.json";
+
+/// Progress of an install that has not finished, saved after every step so a run that failed
+/// or crashed can be picked up with [`InstallOptions::resume`]. Removed once an install succeeds.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct InstallJournal {
+    /// [`manifest_hash`] of the manifest being installed; resume refuses a different one.
+    pub manifest_hash: String,
+    pub phase: Option<Phase>,
+    pub started_at: String,
+    pub updated_at: String,
+    /// Backup taken by the original run. Resuming restores to it on failure instead of taking
+    /// a new one, which would capture half-installed files.
+    pub backup_dir: Option<PathBuf>,
+    /// Indexes into `installSteps` that finished and were not rolled back.
+    pub completed: Vec<usize>,
+    /// Receipt so far, so patches applied before the interruption can still be reverted.
+    pub receipt: InstallReceipt,
+    pub failed_step: Option<usize>,
+    pub error: Option<String>,
+    /// Components the install was started with.
+    #[serde(default)]
+    pub components: Vec<String>,
+}
+
+/// SHA-256 of the manifest's JSON with object keys sorted, so it doesn't depend on field or
+/// map ordering.
+pub fn manifest_hash(manifest: &InstallManifest) -> Result<String> {
+    fn sorted(value: serde_json::Value) -> serde_json::Value {
+        match value {
+            serde_json::Value::Object(map) => {
+                let mut entries: Vec<_> = map.into_iter().collect();
+                entries.sort_by(|a, b| a.0.cmp(&b.0));
+                serde_json::Value::Object(entries.into_iter().map(|(k, v)| (k, sorted(v))).collect())
+            }
+            serde_json::Value::Array(items) => serde_json::Value::Array(items.into_iter().map(sorted).collect()),
+            other => other,
+        }
+    }
+    let canonical = sorted(serde_json::to_value(manifest)?);
+    Ok(sha256_hex(serde_json::to_string(&canonical)?.as_bytes()))
+}
+
+pub fn load_journal(backup_root: &Path) -> Result<Option<InstallJournal>> {
+    let path = backup_root.join(JOURNAL_FILE);
+    if !path.exists() {
+        return Ok(None);
+    }
+    let content = fs::read_to_string(&path).context("Failed to read install journal")?;
+    let journal = serde_json::from_str(&content).context("Failed to parse install journal")?;
+    Ok(Some(journal))
+}
+
+fn save_journal(journal: &mut InstallJournal, backup_root: &Path) -> Result<()> {
+    journal.updated_at = chrono::Local::now().to_rfc3339();
+    fs::create_dir_all(backup_root).context("Failed to create journal directory")?;
+    let json = serde_json::to_string_pretty(journal)?;
+    write_atomic(&backup_root.join(JOURNAL_FILE), json.as_bytes()).context("Failed to write install journal")?;
+    Ok(())
+}
+
+/// The unfinished install of `manifest` under `backups_root`, if there is one that can be resumed.
+pub fn pending_install(manifest: &InstallManifest, backups_root: &Path) -> Result<Option<InstallJournal>> {
+    let backup_root = backups_root.join(backup_namespace(&manifest.app_name));
+    let Some(journal) = load_journal(&backup_root)? else { return Ok(None) };
+    Ok((journal.manifest_hash == manifest_hash(manifest)?).then_some(journal))
+}
+
+/// Refuses a delete that would take the install target, one of its parents, a filesystem root
+/// or the home folder with it, whatever `path` looked like before it was resolved.
+fn check_delete_target(path: &str, target_path: &Path, target_base: &Path) -> Result<()> {
+    let resolved = |p: &Path| fs::canonicalize(p).unwrap_or_else(|_| p.to_path_buf());
+    let target = resolved(target_path);
+    let protected = target.parent().is_none()
+        || resolved(target_base).starts_with(&target)
+        || dirs::home_dir().is_some_and(|home| resolved(&home) == target);
+    let problem = match check_delete_path(path) {
+        Err(problem) => problem,
+        Ok(()) if protected => format!("delete path {:?} resolves to {}, which contains the install target or is a root folder", path, target.display()),
+        Ok(()) => return Ok(()),
+    };
+    Err(MisfitError::new(ErrorCode::InvalidManifest, problem).with_path(target_path).into())
+}
+
+/// Steps whose effects survive a rollback: commands and extensions are not covered by the backup,
+/// so they stay done, while copies and everything that edits a backed-up file have to run again.
+fn survives_rollback(step: &InstallStep) -> bool {
+    matches!(step, InstallStep::RunCommand { .. } | InstallStep::InstallVsix { .. })
+}
+
+/// Where a copy step writes: the existing files it may overwrite, which are backed up, and
+/// for each file it creates, its topmost folder that doesn't exist yet (or the file itself),
+/// which a rollback removes. A copy that can't be planned is left to fail when it runs.
+fn copy_targets(step: &InstallStep, ctx: &StepContext) -> (Vec<PathBuf>, Vec<PathBuf>) {
+    let InstallStep::Copy { src, dest, exclude, .. } = step else { return Default::default() };
+    let dest = resolve_path(&ctx.target_base, dest);
+    let Ok(plan) = plan_copy_step(src, &dest, exclude.as_deref().unwrap_or_default(), &ctx.payload_source) else { return Default::default() };
+    let (mut overwritten, mut created) = (Vec::new(), Vec::new());
+    for job in plan.files {
+        if fs::symlink_metadata(&job.dest).is_ok() {
+            overwritten.push(job.dest);
+            continue;
+        }
+        let mut top = job.dest;
+        while let Some(parent) = top.parent().filter(|p| !p.as_os_str().is_empty() && fs::symlink_metadata(p).is_err()) {
+            top = parent.to_path_buf();
+        }
+        created.push(top);
+    }
+    (overwritten, created)
+}
+
+/// What running `step` backs up first, and what it creates (see [`copy_targets`]), as the
+/// install and its plan both list them.
+fn step_backups(step: &InstallStep, ctx: &StepContext) -> (Vec<PathBuf>, Vec<PathBuf>) {
+    match step {
+        InstallStep::Copy { .. } => copy_targets(step, ctx),
+        InstallStep::PatchBlock { file, .. } | InstallStep::SetJsonValue { file, .. } | InstallStep::Base64Embed { file, .. } | InstallStep::Delete { path: file } => {
+            (vec![containing_file(&resolve_path(&ctx.target_base, file))], Vec::new())
+        }
+        InstallStep::FixVsCodeChecksums { app_dir } => (vscode_product_json(&resolve_path(&ctx.target_base, app_dir)).into_iter().collect(), Vec::new()),
+        _ => Default::default(),
+    }
+}
+
+/// Runs every step of `manifest`. Targets are backed up first and restored if a step fails;
+/// the receipt (used by `revert_all_patches`) is only saved when all steps succeed. Progress
+/// is kept in an [`InstallJournal`] until then. Once the steps have run, successfully or not,
+/// an [`InstallReport`] is written into the install's backup folder; its path is returned.
+pub fn run_install(manifest: &InstallManifest, options: &InstallOptions, observer: &dyn InstallObserver) -> Result<PathBuf> {
+    let started = std::time::Instant::now();
+    let started_at = chrono::Local::now().to_rfc3339();
+    let reporter = ReportingObserver::new(observer);
+    let observer: &dyn InstallObserver = &reporter;
+    // The journal is keyed on the manifest as written, so resuming doesn't depend on the overrides
+    let written = manifest;
+    let manifest = &written.for_platform(Platform::current());
+    let advanced_mode = manifest.advanced_mode.unwrap_or(false);
+
+    // The signature covers the manifest files, so the manifest that runs has to be one of them
+    let signature = verify_install_signature(&options.manifest_dir, options.trusted_key.as_deref()).and_then(|status| match status {
+        SignatureStatus::Valid { .. } if !is_signed_manifest(written, &options.manifest_dir)? => {
+            Ok(SignatureStatus::Invalid { reason: "the manifest being installed is not the signed one".to_string() })
+        }
+        status => Ok(status),
+    });
+    match signature {
+        Ok(SignatureStatus::Valid { fingerprint }) => {
+            observer.log(&format!("Signature verified (key {})", fingerprint));
+        }
+        Ok(SignatureStatus::Unsigned) => {}
+        // An installer built with a signing key refuses to run anything it can't vouch for
+        Ok(SignatureStatus::Invalid { reason }) if options.trusted_key.is_some() => {
+            let message = format!("Installer contents were modified after they were built ({})", reason);
+            return Err(MisfitError::new(ErrorCode::IntegrityFailed, message).into());
+        }
+        Err(err) if options.trusted_key.is_some() => {
+            let message = format!("Could not verify the installer signature: {:#}", err);
+            return Err(MisfitError::new(ErrorCode::IntegrityFailed, message).into());
+        }
+        Ok(SignatureStatus::Invalid { reason }) => {
+            let msg = format!("WARNING: installer contents were modified after they were built ({})", reason);
+            observer.event("integrity-warning", serde_json::Value::String(msg.clone()));
+            observer.warn(&msg);
+        }
+        Err(err) => {
+            let msg = format!("WARNING: could not verify installer signature: {}", err);
+            observer.event("integrity-warning", serde_json::Value::String(msg.clone()));
+            observer.warn(&msg);
+        }
+    }
+
+    if !options.accepted_license && license_text(manifest, &options.payload_source)?.is_some() {
+        let message = format!("The license of {} has to be accepted before installing", manifest.app_name);
+        return Err(MisfitError::new(ErrorCode::LicenseNotAccepted, message).into());
+    }
+
+    let lock_path = options.manifest_dir.join(PAYLOAD_LOCK_FILE);
+    if lock_path.exists() {
+        let lock_json = fs::read_to_string(&lock_path)?;
+        let lock: PayloadLock = serde_json::from_str(&lock_json).map_err(|e| anyhow!("Invalid payload lockfile: {}", e))?;
+        verify_payload_lock(&options.payload_source, &lock)?;
+        observer.log(&format!("Verified {} payload file(s)", lock.files.len()));
+    } else {
+        observer.log("No payload lockfile found, skipping integrity check");
+    }
+
+    let namespace = backup_namespace(&manifest.app_name);
+    let backup_root = options.backups_root.join(&namespace);
+    let _lock = lock_operation(&backup_root, "install")?;
+    let _target_lock = lock_target(&options.target_base, "install")?;
+    let resumed = if options.resume {
+        let journal = pending_install(written, &options.backups_root)?
+            .ok_or_else(|| MisfitError::new(ErrorCode::NotFound, "No unfinished install of this manifest to resume"))?;
+        observer.log(&format!("Resuming install; {} step(s) already done", journal.completed.len()));
+        Some(journal)
+    } else {
+        None
+    };
+    let phase = resumed.as_ref().map_or(options.phase, |j| j.phase);
+    let requested = resumed.as_ref().map(|j| j.components.clone()).or_else(|| options.components.clone());
+    let components = resolve_components(manifest, requested.as_deref())?;
+    let prompts = PromptValues::resolve(manifest, &options.prompt_values)?;
+
+    let target_base = &options.target_base;
+    let preflight = preflight_manifest(&manifest.with_components(&components), target_base, &options.payload_source, &options.backups_root)?;
+    if let Some(message) = preflight.target_version.as_ref().and_then(|check| check.message.clone()) {
+        let mut err = MisfitError::new(ErrorCode::IncompatibleTarget, message);
+        err.path = preflight.target_version.as_ref().map(|check| check.path.clone()).filter(|path| !path.is_empty());
+        return Err(err.into());
+    }
+
+    if preflight.needs_elevation {
+        let protected: Vec<String> = preflight.protected_files().map(|f| f.path.clone()).collect();
+        observer.event("elevation-required", serde_json::json!(protected));
+        let message = format!(
+            "Administrator rights are required to write {} file(s), e.g. {}. Relaunch the installer elevated to continue.",
+            protected.len(),
+            protected.first().map(String::as_str).unwrap_or_default()
+        );
+        let mut err = MisfitError::new(ErrorCode::ElevationRequired, message);
+        err.path = protected.first().cloned();
+        return Err(err.into());
+    }
+
+
+    let ctx = StepContext {
+        target_base: target_base.clone(),
+        payload_source: options.payload_source.clone(),
+        advanced_mode,
+        replace_locked_on_reboot: manifest.replace_locked_on_reboot.unwrap_or(false),
+        prompts,
+    };
+    run_hooks("Pre-install", &manifest.pre_install, HookFailure::Abort, &ctx, observer)?;
+
+    // Backup first
+    let steps: Vec<(usize, &ManifestStep)> = manifest.ordered_steps(phase).into_iter().filter(|(_, s)| s.in_components(&components)).collect();
+    let mut backup_paths = Vec::new();
+    let mut created_paths = Vec::new();
+    for (_, step) in &steps {
+        let (backed_up, created) = step_backups(&step.step, &ctx);
+        backup_paths.extend(backed_up.iter().map(|p| p.to_string_lossy().to_string()));
+        created_paths.extend(created.iter().map(|p| p.to_string_lossy().to_string()));
+    }
+    backup_paths.sort();
+    backup_paths.dedup();
+    created_paths.sort();
+    created_paths.dedup();
+
+    let mut backup_dir = resumed.as_ref().and_then(|j| j.backup_dir.clone());
+    if resumed.is_none() && (!backup_paths.is_empty() || !created_paths.is_empty()) {
+        let backup_loc = backup_files(&backup_paths, &backup_root)?;
+        write_atomic(&backup_loc.join(CREATED_FILES_FILE), serde_json::to_string_pretty(&created_paths)?.as_bytes())?;
+        observer.log(&format!("Backup created at {:?}", backup_loc));
+        if let Some(policy) = &manifest.backup_retention {
+            match prune_backups(&options.backups_root, Some(&namespace), policy, false) {
+                Ok(report) if !report.removed.is_empty() => {
+                    observer.log(&format!("Pruned {} old backup(s)", report.removed.len()));
+                }
+                Ok(_) => {}
+                Err(err) => observer.warn(&format!("Backup pruning failed: {}", err)),
+            }
+        }
+        backup_dir = Some(backup_loc);
+    }
+
+    let base_receipt = load_receipt(&backup_root)?.unwrap_or_default();
+    let mut journal = resumed.unwrap_or_else(|| InstallJournal {
+        manifest_hash: String::new(),
+        phase,
+        started_at: chrono::Local::now().to_rfc3339(),
+        updated_at: String::new(),
+        backup_dir: backup_dir.clone(),
+        completed: Vec::new(),
+        receipt: base_receipt.clone(),
+        failed_step: None,
+        error: None,
+        components: components.iter().cloned().collect(),
+    });
+    journal.manifest_hash = manifest_hash(written)?;
+    journal.failed_step = None;
+    journal.error = None;
+    save_journal(&mut journal, &backup_root)?;
+
+    let mut receipt = journal.receipt.clone();
+    receipt.app_name = manifest.app_name.to_string();
+    receipt.version = manifest.version.clone();
+    receipt.installed_at = chrono::Local::now().to_rfc3339();
+    receipt.components = components.iter().cloned().collect();
+    receipt.target_base = Some(fs::canonicalize(target_base).unwrap_or_else(|_| target_base.clone()).to_string_lossy().to_string());
+
+    let snapshot = FileSnapshot::take(&steps, &ctx);
+    let mut report = InstallReport {
+        app_name: manifest.app_name.to_string(),
+        version: manifest.version.clone(),
+        manifest_hash: journal.manifest_hash.clone(),
+        phase,
+        started_at,
+        finished_at: String::new(),
+        duration_ms: 0,
+        total_bytes: preflight.total_bytes,
+        success: false,
+        error: None,
+        steps: Vec::new(),
+        files: Vec::new(),
+        warnings: Vec::new(),
+        system: Some(system_info(&[&options.target_base, &backup_root])),
+    };
+    let finish_report = |report: &mut InstallReport, error: Option<String>| {
+        report.finished_at = chrono::Local::now().to_rfc3339();
+        report.duration_ms = started.elapsed().as_millis() as u64;
+        report.success = error.is_none();
+        report.error = error;
+        report.files = snapshot.changes();
+        report.warnings = reporter.take_warnings();
+        let saved = save_install_report(report, backup_dir.as_deref(), &backup_root);
+        match &saved {
+            Ok(path) => observer.log(&format!("Install report saved to {}", path.display())),
+            Err(err) => observer.log(&format!("Could not save install report: {:#}", err)),
+        }
+        saved
+    };
+
+    for phase in Phase::ALL {
+        let phase_steps: Vec<&(usize, &ManifestStep)> = steps.iter().filter(|(_, s)| s.phase == phase).collect();
+        if phase_steps.is_empty() {
+            continue;
+        }
+        observer.log(&format!("Phase {:?}: {} step(s)", phase, phase_steps.len()));
+        for (completed, (index, step)) in phase_steps.iter().enumerate() {
+            let index = *index;
+            observer.event(
+                "phase-progress",
+                serde_json::json!({ "phase": phase, "stepIndex": index, "completed": completed, "total": phase_steps.len() }),
+            );
+            let reported = |status, step_started: std::time::Instant, error| ReportedStep {
+                index,
+                kind: step_kind(step),
+                phase,
+                status,
+                duration_ms: step_started.elapsed().as_millis() as u64,
+                error,
+            };
+            if journal.completed.contains(&index) {
+                observer.log(&format!("Step {} already done, skipping", index + 1));
+                report.steps.push(reported(ReportedStatus::AlreadyDone, std::time::Instant::now(), None));
+                continue;
+            }
+            let step_started = std::time::Instant::now();
+            if let Err(err) = run_step_or_roll_back(index, step, &ctx, &mut receipt, backup_dir.as_deref(), observer) {
+                report.steps.push(reported(ReportedStatus::Failed, step_started, Some(format!("{:#}", err))));
+                if backup_dir.is_some() {
+                    discard_pending_replacements(&receipt, &base_receipt, observer);
+                    journal.completed.retain(|i| survives_rollback(&manifest.install_steps[*i]));
+                    journal.receipt = base_receipt.clone();
+                }
+                journal.failed_step = Some(index);
+                journal.error = Some(format!("{:#}", err));
+                if let Err(e) = save_journal(&mut journal, &backup_root) {
+                    observer.warn(&format!("Could not save install journal: {}", e));
+                }
+                let _ = finish_report(&mut report, Some(format!("{:#}", err)));
+                return Err(err);
+            }
+            report.steps.push(reported(ReportedStatus::Done, step_started, None));
+            journal.completed.push(index);
+            journal.receipt = receipt.clone();
+            save_journal(&mut journal, &backup_root)?;
+        }
+        observer.event(
+            "phase-progress",
+            serde_json::json!({ "phase": phase, "stepIndex": null, "completed": phase_steps.len(), "total": phase_steps.len() }),
+        );
+    }
+
+    if manifest.watch_installed_files.unwrap_or(false) {
+        receipt.watched_files.extend(snapshot.current());
+    } else {
+        receipt.watched_files.clear();
+    }
+    save_receipt(&receipt, &backup_root)?;
+    let _ = fs::remove_file(backup_root.join(JOURNAL_FILE));
+    if let Err(err) = run_hooks("Post-install", &manifest.post_install, HookFailure::Continue, &ctx, observer) {
+        let _ = finish_report(&mut report, Some(format!("{:#}", err)));
+        return Err(err);
+    }
+    let report_path = finish_report(&mut report, None)?;
+    observer.log("Installation complete!");
+    Ok(report_path)
+}
+
+/// Runs `hooks` in order. A failing hook stops the rest and fails the install unless its
+/// `onError` (or `default`) is `continue`.
+fn run_hooks(kind: &str, hooks: &[Hook], default: HookFailure, ctx: &StepContext, observer: &dyn InstallObserver) -> Result<()> {
+    if hooks.is_empty() {
+        return Ok(());
+    }
+    observer.log(&format!("{} hooks: {}", kind, hooks.len()));
+    // Hooks are not recorded for uninstall
+    let mut receipt = InstallReceipt::default();
+    for (index, hook) in hooks.iter().enumerate() {
+        if !hook.runs_on(Platform::current()) {
+            continue;
+        }
+        let Err(err) = execute_step(&hook.step, ctx, &mut receipt, observer) else { continue };
+        let mut err = MisfitError::from(err);
+        err.message = format!("{} hook {} failed: {}", kind, index + 1, err.message);
+        if hook.on_error.unwrap_or(default) == HookFailure::Abort {
+            return Err(err.into());
+        }
+        observer.warn(&format!("{}; continuing", err.message));
+    }
+    Ok(())
+}
+
+/// Copies `plan`, registering files that stay locked to be replaced at the next restart and
+/// recording them in `receipt`. Reports them in a `reboot-required` event.
+fn copy_or_replace_on_reboot(plan: &CopyPlan, receipt: &mut InstallReceipt, observer: &dyn InstallObserver) -> Result<()> {
+    let locked = copy_plan_skipping_locked(plan, &throttled_progress(observer))?;
+    let mut pending = Vec::new();
+    for job in locked {
+        let staged = replace_on_reboot(&job.src, &job.dest)?;
+        observer.warn(&format!("{} is in use; it will be replaced when Windows restarts", job.dest.display()));
+        let dest = job.dest.to_string_lossy().to_string();
+        receipt.pending_replacements.insert(dest.clone(), staged.to_string_lossy().to_string());
+        pending.push(dest);
+    }
+    if !pending.is_empty() {
+        observer.event("reboot-required", serde_json::json!(pending));
+    }
+    Ok(())
+}
+
+/// Deletes the staged copies of replacements in `receipt` that `kept` doesn't have. Windows
+/// skips a pending replacement whose staged file is gone, so the target stays as it is.
+fn discard_pending_replacements(receipt: &InstallReceipt, kept: &InstallReceipt, observer: &dyn InstallObserver) {
+    for (dest, staged) in &receipt.pending_replacements {
+        if kept.pending_replacements.get(dest) == Some(staged) {
+            continue;
+        }
+        match fs::remove_file(staged) {
+            Err(err) if err.kind() != std::io::ErrorKind::NotFound => {
+                observer.warn(&format!("Could not delete {}: {}", staged, err));
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Runs one step; on failure restores `backup_dir` (when there is one) and returns the error
+/// tagged with the step's index.
+fn run_step_or_roll_back(
+    index: usize,
+    step: &InstallStep,
+    ctx: &StepContext,
+    receipt: &mut InstallReceipt,
+    backup_dir: Option<&Path>,
+    observer: &dyn InstallObserver,
+) -> Result<()> {
+    let Err(err) = execute_step(step, ctx, receipt, observer) else { return Ok(()) };
+    let mut err = MisfitError::from(err).at_step(index);
+    if let Some(backup_dir) = backup_dir {
+        observer.log(&format!("Step {} failed: {}. Rolling back...", index + 1, err));
+        if let Err(e) = roll_back_install(backup_dir) {
+            err.message = format!("{} (rollback failed: {})", err.message, e);
+            return Err(err.into());
+        }
+        observer.log(&format!("Rolled back from {}", backup_dir.display()));
+    }
+    Err(err.into())
+}
+
+pub fn execute_step(step: &InstallStep, ctx: &StepContext, receipt: &mut InstallReceipt, observer: &dyn InstallObserver) -> Result<()> {
+    let step = &ctx.prompts.fill(step);
+    let target_base = &ctx.target_base;
+    let payload_source = &ctx.payload_source;
+    let advanced_mode = ctx.advanced_mode;
+    match step {
+        InstallStep::Copy { src, dest, exclude, overwrite, replacements } => {
+            let d = resolve_path(target_base, dest);
+            observer.log(&format!("Copying {} to {:?}", src, d));
+            let plan = plan_copy_step(src, &d, exclude.as_deref().unwrap_or_default(), payload_source)?;
+            let plan = confirm_conflicts(apply_overwrite_policy(plan, *overwrite)?, observer);
+            if ctx.replace_locked_on_reboot {
+                copy_or_replace_on_reboot(&plan, receipt, observer)?;
+            } else {
+                copy_plan_parallel(&plan, &throttled_progress(observer))?;
+            }
+            if let Some(replacements) = replacements {
+                let replaced = replace_in_copied_files(&plan, replacements)?;
+                if replaced > 0 {
+                    observer.log(&format!("Replaced tokens in {} copied file(s)", replaced));
+                }
+            }
+        }
+        InstallStep::PatchBlock { file, .. } => {
+            let target_path = resolve_path(target_base, file);
+            observer.log(&format!("Patching {}", target_path.display()));
+            let specs = with_stripped_content(patch_specs(step, payload_source, advanced_mode)?, &target_path, receipt);
+            // Without markers, revert finds the block by its text, which the receipt can't keep
+            if advanced_mode && specs.iter().any(|spec| ctx.prompts.redact(&spec.content) != spec.content) {
+                let message = format!("The patch of {} contains a secret prompt value, which needs markers; turn off advancedMode", target_path.display());
+                return Err(MisfitError::new(ErrorCode::InvalidManifest, message).with_path(&target_path).into());
+            }
+
+            let outcomes = patch_file_blocks(&target_path, &specs).map_err(|e| MisfitError::from(e).with_path(&target_path))?;
+            for (spec, outcome) in specs.into_iter().zip(outcomes) {
+                let (original, inserted) = match outcome {
+                    PatchOutcome::Applied { original } => (original, false),
+                    PatchOutcome::Inserted => {
+                        observer.log(&format!("Markers not found in {}, inserted new block", target_path.display()));
+                        (Vec::new(), true)
+                    }
+                    PatchOutcome::AlreadyApplied => {
+                        observer.log(&format!("Patch already applied to {}, skipping", target_path.display()));
+                        continue;
+                    }
+                };
+                // The text from before the install goes back as it was, secret or not
+                let (original, original_base64) = original_fields(original);
+                receipt.record_patch(PatchRecord {
+                    file: target_path.to_string_lossy().to_string(),
+                    start_marker: spec.start_marker,
+                    end_marker: spec.end_marker,
+                    original,
+                    original_base64,
+                    applied: ctx.prompts.redact(&spec.content),
+                    markers_stripped: advanced_mode,
+                    matching: spec.matching,
+                    inserted,
+                });
+            }
+        }
+        InstallStep::SetJsonValue { file, key_path, value } => {
+            let target_path = resolve_path(target_base, file);
+            observer.log(&format!("Updating JSON {} key {}", target_path.display(), key_path));
+            set_json_value(&target_path, key_path, value).map_err(|e| MisfitError::from(e).with_path(&target_path))?;
+        }
+        InstallStep::RunCommand { command, args, cwd, env, timeout_secs, shell, elevate } => {
+            let elevate = elevate.unwrap_or(false);
+            let mut line = command_line(command, args);
+            if let Some(shell) = shell {
+                line = format!("{} (through {:?})", line, shell);
+            }
+            if elevate {
+                line.push_str(" (as administrator)");
+            }
+            observer.log(&format!("Running command: {}", line));
+            if !advanced_mode && !observer.confirm_command(command, &line) {
+                return Err(MisfitError::new(ErrorCode::Cancelled, format!("Command was not approved: {}", line)).into());
+            }
+            let options = CommandOptions {
+                cwd: cwd.as_deref().map(|dir| resolve_path(target_base, dir)),
+                env: env.clone().unwrap_or_default(),
+                timeout: timeout_secs.map(std::time::Duration::from_secs),
+                shell: *shell,
+                elevate,
+            };
+            if elevate && cfg!(windows) && !is_elevated() {
+                observer.log("Output of commands run as administrator is not captured");
+            }
+            run_command(command, args, &options, &|line| observer.log(&format!("> {}", line)))?;
+        }
+        InstallStep::Base64Embed { file, placeholder, input_file, occurrences, data_uri, replacements } => {
+            let target_path = resolve_path(target_base, file);
+            observer.log(&format!("Embedding base64 into {}", target_path.display()));
+            let input_path = payload_source.join(normalize_rel_path(input_file, false)?);
+            let options = EmbedOptions { max_occurrences: *occurrences, data_uri: *data_uri, replacements: replacements.clone() };
+            let count = base64_embed(&target_path, placeholder, &input_path, &options)?;
+            if count == 0 {
+                observer.log(&format!("Base64 content already embedded in {}, skipping", target_path.display()));
+            } else {
+                observer.log(&format!("Replaced {} occurrence(s) of {}", count, placeholder));
+            }
+        }
+        InstallStep::InstallVsix { vsix_file, cli_path } => {
+            let vsix = payload_source.join(normalize_rel_path(vsix_file, false)?);
+            let cli = match cli_path {
+                Some(cli) => resolve_path(target_base, cli),
+                None => default_vscode_cli()?,
+            };
+            observer.log(&format!("Installing extension {} with {}", vsix_file, cli.display()));
+            install_vsix(&cli, &vsix, &|line| observer.log(&format!("> {}", line)))?;
+        }
+        InstallStep::FixVsCodeChecksums { app_dir } => {
+            let app_dir = resolve_path(target_base, app_dir);
+            let fixed = fix_vscode_checksums(&app_dir)?;
+            if fixed.is_empty() {
+                observer.log(&format!("VS Code checksums in {} are up to date", app_dir.display()));
+            } else {
+                observer.log(&format!("Updated {} VS Code checksum(s): {}", fixed.len(), fixed.join(", ")));
+            }
+        }
+        InstallStep::Delete { path } => {
+            let target_path = resolve_path(target_base, path);
+            check_delete_target(path, &target_path, target_base)?;
+            match fs::symlink_metadata(&target_path) {
+                Ok(meta) => {
+                    observer.log(&format!("Deleting {}", target_path.display()));
+                    let removed = retry_if_locked(|| if meta.is_dir() { fs::remove_dir_all(&target_path) } else { fs::remove_file(&target_path) });
+                    removed.map_err(|e| MisfitError::from(e).with_path(&target_path))?;
+                }
+                Err(_) => observer.log(&format!("{} is already gone", target_path.display())),
+            }
+        }
+        InstallStep::Verify { file, contains, sha256, json_path, equals } => {
+            let target_path = resolve_path(target_base, file);
+            observer.log(&format!("Verifying {}", target_path.display()));
+            let checks = VerifyChecks {
+                contains: contains.clone(),
+                sha256: sha256.clone(),
+                json_path: json_path.clone(),
+                equals: equals.clone(),
+            };
+            verify_file(&target_path, &checks).map_err(|e| anyhow!("Verification failed: {}", e))?;
+        }
+    }
+    Ok(())
+}
+
+/// Undoes every patch in the app's install receipt, newest first. The receipt is saved after
+/// each failure so a later attempt resumes where this one stopped.
+pub fn revert_all_patches(app_backup_root: &Path, observer: &dyn InstallObserver) -> Result<()> {
+    let _lock = lock_operation(app_backup_root, "uninstall")?;
+    let mut receipt = load_receipt(app_backup_root)?.ok_or_else(|| anyhow!("No install receipt found"))?;
+    let _target_lock = receipt.target_base.as_deref().map(|target| lock_target(Path::new(target), "uninstall")).transpose()?;
+
+    // Undo in reverse order so blocks patched twice unwind correctly.
+    while let Some(record) = receipt.patches.pop() {
+        observer.log(&format!("Reverting patch in {}", record.file));
+        if let Err(err) = revert_patch(&record) {
+            receipt.patches.push(record);
+            save_receipt(&receipt, app_backup_root)?;
+            return Err(err);
+        }
+    }
+    receipt.watched_files.clear();
+    discard_pending_replacements(&receipt, &InstallReceipt::default(), observer);
+    receipt.pending_replacements.clear();
+    save_receipt(&receipt, app_backup_root)?;
+
+    observer.log("Patches reverted.");
+    Ok(())
+}
+
+/// Reports whether the install can run: payload size against free space on each target
+/// volume (plus the backup folder), and whether every touched file is writable and unlocked.
+pub fn preflight_manifest(manifest: &InstallManifest, target_base: &Path, payload_source: &Path, backups_root: &Path) -> Result<PreflightReport> {
+    let manifest = &manifest.for_platform(Platform::current());
+    let mut targets = Vec::new();
+    let mut backup_bytes = 0;
+    for (_, step) in manifest.ordered_steps(None) {
+        match &step.step {
+            InstallStep::Copy { src, dest, exclude, .. } => {
+                let d = resolve_path(target_base, dest);
+                let plan = plan_copy_step(src, &d, exclude.as_deref().unwrap_or_default(), payload_source)?;
+                targets.extend(plan.files.into_iter().map(|job| PreflightTarget { path: job.dest, bytes: job.size }));
+            }
+            InstallStep::PatchBlock { file, .. }
+            | InstallStep::SetJsonValue { file, .. }
+            | InstallStep::Base64Embed { file, .. } => {
+                let path = containing_file(&resolve_path(target_base, file));
+                backup_bytes += fs::metadata(&path).map(|m| m.len()).unwrap_or(0);
+                targets.push(PreflightTarget { path, bytes: 0 });
+            }
+            InstallStep::FixVsCodeChecksums { app_dir } => {
+                if let Some(path) = vscode_product_json(&resolve_path(target_base, app_dir)) {
+                    backup_bytes += fs::metadata(&path).map(|m| m.len()).unwrap_or(0);
+                    targets.push(PreflightTarget { path, bytes: 0 });
+                }
+            }
+            InstallStep::Delete { path } => {
+                let path = resolve_path(target_base, path);
+                backup_bytes += tree_size(&path);
+                if path.is_file() {
+                    targets.push(PreflightTarget { path, bytes: 0 });
+                }
+            }
+            InstallStep::RunCommand { .. } | InstallStep::Verify { .. } | InstallStep::InstallVsix { .. } => {}
+        }
+    }
+
+    let backup_root = backups_root.join(backup_namespace(&manifest.app_name));
+    let reserved = [PreflightTarget { path: backup_root, bytes: backup_bytes }];
+    let mut report = preflight_check(&targets, &reserved);
+    report.target_version = check_target_version(manifest, target_base);
+    report.ok &= report.target_version.as_ref().map_or(true, |check| check.compatible);
+    Ok(report)
+}
+
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct TargetVersionCheck {
+    /// File the version was read from.
+    pub path: String,
+    /// `None` when the file or key could not be read.
+    pub found: Option<String>,
+    pub min: Option<String>,
+    pub max: Option<String>,
+    pub compatible: bool,
+    /// Why the target was refused, ready to show to the user.
+    pub message: Option<String>,
+}
+
+/// Compares the target app's installed version against `minTargetVersion` and
+/// `maxTargetVersion`. `None` when the manifest sets neither.
+pub fn check_target_version(manifest: &InstallManifest, target_base: &Path) -> Option<TargetVersionCheck> {
+    let manifest = &manifest.for_platform(Platform::current());
+    let (min, max) = (manifest.min_target_version.clone(), manifest.max_target_version.clone());
+    if min.is_none() && max.is_none() {
+        return None;
+    }
+    let Some(source) = &manifest.target_version_from else {
+        return Some(TargetVersionCheck {
+            path: String::new(),
+            found: None,
+            compatible: false,
+            message: Some("The manifest limits the target version but has no targetVersionFrom to read it from".to_string()),
+            min,
+            max,
+        });
+    };
+    let path = resolve_path(target_base, &source.file);
+    let found = read_target_version(&path, source.json_path.as_deref());
+    let message = match &found {
+        Err(err) => Some(format!("Could not read the installed version of {} from {}: {:#}", manifest.app_name, path.display(), err)),
+        Ok(version) => {
+            let too_old = min.as_deref().filter(|min| compare_versions(version, min, false) == std::cmp::Ordering::Less);
+            let too_new = max.as_deref().filter(|max| compare_versions(version, max, true) == std::cmp::Ordering::Greater);
+            match (too_old, too_new) {
+                (Some(min), _) => Some(format!("This installer needs version {} or newer of the target, but {} is installed", min, version)),
+                (_, Some(max)) => Some(format!("This installer supports the target up to version {}, but {} is installed", max, version)),
+                _ => None,
+            }
+        }
+    };
+    Some(TargetVersionCheck {
+        path: path.to_string_lossy().to_string(),
+        found: found.ok(),
+        compatible: message.is_none(),
+        message,
+        min,
+        max,
+    })
+}
+
+fn read_target_version(path: &Path, json_path: Option<&str>) -> Result<String> {
+    let bytes = read_target(path)?;
+    let Some(key_path) = json_path else {
+        let text = String::from_utf8_lossy(&bytes);
+        let version = regex::Regex::new(r"\d+(?:\.\d+)+").expect("valid regex").find(&text).map(|m| m.as_str().to_string());
+        return version.ok_or_else(|| anyhow!("no version number in the file"));
+    };
+    let json: serde_json::Value = serde_json::from_slice(&bytes).context("Failed to parse JSON")?;
+    let mut current = &json;
+    for part in split_key_path(key_path)? {
+        current = current.get(&part).ok_or_else(|| anyhow!("no value at {}", key_path))?;
+    }
+    current.as_str().map(str::to_string).ok_or_else(|| anyhow!("{} is not a string", key_path))
+}
+
+/// Orders dotted versions by their leading numeric parts, ignoring suffixes such as
+/// `-insider`. Missing parts count as 0; with `prefix_only` only the parts `bound` has are
+/// compared, so `1.90.2` equals a bound of `1.90`.
+pub fn compare_versions(version: &str, bound: &str, prefix_only: bool) -> std::cmp::Ordering {
+    let parts = |v: &str| -> Vec<u64> {
+        v.trim()
+            .trim_start_matches(['v', 'V'])
+            .split(['.', '-', '+', ' '])
+            .map_while(|part| part.parse().ok())
+            .collect()
+    };
+    let (version, bound) = (parts(version), parts(bound));
+    let len = if prefix_only { bound.len() } else { version.len().max(bound.len()) };
+    (0..len)
+        .map(|i| version.get(i).unwrap_or(&0).cmp(bound.get(i).unwrap_or(&0)))
+        .find(|order| order.is_ne())
+        .unwrap_or(std::cmp::Ordering::Equal)
+}
+
+/// Size of a file, or of every file under a folder; 0 when missing.
+fn tree_size(path: &Path) -> u64 {
+    walkdir::WalkDir::new(path)
+        .into_iter()
+        .flatten()
+        .filter(|e| e.file_type().is_file())
+        .filter_map(|e| e.metadata().ok())
+        .map(|m| m.len())
+        .sum()
+}
+
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct PlannedStep {
+    /// Position in `installSteps`; steps are listed in run order, which groups them by phase.
+    pub index: usize,
+    pub phase: Phase,
+    /// The step's `type` as written in the manifest.
+    pub kind: String,
+    pub description: String,
+    /// Absolute payload path the step reads from.
+    pub source: Option<String>,
+    /// Absolute path the step writes to or checks.
+    pub target: Option<String>,
+    pub file_count: usize,
+    pub bytes: u64,
+    /// `false` when the step would change nothing (patch already applied, every file kept).
+    pub will_change: bool,
+    pub notes: Vec<String>,
+    /// Why the step could not be resolved; the install would fail here.
+    pub error: Option<String>,
+}
+
+#[derive(Serialize, Debug, Clone, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct InstallPlan {
+    pub steps: Vec<PlannedStep>,
+    pub total_bytes: u64,
+    /// Existing files that are backed up before the first step runs.
+    pub backup_files: Vec<String>,
+}
+
+pub(crate) fn step_kind(step: &InstallStep) -> String {
+    serde_json::to_value(step)
+        .ok()
+        .and_then(|v| v["type"].as_str().map(str::to_string))
+        .unwrap_or_default()
+}
+
+/// Resolves `manifest` into the concrete, ordered list of what `run_install` would do with the
+/// same roots and `components` (the defaults when `None`), without changing anything. A step
+/// that cannot be resolved gets an `error` instead of failing the whole plan.
+pub fn resolve_install_plan(manifest: &InstallManifest, target_base: &Path, payload_source: &Path, components: Option<&[String]>) -> Result<InstallPlan> {
+    let manifest = &manifest.for_platform(Platform::current());
+    let components = resolve_components(manifest, components)?;
+    let advanced_mode = manifest.advanced_mode.unwrap_or(false);
+    let ctx = StepContext {
+        target_base: target_base.to_path_buf(),
+        payload_source: payload_source.to_path_buf(),
+        advanced_mode,
+        replace_locked_on_reboot: false,
+        prompts: PromptValues::default(),
+    };
+    let mut plan = InstallPlan::default();
+    for (index, step) in manifest.ordered_steps(None).into_iter().filter(|(_, s)| s.in_components(&components)) {
+        let mut planned = PlannedStep {
+            index,
+            phase: step.phase,
+            kind: step_kind(step),
+            description: String::new(),
+            source: None,
+            target: None,
+            file_count: 0,
+            bytes: 0,
+            will_change: true,
+            notes: Vec::new(),
+            error: None,
+        };
+        if let Err(err) = plan_step(step, target_base, payload_source, advanced_mode, &mut planned) {
+            planned.error = Some(format!("{:#}", err));
+        }
+        plan.backup_files.extend(step_backups(step, &ctx).0.iter().filter(|p| p.exists()).map(|p| p.to_string_lossy().to_string()));
+        plan.total_bytes += planned.bytes;
+        plan.steps.push(planned);
+    }
+    plan.backup_files.sort();
+    plan.backup_files.dedup();
+    Ok(plan)
+}
+
+fn plan_step(step: &InstallStep, target_base: &Path, payload_source: &Path, advanced_mode: bool, planned: &mut PlannedStep) -> Result<()> {
+    let display = |p: &Path| p.to_string_lossy().to_string();
+    match step {
+        InstallStep::Copy { src, dest, exclude, overwrite, .. } => {
+            let d = resolve_path(target_base, dest);
+            planned.description = format!("Copy {} to {}", src, d.display());
+            planned.source = Some(display(&payload_source.join(src)));
+            planned.target = Some(display(&d));
+            let filtered = apply_overwrite_policy(plan_copy_step(src, &d, exclude.as_deref().unwrap_or_default(), payload_source)?, *overwrite)?;
+            planned.file_count = filtered.plan.files.len() + filtered.conflicts.len();
+            planned.bytes = filtered.plan.files.iter().chain(&filtered.conflicts).map(|job| job.size).sum();
+            if !filtered.skipped.is_empty() {
+                planned.notes.push(format!("{} existing file(s) kept", filtered.skipped.len()));
+            }
+            if !filtered.conflicts.is_empty() {
+                planned.notes.push(format!("{} existing file(s) need confirmation", filtered.conflicts.len()));
+            }
+            planned.will_change = planned.file_count > 0;
+        }
+        InstallStep::PatchBlock { file, .. } => {
+            let target_path = resolve_path(target_base, file);
+            planned.description = format!("Patch {}", target_path.display());
+            planned.target = Some(display(&target_path));
+            let specs = patch_specs(step, payload_source, advanced_mode)?;
+            planned.file_count = 1;
+            let content = read_target(&target_path).with_context(|| format!("Failed to read {}", target_path.display()))?;
+            let (_, outcomes) = apply_patches(&content, &specs)?;
+            let applied = outcomes.iter().filter(|o| **o == PatchOutcome::AlreadyApplied).count();
+            let inserted = outcomes.iter().filter(|o| **o == PatchOutcome::Inserted).count();
+            if applied > 0 {
+                planned.notes.push(format!("{} of {} block(s) already applied", applied, outcomes.len()));
+            }
+            if inserted > 0 {
+                planned.notes.push(format!("{} block(s) inserted because their markers are missing", inserted));
+            }
+            planned.will_change = applied < outcomes.len();
+        }
+        InstallStep::SetJsonValue { file, key_path, value } => {
+            let target_path = resolve_path(target_base, file);
+            planned.description = format!("Set {} = {} in {}", key_path, value, target_path.display());
+            planned.target = Some(display(&target_path));
+            planned.file_count = 1;
+        }
+        InstallStep::RunCommand { command, args, cwd, .. } => {
+            // Commands run as written; only paths get environment variables expanded
+            planned.description = format!("Run {}", command_line(command, args));
+            planned.target = cwd.as_deref().map(|dir| display(&resolve_path(target_base, dir)));
+        }
+        InstallStep::Base64Embed { file, placeholder, input_file, .. } => {
+            let target_path = resolve_path(target_base, file);
+            let input_path = payload_source.join(normalize_rel_path(input_file, false)?);
+            planned.description = format!("Embed {} into {} at {}", input_file, target_path.display(), placeholder);
+            planned.source = Some(display(&input_path));
+            planned.target = Some(display(&target_path));
+            planned.file_count = 1;
+            planned.bytes = fs::metadata(&input_path).with_context(|| format!("Failed to read {}", input_path.display()))?.len();
+        }
+        InstallStep::InstallVsix { vsix_file, cli_path } => {
+            let vsix = payload_source.join(normalize_rel_path(vsix_file, false)?);
+            let cli = match cli_path {
+                Some(cli) => resolve_path(target_base, cli),
+                None => default_vscode_cli()?,
+            };
+            planned.description = format!("Install extension {} with {}", vsix_file, cli.display());
+            planned.source = Some(display(&vsix));
+            planned.target = Some(display(&cli));
+            planned.file_count = 1;
+            planned.bytes = fs::metadata(&vsix).with_context(|| format!("Failed to read {}", vsix.display()))?.len();
+        }
+        InstallStep::FixVsCodeChecksums { app_dir } => {
+            let app_dir = resolve_path(target_base, app_dir);
+            planned.description = format!("Fix VS Code checksums in {}", app_dir.display());
+            let product = vscode_product_json(&app_dir).ok_or_else(|| anyhow!("No VS Code product.json found in {}", app_dir.display()))?;
+            planned.target = Some(display(&product));
+            planned.file_count = 1;
+            // Patches earlier in the plan haven't run yet, so an up-to-date file may still change
+            let stale = stale_vscode_checksums(&product)?;
+            if !stale.is_empty() {
+                planned.notes.push(format!("{} checksum(s) out of date", stale.len()));
+            }
+        }
+        InstallStep::Delete { path } => {
+            let target_path = resolve_path(target_base, path);
+            planned.description = format!("Delete {}", target_path.display());
+            planned.target = Some(display(&target_path));
+            planned.will_change = fs::symlink_metadata(&target_path).is_ok();
+            if !planned.will_change {
+                planned.notes.push("already gone".to_string());
+            }
+        }
+        InstallStep::Verify { file, .. } => {
+            let target_path = resolve_path(target_base, file);
+            planned.description = format!("Verify {}", target_path.display());
+            planned.target = Some(display(&target_path));
+            planned.will_change = false;
+        }
+    }
+    Ok(())
+}
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum RepairStatus {
+    /// The step's outcome is still in place.
+    Intact,
+    Repaired,
+    /// Nothing to check (`runCommand`, `installVsix`).
+    Skipped,
+    Failed,
+}
+
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct RepairedStep {
+    pub index: usize,
+    pub kind: String,
+    pub status: RepairStatus,
+    /// What was found broken, for `repaired` and `failed` steps.
+    pub problem: Option<String>,
+    pub error: Option<String>,
+}
+
+#[derive(Serialize, Debug, Clone, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct RepairReport {
+    pub steps: Vec<RepairedStep>,
+    /// Backup taken before re-applying anything; `None` when nothing needed it.
+    pub backup_dir: Option<PathBuf>,
+}
+
+impl RepairReport {
+    pub fn count(&self, status: RepairStatus) -> usize {
+        self.steps.iter().filter(|s| s.status == status).count()
+    }
+}
+
+/// Whether a step's outcome is still in place: `Ok(None)` when it is, otherwise what is wrong.
+/// `runCommand` and `installVsix` always pass since their effect can't be observed.
+pub fn check_step(step: &InstallStep, ctx: &StepContext, receipt: &InstallReceipt) -> Result<Option<String>> {
+    let step = &ctx.prompts.fill(step);
+    let target_base = &ctx.target_base;
+    match step {
+        InstallStep::Copy { src, dest, exclude, overwrite, replacements } => {
+            let plan = plan_copy_step(src, &resolve_path(target_base, dest), exclude.as_deref().unwrap_or_default(), &ctx.payload_source)?;
+            let mut missing = 0;
+            let mut changed = 0;
+            for job in &plan.files {
+                if fs::symlink_metadata(&job.dest).is_err() {
+                    missing += 1;
+                } else if job.link.is_none() && *overwrite == OverwritePolicy::Always && !copy_matches(job, replacements.as_ref())? {
+                    // Other policies may have kept the user's file on purpose
+                    changed += 1;
+                }
+            }
+            Ok(match (missing, changed) {
+                (0, 0) => None,
+                _ => Some(format!("{} file(s) missing, {} file(s) changed", missing, changed)),
+            })
+        }
+        InstallStep::PatchBlock { file, .. } => {
+            let target_path = resolve_path(target_base, file);
+            let specs = with_stripped_content(patch_specs(step, &ctx.payload_source, ctx.advanced_mode)?, &target_path, receipt);
+            let content = read_target(&target_path).with_context(|| format!("Failed to read {}", target_path.display()))?;
+            let (_, outcomes) = apply_patches(&content, &specs)?;
+            let pending = outcomes.iter().filter(|o| **o != PatchOutcome::AlreadyApplied).count();
+            Ok((pending > 0).then(|| format!("{} of {} block(s) not applied in {}", pending, outcomes.len(), target_path.display())))
+        }
+        InstallStep::SetJsonValue { file, key_path, value } => {
+            let checks = VerifyChecks { json_path: Some(key_path.clone()), equals: Some(value.clone()), ..Default::default() };
+            Ok(verify_file(&resolve_path(target_base, file), &checks).err().map(|e| e.to_string()))
+        }
+        InstallStep::Base64Embed { file, placeholder, .. } => {
+            let target_path = resolve_path(target_base, file);
+            let content = read_target(&target_path).with_context(|| format!("Failed to read {}", target_path.display()))?;
+            Ok(find_bytes(&content, placeholder.as_bytes())
+                .map(|_| format!("{} still contains {}", target_path.display(), placeholder)))
+        }
+        InstallStep::Verify { file, contains, sha256, json_path, equals } => {
+            let checks = VerifyChecks {
+                contains: contains.clone(),
+                sha256: sha256.clone(),
+                json_path: json_path.clone(),
+                equals: equals.clone(),
+            };
+            Ok(verify_file(&resolve_path(target_base, file), &checks).err().map(|e| e.to_string()))
+        }
+        InstallStep::Delete { path } => {
+            let target_path = resolve_path(target_base, path);
+            Ok(fs::symlink_metadata(&target_path).ok().map(|_| format!("{} exists again", target_path.display())))
+        }
+        InstallStep::FixVsCodeChecksums { app_dir } => {
+            let app_dir = resolve_path(target_base, app_dir);
+            let product = vscode_product_json(&app_dir).ok_or_else(|| anyhow!("No VS Code product.json found in {}", app_dir.display()))?;
+            let stale = stale_vscode_checksums(&product)?;
+            Ok((!stale.is_empty()).then(|| format!("{} VS Code checksum(s) out of date in {}", stale.len(), product.display())))
+        }
+        InstallStep::RunCommand { .. } | InstallStep::InstallVsix { .. } => Ok(None),
+    }
+}
+
+fn same_contents(a: &Path, b: &Path) -> Result<bool> {
+    if fs::metadata(a)?.len() != fs::metadata(b)?.len() {
+        return Ok(false);
+    }
+    Ok(sha256_hex(&fs::read(a)?) == sha256_hex(&fs::read(b)?))
+}
+
+/// Whether `job.dest` holds what copying `job.src` writes, `replacements` included.
+fn copy_matches(job: &CopyJob, replacements: Option<&HashMap<String, String>>) -> Result<bool> {
+    let Some(replacements) = replacements else { return same_contents(&job.src, &job.dest) };
+    let src = fs::read(&job.src)?;
+    let expected = match std::str::from_utf8(&src) {
+        Ok(text) => apply_replacements(text, replacements).into_bytes(),
+        Err(_) => src,
+    };
+    Ok(fs::read(&job.dest)? == expected)
+}
+
+/// Checks every step of `manifest` and re-applies only the ones whose outcome is gone, e.g.
+/// after an update of the target app replaced a patched file. Files the broken steps touch
+/// are backed up first. Steps are repaired one by one: a failure is reported and the rest
+/// still run. `verify` steps are re-checked in order after the repairs before them.
+pub fn repair_install(manifest: &InstallManifest, options: &InstallOptions, observer: &dyn InstallObserver) -> Result<RepairReport> {
+    let manifest = &manifest.for_platform(Platform::current());
+    let ctx = StepContext {
+        target_base: options.target_base.clone(),
+        payload_source: options.payload_source.clone(),
+        advanced_mode: manifest.advanced_mode.unwrap_or(false),
+        replace_locked_on_reboot: manifest.replace_locked_on_reboot.unwrap_or(false),
+        prompts: PromptValues::resolve(manifest, &options.prompt_values)?,
+    };
+    let backup_root = options.backups_root.join(backup_namespace(&manifest.app_name));
+    let _lock = lock_operation(&backup_root, "repair")?;
+    let _target_lock = lock_target(&options.target_base, "repair")?;
+    let mut receipt = load_receipt(&backup_root)?.unwrap_or_default();
+    // The components of the last install, unless others were asked for
+    let installed = Some(receipt.components.clone()).filter(|c| !c.is_empty());
+    let components = resolve_components(manifest, options.components.clone().or(installed).as_deref())?;
+    let steps: Vec<(usize, &ManifestStep)> = manifest.ordered_steps(options.phase).into_iter().filter(|(_, s)| s.in_components(&components)).collect();
+
+    let mut report = RepairReport::default();
+    let mut backup_paths = Vec::new();
+    for (index, step) in &steps {
+        let status = match step.step {
+            InstallStep::RunCommand { .. } | InstallStep::InstallVsix { .. } => RepairStatus::Skipped,
+            _ => RepairStatus::Intact,
+        };
+        let problem = check_step(step, &ctx, &receipt).unwrap_or_else(|e| Some(format!("{:#}", e)));
+        if let (
+            Some(_),
+            InstallStep::PatchBlock { file, .. }
+            | InstallStep::SetJsonValue { file, .. }
+            | InstallStep::Base64Embed { file, .. }
+            | InstallStep::Delete { path: file },
+        ) = (&problem, &step.step)
+        {
+            let path = containing_file(&resolve_path(&ctx.target_base, file));
+            if path.exists() {
+                backup_paths.push(path.to_string_lossy().to_string());
+            }
+        }
+        if let (Some(_), InstallStep::FixVsCodeChecksums { app_dir }) = (&problem, &step.step) {
+            backup_paths.extend(vscode_product_json(&resolve_path(&ctx.target_base, app_dir)).map(|p| p.to_string_lossy().to_string()));
+        }
+        report.steps.push(RepairedStep { index: *index, kind: step_kind(step), status, problem, error: None });
+    }
+
+    backup_paths.sort();
+    backup_paths.dedup();
+    if !backup_paths.is_empty() {
+        let backup_loc = backup_files(&backup_paths, &backup_root)?;
+        observer.log(&format!("Backup created at {:?}", backup_loc));
+        report.backup_dir = Some(backup_loc);
+    }
+
+    let snapshot = FileSnapshot::take(&steps, &ctx);
+    let mut changed = false;
+    for ((_, step), result) in steps.iter().zip(report.steps.iter_mut()) {
+        if let InstallStep::Verify { .. } = step.step {
+            // Judge verify steps on the repaired files
+            result.problem = check_step(step, &ctx, &receipt).unwrap_or_else(|e| Some(format!("{:#}", e)));
+            if let Some(problem) = &result.problem {
+                observer.log(&format!("Step {} still fails: {}", result.index + 1, problem));
+                result.status = RepairStatus::Failed;
+            }
+            continue;
+        }
+        let Some(problem) = &result.problem else { continue };
+        observer.log(&format!("Step {} needs repair: {}", result.index + 1, problem));
+        match execute_step(step, &ctx, &mut receipt, observer) {
+            Ok(()) => {
+                result.status = RepairStatus::Repaired;
+                changed = true;
+            }
+            Err(err) => {
+                observer.log(&format!("Step {} could not be repaired: {:#}", result.index + 1, err));
+                result.status = RepairStatus::Failed;
+                result.error = Some(format!("{:#}", err));
+            }
+        }
+    }
+    if changed {
+        if manifest.watch_installed_files.unwrap_or(false) {
+            receipt.watched_files.extend(snapshot.current());
+        }
+        save_receipt(&receipt, &backup_root)?;
+    }
+    observer.log(&format!(
+        "Repair finished: {} repaired, {} failed",
+        report.count(RepairStatus::Repaired),
+        report.count(RepairStatus::Failed)
+    ));
+    Ok(report)
+}
+
+/// Plans a Copy step; `src` is a payload-relative path or glob.
+pub fn plan_copy_step(src: &str, dest: &Path, exclude: &[String], payload_source: &Path) -> Result<CopyPlan> {
+    if has_glob_meta(src) {
+        let (base, pattern) = split_glob(src);
+        let base = payload_source.join(normalize_rel_path(&base.to_string_lossy(), true)?);
+        plan_copy_glob(&base, &pattern.unwrap_or_default(), dest, exclude)
+    } else {
+        let s = payload_source.join(normalize_rel_path(src, false)?);
+        plan_copy(&s, dest, exclude)
+    }
+}
+
+/// Expands a PatchBlock step (single markers and/or `blocks`) into engine patch specs.
+/// Fills each spec's [`PatchSpec::stripped_content`] from the receipt's record of the same block.
+fn with_stripped_content(mut specs: Vec<PatchSpec>, target_path: &Path, receipt: &InstallReceipt) -> Vec<PatchSpec> {
+    let file = target_path.to_string_lossy();
+    for spec in &mut specs {
+        spec.stripped_content = receipt
+            .patches
+            .iter()
+            .find(|p| p.markers_stripped && p.file == file && p.start_marker == spec.start_marker && p.end_marker == spec.end_marker)
+            .map(|p| p.applied.clone());
+    }
+    specs
+}
+
+pub fn patch_specs(step: &InstallStep, payload_source: &Path, strip_markers: bool) -> Result<Vec<PatchSpec>> {
+    let InstallStep::PatchBlock {
+        start_marker,
+        end_marker,
+        content_file,
+        content,
+        replacements,
+        blocks,
+        patch_id,
+        marker_mode,
+        start_offset,
+        end_offset,
+        similarity,
+        insert_if_missing,
+        ..
+    } = step
+    else {
+        return Err(anyhow!("Step is not a PatchBlock"));
+    };
+
+    let matching = MarkerMatch {
+        mode: *marker_mode,
+        start_offset: *start_offset,
+        end_offset: *end_offset,
+        similarity: *similarity,
+    };
+    let mut specs = Vec::new();
+    if !start_marker.is_empty() || !end_marker.is_empty() {
+        specs.push(PatchSpec {
+            start_marker: start_marker.clone(),
+            end_marker: end_marker.clone(),
+            content: patch_content(payload_source, content.as_deref(), content_file.as_deref(), replacements.as_ref())?,
+            strip_markers,
+            patch_id: patch_id.clone(),
+            matching: matching.clone(),
+            insert_if_missing: insert_if_missing.clone(),
+            stripped_content: None,
+        });
+    }
+    for block in blocks.iter().flatten() {
+        specs.push(PatchSpec {
+            start_marker: block.start_marker.clone(),
+            end_marker: block.end_marker.clone(),
+            content: patch_content(payload_source, block.content.as_deref(), Some(&block.content_file), replacements.as_ref())?,
+            strip_markers,
+            patch_id: block.patch_id.clone(),
+            matching: matching.clone(),
+            insert_if_missing: insert_if_missing.clone(),
+            stripped_content: None,
+        });
+    }
+    if specs.is_empty() {
+        return Err(anyhow!("PatchBlock requires startMarker/endMarker or blocks"));
+    }
+    Ok(specs)
+}
+
+/// The text a block writes: its inline `content` or its `contentFile` from the payload, with
+/// `replacements` applied.
+fn patch_content(payload_source: &Path, content: Option<&str>, content_file: Option<&str>, replacements: Option<&HashMap<String, String>>) -> Result<String> {
+    let content = match (content, content_file.filter(|f| !f.is_empty())) {
+        (Some(_), Some(_)) => return Err(anyhow!("PatchBlock takes either content or contentFile, not both")),
+        (Some(content), None) => content.to_string(),
+        (None, Some(content_file)) => {
+            let content_path = payload_source.join(normalize_rel_path(content_file, false)?);
+            fs::read_to_string(&content_path).map_err(|e| anyhow!("Failed to read patch content {}: {}", content_path.display(), e))?
+        }
+        (None, None) => return Err(anyhow!("PatchBlock requires contentFile or content")),
+    };
+    Ok(match replacements {
+        Some(replacements) => apply_replacements(&content, replacements),
+        None => content,
+    })
+}
+
+/// `text` with every key of `replacements` replaced by its value.
+pub(crate) fn apply_replacements(text: &str, replacements: &HashMap<String, String>) -> String {
+    replacements.iter().fold(text.to_string(), |text, (token, value)| text.replace(token, value))
+}
+
+/// The bytes a `base64Embed` step encodes: the input file, with `replacements` applied to it
+/// as text when given.
+pub(crate) fn embed_input(input_file: &Path, replacements: Option<&HashMap<String, String>>) -> Result<Vec<u8>> {
+    let bytes = fs::read(input_file).context("Failed to read input file for embedding")?;
+    let Some(replacements) = replacements else { return Ok(bytes) };
+    let text = String::from_utf8(bytes).map_err(|_| anyhow!("{} is not text, so its replacements can't be applied", input_file.display()))?;
+    Ok(apply_replacements(&text, replacements).into_bytes())
+}
+
+/// Applies `replacements` in place to the text files `plan` copied and returns how many
+/// changed. Binary files and symlinks are left alone.
+fn replace_in_copied_files(plan: &CopyPlan, replacements: &HashMap<String, String>) -> Result<usize> {
+    let mut replaced = 0;
+    for job in plan.files.iter().filter(|job| job.link.is_none()) {
+        let bytes = fs::read(&job.dest).map_err(|e| MisfitError::from(e).with_path(&job.dest))?;
+        let Ok(text) = std::str::from_utf8(&bytes) else { continue };
+        let new_text = apply_replacements(text, replacements);
+        if new_text != text {
+            write_atomic(&job.dest, new_text.as_bytes()).map_err(|e| MisfitError::from(e).with_path(&job.dest))?;
+            replaced += 1;
+        }
+    }
+    Ok(replaced)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::{Quiet, install_options, step_context, temp_file, test_manifest};
+
+    #[test]
+    fn preflight_reports_sizes_and_writable_targets() {
+        let existing = temp_file("preflight/settings.json", "{}");
+        let missing = existing.with_file_name("new/payload.bin");
+        let targets = vec![
+            PreflightTarget { path: existing.clone(), bytes: 0 },
+            PreflightTarget { path: missing, bytes: 1024 },
+        ];
+        let report = preflight_check(&targets, &[PreflightTarget { path: existing.clone(), bytes: 2 }]);
+        assert_eq!(report.total_bytes, 1024);
+        assert_eq!(report.volumes.len(), 1);
+        assert_eq!(report.volumes[0].required_bytes, 1026);
+        assert_eq!(report.files.len(), 2);
+        assert!(report.files.iter().all(|f| f.writable && !f.locked));
+        assert!(report.files[0].exists && !report.files[1].exists);
+    }
+
+    #[test]
+    fn install_plan_resolves_paths_sizes_and_noops() {
+        let payload = temp_file("plan/payload/app/a.txt", "12345").parent().unwrap().parent().unwrap().to_path_buf();
+        temp_file("plan/payload/patch.txt", "x");
+        let target_base = temp_file("plan/target/theme.css", "a/*S*/x/*E*/b").parent().unwrap().to_path_buf();
+        temp_file("plan/target/out/a.txt", "old");
+        let manifest = test_manifest("Plan", serde_json::json!({
+            "installSteps": [
+                { "type": "copy", "src": "app", "dest": "out" },
+                { "type": "patchBlock", "file": "theme.css", "startMarker": "/*S*/", "endMarker": "/*E*/",
+                  "contentFile": "patch.txt" },
+                { "type": "setJsonValue", "file": "missing.json", "keyPath": "a", "value": 1 },
+                { "type": "base64Embed", "file": "theme.css", "placeholder": "LOGO", "inputFile": "nope.png" }
+            ]
+        }));
+
+        let plan = resolve_install_plan(&manifest, &target_base, &payload, None).unwrap();
+        assert_eq!(plan.steps.len(), 4);
+        assert_eq!(plan.steps[0].kind, "copy");
+        assert_eq!((plan.steps[0].file_count, plan.steps[0].bytes), (1, 5));
+        assert_eq!(plan.steps[0].target.as_deref(), Some(target_base.join("out").to_string_lossy().as_ref()));
+        assert!(!plan.steps[1].will_change && plan.steps[1].error.is_none());
+        assert!(plan.steps[3].error.is_some());
+        assert_eq!(plan.total_bytes, 5);
+        // The existing files the install backs up: the one the copy overwrites and the patched one
+        let backups = [target_base.join("out").join("a.txt"), target_base.join("theme.css")].map(|p| p.to_string_lossy().to_string());
+        assert_eq!(plan.backup_files, backups);
+    }
+
+    #[test]
+    fn steps_run_phase_by_phase_in_manifest_order() {
+        let manifest = test_manifest("Phases", serde_json::json!({
+            "installSteps": [
+                { "type": "runCommand", "command": "a", "args": [], "phase": "finish" },
+                { "type": "runCommand", "command": "b", "args": [] },
+                { "type": "runCommand", "command": "c", "args": [], "phase": "prepare" },
+                { "type": "runCommand", "command": "d", "args": [] }
+            ]
+        }));
+
+        let order: Vec<usize> = manifest.ordered_steps(None).iter().map(|(i, _)| *i).collect();
+        assert_eq!(order, vec![2, 1, 3, 0]);
+        let finish: Vec<usize> = manifest.ordered_steps(Some(Phase::Finish)).iter().map(|(i, _)| *i).collect();
+        assert_eq!(finish, vec![0]);
+
+        let json = serde_json::to_value(&manifest.install_steps[1]).unwrap();
+        assert_eq!(json, serde_json::json!({ "type": "runCommand", "command": "b", "args": [] }));
+    }
+
+    #[test]
+    fn failed_install_resumes_after_completed_steps() {
+        let root = temp_file("resume/payload/app/a.txt", "hello").parent().unwrap().parent().unwrap().parent().unwrap().to_path_buf();
+        let conf = temp_file("resume/conf.json", "{}");
+        let manifest = test_manifest("Resume", serde_json::json!({
+            "installSteps": [
+                { "type": "copy", "src": "app", "dest": "out" },
+                { "type": "setJsonValue", "file": "conf.json", "keyPath": "ready", "value": true },
+                { "type": "verify", "file": "out/a.txt", "contains": "ready" }
+            ]
+        }));
+        let backups_root = root.join("backups");
+        let mut options = InstallOptions { resume: true, ..install_options(&root, root.join("payload"), backups_root.clone()) };
+        assert_eq!(MisfitError::from(run_install(&manifest, &options, &Quiet).unwrap_err()).code, ErrorCode::NotFound);
+
+        options.resume = false;
+        let err = MisfitError::from(run_install(&manifest, &options, &Quiet).unwrap_err());
+        assert_eq!(err.step_index, Some(2));
+        let journal = pending_install(&manifest, &backups_root).unwrap().expect("journal");
+        assert_eq!((journal.completed, journal.failed_step), (vec![], Some(2)));
+        assert_eq!(std::fs::read_to_string(&conf).unwrap(), "{}");
+        assert!(!root.join("out").exists());
+
+        // The user fixes the problem; resuming runs the rolled-back steps again
+        std::fs::write(root.join("payload/app/a.txt"), "ready").unwrap();
+        options.resume = true;
+        run_install(&manifest, &options, &Quiet).unwrap();
+        assert!(std::fs::read_to_string(&conf).unwrap().contains("\"ready\": true"));
+        assert!(pending_install(&manifest, &backups_root).unwrap().is_none());
+    }
+
+    #[test]
+    fn hooks_run_around_the_install_with_their_own_error_policy() {
+        let conf = temp_file("hooks/conf.json", "{}");
+        let root = conf.parent().unwrap().to_path_buf();
+        let mut manifest = test_manifest("Hooks", serde_json::json!({
+            "preInstall": [{ "type": "verify", "file": "service.stopped" }],
+            "installSteps": [{ "type": "setJsonValue", "file": "conf.json", "keyPath": "ready", "value": true }],
+            "postInstall": [
+                { "type": "verify", "file": "missing.txt" },
+                { "type": "setJsonValue", "file": "post.json", "keyPath": "launched", "value": true }
+            ]
+        }));
+        std::fs::write(root.join("post.json"), "{}").unwrap();
+        let options = install_options(&root, root.join("payload"), root.join("backups"));
+
+        let err = MisfitError::from(run_install(&manifest, &options, &Quiet).unwrap_err());
+        assert!(err.message.starts_with("Pre-install hook 1 failed"), "{}", err.message);
+        assert_eq!(err.step_index, None);
+        assert!(!root.join("backups").exists());
+        assert_eq!(std::fs::read_to_string(&conf).unwrap(), "{}");
+
+        manifest.pre_install[0].on_error = Some(HookFailure::Continue);
+        run_install(&manifest, &options, &Quiet).unwrap();
+        assert!(std::fs::read_to_string(&conf).unwrap().contains("\"ready\": true"));
+        assert!(std::fs::read_to_string(root.join("post.json")).unwrap().contains("\"launched\": true"));
+
+        manifest.post_install[0].on_error = Some(HookFailure::Abort);
+        let err = MisfitError::from(run_install(&manifest, &options, &Quiet).unwrap_err());
+        assert!(err.message.starts_with("Post-install hook 1 failed"), "{}", err.message);
+        assert!(load_receipt(&root.join("backups").join(backup_namespace("Hooks"))).unwrap().is_some());
+    }
+
+    #[test]
+    fn repair_reapplies_only_broken_steps() {
+        let root = temp_file("repair/payload/app/a.txt", "hello").parent().unwrap().parent().unwrap().parent().unwrap().to_path_buf();
+        temp_file("repair/payload/patch.txt", "new");
+        let theme = temp_file("repair/theme.css", "a/*S*/old/*E*/b");
+        temp_file("repair/conf.json", "{}");
+        let manifest = test_manifest("Repair", serde_json::json!({
+            "installSteps": [
+                { "type": "copy", "src": "app", "dest": "out" },
+                { "type": "patchBlock", "file": "theme.css", "startMarker": "/*S*/", "endMarker": "/*E*/",
+                  "contentFile": "patch.txt" },
+                { "type": "setJsonValue", "file": "conf.json", "keyPath": "ready", "value": true },
+                { "type": "runCommand", "command": "true", "args": [] },
+                { "type": "verify", "file": "theme.css", "contains": "new" }
+            ]
+        }));
+        let options = install_options(&root, root.join("payload"), root.join("backups"));
+        let statuses = |report: &RepairReport| report.steps.iter().map(|s| s.status).collect::<Vec<_>>();
+        let ctx = step_context(&root, &root.join("payload"));
+        assert!(check_step(&manifest.install_steps[0], &ctx, &InstallReceipt::default()).unwrap().is_some());
+
+        // Nothing installed yet, so repairing does the whole install
+        let report = repair_install(&manifest, &options, &Quiet).unwrap();
+        assert_eq!(report.count(RepairStatus::Repaired), 3);
+
+        // An app update put back the original theme and removed the copied file
+        std::fs::write(&theme, "a/*S*/old/*E*/b").unwrap();
+        std::fs::remove_file(root.join("out/a.txt")).unwrap();
+
+        let report = repair_install(&manifest, &options, &Quiet).unwrap();
+        use RepairStatus::*;
+        assert_eq!(statuses(&report), vec![Repaired, Repaired, Intact, Skipped, Intact]);
+        assert!(report.backup_dir.is_some());
+        assert_eq!(std::fs::read_to_string(&theme).unwrap(), "a/*S*/new/*E*/b");
+        assert_eq!(std::fs::read_to_string(root.join("out/a.txt")).unwrap(), "hello");
+
+        let report = repair_install(&manifest, &options, &Quiet).unwrap();
+        assert_eq!(statuses(&report), vec![Intact, Intact, Intact, Skipped, Intact]);
+        assert!(report.backup_dir.is_none());
+    }
+
+    #[test]
+    fn target_version_limits_refuse_unsupported_installs() {
+        assert_eq!(compare_versions("1.90.2", "1.90", true), std::cmp::Ordering::Equal);
+        assert_eq!(compare_versions("1.90.2", "1.90", false), std::cmp::Ordering::Greater);
+        assert_eq!(compare_versions("v1.9.0-insider", "1.10", false), std::cmp::Ordering::Less);
+
+        let package = temp_file("target-version/resources/app/package.json", r#"{ "name": "code", "version": "1.95.1" }"#);
+        let base = package.ancestors().nth(3).unwrap().to_path_buf();
+        let mut manifest: InstallManifest = serde_json::from_value(serde_json::json!({
+            "appName": "Versioned", "version": "1.0.0", "publisher": "", "description": "",
+            "targets": [], "payloadDir": "payload", "installSteps": [],
+            "minTargetVersion": "1.85", "maxTargetVersion": "1.90",
+            "targetVersionFrom": { "file": "resources/app/package.json", "jsonPath": "version" }
+        }))
+        .unwrap();
+        let check = check_target_version(&manifest, &base).expect("check");
+        assert_eq!(check.found.as_deref(), Some("1.95.1"));
+        assert!(!check.compatible);
+        assert!(check.message.unwrap().contains("up to version 1.90"));
+
+        manifest.max_target_version = Some("1.95".to_string());
+        assert!(check_target_version(&manifest, &base).unwrap().compatible);
+        manifest.target_version_from = None;
+        assert!(lint_manifest(&manifest, None).iter().any(|i| i.code == "version-without-source"));
+    }
+
+    #[test]
+    fn expand_env_vars_resolves_known_folder_tokens() {
+        let temp = std::env::temp_dir().to_string_lossy().to_string();
+        assert_eq!(expand_env_vars("{temp}/misfit"), format!("{}/misfit", temp));
+        assert_eq!(expand_env_vars("{TEMP}"), temp);
+        if let Some(config) = dirs::config_dir() {
+            assert_eq!(expand_env_vars("{appData}/Code/User"), format!("{}/Code/User", config.to_string_lossy()));
+        }
+        assert_eq!(expand_env_vars("{unknown}/{ not a token"), "{unknown}/{ not a token");
+    }
+
+    #[test]
+    fn copy_and_embed_apply_replacements() {
+        let config = temp_file("copy_replace/payload/config/app.ini", "user=@USER@\nversion=@VERSION@\n");
+        let root = config.parent().unwrap().parent().unwrap().parent().unwrap().to_path_buf();
+        std::fs::write(root.join("payload/config/logo.bin"), [0xff, 0xfe, b'@', b'U']).unwrap();
+        std::fs::write(root.join("payload/icon.svg"), "<svg fill=\"@COLOR@\"/>").unwrap();
+        std::fs::write(root.join("page.html"), "<img src=\"__ICON__\">").unwrap();
+        let manifest = test_manifest("CopyReplace", serde_json::json!({
+            "version": "2.1",
+            "installSteps": [
+                { "type": "copy", "src": "config", "dest": "out", "replacements": { "@USER@": "ada", "@VERSION@": "2.1" } },
+                { "type": "base64Embed", "file": "page.html", "placeholder": "__ICON__", "inputFile": "icon.svg", "replacements": { "@COLOR@": "red" } }
+            ]
+        }));
+        let options = install_options(&root, root.join("payload"), root.join("backups"));
+        run_install(&manifest, &options, &Quiet).expect("install");
+
+        assert_eq!(std::fs::read_to_string(root.join("out/app.ini")).unwrap(), "user=ada\nversion=2.1\n");
+        assert_eq!(std::fs::read(root.join("out/logo.bin")).unwrap(), [0xff, 0xfe, b'@', b'U']);
+        use base64::Engine as _;
+        let icon = base64::engine::general_purpose::STANDARD.encode("<svg fill=\"red\"/>");
+        assert_eq!(std::fs::read_to_string(root.join("page.html")).unwrap(), format!("<img src=\"{}\">", icon));
+        let report = repair_install(&manifest, &options, &Quiet).expect("repair");
+        assert_eq!(report.count(RepairStatus::Intact), 2, "{:?}", report);
+    }
+
+    #[test]
+    fn licensed_manifests_install_only_after_acceptance() {
+        let license = temp_file("license/payload/EULA.txt", "Do not redistribute.\n");
+        let payload = license.parent().unwrap().to_path_buf();
+        let root = payload.parent().unwrap().to_path_buf();
+        std::fs::write(root.join("settings.json"), "{}").unwrap();
+        let manifest = test_manifest("Licensed", serde_json::json!({
+            "licenseFile": "EULA.txt",
+            "installSteps": [{ "type": "setJsonValue", "file": "settings.json", "keyPath": "theme", "value": "Dark" }]
+        }));
+        assert_eq!(license_text(&manifest, &payload).unwrap().as_deref(), Some("Do not redistribute.\n"));
+        assert!(lint_manifest(&manifest, Some(&["EULA.txt".to_string()])).is_empty());
+
+        let mut options = install_options(&root, payload.clone(), root.join("backups"));
+        let err = run_install(&manifest, &options, &Quiet).unwrap_err();
+        assert_eq!(MisfitError::from(err).code, ErrorCode::LicenseNotAccepted);
+        assert_eq!(std::fs::read_to_string(root.join("settings.json")).unwrap(), "{}");
+
+        options.accepted_license = true;
+        run_install(&manifest, &options, &Quiet).expect("install");
+        assert!(std::fs::read_to_string(root.join("settings.json")).unwrap().contains("Dark"));
+
+        // Without the file there is nothing to accept
+        std::fs::remove_file(&license).unwrap();
+        assert_eq!(license_text(&manifest, &payload).unwrap(), None);
+        options.accepted_license = false;
+        run_install(&manifest, &options, &Quiet).expect("install without license");
+    }
+
+    #[test]
+    fn reinstalls_keep_the_text_from_before_the_first_install() {
+        let theme = temp_file("reinstall/theme.css", "a/*S*/pristine/*E*/b");
+        let root = theme.parent().unwrap().to_path_buf();
+        let patch = temp_file("reinstall/payload/patch.txt", "v1");
+        let manifest = test_manifest("Reinstall", serde_json::json!({
+            "installSteps": [{ "type": "patchBlock", "file": "theme.css", "startMarker": "/*S*/", "endMarker": "/*E*/",
+                               "contentFile": "patch.txt" }]
+        }));
+        let options = install_options(&root, root.join("payload"), root.join("backups"));
+        run_install(&manifest, &options, &Quiet).expect("install v1");
+        std::fs::write(&patch, "v2").unwrap();
+        run_install(&manifest, &options, &Quiet).expect("install v2");
+        assert_eq!(std::fs::read_to_string(&theme).unwrap(), "a/*S*/v2/*E*/b");
+
+        let app_root = root.join("backups").join(backup_namespace("Reinstall"));
+        assert_eq!(load_receipt(&app_root).unwrap().unwrap().patches.len(), 1);
+        revert_all_patches(&app_root, &Quiet).expect("revert");
+        assert_eq!(std::fs::read_to_string(&theme).unwrap(), "a/*S*/pristine/*E*/b");
+    }
+
+    #[test]
+    fn deletes_never_take_the_target_or_a_root() {
+        for path in ["", ".", "./", "/", "\\", "C:", "C:\\", "~", "~/", "..", "../..", " "] {
+            assert!(check_delete_path(path).is_err(), "{:?}", path);
+            let step = serde_json::json!({ "type": "delete", "path": path });
+            assert!(serde_json::from_value::<InstallStep>(step).is_err(), "{:?}", path);
+        }
+        for path in ["cache", "./cache", "~/.cache/app", "C:/Temp/app", "%APPDATA%/App/old.json"] {
+            assert!(check_delete_path(path).is_ok(), "{:?}", path);
+        }
+
+        let kept = temp_file("delete_guard/app/keep.txt", "keep");
+        let root = kept.parent().unwrap().to_path_buf();
+        let ctx = step_context(&root, &root);
+        let delete = |path: &str| InstallStep::Delete { path: path.to_string() };
+        let mut receipt = InstallReceipt::default();
+        for path in ["..", "../app/.."] {
+            assert!(execute_step(&delete(path), &ctx, &mut receipt, &Quiet).is_err(), "{:?}", path);
+        }
+        assert!(execute_step(&delete(&root.to_string_lossy()), &ctx, &mut receipt, &Quiet).is_err());
+        assert!(kept.exists());
+        execute_step(&delete("keep.txt"), &ctx, &mut receipt, &Quiet).expect("delete");
+        assert!(!kept.exists());
+    }
+
+    #[test]
+    fn rollback_removes_copied_files_and_restores_overwritten_ones() {
+        let root = temp_file("copy-rollback/payload/app/old.txt", "new").parent().unwrap().parent().unwrap().parent().unwrap().to_path_buf();
+        std::fs::write(root.join("payload/app/fresh.txt"), "fresh").unwrap();
+        std::fs::create_dir_all(root.join("payload/app/nested/deep")).unwrap();
+        std::fs::write(root.join("payload/app/nested/deep/n.txt"), "n").unwrap();
+        std::fs::create_dir_all(root.join("out")).unwrap();
+        std::fs::write(root.join("out/old.txt"), "old").unwrap();
+        let manifest = test_manifest("CopyRollback", serde_json::json!({
+            "installSteps": [
+                { "type": "copy", "src": "app", "dest": "out" },
+                { "type": "verify", "file": "out/missing.txt" }
+            ]
+        }));
+        let options = install_options(&root, root.join("payload"), root.join("backups"));
+        run_install(&manifest, &options, &Quiet).unwrap_err();
+        assert_eq!(std::fs::read_to_string(root.join("out/old.txt")).unwrap(), "old");
+        assert!(!root.join("out/fresh.txt").exists());
+        assert!(!root.join("out/nested").exists());
+    }
+
+    #[test]
+    fn uninstalling_deletes_staged_reboot_replacements() {
+        let staged = temp_file("pending_reboot/app/tool.exe.misfit-reboot", "new");
+        let app_root = staged.parent().unwrap().parent().unwrap().join("backups");
+        let mut receipt = InstallReceipt::default();
+        let dest = staged.with_file_name("tool.exe").to_string_lossy().to_string();
+        receipt.pending_replacements.insert(dest, staged.to_string_lossy().to_string());
+        save_receipt(&receipt, &app_root).unwrap();
+
+        revert_all_patches(&app_root, &Quiet).expect("revert");
+        assert!(!staged.exists());
+        assert!(load_receipt(&app_root).unwrap().unwrap().pending_replacements.is_empty());
+    }
+}
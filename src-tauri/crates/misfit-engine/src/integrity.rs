@@ -0,0 +1,82 @@
+//! Watching installed files: with `watchInstalledFiles`, the receipt keeps the hash of every
+//! file the install wrote, and [`check_install_integrity`] compares them with the files on
+//! disk to notice an update of the target app putting its own files back.
+
+use crate::*;
+
+#[derive(Serialize, Debug, Clone, Default, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct IntegrityReport {
+    /// Whether the install recorded hashes at all; everything else is empty when it didn't.
+    pub watched: bool,
+    /// When the checked install ran.
+    pub installed_at: Option<String>,
+    pub checked: usize,
+    /// Files whose contents are no longer what the install left.
+    pub modified: Vec<String>,
+    pub missing: Vec<String>,
+}
+
+impl IntegrityReport {
+    pub fn is_intact(&self) -> bool {
+        self.modified.is_empty() && self.missing.is_empty()
+    }
+}
+
+/// Compares the files watched by the install whose receipt is in `app_backup_root` with their
+/// recorded hashes. Reverted files are fixed by [`repair_install`].
+pub fn check_install_integrity(app_backup_root: &Path) -> Result<IntegrityReport> {
+    let Some(receipt) = load_receipt(app_backup_root)? else { return Ok(IntegrityReport::default()) };
+    let mut report = IntegrityReport {
+        watched: !receipt.watched_files.is_empty(),
+        installed_at: Some(receipt.installed_at).filter(|_| !receipt.watched_files.is_empty()),
+        checked: receipt.watched_files.len(),
+        ..Default::default()
+    };
+    for (path, expected) in receipt.watched_files {
+        match file_sha256(Path::new(&path)) {
+            None => report.missing.push(path),
+            Some(actual) if actual != expected => report.modified.push(path),
+            Some(_) => {}
+        }
+    }
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::{Quiet, install_options, temp_file, test_manifest};
+
+    #[test]
+    fn watched_files_report_what_an_update_reverted() {
+        let root = temp_file("watch/payload/app/a.txt", "hello").parent().unwrap().parent().unwrap().parent().unwrap().to_path_buf();
+        temp_file("watch/payload/patch.txt", "new");
+        let theme = temp_file("watch/theme.css", "a/*S*/old/*E*/b");
+        let manifest = test_manifest("Watch", serde_json::json!({
+            "watchInstalledFiles": true,
+            "installSteps": [
+                { "type": "copy", "src": "app", "dest": "out" },
+                { "type": "patchBlock", "file": "theme.css", "startMarker": "/*S*/", "endMarker": "/*E*/",
+                  "contentFile": "patch.txt" }
+            ]
+        }));
+        let options = install_options(&root, root.join("payload"), root.join("backups"));
+        let app_backup_root = root.join("backups").join(backup_namespace(&manifest.app_name));
+        assert!(!check_install_integrity(&app_backup_root).unwrap().watched);
+
+        run_install(&manifest, &options, &Quiet).unwrap();
+        let report = check_install_integrity(&app_backup_root).unwrap();
+        assert_eq!((report.watched, report.checked, report.is_intact()), (true, 2, true));
+
+        // An app update put back the original theme and removed the copied file
+        std::fs::write(&theme, "a/*S*/old/*E*/b").unwrap();
+        std::fs::remove_file(root.join("out/a.txt")).unwrap();
+        let report = check_install_integrity(&app_backup_root).unwrap();
+        assert_eq!(report.modified, vec![theme.to_string_lossy().to_string()]);
+        assert_eq!(report.missing, vec![root.join("out/a.txt").to_string_lossy().to_string()]);
+
+        repair_install(&manifest, &options, &Quiet).unwrap();
+        assert!(check_install_integrity(&app_backup_root).unwrap().is_intact());
+    }
+}
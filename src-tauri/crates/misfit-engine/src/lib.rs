@@ -0,0 +1,4665 @@
+use serde::{Deserialize, Serialize};
+use std::path::{Component, Path, PathBuf};
+use std::fs;
+use anyhow::{Context, Result, anyhow};
+use std::process::Command;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+
+mod asar;
+mod branding;
+mod components;
+mod dedupe;
+mod electron;
+mod error;
+mod exe_info;
+mod file_data;
+mod install;
+mod integrity;
+mod lint;
+mod localize;
+mod migrate;
+mod operation;
+mod profile;
+mod programs;
+mod prompts;
+mod record;
+mod report;
+mod scan;
+mod script;
+mod simulate;
+mod snippet;
+mod system;
+mod vscode;
+pub use asar::*;
+pub use branding::*;
+pub use components::*;
+pub use dedupe::*;
+pub use electron::*;
+pub use error::*;
+pub use exe_info::*;
+pub use file_data::*;
+pub use install::*;
+pub use integrity::*;
+pub use lint::*;
+pub use localize::*;
+pub use migrate::*;
+pub use operation::*;
+pub use profile::*;
+pub use programs::*;
+pub use prompts::*;
+pub use record::*;
+pub use report::*;
+pub use scan::*;
+pub use script::*;
+pub use simulate::*;
+pub use snippet::*;
+pub use system::*;
+pub use vscode::*;
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct InstallManifest {
+    /// Schema version the manifest was written for; see [`migrate_manifest`].
+    #[serde(default = "current_manifest_version")]
+    pub manifest_version: u32,
+    /// A string, or translations keyed by locale; see [`LocalizedText`].
+    pub app_name: LocalizedText,
+    pub version: String,
+    pub publisher: String,
+    pub description: LocalizedText,
+    pub logo_path: Option<String>,
+    pub advanced_mode: Option<bool>,
+    pub targets: Vec<String>,
+    pub payload_dir: String,
+    pub install_steps: Vec<ManifestStep>,
+    /// Applied to this app's backups after each install; no pruning when absent.
+    #[serde(default)]
+    pub backup_retention: Option<RetentionPolicy>,
+    /// Overrides `Documents/MisfitBackups`; environment variables are expanded and relative
+    /// paths resolve from the manifest folder.
+    #[serde(default)]
+    pub backup_dir: Option<String>,
+    /// Run before anything is backed up, e.g. to stop a service that holds the targets open.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub pre_install: Vec<Hook>,
+    /// Run once every install step has succeeded, e.g. to relaunch the app.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub post_install: Vec<Hook>,
+    /// Offered on the installer's finish screen ("Launch <app> now").
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub launch_after_install: Option<LaunchTarget>,
+    /// Oldest version of the target app this installer supports, read through `targetVersionFrom`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub min_target_version: Option<String>,
+    /// Newest supported version; only the parts given are compared, so `1.90` allows every `1.90.x`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_target_version: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub target_version_from: Option<VersionSource>,
+    /// Per-OS replacements for path prefixes used by steps, e.g.
+    /// `{"%APPDATA%/Code/User": {"macos": "~/Library/Application Support/Code/User"}}`.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub target_overrides: BTreeMap<String, BTreeMap<Platform, String>>,
+    /// Windows only: copy targets still locked after retrying are replaced at the next
+    /// restart instead of failing the install. Needs administrator rights.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub replace_locked_on_reboot: Option<bool>,
+    /// Windows only: list the app under Add/Remove Programs for the current user, with an
+    /// uninstall command that opens the installer's uninstaller mode.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub register_uninstall: Option<bool>,
+    /// Record the hashes of the files the install writes, so [`check_install_integrity`] can
+    /// tell when an update of the target app replaced them.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub watch_installed_files: Option<bool>,
+    /// Optional parts of the install the user can choose from; steps opt in with `component`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub components: Vec<ManifestComponent>,
+    /// Values for `{{name}}` placeholders in steps, hooks and paths; profiles can replace them.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub variables: BTreeMap<String, String>,
+    /// Deployment variants chosen at install time, see [`apply_profile`].
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub profiles: BTreeMap<String, ManifestProfile>,
+    /// Values asked for at install time, see [`PromptValues`].
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub prompts: Vec<PromptedVariable>,
+    /// License (EULA) in the payload folder the user must accept before installing, see
+    /// [`license_text`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub license_file: Option<String>,
+    /// Colors, title, welcome text and logo of the installer window.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub branding: Option<Branding>,
+}
+
+impl InstallManifest {
+    /// Steps in the order they run: phase by phase, manifest order within a phase, each with
+    /// its index in `install_steps`. `only` limits the result to one phase. Steps limited to
+    /// other platforms are left out.
+    pub fn ordered_steps(&self, only: Option<Phase>) -> Vec<(usize, &ManifestStep)> {
+        let mut steps: Vec<(usize, &ManifestStep)> = self
+            .install_steps
+            .iter()
+            .enumerate()
+            .filter(|(_, s)| only.map_or(true, |p| s.phase == p) && s.runs_on(Platform::current()))
+            .collect();
+        steps.sort_by_key(|(_, s)| s.phase);
+        steps
+    }
+
+    /// The manifest with `targetOverrides` applied for `platform`: every step, hook, launch and
+    /// version source path that starts with an overridden prefix gets that platform's
+    /// replacement. Steps keep their indices.
+    pub fn for_platform(&self, platform: Platform) -> InstallManifest {
+        let mut manifest = self.clone();
+        if self.target_overrides.is_empty() {
+            return manifest;
+        }
+        let overrides: Vec<(&str, &str)> = self
+            .target_overrides
+            .iter()
+            .filter_map(|(prefix, paths)| paths.get(&platform).map(|path| (prefix.as_str(), path.as_str())))
+            .collect();
+        let apply = |path: &mut String| {
+            let normalized = path.replace('\\', "/");
+            // Longest prefix first, so nested overrides win over their parents
+            let found = overrides
+                .iter()
+                .filter_map(|(prefix, replacement)| {
+                    let prefix = prefix.replace('\\', "/");
+                    let prefix = prefix.trim_end_matches('/');
+                    let rest = normalized.strip_prefix(prefix)?;
+                    (rest.is_empty() || rest.starts_with('/')).then(|| (prefix.len(), format!("{}{}", replacement.trim_end_matches(['/', '\\']), rest)))
+                })
+                .max_by_key(|(len, _)| *len);
+            if let Some((_, replaced)) = found {
+                *path = replaced;
+            }
+        };
+        let steps = manifest.install_steps.iter_mut().map(|s| &mut s.step);
+        let hooks = manifest.pre_install.iter_mut().chain(manifest.post_install.iter_mut()).map(|h| &mut h.step);
+        for step in steps.chain(hooks) {
+            step.target_paths_mut().into_iter().for_each(apply);
+        }
+        if let Some(source) = &mut manifest.target_version_from {
+            apply(&mut source.file);
+        }
+        if let Some(launch) = &mut manifest.launch_after_install {
+            apply(&mut launch.command);
+        }
+        manifest
+    }
+}
+
+/// Operating systems a step can be limited to.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[serde(rename_all = "lowercase")]
+pub enum Platform {
+    Windows,
+    Macos,
+    Linux,
+}
+
+impl Platform {
+    /// The platform this build runs on; other Unix systems count as Linux.
+    pub fn current() -> Platform {
+        if cfg!(windows) {
+            Platform::Windows
+        } else if cfg!(target_os = "macos") {
+            Platform::Macos
+        } else {
+            Platform::Linux
+        }
+    }
+}
+
+/// Named groups of steps, run in this order.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+#[serde(rename_all = "camelCase")]
+pub enum Phase {
+    Prepare,
+    #[default]
+    Install,
+    Configure,
+    Finish,
+}
+
+impl Phase {
+    pub const ALL: [Phase; 4] = [Phase::Prepare, Phase::Install, Phase::Configure, Phase::Finish];
+
+    fn is_default(&self) -> bool {
+        *self == Phase::default()
+    }
+}
+
+/// One entry of `installSteps`: the step plus the phase it belongs to (`install` when omitted).
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ManifestStep {
+    #[serde(default, skip_serializing_if = "Phase::is_default")]
+    pub phase: Phase,
+    /// Platforms the step runs on; every platform when empty.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub platforms: Vec<Platform>,
+    /// Id of the optional component the step belongs to; the step always runs when unset.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub component: Option<String>,
+    #[serde(flatten)]
+    pub step: InstallStep,
+}
+
+/// One entry of `preInstall` or `postInstall`: any install step plus what its failure does.
+/// Hooks are not backed up, rolled back or recorded in the receipt.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct Hook {
+    /// `abort` for `preInstall` hooks and `continue` for `postInstall` ones when omitted.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub on_error: Option<HookFailure>,
+    /// Platforms the hook runs on; every platform when empty.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub platforms: Vec<Platform>,
+    #[serde(flatten)]
+    pub step: InstallStep,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum HookFailure {
+    /// Fail the install. Before the install nothing has been changed yet; after it, the
+    /// installed files stay.
+    Abort,
+    /// Log the failure and run the next hook.
+    Continue,
+}
+
+/// A program to start once the install has finished.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct LaunchTarget {
+    /// Environment variables are expanded and relative paths resolve from the manifest folder;
+    /// a bare name is looked up on `PATH`.
+    pub command: String,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub args: Vec<String>,
+}
+
+/// Where the target app's installed version is read from.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct VersionSource {
+    /// Resolved like a step's `file`, so it may point into an asar archive.
+    pub file: String,
+    /// Dotted key of a JSON string holding the version, e.g. `version` in `package.json`.
+    /// Without it the first version-like text in the file is used.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub json_path: Option<String>,
+}
+
+impl ManifestStep {
+    pub fn runs_on(&self, platform: Platform) -> bool {
+        self.platforms.is_empty() || self.platforms.contains(&platform)
+    }
+}
+
+impl Hook {
+    pub fn runs_on(&self, platform: Platform) -> bool {
+        self.platforms.is_empty() || self.platforms.contains(&platform)
+    }
+}
+
+impl std::ops::Deref for ManifestStep {
+    type Target = InstallStep;
+
+    fn deref(&self) -> &InstallStep {
+        &self.step
+    }
+}
+
+/// Why `path` can't be the target of a `delete` step: it is empty or names the target base,
+/// a parent of it, a filesystem root or the home folder rather than something inside them.
+pub fn check_delete_path(path: &str) -> std::result::Result<(), String> {
+    let parts: Vec<&str> = path.split(['/', '\\']).map(str::trim).filter(|p| !p.is_empty() && *p != ".").collect();
+    let names_nothing = parts.iter().all(|p| *p == "..")
+        || matches!(parts.as_slice(), [only] if *only == "~" || (only.len() == 2 && only.ends_with(':')));
+    if names_nothing {
+        return Err(format!("delete path {:?} would remove the whole target, a drive or the home folder", path));
+    }
+    Ok(())
+}
+
+fn deserialize_delete_path<'de, D: serde::Deserializer<'de>>(deserializer: D) -> std::result::Result<String, D::Error> {
+    let path = String::deserialize(deserializer)?;
+    check_delete_path(&path).map_err(serde::de::Error::custom)?;
+    Ok(path)
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub enum InstallStep {
+    Copy {
+        src: String,
+        dest: String,
+        /// Glob patterns (relative to the copied folder) for files to leave out.
+        #[serde(default)]
+        exclude: Option<Vec<String>>,
+        #[serde(default)]
+        overwrite: OverwritePolicy,
+        /// Tokens replaced in the copied text files (binary files are copied as they are).
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        replacements: Option<HashMap<String, String>>,
+    },
+    PatchBlock { 
+        file: String, 
+        #[serde(rename = "startMarker", default)]
+        start_marker: String, 
+        #[serde(rename = "endMarker", default)]
+        end_marker: String, 
+        #[serde(rename = "contentFile")]
+        content_file: Option<String>, 
+        /// Patch text written in the manifest itself, instead of a `contentFile`.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        content: Option<String>,
+        replacements: Option<std::collections::HashMap<String, String>>,
+        /// Additional marker blocks applied to the same file in one write.
+        #[serde(default)]
+        blocks: Option<Vec<PatchBlockEntry>>,
+        #[serde(rename = "patchId", default)]
+        patch_id: Option<String>,
+        #[serde(rename = "markerMode", default)]
+        marker_mode: MarkerMode,
+        #[serde(rename = "startOffset", default)]
+        start_offset: isize,
+        #[serde(rename = "endOffset", default)]
+        end_offset: isize,
+        #[serde(default)]
+        similarity: Option<f64>,
+        #[serde(rename = "insertIfMissing", default)]
+        insert_if_missing: Option<InsertAnchor>,
+    },
+    SetJsonValue { 
+        file: String, 
+        #[serde(rename = "keyPath")]
+        key_path: String, 
+        value: serde_json::Value 
+    },
+    RunCommand {
+        command: String,
+        args: Vec<String>,
+        /// Working directory, relative to the install target; the installer's own when omitted.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        cwd: Option<String>,
+        /// Variables added to the installer's environment.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        env: Option<BTreeMap<String, String>>,
+        /// Kill the command and fail the step after this many seconds.
+        #[serde(rename = "timeoutSecs", default, skip_serializing_if = "Option::is_none")]
+        timeout_secs: Option<u64>,
+        /// Run the command line through a shell instead of starting `command` directly.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        shell: Option<CommandShell>,
+        /// Run with administrator rights: through the UAC prompt on Windows, pkexec or sudo elsewhere.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        elevate: Option<bool>,
+    },
+    /// Recomputes the checksums in VS Code's `product.json` for files earlier steps patched,
+    /// so VS Code doesn't report itself as corrupt.
+    FixVsCodeChecksums {
+        /// Install folder, `resources/app` folder or `.app` bundle.
+        #[serde(rename = "appDir")]
+        app_dir: String,
+    },
+    /// Installs a bundled `.vsix` with the editor's command line tool.
+    InstallVsix {
+        /// The `.vsix`, relative to the payload folder.
+        #[serde(rename = "vsixFile")]
+        vsix_file: String,
+        /// The `code`-style tool to use; the first VS Code install found when omitted.
+        #[serde(rename = "cliPath", default, skip_serializing_if = "Option::is_none")]
+        cli_path: Option<String>,
+    },
+    Base64Embed { 
+        file: String, 
+        placeholder: String, 
+        #[serde(rename = "inputFile")]
+        input_file: String,
+        /// Replace at most this many occurrences (all when omitted).
+        #[serde(default)]
+        occurrences: Option<usize>,
+        /// Emit a full `data:<mime>;base64,...` URI instead of the bare base64 string.
+        #[serde(rename = "dataUri", default)]
+        data_uri: bool,
+        /// Tokens replaced in the input file before encoding; it must then be text.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        replacements: Option<HashMap<String, String>>,
+    },
+    /// Removes a file or folder; nothing happens when it is already gone. Paths naming no
+    /// file or folder of their own (`""`, `.`, `/`, `C:\\`, `~`) are refused.
+    Delete {
+        #[serde(deserialize_with = "deserialize_delete_path")]
+        path: String,
+    },
+    /// Post-condition check; a failure aborts the install and rolls back.
+    Verify {
+        file: String,
+        #[serde(default)]
+        contains: Option<String>,
+        #[serde(default)]
+        sha256: Option<String>,
+        #[serde(rename = "jsonPath", default)]
+        json_path: Option<String>,
+        #[serde(default)]
+        equals: Option<serde_json::Value>,
+    },
+}
+
+impl InstallStep {
+    /// The paths on the target machine the step writes to, runs in or checks; payload
+    /// paths are left out.
+    pub fn target_paths_mut(&mut self) -> Vec<&mut String> {
+        match self {
+            InstallStep::Copy { dest, .. } => vec![dest],
+            InstallStep::PatchBlock { file, .. }
+            | InstallStep::SetJsonValue { file, .. }
+            | InstallStep::Base64Embed { file, .. }
+            | InstallStep::Verify { file, .. } => vec![file],
+            InstallStep::Delete { path } => vec![path],
+            InstallStep::FixVsCodeChecksums { app_dir } => vec![app_dir],
+            InstallStep::InstallVsix { cli_path, .. } => cli_path.iter_mut().collect(),
+            InstallStep::RunCommand { cwd, .. } => cwd.iter_mut().collect(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct PatchBlockEntry {
+    pub start_marker: String,
+    pub end_marker: String,
+    /// Empty when the block has inline `content`.
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub content_file: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub content: Option<String>,
+    #[serde(default)]
+    pub patch_id: Option<String>,
+}
+
+/// Where a missing marker block gets seeded when `insertIfMissing` is set.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(tag = "at", rename_all = "camelCase")]
+pub enum InsertAnchor {
+    AppendToEnd,
+    /// Inserts after the first line matching `pattern` (a regex).
+    AfterLineMatching { pattern: String },
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum PatchOutcome {
+    /// The block was written; `original` holds the bytes it replaced (markers included
+    /// when they were stripped).
+    Applied { original: Vec<u8> },
+    /// The markers were missing and a new block was inserted.
+    Inserted,
+    AlreadyApplied,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct PatchRecord {
+    pub file: String,
+    pub start_marker: String,
+    pub end_marker: String,
+    /// The replaced text; empty when it wasn't UTF-8 and `original_base64` holds it instead.
+    pub original: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub original_base64: Option<String>,
+    pub applied: String,
+    pub markers_stripped: bool,
+    #[serde(default)]
+    pub matching: MarkerMatch,
+    /// The block did not exist before install; reverting removes it entirely.
+    #[serde(default)]
+    pub inserted: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct InstallReceipt {
+    pub app_name: String,
+    pub version: String,
+    pub installed_at: String,
+    pub patches: Vec<PatchRecord>,
+    /// Components chosen by the latest install, which `repair_install` sticks to.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub components: Vec<String>,
+    /// Path → SHA-256 of each file the install left behind, when the manifest sets
+    /// `watchInstalledFiles`.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub watched_files: BTreeMap<String, String>,
+    /// Folder the install wrote to, which uninstalling locks like installing does.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub target_base: Option<String>,
+    /// File left in use → its staged `.misfit-reboot` copy, which Windows moves over it at the
+    /// next restart. Uninstalling deletes staged copies that are still waiting.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub pending_replacements: BTreeMap<String, String>,
+}
+
+impl PatchRecord {
+    /// The bytes a revert puts back.
+    pub fn original_bytes(&self) -> Result<Vec<u8>> {
+        use base64::Engine as _;
+        match &self.original_base64 {
+            Some(encoded) => base64::engine::general_purpose::STANDARD.decode(encoded).context("Invalid original in the install receipt"),
+            None => Ok(self.original.clone().into_bytes()),
+        }
+    }
+}
+
+/// Replaced bytes as the `original` and `original_base64` of a [`PatchRecord`].
+pub fn original_fields(bytes: Vec<u8>) -> (String, Option<String>) {
+    use base64::Engine as _;
+    match String::from_utf8(bytes) {
+        Ok(text) => (text, None),
+        Err(err) => (String::new(), Some(base64::engine::general_purpose::STANDARD.encode(err.as_bytes()))),
+    }
+}
+
+impl InstallReceipt {
+    /// Replaces any earlier record for the same file and marker pair, so re-installs keep a
+    /// single entry. Its `original` (and whether the block was inserted) stay those of the
+    /// first install, so uninstalling goes back to the file from before any install.
+    pub fn record_patch(&mut self, mut record: PatchRecord) {
+        let same = |p: &PatchRecord| p.file == record.file && p.start_marker == record.start_marker && p.end_marker == record.end_marker;
+        if let Some(first) = self.patches.iter().position(same).map(|i| self.patches.remove(i)) {
+            record.original = first.original;
+            record.original_base64 = first.original_base64;
+            record.inserted = first.inserted;
+        }
+        self.patches.push(record);
+    }
+}
+
+pub const RECEIPT_FILE: &str = "install_receipt.json";
+
+pub fn load_receipt(backup_root: &Path) -> Result<Option<InstallReceipt>> {
+    let path = backup_root.join(RECEIPT_FILE);
+    if !path.exists() {
+        return Ok(None);
+    }
+    let content = fs::read_to_string(&path).context("Failed to read install receipt")?;
+    let receipt = serde_json::from_str(&content).context("Failed to parse install receipt")?;
+    Ok(Some(receipt))
+}
+
+pub fn save_receipt(receipt: &InstallReceipt, backup_root: &Path) -> Result<()> {
+    fs::create_dir_all(backup_root).context("Failed to create receipt directory")?;
+    let json = serde_json::to_string_pretty(receipt)?;
+    write_atomic(&backup_root.join(RECEIPT_FILE), json.as_bytes()).context("Failed to write install receipt")?;
+    Ok(())
+}
+
+/// Reads the manifest at `path`, replacing every `{ "$include": "<file>" }` entry of
+/// `installSteps`, `preInstall` and `postInstall` with the steps in that file (see
+/// [`INCLUDE_KEY`]).
+pub fn load_manifest(path: &Path) -> Result<InstallManifest> {
+    let content = fs::read_to_string(path).context(format!("Failed to read manifest file at {:?}", path))?;
+    
+    // Strip BOM if present
+    let content = content.strip_prefix("\u{feff}").unwrap_or(&content);
+
+    let parse_error = |e: String| {
+        let message = format!("Failed to parse manifest: {}. Content snippet: {:.50}...", e, content);
+        MisfitError::new(ErrorCode::InvalidManifest, message).with_path(path)
+    };
+    let mut value = parse_manifest_text(path, content).map_err(parse_error)?;
+    migrate_manifest(&mut value).map_err(|e| MisfitError::from(e).with_path(path))?;
+    let dir = path.parent().unwrap_or(Path::new("."));
+    let mut stack = vec![fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf())];
+    if let Some(steps) = value.get_mut("installSteps") {
+        *steps = serde_json::Value::Array(expand_includes::<ManifestStep>("installSteps", steps.take(), dir, &mut stack)?);
+    }
+    for hooks in ["preInstall", "postInstall"] {
+        if let Some(list) = value.get_mut(hooks) {
+            *list = serde_json::Value::Array(expand_includes::<Hook>(hooks, list.take(), dir, &mut stack)?);
+        }
+    }
+    let manifest: InstallManifest = serde_json::from_value(value).map_err(|e| parse_error(e.to_string()))?;
+    Ok(manifest)
+}
+
+/// Extensions a manifest (or an included step file) can have, in the order bundles are
+/// searched: JSON, then YAML and TOML, which read the same fields.
+pub const MANIFEST_EXTENSIONS: [&str; 4] = ["json", "yaml", "yml", "toml"];
+
+/// Parses `content` as YAML or TOML when `path` says so, otherwise as JSON.
+fn parse_manifest_text(path: &Path, content: &str) -> std::result::Result<serde_json::Value, String> {
+    let extension = path.extension().map(|e| e.to_string_lossy().to_ascii_lowercase());
+    match extension.as_deref() {
+        Some("yaml" | "yml") => serde_yaml::from_str(content).map_err(|e| e.to_string()),
+        Some("toml") => toml::from_str(content).map_err(|e| e.to_string()),
+        _ => serde_json::from_str(content).map_err(|e| e.to_string()),
+    }
+}
+
+/// An entry of a step list that is only `{ "$include": "steps/theme.json" }` stands for the
+/// steps in that file: an array of steps, or a single step (the only form TOML allows). The
+/// path is relative to the including file, and included files may include others.
+pub const INCLUDE_KEY: &str = "$include";
+
+/// Replaces the include entries of the step list `entries` (the manifest's `key`), each
+/// included step checked to be a `T`. `stack` holds the files being included, to refuse cycles.
+fn expand_includes<T: serde::de::DeserializeOwned>(key: &str, entries: serde_json::Value, dir: &Path, stack: &mut Vec<PathBuf>) -> Result<Vec<serde_json::Value>> {
+    let serde_json::Value::Array(entries) = entries else {
+        let file = stack.last().map_or(dir, |file| file.as_path());
+        return Err(MisfitError::new(ErrorCode::InvalidManifest, format!("{} must be an array", key)).with_path(file).into());
+    };
+    let mut expanded = Vec::new();
+    for entry in entries {
+        let Some(include) = entry.as_object().filter(|o| o.len() == 1).and_then(|o| o.get(INCLUDE_KEY)) else {
+            expanded.push(entry);
+            continue;
+        };
+        let rel = include
+            .as_str()
+            .ok_or_else(|| MisfitError::new(ErrorCode::InvalidManifest, format!("{} must be a file path", INCLUDE_KEY)).with_path(dir))?;
+        let path = dir.join(rel);
+        let canonical = fs::canonicalize(&path).map_err(|e| MisfitError::from(e).with_path(&path))?;
+        if stack.contains(&canonical) {
+            let message = format!("{} includes itself through {}", path.display(), INCLUDE_KEY);
+            return Err(MisfitError::new(ErrorCode::InvalidManifest, message).with_path(&path).into());
+        }
+        let content = fs::read_to_string(&path).map_err(|e| MisfitError::from(e).with_path(&path))?;
+        let content = content.strip_prefix("\u{feff}").unwrap_or(&content);
+        let invalid = |message: String| MisfitError::new(ErrorCode::InvalidManifest, message).with_path(&path);
+        let steps = match parse_manifest_text(&path, content).map_err(|e| invalid(format!("Failed to parse included steps: {}", e)))? {
+            serde_json::Value::Array(steps) => steps,
+            step @ serde_json::Value::Object(_) => vec![step],
+            _ => return Err(invalid("Included files must hold a step or an array of steps".to_string()).into()),
+        };
+        stack.push(canonical);
+        let steps = expand_includes::<T>(key, serde_json::Value::Array(steps), path.parent().unwrap_or(Path::new(".")), stack)?;
+        stack.pop();
+        for (index, step) in steps.iter().enumerate() {
+            serde_json::from_value::<T>(step.clone()).map_err(|e| invalid(format!("Included step {} is invalid: {}", index + 1, e)))?;
+        }
+        expanded.extend(steps);
+    }
+    Ok(expanded)
+}
+
+/// Id of a bundle's main manifest, `manifests/install.manifest.json` (or `.yaml`, `.yml`,
+/// `.toml`). Other manifests in the folder are `<id>.manifest.<extension>`.
+pub const DEFAULT_MANIFEST_ID: &str = "install";
+
+/// The id and extension of a bundle manifest file named `<id>.manifest.<extension>`.
+fn bundle_manifest_id(path: &Path) -> Option<(String, usize)> {
+    let name = path.file_name()?.to_string_lossy();
+    MANIFEST_EXTENSIONS.iter().enumerate().find_map(|(index, ext)| {
+        let id = name.strip_suffix(ext)?.strip_suffix(".manifest.")?;
+        validate_manifest_id(id).is_ok().then(|| (id.to_string(), index))
+    })
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct BundledManifest {
+    pub id: String,
+    pub app_name: String,
+    pub version: String,
+    pub description: String,
+    pub path: String,
+}
+
+/// Ids name files, so they are limited to letters, digits, `-` and `_`.
+pub fn validate_manifest_id(id: &str) -> Result<()> {
+    if id.is_empty() || !id.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_') {
+        let message = format!("Invalid manifest id {:?}: use letters, digits, '-' and '_'", id);
+        return Err(MisfitError::new(ErrorCode::InvalidManifest, message).into());
+    }
+    Ok(())
+}
+
+/// The file of manifest `id`: the first extension in [`MANIFEST_EXTENSIONS`] that exists, or
+/// `<id>.manifest.json` when none does.
+pub fn bundle_manifest_path(manifests_dir: &Path, id: &str) -> Result<PathBuf> {
+    validate_manifest_id(id)?;
+    let path = |ext: &str| manifests_dir.join(format!("{}.manifest.{}", id, ext));
+    Ok(MANIFEST_EXTENSIONS.iter().map(|ext| path(ext)).find(|p| p.is_file()).unwrap_or_else(|| path("json")))
+}
+
+/// Every `<id>.manifest.<extension>` in `manifests_dir`: the main one first, then the others by
+/// id. An id with several files keeps the one [`bundle_manifest_path`] picks.
+pub fn list_bundle_manifests(manifests_dir: &Path) -> Result<Vec<BundledManifest>> {
+    let mut manifests = Vec::new();
+    for path in bundle_manifest_files(manifests_dir)? {
+        let (id, _) = bundle_manifest_id(&path).unwrap_or_default();
+        let manifest = load_manifest(&path)?;
+        manifests.push(BundledManifest {
+            id,
+            app_name: manifest.app_name.to_string(),
+            version: manifest.version,
+            description: manifest.description.to_string(),
+            path: path.to_string_lossy().to_string(),
+        });
+    }
+    Ok(manifests)
+}
+
+fn bundle_manifest_files(manifests_dir: &Path) -> Result<Vec<PathBuf>> {
+    let mut files: Vec<(String, usize, PathBuf)> = fs::read_dir(manifests_dir)
+        .map_err(|e| MisfitError::from(e).with_path(manifests_dir))?
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(|path| path.is_file())
+        .filter_map(|path| bundle_manifest_id(&path).map(|(id, ext)| (id, ext, path)))
+        .collect();
+    files.sort_by(|a, b| (a.0 != DEFAULT_MANIFEST_ID, &a.0, a.1).cmp(&(b.0 != DEFAULT_MANIFEST_ID, &b.0, b.1)));
+    files.dedup_by(|later, first| later.0 == first.0);
+    Ok(files.into_iter().map(|(_, _, path)| path).collect())
+}
+
+fn sanitize_component_name(input: &str) -> String {
+    let mut out = String::new();
+    for ch in input.chars() {
+        if ch.is_ascii_alphanumeric() || ch == '-' || ch == '_' || ch == '.' {
+            out.push(ch);
+        } else {
+            out.push('_');
+        }
+    }
+    if out.is_empty() { "_".to_string() } else { out }
+}
+
+fn backup_rel_path(path: &Path) -> Result<PathBuf> {
+    let abs_path = fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+    let mut rel = PathBuf::new();
+    rel.push("abs");
+
+    for component in abs_path.components() {
+        match component {
+            Component::Prefix(prefix) => {
+                let prefix_str = prefix.as_os_str().to_string_lossy();
+                rel.push(sanitize_component_name(&prefix_str));
+            }
+            Component::RootDir => {},
+            Component::CurDir => {},
+            Component::ParentDir => {},
+            Component::Normal(os) => rel.push(os),
+        }
+    }
+
+    if rel.as_os_str().is_empty() {
+        return Err(anyhow!("Failed to build backup path"));
+    }
+    Ok(rel)
+}
+
+const OBJECT_STORE_DIR: &str = "objects";
+const BACKUP_OBJECTS_FILE: &str = "objects.json";
+
+/// A backed-up file's content in the object store shared by all backups of one app, so
+/// files that did not change between installs are only stored once. A symlink inside a
+/// backed-up folder has no blob, only its `link` target.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+struct ObjectRef {
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    sha256: String,
+    size: u64,
+    /// Unix seconds; restored onto the target since blobs are shared.
+    modified: Option<i64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    link: Option<PathBuf>,
+}
+
+fn object_path(store: &Path, hash: &str) -> PathBuf {
+    store.join(&hash[..2]).join(hash)
+}
+
+fn store_object(store: &Path, src: &Path) -> Result<ObjectRef> {
+    let bytes = fs::read(src).with_context(|| format!("Failed to read {}", src.display()))?;
+    let hash = sha256_hex(&bytes);
+    let path = object_path(store, &hash);
+    if !path.exists() {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        // Goes through a temporary file that is removed again if the write fails
+        write_atomic(&path, &bytes).with_context(|| format!("Failed to store {}", src.display()))?;
+    }
+    let modified = fs::metadata(src)?
+        .modified()
+        .ok()
+        .map(|t| filetime::FileTime::from_system_time(t).unix_seconds());
+    Ok(ObjectRef { sha256: hash, size: bytes.len() as u64, modified, link: None })
+}
+
+/// Backs up `paths` into a new `backup_<timestamp>` folder under `backup_root`. File contents
+/// go to `backup_root/objects` keyed by SHA-256; the folder itself only holds
+/// `restore_map.json` (backup path -> original path) and `objects.json` (file -> blob, or
+/// link target for the symlinks inside backed-up folders).
+pub fn backup_files(paths: &[String], backup_root: &Path) -> Result<PathBuf> {
+    let timestamp = chrono::Local::now().format("%Y%m%d_%H%M%S").to_string();
+    let backup_dir = backup_root.join(format!("backup_{}", timestamp));
+    fs::create_dir_all(&backup_dir).context("Failed to create backup directory")?;
+    let store = backup_root.join(OBJECT_STORE_DIR);
+
+    let mut restore_map: HashMap<String, String> = HashMap::new();
+    let mut objects: BTreeMap<String, ObjectRef> = BTreeMap::new();
+
+    for path_str in paths {
+        let path = Path::new(path_str);
+        if path.exists() {
+            let backup_rel = backup_rel_path(path)?;
+            let key = lock_key(&backup_rel);
+            if path.is_dir() {
+                for entry in walkdir::WalkDir::new(path) {
+                    let entry = entry?;
+                    let object = if entry.file_type().is_symlink() {
+                        let target = fs::read_link(entry.path()).with_context(|| format!("Failed to read {}", entry.path().display()))?;
+                        ObjectRef { sha256: String::new(), size: 0, modified: None, link: Some(target) }
+                    } else if entry.file_type().is_file() {
+                        store_object(&store, entry.path())?
+                    } else {
+                        continue;
+                    };
+                    let rel = entry.path().strip_prefix(path).context("Walk escaped backup source")?;
+                    objects.insert(format!("{}/{}", key, lock_key(rel)), object);
+                }
+            } else {
+                objects.insert(key, store_object(&store, path)?);
+            }
+            // Store absolute path in map
+            let abs_path = fs::canonicalize(path).unwrap_or(path.to_path_buf());
+            restore_map.insert(backup_rel.to_string_lossy().to_string(), abs_path.to_string_lossy().to_string());
+        }
+    }
+    
+    // Save restore map
+    let map_json = serde_json::to_string_pretty(&restore_map)?;
+    let map_path = backup_dir.join("restore_map.json");
+    write_atomic(&map_path, map_json.as_bytes()).map_err(|e| MisfitError::from(e).with_path(&map_path))?;
+    let objects_path = backup_dir.join(BACKUP_OBJECTS_FILE);
+    write_atomic(&objects_path, serde_json::to_string_pretty(&objects)?.as_bytes()).map_err(|e| MisfitError::from(e).with_path(&objects_path))?;
+
+    Ok(backup_dir)
+}
+
+/// One file to restore from a backup entry.
+struct BackupFile {
+    /// Path below the entry's target; empty when the entry is a single file.
+    rel: PathBuf,
+    /// Where the backed-up content is read from.
+    source: PathBuf,
+    size: u64,
+    modified: Option<i64>,
+    /// Target of a backed-up symlink, which is recreated instead of copied from `source`.
+    link: Option<PathBuf>,
+}
+
+/// A backup's restore map plus where each file's content lives: the app's object store, or
+/// the backup folder itself for backups made before the store existed.
+struct BackupIndex {
+    dir: PathBuf,
+    restore_map: HashMap<String, String>,
+    objects: Option<BTreeMap<String, ObjectRef>>,
+}
+
+impl BackupIndex {
+    fn open(dir: &Path) -> Result<Self> {
+        let restore_map = read_restore_map(dir)?;
+        let objects_path = dir.join(BACKUP_OBJECTS_FILE);
+        let objects = if objects_path.exists() {
+            Some(serde_json::from_str(&fs::read_to_string(&objects_path)?)?)
+        } else {
+            None
+        };
+        Ok(BackupIndex { dir: dir.to_path_buf(), restore_map, objects })
+    }
+
+    fn files(&self, backup_rel: &str) -> Result<Vec<BackupFile>> {
+        let Some(objects) = &self.objects else {
+            let root = self.dir.join(backup_rel);
+            let mut files = Vec::new();
+            if !root.exists() {
+                return Ok(files);
+            }
+            for entry in walkdir::WalkDir::new(&root) {
+                let entry = entry?;
+                if entry.file_type().is_dir() {
+                    continue;
+                }
+                let rel = entry.path().strip_prefix(&root).context("Walk escaped backup")?.to_path_buf();
+                let size = entry.metadata()?.len();
+                files.push(BackupFile { rel, source: entry.path().to_path_buf(), size, modified: None, link: None });
+            }
+            return Ok(files);
+        };
+        let store = self.dir.parent().unwrap_or(&self.dir).join(OBJECT_STORE_DIR);
+        let key = lock_key(Path::new(backup_rel));
+        let prefix = format!("{}/", key);
+        Ok(objects
+            .iter()
+            .filter_map(|(name, obj)| {
+                let rel = if *name == key { "" } else { name.strip_prefix(&prefix)? };
+                Some(BackupFile {
+                    rel: PathBuf::from(rel),
+                    source: if obj.link.is_some() { PathBuf::new() } else { object_path(&store, &obj.sha256) },
+                    size: obj.size,
+                    modified: obj.modified,
+                    link: obj.link.clone(),
+                })
+            })
+            .collect())
+    }
+}
+
+/// Joining an empty path would add a trailing separator.
+fn join_rel(base: &Path, rel: &Path) -> PathBuf {
+    if rel.as_os_str().is_empty() {
+        base.to_path_buf()
+    } else {
+        base.join(rel)
+    }
+}
+
+fn restore_file(file: &BackupFile, dest: &Path) -> Result<()> {
+    if let Some(target) = &file.link {
+        if let Some(parent) = dest.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        if fs::read_link(dest).is_ok_and(|current| current == *target) {
+            return Ok(());
+        }
+        match fs::symlink_metadata(dest) {
+            Ok(meta) if meta.is_dir() => fs::remove_dir_all(dest)?,
+            Ok(_) => fs::remove_file(dest)?,
+            Err(_) => {}
+        }
+        return create_symlink(target, dest).with_context(|| format!("Failed to restore {}", dest.display()));
+    }
+    if !file.source.exists() {
+        return Err(anyhow!("Backup copy of {} is missing", dest.display()));
+    }
+    if let Some(parent) = dest.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    copy_file_preserving(&file.source, dest).with_context(|| format!("Failed to restore {}", dest.display()))?;
+    if let Some(modified) = file.modified {
+        filetime::set_file_mtime(dest, filetime::FileTime::from_unix_time(modified, 0))?;
+    }
+    Ok(())
+}
+
+pub fn restore_latest_backup(backup_root: &Path) -> Result<String> {
+    // Find latest backup dir
+    if !backup_root.is_dir() {
+        return Err(MisfitError::new(ErrorCode::BackupNotFound, "Backup root not found").with_path(backup_root).into());
+    }
+    let mut dirs = backup_dirs_in(backup_root);
+    
+    dirs.sort(); // Lexicographical sort works for YYYYMMDD_HHMMSS
+    
+    let latest = dirs.last().ok_or_else(|| MisfitError::new(ErrorCode::BackupNotFound, "No backups found").with_path(backup_root))?;
+    let _lock = lock_operation(backup_root, "restore")?;
+    restore_backup_dir(latest)?;
+    
+    Ok(latest.to_string_lossy().to_string())
+}
+
+/// Paths an install was about to create, which a rollback removes; kept in its backup folder.
+pub const CREATED_FILES_FILE: &str = "created_files.json";
+
+/// Undoes a failed install: removes what it created (see [`CREATED_FILES_FILE`]), then puts
+/// back the backed-up files.
+pub fn roll_back_install(backup_dir: &Path) -> Result<()> {
+    if let Ok(json) = fs::read_to_string(backup_dir.join(CREATED_FILES_FILE)) {
+        let created: Vec<PathBuf> = serde_json::from_str(&json).context("Invalid list of created files")?;
+        for path in created {
+            let removed = match fs::symlink_metadata(&path) {
+                Ok(meta) if meta.is_dir() => fs::remove_dir_all(&path),
+                Ok(_) => fs::remove_file(&path),
+                Err(_) => continue,
+            };
+            removed.with_context(|| format!("Failed to remove {}", path.display()))?;
+        }
+    }
+    restore_backup_dir(backup_dir)
+}
+
+pub fn restore_backup_dir(backup_dir: &Path) -> Result<()> {
+    let index = BackupIndex::open(backup_dir)?;
+    
+    for (backup_rel, target_path_str) in &index.restore_map {
+        for file in index.files(backup_rel)? {
+            restore_file(&file, &join_rel(Path::new(target_path_str), &file.rel))?;
+        }
+    }
+    Ok(())
+}
+
+/// Restores only `files` (original target paths) from a backup. A path inside a backed-up
+/// folder restores just that file. Returns the number of paths restored.
+pub fn restore_backup_files(backup_dir: &Path, files: &[String]) -> Result<usize> {
+    let index = BackupIndex::open(backup_dir)?;
+    let mut restored = 0;
+    for file in files {
+        let wanted = Path::new(file);
+        let (backup_rel, target, sub) = index
+            .restore_map
+            .iter()
+            .find_map(|(backup_rel, target)| Some((backup_rel, target, wanted.strip_prefix(target).ok()?)))
+            .ok_or_else(|| anyhow!("{} is not part of backup {}", file, backup_dir.display()))?;
+        let matching: Vec<BackupFile> = index.files(backup_rel)?.into_iter().filter(|f| f.rel.starts_with(sub)).collect();
+        if matching.is_empty() {
+            return Err(anyhow!("Backup copy of {} is missing", file));
+        }
+        for item in &matching {
+            restore_file(item, &join_rel(Path::new(target), &item.rel))?;
+        }
+        restored += 1;
+    }
+    Ok(restored)
+}
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum RestoreStatus {
+    /// The target already matches the backup.
+    Unchanged,
+    /// The target exists and will be overwritten.
+    Differs,
+    /// The target is gone and will be recreated.
+    Missing,
+}
+
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct RestorePreviewEntry {
+    pub target: String,
+    pub backup_path: String,
+    pub status: RestoreStatus,
+}
+
+/// Compares every file in a backup with its target by SHA-256 without writing anything.
+/// Backed-up folders are expanded into one entry per file.
+pub fn preview_restore(backup_dir: &Path) -> Result<Vec<RestorePreviewEntry>> {
+    let index = BackupIndex::open(backup_dir)?;
+    let mut entries = Vec::new();
+    for (backup_rel, target) in &index.restore_map {
+        for file in index.files(backup_rel)? {
+            let dest = join_rel(Path::new(target), &file.rel);
+            let status = if let Some(link) = &file.link {
+                match fs::read_link(&dest) {
+                    Ok(current) if current == *link => RestoreStatus::Unchanged,
+                    Ok(_) => RestoreStatus::Differs,
+                    Err(_) if fs::symlink_metadata(&dest).is_ok() => RestoreStatus::Differs,
+                    Err(_) => RestoreStatus::Missing,
+                }
+            } else if !dest.is_file() {
+                RestoreStatus::Missing
+            } else if sha256_hex(&fs::read(&file.source)?) == sha256_hex(&fs::read(&dest)?) {
+                RestoreStatus::Unchanged
+            } else {
+                RestoreStatus::Differs
+            };
+            entries.push(RestorePreviewEntry {
+                target: dest.to_string_lossy().to_string(),
+                backup_path: lock_key(&join_rel(Path::new(backup_rel), &file.rel)),
+                status,
+            });
+        }
+    }
+    entries.sort_by(|a, b| a.target.cmp(&b.target));
+    Ok(entries)
+}
+
+/// Deletes blobs in `namespace_dir/objects` that no remaining backup references.
+fn collect_garbage(namespace_dir: &Path) -> Result<()> {
+    let store = namespace_dir.join(OBJECT_STORE_DIR);
+    if !store.is_dir() {
+        return Ok(());
+    }
+    let mut referenced = HashSet::new();
+    for dir in backup_dirs_in(namespace_dir) {
+        if let Some(objects) = BackupIndex::open(&dir)?.objects {
+            referenced.extend(objects.into_values().map(|o| o.sha256));
+        }
+    }
+    for entry in walkdir::WalkDir::new(&store).into_iter().filter_map(|e| e.ok()) {
+        let name = entry.file_name().to_string_lossy();
+        if entry.file_type().is_file() && !referenced.contains(name.as_ref()) {
+            fs::remove_file(entry.path())?;
+        }
+    }
+    Ok(())
+}
+
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct BackupSummary {
+    /// `<namespace>/backup_<timestamp>` relative to the backups root (just the folder name
+    /// for backups made before per-app namespaces).
+    pub id: String,
+    pub app: Option<String>,
+    pub created_at: Option<String>,
+    pub file_count: usize,
+    pub total_bytes: u64,
+}
+
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct BackupEntry {
+    /// Path inside the backup folder.
+    pub backup_path: String,
+    /// Where the file is restored to.
+    pub target: String,
+    pub size: u64,
+    pub is_dir: bool,
+}
+
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct BackupDetails {
+    #[serde(flatten)]
+    pub summary: BackupSummary,
+    pub files: Vec<BackupEntry>,
+}
+
+fn is_backup_dir(path: &Path) -> bool {
+    path.is_dir() && path.file_name().is_some_and(|n| n.to_string_lossy().starts_with("backup_"))
+}
+
+fn backup_dirs_in(dir: &Path) -> Vec<PathBuf> {
+    let Ok(entries) = fs::read_dir(dir) else { return Vec::new() };
+    entries.filter_map(|e| e.ok()).map(|e| e.path()).filter(|p| is_backup_dir(p)).collect()
+}
+
+/// Resolves a backup id to its folder, rejecting ids that would leave the backups root.
+pub fn resolve_backup_id(backups_root: &Path, id: &str) -> Result<PathBuf> {
+    let rel = Path::new(id);
+    let depth = rel.components().count();
+    if depth == 0 || depth > 2 || !rel.components().all(|c| matches!(c, Component::Normal(_))) {
+        return Err(anyhow!("Invalid backup id: {}", id));
+    }
+    let dir = backups_root.join(rel);
+    if !is_backup_dir(&dir) {
+        return Err(MisfitError::new(ErrorCode::BackupNotFound, format!("Backup not found: {}", id)).with_path(&dir).into());
+    }
+    Ok(dir)
+}
+
+fn read_restore_map(backup_dir: &Path) -> Result<HashMap<String, String>> {
+    let map_path = backup_dir.join("restore_map.json");
+    if !map_path.exists() {
+        return Err(anyhow!("Restore map not found in backup {}", backup_dir.display()));
+    }
+    let map_content = fs::read_to_string(&map_path)?;
+    Ok(serde_json::from_str(&map_content)?)
+}
+
+pub fn backup_details(backups_root: &Path, id: &str) -> Result<BackupDetails> {
+    let dir = resolve_backup_id(backups_root, id)?;
+    let index = BackupIndex::open(&dir)?;
+    let mut files = Vec::new();
+    for (backup_rel, target) in &index.restore_map {
+        let contents = index.files(backup_rel)?;
+        let is_dir = !(contents.len() == 1 && contents[0].rel.as_os_str().is_empty());
+        files.push(BackupEntry {
+            backup_path: backup_rel.clone(),
+            target: target.clone(),
+            size: contents.iter().map(|f| f.size).sum(),
+            is_dir,
+        });
+    }
+    files.sort_by(|a, b| a.target.cmp(&b.target));
+
+    let name = dir.file_name().unwrap_or_default().to_string_lossy().to_string();
+    let created_at = chrono::NaiveDateTime::parse_from_str(name.trim_start_matches("backup_"), "%Y%m%d_%H%M%S")
+        .ok()
+        .and_then(|t| t.and_local_timezone(chrono::Local).single())
+        .map(|t| t.to_rfc3339());
+    let app = Path::new(id).parent().filter(|p| !p.as_os_str().is_empty()).map(|p| p.to_string_lossy().to_string());
+    let summary = BackupSummary {
+        id: id.replace('\\', "/"),
+        app,
+        created_at,
+        file_count: files.len(),
+        total_bytes: files.iter().map(|f| f.size).sum(),
+    };
+    Ok(BackupDetails { summary, files })
+}
+
+/// Backups under `backups_root`, newest first. With a namespace only that app's backups are
+/// listed; otherwise every app's plus any legacy backups directly in the root.
+pub fn list_backups(backups_root: &Path, namespace: Option<&str>) -> Result<Vec<BackupSummary>> {
+    let mut ids = Vec::new();
+    let namespaces: Vec<String> = match namespace {
+        Some(ns) => vec![ns.to_string()],
+        None => {
+            ids.extend(backup_dirs_in(backups_root).into_iter().filter_map(|d| d.file_name().map(|n| n.to_string_lossy().to_string())));
+            fs::read_dir(backups_root)
+                .map(|entries| {
+                    entries
+                        .filter_map(|e| e.ok())
+                        .map(|e| e.path())
+                        .filter(|p| p.is_dir() && !is_backup_dir(p))
+                        .filter_map(|p| p.file_name().map(|n| n.to_string_lossy().to_string()))
+                        .collect()
+                })
+                .unwrap_or_default()
+        }
+    };
+    for ns in namespaces {
+        for dir in backup_dirs_in(&backups_root.join(&ns)) {
+            if let Some(name) = dir.file_name() {
+                ids.push(format!("{}/{}", ns, name.to_string_lossy()));
+            }
+        }
+    }
+
+    let mut summaries: Vec<BackupSummary> = ids
+        .iter()
+        .filter_map(|id| backup_details(backups_root, id).ok())
+        .map(|details| details.summary)
+        .collect();
+    let folder = |s: &BackupSummary| s.id.rsplit('/').next().unwrap_or_default().to_string();
+    summaries.sort_by_key(|s| std::cmp::Reverse(folder(s)));
+    Ok(summaries)
+}
+
+pub fn delete_backup(backups_root: &Path, id: &str) -> Result<()> {
+    collect_garbage(&remove_backup_dir(backups_root, id)?)
+}
+
+/// Deletes a backup's folder but not its objects; returns the folder holding its object store.
+fn remove_backup_dir(backups_root: &Path, id: &str) -> Result<PathBuf> {
+    let dir = resolve_backup_id(backups_root, id)?;
+    fs::remove_dir_all(&dir).with_context(|| format!("Failed to delete backup {}", dir.display()))?;
+    Ok(dir.parent().unwrap_or(backups_root).to_path_buf())
+}
+
+/// Limits applied per app; a backup is pruned once it falls outside any of them. The newest
+/// backup of each app is always kept.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct RetentionPolicy {
+    pub keep_last: Option<usize>,
+    pub max_total_bytes: Option<u64>,
+    pub max_age_days: Option<u64>,
+}
+
+#[derive(Serialize, Debug, Clone, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct PruneReport {
+    pub dry_run: bool,
+    pub removed: Vec<BackupSummary>,
+    pub kept: usize,
+    pub freed_bytes: u64,
+}
+
+/// Applies `policy` to the backups of `namespace` (or of every app when `None`). With
+/// `dry_run` nothing is deleted and the report lists what would be.
+pub fn prune_backups(backups_root: &Path, namespace: Option<&str>, policy: &RetentionPolicy, dry_run: bool) -> Result<PruneReport> {
+    let mut groups: BTreeMap<Option<String>, Vec<BackupSummary>> = BTreeMap::new();
+    for backup in list_backups(backups_root, namespace)? {
+        groups.entry(backup.app.clone()).or_default().push(backup);
+    }
+    let now = chrono::Local::now();
+    let mut report = PruneReport { dry_run, ..PruneReport::default() };
+    let mut stores = BTreeSet::new();
+    for backups in groups.into_values() {
+        // Objects kept backups store, and the bytes they take up; shared objects count once
+        let mut stored = HashSet::new();
+        let mut total = 0u64;
+        let mut removed = Vec::new();
+        // list_backups returns newest first
+        for (idx, backup) in backups.into_iter().enumerate() {
+            let storage = backup_storage(backups_root, &backup);
+            let added: u64 = storage.iter().filter(|(key, _)| !stored.contains(*key)).map(|(_, size)| size).sum();
+            let age_days = backup
+                .created_at
+                .as_deref()
+                .and_then(|t| chrono::DateTime::parse_from_rfc3339(t).ok())
+                .map(|t| (now.fixed_offset() - t).num_days().max(0) as u64);
+            let keep = idx == 0
+                || (policy.keep_last.map_or(true, |n| idx < n)
+                    && policy.max_age_days.map_or(true, |max| age_days.map_or(true, |age| age <= max))
+                    && policy.max_total_bytes.map_or(true, |max| total + added <= max));
+            if keep {
+                total += added;
+                stored.extend(storage.into_keys());
+                report.kept += 1;
+                continue;
+            }
+            removed.push((backup, storage));
+        }
+        let mut freed = HashSet::new();
+        for (backup, storage) in removed {
+            if !dry_run {
+                stores.insert(remove_backup_dir(backups_root, &backup.id)?);
+            }
+            for (key, size) in storage {
+                if !stored.contains(&key) && freed.insert(key) {
+                    report.freed_bytes += size;
+                }
+            }
+            report.removed.push(backup);
+        }
+    }
+    // Objects are only dropped once every removal is done, in one pass per store
+    for store in stores {
+        collect_garbage(&store)?;
+    }
+    Ok(report)
+}
+
+/// What a backup takes up on disk: its objects by hash with their sizes. A backup from before
+/// the object store holds its own copies, counted under its id.
+fn backup_storage(backups_root: &Path, backup: &BackupSummary) -> BTreeMap<String, u64> {
+    let objects = resolve_backup_id(backups_root, &backup.id).and_then(|dir| BackupIndex::open(&dir)).ok().and_then(|index| index.objects);
+    match objects {
+        Some(objects) => objects.into_values().filter(|o| o.link.is_none()).map(|o| (o.sha256, o.size)).collect(),
+        None => BTreeMap::from([(backup.id.clone(), backup.total_bytes)]),
+    }
+}
+
+pub const PAYLOAD_LOCK_FILE: &str = "payload.lock.json";
+
+/// SHA-256 digests of payload files, keyed by path relative to the payload folder
+/// (forward slashes).
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct PayloadLock {
+    pub algorithm: String,
+    pub files: BTreeMap<String, String>,
+}
+
+fn lock_key(rel: &Path) -> String {
+    rel.components()
+        .map(|c| c.as_os_str().to_string_lossy().to_string())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+/// Hashes every file under each of `entries` (paths relative to `payload_root`).
+pub fn build_payload_lock(payload_root: &Path, entries: &[PathBuf]) -> Result<PayloadLock> {
+    let mut files = BTreeMap::new();
+    for entry in entries {
+        for item in walkdir::WalkDir::new(payload_root.join(entry)) {
+            let item = item?;
+            if !item.file_type().is_file() {
+                continue;
+            }
+            let rel = item.path().strip_prefix(payload_root).context("Payload escaped payload root")?;
+            let bytes = fs::read(item.path()).with_context(|| format!("Failed to hash {}", item.path().display()))?;
+            files.insert(lock_key(rel), sha256_hex(&bytes));
+        }
+    }
+    Ok(PayloadLock { algorithm: "sha256".to_string(), files })
+}
+
+/// Fails listing every payload file that is missing or whose digest changed.
+pub fn verify_payload_lock(payload_root: &Path, lock: &PayloadLock) -> Result<()> {
+    if lock.algorithm != "sha256" {
+        return Err(anyhow!("Unsupported payload lock algorithm: {}", lock.algorithm));
+    }
+    let mut problems = Vec::new();
+    for (rel, expected) in &lock.files {
+        let path = payload_root.join(rel);
+        match fs::read(&path) {
+            Ok(bytes) if sha256_hex(&bytes) == *expected => {}
+            Ok(_) => problems.push(format!("{} (modified)", rel)),
+            Err(_) => problems.push(format!("{} (missing)", rel)),
+        }
+    }
+    if !problems.is_empty() {
+        return Err(MisfitError::new(ErrorCode::IntegrityFailed, format!("Payload integrity check failed: {}", problems.join(", "))).into());
+    }
+    Ok(())
+}
+
+pub const SIGNATURE_FILE: &str = "install.sig.json";
+/// Tag for the author's public key appended to the generated executable.
+pub const PUBLIC_KEY_TRAILER: &[u8; 8] = b"MSFTKEY1";
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct InstallSignature {
+    pub public_key: String,
+    pub signature: String,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+#[serde(tag = "status", rename_all = "camelCase")]
+pub enum SignatureStatus {
+    Unsigned,
+    Valid { fingerprint: String },
+    Invalid { reason: String },
+}
+
+fn b64() -> base64::engine::GeneralPurpose {
+    base64::engine::general_purpose::STANDARD
+}
+
+/// The signed message binds the manifest and payload lockfile together, followed by the
+/// bundle's other manifests, if any, by id.
+fn signing_message(manifest_dir: &Path) -> Result<Vec<u8>> {
+    let manifest = fs::read(bundle_manifest_path(manifest_dir, DEFAULT_MANIFEST_ID)?).context("Failed to read manifest for signing")?;
+    let lock = fs::read(manifest_dir.join(PAYLOAD_LOCK_FILE)).unwrap_or_default();
+    let mut message = format!("misfit-sig-v1\n{}\n{}\n", sha256_hex(&manifest), sha256_hex(&lock));
+    let others = bundle_manifest_files(manifest_dir)?.into_iter().filter(|path| bundle_manifest_id(path).is_some_and(|(id, _)| id != DEFAULT_MANIFEST_ID));
+    for path in others {
+        let extra = fs::read(&path).context("Failed to read manifest for signing")?;
+        message.push_str(&format!("{}\n{}\n", path.file_name().unwrap_or_default().to_string_lossy(), sha256_hex(&extra)));
+    }
+    Ok(message.into_bytes())
+}
+
+pub fn key_fingerprint(public_key: &[u8]) -> String {
+    sha256_hex(public_key)[..16].to_string()
+}
+
+/// Returns `(secret_key, public_key)`, both base64.
+pub fn generate_signing_key() -> Result<(String, String)> {
+    use base64::Engine as _;
+    let mut seed = [0u8; 32];
+    getrandom::getrandom(&mut seed).map_err(|e| anyhow!("Failed to generate key: {}", e))?;
+    let key = ed25519_dalek::SigningKey::from_bytes(&seed);
+    Ok((b64().encode(key.to_bytes()), b64().encode(key.verifying_key().to_bytes())))
+}
+
+/// Writes `contents` to a new file at `path` that only the current user can read: mode 0600 on
+/// Unix, an ACL granting only the current user on Windows. Fails if `path` exists.
+pub fn write_secret_file(path: &Path, contents: &[u8]) -> Result<()> {
+    use std::io::Write;
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    let mut file = options.open(path).map_err(|e| MisfitError::from(e).with_path(path))?;
+    #[cfg(windows)]
+    {
+        use std::os::windows::process::CommandExt;
+        // Drop the inherited entries so only the owner keeps access
+        let user = std::env::var("USERNAME").context("USERNAME is not set")?;
+        let status = Command::new("icacls")
+            .arg(path)
+            .args(["/inheritance:r", "/grant:r", &format!("{}:F", user)])
+            .stdout(std::process::Stdio::null())
+            .stderr(std::process::Stdio::null())
+            .creation_flags(CREATE_NO_WINDOW)
+            .status();
+        if !status.is_ok_and(|s| s.success()) {
+            drop(file);
+            let _ = fs::remove_file(path);
+            return Err(MisfitError::new(ErrorCode::Failed, "Failed to restrict access to the key file").with_path(path).into());
+        }
+    }
+    file.write_all(contents)?;
+    file.sync_all()?;
+    Ok(())
+}
+
+/// Signs the manifest + lockfile in `manifest_dir`, writes the signature file and returns
+/// the raw public key bytes.
+pub fn sign_install(manifest_dir: &Path, secret_key_b64: &str) -> Result<[u8; 32]> {
+    use base64::Engine as _;
+    use ed25519_dalek::Signer;
+    let secret: [u8; 32] = b64()
+        .decode(secret_key_b64.trim())
+        .context("Signing key is not valid base64")?
+        .try_into()
+        .map_err(|_| anyhow!("Signing key must be 32 bytes"))?;
+    let key = ed25519_dalek::SigningKey::from_bytes(&secret);
+    let signature = key.sign(&signing_message(manifest_dir)?);
+    let public_key = key.verifying_key().to_bytes();
+
+    let sig = InstallSignature {
+        public_key: b64().encode(public_key),
+        signature: b64().encode(signature.to_bytes()),
+    };
+    fs::write(manifest_dir.join(SIGNATURE_FILE), serde_json::to_string_pretty(&sig)?).context("Failed to write signature")?;
+    Ok(public_key)
+}
+
+/// Checks the signature file against `trusted_key` (the key embedded in the executable).
+/// Without a trusted key, the key stored next to the signature is used, which only proves
+/// the files are consistent with each other.
+pub fn verify_install_signature(manifest_dir: &Path, trusted_key: Option<&[u8]>) -> Result<SignatureStatus> {
+    use base64::Engine as _;
+    let sig_path = manifest_dir.join(SIGNATURE_FILE);
+    if !sig_path.exists() {
+        return Ok(match trusted_key {
+            Some(_) => SignatureStatus::Invalid { reason: "Signature file is missing".to_string() },
+            None => SignatureStatus::Unsigned,
+        });
+    }
+    let sig: InstallSignature = serde_json::from_str(&fs::read_to_string(&sig_path)?).context("Failed to parse signature file")?;
+    let stored_key = b64().decode(&sig.public_key).context("Invalid public key encoding")?;
+    if let Some(trusted) = trusted_key {
+        if trusted != stored_key.as_slice() {
+            return Ok(SignatureStatus::Invalid { reason: "Signed with an unexpected key".to_string() });
+        }
+    }
+
+    let key_bytes: [u8; 32] = stored_key.as_slice().try_into().map_err(|_| anyhow!("Public key must be 32 bytes"))?;
+    let key = ed25519_dalek::VerifyingKey::from_bytes(&key_bytes).context("Invalid public key")?;
+    let sig_bytes: [u8; 64] = b64()
+        .decode(&sig.signature)
+        .context("Invalid signature encoding")?
+        .try_into()
+        .map_err(|_| anyhow!("Signature must be 64 bytes"))?;
+    let signature = ed25519_dalek::Signature::from_bytes(&sig_bytes);
+
+    Ok(match key.verify_strict(&signing_message(manifest_dir)?, &signature) {
+        Ok(()) => SignatureStatus::Valid { fingerprint: key_fingerprint(&key_bytes) },
+        Err(_) => SignatureStatus::Invalid { reason: "Manifest or payload lockfile was modified after signing".to_string() },
+    })
+}
+
+/// Whether `manifest` is one of the manifests signed in `manifest_dir`, as written or with one
+/// of its profiles applied. A valid signature only vouches for the steps of those.
+pub fn is_signed_manifest(manifest: &InstallManifest, manifest_dir: &Path) -> Result<bool> {
+    let running = serde_json::to_value(manifest)?;
+    for path in bundle_manifest_files(manifest_dir)? {
+        let signed = load_manifest(&path)?;
+        let profiles = std::iter::once(None).chain(signed.profiles.keys().map(|name| Some(name.as_str())));
+        let mut variants = vec![signed.clone()];
+        for profile in profiles {
+            variants.push(apply_profile(&signed, profile)?);
+        }
+        if variants.iter().any(|variant| serde_json::to_value(variant).is_ok_and(|value| value == running)) {
+            return Ok(true);
+        }
+    }
+    Ok(false)
+}
+
+/// Appends `data` to the end of an executable as `[data][tag][len: u64 LE]`. Executable
+/// formats ignore trailing bytes, so the binary still runs.
+pub fn append_exe_trailer(exe: &Path, tag: &[u8; 8], data: &[u8]) -> Result<()> {
+    use std::io::Write;
+    let mut file = fs::OpenOptions::new().append(true).open(exe).context("Failed to open executable")?;
+    file.write_all(data)?;
+    file.write_all(tag)?;
+    file.write_all(&(data.len() as u64).to_le_bytes())?;
+    Ok(())
+}
+
+/// Walks trailers from the end of the file and returns the data for `tag`, if present.
+pub fn read_exe_trailer(exe: &Path, tag: &[u8; 8]) -> Result<Option<Vec<u8>>> {
+    use std::io::{Read, Seek, SeekFrom};
+    let Some((offset, len)) = exe_trailer_range(exe, tag)? else {
+        return Ok(None);
+    };
+    let mut file = fs::File::open(exe).context("Failed to open executable")?;
+    let mut data = vec![0u8; len as usize];
+    file.seek(SeekFrom::Start(offset))?;
+    file.read_exact(&mut data)?;
+    Ok(Some(data))
+}
+
+/// Returns the byte offset and length of the trailer data for `tag`.
+pub fn exe_trailer_range(exe: &Path, tag: &[u8; 8]) -> Result<Option<(u64, u64)>> {
+    use std::io::{Read, Seek, SeekFrom};
+    let mut file = fs::File::open(exe).context("Failed to open executable")?;
+    let Some(mut end) = trailers_end(&mut file)? else { return Ok(None) };
+    while end >= 16 {
+        let mut footer = [0u8; 16];
+        file.seek(SeekFrom::Start(end - 16))?;
+        file.read_exact(&mut footer)?;
+        let len = u64::from_le_bytes(footer[8..].try_into().unwrap_or_default());
+        if !footer[..8].starts_with(b"MSFT") || len > end - 16 {
+            return Ok(None);
+        }
+        let data_start = end - 16 - len;
+        if &footer[..8] == tag {
+            return Ok(Some((data_start, len)));
+        }
+        end = data_start;
+    }
+    Ok(None)
+}
+
+/// Where the trailers of `file` end: the end of the file, or for an Authenticode-signed
+/// executable, the start of the certificate table the signing tool appended after them (the
+/// table is 8-byte aligned, so up to 7 bytes of padding may sit in between).
+fn trailers_end(file: &mut fs::File) -> Result<Option<u64>> {
+    use std::io::{Read, Seek, SeekFrom};
+    let len = file.metadata()?.len();
+    let mut read_at = |offset: u64, buf: &mut [u8]| -> Result<bool> {
+        if offset + buf.len() as u64 > len {
+            return Ok(false);
+        }
+        file.seek(SeekFrom::Start(offset))?;
+        file.read_exact(buf)?;
+        Ok(true)
+    };
+
+    // DOS header -> PE header -> optional header -> data directory 4 (certificates)
+    let mut word = [0u8; 4];
+    if !read_at(0x3C, &mut word)? {
+        return Ok(Some(len));
+    }
+    let pe = u64::from(u32::from_le_bytes(word));
+    let mut magic = [0u8; 2];
+    if !read_at(pe, &mut word)? || &word != b"PE\0\0" || !read_at(pe + 24, &mut magic)? {
+        return Ok(Some(len));
+    }
+    let directories = match u16::from_le_bytes(magic) {
+        0x10b => pe + 24 + 96,
+        0x20b => pe + 24 + 112,
+        _ => return Ok(Some(len)),
+    };
+    let mut security = [0u8; 8];
+    if !read_at(directories + 4 * 8, &mut security)? {
+        return Ok(Some(len));
+    }
+    let table = u64::from(u32::from_le_bytes(security[..4].try_into().unwrap_or_default()));
+    let size = u64::from(u32::from_le_bytes(security[4..].try_into().unwrap_or_default()));
+    if table == 0 || table + size != len {
+        return Ok(Some(len));
+    }
+
+    let mut footer = [0u8; 4];
+    for padding in 0..8 {
+        let Some(end) = table.checked_sub(padding) else { break };
+        if end >= 16 && read_at(end - 16, &mut footer)? && &footer == b"MSFT" {
+            return Ok(Some(end));
+        }
+    }
+    Ok(None)
+}
+
+/// Cuts every trailer off `exe`, along with a signature appended after them, so new
+/// trailers don't pile up on those of the executable it was copied from.
+pub fn strip_exe_trailers(exe: &Path) -> Result<()> {
+    use std::io::{Read, Seek, SeekFrom};
+    let mut file = fs::OpenOptions::new().read(true).write(true).open(exe).context("Failed to open executable")?;
+    let Some(trailers_end) = trailers_end(&mut file)? else { return Ok(()) };
+    let mut end = trailers_end;
+    while end >= 16 {
+        let mut footer = [0u8; 16];
+        file.seek(SeekFrom::Start(end - 16))?;
+        file.read_exact(&mut footer)?;
+        let len = u64::from_le_bytes(footer[8..].try_into().unwrap_or_default());
+        if !footer[..8].starts_with(b"MSFT") || len > end - 16 {
+            break;
+        }
+        end -= 16 + len;
+    }
+    if end < trailers_end {
+        file.set_len(end).context("Failed to strip executable trailers")?;
+    }
+    Ok(())
+}
+
+/// Appends a whole file as a trailer, streaming so large payloads never sit in memory.
+pub fn append_exe_trailer_file(exe: &Path, tag: &[u8; 8], data_file: &Path) -> Result<()> {
+    use std::io::Write;
+    let mut out = fs::OpenOptions::new().append(true).open(exe).context("Failed to open executable")?;
+    let len = std::io::copy(&mut fs::File::open(data_file)?, &mut out)?;
+    out.write_all(tag)?;
+    out.write_all(&len.to_le_bytes())?;
+    Ok(())
+}
+
+/// Tag for the zipped manifest + payload bundle appended to single-file installers.
+pub const PAYLOAD_TRAILER: &[u8; 8] = b"MSFTPAY1";
+
+/// Extracts the embedded bundle from `exe` into `dest`. Returns `false` when the
+/// executable carries no bundle.
+pub fn extract_embedded_bundle(exe: &Path, dest: &Path) -> Result<bool> {
+    use std::io::{Read, Seek, SeekFrom};
+    let Some((offset, len)) = exe_trailer_range(exe, PAYLOAD_TRAILER)? else {
+        return Ok(false);
+    };
+    fs::create_dir_all(dest)?;
+    let zip_path = dest.join(".bundle.zip");
+    let mut file = fs::File::open(exe)?;
+    file.seek(SeekFrom::Start(offset))?;
+    std::io::copy(&mut file.take(len), &mut fs::File::create(&zip_path)?)?;
+    let extracted = extract_payload_archive(&zip_path, dest);
+    let _ = fs::remove_file(&zip_path);
+    extracted?;
+    Ok(true)
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum PayloadCompression {
+    #[default]
+    #[serde(rename = "none")]
+    None,
+    #[serde(rename = "zip")]
+    Zip,
+    #[serde(rename = "tar.zst")]
+    TarZst,
+}
+
+impl PayloadCompression {
+    pub fn archive_name(self) -> Option<&'static str> {
+        match self {
+            PayloadCompression::None => None,
+            PayloadCompression::Zip => Some("payloads.zip"),
+            PayloadCompression::TarZst => Some("payloads.tar.zst"),
+        }
+    }
+}
+
+/// Packs every file under `entries` (relative to `payload_root`) into one archive; symlinks are
+/// stored as links, like copy steps recreate them. With `dedupe`, files with the same contents
+/// are stored once and restored on extraction.
+pub fn create_payload_archive(payload_root: &Path, entries: &[PathBuf], dest: &Path, format: PayloadCompression, dedupe: bool) -> Result<()> {
+    let mut files = Vec::new();
+    let mut symlinks = BTreeSet::new();
+    for entry in entries {
+        for item in walkdir::WalkDir::new(payload_root.join(entry)) {
+            let item = item?;
+            if item.file_type().is_file() || item.file_type().is_symlink() {
+                let rel = item.path().strip_prefix(payload_root).context("Payload escaped payload root")?;
+                if item.file_type().is_symlink() {
+                    symlinks.insert(lock_key(rel));
+                }
+                files.push((lock_key(rel), item.path().to_path_buf()));
+            }
+        }
+    }
+
+    let links = if dedupe {
+        let regular: Vec<(String, PathBuf)> = files.iter().filter(|(name, _)| !symlinks.contains(name)).cloned().collect();
+        analyze_payload_files(&regular)?.links()
+    } else {
+        BTreeMap::new()
+    };
+    let links_path = std::env::temp_dir().join(format!("misfit_links_{}.json", &sha256_hex(dest.to_string_lossy().as_bytes())[..16]));
+    if !links.is_empty() {
+        files.retain(|(name, _)| !links.contains_key(name));
+        fs::write(&links_path, serde_json::to_string_pretty(&links)?)?;
+        files.push((PAYLOAD_LINKS_FILE.to_string(), links_path.clone()));
+    }
+    let written = write_payload_archive(dest, &files, format);
+    if !links.is_empty() {
+        let _ = fs::remove_file(&links_path);
+    }
+    written
+}
+
+fn write_payload_archive(dest: &Path, files: &[(String, PathBuf)], format: PayloadCompression) -> Result<()> {
+    let out = fs::File::create(dest).with_context(|| format!("Failed to create {}", dest.display()))?;
+    match format {
+        PayloadCompression::None => return Err(anyhow!("No archive format selected")),
+        PayloadCompression::Zip => zip_files(out, files)?,
+        PayloadCompression::TarZst => {
+            let encoder = zstd::Encoder::new(out, 0)?.auto_finish();
+            let mut tar = tar::Builder::new(encoder);
+            tar.follow_symlinks(false);
+            for (name, path) in files {
+                tar.append_path_with_name(path, name)?;
+            }
+            tar.into_inner()?;
+        }
+    }
+    Ok(())
+}
+
+fn zip_files(out: fs::File, files: &[(String, PathBuf)]) -> Result<()> {
+    let mut zip = zip::ZipWriter::new(out);
+    let options = zip::write::SimpleFileOptions::default()
+        .compression_method(zip::CompressionMethod::Deflated)
+        .large_file(true);
+    for (name, path) in files {
+        if fs::symlink_metadata(path).is_ok_and(|meta| meta.file_type().is_symlink()) {
+            let target = fs::read_link(path).with_context(|| format!("Failed to read {}", path.display()))?;
+            zip.add_symlink(name.as_str(), target.to_string_lossy().replace('\\', "/"), options)?;
+            continue;
+        }
+        let file = fs::File::open(path).with_context(|| format!("Failed to read {}", path.display()))?;
+        // Keeps executables runnable after extraction
+        #[cfg(unix)]
+        let options = {
+            use std::os::unix::fs::PermissionsExt;
+            options.unix_permissions(file.metadata()?.permissions().mode())
+        };
+        zip.start_file(name.as_str(), options)?;
+        std::io::copy(&mut &file, &mut zip)?;
+    }
+    zip.finish()?;
+    Ok(())
+}
+
+/// Writes `(name, file on disk)` entries to a new zip at `dest`, streaming each file.
+pub fn write_zip_files(dest: &Path, files: &[(String, PathBuf)]) -> Result<()> {
+    let out = fs::File::create(dest).with_context(|| format!("Failed to create {}", dest.display()))?;
+    zip_files(out, files)
+}
+
+/// Writes in-memory `(name, contents)` entries to a new zip at `dest`.
+pub fn write_zip(dest: &Path, entries: &[(String, Vec<u8>)]) -> Result<()> {
+    let out = fs::File::create(dest).with_context(|| format!("Failed to create {}", dest.display()))?;
+    let mut zip = zip::ZipWriter::new(out);
+    let options = zip::write::SimpleFileOptions::default().compression_method(zip::CompressionMethod::Deflated);
+    for (name, contents) in entries {
+        zip.start_file(name.as_str(), options)?;
+        std::io::Write::write_all(&mut zip, contents)?;
+    }
+    zip.finish()?;
+    Ok(())
+}
+
+/// Extracts a payload archive created by `create_payload_archive`. Entries that would
+/// escape `dest` are rejected.
+pub fn extract_payload_archive(archive: &Path, dest: &Path) -> Result<()> {
+    fs::create_dir_all(dest)?;
+    let file = fs::File::open(archive).with_context(|| format!("Failed to open {}", archive.display()))?;
+    let name = archive.file_name().unwrap_or_default().to_string_lossy().to_lowercase();
+    if name.ends_with(".zip") {
+        unzip(file, dest)?;
+    } else if name.ends_with(".tar.zst") {
+        let decoder = zstd::Decoder::new(file)?;
+        let mut tar = tar::Archive::new(decoder);
+        for entry in tar.entries()? {
+            let mut entry = entry?;
+            // unpack_in refuses paths containing `..` or roots
+            if !entry.unpack_in(dest)? {
+                return Err(anyhow!("Unsafe path in archive: {}", entry.path()?.display()));
+            }
+        }
+    } else {
+        return Err(anyhow!("Unsupported payload archive: {}", archive.display()));
+    }
+    restore_payload_links(dest)
+}
+
+/// Extracts any zip, whatever its extension, into `dest`. Entries that would escape `dest`
+/// are rejected.
+pub fn extract_zip(archive: &Path, dest: &Path) -> Result<()> {
+    fs::create_dir_all(dest)?;
+    let file = fs::File::open(archive).with_context(|| format!("Failed to open {}", archive.display()))?;
+    unzip(file, dest)
+}
+
+fn unzip(file: fs::File, dest: &Path) -> Result<()> {
+    let mut zip = zip::ZipArchive::new(file).context("Invalid zip archive")?;
+    // Links are made last, so no entry is written through one
+    let mut symlinks = Vec::new();
+    for i in 0..zip.len() {
+        let mut entry = zip.by_index(i)?;
+        let rel = entry.enclosed_name().ok_or_else(|| anyhow!("Unsafe path in archive: {}", entry.name()))?;
+        let out_path = dest.join(rel);
+        if entry.is_dir() {
+            fs::create_dir_all(&out_path)?;
+            continue;
+        }
+        if let Some(parent) = out_path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        if entry.is_symlink() {
+            let mut target = String::new();
+            std::io::Read::read_to_string(&mut entry, &mut target)?;
+            symlinks.push((out_path, PathBuf::from(target)));
+            continue;
+        }
+        std::io::copy(&mut entry, &mut fs::File::create(&out_path)?)?;
+        // Keeps executables runnable, as packed by `zip_files`
+        #[cfg(unix)]
+        if let Some(mode) = entry.unix_mode() {
+            use std::os::unix::fs::PermissionsExt;
+            fs::set_permissions(&out_path, fs::Permissions::from_mode(mode & 0o7777))?;
+        }
+    }
+    for (link, target) in symlinks {
+        create_symlink(&target, &link).with_context(|| format!("Failed to create link {}", link.display()))?;
+    }
+    Ok(())
+}
+
+fn create_symlink(target: &Path, link: &Path) -> std::io::Result<()> {
+    remove_dest_symlink(link)?;
+    #[cfg(unix)]
+    return std::os::unix::fs::symlink(target, link);
+    #[cfg(windows)]
+    {
+        let resolved = link.parent().unwrap_or(Path::new(".")).join(target);
+        let linked = if resolved.is_dir() {
+            std::os::windows::fs::symlink_dir(target, link)
+        } else {
+            std::os::windows::fs::symlink_file(target, link)
+        };
+        // Creating symlinks needs extra privileges on Windows; fall back to copying files.
+        match linked {
+            Err(_) if resolved.is_file() => fs::copy(&resolved, link).map(|_| ()),
+            linked => linked,
+        }
+    }
+    #[cfg(not(any(unix, windows)))]
+    Err(std::io::ErrorKind::Unsupported.into())
+}
+
+/// Copies `src` into `dest` under `policy`; with `Prompt`, `observer` decides about the files
+/// that already exist, as for a Copy step.
+pub fn copy_payload(src: &Path, dest: &Path, policy: OverwritePolicy, observer: &dyn InstallObserver) -> Result<()> {
+    let filtered = apply_overwrite_policy(plan_copy(src, dest, &[])?, policy)?;
+    copy_plan_parallel(&confirm_conflicts(filtered, observer), &|_| {})
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "camelCase")]
+pub enum OverwritePolicy {
+    #[default]
+    Always,
+    Never,
+    IfNewer,
+    Prompt,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct OverwriteFilter {
+    /// Jobs that should be copied without asking.
+    pub plan: CopyPlan,
+    pub skipped: Vec<CopyJob>,
+    /// Existing destinations awaiting a decision (only for `Prompt`).
+    pub conflicts: Vec<CopyJob>,
+}
+
+pub fn apply_overwrite_policy(plan: CopyPlan, policy: OverwritePolicy) -> Result<OverwriteFilter> {
+    let mut filter = OverwriteFilter {
+        plan: CopyPlan { dirs: plan.dirs, files: Vec::new() },
+        ..OverwriteFilter::default()
+    };
+    for job in plan.files {
+        let existing = match fs::metadata(&job.dest) {
+            Ok(meta) => meta,
+            Err(_) => {
+                filter.plan.files.push(job);
+                continue;
+            }
+        };
+        match policy {
+            OverwritePolicy::Always => filter.plan.files.push(job),
+            OverwritePolicy::Never => filter.skipped.push(job),
+            OverwritePolicy::Prompt => filter.conflicts.push(job),
+            OverwritePolicy::IfNewer => {
+                let src_time = fs::metadata(&job.src)?.modified()?;
+                if existing.modified().map_or(true, |dest_time| src_time > dest_time) {
+                    filter.plan.files.push(job);
+                } else {
+                    filter.skipped.push(job);
+                }
+            }
+        }
+    }
+    Ok(filter)
+}
+
+/// The jobs of `filtered` to run: its plan, plus the conflicts if `observer` agrees to
+/// overwrite them.
+pub fn confirm_conflicts(filtered: OverwriteFilter, observer: &dyn InstallObserver) -> CopyPlan {
+    let mut plan = filtered.plan;
+    if !filtered.conflicts.is_empty() {
+        let files: Vec<String> = filtered.conflicts.iter().map(|j| j.dest.to_string_lossy().to_string()).collect();
+        if observer.confirm_overwrite(&files) {
+            plan.files.extend(filtered.conflicts);
+        } else {
+            observer.log(&format!("Keeping {} existing file(s)", filtered.conflicts.len()));
+        }
+    }
+    if !filtered.skipped.is_empty() {
+        observer.log(&format!("Skipped {} existing file(s)", filtered.skipped.len()));
+    }
+    plan
+}
+
+/// Keeps the jobs whose destination is missing or out of date. Copies keep their source's
+/// modification time, so a destination matching its source in size and time is up to date.
+pub fn stale_jobs(plan: CopyPlan) -> CopyPlan {
+    let up_to_date = |job: &CopyJob| match &job.link {
+        Some(target) => fs::read_link(&job.dest).is_ok_and(|current| current == *target),
+        None => match (fs::metadata(&job.src), fs::symlink_metadata(&job.dest)) {
+            (Ok(src), Ok(dest)) => {
+                dest.is_file() && src.len() == dest.len() && src.modified().ok().is_some_and(|time| dest.modified().ok() == Some(time))
+            }
+            _ => false,
+        },
+    };
+    CopyPlan { dirs: plan.dirs, files: plan.files.into_iter().filter(|job| !up_to_date(job)).collect() }
+}
+
+pub fn has_glob_meta(path: &str) -> bool {
+    path.contains(['*', '?', '['])
+}
+
+/// Splits a glob into its literal leading directory and the pattern below it
+/// (forward-slash separated), e.g. `themes/**/*.json` -> (`themes`, `**/*.json`).
+pub fn split_glob(pattern: &str) -> (PathBuf, Option<String>) {
+    let normalized = pattern.replace('\\', "/");
+    let mut base = PathBuf::new();
+    let mut rest: Vec<&str> = Vec::new();
+    for (idx, part) in normalized.split('/').enumerate() {
+        if !rest.is_empty() || has_glob_meta(part) {
+            rest.push(part);
+        } else if idx == 0 && part.is_empty() {
+            base.push("/");
+        } else {
+            base.push(part);
+        }
+    }
+    if rest.is_empty() {
+        (base, None)
+    } else {
+        (base, Some(rest.join("/")))
+    }
+}
+
+fn compile_patterns(patterns: &[String]) -> Result<Vec<glob::Pattern>> {
+    patterns
+        .iter()
+        .map(|p| glob::Pattern::new(p).with_context(|| format!("Invalid glob pattern: {}", p)))
+        .collect()
+}
+
+#[derive(Debug, Clone)]
+pub struct CopyJob {
+    pub src: PathBuf,
+    pub dest: PathBuf,
+    pub size: u64,
+    /// Target of `src` when it is a symlink; the link is recreated instead of followed.
+    pub link: Option<PathBuf>,
+}
+
+/// Every directory to create and file to copy for one or more payload copies, so
+/// several sources can share one worker pool and one progress total.
+#[derive(Debug, Clone, Default)]
+pub struct CopyPlan {
+    pub dirs: Vec<PathBuf>,
+    pub files: Vec<CopyJob>,
+}
+
+impl CopyPlan {
+    pub fn extend(&mut self, other: CopyPlan) {
+        self.dirs.extend(other.dirs);
+        self.files.extend(other.files);
+    }
+
+    pub fn total_bytes(&self) -> u64 {
+        self.files.iter().map(|f| f.size).sum()
+    }
+}
+
+#[derive(Serialize, Debug, Clone, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct CopyProgress {
+    pub bytes_copied: u64,
+    pub total_bytes: u64,
+    pub files_copied: usize,
+    pub total_files: usize,
+    /// Destination of the file that just finished.
+    pub current_file: String,
+}
+
+/// Digests of a copy plan's source files, taken before copying so a build can tell whether
+/// an editor or watcher changed them underneath it.
+#[derive(Debug, Clone, Default)]
+pub struct SourceSnapshot {
+    files: BTreeMap<PathBuf, String>,
+}
+
+impl SourceSnapshot {
+    pub fn len(&self) -> usize {
+        self.files.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.files.is_empty()
+    }
+}
+
+/// Hashes every regular file `plan` copies from. Symlinks are recreated rather than read, so
+/// they are left out.
+pub fn snapshot_sources(plan: &CopyPlan) -> Result<SourceSnapshot> {
+    let mut files = BTreeMap::new();
+    for job in plan.files.iter().filter(|job| job.link.is_none()) {
+        let bytes = fs::read(&job.src).with_context(|| format!("Failed to hash {}", job.src.display()))?;
+        files.insert(job.src.clone(), sha256_hex(&bytes));
+    }
+    Ok(SourceSnapshot { files })
+}
+
+/// Fails with [`ErrorCode::SourcesChanged`], naming every file that was modified or removed
+/// since `snapshot` was taken.
+pub fn verify_sources(snapshot: &SourceSnapshot) -> Result<()> {
+    let mut changed = Vec::new();
+    for (path, expected) in &snapshot.files {
+        match fs::read(path) {
+            Ok(bytes) if sha256_hex(&bytes) == *expected => {}
+            Ok(_) => changed.push(format!("{} (modified)", path.display())),
+            Err(_) => changed.push(format!("{} (missing)", path.display())),
+        }
+    }
+    if !changed.is_empty() {
+        let message = format!("Payload sources changed during the build: {}", changed.join(", "));
+        return Err(MisfitError::new(ErrorCode::SourcesChanged, message).into());
+    }
+    Ok(())
+}
+
+/// Asks a long-running operation to stop. Clones share one flag, so the token handed to the
+/// work can be cancelled from another thread.
+#[derive(Debug, Clone, Default)]
+pub struct CancelToken(std::sync::Arc<std::sync::atomic::AtomicBool>);
+
+impl CancelToken {
+    pub fn cancel(&self) {
+        self.0.store(true, std::sync::atomic::Ordering::Relaxed);
+    }
+
+    pub fn reset(&self) {
+        self.0.store(false, std::sync::atomic::Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(std::sync::atomic::Ordering::Relaxed)
+    }
+
+    /// Fails with [`ErrorCode::Cancelled`] once [`cancel`](Self::cancel) has been called.
+    pub fn check(&self) -> std::result::Result<(), MisfitError> {
+        if self.is_cancelled() {
+            return Err(MisfitError::new(ErrorCode::Cancelled, "Cancelled"));
+        }
+        Ok(())
+    }
+}
+
+/// Plans copying `src` (file or folder) to `dest`, skipping paths below `src` that match
+/// any `exclude` glob.
+pub fn plan_copy(src: &Path, dest: &Path, exclude: &[String]) -> Result<CopyPlan> {
+    plan_copy_matching(src, None, dest, exclude)
+}
+
+/// Plans copying every file under `base` whose relative path matches `pattern`; matches
+/// keep their path relative to `base` inside `dest`.
+pub fn plan_copy_glob(base: &Path, pattern: &str, dest: &Path, exclude: &[String]) -> Result<CopyPlan> {
+    let pattern = glob::Pattern::new(pattern).with_context(|| format!("Invalid glob pattern: {}", pattern))?;
+    let plan = plan_copy_matching(base, Some(&pattern), dest, exclude)?;
+    if plan.files.is_empty() {
+        return Err(anyhow!("No files match {} in {}", pattern, base.display()));
+    }
+    Ok(plan)
+}
+
+fn plan_copy_matching(src: &Path, include: Option<&glob::Pattern>, dest: &Path, exclude: &[String]) -> Result<CopyPlan> {
+    let exclude = compile_patterns(exclude)?;
+    let mut plan = CopyPlan::default();
+    if !src.is_dir() {
+        let size = fs::metadata(src).with_context(|| format!("Failed to read {}", src.display()))?.len();
+        plan.files.push(CopyJob { src: src.to_path_buf(), dest: dest.to_path_buf(), size, link: None });
+        return Ok(plan);
+    }
+    // Symlinks are never followed, but junctions and bind mounts can still lead back into
+    // a folder already being walked, so each directory is only entered once.
+    let mut visited: HashSet<PathBuf> = HashSet::new();
+    let mut walker = walkdir::WalkDir::new(src).follow_links(false).into_iter();
+    while let Some(entry) = walker.next() {
+        let entry = entry?;
+        let rel = entry.path().strip_prefix(src).context("Walk escaped copy source")?;
+        let key = lock_key(rel);
+        if !key.is_empty() && exclude.iter().any(|p| p.matches(&key)) {
+            if entry.file_type().is_dir() {
+                walker.skip_current_dir();
+            }
+            continue;
+        }
+        let target = dest.join(rel);
+        if entry.file_type().is_dir() {
+            let canonical = fs::canonicalize(entry.path()).unwrap_or_else(|_| entry.path().to_path_buf());
+            if !visited.insert(canonical) {
+                log::warn!("Skipping {}: directory cycle", entry.path().display());
+                walker.skip_current_dir();
+                continue;
+            }
+            // With an include pattern only folders that end up holding files are created.
+            if include.is_none() {
+                plan.dirs.push(target);
+            }
+        } else if include.map_or(true, |p| p.matches(&key)) {
+            let link = if entry.file_type().is_symlink() { Some(fs::read_link(entry.path())?) } else { None };
+            let size = if link.is_some() { 0 } else { entry.metadata()?.len() };
+            plan.files.push(CopyJob { src: entry.path().to_path_buf(), dest: target, size, link });
+        }
+    }
+    Ok(plan)
+}
+
+/// Copies the plan's files on a small worker pool fed through a bounded queue, reporting
+/// aggregate progress after each file. Stops at the first error.
+pub fn copy_plan_parallel(plan: &CopyPlan, on_progress: &(dyn Fn(&CopyProgress) + Sync)) -> Result<()> {
+    copy_plan_cancellable(plan, on_progress, &CancelToken::default())
+}
+
+/// [`copy_plan_parallel`] that stops once `cancel` is set. Files already being copied are
+/// finished; the rest are skipped and the copy fails with [`ErrorCode::Cancelled`].
+pub fn copy_plan_cancellable(plan: &CopyPlan, on_progress: &(dyn Fn(&CopyProgress) + Sync), cancel: &CancelToken) -> Result<()> {
+    copy_plan_jobs(plan, on_progress, cancel, false).map(drop)
+}
+
+/// [`copy_plan_parallel`] that carries on past regular files that stay locked and returns
+/// them, so the caller can deal with just those.
+pub fn copy_plan_skipping_locked<'a>(plan: &'a CopyPlan, on_progress: &(dyn Fn(&CopyProgress) + Sync)) -> Result<Vec<&'a CopyJob>> {
+    copy_plan_jobs(plan, on_progress, &CancelToken::default(), true)
+}
+
+fn copy_plan_jobs<'a>(
+    plan: &'a CopyPlan,
+    on_progress: &(dyn Fn(&CopyProgress) + Sync),
+    cancel: &CancelToken,
+    skip_locked: bool,
+) -> Result<Vec<&'a CopyJob>> {
+    use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+    use std::sync::{mpsc, Mutex};
+
+    for dir in &plan.dirs {
+        fs::create_dir_all(dir)?;
+    }
+    for job in &plan.files {
+        if let Some(parent) = job.dest.parent() {
+            fs::create_dir_all(parent)?;
+        }
+    }
+
+    let total_bytes = plan.total_bytes();
+    let total_files = plan.files.len();
+    let bytes_copied = AtomicU64::new(0);
+    let files_copied = AtomicUsize::new(0);
+    let failed = AtomicBool::new(false);
+    let first_error: Mutex<Option<anyhow::Error>> = Mutex::new(None);
+    let locked: Mutex<Vec<&CopyJob>> = Mutex::new(Vec::new());
+    let workers = std::thread::available_parallelism().map(|n| n.get()).unwrap_or(4).clamp(1, 8);
+    let (tx, rx) = mpsc::sync_channel::<&CopyJob>(workers * 4);
+    let rx = Mutex::new(rx);
+
+    std::thread::scope(|scope| {
+        for _ in 0..workers {
+            scope.spawn(|| loop {
+                let job = match rx.lock() {
+                    Ok(guard) => match guard.recv() {
+                        Ok(job) => job,
+                        Err(_) => break,
+                    },
+                    Err(_) => break,
+                };
+                if failed.load(Ordering::Relaxed) || cancel.is_cancelled() {
+                    continue;
+                }
+                match copy_job(job) {
+                    Ok(_) => {
+                        let progress = CopyProgress {
+                            bytes_copied: bytes_copied.fetch_add(job.size, Ordering::Relaxed) + job.size,
+                            total_bytes,
+                            files_copied: files_copied.fetch_add(1, Ordering::Relaxed) + 1,
+                            total_files,
+                            current_file: job.dest.to_string_lossy().to_string(),
+                        };
+                        on_progress(&progress);
+                    }
+                    Err(err) if skip_locked && is_lock_error(&err) && job.link.is_none() => {
+                        if let Ok(mut jobs) = locked.lock() {
+                            jobs.push(job);
+                        }
+                    }
+                    Err(err) => {
+                        failed.store(true, Ordering::Relaxed);
+                        if let Ok(mut slot) = first_error.lock() {
+                            slot.get_or_insert_with(|| anyhow::Error::new(err).context(format!("Failed to copy {}", job.src.display())));
+                        }
+                    }
+                }
+            });
+        }
+        for job in &plan.files {
+            if failed.load(Ordering::Relaxed) || cancel.is_cancelled() || tx.send(job).is_err() {
+                break;
+            }
+        }
+        drop(tx);
+    });
+
+    match first_error.into_inner() {
+        Ok(Some(err)) => Err(err),
+        _ => {
+            cancel.check()?;
+            Ok(locked.into_inner().unwrap_or_default())
+        }
+    }
+}
+
+/// Replaces `path` with `contents` without ever leaving it half-written: the contents go to a
+/// temporary file in the same folder, which is flushed and renamed over the original. The
+/// original's permissions (and owner on Unix) are kept; a symlink is written through to its target.
+pub fn write_atomic(path: &Path, contents: &[u8]) -> std::io::Result<()> {
+    use std::io::Write;
+    let path = match fs::symlink_metadata(path) {
+        Ok(meta) if meta.file_type().is_symlink() => fs::canonicalize(path)?,
+        _ => path.to_path_buf(),
+    };
+    let name = path.file_name().ok_or_else(|| std::io::Error::new(std::io::ErrorKind::InvalidInput, "not a file path"))?;
+    let mut temp_name = std::ffi::OsString::from(".");
+    temp_name.push(name);
+    temp_name.push(format!(".misfit-{}.tmp", std::process::id()));
+    let temp = path.with_file_name(temp_name);
+
+    let original = fs::metadata(&path).ok();
+    let written = (|| {
+        let mut file = fs::File::create(&temp)?;
+        file.write_all(contents)?;
+        if let Some(meta) = &original {
+            #[cfg(unix)]
+            {
+                use std::os::unix::fs::MetadataExt;
+                // Only root can hand a file to another owner; anyone else keeps their own
+                let _ = std::os::unix::fs::chown(&temp, Some(meta.uid()), Some(meta.gid()));
+            }
+            fs::set_permissions(&temp, meta.permissions())?;
+        }
+        file.sync_all()?;
+        drop(file);
+        retry_if_locked(|| fs::rename(&temp, &path))
+    })();
+    if written.is_err() {
+        let _ = fs::remove_file(&temp);
+    }
+    written
+}
+
+/// Waits between attempts at a file another process holds open; about 4 s in total.
+const LOCK_RETRY_DELAYS_MS: [u64; 5] = [100, 250, 500, 1000, 2000];
+
+/// Whether `err` means another process holds the file open: a sharing or lock violation on
+/// Windows, a busy file or running executable on Unix.
+pub fn is_lock_error(err: &std::io::Error) -> bool {
+    #[cfg(windows)]
+    {
+        // ERROR_SHARING_VIOLATION / ERROR_LOCK_VIOLATION
+        matches!(err.raw_os_error(), Some(32 | 33))
+    }
+    #[cfg(unix)]
+    {
+        matches!(err.raw_os_error(), Some(libc::EBUSY | libc::ETXTBSY))
+    }
+    #[cfg(not(any(unix, windows)))]
+    {
+        let _ = err;
+        false
+    }
+}
+
+/// Runs `op`, trying again with growing delays while the file is locked (the target app or
+/// an antivirus scanner holding it). The last error is returned when it stays locked.
+pub fn retry_if_locked<T>(mut op: impl FnMut() -> std::io::Result<T>) -> std::io::Result<T> {
+    for delay in LOCK_RETRY_DELAYS_MS {
+        match op() {
+            Err(err) if is_lock_error(&err) => std::thread::sleep(std::time::Duration::from_millis(delay)),
+            result => return result,
+        }
+    }
+    op()
+}
+
+/// Arranges for `dest` to be replaced with `src` when Windows next starts, for a file that
+/// stays locked. `src` is staged next to `dest` as `<name>.misfit-reboot`, which is returned.
+/// Registering the replacement needs administrator rights; other platforms don't support it.
+pub fn replace_on_reboot(src: &Path, dest: &Path) -> Result<PathBuf> {
+    let mut staged = dest.as_os_str().to_owned();
+    staged.push(".misfit-reboot");
+    let staged = PathBuf::from(staged);
+    #[cfg(windows)]
+    {
+        use std::os::windows::ffi::OsStrExt;
+        use windows_sys::Win32::Storage::FileSystem::{MoveFileExW, MOVEFILE_DELAY_UNTIL_REBOOT, MOVEFILE_REPLACE_EXISTING};
+
+        fs::copy(src, &staged).map_err(|e| MisfitError::from(e).with_path(&staged))?;
+        let wide = |p: &Path| p.as_os_str().encode_wide().chain(Some(0)).collect::<Vec<u16>>();
+        let (from, to) = (wide(&staged), wide(dest));
+        // SAFETY: both paths are NUL-terminated UTF-16 buffers that outlive the call.
+        let registered = unsafe { MoveFileExW(from.as_ptr(), to.as_ptr(), MOVEFILE_DELAY_UNTIL_REBOOT | MOVEFILE_REPLACE_EXISTING) };
+        if registered == 0 {
+            let err = std::io::Error::last_os_error();
+            let _ = fs::remove_file(&staged);
+            return Err(MisfitError::from(err).with_path(dest).into());
+        }
+        Ok(staged)
+    }
+    #[cfg(not(windows))]
+    {
+        let _ = (src, staged);
+        Err(MisfitError::new(ErrorCode::FileLocked, "Replacing files at restart is only supported on Windows").with_path(dest).into())
+    }
+}
+
+fn copy_job(job: &CopyJob) -> std::io::Result<()> {
+    match &job.link {
+        Some(target) => copy_symlink(&job.src, target, &job.dest),
+        None => copy_file_preserving(&job.src, &job.dest),
+    }
+}
+
+/// Copies a file keeping its permissions (via `fs::copy`) and its access/modification
+/// times. A symlink sitting at `dest` is replaced rather than written through.
+fn copy_file_preserving(src: &Path, dest: &Path) -> std::io::Result<()> {
+    remove_dest_symlink(dest)?;
+    retry_if_locked(|| fs::copy(src, dest))?;
+    let meta = fs::metadata(src)?;
+    filetime::set_file_times(
+        dest,
+        filetime::FileTime::from_last_access_time(&meta),
+        filetime::FileTime::from_last_modification_time(&meta),
+    )
+}
+
+fn remove_dest_symlink(dest: &Path) -> std::io::Result<()> {
+    match fs::symlink_metadata(dest) {
+        Ok(meta) if meta.file_type().is_symlink() => fs::remove_file(dest),
+        _ => Ok(()),
+    }
+}
+
+fn copy_symlink(src: &Path, target: &Path, dest: &Path) -> std::io::Result<()> {
+    match fs::symlink_metadata(dest) {
+        Ok(meta) if !meta.is_dir() => fs::remove_file(dest)?,
+        _ => {}
+    }
+    #[cfg(unix)]
+    let linked = std::os::unix::fs::symlink(target, dest);
+    #[cfg(windows)]
+    let linked = if src.is_dir() {
+        std::os::windows::fs::symlink_dir(target, dest)
+    } else {
+        std::os::windows::fs::symlink_file(target, dest)
+    };
+    #[cfg(not(any(unix, windows)))]
+    let linked: std::io::Result<()> = Err(std::io::ErrorKind::Unsupported.into());
+    match linked {
+        Ok(()) => {
+            let meta = fs::symlink_metadata(src)?;
+            filetime::set_symlink_file_times(
+                dest,
+                filetime::FileTime::from_last_access_time(&meta),
+                filetime::FileTime::from_last_modification_time(&meta),
+            )
+        }
+        // Creating symlinks needs extra privileges on Windows; fall back to copying files.
+        Err(_) if src.is_file() => copy_file_preserving(src, dest),
+        Err(err) => Err(err),
+    }
+}
+
+/// How `startMarker` / `endMarker` are located in the target file.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "camelCase")]
+pub enum MarkerMode {
+    #[default]
+    Exact,
+    Regex,
+    /// Markers are anchor lines matched by similarity; the marker line sits
+    /// `startOffset` / `endOffset` lines away from its anchor.
+    Anchor,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct MarkerMatch {
+    pub mode: MarkerMode,
+    #[serde(default)]
+    pub start_offset: isize,
+    #[serde(default)]
+    pub end_offset: isize,
+    /// Minimum line similarity (0.0-1.0) for anchor matching. Defaults to an exact line match.
+    #[serde(default)]
+    pub similarity: Option<f64>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct PatchSpec {
+    pub start_marker: String,
+    pub end_marker: String,
+    pub content: String,
+    pub strip_markers: bool,
+    pub patch_id: Option<String>,
+    pub matching: MarkerMatch,
+    pub insert_if_missing: Option<InsertAnchor>,
+    /// What an earlier install put in place of this block when it stripped the markers, from
+    /// the receipt. Without markers the block only counts as applied if that is `content`.
+    pub stripped_content: Option<String>,
+}
+
+/// Byte offsets of a located block: `start..end` covers both markers,
+/// `inner_start..inner_end` the text between them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct BlockSpan {
+    start: usize,
+    inner_start: usize,
+    inner_end: usize,
+    end: usize,
+}
+
+pub fn find_bytes(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    if needle.is_empty() {
+        return Some(0);
+    }
+    haystack.windows(needle.len()).position(|w| w == needle)
+}
+
+/// Replaces occurrences of `needle` (at most `limit` when given), returning the new
+/// buffer and the number of replacements made.
+pub fn replace_bytes(haystack: &[u8], needle: &[u8], replacement: &[u8], limit: Option<usize>) -> (Vec<u8>, usize) {
+    if needle.is_empty() {
+        return (haystack.to_vec(), 0);
+    }
+    let mut out = Vec::with_capacity(haystack.len());
+    let mut count = 0;
+    let mut rest = haystack;
+    while limit.map_or(true, |max| count < max) {
+        let Some(idx) = find_bytes(rest, needle) else { break };
+        out.extend_from_slice(&rest[..idx]);
+        out.extend_from_slice(replacement);
+        rest = &rest[idx + needle.len()..];
+        count += 1;
+    }
+    out.extend_from_slice(rest);
+    (out, count)
+}
+
+fn splice_bytes(content: &[u8], start: usize, end: usize, replacement: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(content.len() - (end - start) + replacement.len());
+    out.extend_from_slice(&content[..start]);
+    out.extend_from_slice(replacement);
+    out.extend_from_slice(&content[end..]);
+    out
+}
+
+/// Returns `Ok(None)` when the start marker is absent so callers can tell a missing
+/// block apart from a malformed one. Works on raw bytes so non-UTF-8 targets round-trip.
+fn locate_block(content: &[u8], start_marker: &str, end_marker: &str, matching: &MarkerMatch) -> Result<Option<BlockSpan>> {
+    match matching.mode {
+        MarkerMode::Exact => {
+            let start = match find_bytes(content, start_marker.as_bytes()) {
+                Some(idx) => idx,
+                None => return Ok(None),
+            };
+            let inner_start = start + start_marker.len();
+            let end_rel = find_bytes(&content[inner_start..], end_marker.as_bytes()).ok_or_else(|| MisfitError::new(ErrorCode::MarkerNotFound, "End marker not found"))?;
+            let inner_end = inner_start + end_rel;
+            Ok(Some(BlockSpan { start, inner_start, inner_end, end: inner_end + end_marker.len() }))
+        }
+        MarkerMode::Regex => {
+            let start_re = regex::bytes::Regex::new(start_marker).context("Invalid start marker regex")?;
+            let end_re = regex::bytes::Regex::new(end_marker).context("Invalid end marker regex")?;
+            let start_match = match start_re.find(content) {
+                Some(m) => m,
+                None => return Ok(None),
+            };
+            let end_match = end_re
+                .find_at(content, start_match.end())
+                .ok_or_else(|| MisfitError::new(ErrorCode::MarkerNotFound, "End marker not found"))?;
+            Ok(Some(BlockSpan {
+                start: start_match.start(),
+                inner_start: start_match.end(),
+                inner_end: end_match.start(),
+                end: end_match.end(),
+            }))
+        }
+        MarkerMode::Anchor => locate_anchor_block(content, start_marker, end_marker, matching),
+    }
+}
+
+fn locate_anchor_block(content: &[u8], start_anchor: &str, end_anchor: &str, matching: &MarkerMatch) -> Result<Option<BlockSpan>> {
+    let threshold = matching.similarity.unwrap_or(1.0);
+    let mut lines: Vec<(usize, &[u8])> = Vec::new();
+    let mut offset = 0;
+    for line in content.split_inclusive(|b| *b == b'\n') {
+        lines.push((offset, line));
+        offset += line.len();
+    }
+
+    let start_anchor_idx = match best_matching_line(&lines, 0, start_anchor, threshold) {
+        Some(idx) => idx,
+        None => return Ok(None),
+    };
+    let start_line = start_anchor_idx
+        .checked_add_signed(matching.start_offset)
+        .filter(|idx| *idx < lines.len())
+        .ok_or_else(|| anyhow!("Start offset moves outside the file"))?;
+
+    let end_anchor_idx = best_matching_line(&lines, start_line + 1, end_anchor, threshold)
+        .ok_or_else(|| MisfitError::new(ErrorCode::MarkerNotFound, "End marker not found"))?;
+    let end_line = end_anchor_idx
+        .checked_add_signed(matching.end_offset)
+        .filter(|idx| *idx > start_line && *idx < lines.len())
+        .ok_or_else(|| anyhow!("End offset moves outside the block"))?;
+
+    let (start, start_text) = lines[start_line];
+    let (inner_end, end_text) = lines[end_line];
+    Ok(Some(BlockSpan {
+        start,
+        inner_start: start + start_text.len(),
+        inner_end,
+        end: inner_end + end_text.len(),
+    }))
+}
+
+fn best_matching_line(lines: &[(usize, &[u8])], from: usize, anchor: &str, threshold: f64) -> Option<usize> {
+    let mut best: Option<(usize, f64)> = None;
+    for (idx, (_, line)) in lines.iter().enumerate().skip(from) {
+        let score = line_similarity(&String::from_utf8_lossy(line), anchor);
+        if score >= threshold && best.map_or(true, |(_, b)| score > b) {
+            best = Some((idx, score));
+        }
+    }
+    best.map(|(idx, _)| idx)
+}
+
+/// Dice coefficient over character bigrams of the trimmed lines.
+fn line_similarity(a: &str, b: &str) -> f64 {
+    let a = a.trim();
+    let b = b.trim();
+    if a == b {
+        return 1.0;
+    }
+    let bigrams = |s: &str| -> Vec<(char, char)> {
+        let chars: Vec<char> = s.chars().collect();
+        chars.windows(2).map(|w| (w[0], w[1])).collect()
+    };
+    let a_grams = bigrams(a);
+    let mut b_grams = bigrams(b);
+    if a_grams.is_empty() || b_grams.is_empty() {
+        return 0.0;
+    }
+    let total = a_grams.len() + b_grams.len();
+    let mut shared = 0;
+    for gram in a_grams {
+        if let Some(pos) = b_grams.iter().position(|g| *g == gram) {
+            b_grams.swap_remove(pos);
+            shared += 1;
+        }
+    }
+    (2 * shared) as f64 / total as f64
+}
+
+pub fn patch_file(target: &Path, spec: &PatchSpec) -> Result<PatchOutcome> {
+    let mut outcomes = patch_file_blocks(target, std::slice::from_ref(spec))?;
+    Ok(outcomes.remove(0))
+}
+
+/// Applies every block to one in-memory copy of the file and writes it once. If any
+/// block fails the file is left untouched.
+pub fn patch_file_blocks(target: &Path, specs: &[PatchSpec]) -> Result<Vec<PatchOutcome>> {
+    let file_content = read_target(target).context("Failed to read target file for patching")?;
+    let (patched, outcomes) = apply_patches(&file_content, specs)?;
+
+    if outcomes.iter().any(|o| *o != PatchOutcome::AlreadyApplied) {
+        write_target(target, &patched).context("Failed to write patched file")?;
+    }
+    Ok(outcomes)
+}
+
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct PatchPreview {
+    pub file: String,
+    pub original: String,
+    pub patched: String,
+    pub diff: String,
+}
+
+/// Computes what `patch_file_blocks` would write, without touching the file. Non-UTF-8
+/// bytes are shown lossily since the preview is for display only.
+pub fn preview_patch_blocks(target: &Path, specs: &[PatchSpec]) -> Result<PatchPreview> {
+    let original = read_target(target).context("Failed to read target file for preview")?;
+    let (patched, _) = apply_patches(&original, specs)?;
+    let text = |bytes: &[u8]| String::from_utf8_lossy(&TextStyle::decode(bytes).0).to_string();
+    let original = text(&original);
+    let patched = text(&patched);
+    let label = target.to_string_lossy().to_string();
+    let diff = unified_diff(&original, &patched, &label);
+    Ok(PatchPreview { file: label, original, patched, diff })
+}
+
+pub fn unified_diff(old: &str, new: &str, label: &str) -> String {
+    similar::TextDiff::from_lines(old, new)
+        .unified_diff()
+        .context_radius(3)
+        .header(&format!("a/{}", label), &format!("b/{}", label))
+        .to_string()
+}
+
+/// Patches the decoded text of `file_content` and encodes it back in the file's own
+/// [`TextStyle`], with the patch content's line breaks turned into the file's.
+fn apply_patches(file_content: &[u8], specs: &[PatchSpec]) -> Result<(Vec<u8>, Vec<PatchOutcome>)> {
+    let (text, style) = TextStyle::decode(file_content);
+    let mut current = text.into_owned();
+    let mut outcomes = Vec::with_capacity(specs.len());
+    for (idx, spec) in specs.iter().enumerate() {
+        let spec = PatchSpec {
+            content: style.line_breaks(&spec.content),
+            stripped_content: spec.stripped_content.as_deref().map(|c| style.line_breaks(c)),
+            ..spec.clone()
+        };
+        let (patched, outcome) = apply_patch(&current, &spec, &style).with_context(|| format!("Patch block {} failed", idx + 1))?;
+        if let Some(patched) = patched {
+            current = patched;
+        }
+        outcomes.push(outcome);
+    }
+    Ok((style.encode(&current), outcomes))
+}
+
+/// Returns the patched bytes, or `None` when the block is already applied.
+fn apply_patch(file_content: &[u8], spec: &PatchSpec, style: &TextStyle) -> Result<(Option<Vec<u8>>, PatchOutcome)> {
+    let patch_id = spec.patch_id.as_deref().map(str::as_bytes);
+    let content = spec.content.as_bytes();
+    let span = match locate_block(file_content, &spec.start_marker, &spec.end_marker, &spec.matching)? {
+        Some(span) => span,
+        None => {
+            // A one-shot (marker stripping) patch removes its own markers, so a re-run
+            // only finds the injected content. Text that merely looks like it doesn't count:
+            // the receipt has to show this content went where the markers were.
+            let id_present = patch_id.is_some_and(|id| find_bytes(file_content, id).is_some());
+            let content_present = spec.strip_markers
+                && !content.is_empty()
+                && spec.stripped_content.as_deref() == Some(&spec.content)
+                && find_bytes(file_content, content).is_some();
+            if id_present || content_present {
+                return Ok((None, PatchOutcome::AlreadyApplied));
+            }
+            if let Some(anchor) = &spec.insert_if_missing {
+                return Ok((Some(insert_block(file_content, spec, anchor, style)?), PatchOutcome::Inserted));
+            }
+            return Err(MisfitError::new(ErrorCode::MarkerNotFound, "Start marker not found").into());
+        }
+    };
+
+    if !spec.strip_markers {
+        let existing = &file_content[span.inner_start..span.inner_end];
+        if existing == content || patch_id.is_some_and(|id| find_bytes(existing, id).is_some()) {
+            return Ok((None, PatchOutcome::AlreadyApplied));
+        }
+    }
+
+    // When markers are stripped the whole span (markers included) is what gets replaced.
+    let (replace_start, replace_end) = if spec.strip_markers {
+        (span.start, span.end)
+    } else {
+        (span.inner_start, span.inner_end)
+    };
+
+    let original = file_content[replace_start..replace_end].to_vec();
+    let new_content = splice_bytes(file_content, replace_start, replace_end, content);
+    Ok((Some(new_content), PatchOutcome::Applied { original }))
+}
+
+fn inserted_block_text(spec: &PatchSpec, newline: &str) -> String {
+    if spec.strip_markers {
+        format!("{}{}", spec.content, newline)
+    } else {
+        format!("{}{}{}{}", spec.start_marker, spec.content, spec.end_marker, newline)
+    }
+}
+
+fn insert_block(file_content: &[u8], spec: &PatchSpec, anchor: &InsertAnchor, style: &TextStyle) -> Result<Vec<u8>> {
+    if spec.matching.mode != MarkerMode::Exact {
+        return Err(anyhow!("insertIfMissing requires exact markers"));
+    }
+    let insert_at = match anchor {
+        InsertAnchor::AppendToEnd => file_content.len(),
+        InsertAnchor::AfterLineMatching { pattern } => {
+            let re = regex::bytes::Regex::new(pattern).context("Invalid insert anchor regex")?;
+            let m = re.find(file_content).ok_or_else(|| MisfitError::new(ErrorCode::MarkerNotFound, format!("Insert anchor not found: {}", pattern)))?;
+            file_content[m.end()..]
+                .iter()
+                .position(|b| *b == b'\n')
+                .map(|rel| m.end() + rel + 1)
+                .unwrap_or(file_content.len())
+        }
+    };
+
+    let mut block = Vec::new();
+    if insert_at > 0 && file_content[insert_at - 1] != b'\n' {
+        block.extend_from_slice(style.newline().as_bytes());
+    }
+    block.extend_from_slice(inserted_block_text(spec, style.newline()).as_bytes());
+    Ok(splice_bytes(file_content, insert_at, insert_at, &block))
+}
+
+/// Puts the recorded original text back in place of an applied patch. Only the patched
+/// block is touched, so edits made elsewhere in the file after install survive.
+pub fn revert_patch(record: &PatchRecord) -> Result<()> {
+    let target = Path::new(&record.file);
+    let raw = read_target(target).context("Failed to read patched file for revert")?;
+    let (file_content, style) = TextStyle::decode(&raw);
+    let applied = style.line_breaks(&record.applied);
+
+    if record.inserted {
+        let (start, end) = if record.markers_stripped {
+            let block = format!("{}{}", applied, style.newline());
+            let idx = find_bytes(&file_content, block.as_bytes())
+                .ok_or_else(|| anyhow!("Inserted patch block not found in {}", target.display()))?;
+            (idx, idx + block.len())
+        } else {
+            // Located by its markers, since `applied` may have secrets masked
+            let span = locate_block(&file_content, &record.start_marker, &record.end_marker, &record.matching)?
+                .ok_or_else(|| anyhow!("Inserted patch block not found in {}", target.display()))?;
+            let newline = style.newline().as_bytes();
+            (span.start, span.end + if file_content[span.end..].starts_with(newline) { newline.len() } else { 0 })
+        };
+        let new_content = splice_bytes(&file_content, start, end, b"");
+        write_target(target, &style.encode(&new_content)).context("Failed to write reverted file")?;
+        return Ok(());
+    }
+
+    let (replace_start, replace_end) = if record.markers_stripped {
+        let idx = find_bytes(&file_content, applied.as_bytes())
+            .ok_or_else(|| anyhow!("Applied patch content not found in {}", target.display()))?;
+        (idx, idx + applied.len())
+    } else {
+        let span = locate_block(&file_content, &record.start_marker, &record.end_marker, &record.matching)?
+            .ok_or_else(|| MisfitError::new(ErrorCode::MarkerNotFound, "Start marker not found").with_path(target))?;
+        (span.inner_start, span.inner_end)
+    };
+
+    let new_content = splice_bytes(&file_content, replace_start, replace_end, &record.original_bytes()?);
+    write_target(target, &style.encode(&new_content)).context("Failed to write reverted file")?;
+    Ok(())
+}
+
+pub fn set_json_value(target: &Path, key_path: &str, value: &serde_json::Value) -> Result<()> {
+    let content = if target.exists() || split_asar_path(target).is_some() {
+        String::from_utf8(read_target(target).context("Failed to read JSON file")?).context("Failed to read JSON file")?
+    } else {
+        "{}".to_string()
+    };
+    let mut json_val: serde_json::Value = serde_json::from_str(&content).context("Failed to parse JSON")?;
+
+    let parts = split_key_path(key_path)?;
+    let mut current = &mut json_val;
+
+    for (i, part) in parts.iter().enumerate() {
+        if i == parts.len() - 1 {
+            if let Some(obj) = current.as_object_mut() {
+                obj.insert(part.to_string(), value.clone());
+            } else {
+                return Err(anyhow!("Target path is not an object"));
+            }
+        } else {
+            if !current.is_object() {
+                 return Err(anyhow!("Path traversal failed, not an object"));
+            }
+            if current.get(part).is_none() {
+                 if let Some(obj) = current.as_object_mut() {
+                    obj.insert(part.to_string(), serde_json::json!({}));
+                 }
+            }
+            current = current.get_mut(part).unwrap();
+        }
+    }
+
+    let new_content = serde_json::to_string_pretty(&json_val)?;
+    write_target(target, new_content.as_bytes())?;
+    Ok(())
+}
+
+fn split_key_path(key_path: &str) -> Result<Vec<String>> {
+    let trimmed = key_path.trim();
+    if trimmed.is_empty() {
+        return Err(anyhow!("Key path cannot be empty"));
+    }
+
+    let mut parts = Vec::new();
+    let mut current = String::new();
+    let mut escaped = false;
+
+    for ch in trimmed.chars() {
+        if escaped {
+            current.push(ch);
+            escaped = false;
+            continue;
+        }
+        if ch == '\\' {
+            escaped = true;
+            continue;
+        }
+        if ch == '.' {
+            if current.is_empty() {
+                return Err(anyhow!("Key path contains an empty segment"));
+            }
+            parts.push(current);
+            current = String::new();
+            continue;
+        }
+        current.push(ch);
+    }
+
+    if escaped {
+        return Err(anyhow!("Key path ends with an escape character"));
+    }
+    if current.is_empty() {
+        return Err(anyhow!("Key path contains an empty segment"));
+    }
+    parts.push(current);
+
+    Ok(parts)
+}
+
+/// `command` and `args` as one line for logs and prompts, quoting arguments that are empty or
+/// contain whitespace.
+pub fn command_line(command: &str, args: &[String]) -> String {
+    let mut line = command.to_string();
+    for arg in args {
+        line.push(' ');
+        if arg.is_empty() || arg.contains(char::is_whitespace) {
+            line.push_str(&format!("\"{}\"", arg.replace('"', "\\\"")));
+        } else {
+            line.push_str(arg);
+        }
+    }
+    line
+}
+
+/// Shell a `runCommand` step can run through, for built-ins, pipes and scripts.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum CommandShell {
+    /// `cmd /C` (Windows only).
+    Cmd,
+    /// Windows PowerShell, or `pwsh` elsewhere, without profile or execution policy checks.
+    Powershell,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct CommandOptions {
+    pub cwd: Option<PathBuf>,
+    pub env: BTreeMap<String, String>,
+    pub timeout: Option<std::time::Duration>,
+    pub shell: Option<CommandShell>,
+    /// Ask for administrator rights unless the installer already has them.
+    pub elevate: bool,
+}
+
+/// Console programs started from the GUI would otherwise each open a console window.
+#[cfg(windows)]
+const CREATE_NO_WINDOW: u32 = 0x0800_0000;
+
+fn build_command(cmd: &str, args: &[String], shell: Option<CommandShell>) -> Command {
+    match shell {
+        None => {
+            let mut command = Command::new(cmd);
+            command.args(args);
+            command
+        }
+        Some(CommandShell::Cmd) => {
+            let mut command = Command::new("cmd");
+            #[cfg(windows)]
+            {
+                // cmd has its own quoting rules; /S makes it strip just the outer quotes
+                use std::os::windows::process::CommandExt;
+                command.raw_arg(format!("/S /C \"{}\"", command_line(cmd, args)));
+            }
+            #[cfg(not(windows))]
+            command.args(["/C", &command_line(cmd, args)]);
+            command
+        }
+        Some(CommandShell::Powershell) => {
+            let mut command = Command::new(if cfg!(windows) { "powershell" } else { "pwsh" });
+            command.args(["-NoProfile", "-NonInteractive", "-ExecutionPolicy", "Bypass", "-Command", &command_line(cmd, args)]);
+            command
+        }
+    }
+}
+
+fn declined(cmd: &str) -> anyhow::Error {
+    MisfitError::new(ErrorCode::Cancelled, format!("Administrator rights for {} were declined", cmd)).into()
+}
+
+fn command_failed(cmd: &str, code: Option<i32>) -> anyhow::Error {
+    let message = match code {
+        Some(code) => format!("Command {} exited with code {}", cmd, code),
+        None => format!("Command {} was terminated by a signal", cmd),
+    };
+    MisfitError::new(ErrorCode::Failed, message).with_exit_code(code).into()
+}
+
+/// Runs `cmd` as administrator through the UAC prompt, hidden. Windows can't hand the output
+/// of an elevated process back, and `env` doesn't reach it; only the exit code is reported.
+#[cfg(windows)]
+fn run_elevated(cmd: &str, args: &[String], options: &CommandOptions) -> Result<()> {
+    use std::os::windows::ffi::OsStrExt;
+    use windows_sys::Win32::Foundation::{CloseHandle, ERROR_CANCELLED, WAIT_TIMEOUT};
+    use windows_sys::Win32::System::Threading::{GetExitCodeProcess, TerminateProcess, WaitForSingleObject, INFINITE};
+    use windows_sys::Win32::UI::Shell::{ShellExecuteExW, SEE_MASK_NOASYNC, SEE_MASK_NOCLOSEPROCESS, SHELLEXECUTEINFOW};
+
+    let (program, parameters) = match options.shell {
+        None => (cmd.to_string(), command_line("", args)),
+        Some(CommandShell::Cmd) => ("cmd".to_string(), format!("/S /C \"{}\"", command_line(cmd, args))),
+        Some(CommandShell::Powershell) => {
+            let script = [
+                "-NoProfile", "-NonInteractive", "-ExecutionPolicy", "Bypass", "-Command", &command_line(cmd, args),
+            ]
+            .map(String::from);
+            ("powershell".to_string(), command_line("", &script))
+        }
+    };
+    let wide = |s: &std::ffi::OsStr| s.encode_wide().chain(Some(0)).collect::<Vec<u16>>();
+    let verb = wide("runas".as_ref());
+    let file = wide(program.as_ref());
+    let params = wide(parameters.trim_start().as_ref());
+    let dir = options.cwd.as_ref().map(|dir| wide(dir.as_os_str()));
+    let mut info = SHELLEXECUTEINFOW {
+        cbSize: std::mem::size_of::<SHELLEXECUTEINFOW>() as u32,
+        fMask: SEE_MASK_NOCLOSEPROCESS | SEE_MASK_NOASYNC,
+        lpVerb: verb.as_ptr(),
+        lpFile: file.as_ptr(),
+        lpParameters: params.as_ptr(),
+        lpDirectory: dir.as_ref().map_or(std::ptr::null(), |dir| dir.as_ptr()),
+        // SW_HIDE
+        nShow: 0,
+        ..Default::default()
+    };
+    // SAFETY: every string is NUL-terminated and outlives the call, and the process handle is
+    // closed on every path.
+    let (timed_out, code) = unsafe {
+        if ShellExecuteExW(&mut info) == 0 {
+            let err = std::io::Error::last_os_error();
+            if err.raw_os_error() == Some(ERROR_CANCELLED as i32) {
+                return Err(declined(cmd));
+            }
+            return Err(anyhow::Error::new(err).context(format!("Failed to execute command: {}", cmd)));
+        }
+        // Copied out: the struct is packed on 32-bit targets
+        let process = info.hProcess;
+        if process.is_null() {
+            // Handed to an already running process; there is nothing to wait for
+            return Ok(());
+        }
+        let wait = options.timeout.map_or(INFINITE, |timeout| timeout.as_millis().min(u128::from(INFINITE - 1)) as u32);
+        let timed_out = WaitForSingleObject(process, wait) == WAIT_TIMEOUT;
+        let mut code = 0u32;
+        if timed_out {
+            TerminateProcess(process, 1);
+        } else {
+            GetExitCodeProcess(process, &mut code);
+        }
+        CloseHandle(process);
+        (timed_out, code)
+    };
+    if timed_out {
+        let secs = options.timeout.unwrap_or_default().as_secs();
+        return Err(MisfitError::new(ErrorCode::Failed, format!("Command {} timed out after {} s", cmd, secs)).into());
+    }
+    if code != 0 {
+        return Err(command_failed(cmd, Some(code as i32)));
+    }
+    Ok(())
+}
+
+/// Wraps `command` in pkexec (in a desktop session) or non-interactive sudo. Both reset the
+/// environment and working folder, so those are handed over through `env` and `sh`.
+#[cfg(unix)]
+fn elevated(command: &Command, options: &CommandOptions) -> Command {
+    let desktop = std::env::var_os("DISPLAY").is_some() || std::env::var_os("WAYLAND_DISPLAY").is_some();
+    let has_pkexec = std::env::var_os("PATH")
+        .is_some_and(|path| std::env::split_paths(&path).any(|dir| dir.join("pkexec").is_file()));
+    let mut wrapper = if desktop && has_pkexec {
+        Command::new("pkexec")
+    } else {
+        let mut sudo = Command::new("sudo");
+        sudo.args(["-n", "--"]);
+        sudo
+    };
+    let cwd = options.cwd.clone().or_else(|| std::env::current_dir().ok()).unwrap_or_else(|| PathBuf::from("/"));
+    wrapper
+        .arg("env")
+        .args(options.env.iter().map(|(key, value)| format!("{}={}", key, value)))
+        .args(["sh", "-c", "cd -- \"$0\" && exec \"$@\""])
+        .arg(cwd)
+        .arg(command.get_program())
+        .args(command.get_args());
+    wrapper
+}
+
+/// Runs `cmd`, passing every line it prints (stdout and stderr) to `on_line` as it arrives.
+/// Fails with the exit code when the command fails, and kills it once `timeout` has passed.
+/// On Windows no console window is shown. With `elevate` and no administrator rights yet,
+/// declining the prompt fails with [`ErrorCode::Cancelled`].
+pub fn run_command(cmd: &str, args: &[String], options: &CommandOptions, on_line: &dyn Fn(&str)) -> Result<()> {
+    use std::io::BufRead;
+    use std::process::Stdio;
+    use std::sync::mpsc::{self, RecvTimeoutError};
+    use std::time::{Duration, Instant};
+
+    // CreateProcess can't start a process in a folder past MAX_PATH, long paths enabled or not
+    if let Some(cwd) = options.cwd.as_deref().filter(|cwd| cfg!(windows) && cwd.as_os_str().len() >= 260) {
+        let message = format!("The working folder of {} is longer than Windows allows for commands (260 characters)", cmd);
+        return Err(MisfitError::new(ErrorCode::Failed, message).with_path(cwd).into());
+    }
+    let elevate = options.elevate && !is_elevated();
+    #[cfg(windows)]
+    if elevate {
+        return run_elevated(cmd, args, options);
+    }
+    let mut command = build_command(cmd, args, options.shell);
+    #[cfg(unix)]
+    if elevate {
+        command = elevated(&command, options);
+    }
+    command.envs(&options.env).stdin(Stdio::null()).stdout(Stdio::piped()).stderr(Stdio::piped());
+    if let Some(cwd) = &options.cwd {
+        command.current_dir(cwd);
+    }
+    #[cfg(windows)]
+    {
+        use std::os::windows::process::CommandExt;
+        command.creation_flags(CREATE_NO_WINDOW);
+    }
+    let mut child = command.spawn().with_context(|| format!("Failed to execute command: {}", cmd))?;
+
+    // Readers own the pipes and are never joined: a process the command started may keep
+    // them open long after the command itself is gone
+    let (tx, rx) = mpsc::channel::<String>();
+    let pipes: [Option<Box<dyn std::io::Read + Send>>; 2] = [
+        child.stdout.take().map(|p| Box::new(p) as Box<dyn std::io::Read + Send>),
+        child.stderr.take().map(|p| Box::new(p) as Box<dyn std::io::Read + Send>),
+    ];
+    for pipe in pipes.into_iter().flatten() {
+        let tx = tx.clone();
+        std::thread::spawn(move || {
+            let mut reader = std::io::BufReader::new(pipe);
+            let mut line = Vec::new();
+            while matches!(reader.read_until(b'\n', &mut line), Ok(n) if n > 0) {
+                let text = String::from_utf8_lossy(&line).trim_end().to_string();
+                if tx.send(text).is_err() {
+                    break;
+                }
+                line.clear();
+            }
+        });
+    }
+    drop(tx);
+
+    let started = Instant::now();
+    let status = loop {
+        match rx.recv_timeout(Duration::from_millis(50)) {
+            Ok(line) => on_line(&line),
+            Err(RecvTimeoutError::Timeout) => {}
+            Err(RecvTimeoutError::Disconnected) => std::thread::sleep(Duration::from_millis(50)),
+        }
+        if let Some(status) = child.try_wait()? {
+            break status;
+        }
+        if options.timeout.is_some_and(|timeout| started.elapsed() >= timeout) {
+            let _ = child.kill();
+            let _ = child.wait();
+            let secs = options.timeout.unwrap_or_default().as_secs();
+            return Err(MisfitError::new(ErrorCode::Failed, format!("Command {} timed out after {} s", cmd, secs)).into());
+        }
+    };
+    // Output printed just before exiting, until the pipes close or go quiet
+    while let Ok(line) = rx.recv_timeout(Duration::from_millis(200)) {
+        on_line(&line);
+    }
+
+    if !status.success() {
+        // pkexec exits with 126 when its authentication dialog is dismissed
+        if elevate && command.get_program() == "pkexec" && status.code() == Some(126) {
+            return Err(declined(cmd));
+        }
+        return Err(command_failed(cmd, status.code()));
+    }
+    Ok(())
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct VerifyChecks {
+    pub contains: Option<String>,
+    pub sha256: Option<String>,
+    pub json_path: Option<String>,
+    pub equals: Option<serde_json::Value>,
+}
+
+pub fn sha256_hex(bytes: &[u8]) -> String {
+    use sha2::{Digest, Sha256};
+    let digest = Sha256::digest(bytes);
+    digest.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Checks post-conditions on an installed file. With no checks set it only asserts
+/// the file exists; `equals` without `jsonPath` compares the whole document.
+pub fn verify_file(target: &Path, checks: &VerifyChecks) -> Result<()> {
+    let bytes = read_target(target).with_context(|| format!("{} does not exist or cannot be read", target.display()))?;
+
+    if let Some(needle) = &checks.contains {
+        if find_bytes(&bytes, needle.as_bytes()).is_none() {
+            return Err(anyhow!("{} does not contain {:?}", target.display(), needle));
+        }
+    }
+
+    if let Some(expected) = &checks.sha256 {
+        let actual = sha256_hex(&bytes);
+        if !actual.eq_ignore_ascii_case(expected.trim()) {
+            return Err(anyhow!("{} has SHA-256 {}, expected {}", target.display(), actual, expected));
+        }
+    }
+
+    if checks.json_path.is_some() || checks.equals.is_some() {
+        let json: serde_json::Value = serde_json::from_slice(&bytes).context("Failed to parse JSON")?;
+        let mut current = &json;
+        if let Some(key_path) = &checks.json_path {
+            for part in split_key_path(key_path)? {
+                current = current
+                    .get(&part)
+                    .ok_or_else(|| anyhow!("{} has no value at {}", target.display(), key_path))?;
+            }
+        }
+        if let Some(expected) = &checks.equals {
+            if current != expected {
+                return Err(anyhow!("{} value is {}, expected {}", target.display(), current, expected));
+            }
+        }
+    }
+    Ok(())
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct EmbedOptions {
+    pub max_occurrences: Option<usize>,
+    pub data_uri: bool,
+    pub replacements: Option<HashMap<String, String>>,
+}
+
+/// Guesses a MIME type from magic bytes, falling back to the file extension.
+pub fn sniff_mime(bytes: &[u8], path: &Path) -> &'static str {
+    const SIGNATURES: &[(&[u8], &str)] = &[
+        (b"\x89PNG\r\n\x1a\n", "image/png"),
+        (b"\xff\xd8\xff", "image/jpeg"),
+        (b"GIF87a", "image/gif"),
+        (b"GIF89a", "image/gif"),
+        (b"\x00\x00\x01\x00", "image/x-icon"),
+        (b"wOFF", "font/woff"),
+        (b"wOF2", "font/woff2"),
+        (b"OTTO", "font/otf"),
+        (b"\x00\x01\x00\x00", "font/ttf"),
+        (b"%PDF", "application/pdf"),
+    ];
+    for (magic, mime) in SIGNATURES {
+        if bytes.starts_with(magic) {
+            return mime;
+        }
+    }
+    if bytes.len() >= 12 && &bytes[..4] == b"RIFF" && &bytes[8..12] == b"WEBP" {
+        return "image/webp";
+    }
+
+    let ext = path.extension().map(|e| e.to_string_lossy().to_lowercase()).unwrap_or_default();
+    match ext.as_str() {
+        "svg" => "image/svg+xml",
+        "css" => "text/css",
+        "js" => "text/javascript",
+        "json" => "application/json",
+        "html" | "htm" => "text/html",
+        "txt" => "text/plain",
+        _ => "application/octet-stream",
+    }
+}
+
+/// Replaces the placeholder with the encoded input and returns how many occurrences were
+/// replaced. A missing placeholder is an error unless the encoded value is already present
+/// (a previous run embedded it), in which case `0` is returned.
+pub fn base64_embed(target: &Path, placeholder: &str, input_file: &Path, options: &EmbedOptions) -> Result<usize> {
+    let input_bytes = embed_input(input_file, options.replacements.as_ref())?;
+    use base64::Engine as _;
+    let mut encoded = base64::engine::general_purpose::STANDARD.encode(&input_bytes);
+    if options.data_uri {
+        encoded = format!("data:{};base64,{}", sniff_mime(&input_bytes, input_file), encoded);
+    }
+
+    let target_content = read_target(target).context("Failed to read target file for embedding")?;
+    let (new_content, count) = replace_bytes(&target_content, placeholder.as_bytes(), encoded.as_bytes(), options.max_occurrences);
+    if count == 0 {
+        if find_bytes(&target_content, encoded.as_bytes()).is_some() {
+            return Ok(0);
+        }
+        return Err(MisfitError::new(ErrorCode::MarkerNotFound, format!("Placeholder {:?} not found in {}", placeholder, target.display())).with_path(target).into());
+    }
+
+    write_target(target, &new_content)?;
+    Ok(count)
+}
+
+/// A path an install will write to and roughly how many bytes it adds.
+#[derive(Debug, Clone)]
+pub struct PreflightTarget {
+    pub path: PathBuf,
+    pub bytes: u64,
+}
+
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct VolumeCheck {
+    /// Closest existing folder on the volume, used to query free space.
+    pub path: String,
+    pub required_bytes: u64,
+    pub available_bytes: Option<u64>,
+    pub sufficient: bool,
+}
+
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct FileCheck {
+    pub path: String,
+    pub exists: bool,
+    pub writable: bool,
+    pub locked: bool,
+    /// The file has the read-only attribute (Windows), which administrator rights don't get
+    /// past either.
+    pub read_only: bool,
+    pub error: Option<String>,
+}
+
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct PreflightReport {
+    pub total_bytes: u64,
+    pub volumes: Vec<VolumeCheck>,
+    pub files: Vec<FileCheck>,
+    /// Some targets are read-only for this user but would be writable as administrator/root.
+    pub needs_elevation: bool,
+    /// Set when the manifest constrains the target app's version.
+    pub target_version: Option<TargetVersionCheck>,
+    pub ok: bool,
+}
+
+impl PreflightReport {
+    /// Files this user may not write (by their ACL or permissions, or those of the folder
+    /// they would be created in), which administrator/root rights would allow.
+    pub fn protected_files(&self) -> impl Iterator<Item = &FileCheck> {
+        self.files.iter().filter(|f| !f.writable && !f.locked && !f.read_only)
+    }
+}
+
+/// Checks free space, writability and locks for every target without modifying any of
+/// them. `reserved` only counts against free space (e.g. the backup folder).
+pub fn preflight_check(targets: &[PreflightTarget], reserved: &[PreflightTarget]) -> PreflightReport {
+    let mut volumes: BTreeMap<String, VolumeCheck> = BTreeMap::new();
+    for target in targets.iter().chain(reserved) {
+        let probe = nearest_existing(&target.path);
+        let volume = volumes.entry(volume_key(&probe)).or_insert_with(|| VolumeCheck {
+            path: probe.to_string_lossy().to_string(),
+            required_bytes: 0,
+            available_bytes: fs2::available_space(&probe).ok(),
+            sufficient: true,
+        });
+        volume.required_bytes += target.bytes;
+    }
+    for volume in volumes.values_mut() {
+        volume.sufficient = volume.available_bytes.map_or(true, |free| free >= volume.required_bytes);
+    }
+
+    let mut seen = HashSet::new();
+    let files: Vec<FileCheck> = targets
+        .iter()
+        .filter(|t| seen.insert(t.path.clone()))
+        .map(|t| check_file(&t.path))
+        .collect();
+
+    let ok = volumes.values().all(|v| v.sufficient) && files.iter().all(|f| f.writable && !f.locked);
+    let mut report = PreflightReport {
+        total_bytes: targets.iter().map(|t| t.bytes).sum(),
+        volumes: volumes.into_values().collect(),
+        files,
+        needs_elevation: false,
+        target_version: None,
+        ok,
+    };
+    report.needs_elevation = !is_elevated() && report.protected_files().next().is_some();
+    report
+}
+
+/// Whether the process runs as root (Unix) or with an elevated admin token (Windows).
+pub fn is_elevated() -> bool {
+    #[cfg(unix)]
+    {
+        // SAFETY: geteuid has no preconditions and cannot fail.
+        unsafe { libc::geteuid() == 0 }
+    }
+    #[cfg(windows)]
+    {
+        use windows_sys::Win32::Foundation::{CloseHandle, HANDLE};
+        use windows_sys::Win32::Security::{GetTokenInformation, TokenElevation, TOKEN_ELEVATION, TOKEN_QUERY};
+        use windows_sys::Win32::System::Threading::{GetCurrentProcess, OpenProcessToken};
+
+        let mut token: HANDLE = std::ptr::null_mut();
+        // SAFETY: the current process pseudo handle is always valid; `token` is closed below.
+        if unsafe { OpenProcessToken(GetCurrentProcess(), TOKEN_QUERY, &mut token) } == 0 {
+            return false;
+        }
+        let mut elevation = TOKEN_ELEVATION::default();
+        let mut size = 0u32;
+        // SAFETY: `elevation` is the TOKEN_ELEVATION that TokenElevation fills, with its size.
+        let queried = unsafe {
+            GetTokenInformation(
+                token,
+                TokenElevation,
+                &mut elevation as *mut TOKEN_ELEVATION as *mut std::ffi::c_void,
+                std::mem::size_of::<TOKEN_ELEVATION>() as u32,
+                &mut size,
+            )
+        };
+        // SAFETY: `token` was opened above and is not used afterwards.
+        unsafe { CloseHandle(token) };
+        queried != 0 && elevation.TokenIsElevated != 0
+    }
+    #[cfg(not(any(unix, windows)))]
+    {
+        false
+    }
+}
+
+/// Starts `exe` again with administrator rights (UAC on Windows, pkexec on Linux, an
+/// authorization prompt on macOS). Returns once the elevated process was launched.
+pub fn relaunch_elevated(exe: &Path, args: &[String]) -> Result<()> {
+    #[cfg(windows)]
+    let mut command = {
+        let quote = |s: &str| format!("'{}'", s.replace('\'', "''"));
+        let arg_list = args.iter().map(|a| quote(a)).collect::<Vec<_>>().join(",");
+        let mut script = format!("Start-Process -FilePath {} -Verb RunAs", quote(&exe.to_string_lossy()));
+        if !arg_list.is_empty() {
+            script.push_str(&format!(" -ArgumentList {}", arg_list));
+        }
+        let mut command = Command::new("powershell");
+        command.args(["-NoProfile", "-NonInteractive", "-Command", &script]);
+        command
+    };
+    #[cfg(target_os = "macos")]
+    let mut command = {
+        let quote = |s: &str| format!("'{}'", s.replace('\'', "'\\''"));
+        let line = std::iter::once(exe.to_string_lossy().to_string())
+            .chain(args.iter().cloned())
+            .map(|a| quote(&a))
+            .collect::<Vec<_>>()
+            .join(" ");
+        let script = format!(
+            "do shell script \"{} > /dev/null 2>&1 &\" with administrator privileges",
+            line.replace('\\', "\\\\").replace('"', "\\\"")
+        );
+        let mut command = Command::new("osascript");
+        command.args(["-e", &script]);
+        command
+    };
+    #[cfg(all(unix, not(target_os = "macos")))]
+    let mut command = {
+        // pkexec clears the environment; pass through what a GUI app needs to show a window.
+        let mut command = Command::new("pkexec");
+        command.arg("env");
+        for key in ["DISPLAY", "XAUTHORITY", "WAYLAND_DISPLAY", "XDG_RUNTIME_DIR"] {
+            if let Ok(value) = std::env::var(key) {
+                command.arg(format!("{}={}", key, value));
+            }
+        }
+        command.arg(exe).args(args);
+        command
+    };
+    #[cfg(not(any(unix, windows)))]
+    return Err(anyhow!("Elevation is not supported on this platform"));
+
+    #[cfg(any(unix, windows))]
+    {
+        command.spawn().context("Failed to request elevation")?;
+        Ok(())
+    }
+}
+
+/// Starts `target` in its own process group with no console or pipes, so closing the
+/// installer leaves it running. An absolute program starts in its own folder.
+pub fn launch_detached(target: &LaunchTarget, base: &Path) -> Result<()> {
+    use std::process::Stdio;
+
+    let program = if target.command.contains(['/', '\\', '%', '$']) {
+        resolve_path(base, &target.command)
+    } else {
+        PathBuf::from(&target.command)
+    };
+    let mut command = if cfg!(target_os = "macos") && program.extension().is_some_and(|ext| ext == "app") {
+        let mut open = Command::new("open");
+        open.arg("-a").arg(&program).arg("--args");
+        open
+    } else {
+        Command::new(&program)
+    };
+    command.args(&target.args).stdin(Stdio::null()).stdout(Stdio::null()).stderr(Stdio::null());
+    if let Some(dir) = program.parent().filter(|dir| dir.is_absolute()) {
+        command.current_dir(dir);
+    }
+    #[cfg(unix)]
+    {
+        use std::os::unix::process::CommandExt;
+        command.process_group(0);
+    }
+    #[cfg(windows)]
+    {
+        use std::os::windows::process::CommandExt;
+        const DETACHED_PROCESS: u32 = 0x0000_0008;
+        const CREATE_NEW_PROCESS_GROUP: u32 = 0x0000_0200;
+        command.creation_flags(DETACHED_PROCESS | CREATE_NEW_PROCESS_GROUP);
+    }
+    let mut child = command.spawn().with_context(|| format!("Failed to launch {}", program.display()))?;
+    // Reaped in the background so it doesn't linger as a zombie while the installer is open
+    std::thread::spawn(move || child.wait());
+    Ok(())
+}
+
+fn nearest_existing(path: &Path) -> PathBuf {
+    let mut current = path;
+    loop {
+        if current.exists() {
+            return current.to_path_buf();
+        }
+        match current.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => current = parent,
+            _ => return PathBuf::from("."),
+        }
+    }
+}
+
+#[cfg(unix)]
+fn volume_key(path: &Path) -> String {
+    use std::os::unix::fs::MetadataExt;
+    fs::metadata(path).map(|m| m.dev().to_string()).unwrap_or_default()
+}
+
+#[cfg(not(unix))]
+fn volume_key(path: &Path) -> String {
+    let absolute = fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+    absolute
+        .components()
+        .next()
+        .map(|c| c.as_os_str().to_string_lossy().to_uppercase())
+        .unwrap_or_default()
+}
+
+pub fn can_write_dir(dir: &Path) -> bool {
+    let nanos = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_nanos())
+        .unwrap_or(0);
+    let test_path = dir.join(format!(".misfit_write_test_{}", nanos));
+    match fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(&test_path)
+    {
+        Ok(_) => {
+            let _ = fs::remove_file(&test_path);
+            true
+        }
+        Err(_) => false,
+    }
+}
+
+fn check_file(path: &Path) -> FileCheck {
+    use fs2::FileExt;
+
+    let mut check = FileCheck {
+        path: path.to_string_lossy().to_string(),
+        exists: path.exists(),
+        writable: false,
+        locked: false,
+        read_only: false,
+        error: None,
+    };
+    if !check.exists {
+        let parent = nearest_existing(path.parent().unwrap_or(Path::new(".")));
+        check.writable = parent.is_dir() && can_write_dir(&parent);
+        if !check.writable {
+            check.error = Some(format!("Cannot create files in {}", parent.display()));
+        }
+        return check;
+    }
+    if path.is_dir() {
+        check.writable = can_write_dir(path);
+        return check;
+    }
+    match fs::OpenOptions::new().write(true).open(path) {
+        Ok(file) => {
+            check.writable = true;
+            // Advisory on Unix; on Windows an open handle without sharing fails the open itself.
+            if FileExt::try_lock_exclusive(&file).is_err() {
+                check.locked = true;
+            } else {
+                let _ = FileExt::unlock(&file);
+            }
+        }
+        Err(err) => {
+            // ERROR_SHARING_VIOLATION / ERROR_LOCK_VIOLATION
+            check.locked = cfg!(windows) && matches!(err.raw_os_error(), Some(32) | Some(33));
+            // On Unix the mode bits are the permissions themselves, which root does get past
+            check.read_only = cfg!(windows) && fs::metadata(path).is_ok_and(|meta| meta.permissions().readonly());
+            check.error = Some(err.to_string());
+        }
+    }
+    check
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    pub(crate) fn temp_file(name: &str, contents: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("misfit_engine_test_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).expect("temp dir");
+        let path = dir.join(name);
+        std::fs::create_dir_all(path.parent().unwrap()).expect("temp dir");
+        std::fs::write(&path, contents).expect("temp file");
+        path
+    }
+
+    /// Observer for tests that don't look at the install's output.
+    pub(crate) struct Quiet;
+    impl InstallObserver for Quiet {
+        fn log(&self, _message: &str) {}
+    }
+
+    /// The manifest fields every test needs, for app `name`, with `fields` added or overriding
+    /// them; the payload folder is `payload`.
+    pub(crate) fn manifest_json(name: &str, fields: serde_json::Value) -> serde_json::Value {
+        let mut json = serde_json::json!({
+            "appName": name, "version": "1", "publisher": "p", "description": "d", "targets": [], "payloadDir": "payload"
+        });
+        if let (Some(json), serde_json::Value::Object(fields)) = (json.as_object_mut(), fields) {
+            json.extend(fields);
+        }
+        json
+    }
+
+    pub(crate) fn test_manifest(name: &str, fields: serde_json::Value) -> InstallManifest {
+        serde_json::from_value(manifest_json(name, fields)).expect("manifest")
+    }
+
+    /// Options installing into `root`, with the manifest there too; tests override the rest
+    /// with struct update syntax.
+    pub(crate) fn install_options(root: &Path, payload: PathBuf, backups: PathBuf) -> InstallOptions {
+        InstallOptions {
+            manifest_dir: root.to_path_buf(),
+            payload_source: payload,
+            target_base: root.to_path_buf(),
+            backups_root: backups,
+            trusted_key: None,
+            phase: None,
+            resume: false,
+            components: None,
+            prompt_values: BTreeMap::new(),
+            accepted_license: false,
+        }
+    }
+
+    pub(crate) fn step_context(target_base: &Path, payload_source: &Path) -> StepContext {
+        StepContext {
+            target_base: target_base.to_path_buf(),
+            payload_source: payload_source.to_path_buf(),
+            advanced_mode: false,
+            replace_locked_on_reboot: false,
+            prompts: PromptValues::default(),
+        }
+    }
+
+    pub(crate) fn spec(start: &str, end: &str, content: &str, strip_markers: bool) -> PatchSpec {
+        PatchSpec {
+            start_marker: start.to_string(),
+            end_marker: end.to_string(),
+            content: content.to_string(),
+            strip_markers,
+            ..PatchSpec::default()
+        }
+    }
+
+    #[test]
+    fn split_key_path_basic() {
+        let parts = split_key_path("theme.colors.primary").expect("valid path");
+        assert_eq!(parts, vec!["theme", "colors", "primary"]);
+    }
+
+    #[test]
+    fn split_key_path_escaped_dot() {
+        let parts = split_key_path("workbench\\.colorTheme").expect("valid path");
+        assert_eq!(parts, vec!["workbench.colorTheme"]);
+    }
+
+    #[test]
+    fn split_key_path_mixed_escape() {
+        let parts = split_key_path("workbench\\.colorTheme.ui").expect("valid path");
+        assert_eq!(parts, vec!["workbench.colorTheme", "ui"]);
+    }
+
+    #[test]
+    fn split_key_path_trailing_escape_is_error() {
+        let err = split_key_path("workbench\\.colorTheme\\").unwrap_err();
+        assert!(err.to_string().contains("escape"));
+    }
+
+    #[test]
+    fn split_key_path_empty_segment_is_error() {
+        let err = split_key_path("workbench..colorTheme").unwrap_err();
+        assert!(err.to_string().contains("empty segment"));
+    }
+
+    #[test]
+    fn patch_file_skips_when_block_matches() {
+        let path = temp_file("patch_idempotent.css", "a/*S*/old/*E*/b");
+        let first = patch_file(&path, &spec("/*S*/", "/*E*/", "new", false)).expect("patch");
+        assert_eq!(first, PatchOutcome::Applied { original: b"old".to_vec() });
+        let second = patch_file(&path, &spec("/*S*/", "/*E*/", "new", false)).expect("patch");
+        assert_eq!(second, PatchOutcome::AlreadyApplied);
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "a/*S*/new/*E*/b");
+    }
+
+    #[test]
+    fn patch_file_detects_patch_id_after_marker_strip() {
+        let path = temp_file("patch_id.css", "a/*S*/old/*E*/b");
+        let first = PatchSpec { patch_id: Some("misfit:v1".to_string()), ..spec("/*S*/", "/*E*/", "/* misfit:v1 */x", true) };
+        patch_file(&path, &first).expect("patch");
+        let second = PatchSpec { patch_id: Some("misfit:v1".to_string()), ..spec("/*S*/", "/*E*/", "/* misfit:v2 */y", true) };
+        let again = patch_file(&path, &second).expect("patch");
+        assert_eq!(again, PatchOutcome::AlreadyApplied);
+    }
+
+    #[test]
+    fn revert_patch_restores_block_and_keeps_other_edits() {
+        let path = temp_file("patch_revert.css", "a/*S*/old/*E*/b");
+        patch_file(&path, &spec("/*S*/", "/*E*/", "new", true)).expect("patch");
+        std::fs::write(&path, "edited anewb").unwrap();
+        let record = PatchRecord {
+            file: path.to_string_lossy().to_string(),
+            start_marker: "/*S*/".to_string(),
+            end_marker: "/*E*/".to_string(),
+            original: "/*S*/old/*E*/".to_string(),
+            original_base64: None,
+            applied: "new".to_string(),
+            markers_stripped: true,
+            matching: MarkerMatch::default(),
+            inserted: false,
+        };
+        revert_patch(&record).expect("revert");
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "edited a/*S*/old/*E*/b");
+    }
+
+    #[test]
+    fn patch_file_regex_markers() {
+        let path = temp_file("patch_regex.js", "x;/* start v1.2 */old/* end */y");
+        let regex_spec = PatchSpec {
+            matching: MarkerMatch { mode: MarkerMode::Regex, ..MarkerMatch::default() },
+            ..spec(r"/\* start v[\d.]+ \*/", r"/\* end \*/", "new", false)
+        };
+        patch_file(&path, &regex_spec).expect("patch");
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "x;/* start v1.2 */new/* end */y");
+    }
+
+    #[test]
+    fn patch_file_anchor_tolerates_drift() {
+        let path = temp_file("patch_anchor.js", "header\nfunction renderTitleBar(a, b) {\n  old();\n}\nfooter\n");
+        let anchor_spec = PatchSpec {
+            matching: MarkerMatch {
+                mode: MarkerMode::Anchor,
+                similarity: Some(0.8),
+                ..MarkerMatch::default()
+            },
+            ..spec("function renderTitleBar(a) {", "}", "  patched();\n", false)
+        };
+        patch_file(&path, &anchor_spec).expect("patch");
+        assert_eq!(
+            std::fs::read_to_string(&path).unwrap(),
+            "header\nfunction renderTitleBar(a, b) {\n  patched();\n}\nfooter\n"
+        );
+    }
+
+    #[test]
+    fn patch_file_blocks_is_all_or_nothing() {
+        let path = temp_file("patch_multi.css", "/*A*/1/*/A*/ /*B*/2/*/B*/");
+        let specs = vec![spec("/*A*/", "/*/A*/", "x", false), spec("/*C*/", "/*/C*/", "y", false)];
+        assert!(patch_file_blocks(&path, &specs).is_err());
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "/*A*/1/*/A*/ /*B*/2/*/B*/");
+
+        let specs = vec![spec("/*A*/", "/*/A*/", "x", false), spec("/*B*/", "/*/B*/", "y", false)];
+        let outcomes = patch_file_blocks(&path, &specs).expect("patch");
+        assert_eq!(outcomes.len(), 2);
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "/*A*/x/*/A*/ /*B*/y/*/B*/");
+    }
+
+    #[test]
+    fn patch_file_inserts_missing_block_after_anchor() {
+        let path = temp_file("patch_insert.css", "body {}\n.title {}\n");
+        let insert_spec = PatchSpec {
+            insert_if_missing: Some(InsertAnchor::AfterLineMatching { pattern: "^body".to_string() }),
+            ..spec("/*S*/", "/*E*/", "x", false)
+        };
+        assert_eq!(patch_file(&path, &insert_spec).expect("patch"), PatchOutcome::Inserted);
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "body {}\n/*S*/x/*E*/\n.title {}\n");
+        assert_eq!(patch_file(&path, &insert_spec).expect("patch"), PatchOutcome::AlreadyApplied);
+    }
+
+    #[test]
+    fn preview_patch_blocks_leaves_file_untouched() {
+        let path = temp_file("patch_preview.css", "a\n/*S*/\nold\n/*E*/\nb\n");
+        let preview = preview_patch_blocks(&path, &[spec("/*S*/\n", "/*E*/", "new\n", false)]).expect("preview");
+        assert!(preview.diff.contains("-old"));
+        assert!(preview.diff.contains("+new"));
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "a\n/*S*/\nold\n/*E*/\nb\n");
+    }
+
+    #[test]
+    fn patch_file_round_trips_non_utf8_bytes() {
+        let path = temp_file("patch_binary.bin", "");
+        let mut bytes = vec![0xff, 0xfe, 0x00];
+        bytes.extend_from_slice(b"/*S*/old/*E*/");
+        bytes.extend_from_slice(&[0xc3, 0x28, 0x80]);
+        std::fs::write(&path, &bytes).unwrap();
+
+        patch_file(&path, &spec("/*S*/", "/*E*/", "new", false)).expect("patch");
+        let mut expected = vec![0xff, 0xfe, 0x00];
+        expected.extend_from_slice(b"/*S*/new/*E*/");
+        expected.extend_from_slice(&[0xc3, 0x28, 0x80]);
+        assert_eq!(std::fs::read(&path).unwrap(), expected);
+    }
+
+    #[test]
+    fn base64_embed_limits_occurrences_and_builds_data_uri() {
+        let input = temp_file("embed_input.svg", "<svg/>");
+        let target = temp_file("embed_target.css", "a{b:url(LOGO)} c{d:url(LOGO)}");
+        let options = EmbedOptions { max_occurrences: Some(1), data_uri: true, replacements: None };
+        assert_eq!(base64_embed(&target, "LOGO", &input, &options).expect("embed"), 1);
+        assert_eq!(
+            std::fs::read_to_string(&target).unwrap(),
+            "a{b:url(data:image/svg+xml;base64,PHN2Zy8+)} c{d:url(LOGO)}"
+        );
+    }
+
+    #[test]
+    fn base64_embed_missing_placeholder_is_error() {
+        let input = temp_file("embed_missing_input.txt", "hi");
+        let target = temp_file("embed_missing_target.css", "nothing here");
+        let err = base64_embed(&target, "LOGO", &input, &EmbedOptions::default()).unwrap_err();
+        assert!(err.to_string().contains("not found"));
+    }
+
+    #[test]
+    fn verify_file_checks_json_path_and_contains() {
+        let path = temp_file("verify.json", r#"{"workbench.colorTheme": "Misfit", "ui": {"scale": 2}}"#);
+        let ok = VerifyChecks {
+            contains: Some("Misfit".to_string()),
+            json_path: Some("ui.scale".to_string()),
+            equals: Some(serde_json::json!(2)),
+            ..VerifyChecks::default()
+        };
+        verify_file(&path, &ok).expect("verify");
+
+        let wrong = VerifyChecks {
+            json_path: Some("workbench\\.colorTheme".to_string()),
+            equals: Some(serde_json::json!("Default")),
+            ..VerifyChecks::default()
+        };
+        assert!(verify_file(&path, &wrong).unwrap_err().to_string().contains("expected"));
+    }
+
+    #[test]
+    fn payload_lock_detects_tampering() {
+        let file = temp_file("lock_root/theme/styles.css", "body {}");
+        let root = file.parent().unwrap().parent().unwrap().to_path_buf();
+        let lock = build_payload_lock(&root, &[PathBuf::from("theme")]).expect("lock");
+        assert!(lock.files.contains_key("theme/styles.css"));
+        verify_payload_lock(&root, &lock).expect("intact");
+
+        std::fs::write(&file, "body { color: red }").unwrap();
+        let err = verify_payload_lock(&root, &lock).unwrap_err();
+        assert!(err.to_string().contains("theme/styles.css (modified)"));
+    }
+
+    #[test]
+    fn signature_detects_modified_manifest() {
+        let manifest = temp_file("sig/manifests/install.manifest.json", "{}");
+        let dir = manifest.parent().unwrap();
+        let (secret, _) = generate_signing_key().expect("key");
+        let public_key = sign_install(dir, &secret).expect("sign");
+        assert!(matches!(verify_install_signature(dir, Some(&public_key)).unwrap(), SignatureStatus::Valid { .. }));
+
+        std::fs::write(&manifest, "{\"tampered\": true}").unwrap();
+        assert!(matches!(verify_install_signature(dir, Some(&public_key)).unwrap(), SignatureStatus::Invalid { .. }));
+    }
+
+    #[test]
+    fn yaml_bundles_are_listed_and_signed() {
+        let yaml = "appName: Yaml\nversion: '1'\npublisher: p\ndescription: d\ntargets: []\npayloadDir: payload\ninstallSteps: []\n";
+        let manifest = temp_file("sig_yaml/manifests/install.manifest.yaml", yaml);
+        let extras = temp_file("sig_yaml/manifests/extras.manifest.yml", &yaml.replace("Yaml", "Extras"));
+        let dir = manifest.parent().unwrap();
+        let ids: Vec<String> = list_bundle_manifests(dir).unwrap().into_iter().map(|m| m.id).collect();
+        assert_eq!(ids, ["install", "extras"]);
+        assert_eq!(bundle_manifest_path(dir, DEFAULT_MANIFEST_ID).unwrap(), manifest);
+
+        let (secret, _) = generate_signing_key().expect("key");
+        let public_key = sign_install(dir, &secret).expect("sign");
+        assert!(matches!(verify_install_signature(dir, Some(&public_key)).unwrap(), SignatureStatus::Valid { .. }));
+        assert!(is_signed_manifest(&load_manifest(&extras).unwrap(), dir).unwrap());
+
+        std::fs::write(&extras, yaml.replace("Yaml", "Tampered")).unwrap();
+        assert!(matches!(verify_install_signature(dir, Some(&public_key)).unwrap(), SignatureStatus::Invalid { .. }));
+        std::fs::write(&extras, yaml.replace("Yaml", "Extras")).unwrap();
+        std::fs::write(&manifest, yaml.replace("installSteps: []", "installSteps: [{ type: delete, path: old.txt }]")).unwrap();
+        assert!(matches!(verify_install_signature(dir, Some(&public_key)).unwrap(), SignatureStatus::Invalid { .. }));
+    }
+
+    #[test]
+    fn only_the_signed_manifest_counts_as_signed() {
+        let json = manifest_json("Signed", serde_json::json!({
+            "installSteps": [{ "type": "delete", "path": "old.txt" }],
+            "profiles": { "beta": { "variables": { "channel": "beta" } } }
+        }));
+        let path = temp_file("signed/manifests/install.manifest.json", &json.to_string());
+        let dir = path.parent().unwrap();
+        let signed = load_manifest(&path).unwrap();
+        assert!(is_signed_manifest(&signed, dir).unwrap());
+        assert!(is_signed_manifest(&apply_profile(&signed, Some("beta")).unwrap(), dir).unwrap());
+
+        let mut swapped = signed.clone();
+        swapped.install_steps[0].step = serde_json::from_value(serde_json::json!({ "type": "runCommand", "command": "calc", "args": [] })).unwrap();
+        assert!(!is_signed_manifest(&swapped, dir).unwrap());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn secret_files_are_owner_only() {
+        use std::os::unix::fs::PermissionsExt;
+        let path = std::env::temp_dir().join(format!("misfit_secret_{}.key", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        write_secret_file(&path, b"secret").unwrap();
+        assert_eq!(std::fs::metadata(&path).unwrap().permissions().mode() & 0o777, 0o600);
+        assert!(write_secret_file(&path, b"other").is_err());
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn exe_trailers_round_trip() {
+        let exe = temp_file("trailer.exe", "MZ binary");
+        append_exe_trailer(&exe, b"MSFTKEY1", b"key").expect("append");
+        append_exe_trailer(&exe, b"MSFTTEST", b"other").expect("append");
+        assert_eq!(read_exe_trailer(&exe, b"MSFTKEY1").unwrap(), Some(b"key".to_vec()));
+        assert_eq!(read_exe_trailer(&exe, b"MSFTNONE").unwrap(), None);
+    }
+
+    #[test]
+    fn exe_trailers_are_found_before_an_authenticode_signature() {
+        // Minimal PE32+ headers: e_lfanew, "PE\0\0", optional header magic and the
+        // certificate table entry in the data directories
+        let mut exe = vec![0u8; 0x100];
+        exe[..2].copy_from_slice(b"MZ");
+        exe[0x3C] = 0x40;
+        exe[0x40..0x44].copy_from_slice(b"PE\0\0");
+        exe[0x58..0x5A].copy_from_slice(&0x20bu16.to_le_bytes());
+        let path = temp_file("signed.exe", "");
+        std::fs::write(&path, &exe).unwrap();
+        append_exe_trailer(&path, b"MSFTKEY1", b"key").unwrap();
+
+        // What a signing tool does: pad to 8 bytes, append the table, point the directory at it
+        let mut exe = std::fs::read(&path).unwrap();
+        exe.resize(exe.len().next_multiple_of(8), 0);
+        let table = exe.len() as u32;
+        exe.extend([0xCC; 24]);
+        exe[0xE8..0xEC].copy_from_slice(&table.to_le_bytes());
+        exe[0xEC..0xF0].copy_from_slice(&24u32.to_le_bytes());
+        std::fs::write(&path, &exe).unwrap();
+
+        assert_eq!(read_exe_trailer(&path, b"MSFTKEY1").unwrap(), Some(b"key".to_vec()));
+    }
+
+    #[test]
+    fn payload_archives_round_trip() {
+        let file = temp_file("archive_src/theme/a.css", "a {}");
+        temp_file("archive_src/theme/nested/b.json", "{}");
+        let root = file.parent().unwrap().parent().unwrap().to_path_buf();
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let script = temp_file("archive_src/theme/run.sh", "#!/bin/sh\n");
+            std::fs::set_permissions(&script, std::fs::Permissions::from_mode(0o755)).unwrap();
+            let _ = std::fs::remove_file(root.join("theme/link.css"));
+            std::os::unix::fs::symlink("a.css", root.join("theme/link.css")).unwrap();
+        }
+        for format in [PayloadCompression::Zip, PayloadCompression::TarZst] {
+            let archive = root.with_file_name(format.archive_name().unwrap());
+            create_payload_archive(&root, &[PathBuf::from("theme")], &archive, format, true).expect("archive");
+            let out = root.with_file_name(format!("archive_out_{:?}", format));
+            extract_payload_archive(&archive, &out).expect("extract");
+            assert_eq!(std::fs::read_to_string(out.join("theme/a.css")).unwrap(), "a {}");
+            assert_eq!(std::fs::read_to_string(out.join("theme/nested/b.json")).unwrap(), "{}");
+            #[cfg(unix)]
+            {
+                use std::os::unix::fs::PermissionsExt;
+                let mode = std::fs::metadata(out.join("theme/run.sh")).unwrap().permissions().mode();
+                assert_eq!(mode & 0o777, 0o755, "{:?}", format);
+                assert_eq!(std::fs::read_link(out.join("theme/link.css")).unwrap(), PathBuf::from("a.css"), "{:?}", format);
+            }
+        }
+    }
+
+    #[test]
+    fn write_zip_stores_entries_in_folders() {
+        let dir = temp_file("zip_entries/.keep", "").parent().unwrap().to_path_buf();
+        let archive = dir.join("entries.zip");
+        write_zip(&archive, &[("logs/misfit.log".to_string(), b"hello".to_vec())]).expect("zip");
+        extract_payload_archive(&archive, &dir.join("out")).expect("extract");
+        assert_eq!(std::fs::read_to_string(dir.join("out/logs/misfit.log")).unwrap(), "hello");
+    }
+
+    #[test]
+    fn embedded_bundle_round_trips_through_exe() {
+        let manifest = temp_file("bundle_src/manifests/install.manifest.json", "{}");
+        let root = manifest.parent().unwrap().parent().unwrap().to_path_buf();
+        let bundle = root.with_file_name("bundle.zip");
+        create_payload_archive(&root, &[PathBuf::from("manifests")], &bundle, PayloadCompression::Zip, false).expect("archive");
+
+        let exe = temp_file("bundle.exe", "MZ binary");
+        append_exe_trailer_file(&exe, b"MSFTPAY1", &bundle).expect("append");
+        append_exe_trailer(&exe, b"MSFTKEY1", b"key").expect("append");
+
+        let out = root.with_file_name("bundle_out");
+        assert!(extract_embedded_bundle(&exe, &out).expect("extract"));
+        assert!(out.join("manifests/install.manifest.json").exists());
+    }
+
+    #[test]
+    fn copy_plan_parallel_copies_tree_and_reports_totals() {
+        let file = temp_file("copy_src/a/one.txt", "1");
+        temp_file("copy_src/a/b/two.txt", "22");
+        let src = file.parent().unwrap().parent().unwrap().to_path_buf();
+        let dest = src.with_file_name("copy_dest");
+        let plan = plan_copy(&src, &dest, &[]).expect("plan");
+        let last = std::sync::Mutex::new(None);
+        copy_plan_parallel(&plan, &|p| *last.lock().unwrap() = Some(p.clone())).expect("copy");
+        assert_eq!(std::fs::read_to_string(dest.join("a/b/two.txt")).unwrap(), "22");
+        let last = last.into_inner().unwrap().expect("progress");
+        assert_eq!(last.total_bytes, 3);
+        assert_eq!(last.total_files, 2);
+    }
+
+    #[test]
+    fn split_glob_separates_literal_base() {
+        assert_eq!(split_glob("themes/**/*.json"), (PathBuf::from("themes"), Some("**/*.json".to_string())));
+        assert_eq!(split_glob("themes/dark"), (PathBuf::from("themes/dark"), None));
+    }
+
+    #[test]
+    fn plan_copy_glob_applies_excludes() {
+        let file = temp_file("glob_src/themes/a.json", "{}");
+        temp_file("glob_src/themes/node_modules/x.json", "{}");
+        temp_file("glob_src/themes/sub/b.json", "{}");
+        temp_file("glob_src/themes/sub/b.json.map", "");
+        let base = file.parent().unwrap().to_path_buf();
+        let dest = base.with_file_name("glob_dest");
+        let exclude = vec!["**/node_modules/**".to_string(), "*.map".to_string()];
+        let plan = plan_copy_glob(&base, "**/*.json", &dest, &exclude).expect("plan");
+        let mut copied: Vec<_> = plan.files.iter().map(|f| f.dest.strip_prefix(&dest).unwrap().to_path_buf()).collect();
+        copied.sort();
+        assert_eq!(copied, vec![PathBuf::from("a.json"), PathBuf::from("sub/b.json")]);
+    }
+
+    #[test]
+    fn overwrite_policy_never_keeps_existing_files() {
+        let src = temp_file("overwrite_src/config.json", "new");
+        let dest = temp_file("overwrite_dest/config.json", "custom");
+        let plan = plan_copy(&src, &dest, &[]).expect("plan");
+        let filtered = apply_overwrite_policy(plan.clone(), OverwritePolicy::Never).expect("filter");
+        assert!(filtered.plan.files.is_empty());
+        assert_eq!(filtered.skipped.len(), 1);
+
+        let prompted = apply_overwrite_policy(plan, OverwritePolicy::Prompt).expect("filter");
+        assert_eq!(prompted.conflicts.len(), 1);
+    }
+
+    #[test]
+    fn copy_preserves_mtime_and_symlinks() {
+        let src = temp_file("preserve_src/data.txt", "payload");
+        let root = src.parent().unwrap().to_path_buf();
+        let old = filetime::FileTime::from_unix_time(1_500_000_000, 0);
+        filetime::set_file_mtime(&src, old).expect("set mtime");
+        #[cfg(unix)]
+        {
+            let _ = fs::remove_file(root.join("link.txt"));
+            let _ = fs::remove_file(root.join("loop"));
+            std::os::unix::fs::symlink("data.txt", root.join("link.txt")).expect("symlink");
+            std::os::unix::fs::symlink(".", root.join("loop")).expect("symlink");
+        }
+        let dest = root.with_file_name("preserve_dest");
+        let _ = fs::remove_dir_all(&dest);
+        copy_plan_parallel(&plan_copy(&root, &dest, &[]).expect("plan"), &|_| {}).expect("copy");
+
+        let copied = fs::metadata(dest.join("data.txt")).expect("copied");
+        assert_eq!(filetime::FileTime::from_last_modification_time(&copied), old);
+        #[cfg(unix)]
+        {
+            assert_eq!(fs::read_link(dest.join("link.txt")).expect("link"), PathBuf::from("data.txt"));
+            assert_eq!(fs::read_link(dest.join("loop")).expect("loop"), PathBuf::from("."));
+        }
+    }
+
+    #[test]
+    fn backups_are_listed_inspected_and_deleted() {
+        let target = temp_file("backup_api/settings.json", "{\"a\":1}");
+        let root = target.with_file_name("backups");
+        let _ = fs::remove_dir_all(&root);
+        let dir = backup_files(&[target.to_string_lossy().to_string()], &root.join("MyApp")).expect("backup");
+        let id = format!("MyApp/{}", dir.file_name().unwrap().to_string_lossy());
+
+        let listed = list_backups(&root, None).expect("list");
+        assert_eq!(listed.len(), 1);
+        assert_eq!(listed[0].id, id);
+        assert_eq!(listed[0].app.as_deref(), Some("MyApp"));
+
+        let details = backup_details(&root, &id).expect("details");
+        assert_eq!(details.files.len(), 1);
+        assert_eq!(details.files[0].size, 7);
+
+        assert!(resolve_backup_id(&root, "../MyApp").is_err());
+        delete_backup(&root, &id).expect("delete");
+        assert!(list_backups(&root, Some("MyApp")).expect("list").is_empty());
+    }
+
+    #[test]
+    fn restore_backup_files_restores_only_selected() {
+        let first = temp_file("restore_subset/a.txt", "a-original");
+        let second = temp_file("restore_subset/b.txt", "b-original");
+        let root = first.with_file_name("backups");
+        let _ = fs::remove_dir_all(&root);
+        let paths = [first.to_string_lossy().to_string(), second.to_string_lossy().to_string()];
+        let dir = backup_files(&paths, &root).expect("backup");
+        fs::write(&first, "a-changed").unwrap();
+        fs::write(&second, "b-changed").unwrap();
+
+        let target = fs::canonicalize(&second).unwrap().to_string_lossy().to_string();
+        assert_eq!(restore_backup_files(&dir, &[target]).expect("restore"), 1);
+        assert_eq!(fs::read_to_string(&first).unwrap(), "a-changed");
+        assert_eq!(fs::read_to_string(&second).unwrap(), "b-original");
+        assert!(restore_backup_files(&dir, &["/not/backed/up".to_string()]).is_err());
+    }
+
+    #[test]
+    fn preview_restore_reports_per_file_status() {
+        let same = temp_file("restore_preview/same.txt", "same");
+        let changed = temp_file("restore_preview/changed.txt", "before");
+        let gone = temp_file("restore_preview/gone.txt", "gone");
+        let root = same.with_file_name("backups");
+        let _ = fs::remove_dir_all(&root);
+        let paths: Vec<String> = [&same, &changed, &gone].iter().map(|p| p.to_string_lossy().to_string()).collect();
+        let dir = backup_files(&paths, &root).expect("backup");
+        fs::write(&changed, "after").unwrap();
+        fs::remove_file(&gone).unwrap();
+
+        let preview = preview_restore(&dir).expect("preview");
+        let status = |name: &str| preview.iter().find(|e| e.target.ends_with(name)).map(|e| e.status);
+        assert_eq!(status("same.txt"), Some(RestoreStatus::Unchanged));
+        assert_eq!(status("changed.txt"), Some(RestoreStatus::Differs));
+        assert_eq!(status("gone.txt"), Some(RestoreStatus::Missing));
+    }
+
+    #[test]
+    fn prune_backups_keeps_last_n_and_honours_dry_run() {
+        let target = temp_file("prune/settings.json", "{}");
+        let root = target.with_file_name("backups");
+        let _ = fs::remove_dir_all(&root);
+        for stamp in ["20240101_000000", "20240102_000000", "20240103_000000"] {
+            let dir = root.join("App").join(format!("backup_{}", stamp));
+            fs::create_dir_all(&dir).unwrap();
+            fs::write(dir.join("restore_map.json"), "{}").unwrap();
+        }
+        let policy = RetentionPolicy { keep_last: Some(1), ..RetentionPolicy::default() };
+
+        let dry = prune_backups(&root, Some("App"), &policy, true).expect("dry run");
+        assert_eq!(dry.removed.len(), 2);
+        assert_eq!(list_backups(&root, Some("App")).unwrap().len(), 3);
+
+        let report = prune_backups(&root, Some("App"), &policy, false).expect("prune");
+        assert_eq!(report.kept, 1);
+        let left = list_backups(&root, Some("App")).unwrap();
+        assert_eq!(left.len(), 1);
+        assert_eq!(left[0].id, "App/backup_20240103_000000");
+    }
+
+    #[test]
+    fn backups_share_unchanged_content() {
+        let target = temp_file("dedup/settings.json", "{\"theme\":\"dark\"}");
+        let root = target.with_file_name("backups");
+        let _ = fs::remove_dir_all(&root);
+        let paths = [target.to_string_lossy().to_string()];
+        let first = backup_files(&paths, &root).expect("first backup");
+        let second = root.join("backup_19990101_000000");
+        fs::rename(&first, &second).unwrap();
+        backup_files(&paths, &root).expect("second backup");
+
+        let blobs = walkdir::WalkDir::new(root.join(OBJECT_STORE_DIR))
+            .into_iter()
+            .filter_map(|e| e.ok())
+            .filter(|e| e.file_type().is_file())
+            .count();
+        assert_eq!(blobs, 1);
+
+        fs::write(&target, "changed").unwrap();
+        restore_backup_dir(&second).expect("restore");
+        assert_eq!(fs::read_to_string(&target).unwrap(), "{\"theme\":\"dark\"}");
+    }
+
+    #[test]
+    fn cancelled_copy_stops_with_cancelled_code() {
+        let file = temp_file("cancel_src/one.txt", "1");
+        let src = file.parent().unwrap().to_path_buf();
+        let dest = src.with_file_name("cancel_dest");
+        let plan = plan_copy(&src, &dest, &[]).expect("plan");
+        let cancel = CancelToken::default();
+        cancel.clone().cancel();
+        let err = MisfitError::from(copy_plan_cancellable(&plan, &|_| {}, &cancel).unwrap_err());
+        assert_eq!(err.code, ErrorCode::Cancelled);
+        assert!(!dest.join("one.txt").exists());
+
+        cancel.reset();
+        copy_plan_cancellable(&plan, &|_| {}, &cancel).expect("copy");
+        assert!(dest.join("one.txt").exists());
+    }
+
+    #[test]
+    fn source_snapshot_detects_edits_and_removals() {
+        let file = temp_file("snapshot_src/a.txt", "one");
+        let other = temp_file("snapshot_src/b.txt", "two");
+        let src = file.parent().unwrap().to_path_buf();
+        let plan = plan_copy(&src, &src.with_file_name("snapshot_dest"), &[]).expect("plan");
+        let snapshot = snapshot_sources(&plan).expect("snapshot");
+        assert_eq!(snapshot.len(), 2);
+        verify_sources(&snapshot).expect("unchanged");
+
+        std::fs::write(&file, "edited").unwrap();
+        std::fs::remove_file(&other).unwrap();
+        let err = MisfitError::from(verify_sources(&snapshot).unwrap_err());
+        assert_eq!(err.code, ErrorCode::SourcesChanged);
+        assert!(err.message.contains("a.txt (modified)") && err.message.contains("b.txt (missing)"));
+    }
+
+    #[test]
+    fn stale_jobs_skips_copies_that_are_up_to_date() {
+        let file = temp_file("stale_src/a.txt", "one");
+        temp_file("stale_src/b.txt", "two");
+        let src = file.parent().unwrap().to_path_buf();
+        let dest = src.with_file_name("stale_dest");
+        let plan = plan_copy(&src, &dest, &[]).expect("plan");
+        assert_eq!(stale_jobs(plan.clone()).files.len(), 2);
+        copy_plan_parallel(&plan, &|_| {}).expect("copy");
+        assert!(stale_jobs(plan.clone()).files.is_empty());
+
+        std::fs::write(&file, "edited").unwrap();
+        let stale = stale_jobs(plan);
+        assert_eq!(stale.files.len(), 1);
+        assert_eq!(stale.files[0].src, file);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn run_command_streams_output_and_reports_failures() {
+        let dir = temp_file("run_command/marker.txt", "").parent().unwrap().to_path_buf();
+        let options = CommandOptions {
+            cwd: Some(dir.clone()),
+            env: BTreeMap::from([("MISFIT_GREETING".to_string(), "hello".to_string())]),
+            timeout: None,
+            shell: None,
+            elevate: false,
+        };
+        let script = |body: &str| vec!["-c".to_string(), body.to_string()];
+        let lines = std::sync::Mutex::new(Vec::new());
+        run_command("sh", &script("echo $MISFIT_GREETING; ls; echo oops >&2"), &options, &|l| lines.lock().unwrap().push(l.to_string())).expect("run");
+        let lines = lines.into_inner().unwrap();
+        assert!(lines.contains(&"hello".to_string()) && lines.contains(&"marker.txt".to_string()) && lines.contains(&"oops".to_string()));
+
+        let err = MisfitError::from(run_command("sh", &script("exit 3"), &options, &|_| {}).unwrap_err());
+        assert_eq!(err.exit_code, Some(3));
+
+        let timeout = CommandOptions { timeout: Some(std::time::Duration::from_millis(200)), ..options };
+        let err = MisfitError::from(run_command("sh", &script("sleep 5"), &timeout, &|_| {}).unwrap_err());
+        assert!(err.message.contains("timed out"));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn launch_detached_starts_the_target() {
+        let dir = temp_file("launch/marker.txt", "").parent().unwrap().to_path_buf();
+        let launched = dir.join("launched.txt");
+        let _ = std::fs::remove_file(&launched);
+        let target = LaunchTarget {
+            command: "sh".to_string(),
+            args: vec!["-c".to_string(), format!("echo ok > '{}'", launched.display())],
+        };
+        launch_detached(&target, &dir).expect("launch");
+        let deadline = std::time::Instant::now() + std::time::Duration::from_secs(5);
+        while !launched.exists() && std::time::Instant::now() < deadline {
+            std::thread::sleep(std::time::Duration::from_millis(20));
+        }
+        assert!(launched.exists());
+
+        let missing = LaunchTarget { command: "./no-such-app".to_string(), args: Vec::new() };
+        assert!(launch_detached(&missing, &dir).is_err());
+    }
+
+    #[test]
+    fn platform_filters_and_target_overrides() {
+        let manifest: InstallManifest = serde_json::from_value(serde_json::json!({
+            "appName": "Everywhere", "version": "1.0.0", "publisher": "", "description": "",
+            "targets": [], "payloadDir": "payload",
+            "installSteps": [
+                { "type": "delete", "path": "%APPDATA%/Code/User/old.json" },
+                { "type": "delete", "path": "%APPDATA%/Code/User/windows-only.json", "platforms": ["windows"] },
+                { "type": "delete", "path": "%APPDATA%/Code2/x.json" }
+            ],
+            "targetOverrides": {
+                "%APPDATA%/Code": { "linux": "~/.config/Code/", "macos": "~/Library/Application Support/Code" },
+                "%APPDATA%\\Code\\User": { "linux": "~/.config/Code/User" }
+            }
+        }))
+        .unwrap();
+        let paths = |manifest: &InstallManifest| -> Vec<String> {
+            manifest.install_steps.iter().map(|s| match &s.step { InstallStep::Delete { path } => path.clone(), _ => unreachable!() }).collect()
+        };
+        let linux = manifest.for_platform(Platform::Linux);
+        assert_eq!(paths(&linux), ["~/.config/Code/User/old.json", "~/.config/Code/User/windows-only.json", "%APPDATA%/Code2/x.json"]);
+        assert_eq!(paths(&manifest.for_platform(Platform::Macos))[0], "~/Library/Application Support/Code/User/old.json");
+        assert_eq!(paths(&manifest.for_platform(Platform::Windows)), paths(&manifest));
+
+        let indices: Vec<usize> = manifest.ordered_steps(None).iter().map(|(i, _)| *i).collect();
+        assert_eq!(indices, if cfg!(windows) { vec![0, 1, 2] } else { vec![0, 2] });
+    }
+
+    #[test]
+    fn deep_destinations_past_max_path_copy_patch_and_restore() {
+        let src = temp_file("long_src/node_modules/pkg/index.js", "// a\n// b\n");
+        let deep: PathBuf = (0..12).map(|i| format!("node_modules_level_{:02}_with_a_long_name", i)).collect();
+        let dest = src.parent().unwrap().parent().unwrap().parent().unwrap().with_file_name("long_dest").join(deep).join("index.js");
+        assert!(dest.to_string_lossy().len() > 300);
+
+        let plan = plan_copy(&src, &dest, &[]).expect("plan");
+        copy_plan_parallel(&plan, &|_| {}).expect("copy");
+        let backup_root = dest.ancestors().nth(13).unwrap().join("backups");
+        let backup = backup_files(&[dest.to_string_lossy().to_string()], &backup_root).expect("backup");
+        patch_file_blocks(&dest, &[spec("// a", "// b", "patched", false)]).expect("patch");
+        assert!(std::fs::read_to_string(&dest).unwrap().contains("patched"));
+        restore_backup_dir(&backup).expect("restore");
+        assert_eq!(std::fs::read_to_string(&dest).unwrap(), "// a\n// b\n");
+    }
+
+    #[test]
+    fn retry_if_locked_retries_only_lock_errors() {
+        // ERROR_SHARING_VIOLATION / EBUSY
+        let locked = || std::io::Error::from_raw_os_error(if cfg!(windows) { 32 } else { 16 });
+        let attempts = std::cell::Cell::new(0);
+        let result = retry_if_locked(|| {
+            attempts.set(attempts.get() + 1);
+            if attempts.get() < 3 { Err(locked()) } else { Ok(attempts.get()) }
+        });
+        assert_eq!(result.unwrap(), 3);
+        assert_eq!(MisfitError::from(locked()).code, ErrorCode::FileLocked);
+
+        attempts.set(0);
+        let missing = retry_if_locked(|| -> std::io::Result<()> {
+            attempts.set(attempts.get() + 1);
+            Err(std::io::ErrorKind::NotFound.into())
+        });
+        assert!(missing.is_err());
+        assert_eq!(attempts.get(), 1);
+    }
+
+    #[test]
+    fn write_atomic_keeps_permissions_and_leaves_no_temp_files() {
+        let target = temp_file("atomic/settings.json", "{}");
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            std::fs::set_permissions(&target, std::fs::Permissions::from_mode(0o640)).unwrap();
+            let link = target.with_file_name("link.json");
+            std::os::unix::fs::symlink(&target, &link).unwrap();
+            write_atomic(&link, b"{\"via\": \"link\"}").expect("write through link");
+            assert!(std::fs::symlink_metadata(&link).unwrap().file_type().is_symlink());
+            assert_eq!(std::fs::metadata(&target).unwrap().permissions().mode() & 0o777, 0o640);
+        }
+        write_atomic(&target, b"{\"ok\": true}").expect("write");
+        assert_eq!(std::fs::read_to_string(&target).unwrap(), "{\"ok\": true}");
+        let leftovers: Vec<_> = std::fs::read_dir(target.parent().unwrap())
+            .unwrap()
+            .flatten()
+            .filter(|e| e.file_name().to_string_lossy().ends_with(".tmp"))
+            .collect();
+        assert!(leftovers.is_empty());
+    }
+
+    #[test]
+    fn bundles_list_every_manifest_and_sign_the_extras() {
+        let manifest = |name: &str| manifest_json(name, serde_json::json!({ "payloadDir": "payloads", "installSteps": [] })).to_string();
+        let main = temp_file("bundle_ids/manifests/install.manifest.json", &manifest("Core"));
+        let dir = main.parent().unwrap();
+        let extras = temp_file("bundle_ids/manifests/extras.manifest.json", &manifest("Extras"));
+        temp_file("bundle_ids/manifests/Alpha.manifest.json", &manifest("Alpha"));
+        temp_file("bundle_ids/manifests/payload.lock.json", "{}");
+
+        let listed = list_bundle_manifests(dir).expect("list");
+        let ids: Vec<&str> = listed.iter().map(|m| m.id.as_str()).collect();
+        assert_eq!(ids, ["install", "Alpha", "extras"]);
+        assert_eq!(listed[2].app_name, "Extras");
+        assert_eq!(bundle_manifest_path(dir, "extras").unwrap(), extras);
+        assert!(bundle_manifest_path(dir, "../install").is_err());
+
+        let (secret, _) = generate_signing_key().expect("key");
+        let public_key = sign_install(dir, &secret).expect("sign");
+        std::fs::write(&extras, manifest("Tampered")).unwrap();
+        assert!(matches!(verify_install_signature(dir, Some(&public_key)).unwrap(), SignatureStatus::Invalid { .. }));
+    }
+
+    #[test]
+    fn load_manifest_expands_includes() {
+        temp_file("includes/manifests/steps/theme.json", r#"[
+            { "type": "delete", "path": "old.css" },
+            { "$include": "more/last.json" }
+        ]"#);
+        temp_file("includes/manifests/steps/more/last.json", r#"{ "type": "delete", "path": "last.css" }"#);
+        temp_file("includes/manifests/steps/hook.json", r#"[{ "type": "delete", "path": "cache", "onError": "continue" }]"#);
+        let json = manifest_json("Includes", serde_json::json!({
+            "payloadDir": "payloads",
+            "postInstall": [{ "$include": "steps/hook.json" }],
+            "installSteps": [{ "type": "delete", "path": "first.css" }, { "$include": "steps/theme.json" }]
+        }));
+        let manifest = temp_file("includes/manifests/install.manifest.json", &json.to_string());
+        let loaded = load_manifest(&manifest).expect("load");
+        let paths: Vec<&str> = loaded
+            .install_steps
+            .iter()
+            .map(|step| match &step.step {
+                InstallStep::Delete { path } => path.as_str(),
+                _ => "",
+            })
+            .collect();
+        assert_eq!(paths, ["first.css", "old.css", "last.css"]);
+        assert_eq!(loaded.post_install.len(), 1);
+
+        temp_file("includes/manifests/steps/more/last.json", r#"[{ "$include": "../theme.json" }]"#);
+        let err = load_manifest(&manifest).unwrap_err();
+        assert!(err.to_string().contains("includes itself"), "{}", err);
+        temp_file("includes/manifests/steps/more/last.json", r#"[{ "type": "delete" }]"#);
+        let err = load_manifest(&manifest).unwrap_err();
+        assert!(err.to_string().contains("Included step 1 is invalid"), "{}", err);
+
+        let json = manifest_json("Includes", serde_json::json!({ "postInstall": { "type": "delete", "path": "cache" } }));
+        let manifest = temp_file("includes/manifests/hook_object.manifest.json", &json.to_string());
+        let err = MisfitError::from(load_manifest(&manifest).unwrap_err());
+        assert_eq!(err.code, ErrorCode::InvalidManifest);
+        assert!(err.message.contains("postInstall must be an array"), "{}", err.message);
+    }
+
+    #[test]
+    fn load_manifest_reads_yaml_and_toml() {
+        temp_file("formats/manifests/steps/extra.toml", "type = \"delete\"\npath = \"extra.css\"\n");
+        let yaml = temp_file("formats/manifests/install.manifest.yaml", "appName: Yaml\nversion: \"1\"\npublisher: p\ndescription: d\ntargets: [app]\npayloadDir: payloads\ninstallSteps:\n  - type: setJsonValue\n    file: settings.json\n    keyPath: theme\n    value: |\n      line one\n      line two\n  - $include: steps/extra.toml\n");
+        let loaded = load_manifest(&yaml).expect("yaml");
+        assert_eq!(&*loaded.app_name, "Yaml");
+        match &loaded.install_steps[0].step {
+            InstallStep::SetJsonValue { value, .. } => assert_eq!(value, "line one\nline two\n"),
+            _ => panic!("expected a setJsonValue step"),
+        }
+        assert!(matches!(&loaded.install_steps[1].step, InstallStep::Delete { path } if path == "extra.css"));
+
+        let toml = temp_file("formats/manifests/install.manifest.toml", "appName = \"Toml\"\nversion = \"1\"\npublisher = \"p\"\ndescription = \"d\"\ntargets = []\npayloadDir = \"payloads\"\n\n[[installSteps]]\ntype = \"delete\"\npath = \"old.css\"\n");
+        let loaded = load_manifest(&toml).expect("toml");
+        assert_eq!(&*loaded.app_name, "Toml");
+        assert_eq!(loaded.install_steps.len(), 1);
+
+        let broken = temp_file("formats/manifests/broken.manifest.yml", "appName: [unclosed\n");
+        assert!(load_manifest(&broken).unwrap_err().to_string().contains("Failed to parse manifest"));
+    }
+
+    #[test]
+    fn patch_block_takes_inline_content() {
+        let content_file = temp_file("inline_patch/payloads/patch.css", "from file");
+        let payload = content_file.parent().unwrap();
+        let step = |extra: serde_json::Value| -> InstallStep {
+            let mut step = serde_json::json!({
+                "type": "patchBlock", "file": "a.css", "startMarker": "/* S */", "endMarker": "/* E */",
+                "replacements": { "{{color}}": "red" },
+                "blocks": [{ "startMarker": "/* S2 */", "endMarker": "/* E2 */", "content": "second" }]
+            });
+            step.as_object_mut().unwrap().extend(extra.as_object().unwrap().clone());
+            serde_json::from_value(step).expect("step")
+        };
+
+        let specs = patch_specs(&step(serde_json::json!({ "content": "body {\n  color: {{color}};\n}" })), payload, false).expect("inline");
+        assert_eq!(specs[0].content, "body {\n  color: red;\n}");
+        assert_eq!(specs[1].content, "second");
+        let specs = patch_specs(&step(serde_json::json!({ "contentFile": "patch.css" })), payload, false).expect("file");
+        assert_eq!(specs[0].content, "from file");
+
+        let both = step(serde_json::json!({ "content": "x", "contentFile": "patch.css" }));
+        assert!(patch_specs(&both, payload, false).unwrap_err().to_string().contains("not both"));
+        assert!(patch_specs(&step(serde_json::json!({})), payload, false).is_err());
+    }
+
+    #[test]
+    fn non_utf8_blocks_are_reverted_byte_for_byte() {
+        let before = b"a/*S*/caf\xe9/*E*/b".to_vec();
+        let path = temp_file("patch_latin1.css", "");
+        std::fs::write(&path, &before).unwrap();
+        let outcomes = patch_file_blocks(&path, &[spec("/*S*/", "/*E*/", "new", false)]).expect("patch");
+        let PatchOutcome::Applied { original } = &outcomes[0] else { panic!("not applied") };
+        let (original, original_base64) = original_fields(original.clone());
+        assert!(original.is_empty() && original_base64.is_some());
+        let record = PatchRecord {
+            file: path.to_string_lossy().to_string(),
+            start_marker: "/*S*/".to_string(),
+            end_marker: "/*E*/".to_string(),
+            original,
+            original_base64,
+            applied: "new".to_string(),
+            markers_stripped: false,
+            matching: MarkerMatch::default(),
+            inserted: false,
+        };
+        let record: PatchRecord = serde_json::from_str(&serde_json::to_string(&record).unwrap()).unwrap();
+        revert_patch(&record).expect("revert");
+        assert_eq!(std::fs::read(&path).unwrap(), before);
+    }
+
+    #[test]
+    fn extracted_payloads_are_deleted_with_their_guard() {
+        let file = temp_file("payload_guard/src/theme/app.css", "body {}");
+        let root = file.parent().unwrap().parent().unwrap().parent().unwrap().to_path_buf();
+        write_zip_files(&root.join("payloads.zip"), &[("theme/app.css".to_string(), file)]).unwrap();
+        let payload = resolve_payload_source(&root, "payload").unwrap();
+        assert!(payload.is_extracted());
+        let extracted = payload.path().to_path_buf();
+        assert_eq!(std::fs::read_to_string(extracted.join("theme/app.css")).unwrap(), "body {}");
+        drop(payload);
+        assert!(!extracted.exists());
+    }
+
+    #[test]
+    fn stripping_trailers_leaves_the_bare_executable() {
+        let exe = temp_file("strip_trailers.exe", "binary");
+        append_exe_trailer(&exe, PUBLIC_KEY_TRAILER, b"key").unwrap();
+        append_exe_trailer(&exe, PAYLOAD_TRAILER, b"bundle").unwrap();
+        strip_exe_trailers(&exe).unwrap();
+        assert_eq!(std::fs::read(&exe).unwrap(), b"binary");
+        strip_exe_trailers(&exe).unwrap();
+        assert_eq!(std::fs::read(&exe).unwrap(), b"binary");
+    }
+
+    #[test]
+    fn stripped_blocks_only_count_as_applied_when_the_receipt_says_so() {
+        let path = temp_file("stripped_anchor.css", "x/*S*/old/*E*/b");
+        assert!(matches!(patch_file(&path, &spec("/*S*/", "/*E*/", "x", true)).expect("patch"), PatchOutcome::Applied { .. }));
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "xxb");
+
+        // The file happened to hold the content already, so finding it proves nothing
+        assert!(patch_file(&path, &spec("/*S*/", "/*E*/", "x", true)).is_err());
+        let recorded = PatchSpec { stripped_content: Some("x".to_string()), ..spec("/*S*/", "/*E*/", "x", true) };
+        assert_eq!(patch_file(&path, &recorded).expect("patch"), PatchOutcome::AlreadyApplied);
+        let changed = PatchSpec { stripped_content: Some("x".to_string()), ..spec("/*S*/", "/*E*/", "y", true) };
+        assert!(patch_file(&path, &changed).is_err());
+    }
+
+    #[test]
+    fn size_limits_count_shared_backup_content_once() {
+        let target = temp_file("prune_shared/settings.json", "aaaaaaaaaa");
+        let root = target.with_file_name("backups");
+        let _ = fs::remove_dir_all(&root);
+        let app_root = root.join("App");
+        let paths = [target.to_string_lossy().to_string()];
+        for stamp in ["19990101_000000", "19990102_000000"] {
+            let dir = backup_files(&paths, &app_root).expect("backup");
+            fs::rename(&dir, app_root.join(format!("backup_{}", stamp))).unwrap();
+        }
+        fs::write(&target, "bbbbbbbbbb").unwrap();
+        backup_files(&paths, &app_root).expect("newest backup");
+
+        // 30 bytes across three backups, but only 20 stored
+        let policy = RetentionPolicy { max_total_bytes: Some(20), ..RetentionPolicy::default() };
+        assert!(prune_backups(&root, Some("App"), &policy, true).unwrap().removed.is_empty());
+
+        let policy = RetentionPolicy { max_total_bytes: Some(15), ..RetentionPolicy::default() };
+        let report = prune_backups(&root, Some("App"), &policy, false).expect("prune");
+        assert_eq!(report.removed.len(), 2);
+        assert_eq!(report.freed_bytes, 10);
+        let stored: Vec<_> = walkdir::WalkDir::new(app_root.join(OBJECT_STORE_DIR))
+            .into_iter()
+            .filter_map(|e| e.ok())
+            .filter(|e| e.file_type().is_file())
+            .map(|e| fs::read_to_string(e.path()).unwrap())
+            .collect();
+        assert_eq!(stored, ["bbbbbbbbbb"]);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn backups_keep_symlinks_inside_folders() {
+        let file = temp_file("backup_links/app/data.txt", "data");
+        let dir = file.parent().unwrap().to_path_buf();
+        let _ = std::fs::remove_file(dir.join("current.txt"));
+        std::os::unix::fs::symlink("data.txt", dir.join("current.txt")).unwrap();
+        let backup_root = dir.with_file_name("backups");
+        let backup = backup_files(&[dir.to_string_lossy().to_string()], &backup_root).expect("backup");
+        let objects = std::fs::read_to_string(backup.join(BACKUP_OBJECTS_FILE)).unwrap();
+        assert!(objects.contains("\"link\": \"data.txt\""), "{}", objects);
+        assert!(preview_restore(&backup).unwrap().iter().all(|entry| entry.status == RestoreStatus::Unchanged));
+
+        std::fs::remove_dir_all(&dir).unwrap();
+        restore_backup_dir(&backup).expect("restore");
+        assert_eq!(std::fs::read_link(dir.join("current.txt")).unwrap(), PathBuf::from("data.txt"));
+        assert_eq!(std::fs::read_to_string(dir.join("current.txt")).unwrap(), "data");
+    }
+}
@@ -0,0 +1,437 @@
+//! Static checks on a manifest for the studio: problems that would only show up on a user's
+//! machine (missing payload files, steps that can't have an effect) and things an author
+//! should double-check (unused payload, absolute paths, commands). The errors double as the
+//! studio's build validation (`validate_build`).
+
+use crate::*;
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "camelCase")]
+pub enum LintSeverity {
+    /// The install will fail.
+    Error,
+    /// Probably a mistake.
+    Warning,
+    /// Worth knowing, e.g. the manifest runs commands.
+    Info,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct LintIssue {
+    pub severity: LintSeverity,
+    /// Stable identifier of the check, e.g. `missing-payload`.
+    pub code: &'static str,
+    pub message: String,
+    /// Index into `installSteps`, when the issue is about one step.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub step_index: Option<usize>,
+}
+
+impl LintIssue {
+    fn new(severity: LintSeverity, code: &'static str, step_index: Option<usize>, message: String) -> Self {
+        LintIssue { severity, code, message, step_index }
+    }
+}
+
+/// How many unreferenced payload files are named in the `unused-payload` message.
+const UNUSED_PAYLOAD_SAMPLE: usize = 5;
+
+/// Checks `manifest`. `payload` lists every payload file as a `/`-separated path relative to
+/// `payloadDir`; without it the payload checks are skipped. Issues are sorted by severity,
+/// then step.
+pub fn lint_manifest(manifest: &InstallManifest, payload: Option<&[String]>) -> Vec<LintIssue> {
+    let advanced_mode = manifest.advanced_mode.unwrap_or(false);
+    let mut issues = Vec::new();
+    let mut referenced: HashSet<&str> = HashSet::new();
+    let mut json_keys: HashMap<(String, &str), usize> = HashMap::new();
+    let mut deleted: Vec<(String, usize)> = Vec::new();
+    // Every platform's steps, unlike `ordered_steps`
+    let mut steps: Vec<(usize, &ManifestStep)> = manifest.install_steps.iter().enumerate().collect();
+    steps.sort_by_key(|(_, s)| s.phase);
+
+    let payload_dir = match normalize_rel_path(&manifest.payload_dir, true) {
+        Ok(dir) => Some(lock_key(&dir)).filter(|dir| dir != "."),
+        Err(err) => {
+            let message = format!("payloadDir {:?} must be a folder inside the installer: {}", manifest.payload_dir, err);
+            issues.push(LintIssue::new(LintSeverity::Error, "payload-dir", None, message));
+            None
+        }
+    };
+    let payload_dir = payload_dir.as_deref();
+
+    for (position, &(index, step)) in steps.iter().enumerate() {
+        let at = Some(index);
+
+        for target in step_targets(step) {
+            if !advanced_mode && is_machine_specific(target) {
+                let message = format!("{} is an absolute path on this machine; use a folder token such as {{appData}}, an environment variable, or enable advancedMode", target);
+                issues.push(LintIssue::new(LintSeverity::Warning, "absolute-path", at, message));
+            }
+            let key = path_key(target);
+            let recreates = matches!(step.step, InstallStep::Copy { .. });
+            if let Some((_, by)) = deleted.iter().find(|(gone, _)| !recreates && is_within(&key, gone)) {
+                let message = format!("{} was deleted by step {}, so this step has nothing to work on", target, by + 1);
+                issues.push(LintIssue::new(LintSeverity::Warning, "unreachable-step", at, message));
+            }
+        }
+
+        match &step.step {
+            InstallStep::Copy { src, dest, exclude, .. } => {
+                // A copy recreates what an earlier step deleted
+                let dest_key = path_key(dest);
+                deleted.retain(|(gone, _)| !is_within(gone, &dest_key) && !is_within(&dest_key, gone));
+                if let Some(payload) = payload {
+                    let exclude = exclude.as_deref().unwrap_or_default();
+                    let matched = copied_payload(payload, src, exclude);
+                    if matched.is_empty() {
+                        let message = format!("Copy source {} matches no payload file", src);
+                        let (code, message) = match strip_payload_dir(src, payload_dir) {
+                            Some(rel) if !copied_payload(payload, &rel, exclude).is_empty() => ("payload-dir", payload_dir_hint(message, &rel)),
+                            _ => ("missing-payload", message),
+                        };
+                        issues.push(LintIssue::new(LintSeverity::Error, code, at, message));
+                    }
+                    referenced.extend(matched);
+                }
+            }
+            InstallStep::PatchBlock { file, start_marker, end_marker, content_file, content, blocks, replacements, .. } => {
+                let secrets: Vec<&str> = manifest.prompts.iter().filter(|p| p.secret).map(|p| p.name.as_str()).collect();
+                let uses_secret = replacements.iter().flat_map(|r| r.values()).any(|value| placeholders(value).iter().any(|(_, name)| secrets.contains(name)));
+                if advanced_mode && uses_secret {
+                    let message = format!("Patch of {} fills in a secret prompt; with advancedMode its markers are stripped and the patch can't be reverted", file);
+                    issues.push(LintIssue::new(LintSeverity::Error, "secret-without-markers", at, message));
+                }
+                let main_block = content_file.as_ref().or(content.as_ref()).map(|_| (start_marker, end_marker));
+                let markers = main_block.into_iter().chain(blocks.iter().flatten().map(|b| (&b.start_marker, &b.end_marker)));
+                for (start, end) in markers {
+                    if start.trim().is_empty() || end.trim().is_empty() {
+                        let message = format!("Patch of {} has an empty start or end marker", file);
+                        issues.push(LintIssue::new(LintSeverity::Error, "empty-marker", at, message));
+                    }
+                }
+                let content_files = content_file.iter().chain(blocks.iter().flatten().map(|b| &b.content_file)).filter(|f| !f.is_empty());
+                if let Some(payload) = payload {
+                    for content_file in content_files {
+                        check_payload_file(payload, payload_dir, content_file, "contentFile", at, &mut issues, &mut referenced);
+                    }
+                }
+                check_overwritten(&steps[position + 1..], file, index, &mut issues);
+            }
+            InstallStep::Base64Embed { file, placeholder, input_file, .. } => {
+                if placeholder.is_empty() {
+                    let message = format!("Embed into {} has an empty placeholder", file);
+                    issues.push(LintIssue::new(LintSeverity::Error, "empty-marker", at, message));
+                }
+                if let Some(payload) = payload {
+                    check_payload_file(payload, payload_dir, input_file, "inputFile", at, &mut issues, &mut referenced);
+                }
+                check_overwritten(&steps[position + 1..], file, index, &mut issues);
+            }
+            InstallStep::SetJsonValue { file, key_path, .. } => {
+                if let Some(earlier) = json_keys.insert((path_key(file), key_path.as_str()), index) {
+                    let message = format!("{} in {} is already set by step {}; only the last value is kept", key_path, file, earlier + 1);
+                    issues.push(LintIssue::new(LintSeverity::Warning, "duplicate-json-key", at, message));
+                }
+                check_overwritten(&steps[position + 1..], file, index, &mut issues);
+            }
+            InstallStep::RunCommand { command, args, .. } => {
+                let command_line = format!("{} {}", command, args.join(" "));
+                let message = format!("Runs `{}`; without advancedMode users confirm each command before it runs", command_line.trim_end());
+                issues.push(LintIssue::new(LintSeverity::Info, "run-command", at, message));
+            }
+            InstallStep::InstallVsix { vsix_file, .. } => {
+                if !vsix_file.to_lowercase().ends_with(".vsix") {
+                    let message = format!("{} is not a .vsix extension package", vsix_file);
+                    issues.push(LintIssue::new(LintSeverity::Warning, "not-vsix", at, message));
+                }
+                if let Some(payload) = payload {
+                    check_payload_file(payload, payload_dir, vsix_file, "vsixFile", at, &mut issues, &mut referenced);
+                }
+            }
+            InstallStep::FixVsCodeChecksums { app_dir } => {
+                // Checksums are taken from the files as they are when this step runs
+                let dir = path_key(app_dir);
+                let later_edit = steps[position + 1..].iter().find(|(_, s)| match &s.step {
+                    InstallStep::PatchBlock { file, .. } | InstallStep::Base64Embed { file, .. } => is_within(&path_key(file), &dir),
+                    InstallStep::Copy { dest, .. } => is_within(&path_key(dest), &dir),
+                    _ => false,
+                });
+                if let Some((by, _)) = later_edit {
+                    let message = format!("Step {} changes files in {} after their checksums are fixed; move this step after it", by + 1, app_dir);
+                    issues.push(LintIssue::new(LintSeverity::Warning, "checksums-before-patch", at, message));
+                }
+            }
+            InstallStep::Delete { path } => {
+                if let Err(problem) = check_delete_path(path) {
+                    issues.push(LintIssue::new(LintSeverity::Error, "unsafe-delete", at, problem));
+                }
+                deleted.push((path_key(path), index));
+            }
+            InstallStep::Verify { .. } => {}
+        }
+    }
+
+    if let (Some(payload), Some(license)) = (payload, manifest.license_file.as_deref().filter(|f| !f.trim().is_empty())) {
+        // Without the file the installer would skip the license screen silently
+        check_payload_file(payload, payload_dir, license, "licenseFile", None, &mut issues, &mut referenced);
+    }
+
+    if let Some(payload) = payload {
+        let unused: Vec<&String> = payload.iter().filter(|f| !referenced.contains(f.as_str())).collect();
+        if !unused.is_empty() {
+            let sample: Vec<&str> = unused.iter().take(UNUSED_PAYLOAD_SAMPLE).map(|f| f.as_str()).collect();
+            let more = unused.len().saturating_sub(UNUSED_PAYLOAD_SAMPLE);
+            let message = format!(
+                "{} payload file(s) are never used: {}{}",
+                unused.len(),
+                sample.join(", "),
+                if more > 0 { format!(" and {} more", more) } else { String::new() }
+            );
+            issues.push(LintIssue::new(LintSeverity::Warning, "unused-payload", None, message));
+        }
+    }
+
+    let (min, max) = (manifest.min_target_version.as_deref(), manifest.max_target_version.as_deref());
+    if (min.is_some() || max.is_some()) && manifest.target_version_from.is_none() {
+        let message = "minTargetVersion/maxTargetVersion need targetVersionFrom to read the installed version".to_string();
+        issues.push(LintIssue::new(LintSeverity::Error, "version-without-source", None, message));
+    }
+    if let (Some(min), Some(max)) = (min, max) {
+        if compare_versions(min, max, true) == std::cmp::Ordering::Greater {
+            let message = format!("minTargetVersion {} is newer than maxTargetVersion {}, so no version can be installed to", min, max);
+            issues.push(LintIssue::new(LintSeverity::Error, "empty-version-range", None, message));
+        }
+    }
+
+    let mut component_ids = HashSet::new();
+    for component in &manifest.components {
+        if !component_ids.insert(component.id.as_str()) {
+            let message = format!("Component id {:?} is declared twice", component.id);
+            issues.push(LintIssue::new(LintSeverity::Error, "duplicate-component", None, message));
+        }
+    }
+    for component in &manifest.components {
+        for dependency in component.depends_on.iter().filter(|id| !component_ids.contains(id.as_str())) {
+            let message = format!("Component {:?} depends on {:?}, which is not declared in components", component.id, dependency);
+            issues.push(LintIssue::new(LintSeverity::Error, "unknown-component", None, message));
+        }
+    }
+    for (index, step) in manifest.install_steps.iter().enumerate() {
+        if let Some(id) = step.component.as_deref().filter(|id| !component_ids.contains(id)) {
+            let message = format!("Step belongs to component {:?}, which is not declared in components", id);
+            issues.push(LintIssue::new(LintSeverity::Error, "unknown-component", Some(index), message));
+        }
+    }
+
+    if let Some(branding) = &manifest.branding {
+        for (field, color) in [("accentColor", &branding.accent_color), ("background", &branding.background)] {
+            if let Some(color) = color.as_deref().filter(|c| !is_hex_color(c.trim())) {
+                let message = format!("branding.{} {:?} must be a hex color such as #2f7a5b", field, color);
+                issues.push(LintIssue::new(LintSeverity::Error, "branding-color", None, message));
+            }
+        }
+        if let Some(logo) = branding.logo.as_deref().filter(|logo| logo_mime(logo).is_none()) {
+            let formats: Vec<&str> = LOGO_FORMATS.iter().map(|(ext, _)| *ext).collect();
+            let message = format!("branding.logo {} must be one of: {}", logo, formats.join(", "));
+            issues.push(LintIssue::new(LintSeverity::Error, "branding-logo", None, message));
+        }
+    }
+
+    issues.sort_by_key(|issue| (issue.severity, issue.step_index));
+    issues
+}
+
+/// The errors `lint_manifest` finds: what would make the built installer fail. Empty when the
+/// manifest and `payload` are safe to build.
+pub fn validate_build(manifest: &InstallManifest, payload: &[String]) -> Vec<LintIssue> {
+    let mut issues = lint_manifest(manifest, Some(payload));
+    issues.retain(|issue| issue.severity == LintSeverity::Error);
+    issues
+}
+
+/// Paths on the user's machine the step writes to or reads.
+fn step_targets(step: &InstallStep) -> Vec<&str> {
+    match step {
+        InstallStep::Copy { dest, .. } => vec![dest],
+        InstallStep::PatchBlock { file, .. }
+        | InstallStep::SetJsonValue { file, .. }
+        | InstallStep::Base64Embed { file, .. }
+        | InstallStep::Verify { file, .. } => vec![file],
+        InstallStep::Delete { path } => vec![path],
+        InstallStep::FixVsCodeChecksums { app_dir } => vec![app_dir],
+        InstallStep::InstallVsix { cli_path, .. } => cli_path.iter().map(String::as_str).collect(),
+        InstallStep::RunCommand { .. } => vec![],
+    }
+}
+
+/// Absolute paths written out literally (`C:/Users/me/...`) only exist on the author's machine;
+/// ones built from environment variables are fine.
+fn is_machine_specific(path: &str) -> bool {
+    let path = path.trim();
+    if path.starts_with('%') || path.starts_with('$') || path.starts_with('~') {
+        return false;
+    }
+    let bytes = path.as_bytes();
+    let drive = bytes.len() >= 2 && bytes[0].is_ascii_alphabetic() && bytes[1] == b':';
+    drive || path.starts_with('/') || path.starts_with('\\')
+}
+
+/// Comparable form of a manifest path: `/`-separated, no trailing slash, case-insensitive.
+fn path_key(path: &str) -> String {
+    path.trim().replace('\\', "/").trim_end_matches('/').to_lowercase()
+}
+
+fn is_within(path: &str, dir: &str) -> bool {
+    path == dir || path.strip_prefix(dir).is_some_and(|rest| rest.starts_with('/'))
+}
+
+/// Flags an edit that a later Copy onto the same file replaces.
+fn check_overwritten(later: &[(usize, &ManifestStep)], file: &str, index: usize, issues: &mut Vec<LintIssue>) {
+    let key = path_key(file);
+    let copy = later.iter().find(|(_, s)| matches!(&s.step, InstallStep::Copy { dest, .. } if path_key(dest) == key));
+    if let Some((by, _)) = copy {
+        let message = format!("{} is replaced by the copy in step {}, which undoes this step", file, by + 1);
+        issues.push(LintIssue::new(LintSeverity::Warning, "unreachable-step", Some(index), message));
+    }
+}
+
+/// `path` without a leading `payloadDir/`: paths in steps are already relative to it, so a
+/// repeated prefix points one folder too deep.
+fn strip_payload_dir(path: &str, payload_dir: Option<&str>) -> Option<String> {
+    let path = path.trim().replace('\\', "/");
+    let path = path.trim_start_matches("./");
+    let dir = payload_dir?;
+    let rest = path.get(dir.len()..)?;
+    (path[..dir.len()].eq_ignore_ascii_case(dir) && rest.starts_with('/')).then(|| rest[1..].to_string())
+}
+
+fn payload_dir_hint(message: String, rel: &str) -> String {
+    format!("{}; paths are relative to payloadDir, use {}", message, rel)
+}
+
+fn payload_key(file: &str) -> String {
+    normalize_rel_path(file, false).map(|p| lock_key(&p)).unwrap_or_default()
+}
+
+fn check_payload_file<'a>(
+    payload: &'a [String],
+    payload_dir: Option<&str>,
+    file: &str,
+    field: &str,
+    at: Option<usize>,
+    issues: &mut Vec<LintIssue>,
+    referenced: &mut HashSet<&'a str>,
+) {
+    let key = payload_key(file);
+    match payload.iter().find(|f| **f == key) {
+        Some(found) => {
+            referenced.insert(found);
+        }
+        None => {
+            let message = format!("{} {} is not in the payload", field, file);
+            let (code, message) = match strip_payload_dir(file, payload_dir) {
+                Some(rel) if payload.contains(&payload_key(&rel)) => ("payload-dir", payload_dir_hint(message, &rel)),
+                _ => ("missing-payload", message),
+            };
+            issues.push(LintIssue::new(LintSeverity::Error, code, at, message));
+        }
+    }
+}
+
+/// Payload files a Copy step picks up: everything under `src` (or matching it, for a glob)
+/// minus `exclude`.
+fn copied_payload<'a>(payload: &'a [String], src: &str, exclude: &[String]) -> Vec<&'a str> {
+    let exclude = compile_patterns(exclude).unwrap_or_default();
+    let (base, pattern) = if has_glob_meta(src) {
+        let (base, pattern) = split_glob(src);
+        (base.to_string_lossy().to_string(), pattern.and_then(|p| glob::Pattern::new(&p).ok()))
+    } else {
+        (src.to_string(), None)
+    };
+    let base = normalize_rel_path(&base, true).map(|p| lock_key(&p)).unwrap_or_default();
+    let base = if base == "." { String::new() } else { base };
+
+    payload
+        .iter()
+        .filter_map(|file| {
+            let rel = if base.is_empty() {
+                file.as_str()
+            } else if *file == base {
+                ""
+            } else {
+                file.strip_prefix(&base)?.strip_prefix('/')?
+            };
+            let included = pattern.as_ref().map_or(true, |p| p.matches(rel));
+            let excluded = !rel.is_empty() && exclude.iter().any(|p| p.matches(rel));
+            (included && !excluded).then_some(file.as_str())
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::test_manifest;
+
+    #[test]
+    fn lint_flags_payload_and_step_problems() {
+        let manifest = test_manifest("Lint", serde_json::json!({
+            "installSteps": [
+                { "type": "copy", "src": "themes/**/*.css", "dest": "%APPDATA%/App/themes" },
+                { "type": "patchBlock", "file": "C:/Users/me/app.css", "startMarker": "/*S*/", "endMarker": "/*E*/",
+                  "contentFile": "missing.txt" },
+                { "type": "setJsonValue", "file": "settings.json", "keyPath": "a", "value": 1 },
+                { "type": "setJsonValue", "file": "settings.json", "keyPath": "a", "value": 2 },
+                { "type": "delete", "path": "cache" },
+                { "type": "verify", "file": "cache/index.json" },
+                { "type": "runCommand", "command": "echo", "args": [] },
+                { "type": "copy", "src": "app.css", "dest": "C:\\Users\\me\\app.css" }
+            ]
+        }));
+        let payload: Vec<String> = ["themes/dark/a.css", "themes/dark/a.txt", "extra.bin"].iter().map(|s| s.to_string()).collect();
+
+        let issues = lint_manifest(&manifest, Some(&payload));
+        let found: Vec<(LintSeverity, &str, Option<usize>)> = issues.iter().map(|i| (i.severity, i.code, i.step_index)).collect();
+        assert_eq!(
+            found,
+            vec![
+                (LintSeverity::Error, "missing-payload", Some(1)),
+                (LintSeverity::Error, "missing-payload", Some(7)),
+                (LintSeverity::Warning, "unused-payload", None),
+                (LintSeverity::Warning, "absolute-path", Some(1)),
+                (LintSeverity::Warning, "unreachable-step", Some(1)),
+                (LintSeverity::Warning, "duplicate-json-key", Some(3)),
+                (LintSeverity::Warning, "unreachable-step", Some(5)),
+                (LintSeverity::Warning, "absolute-path", Some(7)),
+                (LintSeverity::Info, "run-command", Some(6)),
+            ]
+        );
+        assert!(issues[2].message.starts_with("2 payload file(s)"));
+        assert_eq!(lint_manifest(&manifest, None).len(), 6);
+    }
+
+    #[test]
+    fn build_validation_reports_only_blocking_problems() {
+        let manifest = test_manifest("Check", serde_json::json!({
+            "payloadDir": "payloads",
+            "installSteps": [
+                { "type": "copy", "src": "payloads/theme", "dest": "C:/theme" },
+                { "type": "patchBlock", "file": "app.css", "startMarker": "/*S*/", "endMarker": " ", "contentFile": "block.css" },
+                { "type": "base64Embed", "file": "app.css", "placeholder": "", "inputFile": "payloads/logo.png" },
+                { "type": "runCommand", "command": "echo", "args": [] }
+            ]
+        }));
+        let payload: Vec<String> = ["theme/a.css", "block.css", "logo.png"].iter().map(|s| s.to_string()).collect();
+
+        let issues = validate_build(&manifest, &payload);
+        let found: Vec<(&str, Option<usize>)> = issues.iter().map(|i| (i.code, i.step_index)).collect();
+        assert_eq!(found, vec![("payload-dir", Some(0)), ("empty-marker", Some(1)), ("empty-marker", Some(2)), ("payload-dir", Some(2))]);
+        assert!(issues[0].message.ends_with("use theme"), "{}", issues[0].message);
+
+        let mut escaping = manifest.clone();
+        escaping.payload_dir = "../outside".to_string();
+        escaping.install_steps.clear();
+        assert_eq!(validate_build(&escaping, &[])[0].code, "payload-dir");
+    }
+}
@@ -0,0 +1,125 @@
+//! Installer-facing text in several languages. `appName`, `description`, component names and
+//! the branding texts can be a plain string or a map keyed by locale (`{ "en": "My App", "de":
+//! "Meine App" }`); [`InstallManifest::localized`] picks one string each for the user's locale.
+
+use crate::*;
+use std::fmt;
+use std::ops::Deref;
+
+/// Locale used for a map's own text (backup folder names, logs, the receipt) when it has one.
+pub const DEFAULT_LOCALE: &str = "en";
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(untagged)]
+pub enum LocalizedText {
+    Plain(String),
+    ByLocale(BTreeMap<String, String>),
+}
+
+impl LocalizedText {
+    /// The text for `locale` (e.g. `de-AT`): an exact match, then the language without its
+    /// region (`de`), then [`DEFAULT_LOCALE`], then the first entry. Case and `_` vs `-` don't
+    /// matter.
+    pub fn get(&self, locale: &str) -> &str {
+        let LocalizedText::ByLocale(texts) = self else { return self };
+        let wanted = normalize_locale(locale);
+        let mut tag = wanted.as_str();
+        loop {
+            if let Some((_, text)) = texts.iter().find(|(key, _)| normalize_locale(key) == tag) {
+                return text;
+            }
+            match tag.rfind('-') {
+                Some(end) => tag = &tag[..end],
+                None => return self,
+            }
+        }
+    }
+}
+
+fn normalize_locale(locale: &str) -> String {
+    locale.trim().replace('_', "-").to_ascii_lowercase()
+}
+
+/// The locale-independent text: the string itself, or the [`DEFAULT_LOCALE`] (else first)
+/// entry of a map. This is what identifies the app, so it shouldn't change between releases.
+impl Deref for LocalizedText {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        match self {
+            LocalizedText::Plain(text) => text,
+            LocalizedText::ByLocale(texts) => texts
+                .iter()
+                .find(|(key, _)| normalize_locale(key) == DEFAULT_LOCALE)
+                .or_else(|| texts.iter().next())
+                .map_or("", |(_, text)| text),
+        }
+    }
+}
+
+impl fmt::Display for LocalizedText {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self)
+    }
+}
+
+impl From<&str> for LocalizedText {
+    fn from(text: &str) -> Self {
+        LocalizedText::Plain(text.to_string())
+    }
+}
+
+impl From<String> for LocalizedText {
+    fn from(text: String) -> Self {
+        LocalizedText::Plain(text)
+    }
+}
+
+impl InstallManifest {
+    /// The manifest with every localized string replaced by its text for `locale`, for
+    /// display. Install with the original: the app's backups are keyed on its default name.
+    pub fn localized(&self, locale: &str) -> InstallManifest {
+        let flatten = |text: &mut LocalizedText| *text = text.get(locale).into();
+        let mut manifest = self.clone();
+        flatten(&mut manifest.app_name);
+        flatten(&mut manifest.description);
+        if let Some(branding) = &mut manifest.branding {
+            branding.window_title.iter_mut().chain(&mut branding.welcome_text).for_each(flatten);
+        }
+        for component in &mut manifest.components {
+            flatten(&mut component.name);
+            if let Some(description) = &mut component.description {
+                flatten(description);
+            }
+        }
+        manifest
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn localized_strings_fall_back_to_language_then_english() {
+        let manifest: InstallManifest = serde_json::from_value(serde_json::json!({
+            "appName": { "de": "Meine App", "en": "My App", "pt_BR": "Meu App" },
+            "version": "1", "publisher": "p", "description": "Plain", "targets": [], "payloadDir": "payload",
+            "components": [{ "id": "extras", "name": { "fr": "Extras FR" } }],
+            "installSteps": []
+        }))
+        .unwrap();
+        assert_eq!(&*manifest.app_name, "My App");
+        assert_eq!(backup_namespace(&manifest.app_name), backup_namespace("My App"));
+
+        let german = manifest.localized("de-AT");
+        assert_eq!(german.app_name, LocalizedText::from("Meine App"));
+        assert_eq!(&*german.description, "Plain");
+        assert_eq!(&*german.components[0].name, "Extras FR");
+        assert_eq!(manifest.app_name.get("PT-br"), "Meu App");
+        assert_eq!(manifest.app_name.get("ja"), "My App");
+
+        let json = serde_json::to_value(&german).unwrap();
+        assert_eq!(json["appName"], "Meine App");
+    }
+}
@@ -0,0 +1,114 @@
+//! Manifest format versions. `manifestVersion` records the schema a manifest was written
+//! for; [`load_manifest`] upgrades older ones step by step through [`MIGRATIONS`] before
+//! parsing, so installers built by older Studio versions keep loading.
+
+use crate::*;
+
+/// Version Studio writes and the engine reads natively.
+pub const MANIFEST_VERSION: u32 = 1;
+
+/// Manifests written before `manifestVersion` existed.
+const UNVERSIONED: u32 = 1;
+
+/// Upgrades a manifest from version `from` to `from + 1`, working on the raw JSON so fields
+/// the current schema no longer has can still be read (e.g. renamed fields or an older
+/// marker syntax).
+pub struct Migration {
+    pub from: u32,
+    pub description: &'static str,
+    pub apply: fn(&mut serde_json::Value) -> Result<()>,
+}
+
+/// Registered migrations, one per version bump. When the schema changes incompatibly, raise
+/// [`MANIFEST_VERSION`] and add the step from the previous version here.
+pub const MIGRATIONS: &[Migration] = &[];
+
+pub(crate) fn current_manifest_version() -> u32 {
+    MANIFEST_VERSION
+}
+
+/// Brings the raw manifest `value` up to [`MANIFEST_VERSION`] and returns the descriptions of
+/// the migrations that ran. Manifests newer than this engine are refused.
+pub fn migrate_manifest(value: &mut serde_json::Value) -> Result<Vec<&'static str>> {
+    migrate_with(value, MIGRATIONS, MANIFEST_VERSION)
+}
+
+pub(crate) fn migrate_with(value: &mut serde_json::Value, migrations: &[Migration], target: u32) -> Result<Vec<&'static str>> {
+    let object = value.as_object_mut().ok_or_else(|| MisfitError::new(ErrorCode::InvalidManifest, "Manifest is not a JSON object"))?;
+    let mut version = match object.get("manifestVersion") {
+        None => UNVERSIONED,
+        Some(v) => v
+            .as_u64()
+            .and_then(|v| u32::try_from(v).ok())
+            .filter(|v| *v > 0)
+            .ok_or_else(|| MisfitError::new(ErrorCode::InvalidManifest, format!("manifestVersion must be a positive whole number, not {}", v)))?,
+    };
+    if version > target {
+        let message = format!("Manifest version {} is newer than this installer supports ({}); rebuild it with this Studio version", version, target);
+        return Err(MisfitError::new(ErrorCode::InvalidManifest, message).into());
+    }
+
+    let mut applied = Vec::new();
+    while version < target {
+        let migration = migrations
+            .iter()
+            .find(|m| m.from == version)
+            .ok_or_else(|| anyhow!("No migration from manifest version {}", version))?;
+        (migration.apply)(value).with_context(|| format!("Failed to migrate the manifest from version {}", version))?;
+        applied.push(migration.description);
+        version += 1;
+    }
+    value["manifestVersion"] = target.into();
+    Ok(applied)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::{manifest_json, temp_file};
+
+    #[test]
+    fn manifest_versions_are_migrated_in_order() {
+        fn rename_name(value: &mut serde_json::Value) -> Result<()> {
+            let object = value.as_object_mut().expect("object");
+            if let Some(name) = object.remove("name") {
+                object.insert("appName".to_string(), name);
+            }
+            Ok(())
+        }
+        fn legacy_markers(value: &mut serde_json::Value) -> Result<()> {
+            for step in value["installSteps"].as_array_mut().into_iter().flatten() {
+                if let Some(marker) = step.as_object_mut().and_then(|s| s.remove("marker")) {
+                    let marker = marker.as_str().unwrap_or_default().to_string();
+                    step["startMarker"] = format!("/* {} START */", marker).into();
+                    step["endMarker"] = format!("/* {} END */", marker).into();
+                }
+            }
+            Ok(())
+        }
+        let migrations = [
+            Migration { from: 2, description: "marker to startMarker/endMarker", apply: legacy_markers },
+            Migration { from: 1, description: "name to appName", apply: rename_name },
+        ];
+        let mut value = serde_json::json!({
+            "name": "Old", "version": "1", "publisher": "p", "description": "d", "targets": [], "payloadDir": "payloads",
+            "installSteps": [{ "type": "patchBlock", "file": "a.css", "marker": "THEME" }]
+        });
+        let applied = migrate_with(&mut value, &migrations, 3).expect("migrate");
+        assert_eq!(applied, ["name to appName", "marker to startMarker/endMarker"]);
+        assert_eq!(value["manifestVersion"], 3);
+        let manifest: InstallManifest = serde_json::from_value(value.clone()).expect("parse");
+        assert_eq!(&*manifest.app_name, "Old");
+        match &manifest.install_steps[0].step {
+            InstallStep::PatchBlock { start_marker, end_marker, .. } => assert_eq!((start_marker.as_str(), end_marker.as_str()), ("/* THEME START */", "/* THEME END */")),
+            _ => panic!("expected a patchBlock step"),
+        }
+        assert!(migrate_with(&mut value, &migrations, 3).expect("again").is_empty());
+
+        value["manifestVersion"] = 4.into();
+        assert!(migrate_with(&mut value, &migrations, 3).unwrap_err().to_string().contains("newer"));
+        let json = manifest_json("New", serde_json::json!({ "manifestVersion": 99, "payloadDir": "payloads", "installSteps": [] }));
+        let manifest = temp_file("versions/manifests/install.manifest.json", &json.to_string());
+        assert!(load_manifest(&manifest).is_err());
+    }
+}
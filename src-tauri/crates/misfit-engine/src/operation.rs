@@ -0,0 +1,109 @@
+//! Keeps two processes (two installers, or a Studio build and an install) from writing to the
+//! same place at once. An operation holds an OS file lock on a lockfile for the folder it works
+//! on, with its PID inside for the error the other process shows. The OS drops the lock when
+//! the process dies, so a crash never leaves a folder locked.
+
+use crate::*;
+use fs2::FileExt;
+use std::io::Write;
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+struct LockHolder {
+    pid: u32,
+    operation: String,
+    started_at: String,
+}
+
+/// Held while an operation runs; dropping it releases the lock.
+#[derive(Debug)]
+pub struct OperationLock {
+    file: fs::File,
+}
+
+impl Drop for OperationLock {
+    fn drop(&mut self) {
+        // The file stays: deleting it would let a waiting process lock a file nobody else sees
+        let _ = self.file.set_len(0);
+        let _ = FileExt::unlock(&self.file);
+    }
+}
+
+/// The lockfile guarding `scope`, named after a hash of its absolute path in the temp folder.
+/// Nothing is written to `scope` itself, so it can be missing, deleted or rebuilt while locked.
+fn operation_lock_path(scope: &Path) -> PathBuf {
+    let absolute = if scope.is_absolute() { scope.to_path_buf() } else { std::env::current_dir().unwrap_or_default().join(scope) };
+    let key = absolute.to_string_lossy().replace('\\', "/").trim_end_matches('/').to_string();
+    // Windows paths are case-insensitive
+    let key = if cfg!(windows) { key.to_lowercase() } else { key };
+    std::env::temp_dir().join("misfit-locks").join(format!("{}.lock", &sha256_hex(key.as_bytes())[..16]))
+}
+
+/// Locks `scope` for `operation` (e.g. "install", "build"), failing with
+/// [`ErrorCode::OperationInProgress`] while another process holds it.
+pub fn lock_operation(scope: &Path, operation: &str) -> Result<OperationLock> {
+    let path = operation_lock_path(scope);
+    if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+        fs::create_dir_all(parent).map_err(|e| MisfitError::from(e).with_path(parent))?;
+    }
+    let mut file = fs::OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .open(&path)
+        .map_err(|e| MisfitError::from(e).with_path(&path))?;
+    if FileExt::try_lock_exclusive(&file).is_err() {
+        // Windows locks are mandatory, so the holder can't always be read
+        let holder = fs::read_to_string(&path).ok().and_then(|text| serde_json::from_str::<LockHolder>(&text).ok());
+        let message = match holder {
+            Some(holder) => format!(
+                "Another operation is in progress on {} ({} by process {} since {})",
+                scope.display(),
+                holder.operation,
+                holder.pid,
+                holder.started_at
+            ),
+            None => format!("Another operation is in progress on {}", scope.display()),
+        };
+        return Err(MisfitError::new(ErrorCode::OperationInProgress, message).with_path(scope).into());
+    }
+    let holder = LockHolder { pid: std::process::id(), operation: operation.to_string(), started_at: chrono::Local::now().to_rfc3339() };
+    file.set_len(0)?;
+    file.write_all(serde_json::to_string(&holder)?.as_bytes())?;
+    file.flush()?;
+    Ok(OperationLock { file })
+}
+
+/// Locks the folder an install writes to, by its canonical path so two spellings of it share
+/// one lock. Taken next to the lock on the app's backups, which doesn't keep two apps (or two
+/// copies of a manifest under different names) from editing the same folder at once.
+pub fn lock_target(target_base: &Path, operation: &str) -> Result<OperationLock> {
+    let canonical = fs::canonicalize(target_base).unwrap_or_else(|_| target_base.to_path_buf());
+    lock_operation(&canonical, operation)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::temp_file;
+
+    #[test]
+    fn a_second_operation_on_the_same_folder_is_refused_until_the_first_ends() {
+        let scope = temp_file("oplock/backups/app/keep", "").parent().unwrap().to_path_buf();
+        let lock = lock_operation(&scope, "install").expect("lock");
+        let err = MisfitError::from(lock_operation(&scope, "restore").unwrap_err());
+        assert_eq!(err.code, ErrorCode::OperationInProgress);
+        assert!(err.message.contains(&format!("install by process {}", std::process::id())), "{}", err.message);
+        assert!(lock_operation(&scope.with_file_name("other"), "install").is_ok());
+
+        drop(lock);
+        assert!(lock_operation(&scope, "restore").is_ok());
+        assert!(scope.join("keep").exists());
+
+        let target = lock_target(&scope, "install").expect("lock target");
+        let err = MisfitError::from(lock_target(&scope.join("..").join("app"), "repair").unwrap_err());
+        assert_eq!(err.code, ErrorCode::OperationInProgress);
+        drop(target);
+    }
+}
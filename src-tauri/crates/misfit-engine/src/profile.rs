@@ -0,0 +1,126 @@
+//! Manifest profiles: named deployment variants (`dev`, `prod`, `insiders`) of one manifest.
+//! A profile replaces `{{name}}` variables, `targets` and fields of single steps; the result
+//! is an ordinary manifest.
+
+use crate::*;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct ManifestProfile {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    /// Replace the manifest's `variables` of the same name.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub variables: BTreeMap<String, String>,
+    /// Replaces the manifest's `targets`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub targets: Option<Vec<String>>,
+    /// Fields to change per step, keyed by index in `installSteps`; a `null` field is removed.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub steps: BTreeMap<usize, serde_json::Map<String, serde_json::Value>>,
+}
+
+/// `manifest` with `profile` applied (if any) and its `{{name}}` variables filled in, in steps,
+/// hooks, targets and the other paths. Placeholders without a variable are left as they are.
+pub fn apply_profile(manifest: &InstallManifest, profile: Option<&str>) -> Result<InstallManifest> {
+    let selected = match profile {
+        Some(name) => {
+            let found = manifest.profiles.get(name).ok_or_else(|| {
+                let known: Vec<&str> = manifest.profiles.keys().map(String::as_str).collect();
+                let known = if known.is_empty() { "none".to_string() } else { known.join(", ") };
+                MisfitError::new(ErrorCode::InvalidManifest, format!("Unknown profile {:?}; the manifest has {}", name, known))
+            })?;
+            Some((name, found))
+        }
+        None => None,
+    };
+    if selected.is_none() && manifest.variables.is_empty() {
+        return Ok(manifest.clone());
+    }
+
+    let mut variables = manifest.variables.clone();
+    let mut value = serde_json::to_value(manifest)?;
+    if let Some((name, profile)) = selected {
+        variables.extend(profile.variables.clone());
+        if let Some(targets) = &profile.targets {
+            value["targets"] = serde_json::to_value(targets)?;
+        }
+        for (index, fields) in &profile.steps {
+            let step = value["installSteps"].get_mut(*index).and_then(|s| s.as_object_mut()).ok_or_else(|| {
+                MisfitError::new(ErrorCode::InvalidManifest, format!("Profile {} changes step {}, which doesn't exist", name, index + 1))
+            })?;
+            for (key, field) in fields {
+                match field {
+                    serde_json::Value::Null => step.remove(key),
+                    field => step.insert(key.clone(), field.clone()),
+                };
+            }
+        }
+    }
+
+    // Profiles and variables stay as written, so the result can be applied again
+    let object = value.as_object_mut().ok_or_else(|| anyhow!("Manifest is not an object"))?;
+    for (_, item) in object.iter_mut().filter(|(key, _)| !matches!(key.as_str(), "profiles" | "variables")) {
+        for_each_string(item, &mut |s| {
+            let mut filled = String::new();
+            let mut rest = 0;
+            for (range, name) in placeholders(s) {
+                if let Some(value) = variables.get(name) {
+                    filled.push_str(&s[rest..range.start]);
+                    filled.push_str(value);
+                    rest = range.end;
+                }
+            }
+            filled.push_str(&s[rest..]);
+            *s = filled;
+        });
+    }
+    serde_json::from_value(value).map_err(|e| MisfitError::new(ErrorCode::InvalidManifest, format!("Manifest is invalid after applying its profile: {}", e)).into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::test_manifest;
+
+    #[test]
+    fn apply_profile_overrides_variables_targets_and_steps() {
+        let manifest = test_manifest("Profiles", serde_json::json!({
+            "targets": ["{{channel}}/app"], "payloadDir": "payloads",
+            "variables": { "channel": "stable" },
+            "profiles": {
+                "insiders": {
+                    "variables": { "channel": "insiders" },
+                    "steps": { "1": { "path": "beta.css" } }
+                },
+                "dev": { "targets": ["dev/app"], "steps": { "0": { "onError": null } } }
+            },
+            "installSteps": [
+                { "type": "delete", "path": "{{channel}}.css", "onError": "continue" },
+                { "type": "delete", "path": "{{unset}}.css" }
+            ]
+        }));
+        let paths = |m: &InstallManifest| -> Vec<String> {
+            m.install_steps.iter().map(|step| match &step.step {
+                InstallStep::Delete { path } => path.clone(),
+                _ => String::new(),
+            }).collect()
+        };
+
+        let plain = apply_profile(&manifest, None).expect("no profile");
+        assert_eq!(plain.targets, ["stable/app"]);
+        assert_eq!(paths(&plain), ["stable.css", "{{unset}}.css"]);
+
+        let insiders = apply_profile(&manifest, Some("insiders")).expect("insiders");
+        assert_eq!(insiders.targets, ["insiders/app"]);
+        assert_eq!(paths(&insiders), ["insiders.css", "beta.css"]);
+        assert_eq!(insiders.profiles, manifest.profiles);
+
+        let dev = apply_profile(&manifest, Some("dev")).expect("dev");
+        assert_eq!(dev.targets, ["dev/app"]);
+        assert_eq!(serde_json::to_value(&dev.install_steps[0]).unwrap().get("onError"), None);
+
+        let err = apply_profile(&manifest, Some("prod")).unwrap_err();
+        assert!(err.to_string().contains("dev, insiders"), "{}", err);
+    }
+}
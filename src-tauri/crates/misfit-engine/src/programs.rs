@@ -0,0 +1,160 @@
+//! The app's entry in Windows' Add/Remove Programs ("Installed apps"), kept for the current
+//! user under `HKCU\Software\Microsoft\Windows\CurrentVersion\Uninstall`. Other systems have no
+//! such list, so registering and unregistering do nothing there.
+
+use crate::*;
+
+const UNINSTALL_KEY: &str = r"Software\Microsoft\Windows\CurrentVersion\Uninstall";
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct ProgramEntry {
+    pub display_name: String,
+    pub display_version: String,
+    pub publisher: String,
+    /// An `.ico`, or an executable with an optional `,<icon index>`.
+    pub display_icon: Option<String>,
+    /// Command line Windows runs when the user picks Uninstall.
+    pub uninstall_string: String,
+    /// Same without any UI, for management tools.
+    pub quiet_uninstall_string: Option<String>,
+}
+
+/// Name of the registry key holding `app_name`'s entry.
+pub fn program_key(app_name: &str) -> String {
+    format!("Misfit.{}", backup_namespace(app_name))
+}
+
+/// Creates or updates `app_name`'s entry. Modify and Repair are hidden; the installer has
+/// neither.
+pub fn register_program(app_name: &str, entry: &ProgramEntry) -> Result<()> {
+    let install_date = chrono::Local::now().format("%Y%m%d").to_string();
+    let mut values = vec![
+        ("DisplayName", RegValue::Str(&entry.display_name)),
+        ("DisplayVersion", RegValue::Str(&entry.display_version)),
+        ("Publisher", RegValue::Str(&entry.publisher)),
+        ("UninstallString", RegValue::Str(&entry.uninstall_string)),
+        ("InstallDate", RegValue::Str(&install_date)),
+        ("NoModify", RegValue::Dword(1)),
+        ("NoRepair", RegValue::Dword(1)),
+    ];
+    if let Some(icon) = &entry.display_icon {
+        values.push(("DisplayIcon", RegValue::Str(icon)));
+    }
+    if let Some(quiet) = &entry.quiet_uninstall_string {
+        values.push(("QuietUninstallString", RegValue::Str(quiet)));
+    }
+    let key = format!(r"{}\{}", UNINSTALL_KEY, program_key(app_name));
+    write_user_key(&key, &values).context("Failed to register the app under Add/Remove Programs")?;
+    Ok(())
+}
+
+/// Removes `app_name`'s entry; succeeds when there is none.
+pub fn unregister_program(app_name: &str) -> Result<()> {
+    let key = format!(r"{}\{}", UNINSTALL_KEY, program_key(app_name));
+    delete_user_key(&key).context("Failed to remove the app from Add/Remove Programs")?;
+    Ok(())
+}
+
+// Only read by the Windows registry calls
+#[cfg_attr(not(windows), allow(dead_code))]
+enum RegValue<'a> {
+    Str(&'a str),
+    Dword(u32),
+}
+
+#[cfg(windows)]
+fn wide(text: &str) -> Vec<u16> {
+    text.encode_utf16().chain(Some(0)).collect()
+}
+
+#[cfg(windows)]
+fn reg_result(status: windows_sys::Win32::Foundation::WIN32_ERROR) -> std::io::Result<()> {
+    match status {
+        windows_sys::Win32::Foundation::ERROR_SUCCESS => Ok(()),
+        status => Err(std::io::Error::from_raw_os_error(status as i32)),
+    }
+}
+
+#[cfg(windows)]
+fn write_user_key(subkey: &str, values: &[(&str, RegValue)]) -> std::io::Result<()> {
+    use windows_sys::Win32::System::Registry::{
+        RegCloseKey, RegCreateKeyExW, RegSetValueExW, HKEY, HKEY_CURRENT_USER, KEY_WRITE, REG_DWORD, REG_OPTION_NON_VOLATILE, REG_SZ,
+    };
+
+    let subkey = wide(subkey);
+    let mut key: HKEY = std::ptr::null_mut();
+    // SAFETY: `subkey` is a NUL-terminated UTF-16 buffer and `key` a valid out pointer; the
+    // class, security attributes and disposition are optional.
+    reg_result(unsafe {
+        RegCreateKeyExW(
+            HKEY_CURRENT_USER,
+            subkey.as_ptr(),
+            0,
+            std::ptr::null(),
+            REG_OPTION_NON_VOLATILE,
+            KEY_WRITE,
+            std::ptr::null(),
+            &mut key,
+            std::ptr::null_mut(),
+        )
+    })?;
+    let written = values.iter().try_for_each(|(name, value)| {
+        let name = wide(name);
+        // SAFETY: `key` is open for writing and each data pointer covers the given byte length.
+        reg_result(match value {
+            RegValue::Str(text) => {
+                let data = wide(text);
+                unsafe { RegSetValueExW(key, name.as_ptr(), 0, REG_SZ, data.as_ptr().cast(), (data.len() * 2) as u32) }
+            }
+            RegValue::Dword(number) => unsafe { RegSetValueExW(key, name.as_ptr(), 0, REG_DWORD, (number as *const u32).cast(), 4) },
+        })
+    });
+    // SAFETY: `key` was opened above and is closed exactly once.
+    unsafe { RegCloseKey(key) };
+    written
+}
+
+#[cfg(windows)]
+fn delete_user_key(subkey: &str) -> std::io::Result<()> {
+    use windows_sys::Win32::Foundation::ERROR_FILE_NOT_FOUND;
+    use windows_sys::Win32::System::Registry::{RegDeleteTreeW, HKEY_CURRENT_USER};
+
+    let subkey = wide(subkey);
+    // SAFETY: `subkey` is a NUL-terminated UTF-16 buffer that outlives the call.
+    match unsafe { RegDeleteTreeW(HKEY_CURRENT_USER, subkey.as_ptr()) } {
+        ERROR_FILE_NOT_FOUND => Ok(()),
+        status => reg_result(status),
+    }
+}
+
+/// A string value under `root` (`HKEY_CURRENT_USER`, `HKEY_LOCAL_MACHINE`), or `None` when the
+/// key or value is missing or isn't a string.
+#[cfg(windows)]
+pub(crate) fn read_registry_string(root: windows_sys::Win32::System::Registry::HKEY, subkey: &str, name: &str) -> Option<String> {
+    use windows_sys::Win32::System::Registry::{RegGetValueW, RRF_RT_REG_SZ};
+
+    let subkey = wide(subkey);
+    let name = wide(name);
+    let mut size: u32 = 0;
+    // SAFETY: both strings are NUL-terminated; a null data pointer asks for the size only.
+    reg_result(unsafe { RegGetValueW(root, subkey.as_ptr(), name.as_ptr(), RRF_RT_REG_SZ, std::ptr::null_mut(), std::ptr::null_mut(), &mut size) }).ok()?;
+    let mut data = vec![0u16; (size as usize).div_ceil(2)];
+    // SAFETY: `data` holds `size` bytes, as the call above asked for.
+    reg_result(unsafe {
+        RegGetValueW(root, subkey.as_ptr(), name.as_ptr(), RRF_RT_REG_SZ, std::ptr::null_mut(), data.as_mut_ptr().cast(), &mut size)
+    })
+    .ok()?;
+    let end = data.iter().position(|&c| c == 0).unwrap_or(data.len());
+    Some(String::from_utf16_lossy(&data[..end]))
+}
+
+#[cfg(not(windows))]
+fn write_user_key(_subkey: &str, _values: &[(&str, RegValue)]) -> std::io::Result<()> {
+    Ok(())
+}
+
+#[cfg(not(windows))]
+fn delete_user_key(_subkey: &str) -> std::io::Result<()> {
+    Ok(())
+}
@@ -0,0 +1,168 @@
+//! Prompted variables: values the user enters at install time (a license key, an API token)
+//! that fill `{{name}}` placeholders in the `replacements` of `patchBlock`, `copy` and
+//! `base64Embed` steps. They are substituted in memory only, and `secret` values are masked
+//! again wherever the install records text, such as the receipt.
+
+use crate::*;
+use std::collections::BTreeSet;
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct PromptedVariable {
+    pub name: String,
+    /// Label of the input field; `name` when omitted.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub label: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    /// Used when the user leaves the field empty.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub default: Option<String>,
+    /// Hide the input and keep the value out of logs, reports and receipts.
+    #[serde(default)]
+    pub secret: bool,
+    /// An install without a value (or default) is refused.
+    #[serde(default = "required_by_default")]
+    pub required: bool,
+}
+
+fn required_by_default() -> bool {
+    true
+}
+
+/// The values of a manifest's prompted variables for one install. `Debug` hides secrets.
+#[derive(Clone, Default)]
+pub struct PromptValues {
+    values: BTreeMap<String, String>,
+    secret: BTreeSet<String>,
+}
+
+impl std::fmt::Debug for PromptValues {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let shown = self.values.iter().map(|(name, value)| (name, if self.secret.contains(name) { "***" } else { value.as_str() }));
+        f.debug_map().entries(shown).finish()
+    }
+}
+
+impl PromptValues {
+    /// Checks `given` against `manifest.prompts`, filling in defaults. Unknown names and
+    /// missing required values are errors.
+    pub fn resolve(manifest: &InstallManifest, given: &BTreeMap<String, String>) -> Result<PromptValues> {
+        if let Some(unknown) = given.keys().find(|name| !manifest.prompts.iter().any(|p| &p.name == *name)) {
+            return Err(MisfitError::new(ErrorCode::ValidationFailed, format!("The manifest doesn't prompt for {:?}", unknown)).into());
+        }
+        let mut resolved = PromptValues::default();
+        let mut missing = Vec::new();
+        for prompt in &manifest.prompts {
+            let value = given.get(&prompt.name).filter(|v| !v.is_empty()).or(prompt.default.as_ref());
+            match value {
+                Some(value) => {
+                    resolved.values.insert(prompt.name.clone(), value.clone());
+                }
+                None if prompt.required => missing.push(prompt.label.as_deref().unwrap_or(&prompt.name)),
+                None => {}
+            }
+            if prompt.secret {
+                resolved.secret.insert(prompt.name.clone());
+            }
+        }
+        if !missing.is_empty() {
+            return Err(MisfitError::new(ErrorCode::ValidationFailed, format!("Missing value for {}", missing.join(", "))).into());
+        }
+        Ok(resolved)
+    }
+
+    /// `step` with `{{name}}` placeholders in its `replacements` values filled in.
+    pub fn fill(&self, step: &InstallStep) -> InstallStep {
+        let mut step = step.clone();
+        let replacements = match &mut step {
+            InstallStep::PatchBlock { replacements, .. } | InstallStep::Copy { replacements, .. } | InstallStep::Base64Embed { replacements, .. } => replacements,
+            _ => return step,
+        };
+        for value in replacements.iter_mut().flat_map(|map| map.values_mut()) {
+            let mut filled = String::new();
+            let mut rest = 0;
+            for (range, name) in placeholders(value) {
+                if let Some(prompted) = self.values.get(name) {
+                    filled.push_str(&value[rest..range.start]);
+                    filled.push_str(prompted);
+                    rest = range.end;
+                }
+            }
+            filled.push_str(&value[rest..]);
+            *value = filled;
+        }
+        step
+    }
+
+    /// `text` with every secret value put back as its `{{name}}` placeholder.
+    pub fn redact(&self, text: &str) -> String {
+        self.secret
+            .iter()
+            .filter_map(|name| self.values.get(name).filter(|v| !v.is_empty()).map(|value| (name, value)))
+            .fold(text.to_string(), |text, (name, value)| text.replace(value.as_str(), &format!("{{{{{}}}}}", name)))
+    }
+}
+
+/// Values for `manifest`'s prompts from `MISFIT_VAR_<NAME>` environment variables (the name
+/// uppercased), for headless installs that can't ask.
+pub fn prompt_values_from_env(manifest: &InstallManifest) -> BTreeMap<String, String> {
+    manifest
+        .prompts
+        .iter()
+        .filter_map(|prompt| std::env::var(format!("MISFIT_VAR_{}", prompt.name.to_ascii_uppercase())).ok().map(|value| (prompt.name.clone(), value)))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::{Quiet, install_options, temp_file, test_manifest};
+
+    #[test]
+    fn secret_prompts_fill_replacements_but_stay_out_of_the_receipt() {
+        let config = temp_file("prompts/config.js", "// START\n// END\n");
+        let root = config.parent().unwrap().to_path_buf();
+        std::fs::create_dir_all(root.join("payload")).unwrap();
+        let manifest = test_manifest("Prompts", serde_json::json!({
+            "prompts": [
+                { "name": "apiKey", "label": "API key", "secret": true },
+                { "name": "region", "default": "eu" }
+            ],
+            "installSteps": [{
+                "type": "patchBlock", "file": "config.js", "startMarker": "// START", "endMarker": "// END",
+                "content": "\nkey = \"@KEY@\"; region = \"@REGION@\";\n",
+                "replacements": { "@KEY@": "{{apiKey}}", "@REGION@": "{{region}}" }
+            }]
+        }));
+        let mut options = install_options(&root, root.join("payload"), root.join("backups"));
+        let err = run_install(&manifest, &options, &Quiet).unwrap_err();
+        assert!(err.to_string().contains("Missing value for API key"), "{}", err);
+
+        options.prompt_values.insert("apiKey".to_string(), "sk-12345".to_string());
+        run_install(&manifest, &options, &Quiet).expect("install");
+        assert!(std::fs::read_to_string(&config).unwrap().contains("key = \"sk-12345\"; region = \"eu\";"));
+        let app_root = root.join("backups").join(backup_namespace("Prompts"));
+        let receipt = std::fs::read_to_string(app_root.join(RECEIPT_FILE)).unwrap();
+        assert!(!receipt.contains("sk-12345") && receipt.contains("{{apiKey}}"), "{}", receipt);
+        let values = PromptValues::resolve(&manifest, &options.prompt_values).unwrap();
+        assert!(!format!("{:?}", values).contains("sk-12345"));
+
+        revert_all_patches(&app_root, &Quiet).expect("revert");
+        assert_eq!(std::fs::read_to_string(&config).unwrap(), "// START\n// END\n");
+
+        // Text that was there before goes back unchanged, even where it matches the secret
+        std::fs::write(&config, "// START\nold = \"sk-12345\";\n// END\n").unwrap();
+        run_install(&manifest, &options, &Quiet).expect("install");
+        revert_all_patches(&app_root, &Quiet).expect("revert");
+        assert_eq!(std::fs::read_to_string(&config).unwrap(), "// START\nold = \"sk-12345\";\n// END\n");
+
+        // Without markers the block couldn't be found again, so that combination is refused
+        let mut stripped = manifest.clone();
+        stripped.advanced_mode = Some(true);
+        let err = MisfitError::from(run_install(&stripped, &options, &Quiet).unwrap_err());
+        assert_eq!(err.code, ErrorCode::InvalidManifest);
+        assert!(lint_manifest(&stripped, None).iter().any(|issue| issue.code == "secret-without-markers"));
+        assert!(lint_manifest(&manifest, None).iter().all(|issue| issue.code != "secret-without-markers"));
+    }
+}
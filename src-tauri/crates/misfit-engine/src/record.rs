@@ -0,0 +1,458 @@
+//! Record mode: snapshot a target folder, let the author change it by hand, then turn the
+//! difference into install steps and payload files. New and binary files become Copy steps,
+//! edited JSON becomes SetJsonValue steps, other edited text becomes PatchBlock steps and
+//! removed files become Delete steps. [`generate_manifest_from_diff`] does the same for two
+//! separate folders.
+
+use crate::*;
+use std::collections::BTreeSet;
+
+/// Text files up to this size keep their contents in the snapshot so edits can become patches.
+const MAX_TEXT_BYTES: u64 = 1024 * 1024;
+/// How many lines a generated patch marker may grow to while looking for a unique one.
+const MAX_MARKER_LINES: usize = 5;
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct Snapshot {
+    pub root: PathBuf,
+    pub created_at: String,
+    /// Folders relative to `root`, `/`-separated.
+    pub dirs: BTreeSet<String>,
+    pub files: BTreeMap<String, SnapshotFile>,
+    /// Globs the snapshot skipped; [`record_changes`] skips them in the after-snapshot too.
+    #[serde(default)]
+    pub exclude: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct SnapshotFile {
+    pub size: u64,
+    pub sha256: String,
+    /// Contents of small UTF-8 files; binary and large files only keep the hash.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub text: Option<String>,
+}
+
+/// Steps and payload produced by [`record_changes`].
+#[derive(Serialize, Debug, Clone, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct RecordedChanges {
+    pub steps: Vec<ManifestStep>,
+    /// `(source, payload-relative destination)` pairs, in the shape the studio build takes.
+    pub payload_files: Vec<(String, String)>,
+    /// Changes that were recorded less precisely than they could be, e.g. a text file that is
+    /// copied whole because no markers could be found.
+    pub warnings: Vec<String>,
+}
+
+/// Hashes every file under `root` (skipping `exclude` globs, matched against `/`-separated
+/// relative paths) and keeps the text of small UTF-8 files.
+pub fn take_snapshot(root: &Path, exclude: &[String]) -> Result<Snapshot> {
+    if !root.is_dir() {
+        return Err(MisfitError::new(ErrorCode::NotFound, format!("Folder not found: {}", root.display())).with_path(root).into());
+    }
+    let mut snapshot = Snapshot {
+        root: root.to_path_buf(),
+        created_at: chrono::Local::now().to_rfc3339(),
+        dirs: BTreeSet::new(),
+        files: BTreeMap::new(),
+        exclude: exclude.to_vec(),
+    };
+    let exclude = compile_patterns(exclude)?;
+    let mut walker = walkdir::WalkDir::new(root).follow_links(false).into_iter();
+    while let Some(entry) = walker.next() {
+        let entry = entry?;
+        let key = lock_key(entry.path().strip_prefix(root).context("Walk escaped snapshot root")?);
+        if key.is_empty() {
+            continue;
+        }
+        if exclude.iter().any(|p| p.matches(&key)) {
+            if entry.file_type().is_dir() {
+                walker.skip_current_dir();
+            }
+            continue;
+        }
+        if entry.file_type().is_dir() {
+            snapshot.dirs.insert(key);
+        } else if entry.file_type().is_file() {
+            snapshot.files.insert(key, snapshot_file(entry.path())?);
+        }
+    }
+    Ok(snapshot)
+}
+
+fn snapshot_file(path: &Path) -> Result<SnapshotFile> {
+    let bytes = fs::read(path).with_context(|| format!("Failed to read {}", path.display()))?;
+    let text = if bytes.len() as u64 <= MAX_TEXT_BYTES && !bytes.contains(&0) {
+        String::from_utf8(bytes.clone()).ok()
+    } else {
+        None
+    };
+    Ok(SnapshotFile { size: bytes.len() as u64, sha256: sha256_hex(&bytes), text })
+}
+
+/// Compares `before` with the current contents of `after_root` and writes the payload for the
+/// resulting steps to `payload_root` (`files/` for copies, `patches/` for patch contents;
+/// both are emptied first). Step targets are `target_prefix` joined with the file's relative path.
+/// Files matching the snapshot's `exclude` globs are left out on both sides.
+pub fn record_changes(before: &Snapshot, after_root: &Path, target_prefix: &str, payload_root: &Path) -> Result<RecordedChanges> {
+    let after = take_snapshot(after_root, &before.exclude)?;
+    for dir in ["files", "patches"] {
+        let stale = payload_root.join(dir);
+        if stale.exists() {
+            fs::remove_dir_all(&stale).with_context(|| format!("Failed to clear {}", stale.display()))?;
+        }
+    }
+    let prefix = target_prefix.trim_end_matches(['/', '\\']);
+    let target = |rel: &str| if prefix.is_empty() { rel.to_string() } else { format!("{}/{}", prefix, rel) };
+    let mut changes = RecordedChanges::default();
+    let mut copied_dirs: Vec<String> = Vec::new();
+
+    for (rel, file) in &after.files {
+        if copied_dirs.iter().any(|dir| rel.starts_with(&format!("{}/", dir))) {
+            continue;
+        }
+        let Some(old) = before.files.get(rel) else {
+            // A file in a folder that didn't exist copies the whole new folder once
+            let new_dir = ancestors(rel).find(|dir| !before.dirs.contains(*dir));
+            let copied = new_dir.unwrap_or(rel);
+            copy_to_payload(&after_root.join(copied), &payload_root.join("files").join(copied))?;
+            changes.steps.push(step(InstallStep::Copy {
+                src: format!("files/{}", copied),
+                dest: target(copied),
+                exclude: None,
+                overwrite: OverwritePolicy::Always,
+                replacements: None,
+            }));
+            if let Some(dir) = new_dir {
+                copied_dirs.push(dir.to_string());
+            }
+            continue;
+        };
+        if old.sha256 == file.sha256 {
+            continue;
+        }
+        if let (Some(old_text), Some(new_text)) = (&old.text, &file.text) {
+            if let Some(steps) = json_steps(old_text, new_text, &target(rel)) {
+                changes.steps.extend(steps);
+                continue;
+            }
+            if let Some(patch) = text_patch(old_text, new_text) {
+                let content_file = format!("patches/{}", rel);
+                write_payload(&payload_root.join(&content_file), patch.content.as_bytes())?;
+                changes.steps.push(step(InstallStep::PatchBlock {
+                    file: target(rel),
+                    start_marker: patch.start_marker,
+                    end_marker: patch.end_marker,
+                    content_file: Some(content_file),
+                    content: None,
+                    replacements: None,
+                    blocks: None,
+                    patch_id: None,
+                    marker_mode: MarkerMode::Exact,
+                    start_offset: 0,
+                    end_offset: 0,
+                    similarity: None,
+                    insert_if_missing: None,
+                }));
+                continue;
+            }
+        }
+        // Binary files, and edits too close to the start or end of a file for markers
+        if old.text.is_some() && file.text.is_some() {
+            changes.warnings.push(format!("{} is copied whole because no unchanged lines surround the edit", rel));
+        }
+        copy_to_payload(&after_root.join(rel), &payload_root.join("files").join(rel))?;
+        changes.steps.push(step(InstallStep::Copy {
+            src: format!("files/{}", rel),
+            dest: target(rel),
+            exclude: None,
+            overwrite: OverwritePolicy::Always,
+            replacements: None,
+        }));
+    }
+
+    let mut deleted_dirs: Vec<&str> = Vec::new();
+    for rel in before.files.keys().filter(|rel| !after.files.contains_key(*rel)) {
+        if deleted_dirs.iter().any(|dir| rel.starts_with(&format!("{}/", dir))) {
+            continue;
+        }
+        // A removed folder is deleted as a whole
+        let gone_dir = ancestors(rel).find(|dir| !after.dirs.contains(*dir));
+        changes.steps.push(step(InstallStep::Delete { path: target(gone_dir.unwrap_or(rel)) }));
+        deleted_dirs.extend(gone_dir);
+    }
+
+    for dir in ["files", "patches"] {
+        let path = payload_root.join(dir);
+        if path.exists() {
+            changes.payload_files.push((path.to_string_lossy().to_string(), dir.to_string()));
+        }
+    }
+    Ok(changes)
+}
+
+fn step(step: InstallStep) -> ManifestStep {
+    ManifestStep { phase: Phase::default(), platforms: Vec::new(), component: None, step }
+}
+
+/// `a/b/c.txt` -> `a`, `a/b`; outermost first.
+fn ancestors(rel: &str) -> impl Iterator<Item = &str> {
+    rel.match_indices('/').map(move |(i, _)| &rel[..i])
+}
+
+fn write_payload(dest: &Path, bytes: &[u8]) -> Result<()> {
+    if let Some(parent) = dest.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(dest, bytes).with_context(|| format!("Failed to write {}", dest.display()))
+}
+
+fn copy_to_payload(src: &Path, dest: &Path) -> Result<()> {
+    let plan = plan_copy(src, dest, &[])?;
+    copy_plan_parallel(&plan, &|_| {})
+}
+
+/// SetJsonValue steps turning `before` into `after`, when both are JSON objects and no key was
+/// removed (SetJsonValue can't remove keys).
+fn json_steps(before: &str, after: &str, file: &str) -> Option<Vec<ManifestStep>> {
+    let before: serde_json::Value = serde_json::from_str(before).ok()?;
+    let after: serde_json::Value = serde_json::from_str(after).ok()?;
+    let mut changed = Vec::new();
+    if !diff_json(&before, &after, &mut Vec::new(), &mut changed) {
+        return None;
+    }
+    Some(
+        changed
+            .into_iter()
+            .map(|(key_path, value)| step(InstallStep::SetJsonValue { file: file.to_string(), key_path, value }))
+            .collect(),
+    )
+}
+
+/// Collects changed leaves as `(keyPath, value)`; returns false when the change can't be
+/// expressed that way.
+fn diff_json(before: &serde_json::Value, after: &serde_json::Value, path: &mut Vec<String>, out: &mut Vec<(String, serde_json::Value)>) -> bool {
+    let (Some(before), Some(after)) = (before.as_object(), after.as_object()) else { return false };
+    if before.keys().any(|k| !after.contains_key(k)) {
+        return false;
+    }
+    for (key, value) in after {
+        path.push(key.replace('\\', "\\\\").replace('.', "\\."));
+        match before.get(key) {
+            Some(old) if old == value => {}
+            Some(old) if old.is_object() && value.is_object() => {
+                if !diff_json(old, value, path, out) {
+                    return false;
+                }
+            }
+            _ => out.push((path.join("."), value.clone())),
+        }
+        path.pop();
+    }
+    true
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TextPatch {
+    pub start_marker: String,
+    pub end_marker: String,
+    pub content: String,
+}
+
+/// One marker block covering every changed line: the markers are the unchanged lines just
+/// before and after, grown until the patch engine would find them at the same place in both
+/// versions. `None` when the change touches the first or last line.
+pub fn text_patch(before: &str, after: &str) -> Option<TextPatch> {
+    if before == after {
+        return None;
+    }
+    let (b, a) = (before.as_bytes(), after.as_bytes());
+    let prefix = b.iter().zip(a).take_while(|(x, y)| x == y).count();
+    let max_suffix = b.len().min(a.len()) - prefix;
+    let suffix = b.iter().rev().zip(a.iter().rev()).take(max_suffix).take_while(|(x, y)| x == y).count();
+
+    // Widen the change to whole lines
+    let change_start = before[..prefix].rfind('\n').map_or(0, |i| i + 1);
+    let changed_end = b.len() - suffix;
+    let change_end = if changed_end == 0 || b[changed_end - 1] == b'\n' {
+        changed_end
+    } else {
+        before[changed_end..].find('\n').map_or(b.len(), |i| changed_end + i + 1)
+    };
+    let after_end = a.len() - (b.len() - change_end);
+    if change_start == 0 || change_end == b.len() {
+        return None;
+    }
+
+    let line_starts: Vec<usize> = std::iter::once(0).chain(before.match_indices('\n').map(|(i, _)| i + 1)).collect();
+    let start_marker = line_starts
+        .iter()
+        .rev()
+        .filter(|&&s| s < change_start)
+        .take(MAX_MARKER_LINES)
+        .map(|&s| &before[s..change_start])
+        .find(|m| !m.trim().is_empty() && before.find(*m) == Some(change_start - m.len()) && after.find(*m) == Some(change_start - m.len()))?;
+    let end_marker = line_starts
+        .iter()
+        .filter(|&&s| s > change_end)
+        .map(|&s| &before[change_end..s])
+        .chain(std::iter::once(&before[change_end..]))
+        .take(MAX_MARKER_LINES)
+        .find(|m| {
+            !m.trim().is_empty()
+                && before[change_start..].find(*m) == Some(change_end - change_start)
+                && after[change_start..].find(*m) == Some(after_end - change_start)
+        })?;
+
+    Some(TextPatch {
+        start_marker: start_marker.to_string(),
+        end_marker: end_marker.to_string(),
+        content: after[change_start..after_end].to_string(),
+    })
+}
+
+/// A draft manifest from [`generate_manifest_from_diff`], for the studio to load and refine.
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct ManifestDraft {
+    pub manifest: InstallManifest,
+    pub payload_files: Vec<(String, String)>,
+    pub warnings: Vec<String>,
+}
+
+/// Compares `original_dir` with `modified_dir` and drafts a manifest that turns the first into
+/// the second. Payload files are written to `payload_root`; step targets start with
+/// `target_prefix`, i.e. where the original folder lives on the user's machine.
+pub fn generate_manifest_from_diff(original_dir: &Path, modified_dir: &Path, target_prefix: &str, payload_root: &Path) -> Result<ManifestDraft> {
+    let before = take_snapshot(original_dir, &[])?;
+    let changes = record_changes(&before, modified_dir, target_prefix, payload_root)?;
+    let name = modified_dir.file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_else(|| "Generated".to_string());
+    let manifest = InstallManifest {
+        manifest_version: MANIFEST_VERSION,
+        app_name: name.into(),
+        version: "1.0.0".to_string(),
+        publisher: String::new(),
+        description: format!("Changes from {} to {}", original_dir.display(), modified_dir.display()).into(),
+        logo_path: None,
+        advanced_mode: None,
+        targets: vec![target_prefix.to_string()],
+        payload_dir: "payloads".to_string(),
+        install_steps: changes.steps,
+        backup_retention: None,
+        backup_dir: None,
+        pre_install: Vec::new(),
+        post_install: Vec::new(),
+        launch_after_install: None,
+        min_target_version: None,
+        max_target_version: None,
+        target_version_from: None,
+        target_overrides: BTreeMap::new(),
+        replace_locked_on_reboot: None,
+        register_uninstall: None,
+        watch_installed_files: None,
+        components: Vec::new(),
+        variables: BTreeMap::new(),
+        profiles: BTreeMap::new(),
+        prompts: Vec::new(),
+        license_file: None,
+        branding: None,
+    };
+    Ok(ManifestDraft { manifest, payload_files: changes.payload_files, warnings: changes.warnings })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::{Quiet, spec, step_context, temp_file};
+
+    #[test]
+    fn text_patch_markers_are_unique_unchanged_lines() {
+        let before = "a {\n  color: red;\n}\nb {\n  color: red;\n}\n";
+        let after = "a {\n  color: red;\n}\nb {\n  color: blue;\n}\n";
+        let patch = text_patch(before, after).unwrap();
+        // The unchanged lines around the edit become the markers
+        assert_eq!(patch.start_marker, "b {\n");
+        assert_eq!(patch.content, "  color: blue;\n");
+        assert_eq!(patch.end_marker, "}\n");
+
+        let (patched, _) = apply_patches(before.as_bytes(), &[spec(&patch.start_marker, &patch.end_marker, &patch.content, false)]).unwrap();
+        assert_eq!(String::from_utf8(patched).unwrap(), after);
+        assert!(text_patch("first\nsecond\n", "changed\nsecond\n").is_none());
+    }
+
+    #[test]
+    fn recorded_changes_reproduce_the_manual_edits() {
+        let setup = |dir: &str| {
+            temp_file(&format!("record/{}/theme.css", dir), "body {\n  color: red;\n}\n/* end */\n");
+            temp_file(&format!("record/{}/settings.json", dir), r#"{"editor": {"fontSize": 12}, "theme": "dark"}"#);
+            temp_file(&format!("record/{}/old.txt", dir), "bye").parent().unwrap().to_path_buf()
+        };
+        let target = setup("target");
+        let original = setup("original");
+        let before = take_snapshot(&target, &[]).unwrap();
+
+        std::fs::write(target.join("theme.css"), "body {\n  color: purple;\n  margin: 0;\n}\n/* end */\n").unwrap();
+        std::fs::write(target.join("settings.json"), r#"{"editor": {"fontSize": 14, "tabSize": 2}, "theme": "dark"}"#).unwrap();
+        std::fs::remove_file(target.join("old.txt")).unwrap();
+        temp_file("record/target/extensions/mod/main.js", "run()");
+        temp_file("record/target/extensions/mod/lib/util.js", "util()");
+        temp_file("record/target/logo.bin", "\0\x01");
+
+        let payload = target.parent().unwrap().join("payload");
+        let changes = record_changes(&before, &target, "", &payload).unwrap();
+        let kinds: Vec<serde_json::Value> = changes.steps.iter().map(|s| serde_json::to_value(s).unwrap()["type"].clone()).collect();
+        assert_eq!(kinds, vec!["copy", "copy", "setJsonValue", "setJsonValue", "patchBlock", "delete"]);
+        assert!(changes.warnings.is_empty());
+        assert_eq!(changes.payload_files.iter().map(|(_, rel)| rel.as_str()).collect::<Vec<_>>(), vec!["files", "patches"]);
+
+        let ctx = step_context(&original, &payload);
+        let mut receipt = InstallReceipt::default();
+        for step in &changes.steps {
+            execute_step(step, &ctx, &mut receipt, &Quiet).unwrap();
+        }
+        let read = |dir: &Path, rel: &str| std::fs::read_to_string(dir.join(rel)).unwrap();
+        assert_eq!(read(&original, "theme.css"), read(&target, "theme.css"));
+        assert_eq!(read(&original, "extensions/mod/lib/util.js"), "util()");
+        assert!(!original.join("old.txt").exists());
+        let settings: serde_json::Value = serde_json::from_str(&read(&original, "settings.json")).unwrap();
+        assert_eq!(settings["editor"], serde_json::json!({ "fontSize": 14, "tabSize": 2 }));
+    }
+
+    #[test]
+    fn folder_diff_drafts_a_manifest_with_deletes() {
+        let original = temp_file("folder_diff/original/keep/a.txt", "a").parent().unwrap().parent().unwrap().to_path_buf();
+        temp_file("folder_diff/original/gone/b.txt", "b");
+        temp_file("folder_diff/original/gone/c.txt", "c");
+        let modified = temp_file("folder_diff/modified/keep/a.txt", "a").parent().unwrap().parent().unwrap().to_path_buf();
+        temp_file("folder_diff/modified/new.txt", "new");
+
+        let payload = original.parent().unwrap().join("payload");
+        let draft = generate_manifest_from_diff(&original, &modified, "%APPDATA%/App/", &payload).unwrap();
+        let steps: Vec<serde_json::Value> = draft.manifest.install_steps.iter().map(|s| serde_json::to_value(s).unwrap()).collect();
+        assert_eq!(
+            steps,
+            vec![
+                serde_json::json!({ "type": "copy", "src": "files/new.txt", "dest": "%APPDATA%/App/new.txt", "exclude": null, "overwrite": "always" }),
+                serde_json::json!({ "type": "delete", "path": "%APPDATA%/App/gone" }),
+            ]
+        );
+        assert_eq!(&*draft.manifest.app_name, "modified");
+        assert_eq!(std::fs::read_to_string(payload.join("files/new.txt")).unwrap(), "new");
+    }
+
+    #[test]
+    fn recording_skips_what_the_snapshot_excluded() {
+        let target = temp_file("record_exclude/target/keep.txt", "keep").parent().unwrap().to_path_buf();
+        temp_file("record_exclude/target/cache/old.bin", "old");
+        let before = take_snapshot(&target, &["cache/**".to_string(), "cache".to_string()]).unwrap();
+        let before: Snapshot = serde_json::from_str(&serde_json::to_string(&before).unwrap()).unwrap();
+
+        temp_file("record_exclude/target/cache/new.bin", "new");
+        let payload = target.parent().unwrap().join("payload");
+        let changes = record_changes(&before, &target, "", &payload).unwrap();
+        assert!(changes.steps.is_empty(), "{:?}", changes.steps);
+    }
+}
@@ -0,0 +1,380 @@
+//! The install report: what one [`run_install`] did (steps with their timings, the files that
+//! changed with their hashes before and after, warnings), written into the install's backup
+//! folder for support requests and audits. Installs without a backup keep theirs under
+//! [`REPORTS_DIR`] instead, so no backup is made just to hold a report.
+
+use crate::*;
+use std::collections::BTreeSet;
+use std::sync::Mutex;
+
+pub const INSTALL_REPORT_FILE: &str = "install_report.json";
+/// Folder of an app's backup root holding `report_<timestamp>` folders for the installs that
+/// backed nothing up. Restores, backup lists and pruning never look inside it.
+pub const REPORTS_DIR: &str = "reports";
+/// Report-only folders kept per app; older ones are removed when a new one is written.
+const KEPT_REPORTS: usize = 20;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum ReportedStatus {
+    Done,
+    /// Finished by the run that was resumed.
+    AlreadyDone,
+    Failed,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct ReportedStep {
+    /// Position in `installSteps`.
+    pub index: usize,
+    pub kind: String,
+    pub phase: Phase,
+    pub status: ReportedStatus,
+    pub duration_ms: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// A file whose contents differ after the install. A missing hash means the file didn't exist.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct ReportedFile {
+    pub path: String,
+    pub sha256_before: Option<String>,
+    pub sha256_after: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct InstallReport {
+    pub app_name: String,
+    pub version: String,
+    pub manifest_hash: String,
+    pub phase: Option<Phase>,
+    pub started_at: String,
+    pub finished_at: String,
+    pub duration_ms: u64,
+    /// Bytes the install had to write, as counted by the preflight; the throughput of past
+    /// installs for [`estimate_install`].
+    #[serde(default)]
+    pub total_bytes: u64,
+    pub success: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    pub steps: Vec<ReportedStep>,
+    pub files: Vec<ReportedFile>,
+    pub warnings: Vec<String>,
+    /// The machine the install ran on.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub system: Option<SystemInfo>,
+}
+
+/// Folders under the app's `backup_root` that may hold an install report, its backups and its
+/// report-only folders, newest first.
+pub fn install_report_dirs(backup_root: &Path) -> Vec<PathBuf> {
+    let reports = fs::read_dir(backup_root.join(REPORTS_DIR)).into_iter().flatten().flatten().map(|e| e.path());
+    let mut dirs: Vec<PathBuf> = backup_dirs_in(backup_root).into_iter().chain(reports.filter(|p| is_report_dir(p))).collect();
+    let stamp = |dir: &PathBuf| {
+        let name = dir.file_name().unwrap_or_default().to_string_lossy().to_string();
+        name.split_once('_').map_or(name.clone(), |(_, stamp)| stamp.to_string())
+    };
+    dirs.sort_by_key(|dir| std::cmp::Reverse(stamp(dir)));
+    dirs
+}
+
+fn is_report_dir(path: &Path) -> bool {
+    path.is_dir() && path.file_name().is_some_and(|n| n.to_string_lossy().starts_with("report_"))
+}
+
+pub fn load_install_report(backup_dir: &Path) -> Result<InstallReport> {
+    let path = backup_dir.join(INSTALL_REPORT_FILE);
+    let content = fs::read_to_string(&path).map_err(|e| MisfitError::from(e).with_path(&path))?;
+    serde_json::from_str(&content).context("Failed to parse install report")
+}
+
+/// Write speed assumed before the app has been installed on this machine: a slow disk, so the
+/// first estimate errs on the long side.
+pub const DEFAULT_BYTES_PER_SEC: u64 = 30 * 1024 * 1024;
+
+/// How many of the latest successful installs the throughput is averaged over.
+const ESTIMATE_HISTORY: usize = 5;
+
+/// What an install is about to do and how long it should take, for "~420 MB, about 2 minutes".
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct InstallEstimate {
+    /// Bytes the copy steps write.
+    pub total_bytes: u64,
+    /// Files the copy steps write.
+    pub file_count: usize,
+    /// Distinct files the patch, JSON and embed steps change.
+    pub patched_files: usize,
+    pub bytes_per_sec: u64,
+    pub estimated_ms: u64,
+    /// Past installs of the app the speed was measured from; 0 means [`DEFAULT_BYTES_PER_SEC`].
+    pub based_on_installs: usize,
+}
+
+/// Sizes up an install of `manifest` (its default components) with the given roots, timed by
+/// the throughput of the app's last successful installs under `backups_root`. A manifest whose
+/// components don't resolve is estimated as empty; installing it fails anyway.
+pub fn estimate_install(manifest: &InstallManifest, target_base: &Path, payload_source: &Path, backups_root: &Path) -> InstallEstimate {
+    let plan = resolve_install_plan(manifest, target_base, payload_source, None).unwrap_or_default();
+    let copies: Vec<&PlannedStep> = plan.steps.iter().filter(|s| s.kind == "copy" && s.will_change).collect();
+    let patched: BTreeSet<&str> = plan
+        .steps
+        .iter()
+        .filter(|s| matches!(s.kind.as_str(), "patchBlock" | "setJsonValue" | "base64Embed") && s.will_change)
+        .filter_map(|s| s.target.as_deref())
+        .collect();
+
+    let history: Vec<InstallReport> = install_report_dirs(&backups_root.join(backup_namespace(&manifest.app_name)))
+        .iter()
+        .filter_map(|dir| load_install_report(dir).ok())
+        .filter(|r| r.success && r.phase.is_none() && r.total_bytes > 0 && r.duration_ms > 0)
+        .take(ESTIMATE_HISTORY)
+        .collect();
+    let bytes_per_sec = match history.iter().map(|r| r.duration_ms).sum::<u64>() {
+        0 => DEFAULT_BYTES_PER_SEC,
+        ms => (history.iter().map(|r| r.total_bytes).sum::<u64>().saturating_mul(1000) / ms).max(1),
+    };
+    let total_bytes = copies.iter().map(|s| s.bytes).sum::<u64>();
+    InstallEstimate {
+        total_bytes,
+        file_count: copies.iter().map(|s| s.file_count).sum(),
+        patched_files: patched.len(),
+        bytes_per_sec,
+        estimated_ms: total_bytes.saturating_mul(1000) / bytes_per_sec,
+        based_on_installs: history.len(),
+    }
+}
+
+/// Passes everything on to the frontend's observer and keeps the warnings for the report.
+pub(crate) struct ReportingObserver<'a> {
+    pub inner: &'a dyn InstallObserver,
+    pub warnings: Mutex<Vec<String>>,
+}
+
+impl<'a> ReportingObserver<'a> {
+    pub fn new(inner: &'a dyn InstallObserver) -> Self {
+        ReportingObserver { inner, warnings: Mutex::new(Vec::new()) }
+    }
+
+    pub fn take_warnings(&self) -> Vec<String> {
+        std::mem::take(&mut *self.warnings.lock().unwrap_or_else(|e| e.into_inner()))
+    }
+}
+
+impl InstallObserver for ReportingObserver<'_> {
+    fn log(&self, message: &str) {
+        self.inner.log(message);
+    }
+
+    fn warn(&self, message: &str) {
+        self.warnings.lock().unwrap_or_else(|e| e.into_inner()).push(message.to_string());
+        self.inner.warn(message);
+    }
+
+    fn event(&self, name: &str, payload: serde_json::Value) {
+        self.inner.event(name, payload);
+    }
+
+    fn confirm_overwrite(&self, files: &[String]) -> bool {
+        self.inner.confirm_overwrite(files)
+    }
+
+    fn confirm_command(&self, command: &str, command_line: &str) -> bool {
+        self.inner.confirm_command(command, command_line)
+    }
+}
+
+/// Hashes of the files the steps may change, taken before they run so the report can tell
+/// which ones did. Commands and extension installs aren't covered.
+pub(crate) struct FileSnapshot {
+    before: BTreeMap<PathBuf, Option<String>>,
+}
+
+impl FileSnapshot {
+    pub fn take(steps: &[(usize, &ManifestStep)], ctx: &StepContext) -> Self {
+        let mut before = BTreeMap::new();
+        for (_, step) in steps {
+            for path in touched_files(&step.step, ctx) {
+                before.entry(path).or_insert_with_key(|path| file_sha256(path));
+            }
+        }
+        FileSnapshot { before }
+    }
+
+    pub fn changes(&self) -> Vec<ReportedFile> {
+        self.before
+            .iter()
+            .filter_map(|(path, before)| {
+                let after = file_sha256(path);
+                (after != *before).then(|| ReportedFile {
+                    path: path.to_string_lossy().to_string(),
+                    sha256_before: before.clone(),
+                    sha256_after: after,
+                })
+            })
+            .collect()
+    }
+
+    /// Hashes of the snapshotted files that exist now, keyed by path.
+    pub fn current(&self) -> BTreeMap<String, String> {
+        self.before.keys().filter_map(|path| Some((path.to_string_lossy().to_string(), file_sha256(path)?))).collect()
+    }
+}
+
+/// Files on disk `step` may write or remove; folders are listed file by file.
+fn touched_files(step: &InstallStep, ctx: &StepContext) -> Vec<PathBuf> {
+    let target_base = &ctx.target_base;
+    let paths = match step {
+        InstallStep::Copy { src, dest, exclude, .. } => {
+            let dest = resolve_path(target_base, dest);
+            let plan = plan_copy_step(src, &dest, exclude.as_deref().unwrap_or_default(), &ctx.payload_source);
+            return plan.map(|plan| plan.files.into_iter().map(|job| job.dest).collect()).unwrap_or_default();
+        }
+        InstallStep::PatchBlock { file, .. } | InstallStep::SetJsonValue { file, .. } | InstallStep::Base64Embed { file, .. } | InstallStep::Delete { path: file } => {
+            vec![containing_file(&resolve_path(target_base, file))]
+        }
+        InstallStep::FixVsCodeChecksums { app_dir } => vscode_product_json(&resolve_path(target_base, app_dir)).into_iter().collect(),
+        InstallStep::RunCommand { .. } | InstallStep::InstallVsix { .. } | InstallStep::Verify { .. } => Vec::new(),
+    };
+    paths
+        .into_iter()
+        .flat_map(|path| {
+            if path.is_dir() {
+                walkdir::WalkDir::new(&path).into_iter().flatten().filter(|e| e.file_type().is_file()).map(|e| e.into_path()).collect()
+            } else {
+                vec![path]
+            }
+        })
+        .collect()
+}
+
+pub(crate) fn file_sha256(path: &Path) -> Option<String> {
+    path.is_file().then(|| fs::read(path).ok()).flatten().map(|bytes| sha256_hex(&bytes))
+}
+
+/// Writes `report` into `backup_dir`, or into a new `report_<timestamp>` folder under the
+/// app's [`REPORTS_DIR`] when the install didn't need a backup, and returns the report's path.
+pub(crate) fn save_install_report(report: &InstallReport, backup_dir: Option<&Path>, backup_root: &Path) -> Result<PathBuf> {
+    let dir = match backup_dir {
+        Some(dir) => dir.to_path_buf(),
+        None => {
+            let reports = backup_root.join(REPORTS_DIR);
+            let dir = reports.join(format!("report_{}", chrono::Local::now().format("%Y%m%d_%H%M%S%3f")));
+            fs::create_dir_all(&dir).map_err(|e| MisfitError::from(e).with_path(&dir))?;
+            let mut old: Vec<PathBuf> = fs::read_dir(&reports).into_iter().flatten().flatten().map(|e| e.path()).filter(|p| is_report_dir(p)).collect();
+            old.sort_by(|a, b| b.cmp(a));
+            for stale in old.iter().skip(KEPT_REPORTS) {
+                let _ = fs::remove_dir_all(stale);
+            }
+            dir
+        }
+    };
+    let path = dir.join(INSTALL_REPORT_FILE);
+    write_atomic(&path, serde_json::to_string_pretty(report)?.as_bytes()).map_err(|e| MisfitError::from(e).with_path(&path))?;
+    Ok(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::{Quiet, install_options, temp_file, test_manifest};
+
+    #[test]
+    fn run_install_writes_a_report_into_the_backup() {
+        let conf = temp_file("report/conf.json", "{}");
+        let root = conf.parent().unwrap().to_path_buf();
+        std::fs::create_dir_all(root.join("payload")).unwrap();
+        std::fs::write(root.join("payload/readme.txt"), "hello").unwrap();
+        std::fs::write(root.join("unchanged.json"), "{\n  \"same\": true\n}").unwrap();
+        let mut manifest = test_manifest("Reported", serde_json::json!({
+            "version": "2.0.0",
+            "installSteps": [
+                { "type": "setJsonValue", "file": "conf.json", "keyPath": "ready", "value": true },
+                { "type": "copy", "src": "readme.txt", "dest": "docs/readme.txt" },
+                { "type": "setJsonValue", "file": "unchanged.json", "keyPath": "same", "value": true }
+            ],
+            "postInstall": [{ "type": "verify", "file": "missing.txt" }]
+        }));
+        let options = install_options(&root, root.join("payload"), root.join("backups"));
+
+        let path = run_install(&manifest, &options, &Quiet).expect("install");
+        assert_eq!(path.file_name().unwrap(), INSTALL_REPORT_FILE);
+        let report = load_install_report(path.parent().unwrap()).expect("report");
+        assert!(report.success && report.error.is_none());
+        assert_eq!((report.app_name.as_str(), report.version.as_str()), ("Reported", "2.0.0"));
+        let statuses: Vec<(usize, ReportedStatus)> = report.steps.iter().map(|s| (s.index, s.status)).collect();
+        assert_eq!(statuses, [(0, ReportedStatus::Done), (1, ReportedStatus::Done), (2, ReportedStatus::Done)]);
+        let files: Vec<(String, bool)> = report
+            .files
+            .iter()
+            .map(|f| (Path::new(&f.path).file_name().unwrap().to_string_lossy().to_string(), f.sha256_before.is_some()))
+            .collect();
+        assert_eq!(files, [("conf.json".to_string(), true), ("readme.txt".to_string(), false)]);
+        assert_eq!(report.files[1].sha256_after.as_deref(), Some(sha256_hex(b"hello").as_str()));
+        assert_eq!(report.warnings.len(), 1);
+        assert!(report.warnings[0].contains("Post-install hook 1 failed"));
+
+        manifest.install_steps[0].step = serde_json::from_value(serde_json::json!({ "type": "verify", "file": "conf.json", "contains": "nope" })).unwrap();
+        manifest.install_steps.swap(0, 1);
+        let err = MisfitError::from(run_install(&manifest, &options, &Quiet).unwrap_err());
+        assert_eq!(err.step_index, Some(1));
+        let failed = install_report_dirs(&options.backups_root.join(backup_namespace(&manifest.app_name)))
+            .iter()
+            .filter_map(|dir| load_install_report(dir).ok())
+            .find(|r| !r.success)
+            .expect("failed report");
+        assert_eq!(failed.steps.last().map(|s| (s.index, s.status)), Some((1, ReportedStatus::Failed)));
+        assert!(failed.error.unwrap().contains("does not contain"));
+    }
+
+    #[test]
+    fn estimates_use_the_throughput_of_earlier_installs() {
+        let payload_file = temp_file("estimate/payload/data/big.bin", &"x".repeat(4096));
+        let payload = payload_file.parent().unwrap().parent().unwrap().to_path_buf();
+        let root = payload.parent().unwrap().to_path_buf();
+        std::fs::write(root.join("conf.json"), "{}").unwrap();
+        let manifest = test_manifest("Estimate", serde_json::json!({
+            "installSteps": [
+                { "type": "copy", "src": "data", "dest": "out" },
+                { "type": "setJsonValue", "file": "conf.json", "keyPath": "a", "value": 1 },
+                { "type": "setJsonValue", "file": "conf.json", "keyPath": "b", "value": 2 }
+            ]
+        }));
+        let backups_root = root.join("backups");
+        let first = estimate_install(&manifest, &root, &payload, &backups_root);
+        assert_eq!((first.total_bytes, first.file_count, first.patched_files), (4096, 1, 1));
+        assert_eq!((first.bytes_per_sec, first.based_on_installs), (DEFAULT_BYTES_PER_SEC, 0));
+
+        let options = install_options(&root, payload.clone(), backups_root.clone());
+        let report = load_install_report(run_install(&manifest, &options, &Quiet).unwrap().parent().unwrap()).unwrap();
+        assert!(report.total_bytes >= 4096, "{}", report.total_bytes);
+
+        std::fs::remove_dir_all(root.join("out")).unwrap();
+        let next = estimate_install(&manifest, &root, &payload, &backups_root);
+        assert_eq!(next.based_on_installs, usize::from(report.duration_ms > 0));
+        if let Some(throughput) = (report.total_bytes * 1000).checked_div(report.duration_ms) {
+            assert_eq!(next.bytes_per_sec, throughput);
+        }
+    }
+
+    #[test]
+    fn installs_without_a_backup_keep_their_report_out_of_the_backups() {
+        let root = temp_file("report_only/target/conf.json", "{}").parent().unwrap().parent().unwrap().to_path_buf();
+        let manifest = test_manifest("ReportOnly", serde_json::json!({
+            "installSteps": [{ "type": "verify", "file": "target/conf.json" }]
+        }));
+        let options = install_options(&root, root.join("payload"), root.join("backups"));
+        let path = run_install(&manifest, &options, &Quiet).expect("install");
+        let app_root = options.backups_root.join(backup_namespace("ReportOnly"));
+        assert!(path.starts_with(app_root.join(REPORTS_DIR)));
+        assert!(load_install_report(path.parent().unwrap()).unwrap().success);
+        assert_eq!(install_report_dirs(&app_root), [path.parent().unwrap()]);
+        assert!(list_backups(&options.backups_root, None).unwrap().is_empty());
+        assert!(restore_latest_backup(&app_root).is_err());
+    }
+}
@@ -0,0 +1,426 @@
+//! Lists folders for the Studio: the folders of a tree for the payload picker (e.g. a folder of
+//! extensions), with what's inside each, and the payload tree for its browser.
+
+use crate::*;
+use std::time::SystemTime;
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase", default)]
+pub struct ScanOptions {
+    /// Levels of folders to list: 1 is the root's own subfolders.
+    pub depth: usize,
+    /// Only list folders whose name or relative path matches one of these; all when empty.
+    /// Folders that don't match are still looked into.
+    pub include: Vec<String>,
+    /// Skip folders and files whose name or relative path matches, with everything below them.
+    pub exclude: Vec<String>,
+}
+
+impl Default for ScanOptions {
+    fn default() -> Self {
+        ScanOptions { depth: 1, include: Vec::new(), exclude: Vec::new() }
+    }
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct ScanEntry {
+    pub name: String,
+    pub path: String,
+    /// `/`-separated, from the scanned root.
+    pub relative_path: String,
+    /// 1 for the root's subfolders.
+    pub depth: usize,
+    /// Files anywhere below the folder, excluded ones left out.
+    pub file_count: usize,
+    pub total_bytes: u64,
+    /// When the newest of those files was modified, or the folder itself when it has none.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub modified: Option<String>,
+    /// Whether the folder has a `package.json` of its own (an npm package or extension).
+    pub has_package_json: bool,
+    /// What that `package.json` says, when it names the package.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub extension: Option<ExtensionInfo>,
+}
+
+/// A VS Code extension (or npm package) as its `package.json` describes it.
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct ExtensionInfo {
+    /// `publisher.name`, the id the marketplace and `code --install-extension` use; just the
+    /// name for a package without a publisher.
+    pub id: String,
+    pub name: String,
+    /// With `%key%` placeholders filled in from `package.nls.json`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub display_name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub version: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub publisher: Option<String>,
+}
+
+/// Reads `folder/package.json`; `None` when it is missing, unreadable or has no `name`.
+pub fn read_extension_info(folder: &Path) -> Option<ExtensionInfo> {
+    let package: serde_json::Value = serde_json::from_str(&fs::read_to_string(folder.join("package.json")).ok()?).ok()?;
+    let text = |key: &str| package.get(key).and_then(|v| v.as_str()).map(str::trim).filter(|v| !v.is_empty()).map(str::to_string);
+    let name = text("name")?;
+    let publisher = text("publisher");
+    let display_name = text("displayName").map(|display| {
+        // Localized extensions keep their strings in package.nls.json
+        let Some(key) = display.strip_prefix('%').and_then(|d| d.strip_suffix('%')) else { return display };
+        fs::read_to_string(folder.join("package.nls.json"))
+            .ok()
+            .and_then(|nls| serde_json::from_str::<serde_json::Value>(&nls).ok())
+            .and_then(|nls| match &nls[key] {
+                serde_json::Value::String(text) => Some(text.clone()),
+                // { "message": ..., "comment": ... } entries
+                entry => entry["message"].as_str().map(str::to_string),
+            })
+            .unwrap_or(display)
+    });
+    Some(ExtensionInfo {
+        id: publisher.as_ref().map_or_else(|| name.clone(), |publisher| format!("{}.{}", publisher, name)),
+        name,
+        display_name,
+        version: text("version"),
+        publisher,
+    })
+}
+
+/// Lists the folders under `root` down to `options.depth`, sorted by relative path. Symlinks are
+/// neither listed nor followed.
+pub fn scan_folders(root: &Path, options: &ScanOptions) -> Result<Vec<ScanEntry>> {
+    if !root.is_dir() {
+        return Err(MisfitError::new(ErrorCode::NotFound, "Folder not found.").with_path(root).into());
+    }
+    let include = compile_patterns(&options.include)?;
+    let exclude = compile_patterns(&options.exclude)?;
+    // `*` stays within one folder, so `ext-*` doesn't take in everything below `ext-a`
+    let separated = glob::MatchOptions { require_literal_separator: true, ..Default::default() };
+    let matches = |patterns: &[glob::Pattern], rel: &str| {
+        let name = rel.rsplit('/').next().unwrap_or(rel);
+        patterns.iter().any(|p| p.matches_with(rel, separated) || p.matches_with(name, separated))
+    };
+    let depth = options.depth.max(1);
+
+    let mut entries: Vec<(ScanEntry, Option<SystemTime>)> = Vec::new();
+    let mut listed: HashMap<PathBuf, usize> = HashMap::new();
+    let walker = walkdir::WalkDir::new(root)
+        .follow_links(false)
+        .min_depth(1)
+        .into_iter()
+        .filter_entry(|entry| !entry.path().strip_prefix(root).is_ok_and(|rel| matches(&exclude, &lock_key(rel))));
+    for entry in walker {
+        let entry = entry?;
+        let rel = entry.path().strip_prefix(root).context("Walk escaped scan root")?.to_path_buf();
+        if entry.file_type().is_dir() {
+            let relative_path = lock_key(&rel);
+            if entry.depth() <= depth && (include.is_empty() || matches(&include, &relative_path)) {
+                listed.insert(rel, entries.len());
+                entries.push((
+                    ScanEntry {
+                        name: entry.file_name().to_string_lossy().to_string(),
+                        path: entry.path().to_string_lossy().to_string(),
+                        relative_path,
+                        depth: entry.depth(),
+                        file_count: 0,
+                        total_bytes: 0,
+                        modified: None,
+                        has_package_json: entry.path().join("package.json").is_file(),
+                        extension: read_extension_info(entry.path()),
+                    },
+                    entry.metadata().ok().and_then(|m| m.modified().ok()),
+                ));
+            }
+        } else if entry.file_type().is_file() {
+            let metadata = entry.metadata()?;
+            let modified = metadata.modified().ok();
+            for folder in rel.ancestors().skip(1) {
+                let Some(&index) = listed.get(folder) else { continue };
+                let (scanned, newest) = &mut entries[index];
+                if scanned.file_count == 0 {
+                    *newest = None;
+                }
+                scanned.file_count += 1;
+                scanned.total_bytes += metadata.len();
+                *newest = (*newest).max(modified);
+            }
+        }
+    }
+
+    let mut entries: Vec<ScanEntry> = entries
+        .into_iter()
+        .map(|(mut entry, modified)| {
+            entry.modified = modified.map(|time| chrono::DateTime::<chrono::Local>::from(time).to_rfc3339());
+            entry
+        })
+        .collect();
+    entries.sort_by_key(|e| e.relative_path.to_lowercase());
+    Ok(entries)
+}
+
+/// Children a folder of the payload tree lists unless asked for fewer.
+pub const TREE_PAGE_SIZE: usize = 200;
+
+/// Nodes one [`list_payload_tree`] call returns at most, however deep or wide the request.
+pub const MAX_TREE_NODES: usize = 5000;
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase", default)]
+pub struct TreeOptions {
+    /// `/`-separated folder below the root to list; the root itself when empty.
+    pub path: String,
+    /// Levels of children to include: 1 is the folder's own files and subfolders.
+    pub depth: usize,
+    /// Children of `path` to skip, for the next page.
+    pub offset: usize,
+    /// Children listed per folder, up to [`TREE_PAGE_SIZE`].
+    pub limit: usize,
+    /// Hash every listed file with SHA-256; off by default, it reads each file whole.
+    pub hashes: bool,
+}
+
+impl Default for TreeOptions {
+    fn default() -> Self {
+        TreeOptions { path: String::new(), depth: 1, offset: 0, limit: TREE_PAGE_SIZE, hashes: false }
+    }
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct TreeNode {
+    pub name: String,
+    /// `/`-separated, from the tree's root; what to pass as `path` to page through a folder.
+    pub relative_path: String,
+    pub is_dir: bool,
+    /// The file's size, or everything below the folder.
+    pub size: u64,
+    /// Files anywhere below a folder; 0 for a file.
+    pub file_count: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sha256: Option<String>,
+    /// A folder's files and subfolders, folders first; `None` below the requested depth.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub children: Option<Vec<TreeNode>>,
+    /// All of a folder's direct children, listed or not.
+    pub child_count: usize,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct PayloadTree {
+    /// The folder at `path`, with its page of children.
+    pub node: TreeNode,
+    /// Whether some folder's children were cut short by `limit` or [`MAX_TREE_NODES`]; page
+    /// with `offset` or list that folder on its own.
+    pub truncated: bool,
+}
+
+/// Lists the payload folder `root` (or the folder `options.path` inside it) as a tree with
+/// sizes, so the Studio can browse it in a few calls.
+pub fn list_payload_tree(root: &Path, options: &TreeOptions) -> Result<PayloadTree> {
+    let relative = if options.path.trim().is_empty() { PathBuf::new() } else { normalize_rel_path(&options.path, true)? };
+    let folder = root.join(&relative);
+    if !folder.is_dir() {
+        return Err(MisfitError::new(ErrorCode::NotFound, "Folder not found.").with_path(&folder).into());
+    }
+
+    // One walk sizes every folder below `folder`
+    let mut totals: HashMap<PathBuf, (u64, usize)> = HashMap::new();
+    for entry in walkdir::WalkDir::new(&folder).follow_links(false).min_depth(1) {
+        let entry = entry?;
+        if !entry.file_type().is_file() {
+            continue;
+        }
+        let size = entry.metadata()?.len();
+        let rel = entry.path().strip_prefix(&folder).context("Walk escaped payload folder")?;
+        for ancestor in rel.ancestors().skip(1) {
+            let total = totals.entry(ancestor.to_path_buf()).or_default();
+            total.0 += size;
+            total.1 += 1;
+        }
+    }
+
+    let mut lister = TreeLister {
+        folder: &folder,
+        base: &relative,
+        totals: &totals,
+        limit: options.limit.clamp(1, TREE_PAGE_SIZE),
+        hashes: options.hashes,
+        budget: MAX_TREE_NODES,
+        truncated: false,
+    };
+    let (size, file_count) = totals.get(Path::new("")).copied().unwrap_or_default();
+    let (children, child_count) = lister.children(Path::new(""), options.depth.max(1), options.offset)?;
+    let node = TreeNode {
+        name: folder.file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default(),
+        relative_path: lock_key(&relative),
+        is_dir: true,
+        size,
+        file_count,
+        sha256: None,
+        children: Some(children),
+        child_count,
+    };
+    Ok(PayloadTree { node, truncated: lister.truncated })
+}
+
+struct TreeLister<'a> {
+    folder: &'a Path,
+    /// `folder` relative to the tree's root.
+    base: &'a Path,
+    totals: &'a HashMap<PathBuf, (u64, usize)>,
+    limit: usize,
+    hashes: bool,
+    /// Nodes left before [`MAX_TREE_NODES`].
+    budget: usize,
+    truncated: bool,
+}
+
+impl TreeLister<'_> {
+    /// The page of `rel`'s children starting at `offset`, and how many it has in all.
+    fn children(&mut self, rel: &Path, depth: usize, offset: usize) -> Result<(Vec<TreeNode>, usize)> {
+        let dir = self.folder.join(rel);
+        let mut entries = fs::read_dir(&dir).map_err(|e| MisfitError::from(e).with_path(&dir))?.collect::<std::io::Result<Vec<_>>>()?;
+        // Symlinks are listed as files and never followed
+        let is_dir = |entry: &fs::DirEntry| entry.file_type().is_ok_and(|t| t.is_dir());
+        entries.sort_by_cached_key(|entry| (!is_dir(entry), entry.file_name().to_string_lossy().to_lowercase()));
+        let child_count = entries.len();
+        let page = entries.len().saturating_sub(offset).min(self.limit).min(self.budget);
+        if offset + page < child_count {
+            self.truncated = true;
+        }
+        self.budget -= page;
+
+        let mut nodes = Vec::with_capacity(page);
+        for entry in entries.into_iter().skip(offset).take(page) {
+            let child = rel.join(entry.file_name());
+            let relative_path = lock_key(&self.base.join(&child));
+            let node = if is_dir(&entry) {
+                let (size, file_count) = self.totals.get(&child).copied().unwrap_or_default();
+                let (children, child_count) = if depth > 1 {
+                    let (children, count) = self.children(&child, depth - 1, 0)?;
+                    (Some(children), count)
+                } else {
+                    (None, fs::read_dir(entry.path()).map_or(0, |d| d.count()))
+                };
+                TreeNode {
+                    name: entry.file_name().to_string_lossy().to_string(),
+                    relative_path,
+                    is_dir: true,
+                    size,
+                    file_count,
+                    sha256: None,
+                    children,
+                    child_count,
+                }
+            } else {
+                let path = entry.path();
+                let hash = self.hashes && entry.file_type().is_ok_and(|t| t.is_file());
+                TreeNode {
+                    name: entry.file_name().to_string_lossy().to_string(),
+                    relative_path,
+                    is_dir: false,
+                    size: fs::symlink_metadata(&path).map_or(0, |m| m.len()),
+                    file_count: 0,
+                    sha256: if hash {
+                        Some(sha256_hex(&fs::read(&path).map_err(|e| MisfitError::from(e).with_path(&path))?))
+                    } else {
+                        None
+                    },
+                    children: None,
+                    child_count: 0,
+                }
+            };
+            nodes.push(node);
+        }
+        Ok((nodes, child_count))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::temp_file;
+
+    #[test]
+    fn scans_folders_to_a_depth_with_filters_and_sizes() {
+        let root = temp_file("scan/ext-a/package.json", "{}").parent().unwrap().parent().unwrap().to_path_buf();
+        std::fs::create_dir_all(root.join("ext-a/lib/deep")).unwrap();
+        std::fs::write(root.join("ext-a/lib/deep/main.js"), "12345").unwrap();
+        std::fs::create_dir_all(root.join("ext-b/node_modules/dep")).unwrap();
+        std::fs::write(root.join("ext-b/node_modules/dep/index.js"), "ignored").unwrap();
+        std::fs::write(root.join("ext-b/readme.md"), "b").unwrap();
+        std::fs::create_dir_all(root.join("other")).unwrap();
+
+        let top = scan_folders(&root, &ScanOptions::default()).unwrap();
+        let names: Vec<&str> = top.iter().map(|e| e.name.as_str()).collect();
+        assert_eq!(names, ["ext-a", "ext-b", "other"]);
+        assert_eq!((top[0].file_count, top[0].total_bytes, top[0].has_package_json), (2, 7, true));
+        assert_eq!((top[1].file_count, top[1].has_package_json), (2, false));
+        assert_eq!(top[2].file_count, 0);
+        assert!(top.iter().all(|e| e.modified.is_some()));
+
+        let options = ScanOptions { depth: 3, include: vec!["ext-*".into(), "deep".into()], exclude: vec!["node_modules".into()] };
+        let filtered = scan_folders(&root, &options).unwrap();
+        let paths: Vec<&str> = filtered.iter().map(|e| e.relative_path.as_str()).collect();
+        assert_eq!(paths, ["ext-a", "ext-a/lib/deep", "ext-b"]);
+        assert_eq!((filtered[1].depth, filtered[1].file_count), (3, 1));
+        assert_eq!((filtered[2].file_count, filtered[2].total_bytes), (1, 1));
+        assert!(scan_folders(&root.join("missing"), &ScanOptions::default()).is_err());
+    }
+
+    #[test]
+    fn scanned_extensions_carry_their_package_metadata() {
+        let folder = temp_file(
+            "scan-ext/ms-python.python-2024.1.0/package.json",
+            r#"{ "name": "python", "publisher": "ms-python", "version": "2024.1.0", "displayName": "%extension.displayName%" }"#,
+        )
+        .parent()
+        .unwrap()
+        .to_path_buf();
+        std::fs::write(folder.join("package.nls.json"), r#"{ "extension.displayName": "Python" }"#).unwrap();
+        let plain = folder.parent().unwrap().join("left-pad");
+        std::fs::create_dir_all(&plain).unwrap();
+        std::fs::write(plain.join("package.json"), r#"{ "name": "left-pad" }"#).unwrap();
+
+        let entries = scan_folders(folder.parent().unwrap(), &ScanOptions::default()).unwrap();
+        let extension = entries[1].extension.as_ref().unwrap();
+        assert_eq!(extension.id, "ms-python.python");
+        assert_eq!(extension.display_name.as_deref(), Some("Python"));
+        assert_eq!(extension.version.as_deref(), Some("2024.1.0"));
+        let package = entries[0].extension.as_ref().unwrap();
+        assert_eq!((package.id.as_str(), package.publisher.as_deref()), ("left-pad", None));
+    }
+
+    #[test]
+    fn payload_trees_are_sized_paged_and_capped() {
+        let root = temp_file("payload-tree/b.txt", "bb").parent().unwrap().to_path_buf();
+        std::fs::write(root.join("a.txt"), "a").unwrap();
+        std::fs::create_dir_all(root.join("sub/inner")).unwrap();
+        std::fs::write(root.join("sub/inner/c.bin"), "cccc").unwrap();
+
+        let tree = list_payload_tree(&root, &TreeOptions::default()).unwrap();
+        assert_eq!((tree.node.size, tree.node.file_count, tree.node.child_count), (7, 3, 3));
+        assert!(!tree.truncated);
+        let children = tree.node.children.as_ref().unwrap();
+        let names: Vec<&str> = children.iter().map(|c| c.name.as_str()).collect();
+        assert_eq!(names, ["sub", "a.txt", "b.txt"]);
+        assert_eq!((children[0].size, children[0].child_count, children[0].children.is_none()), (4, 1, true));
+        assert!(children[1].sha256.is_none());
+
+        let options = TreeOptions { path: "sub".into(), depth: 2, hashes: true, ..Default::default() };
+        let sub = list_payload_tree(&root, &options).unwrap();
+        let inner = &sub.node.children.as_ref().unwrap()[0];
+        let file = &inner.children.as_ref().unwrap()[0];
+        assert_eq!(file.relative_path, "sub/inner/c.bin");
+        assert_eq!(file.sha256.as_deref(), Some(sha256_hex(b"cccc").as_str()));
+
+        let page = list_payload_tree(&root, &TreeOptions { offset: 1, limit: 1, ..Default::default() }).unwrap();
+        assert!(page.truncated);
+        assert_eq!(page.node.children.unwrap()[0].name, "a.txt");
+        assert!(list_payload_tree(&root, &TreeOptions { path: "../..".into(), ..Default::default() }).is_err());
+    }
+}
@@ -0,0 +1,999 @@
+//! Exporting a manifest as a standalone PowerShell or bash script, for machines where the
+//! installer itself can't run (locked-down servers, CI provisioning). The script performs the
+//! same copies, patches and edits, but takes no backups and doesn't roll back.
+
+use crate::*;
+use base64::Engine as _;
+use std::collections::BTreeSet;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum ScriptFormat {
+    /// Windows PowerShell 5.1 or later, with the manifest as it applies to Windows.
+    Powershell,
+    /// bash, with the manifest as it applies to macOS and Linux. Patches and JSON edits need
+    /// python3.
+    Bash,
+}
+
+/// Writes the steps of `manifest` (hooks included) as a script, with the steps of `components`
+/// (the defaults when `None`). Payload files are listed one by one as planned from
+/// `payload_source` now, and patch contents are embedded, so the script only needs the payload
+/// folder next to it. Steps a script can't reproduce fail the export.
+pub fn export_install_script(manifest: &InstallManifest, payload_source: &Path, components: Option<&[String]>, format: ScriptFormat) -> Result<String> {
+    // The values are only known at install time, and secret ones must not end up in a script
+    if let Some(prompt) = manifest.prompts.first() {
+        let message = format!("Prompted variables ({:?}) are only supported by the installer", prompt.name);
+        return Err(MisfitError::new(ErrorCode::Failed, message).into());
+    }
+    let components = resolve_components(manifest, components)?;
+    let mut writer = ScriptWriter { format, uses_python: false };
+    let mut lines = Vec::new();
+    match format {
+        ScriptFormat::Powershell => lines.extend(writer.body(manifest, Platform::Windows, &components, payload_source)?),
+        ScriptFormat::Bash => {
+            let macos = writer.body(manifest, Platform::Macos, &components, payload_source)?;
+            let linux = writer.body(manifest, Platform::Linux, &components, payload_source)?;
+            if macos == linux {
+                lines.extend(linux);
+            } else {
+                lines.push("if [ \"$(uname -s)\" = Darwin ]; then".to_string());
+                lines.extend(indent(&macos));
+                lines.push("else".to_string());
+                lines.extend(indent(&linux));
+                lines.push("fi".to_string());
+            }
+        }
+    }
+
+    let title = format!("# {} {} install script, exported by Misfit Studio.", manifest.app_name, manifest.version);
+    let payload_dir = escape_double_quoted(format, &manifest.payload_dir.replace('\\', "/"));
+    let mut script = match format {
+        ScriptFormat::Powershell => format!(
+            "{}\n{}\n{}",
+            title,
+            POWERSHELL_PREAMBLE.replace("{payloadDir}", &payload_dir),
+            POWERSHELL_HELPERS
+        ),
+        ScriptFormat::Bash => {
+            let mut preamble = format!("#!/usr/bin/env bash\n{}\n{}", title, BASH_PREAMBLE.replace("{payloadDir}", &payload_dir));
+            if writer.uses_python {
+                preamble.push_str(&format!("\n{}\n", BASH_PYTHON_HELPER));
+            }
+            preamble
+        }
+    };
+    for line in lines {
+        script.push_str(&line);
+        script.push('\n');
+    }
+    script.push_str(&writer.echo("Install complete"));
+    script.push('\n');
+    Ok(script)
+}
+
+struct ScriptWriter {
+    format: ScriptFormat,
+    /// The bash script calls the python helper, so it gets included.
+    uses_python: bool,
+}
+
+impl ScriptWriter {
+    /// Hooks and steps for one platform, in the order the installer runs them.
+    fn body(&mut self, manifest: &InstallManifest, platform: Platform, components: &BTreeSet<String>, payload_source: &Path) -> Result<Vec<String>> {
+        let manifest = manifest.for_platform(platform);
+        let advanced_mode = manifest.advanced_mode.unwrap_or(false);
+        let mut out = Vec::new();
+        self.hooks(&mut out, "preInstall", &manifest.pre_install, HookFailure::Abort, platform, payload_source, advanced_mode)?;
+
+        let mut steps: Vec<(usize, &ManifestStep)> = manifest.install_steps.iter().enumerate().filter(|(_, s)| s.runs_on(platform) && s.in_components(components)).collect();
+        steps.sort_by_key(|(_, s)| s.phase);
+        for (index, step) in steps {
+            let lines = self
+                .step(&step.step, payload_source, advanced_mode)
+                .map_err(|e| MisfitError::from(e.context(format!("Step {} can't be exported", index + 1))).at_step(index))?;
+            out.push(String::new());
+            out.push(self.echo(&format!("Step {}: {}", index + 1, describe_step(&step.step))));
+            out.extend(lines);
+        }
+
+        self.hooks(&mut out, "postInstall", &manifest.post_install, HookFailure::Continue, platform, payload_source, advanced_mode)?;
+        Ok(out)
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn hooks(
+        &mut self,
+        out: &mut Vec<String>,
+        kind: &str,
+        hooks: &[Hook],
+        default: HookFailure,
+        platform: Platform,
+        payload_source: &Path,
+        advanced_mode: bool,
+    ) -> Result<()> {
+        for (index, hook) in hooks.iter().enumerate().filter(|(_, h)| h.runs_on(platform)) {
+            let label = format!("{} hook {}", kind, index + 1);
+            let lines = self.step(&hook.step, payload_source, advanced_mode).with_context(|| format!("{} can't be exported", label))?;
+            out.push(String::new());
+            out.push(self.echo(&format!("{}: {}", label, describe_step(&hook.step))));
+            if hook.on_error.unwrap_or(default) == HookFailure::Abort || lines.is_empty() {
+                out.extend(lines);
+                continue;
+            }
+            let warning = format!("{} failed; continuing", label);
+            match self.format {
+                ScriptFormat::Powershell => {
+                    out.push("try {".to_string());
+                    out.extend(indent(&lines));
+                    out.push("} catch {".to_string());
+                    out.push(format!("    Write-Warning ({} + \": $_\")", ps_literal(&warning)));
+                    out.push("}".to_string());
+                }
+                // Every line is one command, so chaining them stops at the first failure
+                // even though `set -e` is off inside the condition.
+                ScriptFormat::Bash => {
+                    out.push("if ! {".to_string());
+                    let last = lines.len() - 1;
+                    for (i, line) in lines.iter().enumerate() {
+                        out.push(format!("    {}{}", line, if i == last { ";" } else { " &&" }));
+                    }
+                    out.push(format!("}}; then echo {} >&2; fi", bash_literal(&warning)));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// The commands for one step. In bash each line is a single command.
+    fn step(&mut self, step: &InstallStep, payload_source: &Path, advanced_mode: bool) -> Result<Vec<String>> {
+        if let InstallStep::PatchBlock { file, .. }
+        | InstallStep::SetJsonValue { file, .. }
+        | InstallStep::Base64Embed { file, .. }
+        | InstallStep::Verify { file, .. } = step
+        {
+            if split_asar_path(Path::new(file)).is_some() {
+                return Err(anyhow!("{} is inside an .asar archive", file));
+            }
+        }
+        let ps = self.format == ScriptFormat::Powershell;
+        let mut lines = Vec::new();
+        match step {
+            InstallStep::Copy { src, dest, exclude, overwrite, replacements } => {
+                if replacements.is_some() {
+                    return Err(anyhow!("replacements in copy steps are only supported by the installer"));
+                }
+                // Planned against an empty destination so each job's `dest` is its relative path
+                let plan = plan_copy_step(src, Path::new(""), exclude.as_deref().unwrap_or_default(), payload_source)?;
+                let policy = match overwrite {
+                    OverwritePolicy::Always => "always",
+                    // Nobody can answer a prompt, so existing files are kept
+                    OverwritePolicy::Never | OverwritePolicy::Prompt => "never",
+                    OverwritePolicy::IfNewer => "ifNewer",
+                };
+                let target = |rel: &Path| {
+                    let rel = rel.to_string_lossy().replace('\\', "/");
+                    if rel.is_empty() {
+                        dest.clone()
+                    } else {
+                        format!("{}/{}", dest.trim_end_matches(['/', '\\']), rel)
+                    }
+                };
+                for dir in plan.dirs.iter().filter(|dir| !plan.files.iter().any(|job| job.dest.starts_with(dir))) {
+                    let path = self.target_path(&target(dir));
+                    lines.push(if ps { format!("New-Folder {}", path) } else { format!("mkdir -p -- {}", path) });
+                }
+                for job in &plan.files {
+                    let to = self.target_path(&target(&job.dest));
+                    if let Some(link) = &job.link {
+                        let link = self.literal(&link.to_string_lossy());
+                        lines.push(if ps { format!("New-PayloadLink {} {}", link, to) } else { format!("link_file {} {}", link, to) });
+                        continue;
+                    }
+                    let rel = job.src.strip_prefix(payload_source).context("Copy source is outside the payload folder")?;
+                    let from = self.payload_path(&rel.to_string_lossy());
+                    lines.push(if ps { format!("Copy-PayloadFile {} {} {}", from, to, policy) } else { format!("copy_file {} {} {}", from, to, policy) });
+                }
+            }
+            InstallStep::PatchBlock { file, marker_mode, insert_if_missing, .. } => {
+                if *marker_mode == MarkerMode::Anchor {
+                    return Err(anyhow!("anchor markers are only supported by the installer"));
+                }
+                if insert_if_missing.is_some() {
+                    return Err(anyhow!("insertIfMissing is only supported by the installer"));
+                }
+                let regex = *marker_mode == MarkerMode::Regex;
+                let path = self.target_path(file);
+                let blocks: Vec<Vec<String>> = patch_specs(step, payload_source, advanced_mode)?
+                    .iter()
+                    .map(|spec| {
+                        [&spec.start_marker, &spec.end_marker, &spec.content, spec.patch_id.as_deref().unwrap_or_default()]
+                            .iter()
+                            .map(|text| self.literal(&base64_text(text)))
+                            .collect()
+                    })
+                    .collect();
+                if ps {
+                    let blocks: Vec<String> = blocks.iter().map(|b| format!("@({})", b.join(", "))).collect();
+                    // A single nested array needs the unary comma, or PowerShell flattens it
+                    let list = if blocks.len() == 1 { format!("@(,{})", blocks[0]) } else { format!("@({})", blocks.join(", ")) };
+                    lines.push(format!("Set-PatchBlocks {} ${} ${} {}", path, regex, advanced_mode, list));
+                } else {
+                    self.uses_python = true;
+                    let mode = if regex { "regex" } else { "exact" };
+                    let strip = if advanced_mode { "strip" } else { "keep" };
+                    let blocks: Vec<String> = blocks.concat();
+                    lines.push(format!("misfit_py patch {} {} {} {}", path, mode, strip, blocks.join(" ")));
+                }
+            }
+            InstallStep::SetJsonValue { file, key_path, value } => {
+                let keys = self.literal(&base64_text(&serde_json::to_string(&split_key_path(key_path)?)?));
+                let value = self.literal(&base64_text(&value.to_string()));
+                let path = self.target_path(file);
+                lines.push(self.helper_call("Set-JsonValue", "set-json", &[&path, &keys, &value]));
+            }
+            InstallStep::Base64Embed { file, placeholder, input_file, occurrences, data_uri, replacements } => {
+                let input = payload_source.join(normalize_rel_path(input_file, false)?);
+                let bytes = embed_input(&input, replacements.as_ref()).with_context(|| format!("Failed to read {}", input.display()))?;
+                let mut encoded = base64::engine::general_purpose::STANDARD.encode(&bytes);
+                if *data_uri {
+                    encoded = format!("data:{};base64,{}", sniff_mime(&bytes, &input), encoded);
+                }
+                let limit = occurrences.map_or(-1, |n| n as i64).to_string();
+                let path = self.target_path(file);
+                let placeholder = self.literal(&base64_text(placeholder));
+                let encoded = self.literal(&encoded);
+                lines.push(self.helper_call("Set-EmbeddedValue", "embed", &[&path, &placeholder, &encoded, &limit]));
+            }
+            InstallStep::RunCommand { command, args, cwd, env, shell, elevate, .. } => {
+                let (program, args) = match shell {
+                    None => (command.clone(), args.clone()),
+                    Some(CommandShell::Cmd) if ps => ("cmd".to_string(), vec!["/C".to_string(), command_line(command, args)]),
+                    Some(CommandShell::Cmd) => return Err(anyhow!("the cmd shell is only available on Windows")),
+                    Some(CommandShell::Powershell) => {
+                        let shell = if ps { "powershell" } else { "pwsh" };
+                        let line = command_line(command, args);
+                        (shell.to_string(), ["-NoProfile", "-NonInteractive", "-ExecutionPolicy", "Bypass", "-Command", &line].map(String::from).to_vec())
+                    }
+                };
+                let env = env.clone().unwrap_or_default();
+                let cwd = cwd.as_deref().map(|dir| self.target_path(dir));
+                let elevate = elevate.unwrap_or(false);
+                if ps {
+                    for (name, value) in &env {
+                        lines.push(format!("${{env:{}}} = {}", name, ps_literal(value)));
+                    }
+                    if let Some(cwd) = &cwd {
+                        lines.push(format!("Push-Location -LiteralPath {}", cwd));
+                    }
+                    if elevate {
+                        lines.push(format!("Invoke-Elevated {} {}", ps_literal(&program), ps_literal(command_line("", &args).trim_start())));
+                    } else {
+                        let args: Vec<String> = args.iter().map(|a| ps_literal(a)).collect();
+                        lines.push(format!("& {} {}", ps_literal(&program), args.join(" ")).trim_end().to_string());
+                        lines.push(format!("Assert-ExitCode {}", ps_literal(&program)));
+                    }
+                    if cwd.is_some() {
+                        lines.push("Pop-Location".to_string());
+                    }
+                    for name in env.keys() {
+                        lines.push(format!("Remove-Item {}", ps_literal(&format!("Env:{}", name))));
+                    }
+                } else {
+                    let mut words = Vec::new();
+                    if elevate {
+                        words.push("sudo".to_string());
+                    }
+                    if !env.is_empty() {
+                        words.push("env".to_string());
+                        words.extend(env.iter().map(|(name, value)| bash_literal(&format!("{}={}", name, value))));
+                    }
+                    words.push(bash_literal(&program));
+                    words.extend(args.iter().map(|a| bash_literal(a)));
+                    let line = words.join(" ");
+                    lines.push(match cwd {
+                        Some(cwd) => format!("(cd {} && {})", cwd, line),
+                        None => line,
+                    });
+                }
+            }
+            InstallStep::InstallVsix { vsix_file, cli_path } => {
+                let vsix = self.payload_path(&normalize_rel_path(vsix_file, false)?.to_string_lossy());
+                let cli = match cli_path {
+                    Some(cli) => self.target_path(cli),
+                    None => "code".to_string(),
+                };
+                if ps {
+                    lines.push(format!("& {} --install-extension {} --force", cli, vsix));
+                    lines.push(format!("Assert-ExitCode {}", ps_literal(cli_path.as_deref().unwrap_or("code"))));
+                } else {
+                    lines.push(format!("{} --install-extension {} --force", cli, vsix));
+                }
+            }
+            InstallStep::FixVsCodeChecksums { app_dir } => {
+                let path = self.target_path(app_dir);
+                lines.push(self.helper_call("Update-VsCodeChecksums", "fix-checksums", &[&path]));
+            }
+            InstallStep::Delete { path } => {
+                let path = self.target_path(path);
+                lines.push(if ps { format!("Remove-Target {}", path) } else { format!("remove_target {}", path) });
+            }
+            InstallStep::Verify { file, contains, sha256, json_path, equals } => {
+                let keys = json_path.as_deref().map(split_key_path).transpose()?.map(|keys| serde_json::to_string(&keys)).transpose()?;
+                let args = [
+                    self.target_path(file),
+                    self.literal(&contains.as_deref().map(base64_text).unwrap_or_default()),
+                    self.literal(&sha256.as_deref().map(|s| s.trim().to_ascii_lowercase()).unwrap_or_default()),
+                    self.literal(&keys.as_deref().map(base64_text).unwrap_or_default()),
+                    self.literal(&equals.as_ref().map(|v| base64_text(&v.to_string())).unwrap_or_default()),
+                ];
+                let args: Vec<&str> = args.iter().map(String::as_str).collect();
+                lines.push(self.helper_call("Test-InstalledFile", "verify", &args));
+            }
+        }
+        Ok(lines)
+    }
+
+    /// A call to the PowerShell function or python helper command doing the work.
+    fn helper_call(&mut self, function: &str, command: &str, args: &[&str]) -> String {
+        match self.format {
+            ScriptFormat::Powershell => format!("{} {}", function, args.join(" ")),
+            ScriptFormat::Bash => {
+                self.uses_python = true;
+                format!("misfit_py {} {}", command, args.join(" "))
+            }
+        }
+    }
+
+    fn echo(&self, message: &str) -> String {
+        match self.format {
+            ScriptFormat::Powershell => format!("Write-Host {}", ps_literal(message)),
+            ScriptFormat::Bash => format!("echo {}", bash_literal(message)),
+        }
+    }
+
+    fn literal(&self, text: &str) -> String {
+        match self.format {
+            ScriptFormat::Powershell => ps_literal(text),
+            ScriptFormat::Bash => bash_literal(text),
+        }
+    }
+
+    fn payload_path(&self, rel: &str) -> String {
+        let variable = match self.format {
+            ScriptFormat::Powershell => "${PayloadDir}",
+            ScriptFormat::Bash => "${PAYLOAD_DIR}",
+        };
+        format!("\"{}/{}\"", variable, escape_double_quoted(self.format, &rel.replace('\\', "/")))
+    }
+
+    /// A manifest path as a double-quoted string that expands the same variables and folder
+    /// tokens as [`expand_env_vars`] when the script runs. Relative paths start at the
+    /// script's target base.
+    fn target_path(&self, path: &str) -> String {
+        let ps = self.format == ScriptFormat::Powershell;
+        let parts = path_parts(path);
+        let relative = match parts.first() {
+            Some(PathPart::Text(text)) => !is_absolute_text(text),
+            Some(_) => false,
+            None => true,
+        };
+        let mut out = String::from("\"");
+        if relative {
+            out.push_str(if ps { "${TargetBase}/" } else { "${TARGET_BASE}/" });
+        }
+        for part in parts {
+            match part {
+                PathPart::Text(text) => out.push_str(&escape_double_quoted(self.format, &text)),
+                PathPart::Env(name) if ps => out.push_str(&format!("${{env:{}}}", name)),
+                // Bash can't expand names like `ProgramFiles(x86)` itself
+                PathPart::Env(name) if name.contains('(') => out.push_str(&format!("$(printenv '{}')", name)),
+                PathPart::Env(name) => out.push_str(&format!("${{{}}}", name)),
+                PathPart::Folder(name) if ps => out.push_str(&format!("$(Get-KnownFolder '{}')", name)),
+                PathPart::Folder(name) => out.push_str(&format!("$(known_folder {})", name)),
+                PathPart::Home => out.push_str("${HOME}"),
+            }
+        }
+        out.push('"');
+        out
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum PathPart {
+    Text(String),
+    Env(String),
+    /// A `{token}` from [`KNOWN_FOLDERS`], lowercased.
+    Folder(String),
+    Home,
+}
+
+/// Splits a manifest path the way [`expand_env_vars`] reads it.
+fn path_parts(path: &str) -> Vec<PathPart> {
+    let chars: Vec<char> = path.chars().collect();
+    let mut parts = Vec::new();
+    let mut text = String::new();
+    let mut i = 0;
+    if chars.first() == Some(&'~') && matches!(chars.get(1), None | Some('/') | Some('\\')) {
+        parts.push(PathPart::Home);
+        i = 1;
+    }
+    let name_end = |from: usize, close: char| chars[from..].iter().position(|c| *c == close).map(|end| from + end);
+    while i < chars.len() {
+        let part = match chars[i] {
+            '%' => env_name(&chars, i + 1, name_end(i + 1, '%')),
+            '{' => name_end(i + 1, '}').and_then(|end| {
+                let name = chars[i + 1..end].iter().collect::<String>().to_ascii_lowercase();
+                KNOWN_FOLDERS.contains(&name.as_str()).then_some((PathPart::Folder(name), end + 1))
+            }),
+            '$' if chars.get(i + 1) == Some(&'{') => env_name(&chars, i + 2, name_end(i + 2, '}')),
+            '$' => {
+                let end = chars[i + 1..].iter().position(|c| !(c.is_ascii_alphanumeric() || *c == '_')).map_or(chars.len(), |n| i + 1 + n);
+                (end > i + 1).then(|| (PathPart::Env(chars[i + 1..end].iter().collect()), end))
+            }
+            _ => None,
+        };
+        match part {
+            Some((part, next)) => {
+                if !text.is_empty() {
+                    parts.push(PathPart::Text(std::mem::take(&mut text)));
+                }
+                parts.push(part);
+                i = next;
+            }
+            None => {
+                text.push(chars[i]);
+                i += 1;
+            }
+        }
+    }
+    if !text.is_empty() {
+        parts.push(PathPart::Text(text));
+    }
+    parts
+}
+
+/// The variable named by `chars[from..end]` and where the text after its closing delimiter
+/// starts. Only `[A-Za-z0-9_()]+` counts as a name; anything else stays text and is escaped.
+fn env_name(chars: &[char], from: usize, end: Option<usize>) -> Option<(PathPart, usize)> {
+    let end = end.filter(|end| *end > from)?;
+    let name = &chars[from..end];
+    name.iter().all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '(' | ')')).then(|| (PathPart::Env(name.iter().collect()), end + 1))
+}
+
+fn is_absolute_text(text: &str) -> bool {
+    let bytes = text.as_bytes();
+    text.starts_with(['/', '\\']) || (bytes.len() >= 2 && bytes[0].is_ascii_alphabetic() && bytes[1] == b':')
+}
+
+fn describe_step(step: &InstallStep) -> String {
+    match step {
+        InstallStep::Copy { src, dest, .. } => format!("Copy {} to {}", src, dest),
+        InstallStep::PatchBlock { file, .. } => format!("Patch {}", file),
+        InstallStep::SetJsonValue { file, key_path, value } => format!("Set {} = {} in {}", key_path, value, file),
+        InstallStep::RunCommand { command, args, .. } => format!("Run {}", command_line(command, args)),
+        InstallStep::Base64Embed { file, placeholder, input_file, .. } => format!("Embed {} into {} at {}", input_file, file, placeholder),
+        InstallStep::InstallVsix { vsix_file, .. } => format!("Install extension {}", vsix_file),
+        InstallStep::FixVsCodeChecksums { app_dir } => format!("Fix VS Code checksums in {}", app_dir),
+        InstallStep::Delete { path } => format!("Delete {}", path),
+        InstallStep::Verify { file, .. } => format!("Verify {}", file),
+    }
+}
+
+/// Text handed to the script's helpers is base64 encoded, so markers and contents need no
+/// quoting.
+fn base64_text(text: &str) -> String {
+    base64::engine::general_purpose::STANDARD.encode(text)
+}
+
+fn indent(lines: &[String]) -> Vec<String> {
+    lines.iter().map(|line| if line.is_empty() { String::new() } else { format!("    {}", line) }).collect()
+}
+
+/// PowerShell single-quoted string; it also treats typographic quotes as quotes.
+fn ps_literal(text: &str) -> String {
+    let mut out = String::from("'");
+    for ch in text.chars() {
+        if matches!(ch, '\'' | '\u{2018}' | '\u{2019}' | '\u{201A}' | '\u{201B}') {
+            out.push(ch);
+        }
+        out.push(ch);
+    }
+    out.push('\'');
+    out
+}
+
+fn bash_literal(text: &str) -> String {
+    format!("'{}'", text.replace('\'', "'\\''"))
+}
+
+/// Escapes literal text for the inside of a double-quoted string.
+fn escape_double_quoted(format: ScriptFormat, text: &str) -> String {
+    let mut out = String::new();
+    for ch in text.chars() {
+        match format {
+            ScriptFormat::Powershell if matches!(ch, '`' | '"' | '$' | '\u{201C}' | '\u{201D}' | '\u{201E}') => out.push('`'),
+            ScriptFormat::Bash if matches!(ch, '\\' | '"' | '$' | '`') => out.push('\\'),
+            _ => {}
+        }
+        out.push(ch);
+    }
+    out
+}
+
+const POWERSHELL_PREAMBLE: &str = r#"# Runs the manifest's steps directly: nothing is backed up, and a failed step is not rolled back.
+# Save it in the project folder (next to manifests/) or pass -PayloadDir and -TargetBase.
+param(
+    [string]$PayloadDir = "$PSScriptRoot/{payloadDir}",
+    # Relative target paths start here, like they start at the manifest folder in the installer
+    [string]$TargetBase = "$PSScriptRoot/manifests"
+)
+$ErrorActionPreference = 'Stop'
+"#;
+
+const POWERSHELL_HELPERS: &str = r#"# Files are edited as ISO-8859-1 text, which maps every byte to one character, so markers and
+# contents match byte for byte whatever the file's encoding.
+$Latin1 = [Text.Encoding]::GetEncoding(28591)
+function Read-Bytes([string]$Path) { $Latin1.GetString([IO.File]::ReadAllBytes($Path)) }
+function Write-Bytes([string]$Path, [string]$Text) { [IO.File]::WriteAllBytes($Path, $Latin1.GetBytes($Text)) }
+function Get-Bytes([string]$Base64) { $Latin1.GetString([Convert]::FromBase64String($Base64)) }
+function Get-Text([string]$Base64) { [Text.Encoding]::UTF8.GetString([Convert]::FromBase64String($Base64)) }
+
+function Get-KnownFolder([string]$Name) {
+    switch ($Name) {
+        'appdata' { [Environment]::GetFolderPath('ApplicationData') }
+        'localappdata' { [Environment]::GetFolderPath('LocalApplicationData') }
+        'documents' { [Environment]::GetFolderPath('MyDocuments') }
+        'desktop' { [Environment]::GetFolderPath('Desktop') }
+        'downloads' { Join-Path $HOME 'Downloads' }
+        'home' { $HOME }
+        'temp' { [IO.Path]::GetTempPath().TrimEnd('\') }
+    }
+}
+
+function New-Folder([string]$Path) { [void][IO.Directory]::CreateDirectory($Path) }
+
+function Copy-PayloadFile([string]$Source, [string]$Destination, [string]$Overwrite) {
+    if (Test-Path -LiteralPath $Destination) {
+        if ($Overwrite -eq 'never') { return }
+        if ($Overwrite -eq 'ifNewer' -and [IO.File]::GetLastWriteTimeUtc($Source) -le [IO.File]::GetLastWriteTimeUtc($Destination)) { return }
+    }
+    New-Folder ([IO.Path]::GetDirectoryName($Destination))
+    [IO.File]::Copy($Source, $Destination, $true)
+}
+
+function New-PayloadLink([string]$Target, [string]$Destination) {
+    New-Folder ([IO.Path]::GetDirectoryName($Destination))
+    New-Item -ItemType SymbolicLink -Path $Destination -Target $Target -Force | Out-Null
+}
+
+# Like the installer, refuses to delete a root folder, the home folder, or the target base or one of its parents
+function Remove-Target([string]$Path) {
+    if (-not (Test-Path -LiteralPath $Path)) { return }
+    $separators = [char[]]('\', '/')
+    $full = (Resolve-Path -LiteralPath $Path).ProviderPath.TrimEnd($separators)
+    $base = [IO.Path]::GetFullPath($TargetBase).TrimEnd($separators)
+    $userHome = [Environment]::GetFolderPath('UserProfile').TrimEnd($separators)
+    $isRoot = [IO.Path]::GetPathRoot($full + [IO.Path]::DirectorySeparatorChar).TrimEnd($separators) -eq $full
+    $containsBase = $base -eq $full -or $base.StartsWith($full + [IO.Path]::DirectorySeparatorChar, [StringComparison]::OrdinalIgnoreCase)
+    if ($isRoot -or $full -eq $userHome -or $containsBase) {
+        throw "Refusing to delete ${Path}: it is a root folder, the home folder or contains the target base"
+    }
+    Remove-Item -LiteralPath $Path -Recurse -Force
+}
+
+function Assert-ExitCode([string]$Command) {
+    if ($LASTEXITCODE -ne 0) { throw "Command $Command exited with code $LASTEXITCODE" }
+}
+
+function Invoke-Elevated([string]$Command, [string]$Arguments) {
+    $start = @{ FilePath = $Command; Verb = 'RunAs'; Wait = $true; PassThru = $true }
+    if ($Arguments) { $start.ArgumentList = $Arguments }
+    $process = Start-Process @start
+    if ($process.ExitCode -ne 0) { throw "Command $Command exited with code $($process.ExitCode)" }
+}
+
+# Returns the start, content start, content end and end offsets of a block, or nothing when
+# the start marker is missing.
+function Find-Block([string]$Text, [string]$Start, [string]$End, [bool]$Regex) {
+    if ($Regex) {
+        $s = [regex]::Match($Text, $Start)
+        if (-not $s.Success) { return }
+        $e = ([regex]$End).Match($Text, $s.Index + $s.Length)
+        if (-not $e.Success) { throw 'End marker not found' }
+        return @($s.Index, ($s.Index + $s.Length), $e.Index, ($e.Index + $e.Length))
+    }
+    $s = $Text.IndexOf($Start, [StringComparison]::Ordinal)
+    if ($s -lt 0) { return }
+    $inner = $s + $Start.Length
+    $e = $Text.IndexOf($End, $inner, [StringComparison]::Ordinal)
+    if ($e -lt 0) { throw 'End marker not found' }
+    @($s, $inner, $e, ($e + $End.Length))
+}
+
+# Each block is @(startMarker, endMarker, content, patchId), base64 encoded. Blocks that are
+# already applied are skipped, and the file is written once.
+function Set-PatchBlocks([string]$File, [bool]$Regex, [bool]$StripMarkers, [object[]]$Blocks) {
+    $text = Read-Bytes $File
+    $changed = $false
+    foreach ($block in $Blocks) {
+        $start, $end, $content, $patchId = $block | ForEach-Object { Get-Bytes $_ }
+        $span = Find-Block $text $start $end $Regex
+        if ($null -eq $span) {
+            if (($patchId -and $text.Contains($patchId)) -or ($StripMarkers -and $content -and $text.Contains($content))) { continue }
+            throw "Start marker not found in $File"
+        }
+        $existing = $text.Substring($span[1], $span[2] - $span[1])
+        if (-not $StripMarkers -and ($existing -ceq $content -or ($patchId -and $existing.Contains($patchId)))) { continue }
+        if ($StripMarkers) { $from, $to = $span[0], $span[3] } else { $from, $to = $span[1], $span[2] }
+        $text = $text.Substring(0, $from) + $content + $text.Substring($to)
+        $changed = $true
+    }
+    if ($changed) { Write-Bytes $File $text }
+}
+
+function Set-JsonValue([string]$File, [string]$Keys, [string]$Value) {
+    $doc = if (Test-Path -LiteralPath $File) { [IO.File]::ReadAllText($File) | ConvertFrom-Json } else { [pscustomobject]@{} }
+    $path = @(Get-Text $Keys | ConvertFrom-Json)
+    $node = $doc
+    for ($i = 0; $i -lt $path.Count - 1; $i++) {
+        if ($null -eq $node.PSObject.Properties[$path[$i]]) {
+            $node | Add-Member -NotePropertyName $path[$i] -NotePropertyValue ([pscustomobject]@{})
+        }
+        $node = $node.($path[$i])
+        if ($node -isnot [Management.Automation.PSCustomObject]) { throw "Path traversal failed in $File, not an object" }
+    }
+    $node | Add-Member -NotePropertyName $path[-1] -NotePropertyValue (Get-Text $Value | ConvertFrom-Json) -Force
+    [IO.File]::WriteAllText($File, (ConvertTo-Json $doc -Depth 100))
+}
+
+# Replaces up to $Limit placeholders (all when -1); fine when the value is already embedded.
+function Set-EmbeddedValue([string]$File, [string]$Placeholder, [string]$Value, [int]$Limit) {
+    $text = Read-Bytes $File
+    $Placeholder = Get-Bytes $Placeholder
+    $out = New-Object Text.StringBuilder
+    $count = 0
+    $at = 0
+    while ($Placeholder -and ($Limit -lt 0 -or $count -lt $Limit)) {
+        $idx = $text.IndexOf($Placeholder, $at, [StringComparison]::Ordinal)
+        if ($idx -lt 0) { break }
+        [void]$out.Append($text, $at, $idx - $at).Append($Value)
+        $at = $idx + $Placeholder.Length
+        $count++
+    }
+    if ($count -eq 0) {
+        if ($text.Contains($Value)) { return }
+        throw "Placeholder not found in $File"
+    }
+    [void]$out.Append($text, $at, $text.Length - $at)
+    Write-Bytes $File $out.ToString()
+}
+
+function Test-InstalledFile([string]$File, [string]$Contains, [string]$Sha256, [string]$Keys, [string]$EqualsJson) {
+    if (-not (Test-Path -LiteralPath $File -PathType Leaf)) { throw "$File does not exist or cannot be read" }
+    if ($Contains -and -not (Read-Bytes $File).Contains((Get-Bytes $Contains))) { throw "$File does not contain the expected text" }
+    if ($Sha256 -and (Get-FileHash -LiteralPath $File -Algorithm SHA256).Hash -ne $Sha256) { throw "$File has a different SHA-256" }
+    if ($Keys -or $EqualsJson) {
+        $node = [IO.File]::ReadAllText($File) | ConvertFrom-Json
+        if ($Keys) {
+            foreach ($key in @(Get-Text $Keys | ConvertFrom-Json)) {
+                if ($null -eq $node -or $null -eq $node.PSObject.Properties[$key]) { throw "$File has no value at $key" }
+                $node = $node.$key
+            }
+        }
+        $expected = if ($EqualsJson) { Get-Text $EqualsJson | ConvertFrom-Json }
+        if ($EqualsJson -and (ConvertTo-Json $node -Depth 100 -Compress) -cne (ConvertTo-Json $expected -Depth 100 -Compress)) {
+            throw "$File does not have the expected value"
+        }
+    }
+}
+
+function Update-VsCodeChecksums([string]$AppDir) {
+    $product = @('', '/resources/app', '/Contents/Resources/app') | ForEach-Object { "$AppDir$_/product.json" } |
+        Where-Object { Test-Path -LiteralPath $_ -PathType Leaf } | Select-Object -First 1
+    if (-not $product) { throw "No VS Code product.json found in $AppDir" }
+    $text = [IO.File]::ReadAllText($product)
+    $checksums = ($text | ConvertFrom-Json).checksums
+    if ($null -eq $checksums) { return }
+    $out = Join-Path (Split-Path $product) 'out'
+    $sha = [Security.Cryptography.SHA256]::Create()
+    foreach ($entry in $checksums.PSObject.Properties) {
+        $file = Join-Path $out $entry.Name
+        if ($entry.Value -isnot [string] -or -not (Test-Path -LiteralPath $file -PathType Leaf)) { continue }
+        $actual = [Convert]::ToBase64String($sha.ComputeHash([IO.File]::ReadAllBytes($file))).TrimEnd('=')
+        if ($actual -cne $entry.Value) {
+            $pattern = '("' + [regex]::Escape($entry.Name) + '"\s*:\s*)"' + [regex]::Escape($entry.Value) + '"'
+            $text = ([regex]$pattern).Replace($text, '${1}"' + $actual + '"', 1)
+        }
+    }
+    [IO.File]::WriteAllText($product, $text)
+}
+"#;
+
+const BASH_PREAMBLE: &str = r#"# Runs the manifest's steps directly: nothing is backed up, and a failed step is not rolled back.
+# Save it in the project folder (next to manifests/) or set PAYLOAD_DIR and TARGET_BASE.
+set -euo pipefail
+SCRIPT_DIR="$(cd "$(dirname "$0")" && pwd)"
+PAYLOAD_DIR="${PAYLOAD_DIR:-$SCRIPT_DIR/{payloadDir}}"
+# Relative target paths start here, like they start at the manifest folder in the installer
+TARGET_BASE="${TARGET_BASE:-$SCRIPT_DIR/manifests}"
+
+known_folder() {
+    local darwin=
+    [ "$(uname -s)" = Darwin ] && darwin=1
+    case "$1" in
+        appdata) if [ -n "$darwin" ]; then echo "$HOME/Library/Application Support"; else echo "${XDG_CONFIG_HOME:-$HOME/.config}"; fi ;;
+        localappdata) if [ -n "$darwin" ]; then echo "$HOME/Library/Application Support"; else echo "${XDG_DATA_HOME:-$HOME/.local/share}"; fi ;;
+        documents) echo "$HOME/Documents" ;;
+        desktop) echo "$HOME/Desktop" ;;
+        downloads) echo "$HOME/Downloads" ;;
+        home) echo "$HOME" ;;
+        temp) echo "${TMPDIR:-/tmp}" ;;
+    esac
+}
+
+# copy_file <source> <destination> <always|never|ifNewer>
+copy_file() {
+    if [ -e "$2" ]; then
+        [ "$3" = never ] && return 0
+        [ "$3" = ifNewer ] && ! [ "$1" -nt "$2" ] && return 0
+    fi
+    mkdir -p -- "$(dirname -- "$2")" && cp -- "$1" "$2"
+}
+
+link_file() {
+    mkdir -p -- "$(dirname -- "$2")" && ln -sfn -- "$1" "$2"
+}
+
+# Like the installer, refuses to delete a root folder, the home folder, or the target base or one of its parents
+remove_target() {
+    if [ -d "$1" ] && ! [ -L "$1" ]; then
+        local target base home
+        target="$(cd -P -- "$1" && pwd -P)"
+        base="$(cd -P -- "$TARGET_BASE" 2>/dev/null && pwd -P || echo "$TARGET_BASE")"
+        home="$(cd -P -- "$HOME" 2>/dev/null && pwd -P || echo "$HOME")"
+        case "$base/" in
+            "${target%/}/"*) target=/ ;;
+        esac
+        if [ "$target" = / ] || [ "$target" = "$home" ]; then
+            echo "Refusing to delete $1: it is a root folder, the home folder or contains the target base" >&2
+            return 1
+        fi
+    fi
+    rm -rf -- "$1"
+}
+"#;
+
+const BASH_PYTHON_HELPER: &str = r#"command -v python3 >/dev/null || { echo "This script needs python3" >&2; exit 1; }
+
+# Patches, JSON edits, embeds, checks and VS Code checksums. Text arguments are base64 encoded.
+misfit_py() {
+    python3 - "$@" <<'PY'
+import base64, hashlib, json, os, re, shutil, sys
+
+def raw(value):
+    return base64.b64decode(value)
+
+def text(value):
+    return raw(value).decode("utf-8")
+
+def read(path):
+    with open(path, "rb") as f:
+        return f.read()
+
+def write(path, data):
+    tmp = os.path.join(os.path.dirname(path) or ".", ".%s.misfit-%d.tmp" % (os.path.basename(path), os.getpid()))
+    with open(tmp, "wb") as f:
+        f.write(data)
+    if os.path.exists(path):
+        shutil.copymode(path, tmp)
+    os.replace(tmp, path)
+
+def fail(message):
+    sys.exit("Error: " + message)
+
+def find_block(data, start, end, mode):
+    if mode == "regex":
+        s = re.search(start, data)
+        if not s:
+            return None
+        e = re.compile(end).search(data, s.end())
+        if not e:
+            fail("End marker not found")
+        return s.start(), s.end(), e.start(), e.end()
+    s = data.find(start)
+    if s < 0:
+        return None
+    inner = s + len(start)
+    e = data.find(end, inner)
+    if e < 0:
+        fail("End marker not found")
+    return s, inner, e, e + len(end)
+
+def patch(path, mode, strip, *blocks):
+    data = read(path)
+    changed = False
+    for i in range(0, len(blocks), 4):
+        start, end, content, patch_id = (raw(b) for b in blocks[i:i + 4])
+        span = find_block(data, start, end, mode)
+        if span is None:
+            if (patch_id and patch_id in data) or (strip == "strip" and content and content in data):
+                continue
+            fail("Start marker not found in " + path)
+        existing = data[span[1]:span[2]]
+        if strip != "strip" and (existing == content or (patch_id and patch_id in existing)):
+            continue
+        lo, hi = (span[0], span[3]) if strip == "strip" else (span[1], span[2])
+        data = data[:lo] + content + data[hi:]
+        changed = True
+    if changed:
+        write(path, data)
+
+def set_json(path, keys, value):
+    doc = json.loads(read(path)) if os.path.exists(path) else {}
+    keys = json.loads(text(keys))
+    node = doc
+    for key in keys[:-1]:
+        if not isinstance(node, dict):
+            fail("Path traversal failed in %s, not an object" % path)
+        node = node.setdefault(key, {})
+    if not isinstance(node, dict):
+        fail("Target path in %s is not an object" % path)
+    node[keys[-1]] = json.loads(text(value))
+    write(path, json.dumps(doc, indent=2, ensure_ascii=False).encode("utf-8"))
+
+def embed(path, placeholder, value, limit):
+    data, placeholder, value, limit = read(path), raw(placeholder), value.encode(), int(limit)
+    count = data.count(placeholder) if placeholder else 0
+    if limit >= 0:
+        count = min(count, limit)
+    if count == 0:
+        if value in data:
+            return
+        fail("Placeholder not found in " + path)
+    write(path, data.replace(placeholder, value, count))
+
+def verify(path, contains, sha256, keys, equals):
+    if not os.path.isfile(path):
+        fail(path + " does not exist or cannot be read")
+    data = read(path)
+    if contains and raw(contains) not in data:
+        fail(path + " does not contain the expected text")
+    if sha256 and hashlib.sha256(data).hexdigest() != sha256:
+        fail(path + " has a different SHA-256")
+    if keys or equals:
+        node = json.loads(data)
+        for key in json.loads(text(keys)) if keys else []:
+            if not isinstance(node, dict) or key not in node:
+                fail("%s has no value at %s" % (path, key))
+            node = node[key]
+        if equals and node != json.loads(text(equals)):
+            fail(path + " does not have the expected value")
+
+def fix_checksums(app_dir):
+    for sub in ("", "resources/app", "Contents/Resources/app"):
+        product = os.path.join(app_dir, sub, "product.json")
+        if os.path.isfile(product):
+            break
+    else:
+        fail("No VS Code product.json found in " + app_dir)
+    content = read(product).decode("utf-8")
+    out = os.path.join(os.path.dirname(product), "out")
+    changed = False
+    for name, recorded in (json.loads(content).get("checksums") or {}).items():
+        file = os.path.join(out, name)
+        if not isinstance(recorded, str) or not os.path.isfile(file):
+            continue
+        actual = base64.b64encode(hashlib.sha256(read(file)).digest()).decode().rstrip("=")
+        if actual != recorded:
+            pattern = '("' + re.escape(name) + r'"\s*:\s*)"' + re.escape(recorded) + '"'
+            content = re.sub(pattern, lambda m: m.group(1) + '"' + actual + '"', content, count=1)
+            changed = True
+    if changed:
+        write(product, content.encode("utf-8"))
+
+commands = {"patch": patch, "set-json": set_json, "embed": embed, "verify": verify, "fix-checksums": fix_checksums}
+commands[sys.argv[1]](*sys.argv[2:])
+PY
+}"#;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::{temp_file, test_manifest};
+
+    #[test]
+    fn export_install_script_reproduces_the_install() {
+        let index = temp_file("export/manifests/target/index.html", "<head><!-- misfit:start -->old<!-- misfit:end --></head>");
+        let root = index.parent().unwrap().parent().unwrap().parent().unwrap().to_path_buf();
+        let target = root.join("manifests/target");
+        std::fs::write(target.join("settings.json"), "{\"editor\": {}}").unwrap();
+        std::fs::write(target.join("old.txt"), "old").unwrap();
+        let payload = root.join("payload");
+        std::fs::create_dir_all(payload.join("theme")).unwrap();
+        std::fs::write(payload.join("theme/app.css"), "body { color: red; }").unwrap();
+        std::fs::write(payload.join("theme/app.css.map"), "{}").unwrap();
+        std::fs::write(payload.join("patch.html"), "<style>it's new</style>").unwrap();
+        let mut manifest: InstallManifest = serde_json::from_value(serde_json::json!({
+            "appName": "Exported", "version": "1.0.0", "publisher": "", "description": "",
+            "targets": [], "payloadDir": "payload",
+            "installSteps": [
+                { "type": "copy", "src": "theme", "dest": "target/theme", "exclude": ["*.map"] },
+                { "type": "patchBlock", "file": "target/index.html", "startMarker": "<!-- misfit:start -->",
+                  "endMarker": "<!-- misfit:end -->", "contentFile": "patch.html" },
+                { "type": "setJsonValue", "file": "target/settings.json", "keyPath": "workbench\\.colorTheme", "value": "Misfit" },
+                { "type": "delete", "path": "target/old.txt" },
+                { "type": "verify", "file": "target/settings.json", "jsonPath": "workbench\\.colorTheme", "equals": "Misfit" },
+                { "type": "copy", "src": "patch.html", "dest": "target/windows-only.html", "platforms": ["windows"] }
+            ],
+            "postInstall": [{ "onError": "continue", "type": "runCommand", "command": "false", "args": [] }]
+        }))
+        .unwrap();
+
+        let powershell = export_install_script(&manifest, &payload, None, ScriptFormat::Powershell).expect("export powershell");
+        assert!(powershell.contains("Copy-PayloadFile \"${PayloadDir}/theme/app.css\" \"${TargetBase}/target/theme/app.css\" always"));
+        assert!(powershell.contains("Copy-PayloadFile \"${PayloadDir}/patch.html\" \"${TargetBase}/target/windows-only.html\" always"));
+        assert!(!powershell.contains("app.css.map"));
+        assert!(powershell.contains("Set-PatchBlocks \"${TargetBase}/target/index.html\" $false $false @(,@("));
+
+        let bash = export_install_script(&manifest, &payload, None, ScriptFormat::Bash).expect("export bash");
+        assert!(!bash.contains("windows-only") && !bash.contains("app.css.map"));
+        #[cfg(unix)]
+        if std::process::Command::new("python3").arg("--version").output().is_ok() {
+            std::fs::write(root.join("install.sh"), &bash).unwrap();
+            let output = std::process::Command::new("bash").arg(root.join("install.sh")).output().expect("run script");
+            assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+            assert!(String::from_utf8_lossy(&output.stderr).contains("postInstall hook 1 failed; continuing"));
+            assert_eq!(std::fs::read_to_string(target.join("theme/app.css")).unwrap(), "body { color: red; }");
+            assert!(!target.join("theme/app.css.map").exists() && !target.join("old.txt").exists());
+            assert_eq!(std::fs::read_to_string(&index).unwrap(), "<head><!-- misfit:start --><style>it's new</style><!-- misfit:end --></head>");
+            let settings: serde_json::Value = serde_json::from_str(&std::fs::read_to_string(target.join("settings.json")).unwrap()).unwrap();
+            assert_eq!(settings["workbench.colorTheme"], "Misfit");
+        }
+
+        manifest.install_steps[1].step = serde_json::from_value(serde_json::json!({
+            "type": "patchBlock", "file": "target/index.html", "startMarker": "<head>", "endMarker": "</head>",
+            "contentFile": "patch.html", "markerMode": "anchor"
+        }))
+        .unwrap();
+        let err = MisfitError::from(export_install_script(&manifest, &payload, None, ScriptFormat::Bash).unwrap_err());
+        assert_eq!(err.step_index, Some(1));
+        assert!(err.message.contains("anchor markers"));
+    }
+
+    #[test]
+    fn exported_paths_only_expand_plain_variable_names() {
+        let payload = temp_file("export_env/payload/a.txt", "a").parent().unwrap().to_path_buf();
+        let manifest = test_manifest("ExportEnv", serde_json::json!({
+            "installSteps": [
+                { "type": "copy", "src": "a.txt", "dest": "%ProgramFiles(x86)%/App/a.txt" },
+                { "type": "copy", "src": "a.txt", "dest": "50%\"$(x)%/${a b}/a.txt" }
+            ]
+        }));
+        let powershell = export_install_script(&manifest, &payload, None, ScriptFormat::Powershell).unwrap();
+        assert!(powershell.contains("\"${env:ProgramFiles(x86)}/App/a.txt\""));
+        assert!(powershell.contains("\"${TargetBase}/50%`\"`$(x)%/`${a b}/a.txt\""));
+        let bash = export_install_script(&manifest, &payload, None, ScriptFormat::Bash).unwrap();
+        assert!(bash.contains("\"$(printenv 'ProgramFiles(x86)')/App/a.txt\""));
+        assert!(bash.contains("\"${TARGET_BASE}/50%\\\"\\$(x)%/\\${a b}/a.txt\""));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn exported_bash_scripts_refuse_to_delete_home_or_the_target_base() {
+        let root = temp_file("script_delete/keep.txt", "keep").parent().unwrap().to_path_buf();
+        let home = root.join("home");
+        std::fs::create_dir_all(home.join("stuff")).unwrap();
+        std::fs::create_dir_all(root.join("manifests/old")).unwrap();
+        let payload = root.join("payload");
+        for (path, deleted) in [("{home}", false), ("%HOME%", false), ("old/../..", false), ("old", true), ("{home}/stuff", true)] {
+            let manifest = test_manifest("Delete", serde_json::json!({ "installSteps": [{ "type": "delete", "path": path }] }));
+            let bash = export_install_script(&manifest, &payload, None, ScriptFormat::Bash).expect("export bash");
+            std::fs::write(root.join("install.sh"), &bash).unwrap();
+            let output = std::process::Command::new("bash").arg(root.join("install.sh")).env("HOME", &home).output().expect("run script");
+            assert_eq!(output.status.success(), deleted, "{}: {}", path, String::from_utf8_lossy(&output.stderr));
+        }
+        assert!(home.exists() && !home.join("stuff").exists());
+        assert!(root.join("keep.txt").exists() && !root.join("manifests/old").exists());
+    }
+}
@@ -0,0 +1,334 @@
+//! Dry runs against a sandbox: the files a manifest's steps touch are mirrored into a temp
+//! folder, every step runs against the mirror instead of the real targets, and the result is
+//! diffed against the real files. Commands and extension installs are skipped.
+
+use crate::*;
+use std::collections::BTreeSet;
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum SimulatedStatus {
+    Ran,
+    /// Not simulated (commands, extensions) or not reached after a failure.
+    Skipped,
+    Failed,
+}
+
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct SimulatedStep {
+    /// Position in `installSteps`.
+    pub index: usize,
+    pub kind: String,
+    pub status: SimulatedStatus,
+    pub message: Option<String>,
+}
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum ChangeKind {
+    Added,
+    Modified,
+    Deleted,
+}
+
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct SimulatedChange {
+    /// The real file the install would change.
+    pub path: String,
+    pub kind: ChangeKind,
+    pub bytes_before: u64,
+    pub bytes_after: u64,
+    /// Unified diff for text files.
+    pub diff: Option<String>,
+}
+
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct SimulationReport {
+    pub steps: Vec<SimulatedStep>,
+    pub changes: Vec<SimulatedChange>,
+    /// Every simulated step succeeded.
+    pub ok: bool,
+}
+
+/// The temp mirror: real paths map to the same absolute path below `root`.
+struct Sandbox {
+    root: PathBuf,
+    /// Real files and folders copied (or found missing) so far, so later steps see earlier
+    /// steps' changes instead of a fresh copy.
+    mirrored: Vec<PathBuf>,
+}
+
+impl Sandbox {
+    fn path_for(&self, real: &Path) -> PathBuf {
+        let mut path = self.root.clone();
+        for component in real.components() {
+            match component {
+                Component::Prefix(prefix) => path.push(sanitize_component_name(&prefix.as_os_str().to_string_lossy())),
+                Component::Normal(name) => path.push(name),
+                Component::RootDir | Component::CurDir | Component::ParentDir => {}
+            }
+        }
+        path
+    }
+
+    /// Copies `real` (a file or a whole folder) into the mirror unless it already is.
+    fn mirror(&mut self, real: &Path) -> Result<PathBuf> {
+        let sandboxed = self.path_for(real);
+        if self.mirrored.iter().any(|done| real.starts_with(done)) {
+            return Ok(sandboxed);
+        }
+        if real.is_dir() {
+            for entry in walkdir::WalkDir::new(real).into_iter().flatten().filter(|e| e.file_type().is_file()) {
+                let rel = entry.path().strip_prefix(real).context("Walk escaped the mirrored folder")?;
+                copy_into(entry.path(), &sandboxed.join(rel))?;
+            }
+        } else if real.is_file() {
+            copy_into(real, &sandboxed)?;
+        }
+        self.mirrored.push(real.to_path_buf());
+        Ok(sandboxed)
+    }
+
+    /// Mirrors what a step path points at, keeping the `archive.asar!/inner` form.
+    fn mirror_target(&mut self, real: &Path) -> Result<String> {
+        let Some((archive, inner)) = split_asar_path(real) else {
+            return Ok(self.mirror(real)?.to_string_lossy().to_string());
+        };
+        let mut unpacked = archive.as_os_str().to_owned();
+        unpacked.push(".unpacked");
+        let unpacked = PathBuf::from(unpacked).join(&inner);
+        if unpacked.exists() {
+            self.mirror(&unpacked)?;
+        }
+        Ok(format!("{}!/{}", self.mirror(&archive)?.display(), inner))
+    }
+}
+
+fn copy_into(src: &Path, dest: &Path) -> Result<()> {
+    if let Some(parent) = dest.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::copy(src, dest).with_context(|| format!("Failed to mirror {}", src.display()))?;
+    Ok(())
+}
+
+/// Runs every step of `manifest` against a temp copy of the files it touches and reports the
+/// resulting changes. The real targets are only read; the sandbox is removed afterwards.
+/// `components` (the defaults when `None`) and `prompts`, the values for the manifest's prompted
+/// variables, are resolved like an install's.
+pub fn simulate_install(
+    manifest: &InstallManifest,
+    target_base: &Path,
+    payload_source: &Path,
+    components: Option<&[String]>,
+    prompts: &BTreeMap<String, String>,
+    observer: &dyn InstallObserver,
+) -> Result<SimulationReport> {
+    let manifest = &manifest.for_platform(Platform::current());
+    let components = resolve_components(manifest, components)?;
+    let prompts = PromptValues::resolve(manifest, prompts)?;
+    let stamp = chrono::Local::now().format("%Y%m%d_%H%M%S%3f");
+    let root = std::env::temp_dir().join(format!("misfit-sandbox-{}-{}", std::process::id(), stamp));
+    fs::create_dir_all(&root).map_err(|e| MisfitError::from(e).with_path(&root))?;
+    let mut sandbox = Sandbox { root, mirrored: Vec::new() };
+
+    let result = run_in_sandbox(manifest, target_base, payload_source, &components, prompts, &mut sandbox, observer);
+    let changes = result.as_ref().ok().map(|_| sandbox_changes(&sandbox));
+    let _ = fs::remove_dir_all(&sandbox.root);
+    let steps = result?;
+    Ok(SimulationReport {
+        ok: steps.iter().all(|s| s.status != SimulatedStatus::Failed),
+        steps,
+        changes: changes.transpose()?.unwrap_or_default(),
+    })
+}
+
+fn run_in_sandbox(
+    manifest: &InstallManifest,
+    target_base: &Path,
+    payload_source: &Path,
+    components: &BTreeSet<String>,
+    prompts: PromptValues,
+    sandbox: &mut Sandbox,
+    observer: &dyn InstallObserver,
+) -> Result<Vec<SimulatedStep>> {
+    let ctx = StepContext {
+        target_base: sandbox.path_for(target_base),
+        payload_source: payload_source.to_path_buf(),
+        advanced_mode: manifest.advanced_mode.unwrap_or(false),
+        replace_locked_on_reboot: false,
+        prompts,
+    };
+    let mut receipt = InstallReceipt::default();
+    let mut steps = Vec::new();
+    let mut failed = false;
+    for (index, step) in manifest.ordered_steps(None).into_iter().filter(|(_, s)| s.in_components(components)) {
+        let kind = step_kind(step);
+        let skip = |message: &str| SimulatedStep { index, kind: kind.clone(), status: SimulatedStatus::Skipped, message: Some(message.to_string()) };
+        if failed {
+            steps.push(skip("Not reached"));
+            continue;
+        }
+        if matches!(step.step, InstallStep::RunCommand { .. } | InstallStep::InstallVsix { .. }) {
+            steps.push(skip("Not run in a simulation"));
+            continue;
+        }
+        let outcome = sandbox_step(&step.step, target_base, payload_source, sandbox)
+            .and_then(|sandboxed| execute_step(&sandboxed, &ctx, &mut receipt, observer));
+        let (status, message) = match outcome {
+            Ok(()) => (SimulatedStatus::Ran, None),
+            Err(err) => {
+                failed = true;
+                (SimulatedStatus::Failed, Some(format!("{:#}", err)))
+            }
+        };
+        steps.push(SimulatedStep { index, kind, status, message });
+    }
+    Ok(steps)
+}
+
+/// Mirrors what `step` reads and writes, and returns it pointed at the mirror.
+fn sandbox_step(step: &InstallStep, target_base: &Path, payload_source: &Path, sandbox: &mut Sandbox) -> Result<InstallStep> {
+    let mut sandboxed = step.clone();
+    match &mut sandboxed {
+        // Only the files the copy writes, not the whole destination folder
+        InstallStep::Copy { src, dest, exclude, .. } => {
+            let real = resolve_path(target_base, dest);
+            for job in plan_copy_step(src, &real, exclude.as_deref().unwrap_or_default(), payload_source)?.files {
+                sandbox.mirror(&job.dest)?;
+            }
+            *dest = sandbox.path_for(&real).to_string_lossy().to_string();
+        }
+        InstallStep::FixVsCodeChecksums { app_dir } => {
+            let real = resolve_path(target_base, app_dir);
+            if let Some(product) = vscode_product_json(&real) {
+                let json: serde_json::Value = serde_json::from_slice(&fs::read(&product)?).unwrap_or_default();
+                let out_dir = product.parent().unwrap_or(&real).join("out");
+                for file in json.get("checksums").and_then(|c| c.as_object()).into_iter().flat_map(|c| c.keys()) {
+                    sandbox.mirror(&out_dir.join(file))?;
+                }
+                sandbox.mirror(&product)?;
+            }
+            *app_dir = sandbox.path_for(&real).to_string_lossy().to_string();
+        }
+        other => {
+            for path in other.target_paths_mut() {
+                *path = sandbox.mirror_target(&resolve_path(target_base, path))?;
+            }
+        }
+    }
+    Ok(sandboxed)
+}
+
+/// Compares every mirrored path with its real counterpart.
+fn sandbox_changes(sandbox: &Sandbox) -> Result<Vec<SimulatedChange>> {
+    let mut files = BTreeSet::new();
+    for real in &sandbox.mirrored {
+        let sandboxed = sandbox.path_for(real);
+        for (root, base) in [(real.clone(), real), (sandboxed.clone(), &sandboxed)] {
+            let walk = walkdir::WalkDir::new(&root).into_iter().flatten().filter(|e| e.file_type().is_file());
+            for entry in walk {
+                let rel = entry.path().strip_prefix(base).context("Walk escaped the mirrored folder")?;
+                files.insert(join_rel(real, rel));
+            }
+        }
+    }
+
+    let mut changes = Vec::new();
+    for real in files {
+        let before = fs::read(&real).ok();
+        let after = fs::read(sandbox.path_for(&real)).ok();
+        let kind = match (&before, &after) {
+            (Some(before), Some(after)) if before == after => continue,
+            (Some(_), Some(_)) => ChangeKind::Modified,
+            (None, Some(_)) => ChangeKind::Added,
+            (Some(_), None) => ChangeKind::Deleted,
+            (None, None) => continue,
+        };
+        let text = |bytes: &Option<Vec<u8>>| match bytes {
+            Some(bytes) => std::str::from_utf8(bytes).ok().map(str::to_string),
+            None => Some(String::new()),
+        };
+        let label = real.to_string_lossy().to_string();
+        let diff = match (text(&before), text(&after)) {
+            (Some(old), Some(new)) => Some(unified_diff(&old, &new, &label)),
+            _ => None,
+        };
+        changes.push(SimulatedChange {
+            path: label,
+            kind,
+            bytes_before: before.map_or(0, |b| b.len() as u64),
+            bytes_after: after.map_or(0, |b| b.len() as u64),
+            diff,
+        });
+    }
+    Ok(changes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::{Quiet, temp_file, test_manifest};
+
+    #[test]
+    fn simulate_install_reports_diffs_without_touching_targets() {
+        let settings = temp_file("simulate/target/settings.json", "{\n  \"theme\": \"light\"\n}\n");
+        let root = settings.parent().unwrap().parent().unwrap().to_path_buf();
+        std::fs::write(root.join("target/old.txt"), "old").unwrap();
+        std::fs::create_dir_all(root.join("payload")).unwrap();
+        std::fs::write(root.join("payload/new.txt"), "new").unwrap();
+        let manifest: InstallManifest = serde_json::from_value(serde_json::json!({
+            "appName": "Simulated", "version": "1.0.0", "publisher": "", "description": "",
+            "targets": [], "payloadDir": "payload", "advancedMode": true,
+            "installSteps": [
+                { "type": "setJsonValue", "file": "target/settings.json", "keyPath": "theme", "value": "dark" },
+                { "type": "copy", "src": "new.txt", "dest": "target/new.txt" },
+                { "type": "delete", "path": "target/old.txt" },
+                { "type": "runCommand", "command": "definitely-not-a-real-command", "args": [] },
+                { "type": "verify", "file": "target/settings.json", "contains": "dark" }
+            ]
+        }))
+        .unwrap();
+        let report = simulate_install(&manifest, &root, &root.join("payload"), None, &BTreeMap::new(), &Quiet).expect("simulate");
+        let statuses: Vec<SimulatedStatus> = report.steps.iter().map(|s| s.status).collect();
+        use SimulatedStatus::*;
+        assert_eq!(statuses, [Ran, Ran, Ran, Skipped, Ran]);
+        assert!(report.ok);
+        let kinds: Vec<(String, ChangeKind)> = report
+            .changes
+            .iter()
+            .map(|c| (Path::new(&c.path).file_name().unwrap().to_string_lossy().to_string(), c.kind))
+            .collect();
+        assert_eq!(kinds.len(), 3);
+        assert!(kinds.contains(&("settings.json".to_string(), ChangeKind::Modified)));
+        assert!(kinds.contains(&("new.txt".to_string(), ChangeKind::Added)));
+        assert!(kinds.contains(&("old.txt".to_string(), ChangeKind::Deleted)));
+        let settings_change = report.changes.iter().find(|c| c.path.ends_with("settings.json")).unwrap();
+        assert!(settings_change.diff.as_deref().unwrap().contains("+  \"theme\": \"dark\""));
+
+        assert!(std::fs::read_to_string(&settings).unwrap().contains("light"));
+        assert!(root.join("target/old.txt").exists() && !root.join("target/new.txt").exists());
+    }
+
+    #[test]
+    fn simulations_fill_prompts_and_exports_refuse_them() {
+        let page = temp_file("simulate_prompts/target/index.html", "<head><!-- s -->old<!-- e --></head>");
+        let root = page.parent().unwrap().parent().unwrap().to_path_buf();
+        std::fs::create_dir_all(root.join("payload")).unwrap();
+        let manifest = test_manifest("Prompted", serde_json::json!({
+            "prompts": [{ "name": "key" }],
+            "installSteps": [{ "type": "patchBlock", "file": "target/index.html", "startMarker": "<!-- s -->", "endMarker": "<!-- e -->",
+                "content": "KEY", "replacements": { "KEY": "{{key}}" } }]
+        }));
+        let given = BTreeMap::from([("key".to_string(), "abc-123".to_string())]);
+        let report = simulate_install(&manifest, &root, &root.join("payload"), None, &given, &Quiet).expect("simulate");
+        assert!(report.ok && report.changes[0].diff.as_deref().unwrap().contains("abc-123"), "{:?}", report);
+        assert!(simulate_install(&manifest, &root, &root.join("payload"), None, &BTreeMap::new(), &Quiet).is_err());
+
+        let err = export_install_script(&manifest, &root.join("payload"), None, ScriptFormat::Bash).unwrap_err();
+        assert!(err.to_string().contains("only supported by the installer"));
+    }
+}
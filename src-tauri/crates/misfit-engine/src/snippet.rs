@@ -0,0 +1,107 @@
+//! Step snippets: a named group of manifest steps an author can drop into any project, e.g.
+//! "patch workbench.html". Any string in the steps may contain `{{name}}` placeholders that
+//! are filled in when the snippet is inserted.
+
+use crate::*;
+use std::collections::BTreeSet;
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct Snippet {
+    pub name: String,
+    #[serde(default)]
+    pub description: String,
+    pub steps: Vec<ManifestStep>,
+}
+
+/// Placeholder names used in `steps`, sorted and without duplicates.
+pub fn snippet_variables(steps: &[ManifestStep]) -> Result<Vec<String>> {
+    let mut names = BTreeSet::new();
+    for_each_string(&mut serde_json::to_value(steps)?, &mut |s| {
+        names.extend(placeholders(s).into_iter().map(|(_, name)| name.to_string()));
+    });
+    Ok(names.into_iter().collect())
+}
+
+/// The snippet's steps with every placeholder replaced from `values`. Fails when a
+/// placeholder has no value; values that aren't used are ignored.
+pub fn instantiate_snippet(snippet: &Snippet, values: &HashMap<String, String>) -> Result<Vec<ManifestStep>> {
+    let missing: Vec<String> = snippet_variables(&snippet.steps)?
+        .into_iter()
+        .filter(|name| !values.contains_key(name))
+        .collect();
+    if !missing.is_empty() {
+        return Err(anyhow!("Snippet {} needs a value for {}", snippet.name, missing.join(", ")));
+    }
+
+    let mut steps = serde_json::to_value(&snippet.steps)?;
+    for_each_string(&mut steps, &mut |s| {
+        let mut filled = String::new();
+        let mut rest = 0;
+        for (range, name) in placeholders(s) {
+            filled.push_str(&s[rest..range.start]);
+            filled.push_str(&values[name]);
+            rest = range.end;
+        }
+        filled.push_str(&s[rest..]);
+        *s = filled;
+    });
+    serde_json::from_value(steps).with_context(|| format!("Snippet {} is not valid after filling in its placeholders", snippet.name))
+}
+
+/// `{{name}}` occurrences in `s` with their byte range. Names are letters, digits and `_`;
+/// anything else between braces is left as text.
+pub(crate) fn placeholders(s: &str) -> Vec<(std::ops::Range<usize>, &str)> {
+    let mut found = Vec::new();
+    let mut from = 0;
+    while let Some(start) = s[from..].find("{{").map(|i| from + i) {
+        let Some(end) = s[start + 2..].find("}}").map(|i| start + 2 + i) else { break };
+        let name = s[start + 2..end].trim();
+        if !name.is_empty() && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+            found.push((start..end + 2, name));
+            from = end + 2;
+        } else {
+            from = start + 2;
+        }
+    }
+    found
+}
+
+pub(crate) fn for_each_string(value: &mut serde_json::Value, f: &mut impl FnMut(&mut String)) {
+    match value {
+        serde_json::Value::String(s) => f(s),
+        serde_json::Value::Array(items) => items.iter_mut().for_each(|item| for_each_string(item, f)),
+        serde_json::Value::Object(map) => map.values_mut().for_each(|item| for_each_string(item, f)),
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn snippet_placeholders_are_filled_in_every_string() {
+        let snippet: Snippet = serde_json::from_value(serde_json::json!({
+            "name": "Custom CSS",
+            "steps": [
+                { "type": "copy", "src": "{{theme}}", "dest": "{{ appDir }}/themes/{{theme}}" },
+                { "type": "setJsonValue", "file": "{{appDir}}/settings.json", "keyPath": "theme", "value": { "name": "{{theme}}", "raw": "{{not a var}}" } }
+            ]
+        }))
+        .unwrap();
+        assert_eq!(snippet_variables(&snippet.steps).unwrap(), vec!["appDir", "theme"]);
+
+        let only_theme = HashMap::from([("theme".to_string(), "dark".to_string())]);
+        let err = instantiate_snippet(&snippet, &only_theme).unwrap_err();
+        assert!(err.to_string().contains("appDir"), "{}", err);
+
+        let mut values = only_theme;
+        values.insert("appDir".to_string(), "%APPDATA%/Code".to_string());
+        let steps = serde_json::to_value(instantiate_snippet(&snippet, &values).unwrap()).unwrap();
+        assert_eq!(steps[0]["src"], "dark");
+        assert_eq!(steps[0]["dest"], "%APPDATA%/Code/themes/dark");
+        assert_eq!(steps[1]["file"], "%APPDATA%/Code/settings.json");
+        assert_eq!(steps[1]["value"], serde_json::json!({ "name": "dark", "raw": "{{not a var}}" }));
+    }
+}
@@ -0,0 +1,147 @@
+//! What the installer runs on: OS and version, architecture, whether it is elevated, the user's
+//! locale and the free space on the volumes an install writes to. The installer window tailors
+//! its warnings to it and every install report keeps a copy.
+
+use crate::*;
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct SystemInfo {
+    /// `windows`, `macos`, `linux`, ...
+    pub os: String,
+    /// E.g. `Windows 11 Pro 23H2 (build 22631)`, `14.5`, `Ubuntu 24.04 LTS`; empty when unknown.
+    pub os_version: String,
+    pub arch: String,
+    pub elevated: bool,
+    /// BCP 47 tag such as `de-AT`, when the system has one set.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub locale: Option<String>,
+    pub volumes: Vec<VolumeInfo>,
+}
+
+/// Space on one volume, with the paths that live on it.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct VolumeInfo {
+    pub paths: Vec<String>,
+    pub available_bytes: u64,
+    pub total_bytes: u64,
+}
+
+/// Describes this machine, with free space for each volume holding one of `paths` (the target,
+/// the backups folder, ...). Paths that don't exist yet are measured at their nearest existing
+/// parent; ones with none are left out.
+pub fn system_info(paths: &[&Path]) -> SystemInfo {
+    SystemInfo {
+        os: std::env::consts::OS.to_string(),
+        os_version: os_version().unwrap_or_default(),
+        arch: std::env::consts::ARCH.to_string(),
+        elevated: is_elevated(),
+        locale: system_locale(),
+        volumes: volumes(paths),
+    }
+}
+
+fn volumes(paths: &[&Path]) -> Vec<VolumeInfo> {
+    let mut volumes: Vec<(u64, VolumeInfo)> = Vec::new();
+    for path in paths {
+        let Some(probe) = path.ancestors().find(|p| p.exists()) else { continue };
+        let (Ok(available_bytes), Ok(total_bytes)) = (fs2::available_space(probe), fs2::total_space(probe)) else { continue };
+        let key = volume_key(probe).unwrap_or(total_bytes);
+        let shown = path.to_string_lossy().to_string();
+        match volumes.iter_mut().find(|(k, _)| *k == key) {
+            Some((_, volume)) if !volume.paths.contains(&shown) => volume.paths.push(shown),
+            Some(_) => {}
+            None => volumes.push((key, VolumeInfo { paths: vec![shown], available_bytes, total_bytes })),
+        }
+    }
+    volumes.into_iter().map(|(_, volume)| volume).collect()
+}
+
+/// Something that is the same for every path on a volume: the device on Unix, the drive (or
+/// share) on Windows.
+#[cfg(unix)]
+fn volume_key(path: &Path) -> Option<u64> {
+    use std::os::unix::fs::MetadataExt;
+    fs::metadata(path).ok().map(|m| m.dev())
+}
+
+#[cfg(not(unix))]
+fn volume_key(path: &Path) -> Option<u64> {
+    use std::hash::{Hash, Hasher};
+    let std::path::Component::Prefix(prefix) = path.components().next()? else { return None };
+    let mut hasher = std::collections::hash_map::DefaultHasher::new();
+    prefix.as_os_str().to_string_lossy().to_ascii_lowercase().hash(&mut hasher);
+    Some(hasher.finish())
+}
+
+#[cfg(windows)]
+fn os_version() -> Option<String> {
+    use windows_sys::Win32::System::Registry::HKEY_LOCAL_MACHINE;
+    const CURRENT_VERSION: &str = r"SOFTWARE\Microsoft\Windows NT\CurrentVersion";
+
+    let read = |name| read_registry_string(HKEY_LOCAL_MACHINE, CURRENT_VERSION, name).filter(|v| !v.is_empty());
+    let build = read("CurrentBuild")?;
+    let number: u32 = build.parse().unwrap_or(0);
+    // ProductName still says "Windows 10" on Windows 11
+    let product = read("ProductName").unwrap_or_else(|| "Windows".to_string());
+    let product = if number >= 22000 { product.replace("Windows 10", "Windows 11") } else { product };
+    Some(match read("DisplayVersion") {
+        Some(release) => format!("{} {} (build {})", product, release, build),
+        None => format!("{} (build {})", product, build),
+    })
+}
+
+#[cfg(target_os = "macos")]
+fn os_version() -> Option<String> {
+    let output = Command::new("sw_vers").arg("-productVersion").output().ok()?;
+    Some(String::from_utf8_lossy(&output.stdout).trim().to_string()).filter(|v| !v.is_empty())
+}
+
+#[cfg(not(any(windows, target_os = "macos")))]
+fn os_version() -> Option<String> {
+    let release = fs::read_to_string("/etc/os-release").ok()?;
+    let value = |key: &str| {
+        release
+            .lines()
+            .find_map(|line| line.strip_prefix(key)?.strip_prefix('='))
+            .map(|v| v.trim().trim_matches('"').to_string())
+            .filter(|v| !v.is_empty())
+    };
+    value("PRETTY_NAME").or_else(|| Some(format!("{} {}", value("NAME")?, value("VERSION_ID").unwrap_or_default()).trim().to_string()))
+}
+
+#[cfg(windows)]
+fn system_locale() -> Option<String> {
+    use windows_sys::Win32::System::Registry::HKEY_CURRENT_USER;
+    read_registry_string(HKEY_CURRENT_USER, r"Control Panel\International", "LocaleName").filter(|l| !l.is_empty())
+}
+
+/// From `LC_ALL`, `LC_MESSAGES` or `LANG` (`de_AT.UTF-8` becomes `de-AT`); `C` and `POSIX`
+/// aren't a locale.
+#[cfg(not(windows))]
+fn system_locale() -> Option<String> {
+    let value = ["LC_ALL", "LC_MESSAGES", "LANG"].iter().find_map(|name| std::env::var(name).ok().filter(|v| !v.is_empty()))?;
+    let tag = value.split(['.', '@']).next().unwrap_or_default().replace('_', "-");
+    Some(tag).filter(|t| !t.is_empty() && t != "C" && t != "POSIX")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::temp_file;
+
+    #[test]
+    fn system_info_groups_paths_by_volume() {
+        let file = temp_file("system-info/a/file.txt", "x");
+        let folder = file.parent().unwrap();
+        let missing = folder.join("not-yet/created");
+        let info = system_info(&[folder, &missing, Path::new("")]);
+        assert_eq!(info.os, std::env::consts::OS);
+        assert_eq!(info.elevated, is_elevated());
+        assert_eq!(info.volumes.len(), 1, "{:?}", info.volumes);
+        let volume = &info.volumes[0];
+        assert_eq!(volume.paths, vec![folder.to_string_lossy().to_string(), missing.to_string_lossy().to_string()]);
+        assert!(volume.total_bytes >= volume.available_bytes && volume.total_bytes > 0);
+    }
+}
@@ -0,0 +1,337 @@
+//! VS Code specifics: finding the installs of VS Code and its forks on this machine, and the
+//! checksums in `product.json`. VS Code reports "Your installation appears to be corrupt"
+//! once a patched file no longer matches its checksum; the `fixVsCodeChecksums` step brings
+//! them up to date.
+
+use crate::*;
+use base64::Engine;
+
+/// `product.json` of the VS Code (or fork) install at `app_dir`: the install folder itself,
+/// its `resources/app` folder, or a macOS `.app` bundle.
+pub fn vscode_product_json(app_dir: &Path) -> Option<PathBuf> {
+    ["", "resources/app", "Contents/Resources/app"]
+        .iter()
+        .map(|sub| app_dir.join(sub).join("product.json"))
+        .find(|path| path.is_file())
+}
+
+/// SHA-256 in unpadded base64, the form `product.json` uses.
+pub fn vscode_checksum(bytes: &[u8]) -> String {
+    use sha2::{Digest, Sha256};
+    base64::engine::general_purpose::STANDARD_NO_PAD.encode(Sha256::digest(bytes))
+}
+
+/// A `checksums` entry that no longer matches its file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StaleChecksum {
+    /// Key in `checksums`, relative to the `out` folder next to `product.json`.
+    pub file: String,
+    pub recorded: String,
+    pub actual: String,
+}
+
+/// Entries of `product`'s `checksums` whose file changed. Files that are missing are left
+/// alone, as VS Code skips them too.
+pub fn stale_vscode_checksums(product: &Path) -> Result<Vec<StaleChecksum>> {
+    let json: serde_json::Value = serde_json::from_str(&fs::read_to_string(product)?)
+        .map_err(|e| MisfitError::new(ErrorCode::Failed, format!("Invalid product.json: {}", e)).with_path(product))?;
+    let Some(checksums) = json.get("checksums").and_then(|c| c.as_object()) else {
+        return Ok(Vec::new());
+    };
+    let out_dir = product.parent().unwrap_or(Path::new(".")).join("out");
+    let mut stale = Vec::new();
+    for (file, recorded) in checksums {
+        let (Some(recorded), Ok(bytes)) = (recorded.as_str(), fs::read(out_dir.join(file))) else { continue };
+        let actual = vscode_checksum(&bytes);
+        if actual != recorded {
+            stale.push(StaleChecksum { file: file.clone(), recorded: recorded.to_string(), actual });
+        }
+    }
+    Ok(stale)
+}
+
+/// Rewrites the stale checksums in the `product.json` under `app_dir`, leaving the rest of
+/// the file as it was. Returns the files whose checksum changed.
+pub fn fix_vscode_checksums(app_dir: &Path) -> Result<Vec<String>> {
+    let product = vscode_product_json(app_dir).ok_or_else(|| {
+        MisfitError::new(ErrorCode::NotFound, format!("No VS Code product.json found in {}", app_dir.display())).with_path(app_dir)
+    })?;
+    let stale = stale_vscode_checksums(&product)?;
+    if stale.is_empty() {
+        return Ok(Vec::new());
+    }
+    let mut text = fs::read_to_string(&product)?;
+    for entry in &stale {
+        let pattern = regex::Regex::new(&format!(r#"("{}"\s*:\s*)"{}""#, regex::escape(&entry.file), regex::escape(&entry.recorded)))?;
+        text = pattern.replace(&text, |caps: &regex::Captures| format!("{}\"{}\"", &caps[1], entry.actual)).into_owned();
+    }
+    write_atomic(&product, text.as_bytes()).map_err(|e| MisfitError::from(e).with_path(&product))?;
+    Ok(stale.into_iter().map(|entry| entry.file).collect())
+}
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum VsCodeVariant {
+    Code,
+    Insiders,
+    VsCodium,
+    Cursor,
+}
+
+impl VsCodeVariant {
+    pub const ALL: [VsCodeVariant; 4] = [VsCodeVariant::Code, VsCodeVariant::Insiders, VsCodeVariant::VsCodium, VsCodeVariant::Cursor];
+
+    /// `nameShort` in `product.json`, which names the user data folder.
+    fn name_short(self) -> &'static str {
+        match self {
+            VsCodeVariant::Code => "Code",
+            VsCodeVariant::Insiders => "Code - Insiders",
+            VsCodeVariant::VsCodium => "VSCodium",
+            VsCodeVariant::Cursor => "Cursor",
+        }
+    }
+
+    /// `dataFolderName` in `product.json`: the folder in the home directory holding extensions.
+    fn data_folder_name(self) -> &'static str {
+        match self {
+            VsCodeVariant::Code => ".vscode",
+            VsCodeVariant::Insiders => ".vscode-insiders",
+            VsCodeVariant::VsCodium => ".vscode-oss",
+            VsCodeVariant::Cursor => ".cursor",
+        }
+    }
+
+    /// `applicationName` in `product.json`, the name of the command line tool.
+    fn application_name(self) -> &'static str {
+        match self {
+            VsCodeVariant::Code => "code",
+            VsCodeVariant::Insiders => "code-insiders",
+            VsCodeVariant::VsCodium => "codium",
+            VsCodeVariant::Cursor => "cursor",
+        }
+    }
+
+    /// Install folders this variant uses, per user or for everyone.
+    fn install_roots(self, home: &Path) -> Vec<(InstallScope, PathBuf)> {
+        let mut roots = Vec::new();
+        #[cfg(windows)]
+        {
+            let folder = match self {
+                VsCodeVariant::Code => "Microsoft VS Code",
+                VsCodeVariant::Insiders => "Microsoft VS Code Insiders",
+                VsCodeVariant::VsCodium => "VSCodium",
+                VsCodeVariant::Cursor => "cursor",
+            };
+            let local = std::env::var_os("LOCALAPPDATA").map(PathBuf::from).unwrap_or_else(|| home.join("AppData/Local"));
+            roots.push((InstallScope::User, local.join("Programs").join(folder)));
+            for var in ["ProgramFiles", "ProgramFiles(x86)"] {
+                if let Some(dir) = std::env::var_os(var) {
+                    roots.push((InstallScope::System, PathBuf::from(dir).join(folder)));
+                }
+            }
+        }
+        #[cfg(target_os = "macos")]
+        {
+            let bundle = match self {
+                VsCodeVariant::Code => "Visual Studio Code.app",
+                VsCodeVariant::Insiders => "Visual Studio Code - Insiders.app",
+                VsCodeVariant::VsCodium => "VSCodium.app",
+                VsCodeVariant::Cursor => "Cursor.app",
+            };
+            roots.push((InstallScope::User, home.join("Applications").join(bundle)));
+            roots.push((InstallScope::System, Path::new("/Applications").join(bundle)));
+        }
+        #[cfg(all(unix, not(target_os = "macos")))]
+        {
+            let folders: &[&str] = match self {
+                VsCodeVariant::Code => &["/usr/share/code", "/opt/visual-studio-code", "/snap/code/current/usr/share/code"],
+                VsCodeVariant::Insiders => &["/usr/share/code-insiders", "/opt/visual-studio-code-insiders"],
+                VsCodeVariant::VsCodium => &["/usr/share/codium", "/opt/vscodium-bin", "/snap/codium/current/usr/share/codium"],
+                VsCodeVariant::Cursor => &["/opt/cursor", "/usr/share/cursor"],
+            };
+            let user = match self {
+                VsCodeVariant::Code => "code",
+                VsCodeVariant::Insiders => "code-insiders",
+                VsCodeVariant::VsCodium => "codium",
+                VsCodeVariant::Cursor => "cursor",
+            };
+            roots.push((InstallScope::User, home.join(".local/share").join(user)));
+            roots.extend(folders.iter().map(|dir| (InstallScope::System, PathBuf::from(dir))));
+        }
+        roots
+    }
+}
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum InstallScope {
+    User,
+    System,
+}
+
+/// An install of VS Code or one of its forks, with the paths a manifest usually targets.
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct VsCodeInstall {
+    pub variant: VsCodeVariant,
+    /// `nameLong` from `product.json`, e.g. "Visual Studio Code".
+    pub name: String,
+    pub version: Option<String>,
+    pub scope: InstallScope,
+    pub app_root: PathBuf,
+    /// The folder holding `product.json` and `out/`, where workbench patches go.
+    pub resources_path: PathBuf,
+    pub extensions_dir: PathBuf,
+    pub settings_path: PathBuf,
+    /// The `code` (or `codium`, `cursor`, ...) command line tool, used to install extensions.
+    pub cli_path: Option<PathBuf>,
+    /// Portable installs keep extensions and settings in a `data` folder next to the app.
+    pub portable: bool,
+}
+
+/// Every install of VS Code, Insiders, VSCodium and Cursor found in the usual places.
+pub fn detect_vscode_installs() -> Vec<VsCodeInstall> {
+    let Some(home) = home_dir() else { return Vec::new() };
+    let config_dir = user_config_dir(&home);
+    let mut installs = Vec::new();
+    for variant in VsCodeVariant::ALL {
+        for (scope, root) in variant.install_roots(&home) {
+            if let Some(install) = inspect_vscode_install(variant, scope, &root, &home, &config_dir) {
+                if !installs.iter().any(|i: &VsCodeInstall| i.resources_path == install.resources_path) {
+                    installs.push(install);
+                }
+            }
+        }
+    }
+    installs
+}
+
+/// Where VS Code keeps user data: `%APPDATA%`, `~/Library/Application Support` or `~/.config`.
+fn user_config_dir(home: &Path) -> PathBuf {
+    if cfg!(windows) {
+        std::env::var_os("APPDATA").map(PathBuf::from).unwrap_or_else(|| home.join("AppData/Roaming"))
+    } else if cfg!(target_os = "macos") {
+        home.join("Library/Application Support")
+    } else {
+        std::env::var_os("XDG_CONFIG_HOME").map(PathBuf::from).unwrap_or_else(|| home.join(".config"))
+    }
+}
+
+/// The install at `app_root`, if there is one. Newer Windows builds keep the app in a
+/// per-version subfolder, so one level down is searched too.
+pub(crate) fn inspect_vscode_install(variant: VsCodeVariant, scope: InstallScope, app_root: &Path, home: &Path, config_dir: &Path) -> Option<VsCodeInstall> {
+    let product = vscode_product_json(app_root).or_else(|| {
+        let mut subdirs: Vec<PathBuf> = fs::read_dir(app_root).ok()?.flatten().map(|e| e.path()).filter(|p| p.is_dir()).collect();
+        subdirs.sort();
+        subdirs.iter().rev().find_map(|dir| vscode_product_json(dir))
+    })?;
+    let resources_path = product.parent()?.to_path_buf();
+    let json: serde_json::Value = serde_json::from_str(&fs::read_to_string(&product).ok()?).ok()?;
+    let field = |key: &str| json.get(key).and_then(|v| v.as_str()).map(str::to_string);
+    let version = fs::read_to_string(resources_path.join("package.json"))
+        .ok()
+        .and_then(|text| serde_json::from_str::<serde_json::Value>(&text).ok())
+        .and_then(|package| package.get("version").and_then(|v| v.as_str()).map(str::to_string));
+
+    let portable_data = app_root.join("data");
+    let portable = portable_data.is_dir();
+    let (extensions_dir, settings_path) = if portable {
+        (portable_data.join("extensions"), portable_data.join("user-data/User/settings.json"))
+    } else {
+        let data_folder = field("dataFolderName").unwrap_or_else(|| variant.data_folder_name().to_string());
+        let name_short = field("nameShort").unwrap_or_else(|| variant.name_short().to_string());
+        (home.join(data_folder).join("extensions"), config_dir.join(name_short).join("User/settings.json"))
+    };
+    // `bin` sits next to the app on Windows and Linux, and inside `resources/app` in a macOS bundle
+    let application_name = field("applicationName").unwrap_or_else(|| variant.application_name().to_string());
+    let cli_name = if cfg!(windows) { format!("{}.cmd", application_name) } else { application_name };
+    let cli_path = [app_root.join("bin").join(&cli_name), resources_path.join("bin").join(&cli_name)]
+        .into_iter()
+        .find(|path| path.is_file());
+    Some(VsCodeInstall {
+        variant,
+        name: field("nameLong").unwrap_or_else(|| variant.name_short().to_string()),
+        version,
+        scope,
+        app_root: app_root.to_path_buf(),
+        resources_path,
+        extensions_dir,
+        settings_path,
+        cli_path,
+        portable,
+    })
+}
+
+/// The command line tool of the first VS Code install found, preferring VS Code itself.
+pub fn default_vscode_cli() -> Result<PathBuf> {
+    detect_vscode_installs().into_iter().find_map(|install| install.cli_path).ok_or_else(|| {
+        MisfitError::new(ErrorCode::NotFound, "No VS Code command line tool found; set cliPath on the step").into()
+    })
+}
+
+/// Installs the extension in `vsix` with `cli` (`code --install-extension`), replacing an
+/// installed copy, and passes the tool's output to `on_line`.
+pub fn install_vsix(cli: &Path, vsix: &Path, on_line: &dyn Fn(&str)) -> Result<()> {
+    if !vsix.is_file() {
+        return Err(MisfitError::new(ErrorCode::NotFound, format!("Extension package {} not found", vsix.display())).with_path(vsix).into());
+    }
+    let args = ["--install-extension".to_string(), vsix.to_string_lossy().to_string(), "--force".to_string()];
+    run_command(&cli.to_string_lossy(), &args, &CommandOptions::default(), on_line)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::temp_file;
+
+    #[test]
+    fn fix_vscode_checksums_rewrites_only_stale_entries() {
+        let workbench = temp_file("vscode/resources/app/out/vs/workbench/workbench.js", "patched");
+        let app_dir = workbench.ancestors().nth(6).unwrap().to_path_buf();
+        let main = temp_file("vscode/resources/app/out/main.js", "main");
+        let product = app_dir.join("resources/app/product.json");
+        let good = vscode_checksum(&std::fs::read(&main).unwrap());
+        std::fs::write(
+            &product,
+            format!(
+                "{{\n  \"nameShort\": \"Code\",\n  \"checksums\": {{\n    \"vs/workbench/workbench.js\": \"stale\",\n    \"main.js\": \"{}\",\n    \"gone.js\": \"x\"\n  }}\n}}\n",
+                good
+            ),
+        )
+        .unwrap();
+
+        assert_eq!(fix_vscode_checksums(&app_dir).unwrap(), vec!["vs/workbench/workbench.js".to_string()]);
+        let text = std::fs::read_to_string(&product).unwrap();
+        assert!(text.contains(&format!("\"vs/workbench/workbench.js\": \"{}\"", vscode_checksum(b"patched"))));
+        assert!(text.starts_with("{\n  \"nameShort\": \"Code\",") && text.contains("\"gone.js\": \"x\""));
+        assert!(!vscode_checksum(b"patched").ends_with('='));
+        assert!(fix_vscode_checksums(&app_dir).unwrap().is_empty());
+        assert!(fix_vscode_checksums(&app_dir.join("missing")).is_err());
+    }
+
+    #[test]
+    fn inspect_vscode_install_reads_paths_from_product_json() {
+        let product = temp_file(
+            "vscode_detect/Programs/Code/resources/app/product.json",
+            r#"{ "nameShort": "Code", "nameLong": "Visual Studio Code", "dataFolderName": ".vscode" }"#,
+        );
+        let resources = product.parent().unwrap().to_path_buf();
+        std::fs::write(resources.join("package.json"), r#"{ "version": "1.95.0" }"#).unwrap();
+        let root = resources.parent().unwrap().parent().unwrap().to_path_buf();
+        let home = root.parent().unwrap().parent().unwrap().to_path_buf();
+        let config = home.join("config");
+
+        let install = inspect_vscode_install(VsCodeVariant::Code, InstallScope::User, &root, &home, &config).expect("install");
+        assert_eq!((install.name.as_str(), install.version.as_deref()), ("Visual Studio Code", Some("1.95.0")));
+        assert_eq!(install.resources_path, resources);
+        assert_eq!(install.extensions_dir, home.join(".vscode/extensions"));
+        assert_eq!(install.settings_path, config.join("Code/User/settings.json"));
+        assert_eq!(install.cli_path, None);
+        let cli = root.join("bin").join(if cfg!(windows) { "code.cmd" } else { "code" });
+        std::fs::create_dir_all(cli.parent().unwrap()).unwrap();
+        std::fs::write(&cli, "").unwrap();
+        let install = inspect_vscode_install(VsCodeVariant::Code, InstallScope::User, &root, &home, &config).expect("install");
+        assert_eq!(install.cli_path, Some(cli));
+        assert!(inspect_vscode_install(VsCodeVariant::Cursor, InstallScope::User, &home.join("missing"), &home, &config).is_none());
+    }
+}
@@ -0,0 +1,133 @@
+//! Brands a built installer. The studio copies its own executable, so without this the
+//! installer would show up as Misfit Studio, with the studio's icon, in Explorer.
+
+use crate::engine::{self, MisfitError};
+use std::path::{Path, PathBuf};
+
+#[cfg(windows)]
+const RT_ICON: u16 = 3;
+#[cfg(windows)]
+const RT_GROUP_ICON: u16 = 14;
+#[cfg(windows)]
+const RT_VERSION: u16 = 16;
+/// Id of the studio's icon group (tauri-build embeds the app icon as `IDI_APPLICATION`).
+#[cfg(windows)]
+const APP_ICON_ID: u16 = 32512;
+/// Icon image ids cleared before writing new ones; the studio's icon has far fewer sizes.
+#[cfg(windows)]
+const MAX_ICON_IMAGES: u16 = 32;
+
+/// Deletes the `remove` resources (type, id) in the languages the studio may have been compiled
+/// with, then writes the `add` resources, in one update of `exe`.
+#[cfg(windows)]
+fn replace_resources(exe: &Path, remove: &[(u16, u16)], add: &[(u16, u16, &[u8])]) -> Result<(), MisfitError> {
+    use std::os::windows::ffi::OsStrExt;
+    use windows_sys::Win32::System::LibraryLoader::{BeginUpdateResourceW, EndUpdateResourceW, UpdateResourceW};
+
+    // MAKEINTRESOURCE
+    let int_resource = |id: u16| id as usize as *const u16;
+    let path: Vec<u16> = exe.as_os_str().encode_wide().chain(Some(0)).collect();
+    let failed = |what: &str| {
+        let mut err = MisfitError::from(std::io::Error::last_os_error()).with_path(exe);
+        err.message = format!("{}: {}", what, err.message);
+        err
+    };
+    // SAFETY: `path` is NUL-terminated, every buffer outlives the update handle, and the handle
+    // is closed on every path.
+    unsafe {
+        let update = BeginUpdateResourceW(path.as_ptr(), 0);
+        if update.is_null() {
+            return Err(failed("Failed to open the executable's resources"));
+        }
+        for &(kind, id) in remove {
+            for language in [0, engine::VERSION_INFO_LANGUAGE] {
+                // Fails harmlessly when there is no such resource
+                UpdateResourceW(update, int_resource(kind), int_resource(id), language, std::ptr::null(), 0);
+            }
+        }
+        for &(kind, id, data) in add {
+            let written = UpdateResourceW(
+                update,
+                int_resource(kind),
+                int_resource(id),
+                engine::VERSION_INFO_LANGUAGE,
+                data.as_ptr().cast(),
+                data.len() as u32,
+            );
+            if written == 0 {
+                let err = failed("Failed to write resource");
+                EndUpdateResourceW(update, 1);
+                return Err(err);
+            }
+        }
+        if EndUpdateResourceW(update, 0) == 0 {
+            return Err(failed("Failed to save the executable's resources"));
+        }
+    }
+    Ok(())
+}
+
+/// Replaces the version information of `exe` with `meta`. Only Windows executables carry
+/// it; elsewhere this does nothing.
+#[cfg(windows)]
+pub fn set_version_info(exe: &Path, meta: &engine::ExeMetadata) -> Result<(), MisfitError> {
+    let data = engine::version_info_resource(meta);
+    replace_resources(exe, &[(RT_VERSION, 1)], &[(RT_VERSION, 1, &data)])
+}
+
+#[cfg(not(windows))]
+pub fn set_version_info(_exe: &Path, _meta: &engine::ExeMetadata) -> Result<(), MisfitError> {
+    Ok(())
+}
+
+/// Gives `exe` the icon in `icon` (an `.ico` file) in place of the studio's. Returns the files
+/// written next to `exe`, which are part of the installer rather than its payload.
+#[cfg(windows)]
+pub fn set_icon(exe: &Path, icon: &Path, _meta: &engine::ExeMetadata) -> Result<Vec<PathBuf>, MisfitError> {
+    let ico = std::fs::read(icon).map_err(|e| MisfitError::from(e).with_path(icon))?;
+    let resources = engine::icon_resources(&ico).map_err(|e| MisfitError::from(e).with_path(icon))?;
+
+    let mut remove = vec![(RT_GROUP_ICON, APP_ICON_ID)];
+    remove.extend((1..=MAX_ICON_IMAGES).map(|id| (RT_ICON, id)));
+    let mut add: Vec<(u16, u16, &[u8])> = Vec::new();
+    for (id, image) in (1..).zip(&resources.images) {
+        add.push((RT_ICON, id, image));
+    }
+    add.push((RT_GROUP_ICON, APP_ICON_ID, &resources.group));
+    replace_resources(exe, &remove, &add)?;
+    Ok(Vec::new())
+}
+
+/// Linux executables have no icon of their own; a `.desktop` launcher next to `exe` shows
+/// `icon` (copied beside it) instead. Desktop files need absolute paths, so the launcher
+/// points into the output folder as built.
+#[cfg(target_os = "linux")]
+pub fn set_icon(exe: &Path, icon: &Path, meta: &engine::ExeMetadata) -> Result<Vec<PathBuf>, MisfitError> {
+    let beside = |extension: &str| {
+        let mut path = exe.as_os_str().to_owned();
+        path.push(format!(".{}", extension));
+        PathBuf::from(path)
+    };
+    let extension = icon.extension().map(|e| e.to_string_lossy().to_lowercase()).unwrap_or_else(|| "png".to_string());
+    let icon_copy = beside(&extension);
+    std::fs::copy(icon, &icon_copy).map_err(|e| MisfitError::from(e).with_path(icon))?;
+    let absolute = |path: &Path| std::fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+    let desktop = format!(
+        "[Desktop Entry]\nType=Application\nName={}\nComment={}\nExec=\"{}\"\nIcon={}\nTerminal=false\n",
+        meta.product_name,
+        meta.file_description,
+        absolute(exe).display(),
+        absolute(&icon_copy).display()
+    );
+    let launcher = beside("desktop");
+    std::fs::write(&launcher, desktop)?;
+    Ok(vec![icon_copy, launcher])
+}
+
+/// macOS icons live in an app bundle's `Info.plist`; built installers are a bare executable,
+/// so there is nothing to brand.
+#[cfg(not(any(windows, target_os = "linux")))]
+pub fn set_icon(_exe: &Path, icon: &Path, _meta: &engine::ExeMetadata) -> Result<Vec<PathBuf>, MisfitError> {
+    log::warn!("Installer icons are only applied on Windows and Linux; ignoring {}", icon.display());
+    Ok(Vec::new())
+}
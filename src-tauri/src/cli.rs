@@ -0,0 +1,192 @@
+//! Headless mode: `--install`, `--restore` and `--uninstall --silent` run the bundled (or
+//! `--manifest`) installer without opening a window, print progress to stdout and exit with a
+//! status code. `--uninstall` on its own opens the uninstaller window instead.
+
+use super::engine::{ErrorCode, MisfitError};
+use std::path::PathBuf;
+use tauri::Listener;
+
+pub const EXIT_OK: i32 = 0;
+pub const EXIT_FAILED: i32 = 1;
+pub const EXIT_USAGE: i32 = 2;
+
+pub const USAGE: &str = "Usage: <installer> [--install | --restore | --uninstall] [--silent] [--target <path>] [--manifest <path>]
+                  [--profile <name>] [--accept-license]
+
+  --install          Run all install steps without showing the window
+  --restore          Restore the latest backup for this app
+  --uninstall        Open the uninstaller; with --silent, revert every patch recorded by
+                     previous installs without a window
+  --silent           Only print errors
+  --target <path>    Resolve relative target paths from <path> instead of the manifest folder
+  --manifest <path>  Use this manifest instead of the bundled one
+  --profile <name>   Apply the manifest's profile <name> (e.g. dev, prod)
+  --accept-license   Accept the manifest's license; required to install a licensed manifest
+
+Exit codes: 0 success, 1 failure, 2 invalid arguments";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CliAction {
+    Install,
+    Restore,
+    Uninstall,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct CliArgs {
+    /// The requested action; `is_headless` says whether it runs without a window.
+    pub action: Option<CliAction>,
+    pub silent: bool,
+    pub target: Option<PathBuf>,
+    pub manifest: Option<PathBuf>,
+    pub profile: Option<String>,
+    pub accept_license: bool,
+}
+
+impl CliArgs {
+    /// Whether to run without a window: any action, except `--uninstall` without `--silent`.
+    pub fn is_headless(&self) -> bool {
+        match self.action {
+            Some(CliAction::Uninstall) => self.silent,
+            action => action.is_some(),
+        }
+    }
+}
+
+/// Parses the arguments after the program name. Unknown arguments are left for the GUI
+/// (`--studio`, `--installer`) or the OS to deal with.
+pub fn parse_args<I: IntoIterator<Item = String>>(args: I) -> Result<CliArgs, String> {
+    let mut parsed = CliArgs::default();
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        let action = match arg.to_ascii_lowercase().as_str() {
+            "--install" => Some(CliAction::Install),
+            "--restore" => Some(CliAction::Restore),
+            "--uninstall" => Some(CliAction::Uninstall),
+            "--silent" => {
+                parsed.silent = true;
+                None
+            }
+            "--target" => {
+                parsed.target = Some(args.next().map(PathBuf::from).ok_or("--target needs a path")?);
+                None
+            }
+            "--manifest" => {
+                parsed.manifest = Some(args.next().map(PathBuf::from).ok_or("--manifest needs a path")?);
+                None
+            }
+            "--profile" => {
+                parsed.profile = Some(args.next().ok_or("--profile needs a name")?);
+                None
+            }
+            "--accept-license" => {
+                parsed.accept_license = true;
+                None
+            }
+            _ => None,
+        };
+        if let Some(action) = action {
+            if parsed.action.is_some_and(|a| a != action) {
+                return Err("Only one of --install, --restore and --uninstall can be given".to_string());
+            }
+            parsed.action = Some(action);
+        }
+    }
+    Ok(parsed)
+}
+
+/// Lets a GUI-subsystem executable print to the console it was started from.
+#[cfg(windows)]
+pub fn attach_console() {
+    use windows_sys::Win32::System::Console::{AttachConsole, ATTACH_PARENT_PROCESS};
+    // SAFETY: no preconditions; fails harmlessly when there is no parent console.
+    unsafe {
+        AttachConsole(ATTACH_PARENT_PROCESS);
+    }
+}
+
+#[cfg(not(windows))]
+pub fn attach_console() {}
+
+fn event_text(payload: &str) -> String {
+    serde_json::from_str::<String>(payload).unwrap_or_else(|_| payload.to_string())
+}
+
+/// Runs the requested action to completion and returns the process exit code.
+pub fn run_headless(args: CliArgs, app_handle: &tauri::AppHandle) -> i32 {
+    let Some(action) = args.action else { return EXIT_USAGE };
+
+    for event in ["integrity-warning", "elevation-required"] {
+        app_handle.listen_any(event, |event| eprintln!("{}", event_text(event.payload())));
+    }
+    if !args.silent {
+        app_handle.listen_any("log", |event| println!("{}", event_text(event.payload())));
+        app_handle.listen_any("copy-progress", |event| {
+            if let Ok(progress) = serde_json::from_str::<serde_json::Value>(event.payload()) {
+                println!(
+                    "Copied {}/{} files",
+                    progress["filesCopied"].as_u64().unwrap_or(0),
+                    progress["totalFiles"].as_u64().unwrap_or(0)
+                );
+            }
+        });
+    }
+
+    let result = super::resolve_manifest_path(app_handle)
+        .ok_or_else(|| MisfitError::new(ErrorCode::ManifestNotFound, "Manifest not found"))
+        .and_then(|path| Ok(super::engine::load_manifest(&path)?))
+        .and_then(|manifest| Ok(super::engine::apply_profile(&manifest, args.profile.as_deref())?))
+        .and_then(|manifest| match action {
+            CliAction::Install => {
+                let choices = super::InstallChoices { accepted_license: args.accept_license, ..Default::default() };
+                super::run_install_blocking(manifest, args.target, choices, app_handle.clone()).map(|_| ())
+            }
+            CliAction::Restore => super::restore_backup_blocking(Some(manifest.app_name.to_string()), None, None, app_handle.clone()),
+            CliAction::Uninstall => super::revert_patches_blocking(manifest.app_name.to_string(), app_handle.clone()),
+        });
+
+    match result {
+        Ok(()) => EXIT_OK,
+        Err(err) => {
+            eprintln!("Error: {}", err);
+            EXIT_FAILED
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<CliArgs, String> {
+        parse_args(args.iter().map(|a| a.to_string()))
+    }
+
+    #[test]
+    fn parses_install_flags() {
+        let args = parse(&["--install", "--silent", "--target", "C:/Apps", "--installer"]).expect("parse");
+        assert_eq!(args.action, Some(CliAction::Install));
+        assert!(args.silent);
+        assert_eq!(args.target, Some(PathBuf::from("C:/Apps")));
+        assert!(args.manifest.is_none());
+        assert!(args.profile.is_none());
+        assert!(!args.accept_license);
+        assert!(parse(&["--install", "--accept-license"]).expect("parse").accept_license);
+    }
+
+    #[test]
+    fn rejects_conflicting_actions_and_missing_values() {
+        assert!(parse(&["--install", "--uninstall"]).is_err());
+        assert!(parse(&["--manifest"]).is_err());
+        assert!(parse(&["--profile"]).is_err());
+        assert!(parse(&["--studio"]).expect("parse").action.is_none());
+    }
+
+    #[test]
+    fn uninstall_opens_a_window_unless_silent() {
+        assert!(!parse(&["--uninstall"]).expect("parse").is_headless());
+        assert!(parse(&["--uninstall", "--silent"]).expect("parse").is_headless());
+        assert!(parse(&["--restore"]).expect("parse").is_headless());
+        assert!(!parse(&["--silent"]).expect("parse").is_headless());
+    }
+}
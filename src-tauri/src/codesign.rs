@@ -0,0 +1,201 @@
+//! Authenticode signing of built installers, so end users aren't greeted by SmartScreen. The
+//! build runs either a command line the author supplies or, given a PFX, `signtool` on Windows
+//! and `osslsigncode` elsewhere. Signing runs last, after every other change to the executable.
+//!
+//! The PFX password never appears on a command line, where other processes could read it: the
+//! tools get it from the environment or a private temporary file. A custom command comes from
+//! the project file, which may be someone else's, so it only runs once the user approves it.
+
+use crate::engine::{ErrorCode, MisfitError};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+/// Read when `passwordEnv` is not set.
+pub const DEFAULT_PASSWORD_ENV: &str = "MISFIT_SIGN_PASSWORD";
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct CodeSigning {
+    /// Program and arguments to run instead of the default tool. `{file}`, `{pfx}` and
+    /// `{timestampUrl}` are replaced in every argument; the password stays in `passwordEnv`.
+    pub command: Option<Vec<String>>,
+    pub pfx_path: Option<String>,
+    /// Environment variable holding the PFX password, so it never lands in a project file.
+    pub password_env: Option<String>,
+    pub timestamp_url: Option<String>,
+}
+
+/// A signing run: the command line, the file the password has to be written to first (for
+/// tools that read it from a file), and for tools that write a separate output file, where the
+/// signed copy appears.
+#[derive(Debug, PartialEq)]
+struct SigningCommand {
+    args: Vec<String>,
+    password_file: Option<PathBuf>,
+    signed_copy: Option<PathBuf>,
+}
+
+/// `text` as a PowerShell single-quoted string.
+fn ps_quote(text: &str) -> String {
+    format!("'{}'", text.replace('\'', "''"))
+}
+
+/// The command for `signing`. `password_env` is the variable holding the PFX password when it
+/// is set; it ends up in the tool's environment or a private file, never in `args`.
+fn signing_command(signing: &CodeSigning, exe: &Path, password_env: Option<&str>, windows: bool) -> Result<SigningCommand, MisfitError> {
+    let file = exe.to_string_lossy().to_string();
+    let pfx = signing.pfx_path.clone().unwrap_or_default();
+    let timestamp = signing.timestamp_url.clone().unwrap_or_default();
+
+    if let Some(command) = signing.command.as_ref().filter(|c| !c.is_empty()) {
+        if command.iter().any(|arg| arg.contains("{password}")) {
+            let message = "{password} is no longer filled in; signing commands read the password from the passwordEnv variable";
+            return Err(MisfitError::new(ErrorCode::InvalidManifest, message));
+        }
+        let args = command
+            .iter()
+            .map(|arg| arg.replace("{file}", &file).replace("{pfx}", &pfx).replace("{timestampUrl}", &timestamp))
+            .collect();
+        return Ok(SigningCommand { args, password_file: None, signed_copy: None });
+    }
+    if pfx.is_empty() {
+        return Err("Code signing needs either a command or a PFX file".into());
+    }
+
+    let owned = |items: &[&str]| items.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+    if windows {
+        let Some(password_env) = password_env else {
+            let mut args = owned(&["signtool", "sign", "/fd", "SHA256", "/f", &pfx]);
+            if !timestamp.is_empty() {
+                args.extend(owned(&["/tr", &timestamp, "/td", "SHA256"]));
+            }
+            args.push(file);
+            return Ok(SigningCommand { args, password_file: None, signed_copy: None });
+        };
+        // signtool only takes the password as an argument; PowerShell reads it from the environment
+        if !password_env.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+            return Err(MisfitError::new(ErrorCode::InvalidManifest, format!("passwordEnv {:?} is not a valid variable name", password_env)));
+        }
+        let mut script = format!(
+            "$ErrorActionPreference = 'Stop'; \
+             $cert = New-Object System.Security.Cryptography.X509Certificates.X509Certificate2({}, $env:{}); \
+             $result = Set-AuthenticodeSignature -FilePath {} -Certificate $cert -HashAlgorithm SHA256",
+            ps_quote(&pfx),
+            password_env,
+            ps_quote(&file)
+        );
+        if !timestamp.is_empty() {
+            script.push_str(&format!(" -TimestampServer {}", ps_quote(&timestamp)));
+        }
+        script.push_str("; if (-not $result.SignerCertificate) { Write-Error $result.StatusMessage; exit 1 }");
+        let args = owned(&["powershell", "-NoProfile", "-NonInteractive", "-Command", &script]);
+        Ok(SigningCommand { args, password_file: None, signed_copy: None })
+    } else {
+        // osslsigncode can't sign in place
+        let signed = exe.with_file_name(format!("{}.signed", exe.file_name().unwrap_or_default().to_string_lossy()));
+        let mut args = owned(&["osslsigncode", "sign", "-h", "sha256", "-pkcs12", &pfx]);
+        let password_file = password_env.map(|_| std::env::temp_dir().join(format!("misfit_sign_{}.pass", std::process::id())));
+        if let Some(password_file) = &password_file {
+            args.extend(owned(&["-readpass", &password_file.to_string_lossy()]));
+        }
+        if !timestamp.is_empty() {
+            args.extend(owned(&["-ts", &timestamp]));
+        }
+        args.extend(owned(&["-in", &file, "-out", &signed.to_string_lossy()]));
+        Ok(SigningCommand { args, password_file, signed_copy: Some(signed) })
+    }
+}
+
+/// Deletes the password file when signing ends, however it ends.
+struct PasswordFile(Option<PathBuf>);
+
+impl Drop for PasswordFile {
+    fn drop(&mut self) {
+        if let Some(path) = &self.0 {
+            let _ = std::fs::remove_file(path);
+        }
+    }
+}
+
+/// Signs `exe` in place, passing each line the tool prints to `log` with the password masked.
+/// A custom `command` only runs when `approve` (given the program and the command line) says so.
+pub fn sign_executable(
+    exe: &Path,
+    signing: &CodeSigning,
+    approve: &dyn Fn(&str, &str) -> Result<bool, MisfitError>,
+    log: &dyn Fn(String),
+) -> Result<(), MisfitError> {
+    let password_env = signing.password_env.as_deref().unwrap_or(DEFAULT_PASSWORD_ENV);
+    let password = std::env::var(password_env).ok().filter(|p| !p.is_empty());
+    let command = signing_command(signing, exe, password.as_ref().map(|_| password_env), cfg!(windows))?;
+    let mask = |text: &str| match password.as_deref() {
+        Some(password) => text.replace(password, "***"),
+        None => text.to_string(),
+    };
+
+    let (program, args) = command.args.split_first().ok_or("Code signing command is empty")?;
+    if signing.command.as_ref().is_some_and(|c| !c.is_empty()) && !approve(program, &command.args.join(" "))? {
+        return Err(MisfitError::new(ErrorCode::Cancelled, format!("The code signing command {} was not approved", program)));
+    }
+    let _password_file = PasswordFile(command.password_file.clone());
+    if let (Some(path), Some(password)) = (&command.password_file, &password) {
+        let _ = std::fs::remove_file(path);
+        crate::engine::write_secret_file(path, password.as_bytes())?;
+    }
+    log(mask(&format!("Signing {}: {}", exe.display(), command.args.join(" "))));
+    let output = std::process::Command::new(program).args(args).output().map_err(|e| {
+        let mut err = MisfitError::from(e);
+        err.message = format!("Could not run {}: {}", program, err.message);
+        err
+    })?;
+    let printed = String::from_utf8_lossy(&output.stdout).to_string() + &String::from_utf8_lossy(&output.stderr);
+    for line in printed.lines().filter(|l| !l.trim().is_empty()) {
+        log(mask(line));
+    }
+    if !output.status.success() {
+        let code = output.status.code().map_or_else(|| "a signal".to_string(), |c| format!("exit code {}", c));
+        return Err(MisfitError::new(ErrorCode::Failed, format!("Code signing failed with {}", code)).with_path(exe));
+    }
+
+    if let Some(signed) = command.signed_copy {
+        std::fs::rename(&signed, exe)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn default_and_custom_signing_commands() {
+        let exe = Path::new("/dist/Demo/Demo.exe");
+        let signing = CodeSigning {
+            pfx_path: Some("cert.pfx".into()),
+            timestamp_url: Some("http://ts.example".into()),
+            ..CodeSigning::default()
+        };
+
+        let signtool = signing_command(&signing, exe, None, true).unwrap();
+        assert_eq!(signtool.args.join(" "), "signtool sign /fd SHA256 /f cert.pfx /tr http://ts.example /td SHA256 /dist/Demo/Demo.exe");
+        let osslsigncode = signing_command(&signing, exe, None, false).unwrap();
+        assert_eq!(osslsigncode.signed_copy, Some(PathBuf::from("/dist/Demo/Demo.exe.signed")));
+        assert!(osslsigncode.password_file.is_none());
+
+        // With a password, neither tool gets it on the command line
+        let powershell = signing_command(&signing, exe, Some("SIGN_PW"), true).unwrap();
+        assert_eq!(powershell.args[0], "powershell");
+        assert!(powershell.args[4].contains("$env:SIGN_PW") && powershell.args[4].contains("'/dist/Demo/Demo.exe'"));
+        assert!(signing_command(&signing, exe, Some("PW; calc"), true).is_err());
+        let osslsigncode = signing_command(&signing, exe, Some("SIGN_PW"), false).unwrap();
+        let password_file = osslsigncode.password_file.clone().unwrap().to_string_lossy().to_string();
+        assert!(osslsigncode.args.windows(2).any(|pair| pair == ["-readpass", password_file.as_str()]));
+
+        let custom = CodeSigning { command: Some(vec!["sign.cmd".into(), "{pfx}".into(), "--in={file}".into()]), ..signing };
+        assert_eq!(signing_command(&custom, exe, None, true).unwrap().args, vec!["sign.cmd", "cert.pfx", "--in=/dist/Demo/Demo.exe"]);
+        let with_password = CodeSigning { command: Some(vec!["sign.cmd".into(), "{password}".into()]), ..CodeSigning::default() };
+        assert!(signing_command(&with_password, exe, Some("SIGN_PW"), true).is_err());
+
+        assert!(signing_command(&CodeSigning::default(), exe, None, true).is_err());
+    }
+}
@@ -0,0 +1,123 @@
+//! `export_diagnostics`: one zip with everything needed to look into a failed install — logs,
+//! the active manifest, the install receipt and latest install report, environment info and
+//! the backup listing.
+
+use crate::engine::{self, MisfitError};
+use crate::settings::SettingsState;
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+use tauri::Manager;
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ProbedPath {
+    path: String,
+    exists: bool,
+}
+
+impl ProbedPath {
+    fn new(path: &Path) -> Self {
+        ProbedPath { path: path.to_string_lossy().to_string(), exists: path.exists() }
+    }
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Environment {
+    app_version: String,
+    os: &'static str,
+    arch: &'static str,
+    elevated: bool,
+    created_at: String,
+    executable: Option<String>,
+    working_dir: Option<String>,
+    manifest_path: Option<String>,
+    manifest_candidates: Vec<ProbedPath>,
+    backups_root: Option<String>,
+    log_dir: Option<String>,
+    settings: crate::settings::Settings,
+}
+
+fn json_entry<T: Serialize>(name: &str, value: &T) -> Result<(String, Vec<u8>), MisfitError> {
+    Ok((name.to_string(), serde_json::to_vec_pretty(value)?))
+}
+
+fn display(path: Option<&Path>) -> Option<String> {
+    path.map(|p| p.to_string_lossy().to_string())
+}
+
+/// Writes the bundle to `path` (default: `Documents/misfit-diagnostics-<timestamp>.zip`) and
+/// returns where it went. Missing pieces are skipped rather than failing the export.
+#[tauri::command]
+pub fn export_diagnostics(path: Option<String>, app_handle: tauri::AppHandle) -> Result<String, MisfitError> {
+    let dest = match path {
+        Some(path) => PathBuf::from(path),
+        None => {
+            let docs = app_handle.path().document_dir().map_err(|e| e.to_string())?;
+            docs.join(format!("misfit-diagnostics-{}.zip", chrono::Local::now().format("%Y%m%d_%H%M%S")))
+        }
+    };
+
+    let mut entries = Vec::new();
+
+    let log_dir = crate::logging::log_dir(&app_handle).ok();
+    if let Some(dir) = &log_dir {
+        for item in std::fs::read_dir(dir).into_iter().flatten().flatten() {
+            let name = item.file_name().to_string_lossy().to_string();
+            if name.starts_with("misfit") && name.ends_with(".log") {
+                if let Ok(bytes) = std::fs::read(item.path()) {
+                    entries.push((format!("logs/{}", name), bytes));
+                }
+            }
+        }
+    }
+
+    let manifest_path = crate::resolve_manifest_path(&app_handle);
+    let manifest = manifest_path.as_deref().and_then(|p| engine::load_manifest(p).ok());
+    if let Some(path) = &manifest_path {
+        if let Ok(bytes) = std::fs::read(path) {
+            let name = path.file_name().map_or("install.manifest.json".into(), |n| n.to_string_lossy());
+            entries.push((name.to_string(), bytes));
+        }
+    }
+
+    let backups_root = crate::backups_root(&app_handle, manifest.as_ref()).ok();
+    if let Some(root) = &backups_root {
+        if let Some(manifest) = &manifest {
+            let receipt = root.join(engine::backup_namespace(&manifest.app_name)).join(engine::RECEIPT_FILE);
+            if let Ok(bytes) = std::fs::read(receipt) {
+                entries.push((engine::RECEIPT_FILE.to_string(), bytes));
+            }
+            let latest_report = engine::install_report_dirs(&root.join(engine::backup_namespace(&manifest.app_name)))
+                .iter()
+                .find_map(|dir| std::fs::read(dir.join(engine::INSTALL_REPORT_FILE)).ok());
+            if let Some(bytes) = latest_report {
+                entries.push((engine::INSTALL_REPORT_FILE.to_string(), bytes));
+            }
+        }
+        match engine::list_backups(root, None) {
+            Ok(backups) => entries.push(json_entry("backups.json", &backups)?),
+            Err(err) => entries.push(json_entry("backups.json", &err.to_string())?),
+        }
+    }
+
+    let environment = Environment {
+        app_version: app_handle.package_info().version.to_string(),
+        os: std::env::consts::OS,
+        arch: std::env::consts::ARCH,
+        elevated: engine::is_elevated(),
+        created_at: chrono::Local::now().to_rfc3339(),
+        executable: display(std::env::current_exe().ok().as_deref()),
+        working_dir: display(std::env::current_dir().ok().as_deref()),
+        manifest_path: display(manifest_path.as_deref()),
+        manifest_candidates: crate::manifest_candidates(&app_handle).iter().map(|(path, _)| ProbedPath::new(path)).collect(),
+        backups_root: display(backups_root.as_deref()),
+        log_dir: display(log_dir.as_deref()),
+        settings: app_handle.state::<SettingsState>().get(),
+    };
+    entries.push(json_entry("environment.json", &environment)?);
+
+    engine::write_zip(&dest, &entries)?;
+    log::info!("Diagnostics exported to {}", dest.display());
+    Ok(dest.to_string_lossy().to_string())
+}
@@ -0,0 +1,213 @@
+//! File commands for the Studio's editors. Reads stop at the `maxTextFileBytes` setting so a
+//! stray multi-gigabyte file doesn't end up in memory, and writes stay inside the folders the
+//! Studio works in unless the open project is in advanced mode and the user confirms writing
+//! elsewhere.
+
+use crate::engine::{self, ErrorCode, MisfitError};
+use crate::project::OpenProjectState;
+use crate::settings::SettingsState;
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+use tauri::Manager;
+use tauri_plugin_dialog::{DialogExt, MessageDialogButtons, MessageDialogKind};
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TextPreview {
+    /// The start of the file, decoded; invalid UTF-8 is replaced.
+    text: String,
+    /// Size of the whole file.
+    total_bytes: u64,
+    truncated: bool,
+}
+
+fn max_bytes(app_handle: &tauri::AppHandle) -> u64 {
+    app_handle.state::<SettingsState>().get().max_text_file_bytes
+}
+
+fn too_large(path: &Path, size: u64, max: u64) -> MisfitError {
+    let message = format!("{} is {} bytes, more than the {} byte limit for files the Studio reads or writes", path.display(), size, max);
+    MisfitError::new(ErrorCode::FileTooLarge, message).with_path(path)
+}
+
+/// Folders the Studio writes to without asking: the open project's folder (payloads and
+/// manifests live below it), the dist folder and the Studio's documents folder. The app's data
+/// and config folders are not among them: the recent projects and settings kept there decide
+/// what else may be written.
+fn write_roots(app_handle: &tauri::AppHandle) -> Vec<PathBuf> {
+    let open = app_handle.state::<OpenProjectState>().get().map(|open| open.dir);
+    let dist = app_handle.state::<SettingsState>().get().dist_dir;
+    roots_for(open, dist.as_deref(), app_handle.path().document_dir().ok())
+}
+
+fn roots_for(project_dir: Option<PathBuf>, dist_dir: Option<&str>, documents: Option<PathBuf>) -> Vec<PathBuf> {
+    let mut roots: Vec<PathBuf> = project_dir.into_iter().collect();
+    if let Some(dist) = dist_dir.map(str::trim).filter(|d| !d.is_empty()) {
+        roots.push(PathBuf::from(engine::expand_env_vars(dist)));
+    }
+    roots.extend(documents.map(|docs| docs.join("MisfitStudio")));
+    roots
+}
+
+/// `path` with `..` and symlinks resolved as far as it exists, so `root/../elsewhere` doesn't
+/// pass for a path inside `root`.
+fn resolved(path: &Path) -> PathBuf {
+    let mut missing = Vec::new();
+    let mut existing = path;
+    while let Err(_) | Ok(false) = existing.try_exists() {
+        let (Some(parent), Some(name)) = (existing.parent(), existing.file_name()) else { break };
+        missing.push(name.to_os_string());
+        existing = parent;
+    }
+    let base = std::fs::canonicalize(existing).unwrap_or_else(|_| existing.to_path_buf());
+    missing.iter().rev().fold(base, |path, name| path.join(name))
+}
+
+/// Whether `path` lies inside one of `roots`. Relative paths and `..` after the existing part of
+/// the path never do.
+pub(crate) fn is_within_roots(path: &Path, roots: &[PathBuf]) -> bool {
+    if !path.is_absolute() {
+        return false;
+    }
+    let path = resolved(path);
+    if path.components().any(|c| c == std::path::Component::ParentDir) {
+        return false;
+    }
+    roots.iter().any(|root| path.starts_with(resolved(root)))
+}
+
+/// Refuses a write outside [`write_roots`] unless the open project is in advanced mode and the
+/// user confirms it in a dialog. Blocks until the dialog is answered, so it must not run on the
+/// main thread.
+pub(crate) fn check_write(path: &Path, app_handle: &tauri::AppHandle) -> Result<(), MisfitError> {
+    if is_within_roots(path, &write_roots(app_handle)) {
+        return Ok(());
+    }
+    let outside = format!("{} is outside the project, dist and payload folders", path.display());
+    if !app_handle.state::<OpenProjectState>().get().is_some_and(|open| open.advanced_mode) {
+        let message = format!("{}; turn on advanced mode in the project to write there", outside);
+        return Err(MisfitError::new(ErrorCode::PathNotAllowed, message).with_path(path));
+    }
+    let confirmed = app_handle
+        .dialog()
+        .message(format!("{}.\n\nWrite it anyway?", outside))
+        .title("Write outside the project?")
+        .kind(MessageDialogKind::Warning)
+        .buttons(MessageDialogButtons::OkCancel)
+        .blocking_show();
+    if !confirmed {
+        return Err(MisfitError::new(ErrorCode::Cancelled, format!("Writing {} was declined", path.display())).with_path(path));
+    }
+    log::info!("Writing outside the project folders: {}", path.display());
+    Ok(())
+}
+
+/// The whole file as text; fails with `file-too-large` above the size limit, where
+/// `preview_text_file` still shows its start.
+#[tauri::command]
+pub fn read_text_file(path: String, app_handle: tauri::AppHandle) -> Result<String, MisfitError> {
+    let path = PathBuf::from(path);
+    let size = std::fs::metadata(&path).map_err(|e| MisfitError::from(e).with_path(&path))?.len();
+    let max = max_bytes(&app_handle);
+    if size > max {
+        return Err(too_large(&path, size, max));
+    }
+    std::fs::read_to_string(&path).map_err(|e| MisfitError::from(e).with_path(&path))
+}
+
+/// Up to the size limit (or `max_bytes`, if lower) of the file as text, with its total size.
+#[tauri::command]
+pub fn preview_text_file(path: String, max_bytes: Option<u64>, app_handle: tauri::AppHandle) -> Result<TextPreview, MisfitError> {
+    let limit = self::max_bytes(&app_handle).min(max_bytes.unwrap_or(u64::MAX));
+    let data = engine::read_file_data(Path::new(&path), Some(limit))?;
+    let text = match data.text {
+        Some(text) => text,
+        None => {
+            use base64::Engine as _;
+            let bytes = base64::engine::general_purpose::STANDARD.decode(&data.base64).map_err(|e| e.to_string())?;
+            String::from_utf8_lossy(&bytes).into_owned()
+        }
+    };
+    Ok(TextPreview { text, total_bytes: data.size, truncated: data.truncated })
+}
+
+/// Writes `contents` to `path`. Outside the Studio's folders it fails with `path-not-allowed`,
+/// or in advanced mode asks first and fails with `cancelled` when declined.
+#[tauri::command]
+pub async fn write_text_file(path: String, contents: String, app_handle: tauri::AppHandle) -> Result<(), MisfitError> {
+    crate::run_blocking(move || {
+        let file_path = PathBuf::from(&path);
+        let max = max_bytes(&app_handle);
+        if contents.len() as u64 > max {
+            return Err(too_large(&file_path, contents.len() as u64, max));
+        }
+        check_write(&file_path, &app_handle)?;
+        if let Some(parent) = file_path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::write(&file_path, contents).map_err(|e| MisfitError::from(e).with_path(&file_path))
+    })
+    .await
+}
+
+/// Any file as base64, for previewing images and icons; `text` and `encoding` are set when it
+/// is text. Only up to the size limit (or `max_bytes`, if lower) is read, and `truncated` says
+/// when the file is longer.
+#[tauri::command]
+pub fn read_file_base64(path: String, max_bytes: Option<u64>, app_handle: tauri::AppHandle) -> Result<engine::FileData, MisfitError> {
+    let limit = self::max_bytes(&app_handle).min(max_bytes.unwrap_or(u64::MAX));
+    Ok(engine::read_file_data(Path::new(&path), Some(limit))?)
+}
+
+/// Writes base64 `data` to `path`, replacing it whole; returns the bytes written. Fails with
+/// `file-too-large` above the size limit, and outside the Studio's folders like `write_text_file`.
+#[tauri::command]
+pub async fn write_file_base64(path: String, data: String, app_handle: tauri::AppHandle) -> Result<usize, MisfitError> {
+    crate::run_blocking(move || {
+        // Every 4 base64 characters decode to at most 3 bytes
+        let size = data.trim().len() as u64 / 4 * 3;
+        let max = max_bytes(&app_handle);
+        if size > max {
+            return Err(too_large(Path::new(&path), size, max));
+        }
+        check_write(Path::new(&path), &app_handle)?;
+        Ok(engine::write_file_data(Path::new(&path), &data)?)
+    })
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn writes_are_confined_to_the_roots() {
+        let root = std::env::temp_dir().join(format!("misfit_files_test_{}", std::process::id()));
+        std::fs::create_dir_all(root.join("project")).unwrap();
+        let roots = vec![root.join("project")];
+        assert!(is_within_roots(&root.join("project/manifests/new.json"), &roots));
+        assert!(is_within_roots(&root.join("project"), &roots));
+        assert!(!is_within_roots(&root.join("elsewhere.json"), &roots));
+        assert!(!is_within_roots(&root.join("project/../elsewhere.json"), &roots));
+        assert!(!is_within_roots(&root.join("project/missing/../../elsewhere.json"), &roots));
+        assert!(!is_within_roots(Path::new("relative.json"), &roots));
+        let _ = std::fs::remove_dir_all(&root);
+    }
+
+    #[test]
+    fn rewriting_the_recent_list_opens_nothing_up() {
+        let root = std::env::temp_dir().join(format!("misfit_files_recent_test_{}", std::process::id()));
+        let data = root.join("app-data");
+        let recent = data.join(crate::project::RECENT_PROJECTS_FILE);
+        std::fs::create_dir_all(&data).unwrap();
+        std::fs::write(&recent, r#"[{"path": "/x"}]"#).unwrap();
+        crate::project::remember_project(&recent, &root.join("elsewhere/app.misfit")).unwrap();
+
+        let roots = roots_for(Some(root.join("project")), None, Some(root.join("docs")));
+        assert!(!is_within_roots(&recent, &roots));
+        assert!(!is_within_roots(Path::new("/etc/passwd"), &roots));
+        assert!(!is_within_roots(&root.join("elsewhere/app.misfit"), &roots));
+        assert!(is_within_roots(&root.join("docs/MisfitStudio/app.misfit"), &roots));
+        let _ = std::fs::remove_dir_all(&root);
+    }
+}
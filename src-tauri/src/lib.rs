@@ -1,25 +1,282 @@
-pub mod engine;
+pub use misfit_engine as engine;
+mod branding;
+mod cli;
+mod codesign;
+mod diagnostics;
+mod files;
+mod logging;
+mod project;
+mod record;
+mod settings;
+mod snippets;
+mod uninstall;
+mod watch;
+use engine::{ErrorCode, MisfitError};
+use settings::SettingsState;
+use anyhow::Context;
 use serde::{Deserialize, Serialize};
 use tauri::{Emitter, Manager};
 use std::env;
 use std::path::{Component, Path, PathBuf};
-use std::collections::HashSet;
+use std::collections::{BTreeMap, HashMap, HashSet};
 
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 #[serde(rename_all = "camelCase")]
 enum AppMode {
     Installer,
     Studio,
+    /// Opened with `--uninstall`; reverts what the bundled manifest's installs recorded.
+    Uninstaller,
 }
 
-#[derive(Deserialize)]
+#[derive(Serialize, Deserialize, Clone)]
 #[serde(rename_all = "camelCase")]
 struct BuildRequest {
     project_name: String,
     manifest: engine::InstallManifest,
+    // Optional components shipped in the same installer as (id, manifest), written to
+    // "manifests/{id}.manifest.json" next to the main one and sharing its payloads
+    extra_manifests: Option<Vec<(String, engine::InstallManifest)>>,
     // List of (source_path, relative_dest_path) for payloads
     payload_files: Vec<(String, String)>,
     force_overwrite: Option<bool>,
+    // Path to a base64 ed25519 secret key; when set the output is signed
+    signing_key_path: Option<String>,
+    compress_payloads: Option<engine::PayloadCompression>,
+    // Append manifest + payloads to the executable for single-file distribution
+    embed_payloads: Option<bool>,
+    // Glob patterns applied to every payload source (e.g. "**/node_modules/**")
+    payload_exclude: Option<Vec<String>>,
+    // File name of the installer executable without `.exe`; defaults to the project name
+    exe_name: Option<String>,
+    // Version info shown in the executable's properties; default to the manifest's
+    // version, publisher and "{appName} installer"
+    product_version: Option<String>,
+    company: Option<String>,
+    file_description: Option<String>,
+    // .ico embedded in the executable on Windows; shown by a .desktop launcher on Linux
+    icon_path: Option<String>,
+    output_format: Option<OutputFormat>,
+    output_layout: Option<OutputLayout>,
+    // With a versioned or timestamped layout, how many builds to keep (the new one included)
+    keep_last: Option<usize>,
+    // Authenticode signing of the finished executable
+    code_signing: Option<codesign::CodeSigning>,
+    // Put the zip's contents in a "{project}/" folder instead of at its root
+    zip_root_folder: Option<bool>,
+    // Hash the payload sources before copying and fail if any changed by the end of the build
+    verify_sources: Option<bool>,
+    // Store files with identical contents once in the payload archive or single-file
+    // executable; the installer writes the copies back when it unpacks them
+    dedupe_payloads: Option<bool>,
+    // Saved project being built, so its last build time shows in the recent list
+    #[serde(default, skip_serializing)]
+    project_path: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "camelCase")]
+enum OutputFormat {
+    /// Only the `dist/{project}` folder.
+    #[default]
+    Folder,
+    /// The folder plus `{project}-{version}.zip` next to it, ready for a release page.
+    Zip,
+    /// One executable carrying the manifest and payloads (as with `embed_payloads`), which
+    /// unpacks them to a temp folder and starts in installer mode.
+    SingleFile,
+}
+
+/// Where in `dist/{project}` a build goes.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "camelCase")]
+enum OutputLayout {
+    /// Straight into `dist/{project}`, replacing the previous build.
+    #[default]
+    Replace,
+    /// Into `dist/{project}/{version}`; only a rebuild of the same version is replaced.
+    Versioned,
+    /// Into `dist/{project}/{timestamp}`, never replacing anything.
+    Timestamped,
+}
+
+/// Marks folders the studio built, so it only ever deletes its own output.
+const OUTPUT_MARKER: &str = ".misfit-studio";
+
+struct OutputDirs {
+    /// `dist/{project}`, or the absolute output path in advanced mode.
+    project_root: PathBuf,
+    /// Where this build is written: `project_root` itself or a version folder inside it.
+    dist_root: PathBuf,
+    project_name: String,
+    is_absolute: bool,
+}
+
+fn output_dirs(request: &BuildRequest, app_handle: &tauri::AppHandle) -> Result<OutputDirs, MisfitError> {
+    let advanced_mode = request.manifest.advanced_mode.unwrap_or(false);
+    let is_absolute = advanced_mode && Path::new(&request.project_name).is_absolute();
+    let (project_root, project_name) = if is_absolute {
+        let project_root = PathBuf::from(&request.project_name);
+        let project_name = project_root
+            .file_name()
+            .ok_or("Absolute output path must include a folder name")?
+            .to_string_lossy()
+            .to_string();
+        (project_root, project_name)
+    } else {
+        let dist_base = resolve_dist_base(app_handle)?;
+        let project_name = validate_project_name(&request.project_name)?;
+        let project_root = dist_base.join(&project_name);
+        if !project_root.starts_with(&dist_base) {
+            return Err("Resolved output path escaped dist/".into());
+        }
+        (project_root, project_name)
+    };
+    let dist_root = match request.output_layout.unwrap_or_default() {
+        OutputLayout::Replace => project_root.clone(),
+        OutputLayout::Versioned => {
+            let version = file_name_safe(&request.manifest.version);
+            if version.is_empty() {
+                return Err("A versioned output needs a manifest version".into());
+            }
+            project_root.join(version)
+        }
+        OutputLayout::Timestamped => project_root.join(chrono::Local::now().format("%Y%m%d_%H%M%S").to_string()),
+    };
+    Ok(OutputDirs { project_root, dist_root, project_name, is_absolute })
+}
+
+/// `text` with everything but letters, digits and `._-+` replaced by `_`.
+fn file_name_safe(text: &str) -> String {
+    text.trim().chars().map(|c| if c.is_ascii_alphanumeric() || "._-+".contains(c) { c } else { '_' }).collect()
+}
+
+/// Deletes the oldest marked build folders in `project_root` beyond the newest `keep`.
+fn prune_builds(project_root: &Path, keep: usize) -> Result<Vec<PathBuf>, MisfitError> {
+    let mut builds: Vec<(std::time::SystemTime, PathBuf)> = std::fs::read_dir(project_root)?
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(|path| path.join(OUTPUT_MARKER).is_file())
+        .map(|path| (std::fs::metadata(path.join(OUTPUT_MARKER)).and_then(|m| m.modified()).unwrap_or(std::time::UNIX_EPOCH), path))
+        .collect();
+    builds.sort_by(|a, b| b.cmp(a));
+    let mut removed = Vec::new();
+    for (_, path) in builds.into_iter().skip(keep.max(1)) {
+        std::fs::remove_dir_all(&path)?;
+        removed.push(path);
+    }
+    Ok(removed)
+}
+
+#[derive(Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+struct BuildOutput {
+    output_dir: String,
+    exe_path: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    zip_path: Option<String>,
+}
+
+/// Stage of a running build, in the order they run.
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+enum BuildStep {
+    Exe,
+    Manifest,
+    Payloads,
+    Lockfile,
+    Compress,
+    Sign,
+    Embed,
+    Codesign,
+    Zip,
+}
+
+/// Sent as `build-progress` when a step starts and while payloads are copied. The byte
+/// counts belong to the current step and stay 0 for steps that don't track them.
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+struct BuildProgress {
+    step: BuildStep,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    current_file: Option<String>,
+    bytes_done: u64,
+    bytes_total: u64,
+}
+
+/// Cancellation of the running build, set through `cancel_build`.
+#[derive(Default)]
+struct BuildState {
+    cancel: engine::CancelToken,
+}
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+enum OperationKind {
+    Build,
+    Install,
+    Repair,
+    Restore,
+    Uninstall,
+}
+
+impl OperationKind {
+    fn label(self) -> &'static str {
+        match self {
+            OperationKind::Build => "build",
+            OperationKind::Install => "install",
+            OperationKind::Repair => "repair",
+            OperationKind::Restore => "restore",
+            OperationKind::Uninstall => "uninstall",
+        }
+    }
+}
+
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+struct CurrentOperation {
+    kind: OperationKind,
+    started_at: String,
+}
+
+/// The build, install, repair, restore or uninstall the app is running; they all touch the
+/// output or target files, so only one runs at a time.
+#[derive(Default)]
+struct OperationState {
+    current: std::sync::Mutex<Option<CurrentOperation>>,
+}
+
+/// Clears the running operation when dropped.
+struct OperationGuard(tauri::AppHandle);
+
+impl Drop for OperationGuard {
+    fn drop(&mut self) {
+        if let Ok(mut current) = self.0.state::<OperationState>().current.lock() {
+            *current = None;
+        }
+        let _ = self.0.emit("operation-changed", None::<CurrentOperation>);
+    }
+}
+
+/// Marks `kind` as running, or fails with `operation-in-progress` while another operation is.
+fn begin_operation(app_handle: &tauri::AppHandle, kind: OperationKind) -> Result<OperationGuard, MisfitError> {
+    let state = app_handle.state::<OperationState>();
+    let mut current = state.current.lock().map_err(|e| e.to_string())?;
+    if let Some(running) = current.as_ref() {
+        let message = format!("Can't start a {} while a {} is running", kind.label(), running.kind.label());
+        return Err(MisfitError::new(ErrorCode::OperationInProgress, message));
+    }
+    let operation = CurrentOperation { kind, started_at: chrono::Local::now().to_rfc3339() };
+    *current = Some(operation.clone());
+    let _ = app_handle.emit("operation-changed", Some(operation));
+    Ok(OperationGuard(app_handle.clone()))
+}
+
+/// The operation running right now, if any, so the UI can disable what would conflict with
+/// it; `operation-changed` events report each change.
+#[tauri::command]
+fn get_current_operation(state: tauri::State<'_, OperationState>) -> Result<Option<CurrentOperation>, MisfitError> {
+    Ok(state.current.lock().map_err(|e| e.to_string())?.clone())
 }
 
 #[derive(Serialize)]
@@ -31,133 +288,138 @@ struct BuildTargetInfo {
     is_absolute: bool,
 }
 
-#[derive(Serialize)]
-#[serde(rename_all = "camelCase")]
-struct ScanEntry {
-    name: String,
-    path: String,
-}
+/// Manifest given with `--manifest`; takes precedence over bundled ones.
+static MANIFEST_OVERRIDE: std::sync::OnceLock<PathBuf> = std::sync::OnceLock::new();
 
-fn resolve_manifest_info(app_handle: &tauri::AppHandle) -> Option<(PathBuf, PathBuf)> {
-    // 1. Try resource path (bundled)
+/// Profile given with `--profile`; applied whenever the manifest is loaded.
+static PROFILE: std::sync::OnceLock<String> = std::sync::OnceLock::new();
+
+/// Bundled manifest locations in the order they are checked, each with its project root. Each
+/// location is tried as `.json`, then `.yaml`, `.yml` and `.toml`.
+fn manifest_candidates(app_handle: &tauri::AppHandle) -> Vec<(PathBuf, PathBuf)> {
+    let mut dirs = Vec::new();
     if let Ok(resource_dir) = app_handle.path().resource_dir() {
-        let resource_manifest = resource_dir.join("manifests/install.manifest.json");
-        if resource_manifest.exists() {
-            return Some((resource_manifest, resource_dir));
-        }
-        let root_manifest = resource_dir.join("install.manifest.json");
-        if root_manifest.exists() {
-            return Some((root_manifest, resource_dir));
-        }
+        dirs.push(resource_dir);
     }
-
-    // 2. Try relative to executable (portable mode)
     if let Ok(exe_path) = std::env::current_exe() {
         if let Some(exe_dir) = exe_path.parent() {
-            let relative_manifest = exe_dir.join("manifests/install.manifest.json");
-            if relative_manifest.exists() {
-                return Some((relative_manifest, exe_dir.to_path_buf()));
-            }
-            let root_manifest = exe_dir.join("install.manifest.json");
-            if root_manifest.exists() {
-                return Some((root_manifest, exe_dir.to_path_buf()));
-            }
+            dirs.push(exe_dir.to_path_buf());
         }
     }
-
-    None
+    dirs.into_iter()
+        .flat_map(|dir| [(dir.join("manifests"), dir.clone()), (dir.clone(), dir)])
+        .flat_map(|(folder, root)| {
+            engine::MANIFEST_EXTENSIONS.map(|ext| (folder.join(format!("install.manifest.{}", ext)), root.clone()))
+        })
+        .collect()
 }
 
-fn resolve_manifest_path(app_handle: &tauri::AppHandle) -> Option<PathBuf> {
-    resolve_manifest_info(app_handle).map(|(path, _)| path)
+/// Where a single-file installer unpacks its embedded bundle for this run.
+fn embedded_bundle_dir() -> PathBuf {
+    std::env::temp_dir().join(format!("misfit_embedded_{}", std::process::id()))
 }
 
-fn normalize_rel_path(path_str: &str, allow_current: bool) -> Result<PathBuf, String> {
-    // FIX: Normalize backslashes to forward slashes for cross-platform compatibility
-    let path_str = path_str.replace('\\', "/");
-    let trimmed = path_str.trim();
-    if trimmed.is_empty() {
-        return if allow_current {
-            Ok(PathBuf::from("."))
-        } else {
-            Err("Path cannot be empty".to_string())
-        };
+fn resolve_manifest_info(app_handle: &tauri::AppHandle) -> Option<(PathBuf, PathBuf)> {
+    // 0. Explicit manifest; its project root is the folder holding `manifests/`
+    if let Some(manifest) = MANIFEST_OVERRIDE.get() {
+        return Some((manifest.clone(), engine::project_root_for_manifest(manifest)));
     }
 
-    let path = Path::new(trimmed);
-    let mut normalized = PathBuf::new();
-    for component in path.components() {
-        match component {
-            Component::Normal(os) => normalized.push(os),
-            Component::CurDir => {},
-            Component::ParentDir => return Err("Path cannot contain '..'".to_string()),
-            Component::RootDir | Component::Prefix(_) => return Err("Path must be relative".to_string()),
-        }
+    // 1-2. Bundled next to the resources, then next to the executable (portable mode)
+    if let Some(found) = manifest_candidates(app_handle).into_iter().find(|(manifest, _)| manifest.exists()) {
+        return Some(found);
     }
 
-    if normalized.as_os_str().is_empty() {
-        if allow_current {
-            Ok(PathBuf::from("."))
-        } else {
-            Err("Path cannot be '.'".to_string())
-        }
-    } else {
-        Ok(normalized)
+    // 3. Try a bundle embedded in the executable (single-file installer)
+    embedded_bundle()
+}
+
+/// The manifest and project root of the bundle embedded in this executable, extracted the
+/// first time it is asked for. The Studio never runs an embedded bundle, so it isn't
+/// extracted there.
+fn embedded_bundle() -> Option<(PathBuf, PathBuf)> {
+    static EMBEDDED: std::sync::OnceLock<Option<(PathBuf, PathBuf)>> = std::sync::OnceLock::new();
+    EMBEDDED
+        .get_or_init(|| {
+            if forced_app_mode() == Some(AppMode::Studio) {
+                return None;
+            }
+            let exe_path = std::env::current_exe().ok()?;
+            let extracted = embedded_bundle_dir();
+            match engine::extract_embedded_bundle(&exe_path, &extracted) {
+                Ok(true) => {
+                    let manifest = engine::bundle_manifest_path(&extracted.join("manifests"), engine::DEFAULT_MANIFEST_ID).ok()?;
+                    manifest.exists().then_some((manifest, extracted))
+                }
+                Ok(_) => None,
+                Err(err) => {
+                    log::error!("Failed to extract the embedded bundle: {}", err);
+                    None
+                }
+            }
+        })
+        .clone()
+}
+
+fn resolve_manifest_path(app_handle: &tauri::AppHandle) -> Option<PathBuf> {
+    resolve_manifest_info(app_handle).map(|(path, _)| path)
+}
+
+/// `name` without a trailing `.exe`, checked to be a file name Windows accepts.
+fn validate_exe_name(name: &str) -> Result<String, MisfitError> {
+    let trimmed = name.trim();
+    let stem = match trimmed.len().checked_sub(4) {
+        Some(at) if trimmed.is_char_boundary(at) && trimmed[at..].eq_ignore_ascii_case(".exe") => &trimmed[..at],
+        _ => trimmed,
+    };
+    if stem.is_empty() || stem.ends_with('.') || stem.chars().any(|c| c.is_control() || r#"<>:"/\|?*"#.contains(c)) {
+        return Err(format!("Invalid executable name: {:?}", name).into());
     }
+    Ok(stem.to_string())
 }
 
-fn validate_project_name(name: &str) -> Result<String, String> {
+fn validate_project_name(name: &str) -> Result<String, MisfitError> {
     let trimmed = name.trim();
     if trimmed.is_empty() {
-        return Err("Project name cannot be empty".to_string());
+        return Err("Project name cannot be empty".into());
     }
 
     let path = Path::new(trimmed);
     let mut components = path.components();
-    let first = components.next().ok_or("Project name cannot be empty".to_string())?;
+    let first = components.next().ok_or("Project name cannot be empty")?;
     if components.next().is_some() {
-        return Err("Project name must be a single folder name".to_string());
+        return Err("Project name must be a single folder name".into());
     }
 
     match first {
         Component::Normal(os) => Ok(os.to_string_lossy().to_string()),
-        Component::CurDir => Err("Project name cannot be '.'".to_string()),
-        Component::ParentDir => Err("Project name cannot be '..'".to_string()),
-        Component::RootDir | Component::Prefix(_) => Err("Project name must be a relative name".to_string()),
+        Component::CurDir => Err("Project name cannot be '.'".into()),
+        Component::ParentDir => Err("Project name cannot be '..'".into()),
+        Component::RootDir | Component::Prefix(_) => Err("Project name must be a relative name".into()),
     }
 }
 
-fn can_write_dir(dir: &Path) -> bool {
-    let nanos = std::time::SystemTime::now()
-        .duration_since(std::time::UNIX_EPOCH)
-        .map(|d| d.as_nanos())
-        .unwrap_or(0);
-    let test_path = dir.join(format!(".misfit_write_test_{}", nanos));
-    match std::fs::OpenOptions::new()
-        .write(true)
-        .create_new(true)
-        .open(&test_path)
-    {
-        Ok(_) => {
-            let _ = std::fs::remove_file(&test_path);
-            true
+fn resolve_dist_base(app_handle: &tauri::AppHandle) -> Result<PathBuf, MisfitError> {
+    if let Some(dir) = app_handle.state::<SettingsState>().get().dist_dir.filter(|d| !d.trim().is_empty()) {
+        let dir = PathBuf::from(engine::expand_env_vars(dir.trim()));
+        std::fs::create_dir_all(&dir).with_context(|| format!("Failed to create dist folder {}", dir.display()))?;
+        if !engine::can_write_dir(&dir) {
+            return Err(MisfitError::new(ErrorCode::PermissionDenied, format!("Dist folder not writable: {}", dir.display())).with_path(&dir));
         }
-        Err(_) => false,
+        return Ok(dir);
     }
-}
 
-fn resolve_dist_base(app_handle: &tauri::AppHandle) -> Result<PathBuf, String> {
     let cwd = std::env::current_dir().unwrap_or_else(|_| PathBuf::from("."));
     let candidate = cwd.join("dist");
-    if std::fs::create_dir_all(&candidate).is_ok() && can_write_dir(&candidate) {
+    if std::fs::create_dir_all(&candidate).is_ok() && engine::can_write_dir(&candidate) {
         return Ok(candidate);
     }
 
     let doc_dir = app_handle.path().document_dir().map_err(|e| e.to_string())?;
     let fallback = doc_dir.join("MisfitStudio").join("dist");
-    std::fs::create_dir_all(&fallback).map_err(|e| format!("Failed to create fallback dist at {}: {}", fallback.display(), e))?;
-    if !can_write_dir(&fallback) {
-        return Err(format!("Fallback dist not writable: {}", fallback.display()));
+    std::fs::create_dir_all(&fallback).with_context(|| format!("Failed to create fallback dist at {}", fallback.display()))?;
+    if !engine::can_write_dir(&fallback) {
+        return Err(MisfitError::new(ErrorCode::PermissionDenied, format!("Fallback dist not writable: {}", fallback.display())).with_path(&fallback));
     }
     Ok(fallback)
 }
@@ -207,22 +469,7 @@ fn resolve_payload_source(src: &str) -> PathBuf {
     PathBuf::from(src)
 }
 
-fn should_skip_dir(name: &str) -> bool {
-    matches!(
-        name,
-        "node_modules"
-            | ".git"
-            | "target"
-            | "dist"
-            | ".cache"
-            | "appdata"
-            | "windows"
-            | "program files"
-            | "program files (x86)"
-    )
-}
-
-fn find_payload_dir(base: &Path, payload_dir: &Path, depth: usize) -> Option<PathBuf> {
+fn find_payload_dir(base: &Path, payload_dir: &Path, depth: usize, settings: &settings::Settings) -> Option<PathBuf> {
     let candidate = base.join(payload_dir);
     if candidate.exists() {
         return Some(candidate);
@@ -238,11 +485,10 @@ fn find_payload_dir(base: &Path, payload_dir: &Path, depth: usize) -> Option<Pat
         if !path.is_dir() {
             continue;
         }
-        let name = entry.file_name().to_string_lossy().to_lowercase();
-        if should_skip_dir(&name) {
+        if settings.skips_dir(&entry.file_name().to_string_lossy()) {
             continue;
         }
-        if let Some(found) = find_payload_dir(&path, payload_dir, depth - 1) {
+        if let Some(found) = find_payload_dir(&path, payload_dir, depth - 1, settings) {
             return Some(found);
         }
     }
@@ -251,7 +497,7 @@ fn find_payload_dir(base: &Path, payload_dir: &Path, depth: usize) -> Option<Pat
 
 #[tauri::command]
 fn resolve_payload_root(payload_dir: String, app_handle: tauri::AppHandle) -> Option<String> {
-    let payload_dir = normalize_rel_path(&payload_dir, true).ok()?;
+    let payload_dir = engine::normalize_rel_path(&payload_dir, true).ok()?;
     if payload_dir.as_os_str() == "." {
         return None;
     }
@@ -268,20 +514,21 @@ fn resolve_payload_root(payload_dir: String, app_handle: tauri::AppHandle) -> Op
     if let Ok(docs) = app_handle.path().document_dir() {
         bases.push(docs);
     }
-    if let Some(home) = home_dir() {
+    if let Some(home) = engine::home_dir() {
         bases.push(home);
     }
     if let Some(onedrive) = env::var_os("OneDrive") {
         bases.push(PathBuf::from(onedrive));
     }
 
+    let settings = app_handle.state::<SettingsState>().get();
     let mut seen = HashSet::new();
     for base in bases {
         let key = base.to_string_lossy().to_lowercase();
         if !seen.insert(key) {
             continue;
         }
-        if let Some(found) = find_payload_dir(&base, &payload_dir, 3) {
+        if let Some(found) = find_payload_dir(&base, &payload_dir, 3, &settings) {
             return Some(found.to_string_lossy().to_string());
         }
     }
@@ -289,139 +536,103 @@ fn resolve_payload_root(payload_dir: String, app_handle: tauri::AppHandle) -> Op
     None
 }
 
-fn home_dir() -> Option<PathBuf> {
-    env::var_os("USERPROFILE")
-        .map(PathBuf::from)
-        .or_else(|| env::var_os("HOME").map(PathBuf::from))
+/// Where backups live; each app keeps its backups in a `backup_namespace` subfolder. In order:
+/// `MISFIT_BACKUP_DIR`, the manifest's `backupDir` (`manifest`, or the bundled one when `None`),
+/// the `backupDir` setting, then `Documents/MisfitBackups`.
+fn backups_root(app_handle: &tauri::AppHandle, manifest: Option<&engine::InstallManifest>) -> Result<PathBuf, MisfitError> {
+    let manifest_path = resolve_manifest_path(app_handle);
+    let bundled;
+    let manifest = match manifest {
+        Some(manifest) => Some(manifest),
+        None => {
+            bundled = manifest_path.as_deref().and_then(|p| engine::load_manifest(p).ok());
+            bundled.as_ref()
+        }
+    };
+    let manifest_dir = manifest_path.as_deref().and_then(Path::parent).unwrap_or(Path::new("."));
+    if let Some(dir) = engine::configured_backups_root(manifest, manifest_dir) {
+        return Ok(dir);
+    }
+    if let Some(dir) = app_handle.state::<SettingsState>().get().backup_dir.filter(|d| !d.trim().is_empty()) {
+        return Ok(PathBuf::from(engine::expand_env_vars(dir.trim())));
+    }
+
+    let text_doc_dir = app_handle.path().document_dir().map_err(|e| e.to_string())?;
+    Ok(text_doc_dir.join("MisfitBackups"))
 }
 
-fn backup_namespace(app_name: &str) -> String {
-    let trimmed = app_name.trim();
-    if trimmed.is_empty() {
-        return "default".to_string();
-    }
+/// Backups root of each app (by `backup_namespace`) as of its last install, in the app data
+/// dir. A manifest's `backupDir` can put an app's backups outside the default root, and the
+/// backup commands only get an app name or backup id.
+const BACKUP_ROOTS_FILE: &str = "backup_roots.json";
 
-    let mut out = String::new();
-    for ch in trimmed.chars() {
-        if ch.is_ascii_alphanumeric() || ch == '-' || ch == '_' || ch == '.' {
-            out.push(ch);
-        } else if ch.is_ascii_whitespace() {
-            out.push('_');
-        } else {
-            out.push('_');
-        }
-    }
+fn read_backup_roots(file: &Path) -> BTreeMap<String, PathBuf> {
+    std::fs::read_to_string(file).ok().and_then(|json| serde_json::from_str(&json).ok()).unwrap_or_default()
+}
 
-    if out.is_empty() {
-        "default".to_string()
-    } else {
-        out
+fn remember_backup_root(file: &Path, namespace: &str, root: &Path) -> Result<(), MisfitError> {
+    let mut roots = read_backup_roots(file);
+    if roots.get(namespace).map(PathBuf::as_path) == Some(root) {
+        return Ok(());
+    }
+    roots.insert(namespace.to_string(), root.to_path_buf());
+    if let Some(parent) = file.parent() {
+        std::fs::create_dir_all(parent)?;
     }
+    engine::write_atomic(file, serde_json::to_string_pretty(&roots)?.as_bytes()).map_err(|e| MisfitError::from(e).with_path(file))
 }
 
-fn expand_env_vars(input: &str) -> String {
-    let mut output = String::new();
-    let chars: Vec<char> = input.chars().collect();
-    let mut i = 0;
+fn backup_roots_file(app_handle: &tauri::AppHandle) -> Result<PathBuf, MisfitError> {
+    Ok(app_handle.path().app_data_dir().map_err(|e| e.to_string())?.join(BACKUP_ROOTS_FILE))
+}
 
-    if input.starts_with('~') {
-        if chars.len() == 1 || chars.get(1) == Some(&'\\') || chars.get(1) == Some(&'/') {
-            if let Some(home) = home_dir() {
-                output.push_str(&home.to_string_lossy());
-                i = 1;
-            }
-        }
-    }
+/// The backups root holding the backups of the app with backup `namespace`: the one its last
+/// install used, otherwise [`backups_root`] for the bundled manifest.
+fn app_backups_root(app_handle: &tauri::AppHandle, namespace: Option<&str>) -> Result<PathBuf, MisfitError> {
+    let recorded = namespace.and_then(|ns| read_backup_roots(&backup_roots_file(app_handle).ok()?).remove(ns));
+    recorded.map_or_else(|| backups_root(app_handle, None), Ok)
+}
 
-    while i < chars.len() {
-        let ch = chars[i];
-        if ch == '%' {
-            if let Some(end) = chars[i + 1..].iter().position(|c| *c == '%') {
-                let end_idx = i + 1 + end;
-                let name: String = chars[i + 1..end_idx].iter().collect();
-                if !name.is_empty() {
-                    if let Ok(val) = env::var(&name) {
-                        output.push_str(&val);
-                    } else {
-                        output.push('%');
-                        output.push_str(&name);
-                        output.push('%');
-                    }
-                    i = end_idx + 1;
-                    continue;
-                }
-            }
-        }
+/// The backups root holding backup `id` (`<namespace>/<backup>` or a backup at the top).
+fn backup_id_root(app_handle: &tauri::AppHandle, id: &str) -> Result<PathBuf, MisfitError> {
+    let namespace = id.split_once(['/', '\\']).map(|(namespace, _)| namespace);
+    app_backups_root(app_handle, namespace)
+}
 
-        if ch == '$' {
-            if i + 1 < chars.len() && chars[i + 1] == '{' {
-                if let Some(end) = chars[i + 2..].iter().position(|c| *c == '}') {
-                    let end_idx = i + 2 + end;
-                    let name: String = chars[i + 2..end_idx].iter().collect();
-                    if !name.is_empty() {
-                        if let Ok(val) = env::var(&name) {
-                            output.push_str(&val);
-                        } else {
-                            output.push_str("${");
-                            output.push_str(&name);
-                            output.push('}');
-                        }
-                        i = end_idx + 1;
-                        continue;
-                    }
-                }
-            } else {
-                let mut end_idx = i + 1;
-                while end_idx < chars.len() {
-                    let c = chars[end_idx];
-                    if c.is_ascii_alphanumeric() || c == '_' {
-                        end_idx += 1;
-                    } else {
-                        break;
-                    }
-                }
-                if end_idx > i + 1 {
-                    let name: String = chars[i + 1..end_idx].iter().collect();
-                    if let Ok(val) = env::var(&name) {
-                        output.push_str(&val);
-                    } else {
-                        output.push('$');
-                        output.push_str(&name);
-                    }
-                    i = end_idx;
-                    continue;
-                }
-            }
+/// [`backups_root`] for the bundled manifest followed by every other root an install used.
+fn all_backups_roots(app_handle: &tauri::AppHandle) -> Result<Vec<PathBuf>, MisfitError> {
+    let mut roots = vec![backups_root(app_handle, None)?];
+    for root in read_backup_roots(&backup_roots_file(app_handle)?).into_values() {
+        if !roots.contains(&root) {
+            roots.push(root);
         }
-
-        output.push(ch);
-        i += 1;
     }
-
-    output
+    Ok(roots)
 }
 
-fn resolve_path(base: &Path, path: &str) -> PathBuf {
-    let mut expanded = expand_env_vars(path);
-    
-    // FIX: Specialized mapping for macOS to handle Windows-centric presets
-    if cfg!(target_os = "macos") {
-        expanded = expanded.replace('\\', "/");
-        // Check for common Windows preset paths and map them to macOS standards
-        if expanded.contains("%LOCALAPPDATA%/Programs/Antigravity/resources") {
-            expanded = expanded.replace("%LOCALAPPDATA%/Programs/Antigravity/resources", "/Applications/Antigravity.app/Contents/Resources");
-        } else if expanded.contains("Programs/Antigravity/resources") {
-             // Fallback if env var was stripped or different
-            expanded = expanded.replace("Programs/Antigravity/resources", "/Applications/Antigravity.app/Contents/Resources");
-        }
+/// Shows `path` in the system file manager: a folder is opened, a file is selected in its
+/// folder. Only paths inside the dist, backup or log folders, or inside a build folder carrying
+/// the `.misfit-studio` marker (absolute outputs), are accepted.
+#[tauri::command]
+fn open_path_in_explorer(path: String, app_handle: tauri::AppHandle) -> Result<(), MisfitError> {
+    use tauri_plugin_opener::OpenerExt;
+    let path = PathBuf::from(path);
+    let target = std::fs::canonicalize(&path).map_err(|e| MisfitError::from(e).with_path(&path))?;
+    let mut roots = vec![resolve_dist_base(&app_handle)?, logging::log_dir(&app_handle)?];
+    roots.extend(all_backups_roots(&app_handle)?);
+    let known = roots.iter().filter_map(|root| std::fs::canonicalize(root).ok()).any(|root| target.starts_with(root))
+        || target.ancestors().any(|dir| dir.join(OUTPUT_MARKER).is_file());
+    if !known {
+        let message = format!("{} is not inside a dist, backup or log folder", path.display());
+        return Err(MisfitError::new(ErrorCode::PermissionDenied, message).with_path(&path));
     }
-    
-    let candidate = PathBuf::from(&expanded);
-    if candidate.is_absolute() {
-        candidate
+    let opened = if target.is_dir() {
+        app_handle.opener().open_path(path.to_string_lossy(), None::<&str>)
     } else {
-        base.join(candidate)
-    }
+        app_handle.opener().reveal_item_in_dir(&path)
+    };
+    opened.map_err(|e| e.to_string())?;
+    Ok(())
 }
 
 fn forced_app_mode() -> Option<AppMode> {
@@ -432,12 +643,16 @@ fn forced_app_mode() -> Option<AppMode> {
         if arg.eq_ignore_ascii_case("--installer") {
             return Some(AppMode::Installer);
         }
+        if arg.eq_ignore_ascii_case("--uninstall") {
+            return Some(AppMode::Uninstaller);
+        }
     }
 
     if let Ok(mode) = env::var("MISFIT_MODE") {
         match mode.to_lowercase().as_str() {
             "studio" => return Some(AppMode::Studio),
             "installer" => return Some(AppMode::Installer),
+            "uninstaller" => return Some(AppMode::Uninstaller),
             _ => {}
         }
     }
@@ -452,23 +667,13 @@ fn forced_app_mode() -> Option<AppMode> {
 }
 
 #[tauri::command]
-fn inspect_build_target(request: BuildRequest, app_handle: tauri::AppHandle) -> Result<BuildTargetInfo, String> {
-    let advanced_mode = request.manifest.advanced_mode.unwrap_or(false);
-    let is_absolute_output = advanced_mode && Path::new(&request.project_name).is_absolute();
-    let dist_root = if is_absolute_output {
-        PathBuf::from(&request.project_name)
-    } else {
-        let dist_base = resolve_dist_base(&app_handle)?;
-        let project_name = validate_project_name(&request.project_name)?;
-        dist_base.join(project_name)
-    };
-
-    let marker = dist_root.join(".misfit-studio");
+fn inspect_build_target(request: BuildRequest, app_handle: tauri::AppHandle) -> Result<BuildTargetInfo, MisfitError> {
+    let dirs = output_dirs(&request, &app_handle)?;
     Ok(BuildTargetInfo {
-        path: dist_root.to_string_lossy().to_string(),
-        exists: dist_root.exists(),
-        has_marker: marker.exists(),
-        is_absolute: is_absolute_output,
+        path: dirs.dist_root.to_string_lossy().to_string(),
+        exists: dirs.dist_root.exists(),
+        has_marker: dirs.project_root.join(OUTPUT_MARKER).exists(),
+        is_absolute: dirs.is_absolute,
     })
 }
 
@@ -484,273 +689,1233 @@ fn get_app_mode(app_handle: tauri::AppHandle) -> AppMode {
     }
 }
 
+/// The bundled manifest `manifest_id`, or the main one, with `profile` (or the `--profile`
+/// one) applied.
 #[tauri::command]
-fn get_manifest(app_handle: tauri::AppHandle) -> Result<engine::InstallManifest, String> {
-    match resolve_manifest_path(&app_handle) {
-        Some(path) => engine::load_manifest(&path).map_err(|e| e.to_string()),
-        None => Err("Manifest not found. App should be in Studio Mode.".to_string()),
-    }
+fn get_manifest(manifest_id: Option<String>, profile: Option<String>, app_handle: tauri::AppHandle) -> Result<engine::InstallManifest, MisfitError> {
+    let manifest = match resolve_manifest_path(&app_handle) {
+        Some(path) => match manifest_id {
+            Some(id) => engine::load_manifest(&engine::bundle_manifest_path(path.parent().unwrap_or(Path::new(".")), &id)?)?,
+            None => engine::load_manifest(&path)?,
+        },
+        None => return Err(MisfitError::new(ErrorCode::ManifestNotFound, "Manifest not found. App should be in Studio Mode.")),
+    };
+    Ok(engine::apply_profile(&manifest, profile.as_deref().or(PROFILE.get().map(String::as_str)))?)
 }
 
+/// `get_manifest` with `appName`, `description` and component names flattened to plain strings
+/// for `locale` (e.g. `de-AT`; falls back to `de`, then English, then the first translation),
+/// for display. Installs take the unlocalized manifest.
 #[tauri::command]
-fn read_text_file(path: String) -> Result<String, String> {
-    std::fs::read_to_string(&path).map_err(|e| e.to_string())
+fn get_localized_manifest(
+    locale: String,
+    manifest_id: Option<String>,
+    profile: Option<String>,
+    app_handle: tauri::AppHandle,
+) -> Result<engine::InstallManifest, MisfitError> {
+    Ok(get_manifest(manifest_id, profile, app_handle)?.localized(&locale))
 }
 
+/// The manifest's branding logo as a `data:` URL for the installer window, or `None` without
+/// one. The logo path is relative to the bundled manifest's folder.
 #[tauri::command]
-fn write_text_file(path: String, contents: String) -> Result<(), String> {
-    let file_path = PathBuf::from(&path);
-    if let Some(parent) = file_path.parent() {
-        std::fs::create_dir_all(parent).map_err(|e| e.to_string())?;
-    }
-    std::fs::write(file_path, contents).map_err(|e| e.to_string())
+fn get_branding_logo(manifest: engine::InstallManifest, app_handle: tauri::AppHandle) -> Result<Option<String>, MisfitError> {
+    let manifest_path = resolve_manifest_path(&app_handle).ok_or_else(|| MisfitError::new(ErrorCode::ManifestNotFound, "Manifest not found"))?;
+    Ok(engine::branding_logo_data_url(&manifest, manifest_path.parent().unwrap_or(Path::new(".")))?)
 }
 
+/// Every manifest the installer carries, the main one first, for a "choose what to install"
+/// screen.
 #[tauri::command]
-fn scan_extension_folders(root: String) -> Result<Vec<ScanEntry>, String> {
+fn list_manifests(app_handle: tauri::AppHandle) -> Result<Vec<engine::BundledManifest>, MisfitError> {
+    let path = resolve_manifest_path(&app_handle).ok_or_else(|| MisfitError::new(ErrorCode::ManifestNotFound, "Manifest not found"))?;
+    Ok(engine::list_bundle_manifests(path.parent().unwrap_or(Path::new(".")))?)
+}
+
+/// Writes a new base64 ed25519 secret key to `path` and returns the public key. Outside the
+/// Studio's folders only like `write_text_file`.
+#[tauri::command]
+async fn generate_signing_key(path: String, app_handle: tauri::AppHandle) -> Result<String, MisfitError> {
+    run_blocking(move || {
+        let key_path = PathBuf::from(&path);
+        if key_path.exists() {
+            return Err(format!("Refusing to overwrite existing key at {}", key_path.display()).into());
+        }
+        files::check_write(&key_path, &app_handle)?;
+        let (secret, public_key) = engine::generate_signing_key()?;
+        if let Some(parent) = key_path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        engine::write_secret_file(&key_path, secret.as_bytes())?;
+        Ok(public_key)
+    })
+    .await
+}
+
+/// Folders under `root` with their size and contents; only its direct subfolders without
+/// `options`.
+#[tauri::command]
+fn scan_extension_folders(root: String, options: Option<engine::ScanOptions>) -> Result<Vec<engine::ScanEntry>, MisfitError> {
     let root_path = PathBuf::from(&root);
     if !root_path.exists() {
-        return Err("Folder not found.".to_string());
+        return Err(MisfitError::new(ErrorCode::NotFound, "Folder not found.").with_path(&root_path));
     }
     if !root_path.is_dir() {
-        return Err("Selected path is not a folder.".to_string());
+        return Err("Selected path is not a folder.".into());
     }
+    Ok(engine::scan_folders(&root_path, &options.unwrap_or_default())?)
+}
 
-    let mut entries = Vec::new();
-    let read_dir = std::fs::read_dir(&root_path).map_err(|e| e.to_string())?;
-    for entry in read_dir {
-        let entry = entry.map_err(|e| e.to_string())?;
-        let path = entry.path();
-        if path.is_dir() {
-            let name = entry.file_name().to_string_lossy().to_string();
-            entries.push(ScanEntry {
-                name,
-                path: path.to_string_lossy().to_string(),
-            });
+/// The payload folder `root` as a tree with sizes for the Studio's payload browser: one page
+/// of one folder's children (`options.path`, `offset`, `limit`), `depth` levels deep.
+#[tauri::command]
+async fn list_payload_tree(root: String, options: Option<engine::TreeOptions>) -> Result<engine::PayloadTree, MisfitError> {
+    run_blocking(move || Ok(engine::list_payload_tree(Path::new(&root), &options.unwrap_or_default())?)).await
+}
+
+/// Every file the build's `(source, destination)` pairs put in the payload folder, as
+/// `/`-separated paths relative to it.
+fn payload_listing(payload_files: &[(String, String)], exclude: &[String]) -> Result<Vec<String>, MisfitError> {
+    let mut payload = Vec::new();
+    for (src, relative_dest) in payload_files {
+        let dest = engine::normalize_rel_path(relative_dest, false)?;
+        let plan = if engine::has_glob_meta(src) {
+            let (base, pattern) = engine::split_glob(src);
+            engine::plan_copy_glob(&resolve_payload_source(&base.to_string_lossy()), &pattern.unwrap_or_default(), &dest, exclude)?
+        } else {
+            let src_path = resolve_payload_source(src);
+            if !src_path.exists() {
+                return Err(MisfitError::new(ErrorCode::PayloadMissing, format!("Payload source not found: {:?}", src_path)).with_path(&src_path));
+            }
+            engine::plan_copy(&src_path, &dest, exclude)?
+        };
+        payload.extend(plan.files.iter().map(|job| job.dest.to_string_lossy().replace('\\', "/")));
+    }
+    payload.sort();
+    payload.dedup();
+    Ok(payload)
+}
+
+/// Checks a manifest in the studio. `payload_files` are the build's `(source, destination)`
+/// pairs; without them the payload checks are skipped.
+#[tauri::command]
+fn lint_manifest(manifest: engine::InstallManifest, payload_files: Option<Vec<(String, String)>>) -> Result<Vec<engine::LintIssue>, MisfitError> {
+    let Some(payload_files) = payload_files else { return Ok(engine::lint_manifest(&manifest, None)) };
+    let payload = payload_listing(&payload_files, &[])?;
+    Ok(engine::lint_manifest(&manifest, Some(&payload)))
+}
+
+/// Plans copying the build's `(source, destination)` payloads into `payloads_dir`. Also returns
+/// the destinations relative to `payloads_dir`, which the lockfile covers.
+fn plan_payloads(payload_files: &[(String, String)], payloads_dir: &Path, exclude: &[String]) -> Result<(engine::CopyPlan, Vec<PathBuf>), MisfitError> {
+    let mut copied = Vec::new();
+    let mut plan = engine::CopyPlan::default();
+    for (src, relative_dest) in payload_files {
+        let dest_rel = engine::normalize_rel_path(relative_dest, false)?;
+        let dest_path = payloads_dir.join(&dest_rel);
+        if engine::has_glob_meta(src) {
+            let (base, pattern) = engine::split_glob(src);
+            let base_path = resolve_payload_source(&base.to_string_lossy());
+            let pattern = pattern.unwrap_or_default();
+            plan.extend(engine::plan_copy_glob(&base_path, &pattern, &dest_path, exclude).with_context(|| format!("Failed to expand payload {}", src))?);
+            copied.push(dest_rel);
+            continue;
+        }
+        let src_path = resolve_payload_source(src);
+        if src_path.exists() {
+             plan.extend(engine::plan_copy(&src_path, &dest_path, exclude).with_context(|| format!("Failed to read payload {}", src_path.display()))?);
+             copied.push(dest_rel);
+        } else {
+             return Err(MisfitError::new(ErrorCode::PayloadMissing, format!("Payload source not found: {:?}", src_path)).with_path(&src_path));
+        }
+    }
+    Ok((plan, copied))
+}
+
+/// Duplicate files among the payloads a build would copy and the bytes they waste, keyed by
+/// their path in the payload folder.
+#[tauri::command]
+async fn analyze_payloads(payload_files: Vec<(String, String)>, payload_exclude: Option<Vec<String>>) -> Result<engine::PayloadAnalysis, MisfitError> {
+    run_blocking(move || {
+        let (plan, _) = plan_payloads(&payload_files, Path::new(""), &payload_exclude.unwrap_or_default())?;
+        let files: Vec<(String, PathBuf)> = plan.files.iter().map(|job| (job.dest.to_string_lossy().replace('\\', "/"), job.src.clone())).collect();
+        Ok(engine::analyze_payload_files(&files)?)
+    })
+    .await
+}
+
+/// Refuses a broken installer: fails with `ValidationFailed` and the lint errors.
+fn validate_request(request: &BuildRequest) -> Result<(), MisfitError> {
+    let exclude = request.payload_exclude.clone().unwrap_or_default();
+    let payloads = payload_listing(&request.payload_files, &exclude)?;
+    let mut issues = engine::validate_build(&request.manifest, &payloads);
+    let mut ids = HashSet::new();
+    for (id, manifest) in request.extra_manifests.iter().flatten() {
+        engine::validate_manifest_id(id)?;
+        if id == engine::DEFAULT_MANIFEST_ID || !ids.insert(id) {
+            return Err(MisfitError::new(ErrorCode::ValidationFailed, format!("Manifest id {:?} is used twice", id)));
         }
+        issues.extend(engine::validate_build(manifest, &payloads));
     }
+    let manifests = std::iter::once(&request.manifest).chain(request.extra_manifests.iter().flatten().map(|(_, m)| m));
+    for logo in manifests.filter_map(|m| m.branding.as_ref()?.logo.as_deref()) {
+        if !Path::new(logo).is_file() {
+            return Err(MisfitError::new(ErrorCode::ValidationFailed, format!("Branding logo not found: {}", logo)).with_path(Path::new(logo)));
+        }
+    }
+    if !issues.is_empty() {
+        let message = format!("Build blocked by {} problem(s); the first: {}", issues.len(), issues[0].message);
+        return Err(MisfitError::new(ErrorCode::ValidationFailed, message).with_issues(issues));
+    }
+    Ok(())
+}
 
-    entries.sort_by(|a, b| a.name.to_lowercase().cmp(&b.name.to_lowercase()));
-    Ok(entries)
+#[tauri::command]
+async fn build_project(request: BuildRequest, app_handle: tauri::AppHandle) -> Result<BuildOutput, MisfitError> {
+    run_blocking(move || build_project_blocking(request, app_handle)).await
 }
 
+/// Stops the running build once the files being copied are done. The build then fails with
+/// `cancelled` and removes what it had written.
 #[tauri::command]
-async fn build_project(request: BuildRequest, app_handle: tauri::AppHandle) -> Result<String, String> {
-    let exe_path = std::env::current_exe().map_err(|e| e.to_string())?;
-    let advanced_mode = request.manifest.advanced_mode.unwrap_or(false);
+fn cancel_build(state: tauri::State<'_, BuildState>) {
+    state.cancel.cancel();
+}
+
+fn emit_build_progress(app_handle: &tauri::AppHandle, step: BuildStep, current_file: Option<&Path>, bytes_done: u64, bytes_total: u64) {
+    let current_file = current_file.map(|path| path.to_string_lossy().to_string());
+    let _ = app_handle.emit("build-progress", BuildProgress { step, current_file, bytes_done, bytes_total });
+}
+
+fn build_project_blocking(request: BuildRequest, app_handle: tauri::AppHandle) -> Result<BuildOutput, MisfitError> {
+    let _operation = begin_operation(&app_handle, OperationKind::Build)?;
+    let exe_path = std::env::current_exe()?;
+    let cancel = app_handle.state::<BuildState>().cancel.clone();
+    cancel.reset();
     let force_overwrite = request.force_overwrite.unwrap_or(false);
-    let payload_dir = normalize_rel_path(&request.manifest.payload_dir, true)?;
 
-    // Target dir: "dist/{project_name}"
-    let is_absolute_output = advanced_mode && Path::new(&request.project_name).is_absolute();
-    let (dist_root, project_name) = if is_absolute_output {
-        let dist_root = PathBuf::from(&request.project_name);
-        let project_name = dist_root
-            .file_name()
-            .ok_or("Absolute output path must include a folder name".to_string())?
-            .to_string_lossy()
-            .to_string();
-        (dist_root, project_name)
-    } else {
-        let dist_base = resolve_dist_base(&app_handle)?;
-        let project_name = validate_project_name(&request.project_name)?;
-        let dist_root = dist_base.join(&project_name);
-        if !dist_root.starts_with(&dist_base) {
-            return Err("Resolved output path escaped dist/".to_string());
-        }
-        (dist_root, project_name)
+    // Refuse a broken installer before anything is written
+    validate_request(&request)?;
+    let exclude = request.payload_exclude.clone().unwrap_or_default();
+    let payload_dir = engine::normalize_rel_path(&request.manifest.payload_dir, true)?;
+
+    // Target dir: "dist/{project_name}", or a version folder inside it
+    let OutputDirs { project_root, dist_root, project_name, is_absolute: is_absolute_output } = output_dirs(&request, &app_handle)?;
+    let layout = request.output_layout.unwrap_or_default();
+    // Another build writing the same output would interleave with this one
+    let _lock = engine::lock_operation(&dist_root, "build")?;
+    let exe_name = match request.exe_name.as_deref() {
+        Some(name) => validate_exe_name(name)?,
+        None => project_name.clone(),
     };
     
     // Clean/Create dist
+    let marker = project_root.join(OUTPUT_MARKER);
+    if is_absolute_output && project_root.exists() && !marker.exists() && !force_overwrite {
+        let message = format!(
+            "Refusing to overwrite {} (missing .misfit-studio marker). Create the folder and add .misfit-studio to confirm.",
+            project_root.display()
+        );
+        return Err(MisfitError::new(ErrorCode::MarkerNotFound, message).with_path(&marker));
+    }
     if dist_root.exists() {
-        if is_absolute_output {
-            let marker = dist_root.join(".misfit-studio");
-            if !marker.exists() && !force_overwrite {
-                return Err(format!(
-                    "Refusing to overwrite {} (missing .misfit-studio marker). Create the folder and add .misfit-studio to confirm.",
-                    dist_root.display()
-                ));
-            }
-        }
-        std::fs::remove_dir_all(&dist_root).map_err(|e| e.to_string())?;
+        std::fs::remove_dir_all(&dist_root)?;
     }
-    std::fs::create_dir_all(&dist_root).map_err(|e| e.to_string())?;
+    std::fs::create_dir_all(&dist_root)?;
     if is_absolute_output {
-        let marker = dist_root.join(".misfit-studio");
-        let _ = std::fs::write(marker, "Misfit Studio output");
+        let _ = std::fs::write(&marker, "Misfit Studio output");
+    }
+    if layout != OutputLayout::Replace {
+        std::fs::write(dist_root.join(OUTPUT_MARKER), "Misfit Studio output")?;
     }
+    // A cancelled build leaves nothing half-written behind
+    let cancelled = || {
+        let _ = std::fs::remove_dir_all(&dist_root);
+        let _ = emit_log(&app_handle, "Build cancelled");
+        MisfitError::new(ErrorCode::Cancelled, "Build cancelled").with_path(&dist_root)
+    };
+    let start_step = |step: BuildStep, current_file: Option<&Path>| {
+        if cancel.is_cancelled() {
+            return Err(cancelled());
+        }
+        emit_build_progress(&app_handle, step, current_file, 0, 0);
+        Ok(())
+    };
 
     // 1. Copy Executable (Self-Replication)
     let ext = if cfg!(target_os = "windows") { ".exe" } else { "" };
-    let dest_exe = dist_root.join(format!("{}{}", project_name, ext));
-    std::fs::copy(&exe_path, &dest_exe).map_err(|e| format!("Failed to copy executable: {}", e))?;
+    let dest_exe = dist_root.join(format!("{}{}", exe_name, ext));
+    start_step(BuildStep::Exe, Some(&dest_exe))?;
+    std::fs::copy(&exe_path, &dest_exe).context("Failed to copy executable")?;
+    // A Studio started from a built installer carries that installer's bundle and key
+    engine::strip_exe_trailers(&dest_exe)?;
+    let metadata = engine::ExeMetadata {
+        product_name: request.manifest.app_name.to_string(),
+        product_version: request.product_version.clone().unwrap_or_else(|| request.manifest.version.clone()),
+        company_name: request.company.clone().unwrap_or_else(|| request.manifest.publisher.clone()),
+        file_description: request.file_description.clone().unwrap_or_else(|| format!("{} installer", request.manifest.app_name)),
+        original_filename: dest_exe.file_name().unwrap_or_default().to_string_lossy().to_string(),
+    };
+    branding::set_version_info(&dest_exe, &metadata)?;
+    let launcher_files = match request.icon_path.as_deref() {
+        Some(icon) => branding::set_icon(&dest_exe, Path::new(icon), &metadata)?,
+        None => Vec::new(),
+    };
 
     // 2. Write Manifest
     let manifest_dir = dist_root.join("manifests");
-    std::fs::create_dir_all(&manifest_dir).map_err(|e| e.to_string())?;
+    std::fs::create_dir_all(&manifest_dir)?;
     let manifest_path = manifest_dir.join("install.manifest.json");
-    let manifest_json = serde_json::to_string_pretty(&request.manifest).map_err(|e| e.to_string())?;
-    std::fs::write(&manifest_path, manifest_json).map_err(|e| e.to_string())?;
+    start_step(BuildStep::Manifest, Some(&manifest_path))?;
+    let manifest_json = serde_json::to_string_pretty(&with_bundled_logo(&request.manifest, &manifest_dir)?)?;
+    std::fs::write(&manifest_path, manifest_json)?;
+    for (id, manifest) in request.extra_manifests.iter().flatten() {
+        let manifest_json = serde_json::to_string_pretty(&with_bundled_logo(manifest, &manifest_dir)?)?;
+        engine::validate_manifest_id(id)?;
+        std::fs::write(manifest_dir.join(format!("{}.manifest.json", id)), manifest_json)?;
+    }
 
     // 3. Copy Payloads
+    start_step(BuildStep::Payloads, None)?;
     let payloads_dir = dist_root.join(&payload_dir); // e.g. "payloads" or "."
-    std::fs::create_dir_all(&payloads_dir).map_err(|e| e.to_string())?;
+    std::fs::create_dir_all(&payloads_dir)?;
+
+    let (plan, copied) = plan_payloads(&request.payload_files, &payloads_dir, &exclude)?;
+    let on_progress = engine::throttle_progress(|progress: &engine::CopyProgress| {
+        let current_file = Path::new(&progress.current_file);
+        emit_build_progress(&app_handle, BuildStep::Payloads, Some(current_file), progress.bytes_copied, progress.total_bytes);
+    });
+    let source_snapshot = if request.verify_sources.unwrap_or(false) { Some(engine::snapshot_sources(&plan)?) } else { None };
+    let copied_payloads = engine::copy_plan_cancellable(&plan, &on_progress, &cancel);
+    if cancel.is_cancelled() {
+        return Err(cancelled());
+    }
+    copied_payloads.context("Failed to copy payloads")?;
+
+    // 4. Write payload lockfile next to the manifest
+    start_step(BuildStep::Lockfile, None)?;
+    let lock = engine::build_payload_lock(&payloads_dir, &copied)?;
+    let lock_json = serde_json::to_string_pretty(&lock)?;
+    std::fs::write(manifest_dir.join(engine::PAYLOAD_LOCK_FILE), lock_json)?;
+
+    // 5. Optionally replace the loose payload files with a single archive
+    let compression = request.compress_payloads.unwrap_or_default();
+    let output_format = request.output_format.unwrap_or_default();
+    let embed = request.embed_payloads.unwrap_or(false) || output_format == OutputFormat::SingleFile;
+    let dedupe = request.dedupe_payloads.unwrap_or(false);
+    if dedupe && compression.archive_name().is_none() && !embed {
+        emit_log(&app_handle, "Payloads are only deduplicated in a payload archive or single-file executable")?;
+    }
+    if let Some(archive_name) = compression.archive_name() {
+        let archive_path = dist_root.join(archive_name);
+        start_step(BuildStep::Compress, Some(&archive_path))?;
+        engine::create_payload_archive(&payloads_dir, &copied, &archive_path, compression, dedupe)?;
+        for entry in &copied {
+            let path = payloads_dir.join(entry);
+            let removed = if path.is_dir() { std::fs::remove_dir_all(&path) } else { std::fs::remove_file(&path) };
+            removed?;
+        }
+        if payloads_dir != dist_root {
+            let _ = std::fs::remove_dir(&payloads_dir);
+        }
+        emit_log(&app_handle, format!("Payloads compressed into {}", archive_path.display()))?;
+    }
 
-    for (src, relative_dest) in request.payload_files {
-        let src_path = resolve_payload_source(&src);
-        let dest_rel = normalize_rel_path(&relative_dest, false)?;
-        let dest_path = payloads_dir.join(dest_rel);
-        if src_path.exists() {
-             engine::copy_payload(&src_path, &dest_path).map_err(|e| format!("Failed to copy payload {}: {}", src_path.display(), e))?;
-        } else {
-             return Err(format!("Payload source not found: {:?}", src_path));
+    // 6. Sign manifest + lockfile and embed the public key in the executable
+    if let Some(key_path) = request.signing_key_path.as_deref() {
+        start_step(BuildStep::Sign, None)?;
+        let secret = std::fs::read_to_string(key_path).context("Failed to read signing key")?;
+        let public_key = engine::sign_install(&manifest_dir, &secret)?;
+        engine::append_exe_trailer(&dest_exe, engine::PUBLIC_KEY_TRAILER, &public_key)?;
+        emit_log(&app_handle, format!("Signed with key {}", engine::key_fingerprint(&public_key)))?;
+    }
+
+    // 7. Optionally fold everything except the executable into the executable itself
+    if embed {
+        start_step(BuildStep::Embed, Some(&dest_exe))?;
+        let mut entries = Vec::new();
+        for entry in std::fs::read_dir(&dist_root)? {
+            let entry = entry?;
+            let name = entry.file_name();
+            if entry.path() != dest_exe && name != OUTPUT_MARKER && !launcher_files.contains(&entry.path()) {
+                entries.push(PathBuf::from(name));
+            }
+        }
+        let bundle_path = std::env::temp_dir().join(format!("misfit_bundle_{}.zip", std::process::id()));
+        engine::create_payload_archive(&dist_root, &entries, &bundle_path, engine::PayloadCompression::Zip, dedupe)?;
+        let appended = engine::append_exe_trailer_file(&dest_exe, engine::PAYLOAD_TRAILER, &bundle_path);
+        let _ = std::fs::remove_file(&bundle_path);
+        appended?;
+        for entry in entries {
+            let path = dist_root.join(entry);
+            let removed = if path.is_dir() { std::fs::remove_dir_all(&path) } else { std::fs::remove_file(&path) };
+            removed?;
+        }
+        emit_log(&app_handle, "Embedded manifest and payloads into the executable")?;
+    }
+
+    // 8. Authenticode-sign the executable last; signing tools append their certificate table
+    // after the trailers, where `exe_trailer_range` still finds them
+    if let Some(signing) = request.code_signing.as_ref() {
+        start_step(BuildStep::Codesign, Some(&dest_exe))?;
+        let approve = |program: &str, command_line: &str| ask_signing_command(&app_handle, program, command_line);
+        codesign::sign_executable(&dest_exe, signing, &approve, &|line| {
+            let _ = emit_log(&app_handle, line);
+        })?;
+        emit_log(&app_handle, "Signed the executable")?;
+    }
+
+    // 9. Refuse output copied from files that were being edited; it may mix old and new
+    if let Some(snapshot) = source_snapshot.as_ref() {
+        if let Err(err) = engine::verify_sources(snapshot) {
+            let _ = std::fs::remove_dir_all(&dist_root);
+            return Err(err.into());
+        }
+        emit_log(&app_handle, format!("Verified {} payload source file(s) were unchanged", snapshot.len()))?;
+    }
+
+    let zip_path = match output_format {
+        OutputFormat::Folder | OutputFormat::SingleFile => None,
+        OutputFormat::Zip => {
+            start_step(BuildStep::Zip, None)?;
+            let zip_path = release_zip(&dist_root, &project_name, &request.manifest.version, request.zip_root_folder.unwrap_or(false))?;
+            emit_log(&app_handle, format!("Release zip written to {}", zip_path.display()))?;
+            Some(zip_path.to_string_lossy().to_string())
+        }
+    };
+
+    if let (true, Some(keep)) = (layout != OutputLayout::Replace, request.keep_last) {
+        for removed in prune_builds(&project_root, keep)? {
+            emit_log(&app_handle, format!("Removed old build {}", removed.display()))?;
         }
     }
 
     let msg = format!("Project built successfully at: {}", dist_root.display());
-    app_handle.emit("log", &msg).map_err(|e| e.to_string())?;
+    emit_log(&app_handle, &msg)?;
+    if let Some(project) = request.project_path.as_deref() {
+        project::note_build(&app_handle, project);
+    }
     
-    Ok(dist_root.to_string_lossy().to_string())
+    Ok(BuildOutput {
+        output_dir: dist_root.to_string_lossy().to_string(),
+        exe_path: dest_exe.to_string_lossy().to_string(),
+        zip_path,
+    })
+}
+
+/// `manifest` with its branding logo copied into `manifest_dir/branding` and pointing at the
+/// copy, so the built installer doesn't depend on the author's files.
+fn with_bundled_logo(manifest: &engine::InstallManifest, manifest_dir: &Path) -> Result<engine::InstallManifest, MisfitError> {
+    let mut manifest = manifest.clone();
+    if let Some(logo) = manifest.branding.as_mut().and_then(|b| b.logo.as_mut()) {
+        let source = PathBuf::from(&*logo);
+        let name = source.file_name().ok_or_else(|| format!("Branding logo {} is not a file", logo))?.to_string_lossy().to_string();
+        let dest = manifest_dir.join(engine::BRANDING_DIR).join(&name);
+        std::fs::create_dir_all(dest.parent().unwrap_or(manifest_dir))?;
+        std::fs::copy(&source, &dest).map_err(|e| MisfitError::from(e).with_path(&source))?;
+        *logo = format!("{}/{}", engine::BRANDING_DIR, name);
+    }
+    Ok(manifest)
+}
+
+/// Zips the finished `dist_root` into `{name}-{version}.zip` beside it, replacing an older
+/// zip of the same name. With `root_folder` the files sit under `{name}/` inside the zip.
+fn release_zip(dist_root: &Path, name: &str, version: &str, root_folder: bool) -> Result<PathBuf, MisfitError> {
+    let version = file_name_safe(version);
+    let zip_name = if version.is_empty() { name.to_string() } else { format!("{}-{}", name, version) };
+    let zip_path = dist_root.with_file_name(format!("{}.zip", zip_name));
+
+    let mut files = Vec::new();
+    for item in walkdir::WalkDir::new(dist_root).sort_by_file_name() {
+        let item = item.map_err(std::io::Error::from)?;
+        let rel = item.path().strip_prefix(dist_root).unwrap_or(item.path());
+        if !item.file_type().is_file() || rel == Path::new(OUTPUT_MARKER) {
+            continue;
+        }
+        let rel = rel.to_string_lossy().replace('\\', "/");
+        let entry = if root_folder { format!("{}/{}", name, rel) } else { rel };
+        files.push((entry, item.path().to_path_buf()));
+    }
+    engine::write_zip_files(&zip_path, &files).map_err(|e| MisfitError::from(e).with_path(&zip_path))?;
+    Ok(zip_path)
 }
 
 #[tauri::command]
-async fn restore_backup(app_name: Option<String>, app_handle: tauri::AppHandle) -> Result<(), String> {
-    let text_doc_dir = app_handle.path().document_dir().map_err(|e| e.to_string())?;
-    let fallback_root = text_doc_dir.join("MisfitBackups");
-    let backup_root = if let Some(name) = app_name.as_deref() {
-        fallback_root.join(backup_namespace(name))
-    } else {
-        fallback_root.clone()
+async fn restore_backup(
+    app_name: Option<String>,
+    backup_id: Option<String>,
+    files: Option<Vec<String>>,
+    app_handle: tauri::AppHandle,
+) -> Result<(), MisfitError> {
+    run_blocking(move || restore_backup_blocking(app_name, backup_id, files, app_handle)).await
+}
+
+/// Restores `backup_id` (or the latest backup for `app_name`), optionally limited to `files`.
+fn restore_backup_blocking(
+    app_name: Option<String>,
+    backup_id: Option<String>,
+    files: Option<Vec<String>>,
+    app_handle: tauri::AppHandle,
+) -> Result<(), MisfitError> {
+    let _operation = begin_operation(&app_handle, OperationKind::Restore)?;
+    if let Some(id) = backup_id {
+        let root = backup_id_root(&app_handle, &id)?;
+        let backup_dir = engine::resolve_backup_id(&root, &id)?;
+        let _lock = engine::lock_operation(backup_dir.parent().unwrap_or(&root), "restore")?;
+        match files {
+            Some(files) => {
+                let count = engine::restore_backup_files(&backup_dir, &files)?;
+                emit_log(&app_handle, format!("Restored {} file(s) from {}", count, id))?;
+            }
+            None => {
+                engine::restore_backup_dir(&backup_dir)?;
+                emit_log(&app_handle, format!("Restored successfully from {}", id))?;
+            }
+        }
+        return Ok(());
+    }
+    if files.is_some() {
+        return Err("Restoring selected files requires a backup id".into());
+    }
+
+    let namespace = app_name.as_deref().map(engine::backup_namespace);
+    let fallback_root = app_backups_root(&app_handle, namespace.as_deref())?;
+    let backup_root = match &namespace {
+        Some(namespace) => fallback_root.join(namespace),
+        None => fallback_root.clone(),
     };
-    app_handle.emit("log", format!("Attempting restore from {:?}", backup_root)).map_err(|e| e.to_string())?;
+    emit_log(&app_handle, format!("Attempting restore from {:?}", backup_root))?;
 
     let restored_from = match engine::restore_latest_backup(&backup_root) {
         Ok(path) => path,
         Err(err) => {
-            if app_name.is_some() && backup_root != fallback_root {
-                let _ = app_handle.emit(
-                    "log",
-                    format!("No app-specific backups found, falling back to {:?}", fallback_root),
-                );
-                engine::restore_latest_backup(&fallback_root).map_err(|e| e.to_string())?
+            let err = MisfitError::from(err);
+            // Busy is not the same as missing; don't restore someone else's backup instead
+            if err.code != ErrorCode::OperationInProgress && app_name.is_some() && backup_root != fallback_root {
+                let _ = emit_log(&app_handle, format!("No app-specific backups found, falling back to {:?}", fallback_root));
+                engine::restore_latest_backup(&fallback_root)?
             } else {
-                return Err(err.to_string());
+                return Err(err);
             }
         }
     };
     
-    app_handle.emit("log", format!("Restored successfully from {}", restored_from)).map_err(|e| e.to_string())?;
+    emit_log(&app_handle, format!("Restored successfully from {}", restored_from))?;
     Ok(())
 }
 
 #[tauri::command]
-async fn run_install(manifest: engine::InstallManifest, app_handle: tauri::AppHandle) -> Result<(), String> {
-    let (manifest_path, project_root) = resolve_manifest_info(&app_handle).ok_or("Manifest not found")?;
+fn list_backups(app_name: Option<String>, app_handle: tauri::AppHandle) -> Result<Vec<engine::BackupSummary>, MisfitError> {
+    let Some(namespace) = app_name.as_deref().map(engine::backup_namespace) else {
+        let mut backups = Vec::new();
+        for root in all_backups_roots(&app_handle)? {
+            backups.extend(engine::list_backups(&root, None)?);
+        }
+        return Ok(backups);
+    };
+    engine::list_backups(&app_backups_root(&app_handle, Some(&namespace))?, Some(&namespace)).map_err(MisfitError::from)
+}
+
+#[tauri::command]
+fn get_backup_details(id: String, app_handle: tauri::AppHandle) -> Result<engine::BackupDetails, MisfitError> {
+    engine::backup_details(&backup_id_root(&app_handle, &id)?, &id).map_err(MisfitError::from)
+}
+
+/// Dry run of `restore_backup`: which targets would be overwritten, recreated or left alone.
+#[tauri::command]
+fn preview_restore(backup_id: String, app_handle: tauri::AppHandle) -> Result<Vec<engine::RestorePreviewEntry>, MisfitError> {
+    let backup_dir = engine::resolve_backup_id(&backup_id_root(&app_handle, &backup_id)?, &backup_id)?;
+    engine::preview_restore(&backup_dir).map_err(MisfitError::from)
+}
+
+#[tauri::command]
+fn prune_backups(
+    app_name: Option<String>,
+    policy: engine::RetentionPolicy,
+    dry_run: bool,
+    app_handle: tauri::AppHandle,
+) -> Result<engine::PruneReport, MisfitError> {
+    let Some(namespace) = app_name.as_deref().map(engine::backup_namespace) else {
+        let mut report = engine::PruneReport { dry_run, ..Default::default() };
+        for root in all_backups_roots(&app_handle)? {
+            let pruned = engine::prune_backups(&root, None, &policy, dry_run)?;
+            report.removed.extend(pruned.removed);
+            report.kept += pruned.kept;
+            report.freed_bytes += pruned.freed_bytes;
+        }
+        return Ok(report);
+    };
+    let root = app_backups_root(&app_handle, Some(&namespace))?;
+    engine::prune_backups(&root, Some(&namespace), &policy, dry_run).map_err(MisfitError::from)
+}
+
+#[tauri::command]
+fn delete_backup(id: String, app_handle: tauri::AppHandle) -> Result<(), MisfitError> {
+    engine::delete_backup(&backup_id_root(&app_handle, &id)?, &id)?;
+    emit_log(&app_handle, format!("Deleted backup {}", id))?;
+    Ok(())
+}
+
+/// Records `message` in the log file and shows it in the UI log.
+fn emit_log(app_handle: &tauri::AppHandle, message: impl Into<String>) -> Result<(), MisfitError> {
+    let message = message.into();
+    log::info!("{}", message);
+    app_handle.emit("log", message).map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+/// Runs blocking engine work (file copies, archives, patching) on the blocking thread
+/// pool so the async runtime stays free to deliver events to the webview meanwhile.
+async fn run_blocking<T, F>(work: F) -> Result<T, MisfitError>
+where
+    F: FnOnce() -> Result<T, MisfitError> + Send + 'static,
+    T: Send + 'static,
+{
+    let result = tauri::async_runtime::spawn_blocking(work)
+        .await
+        .map_err(|e| MisfitError::from(format!("Background task failed: {}", e)))?;
+    if let Err(err) = &result {
+        log::error!("{:?}: {}", err.code, err.message);
+    }
+    result
+}
+
+
+/// Payloads extracted from a compressed build, with the project root they came from. Kept
+/// for the rest of the run, so each command doesn't extract them again, and deleted on exit.
+static EXTRACTED_PAYLOAD: std::sync::Mutex<Option<(PathBuf, engine::PayloadSource)>> = std::sync::Mutex::new(None);
+
+/// Returns the manifest folder (base for target paths) and the payload folder.
+fn resolve_install_roots(manifest: &engine::InstallManifest, app_handle: &tauri::AppHandle) -> Result<(PathBuf, PathBuf), MisfitError> {
+    let (manifest_path, project_root) =
+        resolve_manifest_info(app_handle).ok_or_else(|| MisfitError::new(ErrorCode::ManifestNotFound, "Manifest not found"))?;
     let manifest_dir = manifest_path.parent().unwrap_or(Path::new(".")).to_path_buf();
-    let payload_dir = normalize_rel_path(&manifest.payload_dir, true)?;
-    let advanced_mode = manifest.advanced_mode.unwrap_or(false);
+    let mut extracted = EXTRACTED_PAYLOAD.lock().map_err(|e| e.to_string())?;
+    if let Some((_, payload)) = extracted.as_ref().filter(|(root, _)| *root == project_root) {
+        return Ok((manifest_dir, payload.path().to_path_buf()));
+    }
+    let payload = engine::resolve_payload_source(&project_root, &manifest.payload_dir)?;
+    let payload_source = payload.path().to_path_buf();
+    if payload.is_extracted() {
+        *extracted = Some((project_root, payload));
+    }
+    Ok((manifest_dir, payload_source))
+}
 
-    let payload_source = project_root.join(&payload_dir);
-    if !payload_source.exists() {
-        return Err(format!("Payload directory not found: {}", payload_source.display()));
+/// Runs the install and returns the path of its `install_report.json`. With `manifest_id`,
+/// the bundled manifest of that id runs instead of `manifest`. `selected_components` picks the
+/// optional components to install; the manifest's defaults when absent. `prompt_values` holds
+/// what the user entered for the manifest's `prompts`; they are only kept in memory.
+/// `accepted_license` says the user accepted the `get_license_text` license; the engine refuses
+/// to install a licensed manifest without it.
+#[tauri::command]
+async fn run_install(
+    manifest: Option<engine::InstallManifest>,
+    manifest_id: Option<String>,
+    selected_components: Option<Vec<String>>,
+    prompt_values: Option<BTreeMap<String, String>>,
+    accepted_license: bool,
+    app_handle: tauri::AppHandle,
+) -> Result<PathBuf, MisfitError> {
+    run_blocking(move || {
+        let manifest = chosen_manifest(manifest, manifest_id, &app_handle)?;
+        let choices = InstallChoices { components: selected_components, prompt_values, accepted_license, ..Default::default() };
+        run_install_blocking(manifest, None, choices, app_handle)
+    })
+    .await
+}
+
+fn chosen_manifest(
+    manifest: Option<engine::InstallManifest>,
+    manifest_id: Option<String>,
+    app_handle: &tauri::AppHandle,
+) -> Result<engine::InstallManifest, MisfitError> {
+    match (manifest_id, manifest) {
+        (Some(id), _) => get_manifest(Some(id), None, app_handle.clone()),
+        (None, Some(manifest)) => Ok(manifest),
+        (None, None) => Err("run_install needs a manifest or a manifest id".into()),
     }
-    
-    // Backup first
-    let mut backup_paths = Vec::new();
-    for step in &manifest.install_steps {
-        match step {
-            engine::InstallStep::PatchBlock { file, .. } => {
-                let resolved = resolve_path(&manifest_dir, file);
-                backup_paths.push(resolved.to_string_lossy().to_string());
-            }
-            engine::InstallStep::SetJsonValue { file, .. } => {
-                let resolved = resolve_path(&manifest_dir, file);
-                backup_paths.push(resolved.to_string_lossy().to_string());
-            }
-            engine::InstallStep::Base64Embed { file, .. } => {
-                let resolved = resolve_path(&manifest_dir, file);
-                backup_paths.push(resolved.to_string_lossy().to_string());
-            }
-            _ => {}
+}
+
+/// Re-runs the steps of one phase (e.g. `configure` after the user changed something).
+#[tauri::command]
+async fn run_install_phase(
+    manifest: engine::InstallManifest,
+    phase: engine::Phase,
+    prompt_values: Option<BTreeMap<String, String>>,
+    accepted_license: bool,
+    app_handle: tauri::AppHandle,
+) -> Result<PathBuf, MisfitError> {
+    let choices = InstallChoices { phase: Some(phase), prompt_values, accepted_license, ..Default::default() };
+    run_blocking(move || run_install_blocking(manifest, None, choices, app_handle)).await
+}
+
+/// Continues an install that failed or was interrupted, skipping the steps it already did.
+/// Prompted values aren't saved with the journal, so they are passed again.
+#[tauri::command]
+async fn resume_install(
+    manifest: Option<engine::InstallManifest>,
+    manifest_id: Option<String>,
+    prompt_values: Option<BTreeMap<String, String>>,
+    accepted_license: bool,
+    app_handle: tauri::AppHandle,
+) -> Result<PathBuf, MisfitError> {
+    run_blocking(move || {
+        let manifest = chosen_manifest(manifest, manifest_id, &app_handle)?;
+        let choices = InstallChoices { resume: true, prompt_values, accepted_license, ..Default::default() };
+        run_install_blocking(manifest, None, choices, app_handle)
+    })
+    .await
+}
+
+/// The manifest's optional components with their defaults and dependencies, for a "choose
+/// features" screen; the choice goes to `run_install` as `selected_components`. Names are in
+/// `locale` when given.
+#[tauri::command]
+fn get_components(manifest: engine::InstallManifest, locale: Option<String>) -> Vec<engine::ComponentSummary> {
+    match locale {
+        Some(locale) => engine::list_components(&manifest.localized(&locale)),
+        None => engine::list_components(&manifest),
+    }
+}
+
+/// The unfinished install of `manifest`, if any, so the UI can offer to resume it.
+#[tauri::command]
+fn pending_install(manifest: engine::InstallManifest, app_handle: tauri::AppHandle) -> Result<Option<engine::InstallJournal>, MisfitError> {
+    Ok(engine::pending_install(&manifest, &backups_root(&app_handle, Some(&manifest))?)?)
+}
+
+/// The license text (EULA) the user has to accept before `run_install`, or `None` when the
+/// manifest has no `licenseFile` in its payload.
+#[tauri::command]
+fn get_license_text(manifest: engine::InstallManifest, app_handle: tauri::AppHandle) -> Result<Option<String>, MisfitError> {
+    let (_, payload_source) = resolve_install_roots(&manifest, &app_handle)?;
+    Ok(engine::license_text(&manifest, &payload_source)?)
+}
+
+/// What the caller of an install chose; unset fields keep the defaults of `install_options`.
+#[derive(Default)]
+struct InstallChoices {
+    phase: Option<engine::Phase>,
+    resume: bool,
+    components: Option<Vec<String>>,
+    prompt_values: Option<BTreeMap<String, String>>,
+    accepted_license: bool,
+}
+
+fn run_install_blocking(
+    manifest: engine::InstallManifest,
+    target: Option<PathBuf>,
+    choices: InstallChoices,
+    app_handle: tauri::AppHandle,
+) -> Result<PathBuf, MisfitError> {
+    let _operation = begin_operation(&app_handle, OperationKind::Install)?;
+    let defaults = install_options(&manifest, target, &app_handle)?;
+    let options = engine::InstallOptions {
+        phase: choices.phase,
+        resume: choices.resume,
+        components: choices.components,
+        prompt_values: choices.prompt_values.unwrap_or(defaults.prompt_values),
+        accepted_license: choices.accepted_license,
+        ..defaults
+    };
+    let report = engine::run_install(&manifest, &options, &TauriObserver(&app_handle))?;
+    let namespace = engine::backup_namespace(&manifest.app_name);
+    if let Err(err) = backup_roots_file(&app_handle).and_then(|file| remember_backup_root(&file, &namespace, &options.backups_root)) {
+        log::warn!("Could not record where the backups of {} are: {}", manifest.app_name, err);
+    }
+    let app_backup_root = options.backups_root.join(&namespace);
+    let args = match uninstall::copy_uninstaller(&app_backup_root, &manifest) {
+        Ok(args) => args,
+        Err(err) => {
+            log::warn!("Failed to copy the uninstaller: {}", err);
+            return Ok(report);
         }
+    };
+    match uninstall::write_uninstall_shortcut(&app_backup_root, &manifest.app_name, &args) {
+        Ok(shortcut) => emit_log(&app_handle, format!("Uninstall shortcut: {}", shortcut.display()))?,
+        Err(err) => log::warn!("Failed to write the uninstall shortcut: {}", err),
     }
-    backup_paths.sort();
-    backup_paths.dedup();
+    if cfg!(windows) && manifest.register_uninstall.unwrap_or(false) {
+        match uninstall::register_program(&manifest, &args) {
+            Ok(()) => emit_log(&app_handle, format!("Registered {} under Add/Remove Programs", manifest.app_name))?,
+            Err(err) => log::warn!("{}", err),
+        }
+    }
+    Ok(report)
+}
 
-    let text_doc_dir = app_handle.path().document_dir().map_err(|e| e.to_string())?;
-    let backup_root = text_doc_dir
-        .join("MisfitBackups")
-        .join(backup_namespace(&manifest.app_name));
-    
-    if !backup_paths.is_empty() {
-        let _backup_loc = engine::backup_files(&backup_paths, &backup_root).map_err(|e| e.to_string())?;
-        app_handle.emit("log", format!("Backup created at {:?}", _backup_loc)).map_err(|e| e.to_string())?;
-    }
-
-    for step in manifest.install_steps {
-        match step {
-            engine::InstallStep::Copy { src, dest } => {
-                let src_rel = normalize_rel_path(&src, false)?;
-                let s = payload_source.join(src_rel);
-                let d = resolve_path(&manifest_dir, &dest);
-                app_handle.emit("log", format!("Copying {:?} to {:?}", s, d)).map_err(|e| e.to_string())?;
-                engine::copy_payload(&s, &d).map_err(|e| e.to_string())?;
-            },
-            engine::InstallStep::PatchBlock { file, start_marker, end_marker, content_file, replacements } => {
-                let target_path = resolve_path(&manifest_dir, &file);
-                app_handle.emit("log", format!("Patching {}", target_path.display())).map_err(|e| e.to_string())?;
-                let content_file = content_file.ok_or("PatchBlock requires contentFile".to_string())?;
-                let content_rel = normalize_rel_path(&content_file, false)?;
-                let content_path = payload_source.join(content_rel);
-                let mut content = std::fs::read_to_string(&content_path)
-                    .map_err(|e| format!("Failed to read patch content {}: {}", content_path.display(), e))?;
-                if let Some(reps) = replacements {
-                    for (k, v) in reps {
-                        content = content.replace(&k, &v);
-                    }
-                }
-                engine::patch_file(&target_path, &start_marker, &end_marker, &content, advanced_mode).map_err(|e| e.to_string())?;
-            },
-            engine::InstallStep::SetJsonValue { file, key_path, value } => {
-                let target_path = resolve_path(&manifest_dir, &file);
-                app_handle.emit("log", format!("Updating JSON {} key {}", target_path.display(), key_path)).map_err(|e| e.to_string())?;
-                engine::set_json_value(&target_path, &key_path, &value).map_err(|e| e.to_string())?;
-            },
-             engine::InstallStep::RunCommand { command, args } => {
-                app_handle.emit("log", format!("Running command: {} {:?}", command, args)).map_err(|e| e.to_string())?;
-                engine::run_command(&command, &args).map_err(|e| e.to_string())?;
-            },
-            engine::InstallStep::Base64Embed { file, placeholder, input_file } => {
-                 let target_path = resolve_path(&manifest_dir, &file);
-                 app_handle.emit("log", format!("Embedding base64 into {}", target_path.display())).map_err(|e| e.to_string())?;
-                 let input_rel = normalize_rel_path(&input_file, false)?;
-                 let input_path = payload_source.join(input_rel);
-                 engine::base64_embed(&target_path, &placeholder, &input_path).map_err(|e| e.to_string())?;
-            }
+fn install_options(
+    manifest: &engine::InstallManifest,
+    target: Option<PathBuf>,
+    app_handle: &tauri::AppHandle,
+) -> Result<engine::InstallOptions, MisfitError> {
+    let (manifest_dir, payload_source) = resolve_install_roots(manifest, app_handle)?;
+    let trusted_key = std::env::current_exe()
+        .ok()
+        .and_then(|exe| engine::read_exe_trailer(&exe, engine::PUBLIC_KEY_TRAILER).ok().flatten());
+    Ok(engine::InstallOptions {
+        // Relative target paths resolve from the manifest folder unless a target was given
+        target_base: target.unwrap_or_else(|| manifest_dir.clone()),
+        manifest_dir,
+        payload_source,
+        backups_root: backups_root(app_handle, Some(manifest))?,
+        trusted_key,
+        phase: None,
+        resume: false,
+        components: None,
+        // Headless runs can't ask, so prompts are answered from MISFIT_VAR_<NAME>
+        prompt_values: engine::prompt_values_from_env(manifest),
+        accepted_license: false,
+    })
+}
+
+/// Re-applies only the steps whose outcome is gone (e.g. after the target app updated).
+#[tauri::command]
+async fn repair_install(
+    manifest: engine::InstallManifest,
+    prompt_values: Option<BTreeMap<String, String>>,
+    app_handle: tauri::AppHandle,
+) -> Result<engine::RepairReport, MisfitError> {
+    run_blocking(move || {
+        let _operation = begin_operation(&app_handle, OperationKind::Repair)?;
+        let mut options = install_options(&manifest, None, &app_handle)?;
+        if let Some(values) = prompt_values {
+            options.prompt_values = values;
         }
+        Ok(engine::repair_install(&manifest, &options, &TauriObserver(&app_handle))?)
+    })
+    .await
+}
+
+/// Which files of a `watchInstalledFiles` install no longer match their recorded hashes; the
+/// installer checks this on start and offers `repair_install` when some do.
+#[tauri::command]
+async fn check_install_integrity(manifest: engine::InstallManifest, app_handle: tauri::AppHandle) -> Result<engine::IntegrityReport, MisfitError> {
+    run_blocking(move || {
+        let app_backup_root = backups_root(&app_handle, Some(&manifest))?.join(engine::backup_namespace(&manifest.app_name));
+        Ok(engine::check_install_integrity(&app_backup_root)?)
+    })
+    .await
+}
+
+/// Holds the reply channel of each `overwrite-prompt` or `command-prompt` waiting on the user,
+/// by prompt id, so prompts raised at the same time each get their own answer.
+#[derive(Default)]
+struct PromptState {
+    pending: std::sync::Mutex<HashMap<u64, std::sync::mpsc::Sender<bool>>>,
+    next_id: std::sync::atomic::AtomicU64,
+    /// Answer used without asking (headless runs have nobody to ask).
+    auto_answer: Option<bool>,
+}
+
+/// Payload of a prompt event; the answer names `id`.
+#[derive(Serialize, Clone)]
+struct PromptRequest<T> {
+    id: u64,
+    details: T,
+}
+
+/// Asks the frontend whether to overwrite `files` and blocks until it answers through
+/// `answer_overwrite_prompt`. No answer within ten minutes keeps the existing files.
+fn ask_overwrite(app_handle: &tauri::AppHandle, files: Vec<String>) -> Result<bool, MisfitError> {
+    if !app_handle.state::<SettingsState>().get().confirm_overwrite {
+        return Ok(true);
+    }
+    ask_user(app_handle, "overwrite-prompt", files)
+}
+
+/// Asks the frontend whether to run `command_line` and blocks until it answers through
+/// `answer_command_prompt`. Allowlisted commands run without asking; no answer within ten
+/// minutes declines.
+fn ask_command(app_handle: &tauri::AppHandle, command: &str, command_line: &str) -> Result<bool, MisfitError> {
+    let settings = app_handle.state::<SettingsState>().get();
+    if settings.allows_command(command) {
+        log::info!("{} is allowlisted; running it without asking", command);
+        return Ok(true);
+    }
+    if !settings.confirm_run_commands {
+        return Ok(true);
+    }
+    let answer = ask_user(app_handle, "command-prompt", command_line)?;
+    log::info!("Command {}: {}", if answer { "approved" } else { "declined" }, command_line);
+    Ok(answer)
+}
+
+/// Asks before running a project's own code signing command. Unlike install commands this
+/// can't be turned off: the project may come from someone else. Allowlisted programs run.
+fn ask_signing_command(app_handle: &tauri::AppHandle, program: &str, command_line: &str) -> Result<bool, MisfitError> {
+    if app_handle.state::<SettingsState>().get().allows_command(program) {
+        return Ok(true);
+    }
+    let answer = ask_user(app_handle, "command-prompt", command_line)?;
+    log::info!("Signing command {}: {}", if answer { "approved" } else { "declined" }, command_line);
+    Ok(answer)
+}
+
+fn ask_user(app_handle: &tauri::AppHandle, event: &str, payload: impl Serialize + Clone) -> Result<bool, MisfitError> {
+    let state = app_handle.state::<PromptState>();
+    if let Some(answer) = state.auto_answer {
+        return Ok(answer);
+    }
+    let id = state.next_id.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+    let (tx, rx) = std::sync::mpsc::channel();
+    state.pending.lock().map_err(|e| e.to_string())?.insert(id, tx);
+    let asked = app_handle.emit(event, PromptRequest { id, details: payload });
+    let answer = asked.is_ok() && rx.recv_timeout(std::time::Duration::from_secs(600)).unwrap_or(false);
+    state.pending.lock().map_err(|e| e.to_string())?.remove(&id);
+    asked.map_err(|e| e.to_string())?;
+    Ok(answer)
+}
+
+#[tauri::command]
+fn answer_overwrite_prompt(id: u64, overwrite: bool, state: tauri::State<'_, PromptState>) -> Result<(), MisfitError> {
+    answer_prompt(id, overwrite, &state)
+}
+
+#[tauri::command]
+fn answer_command_prompt(id: u64, run: bool, state: tauri::State<'_, PromptState>) -> Result<(), MisfitError> {
+    answer_prompt(id, run, &state)
+}
+
+fn answer_prompt(id: u64, answer: bool, state: &PromptState) -> Result<(), MisfitError> {
+    let pending = state.pending.lock().map_err(|e| e.to_string())?.remove(&id);
+    match pending {
+        Some(tx) => tx.send(answer).map_err(|e| e.to_string().into()),
+        None => Err(format!("Prompt {} is not pending", id).into()),
+    }
+}
+
+/// Forwards engine progress to the webview as events.
+struct TauriObserver<'a>(&'a tauri::AppHandle);
+
+impl engine::InstallObserver for TauriObserver<'_> {
+    fn log(&self, message: &str) {
+        let _ = emit_log(self.0, message);
+    }
+
+    fn event(&self, name: &str, payload: serde_json::Value) {
+        if name == "integrity-warning" || name == "elevation-required" {
+            log::warn!("{}: {}", name, payload);
+        }
+        let _ = self.0.emit(name, payload);
+    }
+
+    fn confirm_overwrite(&self, files: &[String]) -> bool {
+        ask_overwrite(self.0, files.to_vec()).unwrap_or(false)
+    }
+
+    fn confirm_command(&self, command: &str, command_line: &str) -> bool {
+        ask_command(self.0, command, command_line).unwrap_or(false)
+    }
+}
+
+
+#[tauri::command]
+fn preview_patch(manifest: engine::InstallManifest, step_index: usize, app_handle: tauri::AppHandle) -> Result<engine::PatchPreview, MisfitError> {
+    let (manifest_dir, payload_source) = resolve_install_roots(&manifest, &app_handle)?;
+    let manifest = manifest.for_platform(engine::Platform::current());
+    let step = manifest.install_steps.get(step_index).ok_or("Step index out of range")?;
+    let engine::InstallStep::PatchBlock { file, .. } = &step.step else {
+        return Err("Only PatchBlock steps can be previewed".into());
+    };
+    let target_path = engine::resolve_path(&manifest_dir, file);
+    let specs = engine::patch_specs(step, &payload_source, manifest.advanced_mode.unwrap_or(false))?;
+    engine::preview_patch_blocks(&target_path, &specs).map_err(MisfitError::from)
+}
+
+#[tauri::command]
+async fn revert_patches(app_name: String, app_handle: tauri::AppHandle) -> Result<(), MisfitError> {
+    run_blocking(move || revert_patches_blocking(app_name, app_handle)).await
+}
+
+fn revert_patches_blocking(app_name: String, app_handle: tauri::AppHandle) -> Result<(), MisfitError> {
+    let _operation = begin_operation(&app_handle, OperationKind::Uninstall)?;
+    let namespace = engine::backup_namespace(&app_name);
+    let backup_root = app_backups_root(&app_handle, Some(&namespace))?.join(&namespace);
+    engine::revert_all_patches(&backup_root, &TauriObserver(&app_handle))?;
+    // Left over from an install with `registerUninstall`; nothing to do otherwise
+    if let Err(err) = engine::unregister_program(&app_name) {
+        log::warn!("{}", err);
     }
-    
-    app_handle.emit("log", "Installation complete!".to_string()).map_err(|e| e.to_string())?;
     Ok(())
 }
 
+
+/// Reports whether the install can run: payload size against free space on each target
+/// volume (plus the backup folder), and whether every touched file is writable and unlocked.
+#[tauri::command]
+fn preflight_install(manifest: engine::InstallManifest, app_handle: tauri::AppHandle) -> Result<engine::PreflightReport, MisfitError> {
+    let (manifest_dir, payload_source) = resolve_install_roots(&manifest, &app_handle)?;
+    preflight_report(&manifest, &manifest_dir, &payload_source, &app_handle)
+}
+
+fn preflight_report(manifest: &engine::InstallManifest, manifest_dir: &Path, payload_source: &Path, app_handle: &tauri::AppHandle) -> Result<engine::PreflightReport, MisfitError> {
+    let backups_root = backups_root(app_handle, Some(manifest))?;
+    engine::preflight_manifest(manifest, manifest_dir, payload_source, &backups_root).map_err(MisfitError::from)
+}
+
+/// The concrete, ordered list of what `run_install` would do with `selected_components` (the
+/// defaults when absent), for a "what will happen" screen.
+#[tauri::command]
+fn resolve_install_plan(
+    manifest: engine::InstallManifest,
+    selected_components: Option<Vec<String>>,
+    app_handle: tauri::AppHandle,
+) -> Result<engine::InstallPlan, MisfitError> {
+    let (manifest_dir, payload_source) = resolve_install_roots(&manifest, &app_handle)?;
+    Ok(engine::resolve_install_plan(&manifest, &manifest_dir, &payload_source, selected_components.as_deref())?)
+}
+
+/// OS, architecture, elevation, locale and free space, for tailoring warnings. With `manifest`,
+/// the volumes are those of its install target and backups; the temp folder's always is.
+#[tauri::command]
+fn get_system_info(manifest: Option<engine::InstallManifest>, app_handle: tauri::AppHandle) -> Result<engine::SystemInfo, MisfitError> {
+    let mut paths = Vec::new();
+    if let Some(manifest) = &manifest {
+        paths.push(resolve_install_roots(manifest, &app_handle)?.0);
+        paths.push(backups_root(&app_handle, Some(manifest))?);
+    }
+    paths.push(std::env::temp_dir());
+    Ok(engine::system_info(&paths.iter().map(PathBuf::as_path).collect::<Vec<_>>()))
+}
+
+/// Size and expected duration of installing `manifest`, for the screen before it starts.
+#[tauri::command]
+fn estimate_install(manifest: engine::InstallManifest, app_handle: tauri::AppHandle) -> Result<engine::InstallEstimate, MisfitError> {
+    let (manifest_dir, payload_source) = resolve_install_roots(&manifest, &app_handle)?;
+    let backups_root = backups_root(&app_handle, Some(&manifest))?;
+    Ok(engine::estimate_install(&manifest, &manifest_dir, &payload_source, &backups_root))
+}
+
+/// Runs the manifest against a temp copy of the files it touches and returns the diffs, so
+/// authors can try it end to end without changing the real app. `selected_components` and
+/// `prompt_values` are taken like `run_install`'s; `MISFIT_VAR_*` values and defaults fill the
+/// prompted variables when `prompt_values` is absent.
+#[tauri::command]
+async fn simulate_install(
+    manifest: engine::InstallManifest,
+    selected_components: Option<Vec<String>>,
+    prompt_values: Option<BTreeMap<String, String>>,
+    app_handle: tauri::AppHandle,
+) -> Result<engine::SimulationReport, MisfitError> {
+    run_blocking(move || {
+        let (manifest_dir, payload_source) = resolve_install_roots(&manifest, &app_handle)?;
+        let prompt_values = prompt_values.unwrap_or_else(|| engine::prompt_values_from_env(&manifest));
+        Ok(engine::simulate_install(
+            &manifest,
+            &manifest_dir,
+            &payload_source,
+            selected_components.as_deref(),
+            &prompt_values,
+            &TauriObserver(&app_handle),
+        )?)
+    })
+    .await
+}
+
+/// The manifest as a PowerShell or bash script, for machines where the installer can't run,
+/// with the steps of `selected_components` (the defaults when absent).
+#[tauri::command]
+fn export_install_script(
+    manifest: engine::InstallManifest,
+    format: engine::ScriptFormat,
+    selected_components: Option<Vec<String>>,
+    app_handle: tauri::AppHandle,
+) -> Result<String, MisfitError> {
+    let (_, payload_source) = resolve_install_roots(&manifest, &app_handle)?;
+    Ok(engine::export_install_script(&manifest, &payload_source, selected_components.as_deref(), format)?)
+}
+
+/// Installs of VS Code, Insiders, VSCodium and Cursor on this machine, for the path helpers.
+#[tauri::command]
+async fn detect_vscode_installs() -> Result<Vec<engine::VsCodeInstall>, MisfitError> {
+    run_blocking(|| Ok(engine::detect_vscode_installs())).await
+}
+
+/// Electron apps in the usual install folders, so authors can pick a patch target from a list.
+#[tauri::command]
+async fn detect_electron_apps() -> Result<Vec<engine::ElectronApp>, MisfitError> {
+    run_blocking(|| Ok(engine::detect_electron_apps())).await
+}
+
+/// Starts the manifest's `launchAfterInstall` program, detached so closing the installer
+/// doesn't take it down. The manifest comes from the webview, so the command is confirmed
+/// like an install command first.
+#[tauri::command]
+async fn launch_target(manifest: engine::InstallManifest, app_handle: tauri::AppHandle) -> Result<(), MisfitError> {
+    run_blocking(move || {
+        let manifest = manifest.for_platform(engine::Platform::current());
+        let target = manifest
+            .launch_after_install
+            .as_ref()
+            .ok_or_else(|| MisfitError::new(ErrorCode::NotFound, "The manifest has no launchAfterInstall"))?;
+        if !ask_command(&app_handle, &target.command, &engine::command_line(&target.command, &target.args))? {
+            return Err(MisfitError::new(ErrorCode::Cancelled, format!("Launching {} was declined", target.command)));
+        }
+        let (manifest_dir, _) = resolve_install_roots(&manifest, &app_handle)?;
+        engine::launch_detached(target, &manifest_dir)?;
+        emit_log(&app_handle, format!("Launched {}", manifest.app_name))?;
+        Ok(())
+    })
+    .await
+}
+
+/// Relaunches the installer with administrator rights and closes this instance.
+#[tauri::command]
+fn relaunch_elevated(app_handle: tauri::AppHandle) -> Result<(), MisfitError> {
+    let exe = env::current_exe()?;
+    let mut args: Vec<String> = env::args().skip(1).collect();
+    if !args.iter().any(|a| a.eq_ignore_ascii_case("--installer") || a.eq_ignore_ascii_case("--uninstall")) {
+        args.push("--installer".to_string());
+    }
+    engine::relaunch_elevated(&exe, &args)?;
+    app_handle.exit(0);
+    Ok(())
+}
+
+
+
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
 pub fn run() {
+  let cli_args = match cli::parse_args(env::args().skip(1)) {
+    Ok(args) => args,
+    Err(err) => {
+      eprintln!("{}\n\n{}", err, cli::USAGE);
+      std::process::exit(cli::EXIT_USAGE);
+    }
+  };
+  if let Some(manifest) = &cli_args.manifest {
+    let manifest = std::fs::canonicalize(manifest).unwrap_or_else(|_| manifest.clone());
+    let _ = MANIFEST_OVERRIDE.set(manifest);
+  }
+  if let Some(profile) = &cli_args.profile {
+    let _ = PROFILE.set(profile.clone());
+  }
+  let headless = cli_args.is_headless();
+  if headless {
+    cli::attach_console();
+  }
+
   tauri::Builder::default()
     .plugin(tauri_plugin_opener::init())
     .plugin(tauri_plugin_dialog::init())
+    .manage(PromptState {
+        auto_answer: headless.then_some(false),
+        ..PromptState::default()
+    })
+    .manage(BuildState::default())
+    .manage(project::OpenProjectState::default())
+    .manage(OperationState::default())
+    .manage(watch::WatchState::default())
+    .setup(move |app| {
+        logging::init(&logging::log_dir(app.handle())?);
+        let settings = settings::load_settings(&settings::settings_path(app.handle())?);
+        settings::apply(&settings);
+        app.manage(SettingsState(std::sync::Mutex::new(settings)));
+
+        if headless {
+            let handle = app.handle().clone();
+            std::thread::spawn(move || {
+                let code = cli::run_headless(cli_args, &handle);
+                handle.exit(code);
+            });
+        } else {
+            // Windows are created here rather than from the config so headless runs never show one
+            for window in app.config().app.windows.clone() {
+                tauri::WebviewWindowBuilder::from_config(app.handle(), &window)?.build()?;
+            }
+        }
+        Ok(())
+    })
     .invoke_handler(tauri::generate_handler![
         get_app_mode,
         get_manifest,
+        get_localized_manifest,
+        get_branding_logo,
+        list_manifests,
         inspect_build_target,
         resolve_payload_root,
         run_install,
+        run_install_phase,
+        resume_install,
+        pending_install,
+        get_components,
+        get_license_text,
+        get_current_operation,
+        repair_install,
+        check_install_integrity,
         restore_backup,
+        revert_patches,
+        preview_patch,
+        answer_overwrite_prompt,
+        answer_command_prompt,
+        preflight_install,
+        resolve_install_plan,
+        estimate_install,
+        get_system_info,
+        simulate_install,
+        export_install_script,
+        relaunch_elevated,
+        launch_target,
+        detect_vscode_installs,
+        detect_electron_apps,
+        list_backups,
+        get_backup_details,
+        delete_backup,
+        preview_restore,
+        prune_backups,
+        open_path_in_explorer,
         build_project,
-        read_text_file,
-        write_text_file,
-        scan_extension_folders
+        cancel_build,
+        lint_manifest,
+        files::read_text_file,
+        files::preview_text_file,
+        files::write_text_file,
+        files::read_file_base64,
+        files::write_file_base64,
+        generate_signing_key,
+        scan_extension_folders,
+        list_payload_tree,
+        analyze_payloads,
+        settings::get_settings,
+        settings::set_settings,
+        logging::get_recent_logs,
+        logging::open_log_folder,
+        diagnostics::export_diagnostics,
+        record::start_recording,
+        record::finish_recording,
+        record::list_recordings,
+        record::discard_recording,
+        record::generate_manifest_from_diff,
+        project::save_project,
+        project::load_project,
+        project::export_project_archive,
+        project::import_project_archive,
+        project::get_recent_projects,
+        project::save_draft,
+        project::load_draft,
+        project::discard_draft,
+        snippets::list_snippets,
+        snippets::save_snippet,
+        snippets::insert_snippet,
+        snippets::delete_snippet,
+        uninstall::get_uninstall_state,
+        watch::start_build_watch,
+        watch::stop_build_watch
     ])
-    .run(tauri::generate_context!())
-    .expect("error while running tauri application");
+    .build(tauri::generate_context!())
+    .expect("error while running tauri application")
+    .run(|_, event| {
+        if let tauri::RunEvent::Exit = event {
+            // A single-file installer leaves nothing behind in the temp folder
+            let extracted = embedded_bundle_dir();
+            if extracted.exists() {
+                let _ = std::fs::remove_dir_all(extracted);
+            }
+            if let Ok(mut payload) = EXTRACTED_PAYLOAD.lock() {
+                payload.take();
+            }
+        }
+    });
 }
@@ -0,0 +1,153 @@
+//! Writes every `log` record (including what the UI sees as `log` events) to `misfit.log` in
+//! the app log dir, rotating to `misfit.1.log` .. `misfit.N.log` once it grows too large.
+
+use misfit_engine::MisfitError;
+use std::fs::{self, File, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use tauri::Manager;
+
+pub const LOG_FILE: &str = "misfit.log";
+const MAX_LOG_BYTES: u64 = 1024 * 1024;
+const KEEP_ROTATED: usize = 5;
+
+pub struct FileLogger {
+    dir: PathBuf,
+    max_bytes: u64,
+    keep: usize,
+    file: Mutex<Option<File>>,
+}
+
+impl FileLogger {
+    pub fn new(dir: &Path, max_bytes: u64, keep: usize) -> Self {
+        FileLogger { dir: dir.to_path_buf(), max_bytes, keep, file: Mutex::new(None) }
+    }
+
+    fn rotated_path(&self, index: usize) -> PathBuf {
+        self.dir.join(format!("misfit.{}.log", index))
+    }
+
+    /// Shifts `misfit.log` -> `misfit.1.log` -> ... and drops the oldest.
+    fn rotate(&self) -> std::io::Result<()> {
+        let _ = fs::remove_file(self.rotated_path(self.keep));
+        for index in (1..self.keep).rev() {
+            let from = self.rotated_path(index);
+            if from.exists() {
+                fs::rename(&from, self.rotated_path(index + 1))?;
+            }
+        }
+        fs::rename(self.dir.join(LOG_FILE), self.rotated_path(1))
+    }
+
+    pub fn write_line(&self, line: &str) -> std::io::Result<()> {
+        let Ok(mut guard) = self.file.lock() else { return Ok(()) };
+        let path = self.dir.join(LOG_FILE);
+        let size = fs::metadata(&path).map(|m| m.len()).unwrap_or(0);
+        if size > 0 && size + line.len() as u64 > self.max_bytes {
+            *guard = None;
+            self.rotate()?;
+        }
+        if guard.is_none() {
+            fs::create_dir_all(&self.dir)?;
+            *guard = Some(OpenOptions::new().create(true).append(true).open(&path)?);
+        }
+        match guard.as_mut() {
+            Some(file) => file.write_all(line.as_bytes()),
+            None => Ok(()),
+        }
+    }
+}
+
+impl log::Log for FileLogger {
+    fn enabled(&self, metadata: &log::Metadata) -> bool {
+        metadata.level() <= log::max_level()
+    }
+
+    fn log(&self, record: &log::Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+        let line = format!(
+            "{} {:<5} [{}] {}\n",
+            chrono::Local::now().format("%Y-%m-%d %H:%M:%S%.3f"),
+            record.level(),
+            record.target(),
+            record.args()
+        );
+        let _ = self.write_line(&line);
+    }
+
+    fn flush(&self) {
+        if let Ok(mut guard) = self.file.lock() {
+            if let Some(file) = guard.as_mut() {
+                let _ = file.flush();
+            }
+        }
+    }
+}
+
+pub fn log_dir(app_handle: &tauri::AppHandle) -> Result<PathBuf, MisfitError> {
+    Ok(app_handle.path().app_log_dir().map_err(|e| e.to_string())?)
+}
+
+/// Installs the file logger. The level comes from settings (`settings::apply`).
+pub fn init(dir: &Path) {
+    let logger = FileLogger::new(dir, MAX_LOG_BYTES, KEEP_ROTATED);
+    if log::set_boxed_logger(Box::new(logger)).is_err() {
+        eprintln!("A logger is already installed; file logging disabled");
+    }
+}
+
+/// The last `count` lines across the current and rotated log files, oldest first.
+pub fn recent_lines(dir: &Path, count: usize) -> Vec<String> {
+    let mut files = vec![dir.join(LOG_FILE)];
+    files.extend((1..=KEEP_ROTATED).map(|i| dir.join(format!("misfit.{}.log", i))));
+
+    let mut lines: Vec<String> = Vec::new();
+    for file in files {
+        if lines.len() >= count {
+            break;
+        }
+        let Ok(content) = fs::read_to_string(&file) else { continue };
+        let mut older: Vec<String> = content.lines().map(str::to_string).collect();
+        older.append(&mut lines);
+        lines = older;
+    }
+    let skip = lines.len().saturating_sub(count);
+    lines.split_off(skip)
+}
+
+#[tauri::command]
+pub fn get_recent_logs(lines: Option<usize>, app_handle: tauri::AppHandle) -> Result<Vec<String>, MisfitError> {
+    Ok(recent_lines(&log_dir(&app_handle)?, lines.unwrap_or(200)))
+}
+
+#[tauri::command]
+pub fn open_log_folder(app_handle: tauri::AppHandle) -> Result<(), MisfitError> {
+    use tauri_plugin_opener::OpenerExt;
+    let dir = log_dir(&app_handle)?;
+    fs::create_dir_all(&dir)?;
+    app_handle.opener().open_path(dir.to_string_lossy(), None::<&str>).map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rotates_and_reads_back_across_files() {
+        let dir = std::env::temp_dir().join(format!("misfit_log_test_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let logger = FileLogger::new(&dir, 20, 2);
+        for i in 0..6 {
+            logger.write_line(&format!("line {:02} xxxxx\n", i)).unwrap();
+        }
+
+        assert!(dir.join("misfit.2.log").exists());
+        assert!(!dir.join("misfit.3.log").exists());
+        assert_eq!(recent_lines(&dir, 2), vec!["line 04 xxxxx", "line 05 xxxxx"]);
+        assert_eq!(recent_lines(&dir, 100).len(), 3);
+    }
+}
@@ -0,0 +1,512 @@
+//! Studio project files (`.misfitproj`): everything the build screen needs to rebuild an
+//! installer — the manifest, payload `(source, destination)` pairs, build options and output
+//! name — so authors can reopen a project instead of setting it up again.
+//!
+//! Payload sources, the signing key, the code signing certificate and the icon inside the
+//! project folder are stored relative to the project file, so a project can be moved or checked
+//! into a repository with its payloads.
+//!
+//! The app data dir keeps the list of recently opened projects (`recent_projects.json`) and
+//! the studio's autosaved draft (`draft.json`), which is dropped once the project is saved.
+//!
+//! A project archive (`.misfitpkg`) is a zip of the project file, every payload source under
+//! `payloads/<n>/`, the logo and the icon, for moving a project to another machine.
+
+use crate::engine::{self, ErrorCode, MisfitError};
+use crate::BuildRequest;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use tauri::Manager;
+
+pub const PROJECT_EXTENSION: &str = "misfitproj";
+/// Bumped when the file layout changes in a way older studios can't read.
+const PROJECT_FORMAT: u32 = 1;
+pub const ARCHIVE_EXTENSION: &str = "misfitpkg";
+pub const RECENT_PROJECTS_FILE: &str = "recent_projects.json";
+const DRAFT_FILE: &str = "draft.json";
+const MAX_RECENT_PROJECTS: usize = 10;
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ProjectFile {
+    format: u32,
+    saved_at: String,
+    #[serde(flatten)]
+    build: BuildRequest,
+}
+
+/// `path` relative to `base` when it lies inside it.
+fn relative_to(path: &str, base: &Path) -> String {
+    let candidate = Path::new(path);
+    match candidate.strip_prefix(base) {
+        Ok(rel) if candidate.is_absolute() => rel.to_string_lossy().replace('\\', "/"),
+        _ => path.to_string(),
+    }
+}
+
+/// Relative `path` resolved from `base` when something exists there (for a glob, its literal
+/// folder); otherwise unchanged, so sources relative to the working folder keep working.
+fn resolve_from(path: &str, base: &Path) -> String {
+    let joined = base.join(path);
+    if !Path::new(path).is_absolute() && base.join(engine::split_glob(path).0).exists() {
+        joined.to_string_lossy().to_string()
+    } else {
+        path.to_string()
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct RecentProject {
+    pub path: String,
+    /// File name without the extension.
+    pub name: String,
+    pub opened_at: String,
+    pub last_built_at: Option<String>,
+    /// Whether the file is still there; refreshed each time the list is read.
+    #[serde(default)]
+    pub exists: bool,
+}
+
+/// The studio's unsaved work, in whatever shape the UI keeps it.
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Draft {
+    saved_at: String,
+    /// Project the draft was started from, if it had been saved before.
+    project_path: Option<String>,
+    data: serde_json::Value,
+}
+
+fn data_dir(app_handle: &tauri::AppHandle) -> Result<PathBuf, MisfitError> {
+    Ok(app_handle.path().app_data_dir().map_err(|e| e.to_string())?)
+}
+
+fn write_file(path: &Path, contents: String) -> Result<(), MisfitError> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    engine::write_atomic(path, contents.as_bytes()).map_err(|e| MisfitError::from(e).with_path(path))
+}
+
+/// Recent projects, newest first. A missing or unreadable list is empty.
+pub fn read_recent_projects(file: &Path) -> Vec<RecentProject> {
+    let Ok(content) = std::fs::read_to_string(file) else { return Vec::new() };
+    let mut projects: Vec<RecentProject> = serde_json::from_str(&content)
+        .inspect_err(|e| log::warn!("Ignoring {}: {}", file.display(), e))
+        .unwrap_or_default();
+    for project in &mut projects {
+        project.exists = Path::new(&project.path).is_file();
+    }
+    projects
+}
+
+/// Moves `project` to the top of the recent list, keeping its last build time.
+pub fn remember_project(file: &Path, project: &Path) -> Result<(), MisfitError> {
+    let path = project.to_string_lossy().to_string();
+    let mut projects = read_recent_projects(file);
+    let last_built_at = projects.iter().find(|p| p.path == path).and_then(|p| p.last_built_at.clone());
+    projects.retain(|p| p.path != path);
+    projects.insert(
+        0,
+        RecentProject {
+            name: project.file_stem().map(|s| s.to_string_lossy().to_string()).unwrap_or_else(|| path.clone()),
+            path,
+            opened_at: chrono::Local::now().to_rfc3339(),
+            last_built_at,
+            exists: true,
+        },
+    );
+    projects.truncate(MAX_RECENT_PROJECTS);
+    write_file(file, serde_json::to_string_pretty(&projects)?)
+}
+
+/// Stamps the last build time on `project` when it is in the recent list.
+pub fn record_build(file: &Path, project: &Path) -> Result<(), MisfitError> {
+    let path = project.to_string_lossy();
+    let mut projects = read_recent_projects(file);
+    let Some(entry) = projects.iter_mut().find(|p| p.path == path) else { return Ok(()) };
+    entry.last_built_at = Some(chrono::Local::now().to_rfc3339());
+    write_file(file, serde_json::to_string_pretty(&projects)?)
+}
+
+/// The project last saved or loaded in the Studio. Its folder is one the Studio writes to
+/// without asking, and its manifest's `advancedMode` allows writing elsewhere after a confirm.
+#[derive(Default)]
+pub struct OpenProjectState(std::sync::Mutex<Option<OpenProject>>);
+
+#[derive(Clone)]
+pub struct OpenProject {
+    pub dir: PathBuf,
+    pub advanced_mode: bool,
+}
+
+impl OpenProjectState {
+    pub fn get(&self) -> Option<OpenProject> {
+        self.0.lock().ok().and_then(|open| open.clone())
+    }
+
+    fn set(&self, path: &Path, build: &BuildRequest) {
+        if let Ok(mut open) = self.0.lock() {
+            *open = Some(OpenProject { dir: project_dir(path), advanced_mode: build.manifest.advanced_mode.unwrap_or(false) });
+        }
+    }
+}
+
+/// Called after a successful build of a saved project; failures are only logged.
+pub fn note_build(app_handle: &tauri::AppHandle, project: &str) {
+    let recorded = data_dir(app_handle).and_then(|dir| record_build(&dir.join(RECENT_PROJECTS_FILE), Path::new(project)));
+    if let Err(err) = recorded {
+        log::warn!("Could not update recent projects: {}", err);
+    }
+}
+
+fn note_opened(app_handle: &tauri::AppHandle, project: &Path) {
+    let remembered = data_dir(app_handle).and_then(|dir| remember_project(&dir.join(RECENT_PROJECTS_FILE), project));
+    if let Err(err) = remembered {
+        log::warn!("Could not update recent projects: {}", err);
+    }
+}
+
+fn project_dir(path: &Path) -> PathBuf {
+    path.parent().map(Path::to_path_buf).unwrap_or_default()
+}
+
+pub fn write_project(path: &Path, mut build: BuildRequest) -> Result<(), MisfitError> {
+    let base = project_dir(path);
+    for (src, _) in &mut build.payload_files {
+        *src = relative_to(src, &base);
+    }
+    build.signing_key_path = build.signing_key_path.map(|key| relative_to(&key, &base));
+    build.icon_path = build.icon_path.map(|icon| relative_to(&icon, &base));
+    if let Some(logo) = build.manifest.branding.as_mut().and_then(|b| b.logo.as_mut()) {
+        *logo = relative_to(logo, &base);
+    }
+    if let Some(signing) = build.code_signing.as_mut() {
+        signing.pfx_path = signing.pfx_path.as_deref().map(|pfx| relative_to(pfx, &base));
+    }
+
+    let project = ProjectFile { format: PROJECT_FORMAT, saved_at: chrono::Local::now().to_rfc3339(), build };
+    write_file(path, serde_json::to_string_pretty(&project)?)
+}
+
+pub fn read_project(path: &Path) -> Result<BuildRequest, MisfitError> {
+    let content = std::fs::read_to_string(path)
+        .map_err(|e| MisfitError::from(e).with_path(path))?;
+    let project: ProjectFile = serde_json::from_str(&content)
+        .map_err(|e| MisfitError::new(ErrorCode::InvalidManifest, format!("Invalid project file: {}", e)).with_path(path))?;
+    if project.format > PROJECT_FORMAT {
+        return Err(format!("{} was saved by a newer version of the studio", path.display()).into());
+    }
+
+    let base = project_dir(path);
+    let mut build = project.build;
+    for (src, _) in &mut build.payload_files {
+        *src = resolve_from(src, &base);
+    }
+    build.signing_key_path = build.signing_key_path.map(|key| resolve_from(&key, &base));
+    build.icon_path = build.icon_path.map(|icon| resolve_from(&icon, &base));
+    if let Some(logo) = build.manifest.branding.as_mut().and_then(|b| b.logo.as_mut()) {
+        *logo = resolve_from(logo, &base);
+    }
+    if let Some(signing) = build.code_signing.as_mut() {
+        signing.pfx_path = signing.pfx_path.as_deref().map(|pfx| resolve_from(pfx, &base));
+    }
+    Ok(build)
+}
+
+/// `path` with `extension` appended unless it already has it.
+fn with_extension(path: String, extension: &str) -> PathBuf {
+    let mut path = PathBuf::from(path);
+    if path.extension().map_or(true, |e| e != extension) {
+        path.as_mut_os_string().push(format!(".{}", extension));
+    }
+    path
+}
+
+/// Zips `build`, its payload sources and a logo given as an absolute path into `dest`. The
+/// signing key, the code signing certificate and any code signing command stay behind.
+/// Returns the number of files bundled.
+pub fn write_project_archive(dest: &Path, mut build: BuildRequest) -> Result<usize, MisfitError> {
+    let mut files: Vec<(String, PathBuf)> = Vec::new();
+    for (index, (src, _)) in build.payload_files.iter_mut().enumerate() {
+        if engine::has_glob_meta(src) {
+            // Matches keep their path below the glob's literal folder, like in a build
+            let (base, pattern) = engine::split_glob(src);
+            let pattern = pattern.unwrap_or_default();
+            let stored = format!("payloads/{}", index);
+            let plan = engine::plan_copy_glob(&base, &pattern, Path::new(&stored), &[])
+                .map_err(|e| MisfitError::new(ErrorCode::PayloadMissing, format!("Failed to expand payload {}: {}", src, e)).with_path(&base))?;
+            for job in plan.files.into_iter().filter(|job| job.link.is_none()) {
+                files.push((job.dest.to_string_lossy().replace('\\', "/"), job.src));
+            }
+            *src = format!("{}/{}", stored, pattern);
+            continue;
+        }
+        let source = PathBuf::from(&*src);
+        if !source.exists() {
+            return Err(MisfitError::new(ErrorCode::PayloadMissing, format!("Payload source not found: {}", src)).with_path(&source));
+        }
+        let name = source.file_name().ok_or_else(|| format!("Payload source has no file name: {}", src))?;
+        let stored = format!("payloads/{}/{}", index, name.to_string_lossy());
+        for item in walkdir::WalkDir::new(&source) {
+            let item = item.map_err(std::io::Error::from)?;
+            if !item.file_type().is_file() {
+                continue;
+            }
+            let rel = item.path().strip_prefix(&source).unwrap_or(Path::new(""));
+            let entry = match rel.to_string_lossy().replace('\\', "/") {
+                rel if rel.is_empty() => stored.clone(),
+                rel => format!("{}/{}", stored, rel),
+            };
+            files.push((entry, item.path().to_path_buf()));
+        }
+        *src = stored;
+    }
+
+    if let Some(logo) = build.manifest.logo_path.as_deref().map(PathBuf::from) {
+        if let (true, Some(name)) = (logo.is_absolute() && logo.is_file(), logo.file_name()) {
+            let stored = format!("logo/{}", name.to_string_lossy());
+            files.push((stored.clone(), logo.clone()));
+            build.manifest.logo_path = Some(stored);
+        }
+    }
+    if let Some(logo) = build.manifest.branding.as_mut().and_then(|b| b.logo.as_mut()) {
+        let source = PathBuf::from(&*logo);
+        if let (true, Some(name)) = (source.is_file(), source.file_name()) {
+            let stored = format!("{}/{}", engine::BRANDING_DIR, name.to_string_lossy());
+            files.push((stored.clone(), source.clone()));
+            *logo = stored;
+        }
+    }
+    if let Some(icon) = build.icon_path.as_deref().map(PathBuf::from).filter(|icon| icon.is_file()) {
+        let stored = format!("icon/{}", icon.file_name().unwrap_or_default().to_string_lossy());
+        files.push((stored.clone(), icon));
+        build.icon_path = Some(stored);
+    }
+    build.signing_key_path = None;
+    if let Some(signing) = build.code_signing.as_mut() {
+        signing.pfx_path = None;
+        signing.command = None;
+    }
+
+    let stem = dest.file_stem().map(|s| s.to_string_lossy().to_string()).unwrap_or_else(|| "project".to_string());
+    let project = ProjectFile { format: PROJECT_FORMAT, saved_at: chrono::Local::now().to_rfc3339(), build };
+    let project_tmp = dest.with_extension(format!("{}.tmp", PROJECT_EXTENSION));
+    std::fs::write(&project_tmp, serde_json::to_string_pretty(&project)?)?;
+    files.push((format!("{}.{}", stem, PROJECT_EXTENSION), project_tmp.clone()));
+
+    let written = engine::write_zip_files(dest, &files);
+    let _ = std::fs::remove_file(&project_tmp);
+    written?;
+    Ok(files.len())
+}
+
+/// Unpacks a `.misfitpkg` into the empty (or missing) folder `dest_dir` and returns the path
+/// of the project file inside, with the logo pointing at the unpacked copy. A code signing
+/// command in the archive is dropped rather than run on the next build.
+pub fn read_project_archive(archive: &Path, dest_dir: &Path) -> Result<PathBuf, MisfitError> {
+    if std::fs::read_dir(dest_dir).is_ok_and(|mut entries| entries.next().is_some()) {
+        return Err(MisfitError::new(ErrorCode::Failed, format!("{} is not empty", dest_dir.display())).with_path(dest_dir));
+    }
+    engine::extract_zip(archive, dest_dir).map_err(|e| MisfitError::from(e).with_path(archive))?;
+
+    let project = std::fs::read_dir(dest_dir)?
+        .flatten()
+        .map(|entry| entry.path())
+        .find(|path| path.is_file() && path.extension().is_some_and(|e| e == PROJECT_EXTENSION))
+        .ok_or_else(|| MisfitError::new(ErrorCode::InvalidManifest, "Archive contains no project file").with_path(archive))?;
+    let mut build = read_project(&project)?;
+    if let Some(logo) = build.manifest.logo_path.as_deref().map(|logo| dest_dir.join(logo)).filter(|logo| logo.is_file()) {
+        build.manifest.logo_path = Some(logo.to_string_lossy().to_string());
+    }
+    if let Some(signing) = build.code_signing.as_mut() {
+        signing.command = None;
+    }
+    write_project(&project, build)?;
+    Ok(project)
+}
+
+/// Saves `project` to `path` (adding the `.misfitproj` extension when missing) and returns
+/// the path written. The autosaved draft is discarded.
+#[tauri::command]
+pub fn save_project(path: String, project: BuildRequest, app_handle: tauri::AppHandle) -> Result<String, MisfitError> {
+    let path = with_extension(path, PROJECT_EXTENSION);
+    app_handle.state::<OpenProjectState>().set(&path, &project);
+    write_project(&path, project)?;
+    log::info!("Project saved to {}", path.display());
+    note_opened(&app_handle, &path);
+    discard_draft(app_handle)?;
+    Ok(path.to_string_lossy().to_string())
+}
+
+/// Reads a project back in the shape `build_project` takes.
+#[tauri::command]
+pub fn load_project(path: String, app_handle: tauri::AppHandle) -> Result<BuildRequest, MisfitError> {
+    let build = read_project(Path::new(&path))?;
+    app_handle.state::<OpenProjectState>().set(Path::new(&path), &build);
+    note_opened(&app_handle, Path::new(&path));
+    Ok(build)
+}
+
+/// Writes `project` and its payloads to a `.misfitpkg` at `path` and returns the path written.
+#[tauri::command]
+pub async fn export_project_archive(path: String, project: BuildRequest, app_handle: tauri::AppHandle) -> Result<String, MisfitError> {
+    crate::run_blocking(move || {
+        let path = with_extension(path, ARCHIVE_EXTENSION);
+        let count = write_project_archive(&path, project)?;
+        crate::emit_log(&app_handle, format!("Exported {} file(s) to {}", count, path.display()))?;
+        Ok(path.to_string_lossy().to_string())
+    })
+    .await
+}
+
+/// Unpacks a `.misfitpkg` into `dest_dir` and returns the project file path for `load_project`.
+#[tauri::command]
+pub async fn import_project_archive(path: String, dest_dir: String, app_handle: tauri::AppHandle) -> Result<String, MisfitError> {
+    crate::run_blocking(move || {
+        let project = read_project_archive(Path::new(&path), Path::new(&dest_dir))?;
+        crate::emit_log(&app_handle, format!("Imported {} into {}", path, dest_dir))?;
+        note_opened(&app_handle, &project);
+        Ok(project.to_string_lossy().to_string())
+    })
+    .await
+}
+
+/// Recently saved or opened projects, newest first.
+#[tauri::command]
+pub fn get_recent_projects(app_handle: tauri::AppHandle) -> Result<Vec<RecentProject>, MisfitError> {
+    Ok(read_recent_projects(&data_dir(&app_handle)?.join(RECENT_PROJECTS_FILE)))
+}
+
+/// Overwrites the autosaved draft; returns when it was saved.
+#[tauri::command]
+pub fn save_draft(data: serde_json::Value, project_path: Option<String>, app_handle: tauri::AppHandle) -> Result<String, MisfitError> {
+    let draft = Draft { saved_at: chrono::Local::now().to_rfc3339(), project_path, data };
+    write_file(&data_dir(&app_handle)?.join(DRAFT_FILE), serde_json::to_string(&draft)?)?;
+    Ok(draft.saved_at)
+}
+
+/// The draft left behind by a session that didn't save, if any.
+#[tauri::command]
+pub fn load_draft(app_handle: tauri::AppHandle) -> Result<Option<Draft>, MisfitError> {
+    let path = data_dir(&app_handle)?.join(DRAFT_FILE);
+    let Ok(content) = std::fs::read_to_string(&path) else { return Ok(None) };
+    Ok(serde_json::from_str(&content).inspect_err(|e| log::warn!("Ignoring {}: {}", path.display(), e)).ok())
+}
+
+#[tauri::command]
+pub fn discard_draft(app_handle: tauri::AppHandle) -> Result<(), MisfitError> {
+    let path = data_dir(&app_handle)?.join(DRAFT_FILE);
+    if path.exists() {
+        std::fs::remove_file(&path)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn payload_paths_inside_the_project_are_stored_relative() {
+        let dir = std::env::temp_dir().join(format!("misfit_project_test_{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("payloads/theme")).unwrap();
+        let theme = dir.join("payloads/theme").to_string_lossy().to_string();
+        let build: BuildRequest = serde_json::from_value(serde_json::json!({
+            "projectName": "Demo",
+            "manifest": {
+                "appName": "Demo", "version": "1", "publisher": "p", "description": "d", "targets": [],
+                "payloadDir": "payloads", "installSteps": []
+            },
+            "payloadFiles": [[theme, "theme"], ["/elsewhere/logo.png", "logo.png"]],
+            "compressPayloads": "zip"
+        }))
+        .unwrap();
+
+        let path = dir.join(format!("demo.{}", PROJECT_EXTENSION));
+        write_project(&path, build).unwrap();
+        let saved: serde_json::Value = serde_json::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+        assert_eq!(saved["payloadFiles"][0][0], "payloads/theme");
+        assert_eq!(saved["payloadFiles"][1][0], "/elsewhere/logo.png");
+        assert_eq!(saved["format"], PROJECT_FORMAT);
+
+        let loaded = read_project(&path).unwrap();
+        assert_eq!(loaded.payload_files[0], (theme, "theme".to_string()));
+        assert_eq!(loaded.project_name, "Demo");
+        assert_eq!(loaded.compress_payloads, Some(crate::engine::PayloadCompression::Zip));
+    }
+
+    #[test]
+    fn recent_projects_keep_the_newest_first_with_build_times() {
+        let dir = std::env::temp_dir().join(format!("misfit_recent_test_{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let file = dir.join(RECENT_PROJECTS_FILE);
+        let a = dir.join("a.misfitproj");
+        let b = dir.join("b.misfitproj");
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(&a, "{}").unwrap();
+
+        remember_project(&file, &a).unwrap();
+        remember_project(&file, &b).unwrap();
+        record_build(&file, &a).unwrap();
+        remember_project(&file, &a).unwrap();
+
+        let recent = read_recent_projects(&file);
+        assert_eq!(recent.len(), 2);
+        assert_eq!(recent[0].name, "a");
+        assert!(recent[0].exists && recent[0].last_built_at.is_some());
+        assert!(!recent[1].exists && recent[1].last_built_at.is_none());
+
+        for i in 0..MAX_RECENT_PROJECTS {
+            remember_project(&file, &dir.join(format!("p{}.misfitproj", i))).unwrap();
+        }
+        assert_eq!(read_recent_projects(&file).len(), MAX_RECENT_PROJECTS);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn project_archive_round_trips_payloads_and_logo() {
+        let dir = std::env::temp_dir().join(format!("misfit_archive_test_{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(dir.join("src/theme/css")).unwrap();
+        std::fs::write(dir.join("src/theme/css/main.css"), "body {}").unwrap();
+        std::fs::write(dir.join("src/settings.json"), "{}").unwrap();
+        std::fs::write(dir.join("src/logo.png"), "png").unwrap();
+        std::fs::create_dir_all(dir.join("src/icons/dark")).unwrap();
+        std::fs::write(dir.join("src/icons/dark/a.svg"), "svg").unwrap();
+        std::fs::write(dir.join("src/icons/dark/a.txt"), "txt").unwrap();
+        let source = |rel: &str| dir.join("src").join(rel).to_string_lossy().to_string();
+        let build: BuildRequest = serde_json::from_value(serde_json::json!({
+            "projectName": "Demo",
+            "manifest": {
+                "appName": "Demo", "version": "1", "publisher": "p", "description": "d", "targets": [],
+                "payloadDir": "payloads", "installSteps": [], "logoPath": source("logo.png")
+            },
+            "payloadFiles": [[source("theme"), "theme"], [source("settings.json"), "settings.json"], [source("icons/**/*.svg"), "icons"]],
+            "signingKeyPath": "/keys/secret.key"
+        }))
+        .unwrap();
+
+        let archive = dir.join(format!("demo.{}", ARCHIVE_EXTENSION));
+        assert_eq!(write_project_archive(&archive, build).unwrap(), 5);
+
+        let unpacked = dir.join("unpacked");
+        let project = read_project_archive(&archive, &unpacked).unwrap();
+        assert_eq!(project, unpacked.join(format!("demo.{}", PROJECT_EXTENSION)));
+        let loaded = read_project(&project).unwrap();
+        let theme = Path::new(&loaded.payload_files[0].0);
+        assert_eq!(std::fs::read_to_string(theme.join("css/main.css")).unwrap(), "body {}");
+        assert!(Path::new(&loaded.payload_files[1].0).ends_with("payloads/1/settings.json"));
+        let icons = &loaded.payload_files[2].0;
+        assert!(icons.replace('\\', "/").ends_with("payloads/2/**/*.svg") && Path::new(icons).is_absolute());
+        let (base, _) = engine::split_glob(icons);
+        assert_eq!(std::fs::read_to_string(base.join("dark/a.svg")).unwrap(), "svg");
+        assert!(!base.join("dark/a.txt").exists());
+        assert_eq!(loaded.manifest.logo_path.as_deref().map(std::fs::read_to_string).unwrap().unwrap(), "png");
+        assert_eq!(loaded.signing_key_path, None);
+
+        assert!(read_project_archive(&archive, &unpacked).is_err());
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
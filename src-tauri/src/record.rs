@@ -0,0 +1,141 @@
+//! Studio record mode: `start_recording` snapshots a target folder, the author changes it by
+//! hand, and `finish_recording` turns the difference into manifest steps plus payload files.
+//! Snapshots are kept in `recordings/` under the app data dir so a recording survives a restart.
+//! `generate_manifest_from_diff` does the same for two existing folders.
+
+use crate::engine::{self, ErrorCode, MisfitError};
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+use tauri::Manager;
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Recording {
+    id: String,
+    root: String,
+    created_at: String,
+    file_count: usize,
+}
+
+impl Recording {
+    fn new(id: String, snapshot: &engine::Snapshot) -> Self {
+        Recording {
+            id,
+            root: snapshot.root.to_string_lossy().to_string(),
+            created_at: snapshot.created_at.clone(),
+            file_count: snapshot.files.len(),
+        }
+    }
+}
+
+fn recordings_dir(app_handle: &tauri::AppHandle) -> Result<PathBuf, MisfitError> {
+    Ok(app_handle.path().app_data_dir().map_err(|e| e.to_string())?.join("recordings"))
+}
+
+fn snapshot_path(dir: &Path, id: &str) -> Result<PathBuf, MisfitError> {
+    let id = engine::normalize_rel_path(id, false)?;
+    if id.components().count() != 1 {
+        return Err(format!("Invalid recording id: {}", id.display()).into());
+    }
+    Ok(dir.join(id).with_extension("json"))
+}
+
+fn load_snapshot(dir: &Path, id: &str) -> Result<engine::Snapshot, MisfitError> {
+    let path = snapshot_path(dir, id)?;
+    let content = std::fs::read_to_string(&path)
+        .map_err(|_| MisfitError::new(ErrorCode::NotFound, format!("Recording not found: {}", id)).with_path(&path))?;
+    Ok(serde_json::from_str(&content)?)
+}
+
+#[tauri::command]
+pub async fn start_recording(target_dir: String, exclude: Option<Vec<String>>, app_handle: tauri::AppHandle) -> Result<Recording, MisfitError> {
+    crate::run_blocking(move || {
+        let snapshot = engine::take_snapshot(Path::new(&target_dir), &exclude.unwrap_or_default())?;
+        let dir = recordings_dir(&app_handle)?;
+        std::fs::create_dir_all(&dir)?;
+        let id = format!("rec_{}", chrono::Local::now().format("%Y%m%d_%H%M%S"));
+        std::fs::write(snapshot_path(&dir, &id)?, serde_json::to_string(&snapshot)?)?;
+        crate::emit_log(&app_handle, format!("Recording {}: snapshot of {} file(s)", target_dir, snapshot.files.len()))?;
+        Ok(Recording::new(id, &snapshot))
+    })
+    .await
+}
+
+/// Diffs the folder against its snapshot. Payload files go to `output_dir` (default: a folder
+/// named after the recording next to its snapshot); step targets start with `target_prefix`
+/// (default: the recorded folder), e.g. `%APPDATA%/Code/User`.
+#[tauri::command]
+pub async fn finish_recording(
+    id: String,
+    target_prefix: Option<String>,
+    output_dir: Option<String>,
+    app_handle: tauri::AppHandle,
+) -> Result<engine::RecordedChanges, MisfitError> {
+    crate::run_blocking(move || {
+        let dir = recordings_dir(&app_handle)?;
+        let snapshot = load_snapshot(&dir, &id)?;
+        let output = match output_dir {
+            Some(path) => PathBuf::from(path),
+            None => snapshot_path(&dir, &id)?.with_extension(""),
+        };
+        let prefix = target_prefix.unwrap_or_else(|| snapshot.root.to_string_lossy().replace('\\', "/"));
+        let changes = engine::record_changes(&snapshot, &snapshot.root, &prefix, &output)?;
+        crate::emit_log(&app_handle, format!("Recording {}: {} step(s) generated", id, changes.steps.len()))?;
+        Ok(changes)
+    })
+    .await
+}
+
+/// Drafts a manifest that turns `original_dir` into `modified_dir`. Payload files go to
+/// `output_dir` (default: `recordings/diff_<timestamp>`); step targets start with
+/// `target_prefix` (default: `original_dir`).
+#[tauri::command]
+pub async fn generate_manifest_from_diff(
+    original_dir: String,
+    modified_dir: String,
+    target_prefix: Option<String>,
+    output_dir: Option<String>,
+    app_handle: tauri::AppHandle,
+) -> Result<engine::ManifestDraft, MisfitError> {
+    crate::run_blocking(move || {
+        let output = match output_dir {
+            Some(path) => PathBuf::from(path),
+            None => recordings_dir(&app_handle)?.join(format!("diff_{}", chrono::Local::now().format("%Y%m%d_%H%M%S"))),
+        };
+        let prefix = target_prefix.unwrap_or_else(|| original_dir.replace('\\', "/"));
+        let draft = engine::generate_manifest_from_diff(Path::new(&original_dir), Path::new(&modified_dir), &prefix, &output)?;
+        crate::emit_log(&app_handle, format!("Drafted {} step(s) from {} -> {}", draft.manifest.install_steps.len(), original_dir, modified_dir))?;
+        Ok(draft)
+    })
+    .await
+}
+
+#[tauri::command]
+pub fn list_recordings(app_handle: tauri::AppHandle) -> Result<Vec<Recording>, MisfitError> {
+    let dir = recordings_dir(&app_handle)?;
+    let mut recordings = Vec::new();
+    for entry in std::fs::read_dir(&dir).into_iter().flatten().flatten() {
+        let path = entry.path();
+        if path.extension().is_some_and(|e| e == "json") {
+            let Some(id) = path.file_stem().map(|s| s.to_string_lossy().to_string()) else { continue };
+            match load_snapshot(&dir, &id) {
+                Ok(snapshot) => recordings.push(Recording::new(id, &snapshot)),
+                Err(err) => log::warn!("Skipping recording {}: {}", path.display(), err),
+            }
+        }
+    }
+    recordings.sort_by(|a, b| b.created_at.cmp(&a.created_at));
+    Ok(recordings)
+}
+
+/// Deletes the snapshot and the default payload folder of a recording.
+#[tauri::command]
+pub fn discard_recording(id: String, app_handle: tauri::AppHandle) -> Result<(), MisfitError> {
+    let path = snapshot_path(&recordings_dir(&app_handle)?, &id)?;
+    let payload = path.with_extension("");
+    if payload.exists() {
+        std::fs::remove_dir_all(&payload)?;
+    }
+    std::fs::remove_file(&path)
+        .map_err(|_| MisfitError::new(ErrorCode::NotFound, format!("Recording not found: {}", id)).with_path(&path))
+}
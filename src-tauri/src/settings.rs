@@ -0,0 +1,185 @@
+//! User preferences kept in `settings.json` under the app config dir. Every field has a
+//! default, so a missing or partial file still loads.
+
+use misfit_engine::MisfitError;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use tauri::Manager;
+
+pub const SETTINGS_FILE: &str = "settings.json";
+
+/// Folder names skipped when searching for a payload folder (`resolve_payload_root`).
+pub const DEFAULT_SKIP_DIRS: &[&str] = &[
+    "node_modules",
+    ".git",
+    "target",
+    "dist",
+    ".cache",
+    "appdata",
+    "windows",
+    "program files",
+    "program files (x86)",
+];
+
+/// 16 MiB: far more than any manifest or preset, far less than a payload archive.
+pub const DEFAULT_MAX_TEXT_FILE_BYTES: u64 = 16 * 1024 * 1024;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "camelCase")]
+pub enum LogLevel {
+    Error,
+    Warn,
+    #[default]
+    Info,
+    Debug,
+}
+
+impl From<LogLevel> for log::LevelFilter {
+    fn from(level: LogLevel) -> Self {
+        match level {
+            LogLevel::Error => log::LevelFilter::Error,
+            LogLevel::Warn => log::LevelFilter::Warn,
+            LogLevel::Info => log::LevelFilter::Info,
+            LogLevel::Debug => log::LevelFilter::Debug,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase", default)]
+pub struct Settings {
+    /// Where Studio builds go; `None` keeps the `dist/` next to the working folder.
+    pub dist_dir: Option<String>,
+    /// Backup root used when neither `MISFIT_BACKUP_DIR` nor the manifest's `backupDir` is set.
+    pub backup_dir: Option<String>,
+    pub log_level: LogLevel,
+    pub payload_skip_dirs: Vec<String>,
+    /// Ask before running a manifest's `runCommand` steps.
+    pub confirm_run_commands: bool,
+    /// Commands run without asking: a program name (`npm` also matches `npm.cmd`) for commands
+    /// found on `PATH`, or a full path.
+    pub allowed_commands: Vec<String>,
+    /// Ask before replacing files under the `prompt` overwrite policy; when off they are replaced.
+    pub confirm_overwrite: bool,
+    /// Largest file `read_text_file` opens and `write_text_file` writes; the base64 file
+    /// commands read no more and write no larger files.
+    pub max_text_file_bytes: u64,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Settings {
+            dist_dir: None,
+            backup_dir: None,
+            log_level: LogLevel::default(),
+            payload_skip_dirs: DEFAULT_SKIP_DIRS.iter().map(|d| d.to_string()).collect(),
+            confirm_run_commands: true,
+            allowed_commands: Vec::new(),
+            confirm_overwrite: true,
+            max_text_file_bytes: DEFAULT_MAX_TEXT_FILE_BYTES,
+        }
+    }
+}
+
+impl Settings {
+    pub fn skips_dir(&self, name: &str) -> bool {
+        self.payload_skip_dirs.iter().any(|d| d.eq_ignore_ascii_case(name))
+    }
+
+    /// Whether `command` is on the allowlist. A bare program name only matches commands given
+    /// without a folder, so `npm` doesn't let `C:/Temp/npm.exe` through.
+    pub fn allows_command(&self, command: &str) -> bool {
+        let command = command.trim();
+        let has_folder = |path: &str| path.contains(['/', '\\']);
+        self.allowed_commands.iter().map(|entry| entry.trim()).any(|entry| {
+            if has_folder(entry) || has_folder(command) {
+                return Path::new(entry) == Path::new(command);
+            }
+            let stem = Path::new(command).file_stem().map(|s| s.to_string_lossy().to_string()).unwrap_or_default();
+            entry.eq_ignore_ascii_case(command) || entry.eq_ignore_ascii_case(&stem)
+        })
+    }
+}
+
+/// The loaded settings, shared by every command.
+#[derive(Default)]
+pub struct SettingsState(pub Mutex<Settings>);
+
+impl SettingsState {
+    pub fn get(&self) -> Settings {
+        self.0.lock().map(|s| s.clone()).unwrap_or_default()
+    }
+}
+
+pub fn settings_path(app_handle: &tauri::AppHandle) -> Result<PathBuf, MisfitError> {
+    let dir = app_handle.path().app_config_dir().map_err(|e| e.to_string())?;
+    Ok(dir.join(SETTINGS_FILE))
+}
+
+/// Reads `path`, falling back to defaults when it doesn't exist or can't be parsed.
+pub fn load_settings(path: &Path) -> Settings {
+    let Ok(content) = std::fs::read_to_string(path) else { return Settings::default() };
+    serde_json::from_str(&content).unwrap_or_else(|err| {
+        log::warn!("Ignoring invalid settings file {}: {}", path.display(), err);
+        Settings::default()
+    })
+}
+
+pub fn save_settings(path: &Path, settings: &Settings) -> Result<(), MisfitError> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    misfit_engine::write_atomic(path, serde_json::to_string_pretty(settings)?.as_bytes()).map_err(|e| MisfitError::from(e).with_path(path))
+}
+
+pub fn apply(settings: &Settings) {
+    log::set_max_level(settings.log_level.into());
+}
+
+#[tauri::command]
+pub fn get_settings(state: tauri::State<'_, SettingsState>) -> Settings {
+    state.get()
+}
+
+#[tauri::command]
+pub fn set_settings(settings: Settings, state: tauri::State<'_, SettingsState>, app_handle: tauri::AppHandle) -> Result<(), MisfitError> {
+    save_settings(&settings_path(&app_handle)?, &settings)?;
+    apply(&settings);
+    *state.0.lock().map_err(|e| e.to_string())? = settings;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn partial_settings_fill_in_defaults_and_round_trip() {
+        let dir = std::env::temp_dir().join(format!("misfit_settings_test_{}", std::process::id()));
+        let path = dir.join(SETTINGS_FILE);
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(&path, r#"{"logLevel":"debug","confirmRunCommands":false}"#).unwrap();
+
+        let mut settings = load_settings(&path);
+        assert_eq!(settings.log_level, LogLevel::Debug);
+        assert!(!settings.confirm_run_commands);
+        assert!(settings.skips_dir("Node_Modules"));
+
+        settings.dist_dir = Some("D:/Builds".to_string());
+        save_settings(&path, &settings).unwrap();
+        assert_eq!(load_settings(&path), settings);
+        assert_eq!(load_settings(&dir.join("missing.json")), Settings::default());
+    }
+
+    #[test]
+    fn allowlist_matches_program_names_and_full_paths() {
+        let settings = Settings { allowed_commands: vec!["npm".into(), "C:/Tools/setup.exe".into()], ..Settings::default() };
+        assert!(settings.allows_command("npm"));
+        assert!(settings.allows_command("NPM.cmd"));
+        assert!(settings.allows_command("C:/Tools/setup.exe"));
+        assert!(!settings.allows_command("C:/Temp/npm.exe"));
+        assert!(!settings.allows_command("setup.exe"));
+        assert!(!settings.allows_command("node"));
+    }
+}
@@ -0,0 +1,93 @@
+//! The studio's snippet library, kept in `snippets.json` under the app data dir so saved
+//! step groups are available in every project. Snippets are unique by name.
+
+use crate::engine::{self, ErrorCode, MisfitError};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use tauri::Manager;
+
+pub const SNIPPETS_FILE: &str = "snippets.json";
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SnippetInfo {
+    #[serde(flatten)]
+    snippet: engine::Snippet,
+    /// Placeholders that need a value when inserting.
+    variables: Vec<String>,
+}
+
+fn snippets_path(app_handle: &tauri::AppHandle) -> Result<PathBuf, MisfitError> {
+    Ok(app_handle.path().app_data_dir().map_err(|e| e.to_string())?.join(SNIPPETS_FILE))
+}
+
+fn read_snippets(path: &Path) -> Result<Vec<engine::Snippet>, MisfitError> {
+    match std::fs::read_to_string(path) {
+        Ok(content) => Ok(serde_json::from_str(&content).map_err(|e| MisfitError::from(e).with_path(path))?),
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(Vec::new()),
+        Err(err) => Err(MisfitError::from(err).with_path(path)),
+    }
+}
+
+fn write_snippets(path: &Path, snippets: &[engine::Snippet]) -> Result<(), MisfitError> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    engine::write_atomic(path, serde_json::to_string_pretty(snippets)?.as_bytes()).map_err(|e| MisfitError::from(e).with_path(path))
+}
+
+fn not_found(name: &str) -> MisfitError {
+    MisfitError::new(ErrorCode::NotFound, format!("Snippet not found: {}", name))
+}
+
+#[tauri::command]
+pub fn list_snippets(app_handle: tauri::AppHandle) -> Result<Vec<SnippetInfo>, MisfitError> {
+    let mut snippets = Vec::new();
+    for snippet in read_snippets(&snippets_path(&app_handle)?)? {
+        let variables = engine::snippet_variables(&snippet.steps)?;
+        snippets.push(SnippetInfo { snippet, variables });
+    }
+    snippets.sort_by_key(|info| info.snippet.name.to_lowercase());
+    Ok(snippets)
+}
+
+/// Adds `snippet` to the library, replacing one with the same name. Returns its placeholders.
+#[tauri::command]
+pub fn save_snippet(mut snippet: engine::Snippet, app_handle: tauri::AppHandle) -> Result<Vec<String>, MisfitError> {
+    snippet.name = snippet.name.trim().to_string();
+    if snippet.name.is_empty() {
+        return Err("Snippet name is required".into());
+    }
+    if snippet.steps.is_empty() {
+        return Err("Snippet has no steps".into());
+    }
+    let variables = engine::snippet_variables(&snippet.steps)?;
+    let path = snippets_path(&app_handle)?;
+    let mut snippets = read_snippets(&path)?;
+    snippets.retain(|s| s.name != snippet.name);
+    log::info!("Saved snippet {} ({} step(s))", snippet.name, snippet.steps.len());
+    snippets.push(snippet);
+    write_snippets(&path, &snippets)?;
+    Ok(variables)
+}
+
+/// The steps of snippet `name` with its placeholders filled in, ready to append to a manifest.
+#[tauri::command]
+pub fn insert_snippet(name: String, values: HashMap<String, String>, app_handle: tauri::AppHandle) -> Result<Vec<engine::ManifestStep>, MisfitError> {
+    let snippets = read_snippets(&snippets_path(&app_handle)?)?;
+    let snippet = snippets.iter().find(|s| s.name == name).ok_or_else(|| not_found(&name))?;
+    Ok(engine::instantiate_snippet(snippet, &values)?)
+}
+
+#[tauri::command]
+pub fn delete_snippet(name: String, app_handle: tauri::AppHandle) -> Result<(), MisfitError> {
+    let path = snippets_path(&app_handle)?;
+    let mut snippets = read_snippets(&path)?;
+    let count = snippets.len();
+    snippets.retain(|s| s.name != name);
+    if snippets.len() == count {
+        return Err(not_found(&name));
+    }
+    write_snippets(&path, &snippets)
+}
@@ -0,0 +1,125 @@
+//! Uninstaller mode: `--uninstall` (or the shortcut written after each install) opens the
+//! window on the receipt of the bundled manifest's app, and `revert_patches` undoes it. With
+//! `registerUninstall`, Add/Remove Programs on Windows opens it too.
+//!
+//! Each install copies the installer and its manifest into an `uninstaller` folder next to the
+//! receipt, and the shortcut and Add/Remove Programs entry open that copy, so uninstalling
+//! still works after the downloaded installer is deleted.
+
+use crate::engine::{self, ErrorCode, MisfitError};
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct UninstallState {
+    pub app_name: String,
+    pub version: String,
+    pub publisher: String,
+    /// When the receipt was last written; `None` when there is nothing to uninstall.
+    pub installed_at: Option<String>,
+    /// Files with patches still applied, in the order they will be reverted.
+    pub patched_files: Vec<String>,
+}
+
+/// What the uninstaller window shows: the bundled manifest's app and what its receipt still
+/// records.
+#[tauri::command]
+pub fn get_uninstall_state(app_handle: tauri::AppHandle) -> Result<UninstallState, MisfitError> {
+    let path = crate::resolve_manifest_path(&app_handle)
+        .ok_or_else(|| MisfitError::new(ErrorCode::ManifestNotFound, "Manifest not found"))?;
+    let manifest = engine::load_manifest(&path)?;
+    let app_backup_root = crate::backups_root(&app_handle, Some(&manifest))?.join(engine::backup_namespace(&manifest.app_name));
+    let receipt = engine::load_receipt(&app_backup_root)?;
+    let mut patched_files: Vec<String> = Vec::new();
+    // Reverted newest first, like `revert_all_patches`
+    for record in receipt.iter().flat_map(|r| r.patches.iter().rev()) {
+        if !patched_files.contains(&record.file) {
+            patched_files.push(record.file.clone());
+        }
+    }
+    Ok(UninstallState {
+        installed_at: receipt.filter(|r| !r.patches.is_empty()).map(|r| r.installed_at),
+        app_name: manifest.app_name.to_string(),
+        version: manifest.version,
+        publisher: manifest.publisher,
+        patched_files,
+    })
+}
+
+/// Folder next to the receipt holding the copied installer and manifest.
+const UNINSTALLER_DIR: &str = "uninstaller";
+
+/// Copies this installer's executable and `manifest` into [`UNINSTALLER_DIR`] under
+/// `app_backup_root` and returns the arguments that open the copy as the uninstaller.
+pub fn copy_uninstaller(app_backup_root: &Path, manifest: &engine::InstallManifest) -> Result<Vec<String>, MisfitError> {
+    let exe = std::env::current_exe()?;
+    let dir = app_backup_root.join(UNINSTALLER_DIR);
+    std::fs::create_dir_all(&dir)?;
+    let exe_copy = dir.join(exe.file_name().ok_or("The installer has no file name")?);
+    if exe != exe_copy {
+        // Copied aside first, so a failed copy doesn't leave a broken uninstaller behind
+        let partial = dir.join(".installer.tmp");
+        std::fs::copy(&exe, &partial).map_err(|e| MisfitError::from(e).with_path(&exe_copy))?;
+        std::fs::rename(&partial, &exe_copy).map_err(|e| MisfitError::from(e).with_path(&exe_copy))?;
+    }
+    // The copy lives elsewhere, so it names the backup folder the install used outright
+    let mut manifest = manifest.clone();
+    manifest.backup_dir = app_backup_root.parent().map(|root| root.to_string_lossy().to_string());
+    let manifest_copy = dir.join("install.manifest.json");
+    engine::write_atomic(&manifest_copy, serde_json::to_string_pretty(&manifest)?.as_bytes())
+        .map_err(|e| MisfitError::from(e).with_path(&manifest_copy))?;
+    Ok(uninstall_args(&exe_copy, &manifest_copy))
+}
+
+/// Writes a launcher that opens the uninstaller `args` into `app_backup_root`, next to the
+/// receipt, and returns its path: a `.cmd` on Windows, a `.desktop` entry on Linux and a
+/// `.command` script on macOS.
+pub fn write_uninstall_shortcut(app_backup_root: &Path, app_name: &str, args: &[String]) -> Result<PathBuf, MisfitError> {
+    let (extension, contents) = shortcut_contents(app_name, &command_line(args));
+    let path = app_backup_root.join(format!("Uninstall {}.{}", engine::backup_namespace(app_name), extension));
+    std::fs::create_dir_all(app_backup_root)?;
+    std::fs::write(&path, contents).map_err(|e| MisfitError::from(e).with_path(&path))?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o755))?;
+    }
+    Ok(path)
+}
+
+/// Lists the app under Add/Remove Programs (Windows only) with the installer's icon and an
+/// uninstall command that opens the uninstaller `args`.
+pub fn register_program(manifest: &engine::InstallManifest, args: &[String]) -> Result<(), MisfitError> {
+    let command_line = command_line(args);
+    let entry = engine::ProgramEntry {
+        display_name: manifest.app_name.to_string(),
+        display_version: manifest.version.clone(),
+        publisher: manifest.publisher.clone(),
+        display_icon: Some(format!("{},0", args[0])),
+        quiet_uninstall_string: Some(format!("{} --silent", command_line)),
+        uninstall_string: command_line,
+    };
+    engine::register_program(&manifest.app_name, &entry)?;
+    Ok(())
+}
+
+/// `exe` followed by the arguments that open it as the uninstaller of `manifest`.
+fn uninstall_args(exe: &Path, manifest: &Path) -> Vec<String> {
+    [exe, Path::new("--uninstall"), Path::new("--manifest"), manifest].iter().map(|a| a.to_string_lossy().to_string()).collect()
+}
+
+fn command_line(args: &[String]) -> String {
+    args.iter().map(|a| format!("\"{}\"", a)).collect::<Vec<_>>().join(" ")
+}
+
+fn shortcut_contents(app_name: &str, command_line: &str) -> (&'static str, String) {
+    if cfg!(windows) {
+        ("cmd", format!("@echo off\r\nstart \"\" {}\r\n", command_line))
+    } else if cfg!(target_os = "macos") {
+        ("command", format!("#!/bin/sh\nexec {}\n", command_line))
+    } else {
+        let desktop = format!("[Desktop Entry]\nType=Application\nName=Uninstall {}\nExec={}\nTerminal=false\n", app_name, command_line);
+        ("desktop", desktop)
+    }
+}
@@ -0,0 +1,223 @@
+//! Studio watch mode: `start_build_watch` builds once, then watches the payload sources and
+//! rebuilds whenever one changes, so authors iterating on payloads see the result without
+//! pressing Build. A plain folder build is updated in place, copying only the changed files;
+//! builds that pack the payloads (archives, embedding, code signing, release zips) are redone.
+
+use crate::engine::{self, MisfitError};
+use crate::{emit_log, BuildOutput, BuildRequest, OutputFormat};
+use anyhow::Context;
+use notify_debouncer_mini::notify::{RecommendedWatcher, RecursiveMode};
+use notify_debouncer_mini::{new_debouncer, DebounceEventResult, Debouncer};
+use serde::Serialize;
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::Duration;
+use tauri::{Emitter, Manager};
+
+/// How long the sources must stay quiet before a rebuild; editors often save in bursts.
+const DEBOUNCE: Duration = Duration::from_millis(500);
+
+/// The running watch, if any; dropping the debouncer stops it.
+#[derive(Default)]
+pub struct WatchState(Mutex<Option<Debouncer<RecommendedWatcher>>>);
+
+/// Sent as `build-watch` around every rebuild.
+#[derive(Serialize, Clone)]
+#[serde(tag = "status", rename_all = "camelCase")]
+enum WatchEvent {
+    Rebuilding { changed: Vec<String> },
+    #[serde(rename_all = "camelCase")]
+    Built { output: BuildOutput, incremental: bool, updated_files: usize },
+    Failed { error: MisfitError },
+}
+
+/// Each payload source, or the base folder of a glob.
+fn watch_roots(payload_files: &[(String, String)]) -> Vec<PathBuf> {
+    let mut roots: Vec<PathBuf> = payload_files
+        .iter()
+        .map(|(src, _)| {
+            if engine::has_glob_meta(src) {
+                crate::resolve_payload_source(&engine::split_glob(src).0.to_string_lossy())
+            } else {
+                crate::resolve_payload_source(src)
+            }
+        })
+        .collect();
+    roots.sort();
+    roots.dedup();
+    roots
+}
+
+/// Whether the previous output can be updated in place: its payloads must still be loose
+/// files that nothing else (an archive, the executable, a release zip) was made from.
+fn can_sync(request: &BuildRequest) -> bool {
+    request.output_format.unwrap_or_default() == OutputFormat::Folder
+        && request.compress_payloads.unwrap_or_default().archive_name().is_none()
+        && !request.embed_payloads.unwrap_or(false)
+        && request.code_signing.is_none()
+}
+
+/// Destinations of every payload file, for spotting ones whose source went away.
+fn payload_dests(request: &BuildRequest, output_dir: &Path) -> Result<HashSet<PathBuf>, MisfitError> {
+    let payloads_dir = output_dir.join(engine::normalize_rel_path(&request.manifest.payload_dir, true)?);
+    let exclude = request.payload_exclude.clone().unwrap_or_default();
+    let (plan, _) = crate::plan_payloads(&request.payload_files, &payloads_dir, &exclude)?;
+    Ok(plan.files.into_iter().map(|job| job.dest).collect())
+}
+
+struct Rebuilder {
+    request: BuildRequest,
+    app_handle: tauri::AppHandle,
+    roots: Vec<PathBuf>,
+    /// Changes in here are the builds themselves, for payload sources that contain the output.
+    project_root: PathBuf,
+    output: BuildOutput,
+    /// Payload files in `output`, as of the last build.
+    dests: HashSet<PathBuf>,
+}
+
+impl Rebuilder {
+    fn handle(&mut self, result: DebounceEventResult) {
+        let events = match result {
+            Ok(events) => events,
+            Err(err) => {
+                log::warn!("Build watch error: {}", err);
+                return;
+            }
+        };
+        let mut changed: Vec<String> = events
+            .into_iter()
+            .map(|event| event.path)
+            .filter(|path| self.roots.iter().any(|root| path.starts_with(root)) && !path.starts_with(&self.project_root))
+            .map(|path| path.to_string_lossy().to_string())
+            .collect();
+        changed.sort();
+        changed.dedup();
+        if changed.is_empty() {
+            return;
+        }
+
+        self.emit(WatchEvent::Rebuilding { changed });
+        let incremental = can_sync(&self.request);
+        let rebuilt = if incremental { self.sync() } else { self.rebuild() };
+        match rebuilt {
+            Ok(updated_files) => {
+                if incremental {
+                    let _ = emit_log(&self.app_handle, format!("Updated {} payload file(s) in {}", updated_files, self.output.output_dir));
+                }
+                self.emit(WatchEvent::Built { output: self.output.clone(), incremental, updated_files });
+            }
+            Err(error) => {
+                let _ = emit_log(&self.app_handle, format!("Watch rebuild failed: {}", error));
+                self.emit(WatchEvent::Failed { error });
+            }
+        }
+    }
+
+    fn emit(&self, event: WatchEvent) {
+        let _ = self.app_handle.emit("build-watch", event);
+    }
+
+    fn rebuild(&mut self) -> Result<usize, MisfitError> {
+        self.output = crate::build_project_blocking(self.request.clone(), self.app_handle.clone())?;
+        self.dests = payload_dests(&self.request, Path::new(&self.output.output_dir))?;
+        Ok(self.dests.len())
+    }
+
+    /// Copies new and changed payload files into the last output, deletes ones whose source
+    /// is gone, and rewrites (and re-signs) the lockfile. Returns how many files changed.
+    fn sync(&mut self) -> Result<usize, MisfitError> {
+        let _operation = crate::begin_operation(&self.app_handle, crate::OperationKind::Build)?;
+        crate::validate_request(&self.request)?;
+        let output_dir = PathBuf::from(&self.output.output_dir);
+        let payloads_dir = output_dir.join(engine::normalize_rel_path(&self.request.manifest.payload_dir, true)?);
+        let exclude = self.request.payload_exclude.clone().unwrap_or_default();
+        let (plan, copied) = crate::plan_payloads(&self.request.payload_files, &payloads_dir, &exclude)?;
+
+        let dests: HashSet<PathBuf> = plan.files.iter().map(|job| job.dest.clone()).collect();
+        let stale = engine::stale_jobs(plan);
+        let mut updated = stale.files.len();
+        engine::copy_plan_parallel(&stale, &|_| {}).context("Failed to copy payloads")?;
+        for removed in self.dests.difference(&dests) {
+            if std::fs::remove_file(removed).is_ok() {
+                updated += 1;
+            }
+        }
+        self.dests = dests;
+
+        let manifest_dir = output_dir.join("manifests");
+        let lock = engine::build_payload_lock(&payloads_dir, &copied)?;
+        std::fs::write(manifest_dir.join(engine::PAYLOAD_LOCK_FILE), serde_json::to_string_pretty(&lock)?)?;
+        if let Some(key_path) = self.request.signing_key_path.as_deref() {
+            let secret = std::fs::read_to_string(key_path).context("Failed to read signing key")?;
+            engine::sign_install(&manifest_dir, &secret)?;
+        }
+        Ok(updated)
+    }
+}
+
+/// Builds `request`, then rebuilds it on every change to its payload sources until
+/// `stop_build_watch`. Replaces a watch that is already running.
+#[tauri::command]
+pub async fn start_build_watch(request: BuildRequest, app_handle: tauri::AppHandle) -> Result<BuildOutput, MisfitError> {
+    crate::run_blocking(move || start_watch(request, app_handle)).await
+}
+
+fn start_watch(request: BuildRequest, app_handle: tauri::AppHandle) -> Result<BuildOutput, MisfitError> {
+    let state = app_handle.state::<WatchState>();
+    state.0.lock().map_err(|e| e.to_string())?.take();
+
+    let output = crate::build_project_blocking(request.clone(), app_handle.clone())?;
+    let roots = watch_roots(&request.payload_files);
+    let mut rebuilder = Rebuilder {
+        dests: payload_dests(&request, Path::new(&output.output_dir))?,
+        project_root: crate::output_dirs(&request, &app_handle)?.project_root,
+        roots: roots.clone(),
+        output: output.clone(),
+        request,
+        app_handle: app_handle.clone(),
+    };
+    let mut debouncer = new_debouncer(DEBOUNCE, move |result| rebuilder.handle(result)).map_err(|e| e.to_string())?;
+    for root in &roots {
+        // Watch a file's folder: editors that save by replacing the file would end a watch on it
+        let (path, mode) = if root.is_dir() {
+            (root.as_path(), RecursiveMode::Recursive)
+        } else {
+            (root.parent().unwrap_or(root), RecursiveMode::NonRecursive)
+        };
+        debouncer
+            .watcher()
+            .watch(path, mode)
+            .map_err(|e| MisfitError::from(format!("Failed to watch {}: {}", path.display(), e)).with_path(path))?;
+    }
+    emit_log(&app_handle, format!("Watching {} payload source(s) for changes", roots.len()))?;
+    *state.0.lock().map_err(|e| e.to_string())? = Some(debouncer);
+    Ok(output)
+}
+
+/// Stops the watch started by `start_build_watch`. A rebuild already running finishes first.
+#[tauri::command]
+pub fn stop_build_watch(app_handle: tauri::AppHandle) -> Result<(), MisfitError> {
+    let stopped = app_handle.state::<WatchState>().0.lock().map_err(|e| e.to_string())?.take();
+    if stopped.is_some() {
+        emit_log(&app_handle, "Stopped watching payload sources")?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn watch_roots_use_glob_bases_once() {
+        let root = std::env::temp_dir().join("misfit_watch_roots");
+        let files = [
+            (root.join("site/**/*.css").to_string_lossy().to_string(), "css".to_string()),
+            (root.join("site").to_string_lossy().to_string(), "site".to_string()),
+            (root.join("readme.txt").to_string_lossy().to_string(), "readme.txt".to_string()),
+        ];
+        assert_eq!(watch_roots(&files), vec![root.join("readme.txt"), root.join("site")]);
+    }
+}